@@ -0,0 +1,191 @@
+//! Aggregates over record paths.
+//!
+//! Aggregates and distinct values are computed during cursor iteration from
+//! probed values (see [`crate::query`]): the filter and aggregated paths of
+//! every record are extracted in one pass and no record is fully
+//! deserialized, so a dashboard total costs one scan instead of transferring
+//! every record to the app.
+
+use std::collections::HashSet;
+
+use lmdb::{Cursor, Error as LmdbError, Transaction};
+use log::info;
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{AggregateOp, AggregateResult, AggregateSpec};
+use crate::local_db_state::AppDbState;
+use crate::overflow::has_overflow;
+use crate::query::{probe_paths, sort_order};
+
+/// Running state of one aggregate.
+#[derive(Default)]
+struct Accumulator {
+    count: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    fn add(&mut self, n: f64) {
+        self.count += 1;
+        self.sum += n;
+        self.min = Some(self.min.map_or(n, |min| min.min(n)));
+        self.max = Some(self.max.map_or(n, |max| max.max(n)));
+    }
+
+    fn value(&self, op: AggregateOp) -> Option<f64> {
+        match op {
+            AggregateOp::Count => Some(self.count as f64),
+            AggregateOp::Sum => Some(self.sum),
+            AggregateOp::Min => self.min,
+            AggregateOp::Max => self.max,
+            AggregateOp::Avg => (self.count > 0).then(|| self.sum / self.count as f64),
+        }
+    }
+}
+
+impl AppDbState {
+    /// Computes count, sum, min, max and avg aggregates over the records
+    /// matching the filter of `spec`.
+    ///
+    /// Numbers are accumulated as `f64`. Records whose value cannot be decoded
+    /// are logged and skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::AggregateSpec;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("orders".to_string())?;
+    ///
+    /// let spec: AggregateSpec = serde_json::from_str(
+    ///     r#"{"filter": {"data.status": "paid"}, "aggregations": [{"op": "sum", "path": "data.amount"}]}"#,
+    /// )?;
+    /// let result = db.aggregate(&spec)?;
+    /// println!("Revenue: {:?}", result.values["sum(data.amount)"]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if an aggregate other than `count`
+    /// has no path, or a database error if the read fails.
+    pub fn aggregate(&self, spec: &AggregateSpec) -> Result<AggregateResult, AppResponse> {
+        if let Some(aggregation) = spec.aggregations.iter().find(|a| a.op != AggregateOp::Count && a.path.is_none()) {
+            return Err(AppResponse::BadRequest(format!("Aggregate {:?} needs a path", aggregation.op)));
+        }
+
+        let filter_paths = spec.filter.paths();
+        let mut paths = filter_paths.clone();
+        paths.extend(spec.aggregations.iter().filter_map(|a| a.path.as_deref()));
+
+        let mut matched = 0;
+        let mut accumulators: Vec<Accumulator> = spec.aggregations.iter().map(|_| Accumulator::default()).collect();
+
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        for (key, value) in cursor.iter() {
+            let probed = match self.record_json(key, value) {
+                Ok(json) => probe_paths(&json, &paths).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let probed = match probed {
+                Ok(probed) => probed,
+                Err(e) => {
+                    info!("Error probing model: {e}");
+                    continue;
+                }
+            };
+            if !spec.filter.matches_values(&probed[..filter_paths.len()]) {
+                continue;
+            }
+
+            matched += 1;
+            let mut values = probed[filter_paths.len()..].iter();
+            for (aggregation, accumulator) in spec.aggregations.iter().zip(&mut accumulators) {
+                let number = match aggregation.path {
+                    // Each path was probed in aggregation order
+                    Some(_) => values.next().and_then(|value| value.as_ref()).and_then(JsonValue::as_f64),
+                    None => Some(0.0),
+                };
+                if let Some(number) = number {
+                    accumulator.add(number);
+                }
+            }
+        }
+
+        let values = spec
+            .aggregations
+            .iter()
+            .zip(&accumulators)
+            .map(|(aggregation, accumulator)| (aggregation.key(), accumulator.value(aggregation.op)))
+            .collect();
+        Ok(AggregateResult { matched, values })
+    }
+
+    /// Returns the unique values found at `path` across all records, e.g. the
+    /// categories to offer in a filter dropdown.
+    ///
+    /// Array values contribute each of their elements, so a `data.tags` path
+    /// yields the individual tags. Records without the path are skipped. The
+    /// values are sorted in the order used by [`AppDbState::query_sorted`].
+    /// Records with overflowed fields, and all records while field encryption
+    /// is set, are decoded in full to read the value.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("products".to_string())?;
+    ///
+    /// let categories = db.distinct("data.category")?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read fails.
+    pub fn distinct(&self, path: &str) -> Result<Vec<JsonValue>, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        let mut seen = HashSet::new();
+        let mut values = Vec::new();
+        let mut add = |value: JsonValue| {
+            if seen.insert(value.to_string()) {
+                values.push(value);
+            }
+        };
+
+        for (key, value) in cursor.iter() {
+            let probed = if has_overflow(value) || self.field_encryption.is_some() {
+                // Stubs and encrypted fields are only resolved on the whole record
+                self.decode_record(&txn, key, value)
+                    .and_then(|model| Ok(serde_json::to_string(&model)?))
+                    .and_then(|json| Ok(probe_paths(&json, &[path])?))
+                    .map_err(|e| e.to_string())
+            } else {
+                match self.record_json(key, value) {
+                    Ok(json) => probe_paths(&json, &[path]).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                }
+            };
+            match probed.map(|mut probed| probed.pop().flatten()) {
+                Ok(Some(JsonValue::Array(items))) => items.into_iter().for_each(&mut add),
+                Ok(Some(value)) => add(value),
+                Ok(None) => {}
+                Err(e) => info!("Error probing model: {e}"),
+            }
+        }
+
+        values.sort_by(|a, b| sort_order(Some(a), Some(b)));
+        Ok(values)
+    }
+}
@@ -9,7 +9,7 @@ use std::fmt::{Display, Formatter};
 
 use lmdb::Error as LmdbError;
 use serde::{Deserialize, Serialize};
-use serde_json::Error as SerdeError;
+use serde_json::{json, Error as SerdeError, Value as JsonValue};
 
 /// Unified response type for all database operations and FFI interactions.
 ///
@@ -25,6 +25,8 @@ use serde_json::Error as SerdeError;
 /// - [`NotFound`] - Resource not found errors
 /// - [`ValidationError`] - Input validation errors
 /// - [`BadRequest`] - Invalid request parameters
+/// - [`Busy`] - Request throttled, retry later
+/// - [`Conflict`] - Record changed since it was read
 /// - [`Ok`] - Successful operation with result data
 ///
 /// # JSON Format
@@ -39,6 +41,8 @@ use serde_json::Error as SerdeError;
 /// {"DatabaseError": "LMDB error: database is corrupted"}
 /// {"NotFound": "No record found with id: user_123"}
 /// {"BadRequest": "Null pointer passed to function"}
+/// {"Busy": {"message": "Write rate limit exceeded", "retry_after_ms": 20}}
+/// {"Conflict": {"message": "Record user_123 has hash h2, expected h1", "current_hash": "h2"}}
 /// ```
 ///
 /// # Examples
@@ -83,7 +87,7 @@ use serde_json::Error as SerdeError;
 ///     _ => println!("Other error"),
 /// }
 /// ```
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AppResponse {
     /// Database operation error.
     ///
@@ -165,6 +169,52 @@ pub enum AppResponse {
     /// ```
     BadRequest(String),
 
+    /// Throttled request.
+    ///
+    /// This variant is returned when a write is rejected by the write rate
+    /// limiter. Nothing was written; the request can be retried after
+    /// `retry_after_ms` milliseconds.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use offline_first_core::app_response::AppResponse;
+    ///
+    /// let error = AppResponse::Busy {
+    ///     message: "Write rate limit exceeded".to_string(),
+    ///     retry_after_ms: 20,
+    /// };
+    /// ```
+    Busy {
+        /// Why the request was throttled.
+        message: String,
+        /// Milliseconds to wait before retrying.
+        retry_after_ms: u64,
+    },
+
+    /// Failed precondition of a conditional write.
+    ///
+    /// This variant is returned when a record changed since the caller read
+    /// it, e.g. on another isolate or by a sync. Nothing was written; the
+    /// caller can reload the record, reapply its change and retry.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use offline_first_core::app_response::AppResponse;
+    ///
+    /// let error = AppResponse::Conflict {
+    ///     message: "Record user_123 has hash h2, expected h1".to_string(),
+    ///     current_hash: "h2".to_string(),
+    /// };
+    /// ```
+    Conflict {
+        /// Why the write was rejected.
+        message: String,
+        /// Hash of the stored record.
+        current_hash: String,
+    },
+
     /// Successful operation response.
     ///
     /// This variant represents successful operations and contains the
@@ -207,6 +257,8 @@ impl Display for AppResponse {
             AppResponse::NotFound(msg) => write!(f, "Not found: {msg}"),
             AppResponse::ValidationError(msg) => write!(f, "Validation error: {msg}"),
             AppResponse::BadRequest(msg) => write!(f, "Bad Request: {msg}"),
+            AppResponse::Busy { message, retry_after_ms } => write!(f, "Busy: {message}, retry after {retry_after_ms} ms"),
+            AppResponse::Conflict { message, .. } => write!(f, "Conflict: {message}"),
             AppResponse::Ok(msg) => write!(f, "Ok: {msg}"),
         }
     }
@@ -348,4 +400,152 @@ impl AppResponse {
     pub fn success(msg: impl Into<String>) -> Self {
         AppResponse::Ok(msg.into())
     }
-}
\ No newline at end of file
+}
+
+/// Broad class of an error, for deciding how to react to it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The request was invalid; retrying it unchanged fails again.
+    Client,
+    /// The requested record or resource does not exist.
+    NotFound,
+    /// JSON could not be parsed or produced.
+    Serialization,
+    /// The storage engine failed.
+    Database,
+    /// The request was rejected for now and can be retried later.
+    Throttled,
+    /// The record changed since it was read; reload it before retrying.
+    Conflict,
+}
+
+/// Machine-readable description of an error response.
+///
+/// `code` and `name` are stable across releases; `message` is meant for
+/// logs and may change.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ErrorInfo {
+    /// Stable numeric code, see [`AppResponse::code`].
+    pub code: u32,
+    /// Stable name of the code, e.g. `NOT_FOUND`.
+    pub name: String,
+    /// Broad class of the error.
+    pub category: ErrorCategory,
+    /// Human-readable description.
+    pub message: String,
+    /// Structured details, e.g. `{"retry_after_ms": 20}` for throttled requests
+    /// or `{"current_hash": "h2"}` for conflicts.
+    pub detail: Option<JsonValue>,
+}
+
+/// Version 2 response format, with error codes instead of variant names.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"ok": true, "data": "{\"id\":\"user_123\"}", "error": null}
+/// {"ok": false, "data": null, "error": {"code": 1003, "name": "NOT_FOUND", "category": "not_found", "message": "No model found with id: user_123", "detail": null}}
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResponseEnvelope {
+    /// Whether the operation succeeded.
+    pub ok: bool,
+    /// Payload of a successful response.
+    pub data: Option<String>,
+    /// Description of a failed one.
+    pub error: Option<ErrorInfo>,
+}
+
+impl AppResponse {
+    /// Returns the stable numeric code of this response: `0` for
+    /// [`AppResponse::Ok`], 1xxx for client errors, 2xxx for serialization,
+    /// 3xxx for database and 4xxx for throttling errors.
+    ///
+    /// | Code | Name                  | Variant                            |
+    /// |------|-----------------------|------------------------------------|
+    /// | 0    | `OK`                  | [`AppResponse::Ok`]                |
+    /// | 1001 | `BAD_REQUEST`         | [`AppResponse::BadRequest`]        |
+    /// | 1002 | `VALIDATION_ERROR`    | [`AppResponse::ValidationError`]   |
+    /// | 1003 | `NOT_FOUND`           | [`AppResponse::NotFound`]          |
+    /// | 1004 | `CONFLICT`            | [`AppResponse::Conflict`]          |
+    /// | 2001 | `SERIALIZATION_ERROR` | [`AppResponse::SerializationError`]|
+    /// | 3001 | `DATABASE_ERROR`      | [`AppResponse::DatabaseError`]     |
+    /// | 4001 | `BUSY`                | [`AppResponse::Busy`]              |
+    pub fn code(&self) -> u32 {
+        match self {
+            AppResponse::Ok(_) => 0,
+            AppResponse::BadRequest(_) => 1001,
+            AppResponse::ValidationError(_) => 1002,
+            AppResponse::NotFound(_) => 1003,
+            AppResponse::Conflict { .. } => 1004,
+            AppResponse::SerializationError(_) => 2001,
+            AppResponse::DatabaseError(_) => 3001,
+            AppResponse::Busy { .. } => 4001,
+        }
+    }
+
+    /// Returns the stable name of [`AppResponse::code`].
+    pub fn code_name(&self) -> &'static str {
+        match self {
+            AppResponse::Ok(_) => "OK",
+            AppResponse::BadRequest(_) => "BAD_REQUEST",
+            AppResponse::ValidationError(_) => "VALIDATION_ERROR",
+            AppResponse::NotFound(_) => "NOT_FOUND",
+            AppResponse::Conflict { .. } => "CONFLICT",
+            AppResponse::SerializationError(_) => "SERIALIZATION_ERROR",
+            AppResponse::DatabaseError(_) => "DATABASE_ERROR",
+            AppResponse::Busy { .. } => "BUSY",
+        }
+    }
+
+    /// Returns the category of an error, `None` for [`AppResponse::Ok`].
+    pub fn category(&self) -> Option<ErrorCategory> {
+        match self {
+            AppResponse::Ok(_) => None,
+            AppResponse::BadRequest(_) | AppResponse::ValidationError(_) => Some(ErrorCategory::Client),
+            AppResponse::NotFound(_) => Some(ErrorCategory::NotFound),
+            AppResponse::SerializationError(_) => Some(ErrorCategory::Serialization),
+            AppResponse::DatabaseError(_) => Some(ErrorCategory::Database),
+            AppResponse::Busy { .. } => Some(ErrorCategory::Throttled),
+            AppResponse::Conflict { .. } => Some(ErrorCategory::Conflict),
+        }
+    }
+
+    /// Converts this response to the version 2 [`ResponseEnvelope`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use offline_first_core::app_response::AppResponse;
+    ///
+    /// let envelope = AppResponse::NotFound("No model found with id: a".to_string()).envelope();
+    /// assert_eq!(envelope.error.unwrap().name, "NOT_FOUND");
+    /// ```
+    pub fn envelope(&self) -> ResponseEnvelope {
+        let (message, detail) = match self {
+            AppResponse::Ok(data) => {
+                return ResponseEnvelope { ok: true, data: Some(data.clone()), error: None };
+            }
+            AppResponse::Busy { message, retry_after_ms } => (message.clone(), Some(json!({ "retry_after_ms": retry_after_ms }))),
+            AppResponse::Conflict { message, current_hash } => (message.clone(), Some(json!({ "current_hash": current_hash }))),
+            AppResponse::DatabaseError(message)
+            | AppResponse::SerializationError(message)
+            | AppResponse::NotFound(message)
+            | AppResponse::ValidationError(message)
+            | AppResponse::BadRequest(message) => (message.clone(), None),
+        };
+
+        ResponseEnvelope {
+            ok: false,
+            data: None,
+            error: Some(ErrorInfo {
+                code: self.code(),
+                name: self.code_name().to_string(),
+                category: self.category().unwrap_or(ErrorCategory::Database),
+                message,
+                detail,
+            }),
+        }
+    }
+}
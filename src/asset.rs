@@ -0,0 +1,98 @@
+//! Read-only asset databases.
+//!
+//! Apps often ship pre-built data (a dictionary, a product catalog) as an LMDB
+//! database in their assets. Such a database can be attached next to the
+//! writable user database: it is opened read-only and without a lock file, so
+//! it works from read-only storage, and lookups with fallback consult the user
+//! data first and the asset database second.
+
+use std::path::Path;
+
+use lmdb::{Database, Environment, EnvironmentFlags, Error as LmdbError, Transaction};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::{AppDbState, MAIN_DB_NAME};
+use crate::value_codec::decode_model;
+
+/// Handles of an attached asset database.
+pub(crate) struct AssetDb {
+    env: Environment,
+    db: Database,
+}
+
+impl AppDbState {
+    /// Attaches the pre-built database `name` (the `{name}.lmdb` directory) as
+    /// a read-only asset database, replacing any previously attached one.
+    ///
+    /// The asset database must contain the `main` database, i.e. it must have
+    /// been produced by this library. Closing or resetting the user database
+    /// detaches it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("user_data".to_string())?;
+    /// db.attach_asset_db("assets/catalog")?;
+    ///
+    /// let product = db.get_with_fallback("product_42");
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The asset directory does not exist
+    /// - The environment cannot be opened
+    /// - The asset database has no `main` database
+    pub fn attach_asset_db(&mut self, name: &str) -> Result<(), LmdbError> {
+        let asset_dir = format!("{name}.lmdb");
+        let path = Path::new(&asset_dir);
+        if !path.exists() {
+            return Err(LmdbError::NotFound);
+        }
+
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::READ_ONLY | EnvironmentFlags::NO_LOCK)
+            .set_max_dbs(10)
+            .open(path)?;
+        let db = env.open_db(Some(MAIN_DB_NAME))?;
+
+        info!("✅ Asset database attached from {}", asset_dir);
+        self.asset = Some(AssetDb { env, db });
+        Ok(())
+    }
+
+    /// Detaches the asset database, if any.
+    pub fn detach_asset_db(&mut self) {
+        self.asset = None;
+    }
+
+    /// Retrieves a record from the user database, falling back to the attached
+    /// asset database when the ID is not found there.
+    ///
+    /// Without an attached asset database this behaves like [`AppDbState::get_by_id`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`AppDbState::get_by_id`], for whichever database holds the record.
+    pub fn get_with_fallback(&self, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        if let Some(model) = self.get_by_id(id)? {
+            return Ok(Some(model));
+        }
+
+        let Some(asset) = &self.asset else {
+            return Ok(None);
+        };
+
+        let txn = asset.env.begin_ro_txn()?;
+        match txn.get(asset.db, &id) {
+            Ok(bytes) => Ok(Some(decode_model(bytes)?)),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Attachment store with resumable chunked transfers.
+//!
+//! Attachments, such as the photos of a record, are stored apart from the
+//! records, split into [`ATTACHMENT_CHUNK_SIZE`] chunks with a CRC-32 each.
+//! The sync layer moves them one chunk per request, so a transfer cut by a
+//! flaky mobile network resumes from the first missing chunk, even after the
+//! app restarts:
+//!
+//! - Upload: [`AppDbState::put_attachment`] stores the attachment and returns
+//!   its manifest for the server. The sync layer reads each chunk with
+//!   [`AppDbState::read_attachment_chunk`] and acknowledges it with
+//!   [`AppDbState::ack_attachment_chunk`] once the server has it.
+//! - Download: [`AppDbState::begin_attachment_download`] registers the
+//!   server's manifest, and [`AppDbState::write_attachment_chunk`] stores
+//!   each chunk after checking its size and checksum.
+//!
+//! [`AppDbState::pending_attachments`] lists the unfinished transfers with
+//! their missing chunks, and a callback set with
+//! [`AppDbState::set_attachment_progress_callback`] follows every chunk.
+//!
+//! The store lives in the `__attachments` database:
+//!
+//! ```text
+//! 'm' {id}                      -> {manifest and direction, JSON}
+//! 'c' {id} 0x00 {index, u32 BE} -> {chunk bytes}
+//! 'a' {id} 0x00 {index, u32 BE} -> {}    chunk acknowledged by the server
+//! ```
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{AttachmentManifest, AttachmentProgress, TransferDirection};
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+
+/// Side database holding the attachments.
+pub(crate) const ATTACHMENTS_DB_NAME: &str = "__attachments";
+
+/// Size in bytes of the chunks of attachments stored with
+/// [`AppDbState::put_attachment`].
+pub const ATTACHMENT_CHUNK_SIZE: u32 = 256 * 1024;
+
+const MANIFEST_TAG: u8 = b'm';
+const CHUNK_TAG: u8 = b'c';
+const ACK_TAG: u8 = b'a';
+
+/// Callback following the progress of attachment transfers.
+pub(crate) type AttachmentProgressFn = Arc<dyn Fn(&AttachmentProgress) + Send + Sync>;
+
+/// Value stored under the manifest key of an attachment.
+#[derive(Serialize, Deserialize)]
+struct StoredAttachment {
+    manifest: AttachmentManifest,
+    direction: TransferDirection,
+}
+
+impl AppDbState {
+    /// Stores an attachment, replacing an earlier one with the same ID, and
+    /// returns its manifest. Every chunk is pending upload.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// let photo = std::fs::read("photo.jpg").unwrap();
+    /// let manifest = db.put_attachment("photo_1", &photo)?;
+    /// // Send the manifest, then each chunk, to the server
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `id` is empty, or an error if
+    /// the attachment cannot be written.
+    pub fn put_attachment(&self, id: &str, bytes: &[u8]) -> Result<AttachmentManifest, AppResponse> {
+        if id.is_empty() {
+            return Err(AppResponse::BadRequest("Attachment ID cannot be empty".to_string()));
+        }
+
+        let manifest = AttachmentManifest {
+            id: id.to_string(),
+            bytes: bytes.len() as u64,
+            chunk_size: ATTACHMENT_CHUNK_SIZE,
+            checksums: bytes.chunks(ATTACHMENT_CHUNK_SIZE as usize).map(crc32).collect(),
+        };
+
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        delete_attachment_keys(&mut txn, attachments_db, id)?;
+        let stored = StoredAttachment { manifest: manifest.clone(), direction: TransferDirection::Upload };
+        txn.put(attachments_db, &key(MANIFEST_TAG, id), &serde_json::to_vec(&stored)?, WriteFlags::empty())?;
+        for (index, chunk) in bytes.chunks(ATTACHMENT_CHUNK_SIZE as usize).enumerate() {
+            txn.put(attachments_db, &chunk_key(CHUNK_TAG, id, index as u32), &chunk, WriteFlags::empty())?;
+        }
+        txn.commit()?;
+        Ok(manifest)
+    }
+
+    /// Returns the content of a stored or fully downloaded attachment, or
+    /// `None` if there is no attachment with this ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the attachment is still
+    /// downloading, or an error if the database cannot be read.
+    pub fn get_attachment(&self, id: &str) -> Result<Option<Vec<u8>>, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let Some(stored) = read_stored(&txn, attachments_db, id)? else {
+            return Ok(None);
+        };
+
+        let chunks = done_chunks(&txn, attachments_db, CHUNK_TAG, id)?;
+        if chunks.len() < stored.manifest.checksums.len() {
+            return Err(AppResponse::BadRequest(format!(
+                "Attachment {id} is still downloading ({} of {} chunks)",
+                chunks.len(),
+                stored.manifest.checksums.len()
+            )));
+        }
+
+        let mut bytes = Vec::with_capacity(stored.manifest.bytes as usize);
+        for index in chunks {
+            bytes.extend_from_slice(txn.get(attachments_db, &chunk_key(CHUNK_TAG, id, index))?);
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Deletes an attachment and cancels its transfer. Returns whether it
+    /// existed.
+    pub fn delete_attachment(&self, id: &str) -> Result<bool, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let existed = read_stored(&txn, attachments_db, id)?.is_some();
+        delete_attachment_keys(&mut txn, attachments_db, id)?;
+        txn.commit()?;
+        Ok(existed)
+    }
+
+    /// Returns chunk `index` of an attachment, for upload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the attachment or the chunk is
+    /// not stored.
+    pub fn read_attachment_chunk(&self, id: &str, index: u32) -> Result<Vec<u8>, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        match txn.get(attachments_db, &chunk_key(CHUNK_TAG, id, index)) {
+            Ok(chunk) => Ok(chunk.to_vec()),
+            Err(LmdbError::NotFound) => Err(AppResponse::NotFound(format!("Chunk {index} of attachment {id} not found"))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records that the server has received chunk `index` of an upload, and
+    /// returns the progress of the upload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if there is no such attachment, or
+    /// [`AppResponse::BadRequest`] if it is a download or `index` is out of
+    /// range.
+    pub fn ack_attachment_chunk(&self, id: &str, index: u32) -> Result<AttachmentProgress, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let stored = transfer(&txn, attachments_db, id, TransferDirection::Upload, index)?;
+
+        txn.put(attachments_db, &chunk_key(ACK_TAG, id, index), &[], WriteFlags::empty())?;
+        self.track_sync_run(&mut txn, |run| run.bytes_sent += chunk_len(&stored.manifest, index))?;
+        let progress = progress(&txn, attachments_db, stored)?;
+        txn.commit()?;
+        self.report_attachment_progress(&progress);
+        Ok(progress)
+    }
+
+    /// Registers an attachment to download from its server manifest and
+    /// returns the progress of the download.
+    ///
+    /// Registering the same manifest again keeps the chunks received so far,
+    /// so an interrupted download resumes; a different manifest for the ID
+    /// replaces the attachment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the ID is empty, the chunk size
+    /// is `0` or the checksums do not match the number of chunks.
+    pub fn begin_attachment_download(&self, manifest: &AttachmentManifest) -> Result<AttachmentProgress, AppResponse> {
+        if manifest.id.is_empty() {
+            return Err(AppResponse::BadRequest("Attachment ID cannot be empty".to_string()));
+        }
+        if manifest.chunk_size == 0 {
+            return Err(AppResponse::BadRequest("Attachment chunk size must be greater than 0".to_string()));
+        }
+        let chunks = manifest.bytes.div_ceil(u64::from(manifest.chunk_size));
+        if manifest.checksums.len() as u64 != chunks {
+            return Err(AppResponse::BadRequest(format!(
+                "Attachment {} has {chunks} chunks but {} checksums",
+                manifest.id,
+                manifest.checksums.len()
+            )));
+        }
+
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let stored = StoredAttachment { manifest: manifest.clone(), direction: TransferDirection::Download };
+        let resumed = read_stored(&txn, attachments_db, &manifest.id)?
+            .is_some_and(|existing| existing.direction == stored.direction && existing.manifest == stored.manifest);
+        if !resumed {
+            delete_attachment_keys(&mut txn, attachments_db, &manifest.id)?;
+            txn.put(attachments_db, &key(MANIFEST_TAG, &manifest.id), &serde_json::to_vec(&stored)?, WriteFlags::empty())?;
+        }
+        let progress = progress(&txn, attachments_db, stored)?;
+        txn.commit()?;
+        Ok(progress)
+    }
+
+    /// Stores chunk `index` of a download after checking its size and
+    /// checksum, and returns the progress of the download.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the download was not registered
+    /// with [`AppDbState::begin_attachment_download`], or
+    /// [`AppResponse::BadRequest`] if `index` is out of range or the chunk
+    /// does not match the manifest, e.g. when it was corrupted in transit.
+    pub fn write_attachment_chunk(&self, id: &str, index: u32, bytes: &[u8]) -> Result<AttachmentProgress, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let stored = transfer(&txn, attachments_db, id, TransferDirection::Download, index)?;
+
+        let expected = chunk_len(&stored.manifest, index);
+        if bytes.len() as u64 != expected || crc32(bytes) != stored.manifest.checksums[index as usize] {
+            return Err(AppResponse::BadRequest(format!(
+                "Chunk {index} of attachment {id} does not match its manifest ({} bytes, {expected} expected)",
+                bytes.len()
+            )));
+        }
+
+        txn.put(attachments_db, &chunk_key(CHUNK_TAG, id, index), &bytes, WriteFlags::empty())?;
+        self.track_sync_run(&mut txn, |run| run.bytes_received += expected)?;
+        let progress = progress(&txn, attachments_db, stored)?;
+        txn.commit()?;
+        self.report_attachment_progress(&progress);
+        Ok(progress)
+    }
+
+    /// Returns the progress of the transfer of an attachment, or `None` if
+    /// there is no attachment with this ID.
+    pub fn attachment_progress(&self, id: &str) -> Result<Option<AttachmentProgress>, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        match read_stored(&txn, attachments_db, id)? {
+            Some(stored) => Ok(Some(progress(&txn, attachments_db, stored)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the unfinished uploads and downloads, in ID order, for the
+    /// sync layer to resume.
+    pub fn pending_attachments(&self) -> Result<Vec<AttachmentProgress>, AppResponse> {
+        let (env, attachments_db) = self.side_db(ATTACHMENTS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(attachments_db)?;
+
+        let mut pending = Vec::new();
+        for (key, value) in scan_from(&cursor, Some(&[MANIFEST_TAG])).take_while(|(key, _)| key.first() == Some(&MANIFEST_TAG)) {
+            let stored: StoredAttachment = serde_json::from_slice(value).map_err(|e| {
+                AppResponse::SerializationError(format!("Invalid manifest of attachment {}: {e}", String::from_utf8_lossy(&key[1..])))
+            })?;
+            let progress = progress(&txn, attachments_db, stored)?;
+            if !progress.complete {
+                pending.push(progress);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Sets a function called with the progress of a transfer after each
+    /// chunk is acknowledged or written, or removes it with `None`.
+    ///
+    /// The callback runs on the thread that transferred the chunk.
+    pub fn set_attachment_progress_callback<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&AttachmentProgress) + Send + Sync + 'static,
+    {
+        self.attachment_progress = callback.map(|callback| Arc::new(callback) as AttachmentProgressFn);
+    }
+
+    fn report_attachment_progress(&self, progress: &AttachmentProgress) {
+        if let Some(callback) = &self.attachment_progress {
+            callback(progress);
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `bytes`, as used by zlib and most storage APIs.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn key(tag: u8, id: &str) -> Vec<u8> {
+    [&[tag], id.as_bytes()].concat()
+}
+
+fn chunk_key(tag: u8, id: &str, index: u32) -> Vec<u8> {
+    [&[tag], id.as_bytes(), &[0], &index.to_be_bytes()].concat()
+}
+
+/// Size in bytes of chunk `index`.
+fn chunk_len(manifest: &AttachmentManifest, index: u32) -> u64 {
+    let start = u64::from(index) * u64::from(manifest.chunk_size);
+    manifest.bytes.saturating_sub(start).min(u64::from(manifest.chunk_size))
+}
+
+fn read_stored<T: Transaction>(txn: &T, attachments_db: Database, id: &str) -> Result<Option<StoredAttachment>, AppResponse> {
+    match txn.get(attachments_db, &key(MANIFEST_TAG, id)) {
+        Ok(value) => Ok(Some(serde_json::from_slice(value)?)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the attachment `id` if it is a transfer in `direction` with a
+/// chunk `index`.
+fn transfer<T: Transaction>(txn: &T, attachments_db: Database, id: &str, direction: TransferDirection, index: u32) -> Result<StoredAttachment, AppResponse> {
+    let stored = read_stored(txn, attachments_db, id)?
+        .ok_or_else(|| AppResponse::NotFound(format!("Attachment {id} not found")))?;
+    if stored.direction != direction {
+        let transfer = match direction {
+            TransferDirection::Upload => "uploaded",
+            TransferDirection::Download => "downloaded",
+        };
+        return Err(AppResponse::BadRequest(format!("Attachment {id} is not being {transfer}")));
+    }
+    if index as usize >= stored.manifest.checksums.len() {
+        return Err(AppResponse::BadRequest(format!(
+            "Attachment {id} has no chunk {index} ({} chunks)",
+            stored.manifest.checksums.len()
+        )));
+    }
+    Ok(stored)
+}
+
+/// Returns the indexes of the chunks of `id` stored under `tag`.
+fn done_chunks<T: Transaction>(txn: &T, attachments_db: Database, tag: u8, id: &str) -> Result<BTreeSet<u32>, LmdbError> {
+    let prefix = [&[tag], id.as_bytes(), &[0]].concat();
+    let cursor = txn.open_ro_cursor(attachments_db)?;
+    Ok(scan_from(&cursor, Some(&prefix))
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .filter_map(|(key, _)| Some(u32::from_be_bytes(key[prefix.len()..].try_into().ok()?)))
+        .collect())
+}
+
+fn progress<T: Transaction>(txn: &T, attachments_db: Database, stored: StoredAttachment) -> Result<AttachmentProgress, AppResponse> {
+    let tag = match stored.direction {
+        TransferDirection::Upload => ACK_TAG,
+        TransferDirection::Download => CHUNK_TAG,
+    };
+    let done = done_chunks(txn, attachments_db, tag, &stored.manifest.id)?;
+    let chunks = stored.manifest.checksums.len() as u32;
+
+    Ok(AttachmentProgress {
+        missing: (0..chunks).filter(|index| !done.contains(index)).collect(),
+        bytes_done: done.iter().map(|&index| chunk_len(&stored.manifest, index)).sum(),
+        complete: done.len() as u32 == chunks,
+        manifest: stored.manifest,
+        direction: stored.direction,
+    })
+}
+
+/// Removes the manifest, chunks and acknowledgements of `id`.
+fn delete_attachment_keys(txn: &mut RwTransaction, attachments_db: Database, id: &str) -> Result<(), LmdbError> {
+    match txn.del(attachments_db, &key(MANIFEST_TAG, id), None) {
+        Ok(()) | Err(LmdbError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+    for tag in [CHUNK_TAG, ACK_TAG] {
+        for index in done_chunks(txn, attachments_db, tag, id)? {
+            txn.del(attachments_db, &chunk_key(tag, id, index), None)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Backfill of new fields with a default value.
+//!
+//! When an app version introduces a field, records written by older versions
+//! lack it. [`AppDbState::backfill_field`] sets a default on those records
+//! once, so readers can rely on the field being present. Records are visited
+//! in key order, in write transactions of at most `batch_size` records, which
+//! keeps the write lock free for foreground calls between batches.
+
+use lmdb::Transaction;
+use log::info;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{Backfill, BackfillProgress, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::query::{model_value, probe_paths};
+use crate::scan::scan_from;
+
+impl AppDbState {
+    /// Sets `backfill.field` to `backfill.value` on every record matching
+    /// `backfill.filter` where the field is missing or `null`, calling
+    /// `progress` after each batch. Returns the final progress.
+    ///
+    /// Missing parent objects are created. Records where a parent is not an
+    /// object are left unchanged. The `hash` of updated records is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::Backfill;
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("tasks".to_string())?;
+    ///
+    /// let backfill = Backfill {
+    ///     field: "data.priority".to_string(),
+    ///     value: json!(0),
+    ///     filter: Default::default(),
+    ///     batch_size: 500,
+    /// };
+    /// let done = db.backfill_field(&backfill, |progress| println!("{} records updated", progress.updated))?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `field` is not below `data` or
+    /// `batch_size` is zero, or a database error if a batch fails; batches
+    /// committed before stay updated.
+    pub fn backfill_field<F>(&self, backfill: &Backfill, mut progress: F) -> Result<BackfillProgress, AppResponse>
+    where
+        F: FnMut(&BackfillProgress),
+    {
+        let segments: Vec<&str> = match backfill.field.strip_prefix("data.") {
+            Some(rest) if !rest.split('.').any(str::is_empty) => rest.split('.').collect(),
+            _ => return Err(AppResponse::BadRequest(format!("Backfill field {} is not a path below data", backfill.field))),
+        };
+        if backfill.batch_size == 0 {
+            return Err(AppResponse::BadRequest("Backfill batch size must be at least 1".to_string()));
+        }
+
+        let mut paths = backfill.filter.paths();
+        paths.push(&backfill.field);
+
+        let (env, db) = self.env_db()?;
+        let mut done = BackfillProgress::default();
+        let mut resume: Option<Vec<u8>> = None;
+
+        loop {
+            let mut txn = env.begin_rw_txn()?;
+            let writer = self.record_writer(&txn)?;
+
+            let mut scanned = 0;
+            let mut last_key = None;
+            let mut lacking: Vec<LocalDbModel> = Vec::new();
+            {
+                let cursor = txn.open_ro_cursor(db)?;
+                for (key, value) in scan_from(&cursor, resume.as_deref()).take(backfill.batch_size) {
+                    scanned += 1;
+                    last_key = Some(key.to_vec());
+
+                    let probed = match self.record_json(key, value) {
+                        Ok(json) => probe_paths(&json, &paths).map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    let mut probed = match probed {
+                        Ok(probed) => probed,
+                        Err(e) => {
+                            info!("Error probing model: {e}");
+                            continue;
+                        }
+                    };
+                    let field = probed.pop().flatten();
+                    if !backfill.filter.matches_values(&probed) || !field.is_none_or(|field| field.is_null()) {
+                        continue;
+                    }
+                    match self.decode_record(&txn, key, value) {
+                        Ok(model) if model_value(&model, &backfill.field).is_none_or(|field| field.is_null()) => lacking.push(model),
+                        Ok(_) => {}
+                        Err(e) => info!("Error decoding model: {e}"),
+                    }
+                }
+            }
+
+            for mut model in lacking {
+                if set_path(&mut model.data, &segments, &backfill.value) {
+                    self.write_model(&mut txn, &writer, db, &mut model)?;
+                    done.updated += 1;
+                }
+            }
+            writer.commit(txn)?;
+
+            done.scanned += scanned;
+            progress(&done);
+            // Continue right after the last key visited
+            resume = last_key.map(|key| [key, vec![0x00]].concat());
+            if scanned < backfill.batch_size {
+                break;
+            }
+        }
+
+        info!("Backfilled {} on {} of {} records", backfill.field, done.updated, done.scanned);
+        Ok(done)
+    }
+}
+
+/// Sets the value at `segments` below `data`, creating missing or `null`
+/// parent objects. Returns `false` if a parent is another kind of value.
+fn set_path(data: &mut JsonValue, segments: &[&str], value: &JsonValue) -> bool {
+    let Some((last, parents)) = segments.split_last() else {
+        return false;
+    };
+
+    let mut node = data;
+    for segment in parents {
+        if node.is_null() {
+            *node = JsonValue::Object(Map::new());
+        }
+        let Some(object) = node.as_object_mut() else {
+            return false;
+        };
+        node = object.entry(segment.to_string()).or_insert(JsonValue::Null);
+    }
+
+    if node.is_null() {
+        *node = JsonValue::Object(Map::new());
+    }
+    match node.as_object_mut() {
+        Some(object) => {
+            object.insert(last.to_string(), value.clone());
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,97 @@
+//! Hot backups and compacting copies.
+//!
+//! [`AppDbState::backup_database`] copies the environment with
+//! `mdb_env_copy2` from within a read transaction, so the copy is a
+//! consistent snapshot of every database (records, indexes, metadata and
+//! side databases) while the app keeps reading and writing. Writes committed
+//! after the copy started are not part of it. The copy is a plain LMDB
+//! directory holding `data.mdb`; naming it `{name}.lmdb` lets
+//! [`AppDbState::init_with_path`] open it as the database `name`.
+//!
+//! [`AppDbState::compact_database`] copies with `MDB_CP_COMPACT` instead,
+//! leaving out the free pages that deletes accumulate, so the copy is often
+//! much smaller than the data file. [`AppDbState::compact`] does the same in
+//! place and swaps the data file afterwards.
+
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+
+use lmdb::Error as LmdbError;
+use lmdb_sys::{mdb_env_copy2, MDB_CP_COMPACT, MDB_SUCCESS};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::BackupResult;
+use crate::local_db_state::AppDbState;
+
+impl AppDbState {
+    /// Copies the database into `dest_dir`, created when missing, while it
+    /// stays open and writable, e.g. before a sync or on user request.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let backup = db.backup_database("backups/before_sync")?;
+    /// println!("{} bytes written to {}", backup.bytes, backup.path);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `dest_dir` already holds a
+    /// database, or a database error if the directory cannot be created or
+    /// the copy fails.
+    pub fn backup_database(&self, dest_dir: &str) -> Result<BackupResult, AppResponse> {
+        let result = self.copy_environment(dest_dir, 0)?;
+        info!("✅ Backed up {} to {dest_dir} ({} bytes)", self.path, result.bytes);
+        Ok(result)
+    }
+
+    /// Copies the database into `dest_dir` like
+    /// [`backup_database`](Self::backup_database), without free pages and
+    /// with the pages of each database renumbered in order.
+    ///
+    /// The copy takes longer than a plain backup but can be much smaller. It
+    /// holds the same records, indexes and metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `dest_dir` already holds a
+    /// database, or a database error if the directory cannot be created or
+    /// the copy fails.
+    pub fn compact_database(&self, dest_dir: &str) -> Result<BackupResult, AppResponse> {
+        let result = self.copy_environment(dest_dir, MDB_CP_COMPACT)?;
+        info!("✅ Compacted {} into {dest_dir} ({} bytes)", self.path, result.bytes);
+        Ok(result)
+    }
+
+    /// Copies the environment into `dest_dir` with the `mdb_env_copy2`
+    /// `flags`, removing a partial copy on failure.
+    pub(crate) fn copy_environment(&self, dest_dir: &str, flags: u32) -> Result<BackupResult, AppResponse> {
+        let dest_file = Path::new(dest_dir).join("data.mdb");
+        if dest_file.exists() {
+            return Err(AppResponse::BadRequest(format!("Cannot copy {} to {dest_dir}: it already holds a database", self.path)));
+        }
+        fs::create_dir_all(dest_dir).map_err(|e| AppResponse::DatabaseError(format!("Cannot create {dest_dir}: {e}")))?;
+
+        let (env, _) = self.env_db()?;
+        let c_path = CString::new(dest_dir)
+            .map_err(|_| AppResponse::BadRequest("Destination path contains a NUL byte".to_string()))?;
+        // SAFETY: the environment is open for the duration of the call and
+        // `c_path` is a valid NUL-terminated path to a directory without a
+        // data file.
+        let rc = unsafe { mdb_env_copy2(env.env(), c_path.as_ptr(), flags) };
+        if rc != MDB_SUCCESS {
+            let _ = fs::remove_file(&dest_file);
+            return Err(LmdbError::from_err_code(rc).into());
+        }
+
+        let bytes = fs::metadata(&dest_file).map(|file| file.len()).unwrap_or(0);
+        Ok(BackupResult { path: dest_file.to_string_lossy().into_owned(), bytes })
+    }
+}
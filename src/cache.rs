@@ -0,0 +1,241 @@
+//! Cache mode: bounded databases with oldest-first eviction.
+//!
+//! With a [`CacheLimit`] set, the `__cache` database tracks when each record
+//! was last written and how large its stored value is:
+//!
+//! ```text
+//! 'r' {id}                  -> {commit sequence, u64 BE} {value size, u64 BE}
+//! 'o' {commit sequence} {id} -> {id}
+//! ```
+//!
+//! The `'o'` entries order the records from least to most recently written,
+//! and the record count and byte total are kept in `__meta`. A writer that
+//! leaves the database above the limit deletes the oldest records in the
+//! same transaction, never the ones written by that transaction.
+//!
+//! Writes made without a limit are not tracked. The metadata remembers the
+//! commit sequence the tracking is current for, and setting a limit rebuilds
+//! the tracking when other writes happened since; records found by a rebuild
+//! count as older than any tracked write.
+
+use std::cell::Cell;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::CacheLimit;
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64, COMMIT_SEQUENCE_KEY, META_DB_NAME};
+use crate::scan::scan_from;
+
+/// Side database tracking the write order and size of records.
+pub(crate) const CACHE_DB_NAME: &str = "__cache";
+
+/// Commit sequence up to which the cache tracking is current.
+const CACHE_SEQUENCE_KEY: &str = "cache_sequence";
+const CACHE_RECORDS_KEY: &str = "cache_records";
+const CACHE_BYTES_KEY: &str = "cache_bytes";
+
+const RECORD_TAG: u8 = b'r';
+const ORDER_TAG: u8 = b'o';
+
+/// Cache tracking state of one write transaction.
+pub(crate) struct CacheTracker {
+    limit: CacheLimit,
+    cache_db: Database,
+    pub(crate) main_db: Database,
+    records: Cell<u64>,
+    bytes: Cell<u64>,
+}
+
+impl CacheTracker {
+    /// Records that `key` was written with a value of `len` bytes in the
+    /// transaction with commit sequence `sequence`.
+    pub(crate) fn track_put(&self, txn: &mut RwTransaction, key: &[u8], len: usize, sequence: u64) -> Result<(), LmdbError> {
+        if !self.untrack(txn, key)? {
+            self.records.set(self.records.get() + 1);
+        }
+        self.bytes.set(self.bytes.get() + len as u64);
+
+        let entry = [sequence.to_be_bytes(), (len as u64).to_be_bytes()].concat();
+        txn.put(self.cache_db, &record_key(key), &entry, WriteFlags::empty())?;
+        txn.put(self.cache_db, &order_key(sequence, key), &key, WriteFlags::empty())
+    }
+
+    /// Records that `key` was deleted.
+    pub(crate) fn track_del(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<(), LmdbError> {
+        if self.untrack(txn, key)? {
+            self.records.set(self.records.get().saturating_sub(1));
+        }
+        Ok(())
+    }
+
+    /// Records that every record was removed.
+    pub(crate) fn reset(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        self.records.set(0);
+        self.bytes.set(0);
+        txn.clear_db(self.cache_db)
+    }
+
+    /// Returns whether the database is above the limit.
+    pub(crate) fn over_limit(&self) -> bool {
+        self.limit.max_records.is_some_and(|max| self.records.get() > max)
+            || self.limit.max_bytes.is_some_and(|max| self.bytes.get() > max)
+    }
+
+    /// Returns the commit sequence and ID of the least recently written record.
+    pub(crate) fn oldest<T: Transaction>(&self, txn: &T) -> Result<Option<(u64, Vec<u8>)>, LmdbError> {
+        let cursor = txn.open_ro_cursor(self.cache_db)?;
+        let oldest = scan_from(&cursor, Some(&[ORDER_TAG]))
+            .take_while(|(key, _)| key.first() == Some(&ORDER_TAG))
+            .find_map(|(key, id)| {
+                let sequence: [u8; 8] = key.get(1..9)?.try_into().ok()?;
+                Some((u64::from_be_bytes(sequence), id.to_vec()))
+            });
+        Ok(oldest)
+    }
+
+    /// Stores the totals as current for the commit sequence `sequence`.
+    pub(crate) fn store(&self, txn: &mut RwTransaction, meta_db: Database, sequence: u64) -> Result<(), LmdbError> {
+        put_meta_u64(txn, meta_db, CACHE_RECORDS_KEY, self.records.get())?;
+        put_meta_u64(txn, meta_db, CACHE_BYTES_KEY, self.bytes.get())?;
+        put_meta_u64(txn, meta_db, CACHE_SEQUENCE_KEY, sequence)
+    }
+
+    /// Removes the tracking entries of `key`. Returns whether it was tracked.
+    fn untrack(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<bool, LmdbError> {
+        let entry = match txn.get(self.cache_db, &record_key(key)) {
+            Ok(entry) => entry.to_vec(),
+            Err(LmdbError::NotFound) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let (sequence, len) = decode_entry(&entry)?;
+
+        txn.del(self.cache_db, &record_key(key), None)?;
+        match txn.del(self.cache_db, &order_key(sequence, key), None) {
+            Ok(()) | Err(LmdbError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        self.bytes.set(self.bytes.get().saturating_sub(len));
+        Ok(true)
+    }
+}
+
+impl AppDbState {
+    /// Bounds the database as a cache, or removes the bounds with `None` (the
+    /// default). Returns the number of records evicted to fit the new limit.
+    ///
+    /// After every write that leaves the database above the limit, the least
+    /// recently written records are deleted in the same transaction. Reads do
+    /// not refresh a record; rewrite it to keep it. The limit is not
+    /// persisted; apps set it after opening the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::CacheLimit;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("image_cache".to_string())?;
+    /// db.set_cache_limit(Some(CacheLimit { max_records: Some(5000), max_bytes: Some(50 * 1024 * 1024) }))?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the limit has no bound or a
+    /// bound of zero, or a database error if tracking or eviction fails.
+    pub fn set_cache_limit(&mut self, limit: Option<CacheLimit>) -> Result<usize, AppResponse> {
+        if let Some(limit) = &limit {
+            let bounds = [limit.max_records, limit.max_bytes];
+            if bounds.iter().all(Option::is_none) || bounds.contains(&Some(0)) {
+                return Err(AppResponse::BadRequest("Cache limit needs max_records or max_bytes of at least 1".to_string()));
+            }
+        }
+
+        self.cache_limit = limit;
+        if self.cache_limit.is_none() {
+            return Ok(0);
+        }
+
+        self.rebuild_cache_tracking()?;
+        let (env, _) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        let evicted = writer.evict(&mut txn)?;
+        writer.commit(txn)?;
+        Ok(evicted)
+    }
+
+    /// Returns the current cache limit.
+    pub fn cache_limit(&self) -> Option<CacheLimit> {
+        self.cache_limit
+    }
+
+    /// Returns the cache tracking state for a writer within `txn`, `None`
+    /// when no limit is set.
+    pub(crate) fn cache_tracker<T: Transaction>(&self, txn: &T) -> Result<Option<CacheTracker>, LmdbError> {
+        let Some(limit) = self.cache_limit else {
+            return Ok(None);
+        };
+        let (_, main_db) = self.env_db()?;
+        let (_, cache_db) = self.side_db(CACHE_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        Ok(Some(CacheTracker {
+            limit,
+            cache_db,
+            main_db,
+            records: Cell::new(get_meta_u64(txn, meta_db, CACHE_RECORDS_KEY)?.unwrap_or(0)),
+            bytes: Cell::new(get_meta_u64(txn, meta_db, CACHE_BYTES_KEY)?.unwrap_or(0)),
+        }))
+    }
+
+    /// Rebuilds the tracking from the stored records unless it is current.
+    fn rebuild_cache_tracking(&self) -> Result<(), LmdbError> {
+        let (env, db) = self.env_db()?;
+        let (_, cache_db) = self.side_db(CACHE_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let sequence = get_meta_u64(&txn, meta_db, COMMIT_SEQUENCE_KEY)?.unwrap_or(0);
+        if get_meta_u64(&txn, meta_db, CACHE_SEQUENCE_KEY)? == Some(sequence) {
+            return Ok(());
+        }
+
+        let sizes: Vec<(Vec<u8>, u64)> = {
+            let cursor = txn.open_ro_cursor(db)?;
+            scan_from(&cursor, None).map(|(key, value)| (key.to_vec(), value.len() as u64)).collect()
+        };
+
+        txn.clear_db(cache_db)?;
+        let mut bytes = 0;
+        for (key, len) in &sizes {
+            let entry = [0u64.to_be_bytes(), len.to_be_bytes()].concat();
+            txn.put(cache_db, &record_key(key), &entry, WriteFlags::empty())?;
+            txn.put(cache_db, &order_key(0, key), key, WriteFlags::empty())?;
+            bytes += len;
+        }
+        put_meta_u64(&mut txn, meta_db, CACHE_RECORDS_KEY, sizes.len() as u64)?;
+        put_meta_u64(&mut txn, meta_db, CACHE_BYTES_KEY, bytes)?;
+        put_meta_u64(&mut txn, meta_db, CACHE_SEQUENCE_KEY, sequence)?;
+        txn.commit()?;
+
+        info!("Cache tracking of {} rebuilt for {} records", self.path, sizes.len());
+        Ok(())
+    }
+}
+
+fn record_key(id: &[u8]) -> Vec<u8> {
+    [&[RECORD_TAG], id].concat()
+}
+
+fn order_key(sequence: u64, id: &[u8]) -> Vec<u8> {
+    [&[ORDER_TAG], &sequence.to_be_bytes()[..], id].concat()
+}
+
+fn decode_entry(entry: &[u8]) -> Result<(u64, u64), LmdbError> {
+    let sequence: [u8; 8] = entry.get(..8).and_then(|bytes| bytes.try_into().ok()).ok_or(LmdbError::Corrupted)?;
+    let len: [u8; 8] = entry.get(8..16).and_then(|bytes| bytes.try_into().ok()).ok_or(LmdbError::Corrupted)?;
+    Ok((u64::from_be_bytes(sequence), u64::from_be_bytes(len)))
+}
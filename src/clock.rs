@@ -0,0 +1,79 @@
+//! Wall-clock time as seen by a database.
+//!
+//! Expiry checks and the timestamps the library stores read the time through
+//! the [`Clock`] of their database instead of the system clock. Apps correct
+//! a wrong device clock with the offset to server time observed during sync,
+//! so that times compared against server-written values agree; tests freeze
+//! the clock at a fixed instant. Durations such as debounce windows and rate
+//! limits use the monotonic clock and are not affected.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::local_db_state::AppDbState;
+
+/// Clock shared by a database and its background threads.
+#[derive(Default)]
+pub(crate) struct Clock {
+    /// Milliseconds added to the system time.
+    offset_ms: AtomicI64,
+    /// Fixed time in milliseconds since the Unix epoch, `0` when not fixed.
+    fixed_ms: AtomicU64,
+}
+
+impl Clock {
+    /// Returns the current time in milliseconds since the Unix epoch.
+    pub(crate) fn now_ms(&self) -> u64 {
+        match self.fixed_ms.load(Ordering::Relaxed) {
+            0 => {
+                let system = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+                system.saturating_add(self.offset_ms.load(Ordering::Relaxed)).max(0) as u64
+            }
+            fixed => fixed,
+        }
+    }
+}
+
+impl AppDbState {
+    /// Returns the current time of this database's clock, in milliseconds
+    /// since the Unix epoch.
+    ///
+    /// Apps should stamp records with this time rather than the device time,
+    /// so that their timestamps follow the same corrections.
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// Sets the milliseconds added to the system time, typically the server
+    /// time minus the device time measured during sync. `0` (the default)
+    /// uses the system time as is.
+    ///
+    /// The offset is not persisted; apps set it after opening the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let server_time_ms: u64 = 1_760_000_000_000; // From the sync response
+    /// db.set_clock_offset(server_time_ms as i64 - db.now_ms() as i64);
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    pub fn set_clock_offset(&self, offset_ms: i64) {
+        self.clock.offset_ms.store(offset_ms, Ordering::Relaxed);
+    }
+
+    /// Returns the offset set with [`AppDbState::set_clock_offset`].
+    pub fn clock_offset(&self) -> i64 {
+        self.clock.offset_ms.load(Ordering::Relaxed)
+    }
+
+    /// Freezes the clock at `now_ms`, ignoring the system time and the
+    /// offset, or lets it run again with `None`. Meant for tests of
+    /// time-dependent behavior such as expiry.
+    pub fn set_fixed_clock(&self, now_ms: Option<u64>) {
+        self.clock.fixed_ms.store(now_ms.unwrap_or(0), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,185 @@
+//! Named collections.
+//!
+//! Besides the records of `main`, a database holds up to [`MAX_COLLECTIONS`]
+//! collections, e.g. one per logical table of the app. Each is a named LMDB
+//! database in the same environment, so [`AppDbState::drop_collection`]
+//! removes a whole table in one write transaction, however many records it
+//! holds, instead of deleting them one by one.
+//!
+//! Collection records are encoded, and encrypted for tenants with a
+//! registered key, like the records of `main`. They are not indexed, logged
+//! for delta consumers or watched.
+
+use lmdb::{Database, DatabaseFlags, Error as LmdbError, Transaction, WriteFlags};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+
+/// Maximum number of collections in a database.
+pub const MAX_COLLECTIONS: u32 = 32;
+
+/// Prefix of the LMDB database name of a collection.
+const COLLECTION_PREFIX: &str = "collection:";
+
+impl AppDbState {
+    /// Inserts or replaces a record in `collection`, creating the collection
+    /// when missing.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::LocalDbModel;
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("shop".to_string())?;
+    ///
+    /// let order = LocalDbModel { id: "o1".to_string(), hash: "h1".to_string(), data: json!({"total": 42}), ..Default::default() };
+    /// db.collection_put("orders", &order)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the collection name or the ID
+    /// is empty, or if the collection would exceed [`MAX_COLLECTIONS`].
+    pub fn collection_put(&self, collection: &str, model: &LocalDbModel) -> Result<(), AppResponse> {
+        if model.id.is_empty() {
+            return Err(AppResponse::BadRequest("Record ID cannot be empty".to_string()));
+        }
+        self.throttle_write()?;
+        let db = self.create_collection(collection)?;
+        let (env, _) = self.env_db()?;
+
+        let value = self.encode_sealed(model)?;
+        let mut txn = env.begin_rw_txn()?;
+        txn.put(db, &model.id, &value, WriteFlags::empty())?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Retrieves a record of `collection` by ID; `None` if the record or the
+    /// collection does not exist.
+    pub fn collection_get(&self, collection: &str, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        let Some(db) = self.open_collection(collection)? else {
+            return Ok(None);
+        };
+        let (env, _) = self.env_db()?;
+
+        let txn = env.begin_ro_txn()?;
+        match txn.get(db, &id) {
+            Ok(value) => Ok(Some(self.decode_sealed(id.as_bytes(), value)?)),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retrieves every record of `collection`, in ID order.
+    pub fn collection_get_all(&self, collection: &str) -> Result<Vec<LocalDbModel>, AppResponse> {
+        let Some(db) = self.open_collection(collection)? else {
+            return Ok(Vec::new());
+        };
+        let (env, _) = self.env_db()?;
+
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+        scan_from(&cursor, None)
+            .map(|(key, value)| self.decode_sealed(key, value))
+            .collect()
+    }
+
+    /// Deletes a record of `collection`. Returns whether it existed.
+    pub fn collection_delete(&self, collection: &str, id: &str) -> Result<bool, AppResponse> {
+        let Some(db) = self.open_collection(collection)? else {
+            return Ok(false);
+        };
+        self.throttle_write()?;
+        let (env, _) = self.env_db()?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let deleted = match txn.del(db, &id, None) {
+            Ok(()) => true,
+            Err(LmdbError::NotFound) => false,
+            Err(e) => return Err(e.into()),
+        };
+        txn.commit()?;
+        Ok(deleted)
+    }
+
+    /// Returns the names of the collections, in name order.
+    pub fn collections(&self) -> Result<Vec<String>, AppResponse> {
+        let (env, _) = self.env_db()?;
+        let root = env.open_db(None)?;
+
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(root)?;
+        Ok(scan_from(&cursor, Some(COLLECTION_PREFIX.as_bytes()))
+            .take_while(|(key, _)| key.starts_with(COLLECTION_PREFIX.as_bytes()))
+            .map(|(key, _)| String::from_utf8_lossy(&key[COLLECTION_PREFIX.len()..]).into_owned())
+            .collect())
+    }
+
+    /// Removes `collection` and all of its records in one write transaction.
+    /// Returns whether the collection existed.
+    ///
+    /// Takes `&mut self` so that no other call uses the collection while its
+    /// LMDB handle is released.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("shop".to_string())?;
+    /// db.drop_collection("orders")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the collection name is empty,
+    /// or a database error if the transaction fails.
+    pub fn drop_collection(&mut self, collection: &str) -> Result<bool, AppResponse> {
+        let Some(db) = self.open_collection(collection)? else {
+            return Ok(false);
+        };
+        let (env, _) = self.env_db()?;
+
+        let mut txn = env.begin_rw_txn()?;
+        // SAFETY: `&mut self` excludes every other use of the handle, and
+        // later calls open the collection again.
+        unsafe { txn.drop_db(db)? };
+        txn.commit()?;
+        Ok(true)
+    }
+
+    /// Opens the LMDB database of `collection`, `None` if it does not exist.
+    pub(crate) fn open_collection(&self, collection: &str) -> Result<Option<Database>, AppResponse> {
+        let name = collection_db_name(collection)?;
+        let (env, _) = self.env_db()?;
+        match env.open_db(Some(&name)) {
+            Ok(db) => Ok(Some(db)),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Opens the LMDB database of `collection`, creating it when missing.
+    pub(crate) fn create_collection(&self, collection: &str) -> Result<Database, AppResponse> {
+        let name = collection_db_name(collection)?;
+        let (env, _) = self.env_db()?;
+        env.create_db(Some(&name), DatabaseFlags::empty()).map_err(|e| match e {
+            LmdbError::DbsFull => AppResponse::BadRequest(format!("Cannot create collection {collection}: at most {MAX_COLLECTIONS} collections")),
+            e => e.into(),
+        })
+    }
+}
+
+fn collection_db_name(collection: &str) -> Result<String, AppResponse> {
+    if collection.is_empty() || collection.contains('\0') {
+        return Err(AppResponse::BadRequest("Collection name cannot be empty or contain NUL".to_string()));
+    }
+    Ok(format!("{COLLECTION_PREFIX}{collection}"))
+}
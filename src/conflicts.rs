@@ -0,0 +1,406 @@
+//! Conflict inbox.
+//!
+//! [`AppDbState::merge_remote`] applies the changes pulled from the server.
+//! A record changed locally since it was last pulled, and not yet
+//! acknowledged by the sync layer's delta consumer (see [`crate::delta`]),
+//! is not overwritten when the server's version differs: both versions go to
+//! the conflict inbox instead. The app lists them with
+//! [`AppDbState::list_conflicts`] and settles each with
+//! [`AppDbState::resolve_conflict`], e.g. from a manual resolution UI.
+//!
+//! [`AppDbState::apply_remote_changes`] settles new conflicts with a
+//! [`ConflictPolicy`] instead: last write wins by a timestamp in the
+//! records, keep one side, or ask a callback, which may still send a
+//! conflict to the inbox. In CRDT mode (see [`crate::crdt`]) both merge the
+//! conflicting versions field by field first.
+//!
+//! The inbox lives in the `__conflicts` database, next to the hash of the
+//! last server version merged for each record, which tells local edits apart
+//! from records written by a merge:
+//!
+//! ```text
+//! 'b' {id}  -> {hash of the last merged server version, empty when deleted}
+//! 't' {id}  -> {detection time in ms, u64 BE}
+//! 'l' {id}  -> {local version, encoded like a record}    absent when deleted locally
+//! 'r' {id}  -> {server version, encoded like a record}   absent when deleted on the server
+//! ```
+//!
+//! Versions are encoded, and encrypted for tenants with a registered key, like
+//! the records themselves.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+
+use crate::app_response::AppResponse;
+use crate::crdt::{crdt_resolution, latest_clock};
+use crate::hlc::next_hlc;
+use crate::local_db_model::{ConflictEntry, ConflictPolicy, ConflictResolution, ConflictSide, LocalDbModel, MergeResult, RemoteChanges};
+use crate::local_db_state::AppDbState;
+use crate::meta::{decode_u64, META_DB_NAME};
+use crate::query::{model_value, sort_order};
+use crate::scan::scan_from;
+use crate::writer::RecordWriter;
+
+/// Side database holding the conflict inbox and the merged hashes.
+pub(crate) const CONFLICTS_DB_NAME: &str = "__conflicts";
+
+const BASE_TAG: u8 = b'b';
+const TIME_TAG: u8 = b't';
+const LOCAL_TAG: u8 = b'l';
+const REMOTE_TAG: u8 = b'r';
+
+impl AppDbState {
+    /// Applies server changes, moving those that conflict with a pending
+    /// local change to the conflict inbox.
+    ///
+    /// A server change conflicts when the record was changed locally since
+    /// its last merge, the change is not acknowledged by `changes.consumer`,
+    /// and both sides ended up with different hashes (a deletion on one side
+    /// only counts as different). A new server version of a record already in
+    /// the inbox replaces the stored one. Everything else is written or
+    /// deleted in one transaction. Records sealed with the sync key are
+    /// decrypted first, see [`AppDbState::open_sync_records`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::RemoteChanges;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let pulled: RemoteChanges = serde_json::from_str(r#"{"records":[{"id":"n1","hash":"h2","data":{}}]}"#).unwrap();
+    /// let result = db.merge_remote(&pulled)?;
+    /// if !result.conflicts.is_empty() {
+    ///     // Show the conflict inbox
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the consumer is empty, an
+    /// error if a sealed record cannot be decrypted, or an error if a record
+    /// cannot be written.
+    pub fn merge_remote(&self, changes: &RemoteChanges) -> Result<MergeResult, AppResponse> {
+        self.apply_remote_changes(changes, &ConflictPolicy::Inbox, |_| Ok(None))
+    }
+
+    /// Applies server changes like [`merge_remote`](Self::merge_remote),
+    /// settling the conflicts it detects with `policy`.
+    ///
+    /// With [`ConflictPolicy::Callback`], `defer` receives each conflict and
+    /// returns how to settle it, or `None` to move it to the inbox; it runs
+    /// within the write transaction and must not use the database. Records
+    /// already in the inbox stay there, with their server version updated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::{ConflictPolicy, RemoteChanges};
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let pulled: RemoteChanges = serde_json::from_str(r#"{"records":[{"id":"n1","hash":"h2","data":{"updated_at":1736812800000}}]}"#).unwrap();
+    /// let policy = ConflictPolicy::LastWriteWins("data.updated_at".to_string());
+    /// let result = db.apply_remote_changes(&pulled, &policy, |_| Ok(None))?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the consumer is empty, the
+    /// timestamp path of [`ConflictPolicy::LastWriteWins`] is not below
+    /// `data`, or a merged record has another ID, the error of `defer`, an
+    /// error if a sealed record cannot be decrypted, or an error if a record
+    /// cannot be written. Nothing is applied on error.
+    pub fn apply_remote_changes<F>(&self, changes: &RemoteChanges, policy: &ConflictPolicy, mut defer: F) -> Result<MergeResult, AppResponse>
+    where
+        F: FnMut(&ConflictEntry) -> Result<Option<ConflictResolution>, AppResponse>,
+    {
+        if let ConflictPolicy::LastWriteWins(path) = policy {
+            if path.strip_prefix("data.").is_none_or(|rest| rest.split('.').any(str::is_empty)) {
+                return Err(AppResponse::BadRequest(format!("Timestamp path {path} is not a path below data")));
+            }
+        }
+
+        let records = self.open_sync_records(&changes.records)?;
+        let delta = self.peek_delta(&changes.consumer)?;
+        let pending: BTreeMap<String, Option<String>> = delta
+            .records
+            .into_iter()
+            .map(|record| (record.id, Some(record.hash)))
+            .chain(delta.deleted.into_iter().map(|id| (id, None)))
+            .collect();
+
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let (_, conflicts_db) = self.side_db(CONFLICTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        let detected_at = self.now_ms();
+        let mut result = MergeResult::default();
+
+        // Local edits after the merge must order after the merged ones
+        if let Some(latest) = records.iter().filter_map(latest_clock).max().filter(|_| writer.crdt) {
+            let (_, meta_db) = self.side_db(META_DB_NAME)?;
+            next_hlc(&mut txn, meta_db, detected_at, Some(latest))?;
+        }
+
+        let remote = records
+            .iter()
+            .map(|record| (record.id.as_str(), Some(record)))
+            .chain(changes.deleted.iter().map(|id| (id.as_str(), None)));
+        for (id, remote) in remote {
+            let remote_hash = remote.map(|record| record.hash.as_str());
+
+            if read(&txn, conflicts_db, TIME_TAG, id)?.is_some() {
+                self.put_version(&mut txn, conflicts_db, REMOTE_TAG, id, remote)?;
+                result.conflicts.push(id.to_string());
+                continue;
+            }
+
+            if let Some(local_hash) = pending.get(id) {
+                if local_hash.as_deref() == remote_hash {
+                    put_base(&mut txn, conflicts_db, id, remote_hash)?;
+                    result.unchanged += 1;
+                    continue;
+                }
+                let base = read(&txn, conflicts_db, BASE_TAG, id)?;
+                if base != Some(local_hash.as_deref().unwrap_or("").as_bytes()) {
+                    let local = match txn.get(db, &id) {
+                        Ok(value) => Some(self.decode_record(&txn, id.as_bytes(), value)?),
+                        Err(LmdbError::NotFound) => None,
+                        Err(e) => return Err(e.into()),
+                    };
+                    let conflict = ConflictEntry { id: id.to_string(), local, remote: remote.cloned(), detected_at };
+                    let resolution = match writer.crdt.then(|| crdt_resolution(&conflict)).flatten() {
+                        Some(merged) => Some(merged),
+                        None if *policy == ConflictPolicy::Callback => defer(&conflict)?,
+                        None => policy_resolution(policy, &conflict),
+                    };
+                    if let Some(resolution) = resolution {
+                        self.settle(&mut txn, &writer, db, conflicts_db, id, conflict.remote, &resolution)?;
+                        result.resolved += 1;
+                        continue;
+                    }
+
+                    txn.put(conflicts_db, &key(TIME_TAG, id), &detected_at.to_be_bytes(), WriteFlags::empty())?;
+                    self.put_version(&mut txn, conflicts_db, LOCAL_TAG, id, conflict.local.as_ref())?;
+                    self.put_version(&mut txn, conflicts_db, REMOTE_TAG, id, conflict.remote.as_ref())?;
+                    result.conflicts.push(id.to_string());
+                    continue;
+                }
+            }
+
+            if self.apply_remote(&mut txn, &writer, db, conflicts_db, id, remote)? {
+                match remote {
+                    Some(_) => result.applied += 1,
+                    None => result.deleted += 1,
+                }
+            }
+        }
+
+        self.track_sync_run(&mut txn, |run| {
+            run.pulled += result.applied as u64;
+            run.deleted += result.deleted as u64;
+            run.conflicts += result.conflicts.len() as u64;
+        })?;
+        writer.commit(txn)?;
+        Ok(result)
+    }
+
+    /// Returns the records in the conflict inbox, in ID order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read, or if a version is
+    /// encrypted for a tenant whose key is not registered.
+    pub fn list_conflicts(&self) -> Result<Vec<ConflictEntry>, AppResponse> {
+        let (env, conflicts_db) = self.side_db(CONFLICTS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(conflicts_db)?;
+
+        scan_from(&cursor, Some(&[TIME_TAG]))
+            .take_while(|(key, _)| key.first() == Some(&TIME_TAG))
+            .map(|(key, value)| {
+                let id = String::from_utf8_lossy(&key[1..]).into_owned();
+                Ok(ConflictEntry {
+                    local: self.read_version(&txn, conflicts_db, LOCAL_TAG, &id)?,
+                    remote: self.read_version(&txn, conflicts_db, REMOTE_TAG, &id)?,
+                    detected_at: decode_u64(value)?,
+                    id,
+                })
+            })
+            .collect()
+    }
+
+    /// Settles the conflict of the record `id` and removes it from the
+    /// inbox. Returns the record now stored, `None` if it is deleted.
+    ///
+    /// Keeping the local version leaves the record as it is, pending upload.
+    /// Taking the server version writes (or deletes) it like a merge. A
+    /// merged record is written as a local change, pending upload.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::{ConflictResolution, ConflictSide};
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// for conflict in db.list_conflicts()? {
+    ///     db.resolve_conflict(&conflict.id, &ConflictResolution::Keep(ConflictSide::Remote))?;
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the record has no conflict,
+    /// [`AppResponse::BadRequest`] if a merged record has another ID, or an
+    /// error if the record cannot be written.
+    pub fn resolve_conflict(&self, id: &str, resolution: &ConflictResolution) -> Result<Option<LocalDbModel>, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let (_, conflicts_db) = self.side_db(CONFLICTS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+
+        if read(&txn, conflicts_db, TIME_TAG, id)?.is_none() {
+            return Err(AppResponse::NotFound(format!("No conflict for record {id}")));
+        }
+        let remote = self.read_version(&txn, conflicts_db, REMOTE_TAG, id)?;
+        let resolved = self.settle(&mut txn, &writer, db, conflicts_db, id, remote, resolution)?;
+
+        for tag in [TIME_TAG, LOCAL_TAG, REMOTE_TAG] {
+            match txn.del(conflicts_db, &key(tag, id), None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        writer.commit(txn)?;
+        Ok(resolved)
+    }
+
+    /// Settles the conflict of `id` with the server version `remote`.
+    /// Returns the record now stored, `None` if it is deleted.
+    #[allow(clippy::too_many_arguments)]
+    fn settle(
+        &self,
+        txn: &mut RwTransaction,
+        writer: &RecordWriter,
+        db: Database,
+        conflicts_db: Database,
+        id: &str,
+        remote: Option<LocalDbModel>,
+        resolution: &ConflictResolution,
+    ) -> Result<Option<LocalDbModel>, AppResponse> {
+        match resolution {
+            ConflictResolution::Keep(ConflictSide::Local) => {
+                put_base(txn, conflicts_db, id, remote.as_ref().map(|record| record.hash.as_str()))?;
+                match txn.get(db, &id) {
+                    Ok(value) => Ok(Some(self.decode_record(txn, id.as_bytes(), value)?)),
+                    Err(LmdbError::NotFound) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            ConflictResolution::Keep(ConflictSide::Remote) => {
+                self.apply_remote(txn, writer, db, conflicts_db, id, remote.as_ref())?;
+                Ok(remote)
+            }
+            ConflictResolution::Merged(merged) => {
+                if merged.id != id {
+                    return Err(AppResponse::BadRequest(format!("Merged record has id {}, expected {id}", merged.id)));
+                }
+                put_base(txn, conflicts_db, id, remote.as_ref().map(|record| record.hash.as_str()))?;
+                let mut merged = merged.clone();
+                self.write_model(txn, writer, db, &mut merged)?;
+                Ok(Some(merged))
+            }
+        }
+    }
+
+    /// Writes or deletes the server version of `id` and records its hash as
+    /// merged. Returns whether a record was written or deleted.
+    fn apply_remote(
+        &self,
+        txn: &mut RwTransaction,
+        writer: &RecordWriter,
+        db: Database,
+        conflicts_db: Database,
+        id: &str,
+        remote: Option<&LocalDbModel>,
+    ) -> Result<bool, AppResponse> {
+        put_base(txn, conflicts_db, id, remote.map(|record| record.hash.as_str()))?;
+        match remote {
+            Some(record) => {
+                let mut record = record.clone();
+                self.write_model(txn, writer, db, &mut record)?;
+                Ok(true)
+            }
+            None => Ok(writer.del(txn, db, id.as_bytes())?),
+        }
+    }
+
+    /// Stores one version of a conflict, or removes it for a deletion.
+    fn put_version(&self, txn: &mut RwTransaction, conflicts_db: Database, tag: u8, id: &str, version: Option<&LocalDbModel>) -> Result<(), AppResponse> {
+        let Some(version) = version else {
+            return match txn.del(conflicts_db, &key(tag, id), None) {
+                Ok(()) | Err(LmdbError::NotFound) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+        };
+        txn.put(conflicts_db, &key(tag, id), &self.encode_sealed(version)?, WriteFlags::empty())?;
+        Ok(())
+    }
+
+    fn read_version<T: Transaction>(&self, txn: &T, conflicts_db: Database, tag: u8, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        match read(txn, conflicts_db, tag, id)? {
+            Some(value) => Ok(Some(self.decode_sealed(id.as_bytes(), value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns how a built-in `policy` settles `conflict`, `None` to move it to
+/// the inbox.
+fn policy_resolution(policy: &ConflictPolicy, conflict: &ConflictEntry) -> Option<ConflictResolution> {
+    let side = match policy {
+        ConflictPolicy::Inbox | ConflictPolicy::Callback => return None,
+        ConflictPolicy::KeepLocal => ConflictSide::Local,
+        ConflictPolicy::KeepRemote => ConflictSide::Remote,
+        ConflictPolicy::LastWriteWins(path) => {
+            // A deletion, or a version without a timestamp, is the oldest
+            let timestamp = |version: &Option<LocalDbModel>| {
+                version.as_ref().and_then(|model| model_value(model, path)).filter(|value| value.is_number() || value.is_string())
+            };
+            match sort_order(timestamp(&conflict.local).as_ref(), timestamp(&conflict.remote).as_ref()) {
+                Ordering::Greater => ConflictSide::Local,
+                Ordering::Less | Ordering::Equal => ConflictSide::Remote,
+            }
+        }
+    };
+    Some(ConflictResolution::Keep(side))
+}
+
+fn key(tag: u8, id: &str) -> Vec<u8> {
+    [&[tag], id.as_bytes()].concat()
+}
+
+fn read<'txn, T: Transaction>(txn: &'txn T, conflicts_db: Database, tag: u8, id: &str) -> Result<Option<&'txn [u8]>, LmdbError> {
+    match txn.get(conflicts_db, &key(tag, id)) {
+        Ok(value) => Ok(Some(value)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records `hash` as the last merged server version of `id`, `None` for a
+/// deletion.
+fn put_base(txn: &mut RwTransaction, conflicts_db: Database, id: &str, hash: Option<&str>) -> Result<(), LmdbError> {
+    txn.put(conflicts_db, &key(BASE_TAG, id), &hash.unwrap_or(""), WriteFlags::empty())
+}
@@ -0,0 +1,243 @@
+//! Cross-database record copy and sharding.
+//!
+//! Used to split one monolithic database into per-feature databases or into
+//! hash shards: the environments are opened side by side and records are
+//! streamed from the source into the destinations. Values are copied byte for
+//! byte, so the value header (format, flags, schema version) is preserved;
+//! only records with overflowed fields are re-encoded with the fields inlined,
+//! since their chunks stay in the source. Records encrypted for a tenant stay
+//! sealed, as their ciphertext is bound to the ID and header and not to the
+//! environment; filtering or sharding them needs the tenant's key on the
+//! source, and the destination needs it to read them.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use lmdb::{Cursor, Error as LmdbError, Transaction};
+use log::{info, warn};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::ShardResult;
+use crate::local_db_state::AppDbState;
+use crate::query::{probe_paths, PathFilter};
+use crate::registry::{self, SharedDb};
+
+/// Key/value pairs written to a destination in one transaction.
+type Batch<'a> = Vec<(&'a [u8], Cow<'a, [u8]>)>;
+
+/// Number of records written per destination transaction.
+const COPY_BATCH_SIZE: usize = 500;
+
+impl AppDbState {
+    /// Copies the records of `src_db_name` matching `filter` into `dst_db_name`.
+    ///
+    /// Both names are database names as passed to [`AppDbState::init`]. The
+    /// destination is created when missing and records with the same ID are
+    /// overwritten. The source is read in a single read transaction while the
+    /// destination is written in batches of 500 records, so a failure part-way
+    /// leaves the batches already committed in place. Source records that cannot
+    /// be decoded are skipped and logged, except records of a tenant whose key
+    /// is not registered on the source, which fail the copy.
+    ///
+    /// Returns the number of records copied.
+    ///
+    /// A database opened over FFI is copied through its open environment, as
+    /// LMDB must not open an environment twice in one process; one opened with
+    /// [`AppDbState::init`] should be dropped before copying.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    ///
+    /// let filter: PathFilter = serde_json::from_str(r#"{"data.feature":"todos"}"#).unwrap();
+    /// let copied = AppDbState::copy_records("app", "todos", &filter);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the source database does not exist,
+    /// [`AppResponse::BadRequest`] if source and destination are the same or a
+    /// record is encrypted for a tenant whose key is not registered, or a
+    /// database error if either environment cannot be opened or written.
+    pub fn copy_records(src_db_name: &str, dst_db_name: &str, filter: &PathFilter) -> Result<usize, AppResponse> {
+        if src_db_name == dst_db_name {
+            return Err(AppResponse::BadRequest("Source and destination databases must differ".to_string()));
+        }
+
+        let src = open_existing(src_db_name)?;
+        let dst = open_shared(dst_db_name)?;
+        let src = src.read().unwrap_or_else(PoisonError::into_inner);
+        let dst = dst.read().unwrap_or_else(PoisonError::into_inner);
+
+        let (src_env, src_db) = src.env_db()?;
+        let txn = src_env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(src_db)?;
+
+        let mut batch: Batch<'_> = Vec::with_capacity(COPY_BATCH_SIZE);
+        let mut copied = 0;
+
+        for (key, value) in cursor.iter() {
+            let selected = src.record_json(key, value).and_then(|json| {
+                let matches = filter.matches_json(&json)?;
+                matches.then(|| src.inline_overflow(&txn, value)).transpose()
+            });
+
+            match selected {
+                Ok(Some(value)) => batch.push((key, value)),
+                Ok(None) => {}
+                // Encrypted for a tenant whose key is not registered
+                Err(AppResponse::BadRequest(e)) => {
+                    return Err(AppResponse::BadRequest(format!("Cannot copy {}: {e}", String::from_utf8_lossy(key))));
+                }
+                Err(e) => {
+                    warn!("Skipping undecodable record {:?}: {e}", String::from_utf8_lossy(key));
+                    continue;
+                }
+            }
+
+            if batch.len() == COPY_BATCH_SIZE {
+                copied += dst.write_batch(&batch)?;
+                batch.clear();
+            }
+        }
+
+        if !batch.is_empty() {
+            copied += dst.write_batch(&batch)?;
+        }
+
+        info!("Copied {copied} records from {src_db_name} to {dst_db_name}");
+        Ok(copied)
+    }
+
+    /// Redistributes the records of `src_db_name` into `shard_count` databases
+    /// by a hash of the value at `field_path`.
+    ///
+    /// Shard `i` is the database `{src_db_name}_shard_{i}`. The hash is FNV-1a
+    /// over the compact JSON encoding of the field value, so the assignment is
+    /// stable across runs and platforms; records without the field hash as
+    /// `null` and therefore all land in the same shard. Undecodable records are
+    /// skipped and logged, except records of a tenant whose key is not
+    /// registered on the source, which fail the run. The source database is left untouched so the caller
+    /// can verify the shards before resetting it. Shard databases must not
+    /// exist yet, so records of an earlier run never mix into the new shards.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let result = AppDbState::shard_by("catalog", "data.category", 4);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `shard_count` is zero, a shard
+    /// database already exists or a record is encrypted for a tenant whose key
+    /// is not registered, [`AppResponse::NotFound`] if the source
+    /// database does not exist, or a database error if an environment cannot
+    /// be opened or written.
+    pub fn shard_by(src_db_name: &str, field_path: &str, shard_count: usize) -> Result<ShardResult, AppResponse> {
+        if shard_count == 0 {
+            return Err(AppResponse::BadRequest("shard_count must be at least 1".to_string()));
+        }
+
+        let names: Vec<String> = (0..shard_count).map(|i| format!("{src_db_name}_shard_{i}")).collect();
+        if let Some(name) = names.iter().find(|name| Path::new(&format!("{name}.lmdb")).exists()) {
+            return Err(AppResponse::BadRequest(format!("Shard database {name} already exists")));
+        }
+
+        let src = open_existing(src_db_name)?;
+        let src = src.read().unwrap_or_else(PoisonError::into_inner);
+        let shards = names
+            .iter()
+            .map(|name| AppDbState::init(name.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (src_env, src_db) = src.env_db()?;
+        let txn = src_env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(src_db)?;
+
+        let mut batches: Vec<Batch<'_>> = vec![Vec::new(); shard_count];
+        let mut counts = vec![0; shard_count];
+
+        for (key, value) in cursor.iter() {
+            let field = src.record_json(key, value).and_then(|json| {
+                let mut probed = probe_paths(&json, &[field_path])?;
+                Ok((probed.pop().flatten().unwrap_or_default(), src.inline_overflow(&txn, value)?))
+            });
+
+            let (field, value) = match field {
+                Ok(field) => field,
+                // Encrypted for a tenant whose key is not registered
+                Err(AppResponse::BadRequest(e)) => {
+                    return Err(AppResponse::BadRequest(format!("Cannot shard {}: {e}", String::from_utf8_lossy(key))));
+                }
+                Err(e) => {
+                    warn!("Skipping undecodable record {:?}: {e}", String::from_utf8_lossy(key));
+                    continue;
+                }
+            };
+
+            let shard = (fnv1a(field.to_string().as_bytes()) % shard_count as u64) as usize;
+            batches[shard].push((key, value));
+
+            if batches[shard].len() == COPY_BATCH_SIZE {
+                counts[shard] += shards[shard].write_batch(&batches[shard])?;
+                batches[shard].clear();
+            }
+        }
+
+        for (shard, batch) in batches.iter().enumerate() {
+            if !batch.is_empty() {
+                counts[shard] += shards[shard].write_batch(batch)?;
+            }
+        }
+
+        info!("Sharded {src_db_name} by {field_path} into {shard_count} databases");
+        Ok(ShardResult { shards: names, counts })
+    }
+
+    /// Writes raw key/value pairs in one transaction.
+    fn write_batch(&self, batch: &[(&[u8], Cow<[u8]>)]) -> Result<usize, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+
+        for (key, value) in batch {
+            writer.put(&mut txn, db, key, value)?;
+            // Raw values are not versioned; later updates are sent in full once
+            if let Some(versions) = &writer.versions {
+                versions.track_del(&mut txn, key)?;
+            }
+        }
+
+        writer.commit(txn)?;
+        Ok(batch.len())
+    }
+}
+
+/// Returns the database `name` if it is open over FFI, or opens it.
+fn open_shared(name: &str) -> Result<SharedDb, LmdbError> {
+    match registry::find(&format!("{name}.lmdb")) {
+        Some(db) => Ok(db),
+        None => Ok(Arc::new(RwLock::new(AppDbState::init(name.to_string())?))),
+    }
+}
+
+/// Opens like [`open_shared`] a database that must already exist on disk.
+fn open_existing(name: &str) -> Result<SharedDb, AppResponse> {
+    if !Path::new(&format!("{name}.lmdb")).exists() {
+        return Err(AppResponse::NotFound(format!("Source database {name} does not exist")));
+    }
+    Ok(open_shared(name)?)
+}
+
+/// 64-bit FNV-1a hash; unlike `DefaultHasher` it is stable across Rust releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
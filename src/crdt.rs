@@ -0,0 +1,181 @@
+//! CRDT merge of JSON documents.
+//!
+//! By default a record edited on two devices is a conflict, settled by a
+//! [`ConflictPolicy`](crate::local_db_model::ConflictPolicy) or in the
+//! conflict inbox, and one side's edits are lost unless the app merges them.
+//! In CRDT mode the top-level fields of `data` are last-writer-wins
+//! registers: every write stamps the fields it changes with its HLC
+//! timestamp (see [`crate::hlc`]), in a `$clock` member of `data`
+//!
+//! ```json
+//! {"title": "Groceries", "done": true, "$clock": {"title": "0192a3b4c5d60000", "done": "0192a3b4c9e10003"}}
+//! ```
+//!
+//! and [`AppDbState::merge_remote`] merges the two versions of a conflicting
+//! record field by field, keeping the value with the later timestamp. A
+//! removed field keeps its timestamp, so the removal wins over older edits.
+//! Timestamps are packed like [`crate::hlc`] as 16 hex digits; equal ones
+//! are broken by the greater value, so every device computes the same
+//! merge. The merged record gets the hash `crdt_{FNV-1a of its data}` and is
+//! pending upload.
+//!
+//! An edit wins over a deletion of the record on the other device. Records
+//! whose `data` is not an object are settled by the policy as before.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::copy::fnv1a;
+use crate::local_db_model::{ConflictEntry, ConflictResolution, ConflictSide, Hlc, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::meta::{CRDT_MODE_KEY, META_DB_NAME};
+
+/// Member of `data` holding the timestamp of each field.
+pub(crate) const CLOCK_KEY: &str = "$clock";
+
+impl AppDbState {
+    /// Turns CRDT mode on or off. The mode is persisted, and every device
+    /// syncing the records must use the same one.
+    ///
+    /// Records written before CRDT mode was turned on have no timestamps;
+    /// their fields lose against any stamped edit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// db.set_crdt_mode(true)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write fails.
+    pub fn set_crdt_mode(&self, enabled: bool) -> Result<(), AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        if enabled {
+            txn.put(meta_db, &CRDT_MODE_KEY, &1u64.to_be_bytes(), WriteFlags::empty())?;
+        } else {
+            match txn.del(meta_db, &CRDT_MODE_KEY, None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns whether CRDT mode is on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn crdt_mode(&self) -> Result<bool, LmdbError> {
+        Ok(self.meta_u64(CRDT_MODE_KEY)?.is_some())
+    }
+}
+
+/// Stamps the fields of `model` that differ from `previous`, its stored
+/// version, with `hlc`, unless it carries a later timestamp for them, as a
+/// merged server version does.
+pub(crate) fn stamp_clocks(previous: Option<&LocalDbModel>, model: &mut LocalDbModel, hlc: Hlc) {
+    let Some(fields) = model.data.as_object_mut() else {
+        return;
+    };
+    let empty = Map::new();
+    let previous = previous.and_then(|previous| previous.data.as_object()).unwrap_or(&empty);
+    let incoming = fields.remove(CLOCK_KEY);
+    let stamp = format!("{:016x}", hlc.to_u64());
+
+    let known = clock_map(previous);
+    let carried = incoming.as_ref().and_then(JsonValue::as_object);
+    let mut clocks = known.cloned().unwrap_or_default();
+    let names: BTreeSet<&String> = previous.keys().chain(fields.keys()).filter(|name| *name != CLOCK_KEY).collect();
+    for name in names {
+        if previous.get(name) == fields.get(name) {
+            continue;
+        }
+        let carried = Some(clock(carried, name)).filter(|carried| *carried > clock(known, name));
+        clocks.insert(name.clone(), JsonValue::String(carried.map_or_else(|| stamp.clone(), str::to_string)));
+    }
+    fields.insert(CLOCK_KEY.to_string(), JsonValue::Object(clocks));
+}
+
+/// Returns how CRDT mode settles `conflict`, `None` for the policy to
+/// settle it.
+pub(crate) fn crdt_resolution(conflict: &ConflictEntry) -> Option<ConflictResolution> {
+    let (local, remote) = match (&conflict.local, &conflict.remote) {
+        (Some(local), Some(remote)) => (local, remote),
+        // The edit wins over the deletion
+        (Some(_), None) => return Some(ConflictResolution::Keep(ConflictSide::Local)),
+        (None, Some(_)) => return Some(ConflictResolution::Keep(ConflictSide::Remote)),
+        (None, None) => return None,
+    };
+    let (JsonValue::Object(local_fields), JsonValue::Object(remote_fields)) = (&local.data, &remote.data) else {
+        return None;
+    };
+
+    let local_clocks = clock_map(local_fields);
+    let remote_clocks = clock_map(remote_fields);
+    let mut merged = Map::new();
+    let mut clocks = Map::new();
+    let names: BTreeSet<&String> = [local_fields, remote_fields]
+        .into_iter()
+        .chain(local_clocks)
+        .chain(remote_clocks)
+        .flat_map(Map::keys)
+        .filter(|name| *name != CLOCK_KEY)
+        .collect();
+    for name in names {
+        let (local_clock, remote_clock) = (clock(local_clocks, name), clock(remote_clocks, name));
+        let local_wins = match local_clock.cmp(remote_clock) {
+            Ordering::Equal => tie_value(local_fields.get(name)) >= tie_value(remote_fields.get(name)),
+            order => order == Ordering::Greater,
+        };
+        let (winner, winner_clock) = if local_wins { (local_fields, local_clock) } else { (remote_fields, remote_clock) };
+        if let Some(value) = winner.get(name) {
+            merged.insert(name.clone(), value.clone());
+        }
+        if !winner_clock.is_empty() {
+            clocks.insert(name.clone(), JsonValue::String(winner_clock.to_string()));
+        }
+    }
+    merged.insert(CLOCK_KEY.to_string(), JsonValue::Object(clocks));
+
+    let data = JsonValue::Object(merged);
+    let hash = format!("crdt_{:016x}", fnv1a(data.to_string().as_bytes()));
+    Some(ConflictResolution::Merged(LocalDbModel { id: conflict.id.clone(), hash, data, ..LocalDbModel::default() }))
+}
+
+/// Returns the latest field timestamp of `record`, `None` without any.
+pub(crate) fn latest_clock(record: &LocalDbModel) -> Option<Hlc> {
+    let clocks = clock_map(record.data.as_object()?)?;
+    clocks
+        .values()
+        .filter_map(|clock| u64::from_str_radix(clock.as_str()?, 16).ok())
+        .max()
+        .map(Hlc::from_u64)
+}
+
+/// Returns the field timestamps of the fields of a record.
+fn clock_map(fields: &Map<String, JsonValue>) -> Option<&Map<String, JsonValue>> {
+    fields.get(CLOCK_KEY)?.as_object()
+}
+
+/// Returns the timestamp of the field `name`, empty if it has none.
+fn clock<'a>(clocks: Option<&'a Map<String, JsonValue>>, name: &str) -> &'a str {
+    clocks.and_then(|clocks| clocks.get(name)?.as_str()).unwrap_or("")
+}
+
+/// Orders the values of a field with equal timestamps; an absent field
+/// orders first.
+fn tie_value(value: Option<&JsonValue>) -> Option<String> {
+    value.map(JsonValue::to_string)
+}
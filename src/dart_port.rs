@@ -0,0 +1,71 @@
+//! Change events posted to Dart isolate ports.
+//!
+//! A [`WatchCallback`](crate::WatchCallback) runs on the dispatcher thread,
+//! which Dart can only receive through a `NativeCallable.listener`. Flutter
+//! apps usually stream native events into a `ReceivePort` instead: the app
+//! passes `NativeApi.postCObject` once to
+//! [`register_dart_post_cobject`](crate::register_dart_post_cobject), then
+//! the `sendPort.nativePort` of each port to
+//! [`watch_port`](crate::watch_port). Every batch is posted to the port as a
+//! Dart `String` holding the same response JSON a callback receives, and
+//! Dart owns the message, so nothing has to be freed.
+//!
+//! Posting needs no Dart headers: the message is a `Dart_CObject` of type
+//! `Dart_CObject_kString`, laid out as in `dart_native_api.h`.
+
+use std::ffi::{c_void, CString};
+use std::os::raw::c_char;
+use std::sync::{PoisonError, RwLock};
+
+/// `Dart_Port` of a `SendPort`, `0` being the illegal port.
+pub type DartPort = i64;
+
+/// `Dart_PostCObject`, as exposed by `NativeApi.postCObject`. Returns whether
+/// the message was posted; it is not once the port is closed.
+pub type DartPostCObjectFn = unsafe extern "C" fn(port: DartPort, message: *mut c_void) -> bool;
+
+/// `Dart_CObject_kString`.
+pub(crate) const DART_COBJECT_STRING: i32 = 5;
+
+/// `Dart_CObject` restricted to the members used here.
+#[repr(C)]
+pub(crate) struct DartCObject {
+    pub(crate) kind: i32,
+    pub(crate) value: DartCObjectValue,
+}
+
+/// Value union of `Dart_CObject`, padded to its largest member,
+/// `as_external_typed_data`.
+#[repr(C)]
+pub(crate) union DartCObjectValue {
+    pub(crate) as_string: *const c_char,
+    _size: [u64; 5],
+}
+
+static POST_COBJECT: RwLock<Option<DartPostCObjectFn>> = RwLock::new(None);
+
+/// Sets the function posting messages to Dart ports, replacing the one set
+/// before, e.g. by the app before a hot restart.
+pub(crate) fn set_post_cobject(post: DartPostCObjectFn) {
+    *POST_COBJECT.write().unwrap_or_else(PoisonError::into_inner) = Some(post);
+}
+
+/// Returns whether a function posting messages was set.
+pub(crate) fn is_registered() -> bool {
+    POST_COBJECT.read().unwrap_or_else(PoisonError::into_inner).is_some()
+}
+
+/// Posts `message` to `port` as a Dart `String`. Returns whether it was
+/// posted.
+pub(crate) fn post_string(port: DartPort, message: &str) -> bool {
+    let Some(post) = *POST_COBJECT.read().unwrap_or_else(PoisonError::into_inner) else {
+        return false;
+    };
+    let Ok(message) = CString::new(message) else {
+        return false;
+    };
+    let mut object = DartCObject { kind: DART_COBJECT_STRING, value: DartCObjectValue { as_string: message.as_ptr() } };
+    // SAFETY: `post` is `Dart_PostCObject`, which copies the message before
+    // returning, and `object` and the string outlive the call.
+    unsafe { post(port, (&mut object as *mut DartCObject).cast()) }
+}
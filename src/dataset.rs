@@ -0,0 +1,255 @@
+//! Prebuilt asset databases and their differential updates.
+//!
+//! Seed catalogs shipped with an app are generated from a JSON dataset by a
+//! build tool. The records are written in key order into a staging database,
+//! which is then copied with `MDB_CP_COMPACT` so the shipped file has no free
+//! pages and the same input always produces the same set of records.
+//!
+//! Later app updates ship signed [`DatasetPatch`] files instead of the whole
+//! dataset. Every database records its dataset version in the metadata
+//! database, and a patch only applies on top of the version it was made for.
+
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+
+use lmdb::{Transaction, WriteFlags};
+use lmdb_sys::{mdb_env_copy2, MDB_CP_COMPACT, MDB_SUCCESS};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{BuildOptions, BuildResult, DatasetPatch, LocalDbModel, PatchResult};
+use crate::local_db_state::{AppDbState, SIDE_DB_NAMES};
+use crate::meta::{put_meta_u64, DATASET_VERSION_KEY, META_DB_NAME};
+use crate::signing::verify_detached;
+use crate::value_codec::{encode_model_with, ValueHeader};
+
+impl AppDbState {
+    /// Generates the compacted database `{output_name}.lmdb` from a dataset file.
+    ///
+    /// The dataset is either a JSON array of records or NDJSON (one record per
+    /// line, blank lines ignored); every record has the `id`, `hash`, `data`
+    /// shape. An existing output database is replaced. The result is meant to be
+    /// attached with [`AppDbState::attach_asset_db`].
+    ///
+    /// Only the records and the dataset version are shipped; the state the
+    /// staging database keeps for itself, such as its shutdown marker, commit
+    /// sequence and clock, is dropped before the copy.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::BuildOptions;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let options = BuildOptions { compress: false, schema_version: 1, dataset_version: 1 };
+    /// let result = AppDbState::build_prebuilt_db("data/catalog.ndjson", "assets/catalog", &options);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the dataset cannot be read,
+    /// [`AppResponse::SerializationError`] if a record is malformed or cannot be
+    /// encoded with `options`, [`AppResponse::BadRequest`] if an ID appears
+    /// twice, or a database error if writing or compacting fails.
+    pub fn build_prebuilt_db(input_path: &str, output_name: &str, options: &BuildOptions) -> Result<BuildResult, AppResponse> {
+        let dataset = fs::read_to_string(input_path)
+            .map_err(|e| AppResponse::NotFound(format!("Cannot read dataset {input_path}: {e}")))?;
+
+        let mut models = parse_dataset(&dataset)?;
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        if let Some(pair) = models.windows(2).find(|pair| pair[0].id == pair[1].id) {
+            return Err(AppResponse::BadRequest(format!("Duplicate record id in dataset: {}", pair[0].id)));
+        }
+
+        let header = ValueHeader {
+            compressed: options.compress,
+            schema_version: options.schema_version,
+            ..ValueHeader::default()
+        };
+
+        let staging_name = format!("{output_name}.staging");
+        let staging_dir = format!("{staging_name}.lmdb");
+        remove_dir_if_exists(&staging_dir)?;
+
+        {
+            let staging = AppDbState::init(staging_name)?;
+            let (env, db) = staging.env_db()?;
+            let (_, meta) = staging.side_db(META_DB_NAME)?;
+            let mut txn = env.begin_rw_txn()?;
+
+            // Keys arrive sorted, so appending fills pages completely.
+            for model in &models {
+                let value = encode_model_with(model, header)?;
+                txn.put(db, &model.id, &value, WriteFlags::APPEND)?;
+            }
+            for name in SIDE_DB_NAMES {
+                txn.clear_db(staging.side_db(name)?.1)?;
+            }
+            put_meta_u64(&mut txn, meta, DATASET_VERSION_KEY, options.dataset_version)?;
+            txn.commit()?;
+
+            let output_dir = format!("{output_name}.lmdb");
+            remove_dir_if_exists(&output_dir)?;
+            fs::create_dir_all(&output_dir)
+                .map_err(|e| AppResponse::DatabaseError(format!("Cannot create {output_dir}: {e}")))?;
+
+            let c_path = CString::new(output_dir.as_str())
+                .map_err(|_| AppResponse::BadRequest("Output name contains a NUL byte".to_string()))?;
+            // SAFETY: the environment is open for the duration of the call and
+            // `c_path` is a valid NUL-terminated path to an empty directory.
+            let rc = unsafe { mdb_env_copy2(env.env(), c_path.as_ptr(), MDB_CP_COMPACT) };
+            if rc != MDB_SUCCESS {
+                return Err(lmdb::Error::from_err_code(rc).into());
+            }
+        }
+
+        remove_dir_if_exists(&staging_dir)?;
+
+        info!("✅ Prebuilt database {output_name}.lmdb generated with {} records", models.len());
+        Ok(BuildResult {
+            path: format!("{output_name}.lmdb"),
+            records: models.len(),
+        })
+    }
+
+    /// Applies a signed [`DatasetPatch`] file onto this database.
+    ///
+    /// The patch file must be accompanied by `{patch_path}.sig` holding the hex
+    /// ed25519 signature of the file, which is checked against
+    /// `public_key_hex` before anything is parsed. The patch is then applied in
+    /// a single write transaction together with the dataset version bump, so
+    /// it is either fully applied or not at all.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("catalog".to_string())?;
+    /// let public_key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    ///
+    /// let result = db.apply_dataset_patch("updates/catalog-v4.json", public_key);
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the patch or signature cannot be
+    /// read, [`AppResponse::BadRequest`] if the signature is invalid or the patch
+    /// was made for a different dataset version, [`AppResponse::SerializationError`]
+    /// if the patch is malformed, or a database error if the write fails.
+    pub fn apply_dataset_patch(&self, patch_path: &str, public_key_hex: &str) -> Result<PatchResult, AppResponse> {
+        let bytes = fs::read(patch_path)
+            .map_err(|e| AppResponse::NotFound(format!("Cannot read patch {patch_path}: {e}")))?;
+        verify_detached(patch_path, &bytes, public_key_hex)?;
+
+        let mut patch: DatasetPatch = serde_json::from_slice(&bytes)?;
+        let current = self.dataset_version()?;
+        if patch.from_version != current || patch.to_version <= patch.from_version {
+            return Err(AppResponse::BadRequest(format!(
+                "Patch {} -> {} does not apply to dataset version {current}",
+                patch.from_version, patch.to_version
+            )));
+        }
+
+        let (env, db) = self.env_db()?;
+        let (_, meta) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        let mut result = PatchResult { version: patch.to_version, upserted: 0, deleted: 0 };
+
+        for model in &mut patch.upserts {
+            self.write_model(&mut txn, &writer, db, model)?;
+            result.upserted += 1;
+        }
+        for id in &patch.deletes {
+            if writer.del(&mut txn, db, id.as_bytes())? {
+                result.deleted += 1;
+            }
+        }
+        put_meta_u64(&mut txn, meta, DATASET_VERSION_KEY, patch.to_version)?;
+        writer.commit(txn)?;
+
+        info!("✅ Dataset patched from version {current} to {}", patch.to_version);
+        Ok(result)
+    }
+
+    /// Imports the records of a dataset file into this database.
+    ///
+    /// The file uses the same JSON array or NDJSON format as
+    /// [`AppDbState::build_prebuilt_db`]. When `public_key_hex` is given, the
+    /// file must be accompanied by `{path}.sig` and is only parsed once the
+    /// ed25519 signature checks out, so content distributed outside the app
+    /// store can be authenticated before it is loaded. All records are written
+    /// in one transaction, replacing records with the same ID.
+    ///
+    /// Returns the number of records imported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("user_data".to_string())?;
+    /// let public_key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    ///
+    /// let imported = db.import_from_file("downloads/recipes.ndjson", Some(public_key));
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the file or its signature cannot be
+    /// read, [`AppResponse::BadRequest`] if the signature is invalid,
+    /// [`AppResponse::SerializationError`] if a record is malformed, or a
+    /// database error if the write fails.
+    pub fn import_from_file(&self, path: &str, public_key_hex: Option<&str>) -> Result<usize, AppResponse> {
+        let bytes = fs::read(path)
+            .map_err(|e| AppResponse::NotFound(format!("Cannot read dataset {path}: {e}")))?;
+        if let Some(public_key_hex) = public_key_hex {
+            verify_detached(path, &bytes, public_key_hex)?;
+        }
+
+        let dataset = std::str::from_utf8(&bytes)
+            .map_err(|e| AppResponse::SerializationError(format!("Invalid UTF-8 in dataset: {e}")))?;
+        let mut models = parse_dataset(dataset)?;
+
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        for model in &mut models {
+            self.write_model(&mut txn, &writer, db, model)?;
+        }
+        writer.commit(txn)?;
+
+        info!("✅ Imported {} records from {path}", models.len());
+        Ok(models.len())
+    }
+}
+
+/// Parses a JSON array or NDJSON dataset into records.
+pub(crate) fn parse_dataset(dataset: &str) -> Result<Vec<LocalDbModel>, AppResponse> {
+    if dataset.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(dataset)?);
+    }
+
+    dataset
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                AppResponse::SerializationError(format!("Invalid record on line {}: {e}", index + 1))
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn remove_dir_if_exists(dir: &str) -> Result<(), AppResponse> {
+    if Path::new(dir).exists() {
+        fs::remove_dir_all(dir)
+            .map_err(|e| AppResponse::DatabaseError(format!("Cannot remove {dir}: {e}")))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,424 @@
+//! Changes-only reads for named consumers.
+//!
+//! A consumer, such as a feature of the app keeping its own derived state,
+//! calls [`AppDbState::get_all_delta`] to receive the records changed since
+//! it last acknowledged a commit sequence with [`AppDbState::ack_delta`].
+//! Every consumer moves at its own pace.
+//!
+//! While at least one consumer exists, record writers log each change in the
+//! `__changes` database:
+//!
+//! ```text
+//! 'c' {consumer}                      -> {acknowledged sequence, u64 BE}
+//! 'r' {id}                            -> {sequence of the last change, u64 BE}
+//! 's' {sequence, u64 BE} {id}         -> 'p' (written) or 'd' (deleted), {change time in ms, u64 BE}
+//! 'x'                                 -> {sequence below which cursors are reset, u64 BE}
+//! ```
+//!
+//! Only the last change of a record is kept, and changes acknowledged by every
+//! consumer are pruned. A consumer that has not acknowledged anything yet, or
+//! whose cursor predates a clear, receives every record with `reset` set.
+//! Without consumers nothing is logged.
+//!
+//! A consumer that stops acknowledging, e.g. a feature the user turned off,
+//! keeps every later change and deletion in the log. Apps bound the log with
+//! [`AppDbState::purge_changelog`], or with a retention window applied by
+//! [`AppDbState::run_maintenance`]; consumers whose cursor predates the
+//! purged changes receive every record with `reset` set, as after a clear.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ChangeDelta, ChangelogCutoff, ChangelogPurge};
+use crate::local_db_state::AppDbState;
+use crate::meta::{decode_u64, get_meta_u64, COMMIT_SEQUENCE_KEY, META_DB_NAME};
+use crate::scan::scan_from;
+use crate::versions::VERSIONS_DB_NAME;
+
+/// Side database holding the change log and the consumer cursors.
+pub(crate) const CHANGES_DB_NAME: &str = "__changes";
+
+const CONSUMER_TAG: u8 = b'c';
+const RECORD_TAG: u8 = b'r';
+const SEQUENCE_TAG: u8 = b's';
+const RESET_KEY: &[u8] = b"x";
+
+const WRITTEN: u8 = b'p';
+const DELETED: u8 = b'd';
+
+impl AppDbState {
+    /// Returns the records changed since the consumer `consumer` last
+    /// acknowledged a sequence, and the IDs of the records deleted since.
+    ///
+    /// The first call of a consumer registers it and returns every record
+    /// with `reset` set, as does a call after [`AppDbState::clear_all_records`].
+    /// Pass the returned `sequence` to [`AppDbState::ack_delta`] once the
+    /// changes are processed; until then the same changes, and any newer
+    /// ones, are returned again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("todos".to_string())?;
+    ///
+    /// let delta = db.get_all_delta("search_index")?;
+    /// for record in &delta.records {
+    ///     // reindex record
+    /// }
+    /// db.ack_delta("search_index", delta.sequence)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `consumer` is empty, or a
+    /// database error if a transaction fails.
+    pub fn get_all_delta(&self, consumer: &str) -> Result<ChangeDelta, AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+        let key = consumer_key(consumer)?;
+
+        let registered = {
+            let txn = env.begin_ro_txn()?;
+            read_u64(&txn, changes_db, &key)?.is_some()
+        };
+        if !registered {
+            // Register before reading, so deletions after the read are logged
+            let mut txn = env.begin_rw_txn()?;
+            if read_u64(&txn, changes_db, &key)?.is_none() {
+                txn.put(changes_db, &key, &0u64.to_be_bytes(), WriteFlags::empty())?;
+            }
+            txn.commit()?;
+        }
+
+        self.peek_delta(consumer)
+    }
+
+    /// Returns the delta [`AppDbState::get_all_delta`] would return, without
+    /// registering `consumer`; an unknown consumer gets every record.
+    pub(crate) fn peek_delta(&self, consumer: &str) -> Result<ChangeDelta, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let key = consumer_key(consumer)?;
+
+        let txn = env.begin_ro_txn()?;
+        let since = read_u64(&txn, changes_db, &key)?.unwrap_or(0);
+        let sequence = get_meta_u64(&txn, meta_db, COMMIT_SEQUENCE_KEY)?.unwrap_or(0);
+        let cleared = read_u64(&txn, changes_db, RESET_KEY)?.unwrap_or(0);
+
+        let mut delta = ChangeDelta {
+            consumer: consumer.to_string(),
+            since,
+            sequence,
+            reset: since == 0 || since < cleared,
+            records: Vec::new(),
+            deleted: Vec::new(),
+        };
+
+        if delta.reset {
+            let cursor = txn.open_ro_cursor(db)?;
+            for (key, value) in scan_from(&cursor, None) {
+                delta.records.push(self.decode_record(&txn, key, value)?);
+            }
+            return Ok(delta);
+        }
+
+        let start = sequence_key(since + 1, b"");
+        let cursor = txn.open_ro_cursor(changes_db)?;
+        for (key, op) in scan_from(&cursor, Some(&start)).take_while(|(key, _)| key.first() == Some(&SEQUENCE_TAG)) {
+            let id = key.get(9..).ok_or(LmdbError::Corrupted)?;
+            if op.first() == Some(&DELETED) {
+                delta.deleted.push(String::from_utf8_lossy(id).into_owned());
+                continue;
+            }
+            match txn.get(db, &id) {
+                Ok(value) => delta.records.push(self.decode_record(&txn, id, value)?),
+                Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(delta)
+    }
+
+    /// Advances the cursor of `consumer` to `sequence`, the `sequence` of a
+    /// delta it has processed. A cursor never moves backwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `consumer` is empty or
+    /// `sequence` is beyond the current commit sequence, or a database error
+    /// if the write fails.
+    pub fn ack_delta(&self, consumer: &str, sequence: u64) -> Result<(), AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let key = consumer_key(consumer)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let current = get_meta_u64(&txn, meta_db, COMMIT_SEQUENCE_KEY)?.unwrap_or(0);
+        if sequence > current {
+            return Err(AppResponse::BadRequest(format!("Sequence {sequence} is beyond the commit sequence {current}")));
+        }
+
+        let acknowledged = read_u64(&txn, changes_db, &key)?.unwrap_or(0);
+        if sequence > acknowledged {
+            let pushed = self.acknowledged_changes(&txn, changes_db, acknowledged, sequence)?;
+            self.track_sync_run(&mut txn, |run| {
+                if run.consumer == consumer {
+                    run.pushed += pushed;
+                }
+            })?;
+        }
+        txn.put(changes_db, &key, &sequence.max(acknowledged).to_be_bytes(), WriteFlags::empty())?;
+        prune(&mut txn, changes_db)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the number of changes a consumer acknowledges by moving its
+    /// cursor from `from` to `to`: every record when the cursor was reset.
+    fn acknowledged_changes<T: Transaction>(&self, txn: &T, changes_db: Database, from: u64, to: u64) -> Result<u64, AppResponse> {
+        let cleared = read_u64(txn, changes_db, RESET_KEY)?.unwrap_or(0);
+        if from == 0 || from < cleared {
+            let (_, db) = self.env_db()?;
+            let cursor = txn.open_ro_cursor(db)?;
+            return Ok(scan_from(&cursor, None).count() as u64);
+        }
+
+        let start = sequence_key(from + 1, b"");
+        let end = sequence_key(to + 1, b"");
+        let cursor = txn.open_ro_cursor(changes_db)?;
+        Ok(scan_from(&cursor, Some(&start)).take_while(|(key, _)| *key < end.as_slice()).count() as u64)
+    }
+
+    /// Removes the consumer `consumer` and its cursor. Returns whether it
+    /// existed. The change log and the record versions kept for delta
+    /// encoding are dropped with the last consumer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `consumer` is empty, or a
+    /// database error if the write fails.
+    pub fn remove_delta_consumer(&self, consumer: &str) -> Result<bool, AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+        let key = consumer_key(consumer)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let removed = match txn.del(changes_db, &key, None) {
+            Ok(()) => true,
+            Err(LmdbError::NotFound) => false,
+            Err(e) => return Err(e.into()),
+        };
+        if has_consumers(&txn, changes_db)? {
+            prune(&mut txn, changes_db)?;
+        } else {
+            let (_, versions_db) = self.side_db(VERSIONS_DB_NAME)?;
+            txn.clear_db(changes_db)?;
+            txn.clear_db(versions_db)?;
+        }
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Sets the age in milliseconds after which
+    /// [`run_maintenance`](Self::run_maintenance) purges logged changes, or
+    /// `None` to keep them until every consumer acknowledged them (the
+    /// default).
+    ///
+    /// The window is not persisted; apps set it after opening the database.
+    pub fn set_changelog_retention(&mut self, max_age_ms: Option<u64>) {
+        self.changelog_retention = max_age_ms;
+    }
+
+    /// Returns the current changelog retention window.
+    pub fn changelog_retention(&self) -> Option<u64> {
+        self.changelog_retention
+    }
+
+    /// Deletes the logged changes and deletions before `before`, whether or
+    /// not every consumer acknowledged them.
+    ///
+    /// The consumers that had not acknowledged every purged change receive
+    /// every record with `reset` set on their next delta, and are listed in
+    /// the result.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::ChangelogCutoff;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("todos".to_string())?;
+    ///
+    /// // Keep one week of changes
+    /// let purge = db.purge_changelog(ChangelogCutoff::AgeMs(7 * 24 * 60 * 60 * 1000))?;
+    /// println!("Purged {} changes, reset {:?}", purge.purged, purge.reset);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a database error if the write fails.
+    pub fn purge_changelog(&self, before: ChangelogCutoff) -> Result<ChangelogPurge, AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let stale: Vec<Vec<u8>> = {
+            let cursor = txn.open_ro_cursor(changes_db)?;
+            let changes = scan_from(&cursor, Some(&[SEQUENCE_TAG])).take_while(|(key, _)| key.first() == Some(&SEQUENCE_TAG));
+            match before {
+                ChangelogCutoff::Sequence(sequence) => {
+                    let end = sequence_key(sequence, b"");
+                    changes.take_while(|(key, _)| *key < end.as_slice()).map(|(key, _)| key.to_vec()).collect()
+                }
+                ChangelogCutoff::AgeMs(age) => {
+                    // Purge a prefix of the log, so cursors stay meaningful
+                    // even if the clock went backwards
+                    let cutoff = self.now_ms().saturating_sub(age);
+                    changes.take_while(|(_, change)| changed_at(change) < cutoff).map(|(key, _)| key.to_vec()).collect()
+                }
+            }
+        };
+        let Some(last) = stale.last() else {
+            return Ok(ChangelogPurge::default());
+        };
+
+        let purged_to = decode_u64(last.get(1..9).ok_or(LmdbError::Corrupted)?)?;
+        let reset_below = read_u64(&txn, changes_db, RESET_KEY)?.unwrap_or(0);
+        let reset = consumer_cursors(&txn, changes_db)?
+            .into_iter()
+            .filter(|(_, since)| *since != 0 && *since >= reset_below && *since < purged_to)
+            .map(|(consumer, _)| consumer)
+            .collect();
+
+        remove_changes(&mut txn, changes_db, &stale)?;
+        if purged_to > reset_below {
+            txn.put(changes_db, &RESET_KEY, &purged_to.to_be_bytes(), WriteFlags::empty())?;
+        }
+        txn.commit()?;
+
+        info!("Purged {} changes up to sequence {purged_to} from the change log", stale.len());
+        Ok(ChangelogPurge { purged: stale.len(), reset })
+    }
+
+    /// Returns the change log for a writer within `txn`, `None` when there
+    /// are no consumers.
+    pub(crate) fn change_log<T: Transaction>(&self, txn: &T) -> Result<Option<Database>, LmdbError> {
+        let (_, changes_db) = self.side_db(CHANGES_DB_NAME)?;
+        Ok(has_consumers(txn, changes_db)?.then_some(changes_db))
+    }
+}
+
+/// Logs that `id` was written (or deleted, with `deleted`) by the transaction
+/// with commit sequence `sequence` at `changed_at`, replacing its previous
+/// change.
+pub(crate) fn log_change(txn: &mut RwTransaction, changes_db: Database, id: &[u8], sequence: u64, changed_at: u64, deleted: bool) -> Result<(), LmdbError> {
+    let record = [&[RECORD_TAG], id].concat();
+    let previous = read_u64(txn, changes_db, &record)?;
+    if let Some(previous) = previous {
+        match txn.del(changes_db, &sequence_key(previous, id), None) {
+            Ok(()) | Err(LmdbError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let op = if deleted { DELETED } else { WRITTEN };
+    let change = [&[op], &changed_at.to_be_bytes()[..]].concat();
+    txn.put(changes_db, &sequence_key(sequence, id), &change, WriteFlags::empty())?;
+    txn.put(changes_db, &record, &sequence.to_be_bytes(), WriteFlags::empty())
+}
+
+/// Drops the logged changes and marks every record as removed at `sequence`.
+pub(crate) fn log_clear(txn: &mut RwTransaction, changes_db: Database, sequence: u64) -> Result<(), LmdbError> {
+    let stale: Vec<Vec<u8>> = {
+        let cursor = txn.open_ro_cursor(changes_db)?;
+        scan_from(&cursor, None)
+            .map(|(key, _)| key)
+            .filter(|key| matches!(key.first(), Some(&RECORD_TAG) | Some(&SEQUENCE_TAG)))
+            .map(<[u8]>::to_vec)
+            .collect()
+    };
+    for key in stale {
+        txn.del(changes_db, &key, None)?;
+    }
+    txn.put(changes_db, &RESET_KEY, &sequence.to_be_bytes(), WriteFlags::empty())
+}
+
+/// Deletes the changes every consumer has acknowledged.
+fn prune(txn: &mut RwTransaction, changes_db: Database) -> Result<(), LmdbError> {
+    let stale: Vec<Vec<u8>> = {
+        let cursor = txn.open_ro_cursor(changes_db)?;
+        let oldest = oldest_cursor(txn, changes_db)?;
+        let end = sequence_key(oldest + 1, b"");
+        scan_from(&cursor, Some(&[SEQUENCE_TAG]))
+            .map(|(key, _)| key)
+            .take_while(|key| key.first() == Some(&SEQUENCE_TAG) && *key < end.as_slice())
+            .map(<[u8]>::to_vec)
+            .collect()
+    };
+    remove_changes(txn, changes_db, &stale)
+}
+
+/// Deletes the logged changes at the sequence keys `stale`.
+fn remove_changes(txn: &mut RwTransaction, changes_db: Database, stale: &[Vec<u8>]) -> Result<(), LmdbError> {
+    for key in stale {
+        txn.del(changes_db, key, None)?;
+        let record = [&[RECORD_TAG], &key[9..]].concat();
+        match txn.del(changes_db, &record, None) {
+            Ok(()) | Err(LmdbError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns every consumer with the sequence it acknowledged.
+fn consumer_cursors<T: Transaction>(txn: &T, changes_db: Database) -> Result<Vec<(String, u64)>, LmdbError> {
+    let cursor = txn.open_ro_cursor(changes_db)?;
+    scan_from(&cursor, Some(&[CONSUMER_TAG]))
+        .take_while(|(key, _)| key.first() == Some(&CONSUMER_TAG))
+        .map(|(key, value)| Ok((String::from_utf8_lossy(&key[1..]).into_owned(), decode_u64(value)?)))
+        .collect()
+}
+
+/// Returns the oldest sequence acknowledged by a consumer, `0` without
+/// consumers.
+pub(crate) fn oldest_cursor<T: Transaction>(txn: &T, changes_db: Database) -> Result<u64, LmdbError> {
+    Ok(consumer_cursors(txn, changes_db)?.into_iter().map(|(_, since)| since).min().unwrap_or(0))
+}
+
+/// Returns the time of a logged change, `0` for changes logged without one.
+fn changed_at(change: &[u8]) -> u64 {
+    change.get(1..9).and_then(|time| time.try_into().ok()).map_or(0, u64::from_be_bytes)
+}
+
+fn has_consumers<T: Transaction>(txn: &T, changes_db: Database) -> Result<bool, LmdbError> {
+    let cursor = txn.open_ro_cursor(changes_db)?;
+    let found = scan_from(&cursor, Some(&[CONSUMER_TAG])).next().is_some_and(|(key, _)| key.first() == Some(&CONSUMER_TAG));
+    Ok(found)
+}
+
+fn consumer_key(consumer: &str) -> Result<Vec<u8>, AppResponse> {
+    if consumer.is_empty() {
+        return Err(AppResponse::BadRequest("Consumer name cannot be empty".to_string()));
+    }
+    Ok([&[CONSUMER_TAG], consumer.as_bytes()].concat())
+}
+
+fn sequence_key(sequence: u64, id: &[u8]) -> Vec<u8> {
+    [&[SEQUENCE_TAG], &sequence.to_be_bytes()[..], id].concat()
+}
+
+fn read_u64<T: Transaction>(txn: &T, db: Database, key: &[u8]) -> Result<Option<u64>, LmdbError> {
+    match txn.get(db, &key) {
+        Ok(bytes) => Ok(Some(decode_u64(bytes)?)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,297 @@
+//! Sync digests for fast sync diffs.
+//!
+//! Before exchanging records, client and server compare a [`SyncDigest`] of
+//! their records to find the key ranges where they differ. The ID space is
+//! split into [`DIGEST_BUCKETS`] buckets by the first byte of the FNV-1a
+//! hash of the ID, and each bucket hashes the records it holds; the root
+//! hashes the buckets, forming a two-level Merkle tree. Equal roots mean
+//! equal records, and otherwise only the records of the divergent buckets
+//! need to be listed with [`AppDbState::sync_digest_records`] and compared.
+//!
+//! Every value is computed with 64-bit FNV-1a, so a server reproduces the
+//! digest from the IDs and hashes of its records alone:
+//!
+//! ```text
+//! record  = fnv1a({id} 0x00 {hash})
+//! bucket  = fnv1a({record count, u64 BE} {XOR of its records, u64 BE})
+//! root    = fnv1a({bucket 0, u64 BE} ... {bucket 255, u64 BE})
+//! ```
+//!
+//! The buckets are maintained on every write once the digest was first
+//! requested, in the `__digest` database:
+//!
+//! ```text
+//! 'b' {bucket}  -> {record count, u64 BE} {XOR of its records, u64 BE}
+//! 'r' {id}      -> {record, u64 BE}
+//! ```
+//!
+//! The records of collections are not synced through
+//! [`AppDbState::merge_remote`] and are not part of the digest.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde::Deserialize;
+
+use crate::app_response::AppResponse;
+use crate::copy::fnv1a;
+use crate::local_db_model::SyncDigest;
+use crate::local_db_state::AppDbState;
+use crate::meta::{decode_u64, get_meta_u64, put_meta_u64, META_DB_NAME, SYNC_DIGEST_KEY};
+use crate::scan::scan_from;
+use crate::value_codec::json_payload;
+
+/// Side database holding the digest buckets.
+pub(crate) const DIGEST_DB_NAME: &str = "__digest";
+
+/// Number of buckets of a [`SyncDigest`].
+pub(crate) const DIGEST_BUCKETS: usize = 256;
+
+const BUCKET_TAG: u8 = b'b';
+const RECORD_TAG: u8 = b'r';
+
+/// Keeps the digest buckets current within a write transaction.
+pub(crate) struct DigestTracker {
+    digest_db: Database,
+}
+
+impl DigestTracker {
+    /// Tracks a stored value replacing the previous value of `key`. Values
+    /// that are encrypted leave the record out until [`DigestTracker::track`]
+    /// adds it with its hash.
+    pub(crate) fn track_put(&self, txn: &mut RwTransaction, key: &[u8], value: &[u8]) -> Result<(), LmdbError> {
+        self.track_del(txn, key)?;
+        match stored_hash(value) {
+            Some(hash) => self.track(txn, key, &hash),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds the record `key` with `hash`, replacing its previous digest.
+    pub(crate) fn track(&self, txn: &mut RwTransaction, key: &[u8], hash: &str) -> Result<(), LmdbError> {
+        self.track_del(txn, key)?;
+        let record = record_digest(key, hash);
+        self.update_bucket(txn, key, record, true)?;
+        txn.put(self.digest_db, &record_key(key), &record.to_be_bytes(), WriteFlags::empty())
+    }
+
+    pub(crate) fn track_del(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<(), LmdbError> {
+        let record = match txn.get(self.digest_db, &record_key(key)) {
+            Ok(bytes) => decode_u64(bytes)?,
+            Err(LmdbError::NotFound) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        self.update_bucket(txn, key, record, false)?;
+        txn.del(self.digest_db, &record_key(key), None)
+    }
+
+    /// Empties every bucket, for when all records are removed.
+    pub(crate) fn reset(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        txn.clear_db(self.digest_db)
+    }
+
+    /// Adds `record` to the bucket of `key`, or removes it.
+    fn update_bucket(&self, txn: &mut RwTransaction, key: &[u8], record: u64, add: bool) -> Result<(), LmdbError> {
+        let bucket = bucket_key(bucket_of(key));
+        let (count, xor) = match txn.get(self.digest_db, &bucket) {
+            Ok(bytes) => decode_bucket(bytes)?,
+            Err(LmdbError::NotFound) => (0, 0),
+            Err(e) => return Err(e),
+        };
+
+        let count = if add { count + 1 } else { count.saturating_sub(1) };
+        if count == 0 {
+            return match txn.del(self.digest_db, &bucket, None) {
+                Ok(()) | Err(LmdbError::NotFound) => Ok(()),
+                Err(e) => Err(e),
+            };
+        }
+        let value = [count.to_be_bytes(), (xor ^ record).to_be_bytes()].concat();
+        txn.put(self.digest_db, &bucket, &value, WriteFlags::empty())
+    }
+}
+
+impl AppDbState {
+    /// Returns the digest of the records, to compare with the digest of the
+    /// server before a sync.
+    ///
+    /// The first call builds the buckets from the records, and every write
+    /// keeps them current afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let digest = db.sync_digest()?;
+    /// println!("{} records, root {}", digest.records, digest.root);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if a record is encrypted for a
+    /// tenant whose key is not registered while the buckets are built, or a
+    /// database error if the transaction fails.
+    pub fn sync_digest(&self) -> Result<SyncDigest, AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let (_, digest_db) = self.side_db(DIGEST_DB_NAME)?;
+
+        let txn = env.begin_ro_txn()?;
+        if get_meta_u64(&txn, meta_db, SYNC_DIGEST_KEY)?.is_some() {
+            return Ok(read_digest(&txn, digest_db)?);
+        }
+        drop(txn);
+
+        let mut txn = env.begin_rw_txn()?;
+        if get_meta_u64(&txn, meta_db, SYNC_DIGEST_KEY)?.is_none() {
+            self.build_digest(&mut txn, digest_db)?;
+            put_meta_u64(&mut txn, meta_db, SYNC_DIGEST_KEY, 1)?;
+        }
+        let digest = read_digest(&txn, digest_db)?;
+        txn.commit()?;
+        Ok(digest)
+    }
+
+    /// Returns the buckets in which the records differ from `remote`, the
+    /// digest of the server, in bucket order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `remote` does not have 256
+    /// buckets, or an error of
+    /// [`sync_digest`](Self::sync_digest).
+    pub fn diff_sync_digest(&self, remote: &SyncDigest) -> Result<Vec<usize>, AppResponse> {
+        if remote.buckets.len() != DIGEST_BUCKETS {
+            return Err(AppResponse::BadRequest(format!(
+                "Sync digest has {} buckets, expected {DIGEST_BUCKETS}",
+                remote.buckets.len()
+            )));
+        }
+        let local = self.sync_digest()?;
+        if local.root == remote.root {
+            return Ok(Vec::new());
+        }
+        Ok((0..DIGEST_BUCKETS).filter(|&bucket| local.buckets[bucket] != remote.buckets[bucket]).collect())
+    }
+
+    /// Returns the hash of each record in `buckets`, by ID, to exchange with
+    /// the server for the buckets returned by
+    /// [`diff_sync_digest`](Self::diff_sync_digest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if a bucket is out of range, or a
+    /// serialization error if a record cannot be decoded.
+    pub fn sync_digest_records(&self, buckets: &[usize]) -> Result<BTreeMap<String, String>, AppResponse> {
+        if let Some(bucket) = buckets.iter().find(|&&bucket| bucket >= DIGEST_BUCKETS) {
+            return Err(AppResponse::BadRequest(format!("Digest bucket {bucket} is out of range")));
+        }
+        let buckets: BTreeSet<usize> = buckets.iter().copied().collect();
+        let (env, db) = self.env_db()?;
+
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+        let mut records = BTreeMap::new();
+        for (key, value) in scan_from(&cursor, None) {
+            if !buckets.contains(&bucket_of(key)) {
+                continue;
+            }
+            let record: HashOnly = serde_json::from_str(&self.record_json(key, value)?)?;
+            records.insert(String::from_utf8_lossy(key).into_owned(), record.hash);
+        }
+        Ok(records)
+    }
+
+    /// Returns the tracker of the digest buckets, `None` until the digest
+    /// was first requested.
+    pub(crate) fn digest_tracker<T: Transaction>(&self, txn: &T) -> Result<Option<DigestTracker>, LmdbError> {
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        if get_meta_u64(txn, meta_db, SYNC_DIGEST_KEY)?.is_none() {
+            return Ok(None);
+        }
+        let (_, digest_db) = self.side_db(DIGEST_DB_NAME)?;
+        Ok(Some(DigestTracker { digest_db }))
+    }
+
+    /// Fills the buckets from the records.
+    fn build_digest(&self, txn: &mut RwTransaction, digest_db: Database) -> Result<(), AppResponse> {
+        let (_, db) = self.env_db()?;
+        let records: Vec<(Vec<u8>, String)> = {
+            let cursor = txn.open_ro_cursor(db)?;
+            scan_from(&cursor, None)
+                .map(|(key, value)| {
+                    let record: HashOnly = serde_json::from_str(&self.record_json(key, value)?)?;
+                    Ok((key.to_vec(), record.hash))
+                })
+                .collect::<Result<_, AppResponse>>()?
+        };
+
+        let tracker = DigestTracker { digest_db };
+        tracker.reset(txn)?;
+        for (key, hash) in &records {
+            tracker.track(txn, key, hash)?;
+        }
+        Ok(())
+    }
+}
+
+/// The hash of a stored record, decoded without its data.
+#[derive(Deserialize)]
+struct HashOnly {
+    hash: String,
+}
+
+/// Returns the hash of a stored value, `None` if it is encrypted or invalid.
+fn stored_hash(value: &[u8]) -> Option<String> {
+    let json = json_payload(value).ok()?;
+    serde_json::from_str::<HashOnly>(&json).ok().map(|record| record.hash)
+}
+
+fn read_digest<T: Transaction>(txn: &T, digest_db: Database) -> Result<SyncDigest, LmdbError> {
+    let mut records = 0;
+    let mut hashes = Vec::with_capacity(DIGEST_BUCKETS);
+    for bucket in 0..DIGEST_BUCKETS {
+        let (count, xor) = match txn.get(digest_db, &bucket_key(bucket)) {
+            Ok(bytes) => decode_bucket(bytes)?,
+            Err(LmdbError::NotFound) => (0, 0),
+            Err(e) => return Err(e),
+        };
+        records += count;
+        hashes.push(fnv1a(&[count.to_be_bytes(), xor.to_be_bytes()].concat()));
+    }
+
+    let root = fnv1a(&hashes.iter().flat_map(|hash| hash.to_be_bytes()).collect::<Vec<u8>>());
+    Ok(SyncDigest {
+        root: format!("{root:016x}"),
+        records,
+        buckets: hashes.iter().map(|hash| format!("{hash:016x}")).collect(),
+    })
+}
+
+/// Returns the bucket of the record `key`.
+fn bucket_of(key: &[u8]) -> usize {
+    (fnv1a(key) >> 56) as usize
+}
+
+fn record_digest(key: &[u8], hash: &str) -> u64 {
+    fnv1a(&[key, &[0x00], hash.as_bytes()].concat())
+}
+
+fn bucket_key(bucket: usize) -> [u8; 2] {
+    [BUCKET_TAG, bucket as u8]
+}
+
+fn record_key(key: &[u8]) -> Vec<u8> {
+    [&[RECORD_TAG], key].concat()
+}
+
+fn decode_bucket(bytes: &[u8]) -> Result<(u64, u64), LmdbError> {
+    if bytes.len() != 16 {
+        return Err(LmdbError::Corrupted);
+    }
+    Ok((decode_u64(&bytes[..8])?, decode_u64(&bytes[8..])?))
+}
@@ -0,0 +1,213 @@
+//! Record-level encryption with per-tenant keys.
+//!
+//! A multi-account app registers one 256-bit key per tenant, covering the
+//! records whose ID starts with the tenant's prefix (e.g. `acct42:`). Records
+//! written under a prefix with a registered key are stored encrypted with
+//! ChaCha20-Poly1305; all other records stay plain. Keys are never written to
+//! the database: the app keeps them, e.g. in the platform keychain, and
+//! registers them after opening. Destroying one tenant's key
+//! (crypto-shredding) makes exactly that tenant's records unreadable, without
+//! rewriting them.
+//!
+//! An encrypted value has the encrypted flag set in its header, followed by
+//!
+//! ```text
+//! {tenant length: u8} {tenant} {nonce: 12 bytes} {ciphertext and tag}
+//! ```
+//!
+//! where the header, the tenant and the record ID are authenticated as
+//! associated data, so a sealed value cannot be moved to another record.
+//! Encrypted records are not indexed and their large fields never overflow to
+//! the chunk store, since both would keep values in plain text. Scans decrypt
+//! them on the fly and skip those whose key is not registered.
+//!
+//! Encryption requires the `encryption` feature.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::PoisonError;
+
+use crate::app_response::AppResponse;
+use crate::local_db_state::AppDbState;
+use crate::value_codec::{json_payload, split_value, ValueHeader};
+
+/// Size of the ChaCha20-Poly1305 nonce stored in front of the ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// A registered tenant key.
+#[derive(Clone)]
+pub(crate) struct TenantKey {
+    prefix: String,
+    key: [u8; 32],
+}
+
+#[cfg(feature = "encryption")]
+impl Drop for TenantKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.key);
+    }
+}
+
+impl AppDbState {
+    /// Registers the key of `tenant`, encrypting every record written from now
+    /// on whose ID starts with `prefix`, and replacing an earlier key of that
+    /// tenant.
+    ///
+    /// Keys are not persisted; apps register them after every open. Records
+    /// written under `prefix` before the key was registered stay plain until
+    /// they are written again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("accounts".to_string())?;
+    /// let key = [7u8; 32]; // From the platform keychain
+    /// db.set_encryption_key("acct42", "acct42:", &key)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `tenant` is empty or longer than
+    /// 255 bytes, if `prefix` is empty or already used by another tenant, or
+    /// if this build lacks the `encryption` feature.
+    pub fn set_encryption_key(&mut self, tenant: &str, prefix: &str, key: &[u8; 32]) -> Result<(), AppResponse> {
+        if !cfg!(feature = "encryption") {
+            return Err(AppResponse::BadRequest("Encryption is not supported by this build".to_string()));
+        }
+        if tenant.is_empty() || tenant.len() > usize::from(u8::MAX) {
+            return Err(AppResponse::BadRequest("Tenant name must be 1 to 255 bytes".to_string()));
+        }
+        if prefix.is_empty() {
+            return Err(AppResponse::BadRequest("Encryption prefix cannot be empty".to_string()));
+        }
+        let mut keys = self.encryption_keys.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(other) = keys.iter().find(|(name, existing)| *name != tenant && existing.prefix == prefix) {
+            return Err(AppResponse::BadRequest(format!("Prefix {prefix} is already encrypted for tenant {}", other.0)));
+        }
+
+        keys.insert(tenant.to_string(), TenantKey { prefix: prefix.to_string(), key: *key });
+        Ok(())
+    }
+
+    /// Forgets the key of `tenant`, wiping it from memory. Returns whether a
+    /// key was registered.
+    ///
+    /// The tenant's records become unreadable until the key is registered
+    /// again; once the app has destroyed its own copy, they are lost for good.
+    /// Records written under the prefix afterwards are stored plain. The key
+    /// is gone for the background threads of the database too, e.g. the
+    /// expiry sweeper and subscriptions.
+    pub fn remove_encryption_key(&mut self, tenant: &str) -> bool {
+        self.encryption_keys.write().unwrap_or_else(PoisonError::into_inner).remove(tenant).is_some()
+    }
+
+    /// Returns whether a tenant key is registered.
+    pub(crate) fn has_encryption_keys(&self) -> bool {
+        !self.encryption_keys.read().unwrap_or_else(PoisonError::into_inner).is_empty()
+    }
+
+    /// Encrypts the encoded value of the record `id` if a tenant key covers
+    /// it. Returns `None` for records stored plain.
+    pub(crate) fn encrypt_value(&self, id: &str, value: &[u8]) -> Result<Option<Vec<u8>>, AppResponse> {
+        let keys = self.encryption_keys.read().unwrap_or_else(PoisonError::into_inner);
+        let Some((tenant, key)) = tenant_key(&keys, id) else {
+            return Ok(None);
+        };
+
+        let (header, payload) = split_value(value)?;
+        let header = ValueHeader { encrypted: true, ..header }.to_bytes();
+        let mut sealed = Vec::with_capacity(header.len() + 1 + tenant.len() + NONCE_LEN + payload.len() + 16);
+        sealed.extend_from_slice(&header);
+        sealed.push(tenant.len() as u8);
+        sealed.extend_from_slice(tenant.as_bytes());
+        let ciphertext = seal(&key.key, &[sealed.as_slice(), id.as_bytes()].concat(), payload)?;
+        sealed.extend_from_slice(&ciphertext);
+        Ok(Some(sealed))
+    }
+
+    /// Returns the JSON text of the stored value of the record `id` like
+    /// [`json_payload`], decrypting it with its tenant's key when needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the key of the value's tenant is
+    /// not registered, or a serialization error if the value is invalid or
+    /// fails authentication, e.g. because it was stored under another ID.
+    pub(crate) fn record_json<'v>(&self, id: &[u8], value: &'v [u8]) -> Result<Cow<'v, str>, AppResponse> {
+        let (header, payload) = split_value(value)?;
+        if !header.encrypted {
+            return json_payload(value);
+        }
+
+        let truncated = || AppResponse::SerializationError("Truncated encrypted value".to_string());
+        let tenant_len = usize::from(*payload.first().ok_or_else(truncated)?);
+        let tenant = payload.get(1..1 + tenant_len).ok_or_else(truncated)?;
+        let tenant = std::str::from_utf8(tenant)
+            .map_err(|e| AppResponse::SerializationError(format!("Invalid tenant in encrypted value: {e}")))?;
+        let keys = self.encryption_keys.read().unwrap_or_else(PoisonError::into_inner);
+        let key = keys.get(tenant).ok_or_else(|| {
+            AppResponse::BadRequest(format!("Record is encrypted for tenant {tenant}, whose key is not registered"))
+        })?;
+
+        let associated_len = value.len() - payload.len() + 1 + tenant_len;
+        let associated = [&value[..associated_len], id].concat();
+        let plaintext = open(&key.key, &associated, &value[associated_len..])?;
+
+        let mut plain = ValueHeader { encrypted: false, ..header }.to_bytes().to_vec();
+        plain.extend_from_slice(&plaintext);
+        Ok(Cow::Owned(json_payload(&plain)?.into_owned()))
+    }
+}
+
+/// Returns the tenant and key covering the record `id`, preferring the
+/// longest matching prefix.
+fn tenant_key<'k>(keys: &'k BTreeMap<String, TenantKey>, id: &str) -> Option<(&'k str, &'k TenantKey)> {
+    keys.iter()
+        .filter(|(_, key)| id.starts_with(&key.prefix))
+        .max_by_key(|(_, key)| key.prefix.len())
+        .map(|(tenant, key)| (tenant.as_str(), key))
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) fn seal(key: &[u8; 32], associated: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Key};
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: associated })
+        .map_err(|_| AppResponse::SerializationError("Encryption failed".to_string()))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) fn open(key: &[u8; 32], associated: &[u8], sealed: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+    if sealed.len() < NONCE_LEN {
+        return Err(AppResponse::SerializationError("Truncated encrypted value".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: associated })
+        .map_err(|_| AppResponse::SerializationError("Cannot decrypt value: wrong key or tampered data".to_string()))
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn seal(_: &[u8; 32], _: &[u8], _: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    Err(AppResponse::SerializationError(
+        "Encrypted values are not supported by this build".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn open(_: &[u8; 32], _: &[u8], _: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    Err(AppResponse::SerializationError(
+        "Encrypted values are not supported by this build".to_string(),
+    ))
+}
@@ -0,0 +1,185 @@
+//! Background deletion of expired records.
+//!
+//! Records carry their expiry time, in milliseconds since the Unix epoch, at a
+//! path covered by an index such as `["data.expires_at"]`. Index entries sort
+//! by value, so the expired records are the leading numeric entries of that
+//! index and a sweep visits nothing else. They are deleted in write
+//! transactions of at most `batch_size` records, which keeps the write lock
+//! free for foreground calls between batches.
+//!
+//! The sweeper thread works on a view sharing the environment of its
+//! [`AppDbState`] and is stopped when that state closes.
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::app_response::AppResponse;
+use crate::index::{ids_below, INDEX_DB_NAME};
+use crate::local_db_model::ExpirySweep;
+use crate::local_db_state::AppDbState;
+
+/// A running sweeper thread.
+pub(crate) struct ExpirySweeper {
+    config: ExpirySweep,
+    /// Set to `true` to stop the thread, which waits on the condition variable
+    /// between sweeps.
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: JoinHandle<()>,
+}
+
+impl AppDbState {
+    /// Deletes every record whose expiry time in the index `index` lies
+    /// before [`AppDbState::now_ms`], in write transactions of at most
+    /// `batch_size` records.
+    /// Returns the number of records deleted.
+    ///
+    /// Records without a numeric expiry time never expire.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("cache".to_string())?;
+    /// db.create_index("by_expiry", &["data.expires_at".to_string()])?;
+    ///
+    /// let deleted = db.sweep_expired("by_expiry", 500)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if there is no index `index`,
+    /// [`AppResponse::BadRequest`] if `batch_size` is zero, or a database
+    /// error if a batch fails; batches committed before stay deleted.
+    pub fn sweep_expired(&self, index: &str, batch_size: usize) -> Result<usize, AppResponse> {
+        if batch_size == 0 {
+            return Err(AppResponse::BadRequest("Expiry sweep batch size must be at least 1".to_string()));
+        }
+
+        let now = self.now_ms() as f64;
+        let (env, db) = self.env_db()?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let mut deleted = 0;
+
+        loop {
+            let mut txn = env.begin_rw_txn()?;
+            if !self.read_index_definitions(&txn)?.iter().any(|definition| definition.name == index) {
+                return Err(AppResponse::NotFound(format!("Index {index} does not exist")));
+            }
+            let writer = self.record_writer(&txn)?;
+
+            let ids = ids_below(&txn, index_db, index, now, batch_size)?;
+            if ids.is_empty() {
+                break;
+            }
+            for id in &ids {
+                writer.del(&mut txn, db, id)?;
+            }
+            writer.commit(txn)?;
+
+            deleted += ids.len();
+            if ids.len() < batch_size {
+                break;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Starts a background thread that runs [`AppDbState::sweep_expired`]
+    /// every `interval_ms`, replacing a sweeper already running.
+    ///
+    /// The sweeper stops when the database is closed or reset; compaction
+    /// restarts it. Sweep errors are logged and retried at the next interval.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::ExpirySweep;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("cache".to_string())?;
+    /// db.create_index("by_expiry", &["data.expires_at".to_string()])?;
+    ///
+    /// db.start_expiry_sweeper(ExpirySweep {
+    ///     index: "by_expiry".to_string(),
+    ///     interval_ms: 60_000,
+    ///     batch_size: 500,
+    /// })?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `interval_ms` or `batch_size` is
+    /// zero, [`AppResponse::NotFound`] if the index does not exist, or an
+    /// error if the database is closed or the thread cannot be started.
+    pub fn start_expiry_sweeper(&mut self, config: ExpirySweep) -> Result<(), AppResponse> {
+        if config.interval_ms == 0 || config.batch_size == 0 {
+            return Err(AppResponse::BadRequest("Expiry sweeper needs a positive interval_ms and batch_size".to_string()));
+        }
+        if !self.list_indexes()?.iter().any(|definition| definition.name == config.index) {
+            return Err(AppResponse::NotFound(format!("Index {} does not exist", config.index)));
+        }
+
+        self.stop_expiry_sweeper();
+        let view = self.background_view()?;
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let config = config.clone();
+            thread::Builder::new()
+                .name("expiry-sweeper".to_string())
+                .spawn(move || sweep_until_stopped(&view, &config, &stop))
+                .map_err(|e| AppResponse::DatabaseError(format!("Cannot start expiry sweeper: {e}")))?
+        };
+
+        self.sweeper = Some(ExpirySweeper { config, stop, thread });
+        Ok(())
+    }
+
+    /// Stops the expiry sweeper, waiting for a sweep in progress to finish.
+    /// Returns the configuration it ran with, `None` if none was running.
+    pub fn stop_expiry_sweeper(&mut self) -> Option<ExpirySweep> {
+        let sweeper = self.sweeper.take()?;
+        let (stopped, wake) = &*sweeper.stop;
+        *stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        wake.notify_all();
+        if sweeper.thread.join().is_err() {
+            warn!("Expiry sweeper of {} panicked", self.path);
+        }
+        Some(sweeper.config)
+    }
+
+    /// Returns the configuration of the running expiry sweeper, if any.
+    pub fn expiry_sweeper(&self) -> Option<&ExpirySweep> {
+        self.sweeper.as_ref().map(|sweeper| &sweeper.config)
+    }
+}
+
+/// Body of the sweeper thread.
+fn sweep_until_stopped(view: &AppDbState, config: &ExpirySweep, stop: &(Mutex<bool>, Condvar)) {
+    let (stopped, wake) = stop;
+    let interval = Duration::from_millis(config.interval_ms);
+
+    loop {
+        let guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        let (guard, _) = wake
+            .wait_timeout_while(guard, interval, |stopped| !*stopped)
+            .unwrap_or_else(PoisonError::into_inner);
+        if *guard {
+            return;
+        }
+        drop(guard);
+
+        match view.sweep_expired(&config.index, config.batch_size) {
+            Ok(0) => {}
+            Ok(deleted) => info!("Expiry sweeper deleted {deleted} records from {}", view.path),
+            Err(e) => warn!("Expiry sweep of {} failed: {e}", view.path),
+        }
+    }
+}
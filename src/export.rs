@@ -0,0 +1,142 @@
+//! CSV export of flattened records.
+//!
+//! [`AppDbState::export_csv`] writes one row per record so an offline
+//! database can be inspected in a spreadsheet. Nested objects of `data` are
+//! flattened into dotted columns (`data.address.city`); arrays and empty
+//! objects stay whole, as JSON text in one cell. The columns are the union
+//! of the paths of all exported records, so the records are read twice in
+//! the same read transaction: once to collect the columns and once to write
+//! the rows, without holding them in memory.
+//!
+//! Cells are escaped as in RFC 4180: a cell holding a comma, a quote or a
+//! line break is quoted, with its quotes doubled, and rows end with CRLF.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use lmdb::{RoCursor, RoTransaction, Transaction};
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{CsvExportResult, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::query::PathFilter;
+use crate::scan::scan_from;
+
+impl AppDbState {
+    /// Writes the records matching `filter` to a CSV file at `path`, in key
+    /// order, with a header of `id`, `hash` and the flattened `data` paths.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("inventory".to_string())?;
+    ///
+    /// let result = db.export_csv("exports/inventory.csv", &Default::default())?;
+    /// println!("{} rows, columns {:?}", result.records, result.columns);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::DatabaseError`] if the file cannot be written,
+    /// a serialization error if a record cannot be decoded, or a database
+    /// error if the read transaction fails.
+    pub fn export_csv(&self, path: &str, filter: &PathFilter) -> Result<CsvExportResult, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+
+        let mut data_columns = BTreeSet::new();
+        {
+            let cursor = txn.open_ro_cursor(db)?;
+            self.for_each_exported(&txn, &cursor, filter, |model| {
+                let mut cells = BTreeMap::new();
+                flatten("data", &model.data, &mut cells);
+                data_columns.extend(cells.into_keys());
+                Ok(())
+            })?;
+        }
+        let columns: Vec<String> = ["id".to_string(), "hash".to_string()].into_iter().chain(data_columns).collect();
+
+        let write_error = |e: std::io::Error| AppResponse::DatabaseError(format!("Cannot write CSV export {path}: {e}"));
+        let file = File::create(path).map_err(write_error)?;
+        let mut out = BufWriter::new(file);
+        write_row(&mut out, columns.iter().map(String::as_str)).map_err(write_error)?;
+
+        let mut records = 0;
+        {
+            let cursor = txn.open_ro_cursor(db)?;
+            self.for_each_exported(&txn, &cursor, filter, |model| {
+                let mut cells = BTreeMap::new();
+                flatten("data", &model.data, &mut cells);
+                let row: Vec<String> = columns[2..]
+                    .iter()
+                    .map(|column| cells.get(column).map_or_else(String::new, |value| cell_text(value)))
+                    .collect();
+                let row = [model.id.as_str(), model.hash.as_str()].into_iter().chain(row.iter().map(String::as_str));
+                write_row(&mut out, row).map_err(write_error)?;
+                records += 1;
+                Ok(())
+            })?;
+        }
+        out.flush().map_err(write_error)?;
+
+        Ok(CsvExportResult { records, columns })
+    }
+
+    /// Calls `visit` with every record matching `filter`, in key order.
+    fn for_each_exported<F>(&self, txn: &RoTransaction, cursor: &RoCursor, filter: &PathFilter, mut visit: F) -> Result<(), AppResponse>
+    where
+        F: FnMut(LocalDbModel) -> Result<(), AppResponse>,
+    {
+        for (key, value) in scan_from(cursor, None) {
+            if !filter.matches_json(&self.record_json(key, value)?)? {
+                continue;
+            }
+            visit(self.decode_record(txn, key, value)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the leaves of `value` by dotted path below `prefix`. Arrays and
+/// empty objects are leaves.
+fn flatten<'a>(prefix: &str, value: &'a JsonValue, cells: &mut BTreeMap<String, &'a JsonValue>) {
+    match value {
+        JsonValue::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                flatten(&format!("{prefix}.{key}"), child, cells);
+            }
+        }
+        _ => {
+            cells.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+/// Returns the text of a cell: strings unquoted, `null` empty, other values
+/// as JSON.
+fn cell_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn write_row<'a>(out: &mut impl Write, cells: impl Iterator<Item = &'a str>) -> std::io::Result<()> {
+    for (index, cell) in cells.enumerate() {
+        if index > 0 {
+            out.write_all(b",")?;
+        }
+        if cell.contains([',', '"', '\n', '\r']) {
+            write!(out, "\"{}\"", cell.replace('"', "\"\""))?;
+        } else {
+            out.write_all(cell.as_bytes())?;
+        }
+    }
+    out.write_all(b"\r\n")
+}
@@ -0,0 +1,255 @@
+//! Read-only external collections backed by JSON files.
+//!
+//! Large static lookup tables (postal codes, a product catalog) do not always
+//! merit an import into LMDB. Such a file can be registered under a name and
+//! then queried, read by ID and joined against the records of the database
+//! without ever being written to it. The file holds records in the dataset
+//! format of [`AppDbState::import_from_file`]: a JSON array or one record per
+//! line (NDJSON).
+//!
+//! A JSON array is loaded into memory on registration. An NDJSON file is only
+//! scanned for the ID and position of each line; reads by ID seek to the line
+//! and queries stream the file, so only matching lines are deserialized. The
+//! file must not change while it is registered; registering it again picks up
+//! a new version.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::dataset::parse_dataset;
+use crate::local_db_model::{JoinedRecord, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::query::{model_value, probe_paths, PathFilter};
+
+/// A registered external collection.
+pub(crate) enum ExternalCollection {
+    /// NDJSON file, indexed by the ID of each line: `(offset, length)`.
+    Lines { path: PathBuf, lines: BTreeMap<String, (u64, usize)> },
+    /// JSON array loaded into memory, keyed by ID.
+    Loaded(BTreeMap<String, LocalDbModel>),
+}
+
+impl ExternalCollection {
+    fn open(path: &str) -> Result<Self, AppResponse> {
+        let file = File::open(path)
+            .map_err(|e| AppResponse::NotFound(format!("Cannot read external collection {path}: {e}")))?;
+        let mut reader = BufReader::new(file);
+
+        let starts_array = reader
+            .fill_buf()
+            .map_err(|e| read_error(path, e))?
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            == Some(&b'[');
+        if starts_array {
+            let mut dataset = String::new();
+            reader.read_to_string(&mut dataset).map_err(|e| read_error(path, e))?;
+            let models = parse_dataset(&dataset)?;
+            return Ok(Self::Loaded(models.into_iter().map(|model| (model.id.clone(), model)).collect()));
+        }
+
+        let mut lines = BTreeMap::new();
+        let mut offset = 0;
+        let mut line = String::new();
+        for number in 1.. {
+            line.clear();
+            let read = reader.read_line(&mut line).map_err(|e| read_error(path, e))?;
+            if read == 0 {
+                break;
+            }
+            if !line.trim().is_empty() {
+                let id = probe_paths(&line, &["id"])
+                    .map_err(|e| AppResponse::SerializationError(format!("Invalid record on line {number}: {e}")))?
+                    .pop()
+                    .flatten();
+                let Some(serde_json::Value::String(id)) = id else {
+                    return Err(AppResponse::SerializationError(format!("Record on line {number} has no string id")));
+                };
+                lines.insert(id, (offset, read));
+            }
+            offset += read as u64;
+        }
+        Ok(Self::Lines { path: PathBuf::from(path), lines })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Lines { lines, .. } => lines.len(),
+            Self::Loaded(models) => models.len(),
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        match self {
+            Self::Loaded(models) => Ok(models.get(id).cloned()),
+            Self::Lines { path, lines } => {
+                let Some(&(offset, length)) = lines.get(id) else {
+                    return Ok(None);
+                };
+                let mut file = File::open(path).map_err(|e| read_error(&path.display().to_string(), e))?;
+                let mut line = vec![0; length];
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| file.read_exact(&mut line))
+                    .map_err(|e| read_error(&path.display().to_string(), e))?;
+                Ok(Some(serde_json::from_slice(&line)?))
+            }
+        }
+    }
+
+    fn query(&self, filter: &PathFilter) -> Result<Vec<LocalDbModel>, AppResponse> {
+        match self {
+            Self::Loaded(models) => {
+                let paths = filter.paths();
+                Ok(models
+                    .values()
+                    .filter(|model| {
+                        let values: Vec<_> = paths.iter().map(|path| model_value(model, path)).collect();
+                        filter.matches_values(&values)
+                    })
+                    .cloned()
+                    .collect())
+            }
+            Self::Lines { path, .. } => {
+                let file = File::open(path).map_err(|e| read_error(&path.display().to_string(), e))?;
+                let mut matching = Vec::new();
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(|e| read_error(&path.display().to_string(), e))?;
+                    if !line.trim().is_empty() && filter.matches_json(&line)? {
+                        matching.push(serde_json::from_str(&line)?);
+                    }
+                }
+                Ok(matching)
+            }
+        }
+    }
+}
+
+fn read_error(path: &str, e: std::io::Error) -> AppResponse {
+    AppResponse::DatabaseError(format!("Cannot read external collection {path}: {e}"))
+}
+
+impl AppDbState {
+    /// Registers the JSON array or NDJSON file at `path` as the read-only
+    /// external collection `name`, replacing a collection of that name.
+    /// Returns the number of records it holds.
+    ///
+    /// Registrations are not persisted; closing or resetting the database
+    /// drops them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("orders".to_string())?;
+    /// db.register_external("products", "assets/products.ndjson")?;
+    ///
+    /// let product = db.get_external_by_id("products", "p42")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `name` is empty,
+    /// [`AppResponse::NotFound`] if the file cannot be opened, or a
+    /// serialization error if a record is invalid or an NDJSON line has no
+    /// string `id`.
+    pub fn register_external(&mut self, name: &str, path: &str) -> Result<usize, AppResponse> {
+        if name.is_empty() {
+            return Err(AppResponse::BadRequest("External collection name cannot be empty".to_string()));
+        }
+
+        let collection = ExternalCollection::open(path)?;
+        let records = collection.len();
+        self.external.insert(name.to_string(), collection);
+        info!("✅ External collection {name} registered from {path} ({records} records)");
+        Ok(records)
+    }
+
+    /// Removes the external collection `name`. Returns whether it existed.
+    pub fn unregister_external(&mut self, name: &str) -> bool {
+        self.external.remove(name).is_some()
+    }
+
+    /// Returns the names of the registered external collections.
+    pub fn external_collections(&self) -> Vec<String> {
+        self.external.keys().cloned().collect()
+    }
+
+    /// Retrieves a record of the external collection `name` by ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if no collection `name` is
+    /// registered, or an error if its file cannot be read.
+    pub fn get_external_by_id(&self, name: &str, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        self.external_collection(name)?.get(id)
+    }
+
+    /// Returns the records of the external collection `name` matching a
+    /// [`PathFilter`], in ID order for JSON arrays and in file order for
+    /// NDJSON files.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if no collection `name` is
+    /// registered, or an error if its file cannot be read.
+    pub fn query_external(&self, name: &str, filter: &PathFilter) -> Result<Vec<LocalDbModel>, AppResponse> {
+        self.external_collection(name)?.query(filter)
+    }
+
+    /// Returns the records matching `filter`, each paired with the record of
+    /// the external collection `collection` whose ID is the value at `path`.
+    ///
+    /// String and number values are looked up as IDs; records without such a
+    /// value, or without a matching external record, are returned with
+    /// `joined` set to `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    ///
+    /// let mut db = AppDbState::init("orders".to_string())?;
+    /// db.register_external("products", "assets/products.ndjson")?;
+    ///
+    /// let orders = db.join_external(&PathFilter::default(), "data.product_id", "products")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if no collection `collection` is
+    /// registered, or an error if the database or the file cannot be read.
+    pub fn join_external(&self, filter: &PathFilter, path: &str, collection: &str) -> Result<Vec<JoinedRecord>, AppResponse> {
+        let external = self.external_collection(collection)?;
+
+        self.query(filter)?
+            .into_iter()
+            .map(|record| {
+                let key = match model_value(&record, path) {
+                    Some(serde_json::Value::String(key)) => Some(key),
+                    Some(serde_json::Value::Number(key)) => Some(key.to_string()),
+                    _ => None,
+                };
+                let joined = match key {
+                    Some(key) => external.get(&key)?,
+                    None => None,
+                };
+                Ok(JoinedRecord { record, joined })
+            })
+            .collect()
+    }
+
+    fn external_collection(&self, name: &str) -> Result<&ExternalCollection, AppResponse> {
+        self.external
+            .get(name)
+            .ok_or_else(|| AppResponse::NotFound(format!("External collection {name} is not registered")))
+    }
+}
@@ -0,0 +1,191 @@
+//! Field-level encryption of configured paths.
+//!
+//! Where [`AppDbState::set_encryption_key`] encrypts whole records, field
+//! encryption seals only the values at configured paths below `data` (e.g.
+//! `data.ssn`), so the rest of the record stays plain and can be filtered
+//! and indexed. On write, each configured value is serialized, encrypted
+//! with ChaCha20-Poly1305 and replaced in the stored record by a marker
+//!
+//! ```json
+//! {"$encrypted": "<hex-encoded nonce, ciphertext and tag>"}
+//! ```
+//!
+//! with the record ID and the path authenticated as associated data, so a
+//! sealed value cannot be moved to another record or field. Reads through
+//! the record APIs decrypt the markers transparently. Filters and indexes
+//! evaluate the stored value, so they see the marker of an encrypted field.
+//!
+//! The key is never written to the database; apps register it after every
+//! open. Without it, reads return the markers as stored.
+//!
+//! Encryption requires the `encryption` feature.
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::encryption::{open, seal};
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::AppDbState;
+use crate::signing::{decode_hex, encode_hex};
+use crate::value_codec::encode_model;
+
+/// Key of the marker object replacing an encrypted field.
+const MARKER_KEY: &str = "$encrypted";
+
+/// The encrypted paths and their key.
+#[derive(Clone)]
+pub(crate) struct FieldEncryption {
+    /// Paths below `data`, as dotted paths and as their segments below `data`
+    paths: Vec<(String, Vec<String>)>,
+    key: [u8; 32],
+}
+
+#[cfg(feature = "encryption")]
+impl Drop for FieldEncryption {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.key);
+    }
+}
+
+impl AppDbState {
+    /// Encrypts the values at `paths` of every record written from now on
+    /// with `key`, replacing earlier paths and key.
+    ///
+    /// Paths are dotted paths below `data`, e.g. `data.ssn` or
+    /// `data.card.number`. Records written before stay plain until they are
+    /// written again. The configuration is not persisted; apps set it after
+    /// every open.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("patients".to_string())?;
+    /// let key = [7u8; 32]; // From the platform keychain
+    /// db.set_field_encryption(&["data.ssn", "data.insurance.number"], &key)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `paths` is empty, a path is not
+    /// below `data`, or this build lacks the `encryption` feature.
+    pub fn set_field_encryption<S: AsRef<str>>(&mut self, paths: &[S], key: &[u8; 32]) -> Result<(), AppResponse> {
+        if !cfg!(feature = "encryption") {
+            return Err(AppResponse::BadRequest("Encryption is not supported by this build".to_string()));
+        }
+        if paths.is_empty() {
+            return Err(AppResponse::BadRequest("At least one encrypted path is required".to_string()));
+        }
+        let paths = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                match path.strip_prefix("data.") {
+                    Some(rest) if !rest.split('.').any(str::is_empty) => {
+                        Ok((path.to_string(), rest.split('.').map(str::to_string).collect()))
+                    }
+                    _ => Err(AppResponse::BadRequest(format!("Encrypted path {path} is not a path below data"))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.field_encryption = Some(FieldEncryption { paths, key: *key });
+        Ok(())
+    }
+
+    /// Stops encrypting fields and forgets the key, wiping it from memory.
+    /// Returns whether field encryption was set.
+    ///
+    /// Fields encrypted before are read back as their markers until the key
+    /// is set again.
+    pub fn remove_field_encryption(&mut self) -> bool {
+        self.field_encryption.take().is_some()
+    }
+
+    /// Returns `model` with its configured fields encrypted, for storing it,
+    /// or `None` if it has none of them.
+    pub(crate) fn seal_fields(&self, model: &LocalDbModel) -> Result<Option<LocalDbModel>, AppResponse> {
+        let Some(encryption) = &self.field_encryption else {
+            return Ok(None);
+        };
+
+        let mut sealed: Option<LocalDbModel> = None;
+        for (path, segments) in &encryption.paths {
+            if field(&model.data, segments).is_none_or(|value| marker_hex(value).is_some()) {
+                continue;
+            }
+            let target = sealed.get_or_insert_with(|| model.clone());
+            let Some(value) = field_mut(&mut target.data, segments) else {
+                continue;
+            };
+            let ciphertext = seal(&encryption.key, &associated_data(&model.id, path), &serde_json::to_vec(value)?)?;
+            *value = json!({ MARKER_KEY: encode_hex(&ciphertext) });
+        }
+        Ok(sealed)
+    }
+
+    /// Encodes `model` for storing it outside the main database, e.g. in a
+    /// collection, with its configured fields and, for a tenant, the whole
+    /// record encrypted. Large fields are not overflowed.
+    pub(crate) fn encode_sealed(&self, model: &LocalDbModel) -> Result<Vec<u8>, AppResponse> {
+        let sealed_fields = self.seal_fields(model)?;
+        let value = encode_model(sealed_fields.as_ref().unwrap_or(model))?;
+        Ok(self.encrypt_value(&model.id, &value)?.unwrap_or(value))
+    }
+
+    /// Decodes a value written by [`encode_sealed`](Self::encode_sealed).
+    pub(crate) fn decode_sealed(&self, id: &[u8], value: &[u8]) -> Result<LocalDbModel, AppResponse> {
+        let mut model: LocalDbModel = serde_json::from_str(&self.record_json(id, value)?)?;
+        self.open_fields(&mut model)?;
+        Ok(model)
+    }
+
+    /// Replaces the markers of encrypted fields of `model` with their
+    /// values. Markers are kept as stored while no key is set.
+    pub(crate) fn open_fields(&self, model: &mut LocalDbModel) -> Result<(), AppResponse> {
+        let Some(encryption) = &self.field_encryption else {
+            return Ok(());
+        };
+        open_markers(&encryption.key, &model.id, "data", &mut model.data)
+    }
+}
+
+/// Decrypts the markers in `value`, found at `path` of the record `id`.
+fn open_markers(key: &[u8; 32], id: &str, path: &str, value: &mut JsonValue) -> Result<(), AppResponse> {
+    if let Some(hex) = marker_hex(value) {
+        let ciphertext = decode_hex(hex)
+            .ok_or_else(|| AppResponse::SerializationError(format!("Invalid encrypted field {path} of {id}")))?;
+        let plaintext = open(key, &associated_data(id, path), &ciphertext)?;
+        *value = serde_json::from_slice(&plaintext)?;
+        return Ok(());
+    }
+    if let JsonValue::Object(map) = value {
+        for (field, child) in map.iter_mut() {
+            open_markers(key, id, &format!("{path}.{field}"), child)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the value at `segments` of `data`, if every parent is an object.
+fn field<'a>(data: &'a JsonValue, segments: &[String]) -> Option<&'a JsonValue> {
+    segments.iter().try_fold(data, |value, segment| value.as_object()?.get(segment))
+}
+
+fn field_mut<'a>(data: &'a mut JsonValue, segments: &[String]) -> Option<&'a mut JsonValue> {
+    segments.iter().try_fold(data, |value, segment| value.as_object_mut()?.get_mut(segment))
+}
+
+/// Returns the ciphertext of a marker object.
+fn marker_hex(value: &JsonValue) -> Option<&str> {
+    match value.as_object() {
+        Some(map) if map.len() == 1 => map.get(MARKER_KEY)?.as_str(),
+        _ => None,
+    }
+}
+
+fn associated_data(id: &str, path: &str) -> Vec<u8> {
+    [id.as_bytes(), &[0x00], path.as_bytes()].concat()
+}
@@ -0,0 +1,108 @@
+//! Lookup of records by content hash.
+//!
+//! A sync layer receiving a record can skip the write when a record with the
+//! same `hash` already exists locally, e.g. the same attachment uploaded
+//! from two devices under different IDs. [`AppDbState::find_by_hash`]
+//! returns the IDs of those records.
+//!
+//! The lookup scans the records unless the hash index is enabled with
+//! [`AppDbState::set_hash_index`]: a secondary index (see [`crate::index`])
+//! named `__hash` over the `hash` path, kept up to date by every write like
+//! the app's own indexes.
+
+use lmdb::{Error as LmdbError, Transaction};
+use log::info;
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::index::{ids_with_value, is_cut, INDEX_DB_NAME};
+use crate::local_db_model::IndexDefinition;
+use crate::local_db_state::AppDbState;
+use crate::query::probe_paths;
+use crate::scan::scan_from;
+
+/// Name of the index over the `hash` path.
+pub(crate) const HASH_INDEX_NAME: &str = "__hash";
+
+impl AppDbState {
+    /// Enables or disables the hash index. Enabling it indexes the existing
+    /// records; both are no-ops when the index is already in that state.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("photos".to_string())?;
+    /// db.set_hash_index(true)?;
+    ///
+    /// if db.find_by_hash("sha256_9f2c")?.is_empty() {
+    ///     // Store the incoming photo
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write fails.
+    pub fn set_hash_index(&self, enabled: bool) -> Result<(), AppResponse> {
+        if enabled {
+            let definition = IndexDefinition { name: HASH_INDEX_NAME.to_string(), paths: vec!["hash".to_string()] };
+            self.ensure_indexes(&[definition])?;
+        } else {
+            self.drop_index(HASH_INDEX_NAME)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the hash index is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn hash_index_enabled(&self) -> Result<bool, LmdbError> {
+        Ok(self.list_indexes()?.iter().any(|definition| definition.name == HASH_INDEX_NAME))
+    }
+
+    /// Returns the IDs of the records whose `hash` is `hash`, in ID order.
+    ///
+    /// Uses the hash index when it is enabled. Records encrypted at rest are
+    /// not indexed, so the records are scanned instead while an encryption
+    /// key is registered, as they are for hashes too long to be held in full
+    /// by the index. Records that fail to decode are logged and skipped
+    /// by the scan.
+    ///
+    /// # Examples
+    ///
+    /// See [`AppDbState::set_hash_index`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read fails.
+    pub fn find_by_hash(&self, hash: &str) -> Result<Vec<String>, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+
+        let indexed = self.read_index_definitions(&txn)?.iter().any(|definition| definition.name == HASH_INDEX_NAME);
+        let value = JsonValue::String(hash.to_string());
+        if indexed && !self.has_encryption_keys() && !is_cut(&value) {
+            return Ok(ids_with_value(&txn, index_db, HASH_INDEX_NAME, &value)?);
+        }
+
+        let cursor = txn.open_ro_cursor(db)?;
+        let mut ids = Vec::new();
+        for (key, value) in scan_from(&cursor, None) {
+            let probed = match self.record_json(key, value) {
+                Ok(json) => probe_paths(&json, &["hash"]).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            match probed.map(|mut probed| probed.pop().flatten()) {
+                Ok(Some(JsonValue::String(stored))) if stored == hash => ids.push(String::from_utf8_lossy(key).into_owned()),
+                Ok(_) => {}
+                Err(e) => info!("Error probing model: {e}"),
+            }
+        }
+        Ok(ids)
+    }
+}
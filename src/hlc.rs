@@ -0,0 +1,122 @@
+//! Hybrid logical clock timestamps.
+//!
+//! Wall clocks of different devices disagree, so ordering their writes by
+//! time alone can let an older edit win. Every record write transaction is
+//! stamped with a hybrid logical clock (HLC) timestamp instead: the
+//! database clock time in milliseconds, or the last timestamp issued if that
+//! is later, plus a counter ordering the writes within one millisecond. A
+//! timestamp never goes backwards, even when the device clock does, and
+//! after [`AppDbState::observe_hlc`] with the timestamp of a remote change,
+//! every later local write orders after it.
+//!
+//! The timestamp of each record is kept in the `__hlc` database, written in
+//! the same transaction as the record:
+//!
+//! ```text
+//! {id}  -> {physical ms << 16 | counter, u64 BE}
+//! ```
+//!
+//! and the last timestamp issued is kept in `__meta`, so the clock survives
+//! restarts. Deleting a record drops its timestamp.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::Hlc;
+use crate::local_db_state::AppDbState;
+use crate::meta::{decode_u64, get_meta_u64, put_meta_u64, HLC_KEY, META_DB_NAME};
+
+/// Side database holding the HLC timestamp of each record.
+pub(crate) const HLC_DB_NAME: &str = "__hlc";
+
+impl AppDbState {
+    /// Returns the last HLC timestamp issued by this database, zero when no
+    /// record was ever written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn last_hlc(&self) -> Result<Hlc, LmdbError> {
+        Ok(Hlc::from_u64(self.meta_u64(HLC_KEY)?.unwrap_or(0)))
+    }
+
+    /// Returns the HLC timestamp of the last write of the record `id`,
+    /// `None` if it does not exist or was written before timestamps were
+    /// kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn record_hlc(&self, id: &str) -> Result<Option<Hlc>, LmdbError> {
+        let (env, hlc_db) = self.side_db(HLC_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        match txn.get(hlc_db, &id) {
+            Ok(bytes) => Ok(Some(Hlc::from_u64(decode_u64(bytes)?))),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Advances the clock past `remote`, the timestamp of a change received
+    /// from another device or the server, so that every later local write
+    /// orders after it. Returns the timestamp issued for the receipt.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::Hlc;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// // Stamped by the device that wrote the pulled record
+    /// let remote = Hlc { physical_ms: 1_760_000_000_000, counter: 3 };
+    /// db.observe_hlc(remote)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the physical time of `remote`
+    /// does not fit in 48 bits, or a database error if the write fails.
+    pub fn observe_hlc(&self, remote: Hlc) -> Result<Hlc, AppResponse> {
+        if remote.physical_ms >> 48 != 0 {
+            return Err(AppResponse::BadRequest(format!("HLC physical time {} is out of range", remote.physical_ms)));
+        }
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let hlc = next_hlc(&mut txn, meta_db, self.now_ms(), Some(remote))?;
+        txn.commit()?;
+        Ok(hlc)
+    }
+}
+
+impl Hlc {
+    /// Packs the timestamp into a `u64` ordering like the timestamp.
+    pub(crate) fn to_u64(self) -> u64 {
+        (self.physical_ms << 16) | u64::from(self.counter)
+    }
+
+    pub(crate) fn from_u64(packed: u64) -> Self {
+        Hlc { physical_ms: packed >> 16, counter: packed as u16 }
+    }
+}
+
+/// Issues the timestamp of a local event at wall time `now_ms`, or of the
+/// receipt of `remote`, and stores it as the last one issued.
+pub(crate) fn next_hlc(txn: &mut RwTransaction, meta_db: Database, now_ms: u64, remote: Option<Hlc>) -> Result<Hlc, LmdbError> {
+    let last = Hlc::from_u64(get_meta_u64(txn, meta_db, HLC_KEY)?.unwrap_or(0));
+    let latest = remote.map_or(last, |remote| remote.max(last));
+
+    let next = if now_ms > latest.physical_ms {
+        Hlc { physical_ms: now_ms, counter: 0 }
+    } else {
+        match latest.counter.checked_add(1) {
+            Some(counter) => Hlc { counter, ..latest },
+            // Borrow the next millisecond rather than wrap
+            None => Hlc { physical_ms: latest.physical_ms + 1, counter: 0 },
+        }
+    };
+    put_meta_u64(txn, meta_db, HLC_KEY, next.to_u64())?;
+    Ok(next)
+}
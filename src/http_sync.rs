@@ -0,0 +1,128 @@
+//! HTTP sync client.
+//!
+//! [`HttpSyncAdapter`] is the [`SyncAdapter`] of apps whose server speaks a
+//! plain JSON protocol below one base URL:
+//!
+//! ```text
+//! GET  {url}/pull?cursor={cursor}   -> PullPage        cursor omitted on the first pull
+//! POST {url}/push   RemoteChanges   -> any 2xx status
+//! ```
+//!
+//! The configured headers, e.g. `Authorization`, go with every request. A
+//! `429` or `503` answer is reported as [`AppResponse::Busy`], with the
+//! delay of its `Retry-After` header, so the app can schedule the next sync.
+//!
+//! Requires the `http-sync` feature.
+
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ConflictPolicy, PullPage, RemoteChanges, SyncEndpointConfig};
+use crate::sync_engine::SyncAdapter;
+
+/// [`SyncAdapter`] talking to a sync server over HTTP.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::http_sync::HttpSyncAdapter;
+/// use offline_first_core::local_db_state::AppDbState;
+///
+/// let db = AppDbState::init("notes".to_string())?;
+///
+/// let config = serde_json::from_str(r#"{"url":"https://api.example.com/sync"}"#).unwrap();
+/// let mut adapter = HttpSyncAdapter::new(config)?;
+/// let run = db.sync_now(&mut adapter)?;
+/// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+/// ```
+pub struct HttpSyncAdapter {
+    client: Client,
+    config: SyncEndpointConfig,
+}
+
+impl HttpSyncAdapter {
+    /// Creates a client for the server of `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the URL is not an `http` or
+    /// `https` URL, a header is invalid, or the conflict policy is
+    /// `callback`.
+    pub fn new(config: SyncEndpointConfig) -> Result<Self, AppResponse> {
+        if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+            return Err(AppResponse::BadRequest(format!("Sync URL {} is not an http or https URL", config.url)));
+        }
+        if config.conflict_policy == ConflictPolicy::Callback {
+            return Err(AppResponse::BadRequest("The callback conflict policy is not available over HTTP".to_string()));
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let header = HeaderName::from_bytes(name.as_bytes()).ok().zip(HeaderValue::from_str(value).ok());
+            let Some((name, value)) = header else {
+                return Err(AppResponse::BadRequest(format!("Invalid sync header {name}")));
+            };
+            headers.insert(name, value);
+        }
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .map_err(|e| AppResponse::BadRequest(format!("Cannot create the sync client: {e}")))?;
+        Ok(HttpSyncAdapter { client, config })
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{path}", self.config.url.trim_end_matches('/'))
+    }
+}
+
+impl SyncAdapter for HttpSyncAdapter {
+    fn pull(&mut self, cursor: Option<&str>) -> Result<PullPage, AppResponse> {
+        let url = self.endpoint("pull");
+        let mut request = self.client.get(&url);
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        let response = checked(request.send(), &url)?;
+        response
+            .json()
+            .map_err(|e| AppResponse::SerializationError(format!("Error parsing the answer of {url}: {e}")))
+    }
+
+    fn push(&mut self, changes: &RemoteChanges) -> Result<(), AppResponse> {
+        let url = self.endpoint("push");
+        checked(self.client.post(&url).json(changes).send(), &url)?;
+        Ok(())
+    }
+
+    fn conflict_policy(&self) -> ConflictPolicy {
+        self.config.conflict_policy.clone()
+    }
+}
+
+/// Returns the response of a request to `url` if it succeeded.
+fn checked(response: reqwest::Result<Response>, url: &str) -> Result<Response, AppResponse> {
+    let response = response.map_err(|e| AppResponse::DatabaseError(format!("Sync request to {url} failed: {e}")))?;
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after_s = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .unwrap_or(0);
+        return Err(AppResponse::Busy {
+            message: format!("Sync server at {url} is busy ({status})"),
+            retry_after_ms: retry_after_s.saturating_mul(1000),
+        });
+    }
+    if !status.is_success() {
+        return Err(AppResponse::DatabaseError(format!("Sync server at {url} answered {status}")));
+    }
+    Ok(response)
+}
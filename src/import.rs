@@ -0,0 +1,109 @@
+//! Streaming NDJSON import.
+//!
+//! [`AppDbState::import_from_file`] reads a whole dataset and writes it in
+//! one transaction, which suits shipped content of a known size. Restoring a
+//! backup or seeding a large database goes through
+//! [`AppDbState::import_ndjson`] instead: the file is read line by line and
+//! written in transactions of at most `batch_size` records, so neither the
+//! file nor one huge transaction has to fit in memory.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use lmdb::{Error as LmdbError, Transaction};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ImportOptions, ImportPolicy, ImportResult, LocalDbModel};
+use crate::local_db_state::AppDbState;
+
+impl AppDbState {
+    /// Imports the records of an NDJSON file, one record per line, handling
+    /// existing IDs according to `options.policy`. Blank lines are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::{ImportOptions, ImportPolicy};
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let options = ImportOptions { policy: ImportPolicy::Overwrite, ..ImportOptions::default() };
+    /// let result = db.import_ndjson("backups/notes.ndjson", &options)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the file cannot be read,
+    /// [`AppResponse::SerializationError`] if a line is not a record,
+    /// [`AppResponse::BadRequest`] if `batch_size` is zero, a record has an
+    /// empty ID or, with [`ImportPolicy::Fail`], already exists, or a
+    /// database error if a batch fails. Batches committed before the error
+    /// stay imported.
+    pub fn import_ndjson(&self, path: &str, options: &ImportOptions) -> Result<ImportResult, AppResponse> {
+        if options.batch_size == 0 {
+            return Err(AppResponse::BadRequest("Import batch size must be at least 1".to_string()));
+        }
+        let file = File::open(path)
+            .map_err(|e| AppResponse::NotFound(format!("Cannot read import file {path}: {e}")))?;
+
+        let mut result = ImportResult::default();
+        let mut batch: Vec<(usize, LocalDbModel)> = Vec::with_capacity(options.batch_size);
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| AppResponse::NotFound(format!("Cannot read import file {path}: {e}")))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let model: LocalDbModel = serde_json::from_str(&line).map_err(|e| {
+                AppResponse::SerializationError(format!("Invalid record on line {}: {e}", index + 1))
+            })?;
+            if model.id.is_empty() {
+                return Err(AppResponse::BadRequest(format!("Record on line {} has an empty ID", index + 1)));
+            }
+            batch.push((index + 1, model));
+
+            if batch.len() == options.batch_size {
+                self.import_batch(&mut batch, options.policy, &mut result)?;
+            }
+        }
+        self.import_batch(&mut batch, options.policy, &mut result)?;
+
+        info!("✅ Imported {} of {} records from {path}", result.imported, result.read);
+        Ok(result)
+    }
+
+    /// Writes `batch` in one transaction and empties it.
+    fn import_batch(&self, batch: &mut Vec<(usize, LocalDbModel)>, policy: ImportPolicy, result: &mut ImportResult) -> Result<(), AppResponse> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+
+        for (line, model) in batch.iter_mut() {
+            result.read += 1;
+            let exists = match txn.get(db, &model.id) {
+                Ok(_) => true,
+                Err(LmdbError::NotFound) => false,
+                Err(e) => return Err(e.into()),
+            };
+            match (exists, policy) {
+                (true, ImportPolicy::Skip) => result.skipped += 1,
+                (true, ImportPolicy::Fail) => {
+                    return Err(AppResponse::BadRequest(format!("Record {} on line {line} already exists", model.id)));
+                }
+                _ => {
+                    self.write_model(&mut txn, &writer, db, model)?;
+                    result.imported += 1;
+                }
+            }
+        }
+
+        writer.commit(txn)?;
+        batch.clear();
+        Ok(())
+    }
+}
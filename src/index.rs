@@ -0,0 +1,615 @@
+//! Secondary indexes over record paths.
+//!
+//! An index is defined by a name and an ordered list of dotted paths, e.g.
+//! `["data.account_id", "data.created_at"]`. For every record it holds one
+//! entry in the `__index` database whose key is
+//!
+//! ```text
+//! {index name} 0x00 {encoded path values...} {record id}
+//! ```
+//!
+//! and whose value is the record ID. Path values are encoded so that byte
+//! order matches value order (see [`encode_value`]), which lets a cursor
+//! positioned on the encoded values of the leading paths return the matching
+//! records already sorted by the remaining ones.
+//!
+//! LMDB keys are limited to 511 bytes, so a long string or JSON value is cut
+//! after [`MAX_ENCODED_TEXT`] encoded bytes and followed by a hash of its
+//! full text. Lookups by such a value recheck the records they find, and a
+//! record whose entry is still too long, e.g. because of a very long ID, is
+//! left out of the index with a warning.
+//!
+//! Definitions live in the `__index_defs` database. Every write to the main
+//! database goes through a [`RecordWriter`](crate::writer::RecordWriter),
+//! which keeps the entries of all defined indexes in the same transaction as
+//! the record itself.
+//!
+//! The `__meta` database records the version of the entry format the indexes
+//! were built with. Opening a database whose indexes were built with another
+//! format rebuilds them, and [`AppDbState::ensure_indexes`] lets an app
+//! declare its indexes on every start instead of creating them once.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use log::{info, warn};
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::copy::fnv1a;
+use crate::local_db_model::{Direction, IndexDefinition, IndexSyncReport, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64, META_DB_NAME};
+use crate::query::probe_paths;
+use crate::scan::{scan_directed, scan_from};
+use crate::value_codec::{json_payload, split_value};
+
+/// Side database holding the index definitions (name -> JSON array of paths).
+pub(crate) const INDEX_DEFS_DB_NAME: &str = "__index_defs";
+
+/// Side database holding the entries of all indexes.
+pub(crate) const INDEX_DB_NAME: &str = "__index";
+
+/// Metadata key of the entry format the indexes were built with; absent
+/// means the first format.
+const INDEX_FORMAT_KEY: &str = "index_format";
+
+/// Version of the entry format written by [`index_entries`]. Bump it when the
+/// key layout or [`encode_value`] changes, so existing indexes get rebuilt.
+pub(crate) const INDEX_FORMAT_VERSION: u64 = 3;
+
+/// Type tags, in the order values of different types sort in.
+const TAG_NULL: u8 = 0x00;
+const TAG_FALSE: u8 = 0x01;
+const TAG_TRUE: u8 = 0x02;
+const TAG_NUMBER: u8 = 0x03;
+const TAG_STRING: u8 = 0x04;
+const TAG_JSON: u8 = 0x05;
+
+/// Encoded bytes of a string or JSON value kept before it is cut and hashed.
+const MAX_ENCODED_TEXT: usize = 128;
+
+/// Largest key LMDB accepts with its default settings.
+const MAX_KEY_SIZE: usize = 511;
+
+/// Computes the entry keys of a stored value for every index in `definitions`.
+pub(crate) fn index_entries(definitions: &[IndexDefinition], key: &[u8], value: &[u8]) -> Vec<Vec<u8>> {
+    if definitions.is_empty() {
+        return Vec::new();
+    }
+
+    // Index keys would hold the values of encrypted records in plain text
+    if split_value(value).is_ok_and(|(header, _)| header.encrypted) {
+        return Vec::new();
+    }
+
+    let json = match json_payload(value) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Record cannot be indexed: {e}");
+            return Vec::new();
+        }
+    };
+
+    definitions
+        .iter()
+        .filter_map(|definition| {
+            let paths: Vec<&str> = definition.paths.iter().map(String::as_str).collect();
+            match probe_paths(&json, &paths) {
+                Ok(values) => {
+                    let entry = entry_key(&definition.name, &values, key);
+                    if entry.len() > MAX_KEY_SIZE {
+                        warn!("Record is too long to be indexed by {}: {} byte entry", definition.name, entry.len());
+                        return None;
+                    }
+                    Some(entry)
+                }
+                Err(e) => {
+                    warn!("Record cannot be indexed by {}: {e:?}", definition.name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl AppDbState {
+    pub(crate) fn read_index_definitions<T: Transaction>(&self, txn: &T) -> Result<Vec<IndexDefinition>, LmdbError> {
+        let (_, defs_db) = self.side_db(INDEX_DEFS_DB_NAME)?;
+        let cursor = txn.open_ro_cursor(defs_db)?;
+
+        let definitions = scan_from(&cursor, None)
+            .filter_map(|(name, paths)| {
+                let definition = serde_json::from_slice(paths).map(|paths| IndexDefinition {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    paths,
+                });
+                definition.inspect_err(|e| warn!("Invalid index definition: {e:?}")).ok()
+            })
+            .collect();
+        Ok(definitions)
+    }
+
+    /// Creates the index `name` over `paths` and fills it from the existing
+    /// records. Returns the number of records indexed.
+    ///
+    /// Paths are dotted and rooted at the model (e.g. `data.account_id`). A
+    /// record missing a path is indexed as if the value were `null`. From then
+    /// on the index is kept up to date by every write.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let paths = vec!["data.account_id".to_string(), "data.created_at".to_string()];
+    /// db.create_index("by_account_date", &paths)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the name is empty or contains a
+    /// NUL byte, `paths` is empty, or an index with this name already exists,
+    /// or a database error if the write fails.
+    pub fn create_index(&self, name: &str, paths: &[String]) -> Result<usize, AppResponse> {
+        validate_definition(name, paths)?;
+
+        let (env, _) = self.env_db()?;
+        let (_, defs_db) = self.side_db(INDEX_DEFS_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let definition = serde_json::to_vec(paths)?;
+        match txn.put(defs_db, &name, &definition, WriteFlags::NO_OVERWRITE) {
+            Ok(()) => {}
+            Err(LmdbError::KeyExist) => {
+                return Err(AppResponse::BadRequest(format!("Index {name} already exists")));
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let indexed = self.fill_index(&mut txn, &IndexDefinition { name: name.to_string(), paths: paths.to_vec() })?;
+        self.mark_index_format(&mut txn)?;
+        txn.commit()?;
+
+        info!("✅ Index {name} created over {indexed} records");
+        Ok(indexed)
+    }
+
+    /// Makes the defined indexes match `definitions`, for apps declaring
+    /// their indexes on every start.
+    ///
+    /// Missing indexes are created and indexes whose paths differ are rebuilt
+    /// over the new paths, all in one write transaction. Indexes already
+    /// matching are left untouched, so calling this on every start is cheap.
+    /// Defined indexes that are not declared are kept; remove them with
+    /// [`AppDbState::drop_index`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::IndexDefinition;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let report = db.ensure_indexes(&[IndexDefinition {
+    ///     name: "by_slug".to_string(),
+    ///     paths: vec!["data.slug".to_string()],
+    /// }])?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if a definition is invalid (see
+    /// [`AppDbState::create_index`]) or a name is declared twice, or a
+    /// database error if the write fails; nothing is changed then.
+    pub fn ensure_indexes(&self, definitions: &[IndexDefinition]) -> Result<IndexSyncReport, AppResponse> {
+        for (position, definition) in definitions.iter().enumerate() {
+            validate_definition(&definition.name, &definition.paths)?;
+            if definitions[..position].iter().any(|other| other.name == definition.name) {
+                return Err(AppResponse::BadRequest(format!("Index {} is declared twice", definition.name)));
+            }
+        }
+
+        let (env, _) = self.env_db()?;
+        let (_, defs_db) = self.side_db(INDEX_DEFS_DB_NAME)?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let existing = self.read_index_definitions(&txn)?;
+
+        let mut report = IndexSyncReport::default();
+        for definition in definitions {
+            let current = existing.iter().find(|existing| existing.name == definition.name);
+            match current {
+                Some(current) if current.paths == definition.paths => {
+                    report.unchanged.push(definition.name.clone());
+                    continue;
+                }
+                Some(_) => {
+                    clear_index_entries(&mut txn, index_db, &definition.name)?;
+                    report.rebuilt.push(definition.name.clone());
+                }
+                None => report.created.push(definition.name.clone()),
+            }
+            txn.put(defs_db, &definition.name, &serde_json::to_vec(&definition.paths)?, WriteFlags::empty())?;
+            self.fill_index(&mut txn, definition)?;
+        }
+        if !definitions.is_empty() {
+            self.mark_index_format(&mut txn)?;
+        }
+        txn.commit()?;
+
+        if !report.created.is_empty() || !report.rebuilt.is_empty() {
+            info!("✅ Indexes created: {:?}, rebuilt: {:?}", report.created, report.rebuilt);
+        }
+        Ok(report)
+    }
+
+    /// Rebuilds every index if it was built with another entry format than
+    /// the current one. Called when the database is opened.
+    pub(crate) fn validate_indexes(&self) -> Result<(), AppResponse> {
+        let (env, _) = self.env_db()?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+
+        let (definitions, format) = {
+            let txn = env.begin_ro_txn()?;
+            (self.read_index_definitions(&txn)?, get_meta_u64(&txn, meta_db, INDEX_FORMAT_KEY)?.unwrap_or(1))
+        };
+        if definitions.is_empty() || format == INDEX_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        warn!("Indexes of {} use entry format {format}, rebuilding", self.path);
+        let mut txn = env.begin_rw_txn()?;
+        txn.clear_db(index_db)?;
+        for definition in &definitions {
+            self.fill_index(&mut txn, definition)?;
+        }
+        self.mark_index_format(&mut txn)?;
+        txn.commit()?;
+
+        info!("✅ Rebuilt {} indexes", definitions.len());
+        Ok(())
+    }
+
+    /// Adds the entries of every record for `definition`. Returns the number
+    /// of records indexed.
+    fn fill_index(&self, txn: &mut RwTransaction, definition: &IndexDefinition) -> Result<usize, LmdbError> {
+        let (_, db) = self.env_db()?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let definitions = std::slice::from_ref(definition);
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = {
+            let cursor = txn.open_ro_cursor(db)?;
+            scan_from(&cursor, None)
+                .flat_map(|(key, value)| index_entries(definitions, key, value).into_iter().map(|entry| (entry, key.to_vec())))
+                .collect()
+        };
+        for (entry, key) in &entries {
+            txn.put(index_db, entry, key, WriteFlags::empty())?;
+        }
+        Ok(entries.len())
+    }
+
+    fn mark_index_format(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        put_meta_u64(txn, meta_db, INDEX_FORMAT_KEY, INDEX_FORMAT_VERSION)
+    }
+
+    /// Removes the index `name` and its entries. Returns whether it existed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Database operations fail
+    /// - Transaction commit fails
+    pub fn drop_index(&self, name: &str) -> Result<bool, LmdbError> {
+        let (env, defs_db) = self.side_db(INDEX_DEFS_DB_NAME)?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        match txn.del(defs_db, &name, None) {
+            Ok(()) => {}
+            Err(LmdbError::NotFound) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        clear_index_entries(&mut txn, index_db, name)?;
+        txn.commit()?;
+        Ok(true)
+    }
+
+    /// Lists the defined indexes.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn list_indexes(&self) -> Result<Vec<IndexDefinition>, LmdbError> {
+        let (env, _) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        self.read_index_definitions(&txn)
+    }
+
+    /// Retrieves records through the index `name`.
+    ///
+    /// `values` fixes the leading paths of the index to exact values; the
+    /// matching records are returned ordered by the remaining paths (then by
+    /// ID) in `direction`. With an index over `["data.account_id",
+    /// "data.created_at"]`, `values = ["acc_1"]` yields the records of that
+    /// account sorted by date without scanning the other records. Records that
+    /// fail to decode are logged and skipped but still count towards `limit`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::Direction;
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let latest = db.query_index("by_account_date", &[json!("acc_1")], Direction::Desc, 20)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if there is no index `name`,
+    /// [`AppResponse::BadRequest`] if more values than indexed paths are
+    /// given, or a database error if the read fails.
+    pub fn query_index(&self, name: &str, values: &[JsonValue], direction: Direction, limit: usize) -> Result<Vec<LocalDbModel>, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+
+        let definition = self
+            .read_index_definitions(&txn)?
+            .into_iter()
+            .find(|definition| definition.name == name)
+            .ok_or_else(|| AppResponse::NotFound(format!("Index {name} does not exist")))?;
+        if values.len() > definition.paths.len() {
+            return Err(AppResponse::BadRequest(format!(
+                "Index {name} covers {} paths, got {} values",
+                definition.paths.len(),
+                values.len()
+            )));
+        }
+
+        let mut prefix = index_prefix(name);
+        let mut cut = Vec::new();
+        for (value, path) in values.iter().zip(&definition.paths) {
+            if encode_value(Some(value), &mut prefix) {
+                cut.push((path.as_str(), value));
+            }
+        }
+        // The byte following the prefix is a type tag or the first byte of a
+        // UTF-8 ID, never 0xFF, so this sorts after every entry with `prefix`.
+        let upper = [prefix.as_slice(), &[0xFF]].concat();
+        let start = match direction {
+            Direction::Asc => prefix.as_slice(),
+            Direction::Desc => upper.as_slice(),
+        };
+
+        let cursor = txn.open_ro_cursor(index_db)?;
+        let mut models = Vec::new();
+        for (_, id) in scan_directed(&cursor, Some(start), direction)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .take(limit)
+        {
+            match txn.get(db, &id) {
+                Ok(value) => match self.decode_record(&txn, id, value) {
+                    Ok(model) if cut.is_empty() || matches_cut_values(&model, &cut) => models.push(model),
+                    Ok(_) => {}
+                    Err(e) => info!("Error decoding model: {e}"),
+                },
+                Err(LmdbError::NotFound) => warn!("Index {name} references a missing record"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(models)
+    }
+
+    /// Retrieves the records whose first indexed path holds `value`, for
+    /// lookups such as "note by slug".
+    ///
+    /// The index entries, IDs and records are resolved in one read
+    /// transaction. Equivalent to [`AppDbState::query_index`] with a single
+    /// value, in ascending order and without a limit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let note = db.get_by_indexed_value("by_slug", &json!("groceries"))?.pop();
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if there is no index `name`, or a
+    /// database error if the read fails.
+    pub fn get_by_indexed_value(&self, name: &str, value: &JsonValue) -> Result<Vec<LocalDbModel>, AppResponse> {
+        self.query_index(name, std::slice::from_ref(value), Direction::Asc, usize::MAX)
+    }
+}
+
+/// Returns up to `limit` IDs of records whose first value in the index `name`
+/// is a number below `bound`, lowest first.
+pub(crate) fn ids_below<T: Transaction>(txn: &T, index_db: Database, name: &str, bound: f64, limit: usize) -> Result<Vec<Vec<u8>>, LmdbError> {
+    let prefix = index_prefix(name);
+    let start = [prefix.as_slice(), &[TAG_NUMBER]].concat();
+    let mut end = prefix;
+    encode_value(Some(&JsonValue::from(bound)), &mut end);
+
+    let cursor = txn.open_ro_cursor(index_db)?;
+    let ids = scan_from(&cursor, Some(start.as_slice()))
+        .take_while(|(key, _)| *key < end.as_slice())
+        .take(limit)
+        .map(|(_, id)| id.to_vec())
+        .collect();
+    Ok(ids)
+}
+
+/// Returns the IDs of the records whose first value in the index `name` is a
+/// number at or above `bound`, lowest first.
+pub(crate) fn ids_from<T: Transaction>(txn: &T, index_db: Database, name: &str, bound: f64) -> Result<Vec<Vec<u8>>, LmdbError> {
+    let prefix = index_prefix(name);
+    let mut start = prefix.clone();
+    encode_value(Some(&JsonValue::from(bound)), &mut start);
+    let end = [prefix.as_slice(), &[TAG_NUMBER + 1]].concat();
+
+    let cursor = txn.open_ro_cursor(index_db)?;
+    let ids = scan_from(&cursor, Some(start.as_slice()))
+        .take_while(|(key, _)| *key < end.as_slice())
+        .map(|(_, id)| id.to_vec())
+        .collect();
+    Ok(ids)
+}
+
+/// Returns the IDs of the records whose first value in the index `name` is
+/// `value`, in ID order.
+///
+/// The records are not rechecked, so callers must not pass a value that
+/// [`is_cut`] reports as cut.
+pub(crate) fn ids_with_value<T: Transaction>(txn: &T, index_db: Database, name: &str, value: &JsonValue) -> Result<Vec<String>, LmdbError> {
+    let mut prefix = index_prefix(name);
+    encode_value(Some(value), &mut prefix);
+
+    let cursor = txn.open_ro_cursor(index_db)?;
+    let ids = scan_from(&cursor, Some(prefix.as_slice()))
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .map(|(_, id)| String::from_utf8_lossy(id).into_owned())
+        .collect();
+    Ok(ids)
+}
+
+/// Deletes every entry of the index `name`.
+fn clear_index_entries(txn: &mut RwTransaction, index_db: Database, name: &str) -> Result<(), LmdbError> {
+    let prefix = index_prefix(name);
+    let keys: Vec<Vec<u8>> = {
+        let cursor = txn.open_ro_cursor(index_db)?;
+        scan_from(&cursor, Some(&prefix))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, _)| key.to_vec())
+            .collect()
+    };
+    for key in keys {
+        txn.del(index_db, &key, None)?;
+    }
+    Ok(())
+}
+
+fn validate_definition(name: &str, paths: &[String]) -> Result<(), AppResponse> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(AppResponse::BadRequest("Index name must be non-empty and contain no NUL byte".to_string()));
+    }
+    if paths.is_empty() {
+        return Err(AppResponse::BadRequest(format!("Index {name} needs at least one path")));
+    }
+    Ok(())
+}
+
+fn index_prefix(name: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(name.len() + 1);
+    prefix.extend_from_slice(name.as_bytes());
+    prefix.push(0x00);
+    prefix
+}
+
+/// Returns whether index entries hold `value` cut and hashed instead of in
+/// full, so that lookups by it have to recheck the records.
+pub(crate) fn is_cut(value: &JsonValue) -> bool {
+    encode_value(Some(value), &mut Vec::new())
+}
+
+/// Checks the values that were cut in the lookup key against the record.
+fn matches_cut_values(model: &LocalDbModel, cut: &[(&str, &JsonValue)]) -> bool {
+    let Ok(json) = serde_json::to_string(model) else {
+        return false;
+    };
+    let paths: Vec<&str> = cut.iter().map(|(path, _)| *path).collect();
+    probe_paths(&json, &paths).is_ok_and(|probed| {
+        probed.iter().zip(cut).all(|(found, (_, value))| found.as_ref() == Some(*value))
+    })
+}
+
+fn entry_key(name: &str, values: &[Option<JsonValue>], id: &[u8]) -> Vec<u8> {
+    let mut key = index_prefix(name);
+    for value in values {
+        encode_value(value.as_ref(), &mut key);
+    }
+    key.extend_from_slice(id);
+    key
+}
+
+/// Appends the order-preserving encoding of `value` (`None` encodes as
+/// `null`) and returns whether it was cut.
+///
+/// Values of different types sort null < false < true < numbers < strings <
+/// arrays and objects. Numbers are stored as big-endian `f64` with the sign
+/// bit flipped (all bits for negatives) so they compare bytewise, followed by
+/// their exact integer value as a sign-flipped big-endian `i128`, so integers
+/// beyond 2^53 that round to the same `f64` still sort and match exactly;
+/// `1` and `1.0` encode the same. Strings
+/// escape `0x00` as `0x00 0xFE` and end with `0x00 0x00`, so a string sorts
+/// before any longer string it prefixes. Past [`MAX_ENCODED_TEXT`] bytes the
+/// text is cut and ends with `0x00 0x01` and the FNV-1a hash of the full
+/// text, so long values sharing their first bytes sort by hash. Arrays and
+/// objects only get a stable position through their JSON text.
+fn encode_value(value: Option<&JsonValue>, out: &mut Vec<u8>) -> bool {
+    match value {
+        None | Some(JsonValue::Null) => out.push(TAG_NULL),
+        Some(JsonValue::Bool(false)) => out.push(TAG_FALSE),
+        Some(JsonValue::Bool(true)) => out.push(TAG_TRUE),
+        Some(JsonValue::Number(number)) => {
+            let float = number.as_f64().unwrap_or(0.0);
+            let bits = float.to_bits();
+            let ordered = if bits >> 63 == 0 { bits | 1 << 63 } else { !bits };
+            // Floats only tie with integers of their own value, which `as` keeps
+            let exact = match (number.as_i64(), number.as_u64()) {
+                (Some(int), _) => i128::from(int),
+                (_, Some(uint)) => i128::from(uint),
+                _ => float as i128,
+            };
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&ordered.to_be_bytes());
+            out.extend_from_slice(&(exact as u128 ^ 1 << 127).to_be_bytes());
+        }
+        Some(JsonValue::String(text)) => {
+            out.push(TAG_STRING);
+            return encode_bytes(text.as_bytes(), out);
+        }
+        Some(other) => {
+            out.push(TAG_JSON);
+            return encode_bytes(other.to_string().as_bytes(), out);
+        }
+    }
+    false
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) -> bool {
+    let start = out.len();
+    for &byte in bytes {
+        if out.len() - start >= MAX_ENCODED_TEXT {
+            out.extend_from_slice(&[0x00, 0x01]);
+            out.extend_from_slice(&fnv1a(bytes).to_be_bytes());
+            return true;
+        }
+        out.push(byte);
+        if byte == 0x00 {
+            out.push(0xFE);
+        }
+    }
+    out.extend_from_slice(&[0x00, 0x00]);
+    false
+}
@@ -0,0 +1,168 @@
+//! JSON Patch (RFC 6902) on records.
+//!
+//! [`AppDbState::apply_json_patch`] edits the `data` of one record with a
+//! list of [`PatchOp`]s, for fine-grained edits that leave the rest of the
+//! record alone and for patches received from a server. The operations are
+//! applied in order to a copy of the record, which is written only if all of
+//! them succeed, in the write transaction that read it.
+//!
+//! Paths are JSON Pointers (RFC 6901): `/` separates the members, `~1`
+//! stands for `/` and `~0` for `~` in a member name, and array elements are
+//! addressed by index.
+
+use lmdb::{Error as LmdbError, Transaction};
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{LocalDbModel, PatchOp};
+use crate::local_db_state::AppDbState;
+
+impl AppDbState {
+    /// Applies `ops` to the `data` of the record `id` and returns the
+    /// patched record. Either every operation is applied or none is. The
+    /// record keeps its `hash`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::PatchOp;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("mail".to_string())?;
+    ///
+    /// let ops: Vec<PatchOp> = serde_json::from_str(r#"[
+    ///     {"op": "test", "path": "/status", "value": "draft"},
+    ///     {"op": "replace", "path": "/status", "value": "sent"},
+    ///     {"op": "add", "path": "/labels/-", "value": "outbox"}
+    /// ]"#)?;
+    /// let sent = db.apply_json_patch("mail_1", &ops)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if no record has the ID,
+    /// [`AppResponse::Conflict`] with the stored hash if a `test` operation
+    /// fails, [`AppResponse::BadRequest`] if another operation cannot be
+    /// applied, e.g. its path does not exist, or the errors of
+    /// [`AppDbState::put`].
+    pub fn apply_json_patch(&self, id: &str, ops: &[PatchOp]) -> Result<LocalDbModel, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let mut model = match txn.get(db, &id) {
+            Ok(value) => self.decode_record(&txn, id.as_bytes(), value)?,
+            Err(LmdbError::NotFound) => return Err(AppResponse::NotFound(format!("No model found with id: {id}"))),
+            Err(e) => return Err(e.into()),
+        };
+        for (index, op) in ops.iter().enumerate() {
+            match apply(&mut model.data, op) {
+                Ok(()) => {}
+                Err(PatchError::TestFailed(path)) => {
+                    return Err(AppResponse::Conflict {
+                        message: format!("JSON patch test of {path} failed on record {id}"),
+                        current_hash: model.hash,
+                    });
+                }
+                Err(PatchError::Invalid(reason)) => {
+                    return Err(AppResponse::BadRequest(format!("JSON patch operation {index} failed on record {id}: {reason}")));
+                }
+            }
+        }
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn)?;
+        Ok(model)
+    }
+}
+
+/// Why an operation could not be applied.
+enum PatchError {
+    /// A `test` operation failed at the path.
+    TestFailed(String),
+    Invalid(String),
+}
+
+fn apply(data: &mut JsonValue, op: &PatchOp) -> Result<(), PatchError> {
+    match op {
+        PatchOp::Add { path, value } => add(data, path, value.clone()),
+        PatchOp::Remove { path } => remove(data, path).map(drop),
+        PatchOp::Replace { path, value } => {
+            *data.pointer_mut(path).ok_or_else(|| missing(path))? = value.clone();
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            if path.starts_with(&format!("{from}/")) {
+                return Err(PatchError::Invalid(format!("cannot move {from} into its own child {path}")));
+            }
+            let value = remove(data, from)?;
+            add(data, path, value)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = data.pointer(from).ok_or_else(|| missing(from))?.clone();
+            add(data, path, value)
+        }
+        PatchOp::Test { path, value } => match data.pointer(path) {
+            Some(current) if current == value => Ok(()),
+            _ => Err(PatchError::TestFailed(path.clone())),
+        },
+    }
+}
+
+fn add(data: &mut JsonValue, path: &str, value: JsonValue) -> Result<(), PatchError> {
+    let Some((parent, token)) = split_pointer(path)? else {
+        *data = value;
+        return Ok(());
+    };
+    match data.pointer_mut(parent).ok_or_else(|| missing(parent))? {
+        JsonValue::Object(map) => {
+            map.insert(token, value);
+            Ok(())
+        }
+        JsonValue::Array(items) => {
+            let index = match token.as_str() {
+                "-" => items.len(),
+                _ => array_index(&token).filter(|index| *index <= items.len()).ok_or_else(|| missing(path))?,
+            };
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err(PatchError::Invalid(format!("the parent of {path} is not an object or array"))),
+    }
+}
+
+fn remove(data: &mut JsonValue, path: &str) -> Result<JsonValue, PatchError> {
+    let Some((parent, token)) = split_pointer(path)? else {
+        return Err(PatchError::Invalid("cannot remove the whole data".to_string()));
+    };
+    let removed = match data.pointer_mut(parent) {
+        Some(JsonValue::Object(map)) => map.remove(&token),
+        Some(JsonValue::Array(items)) => array_index(&token).filter(|index| *index < items.len()).map(|index| items.remove(index)),
+        _ => None,
+    };
+    removed.ok_or_else(|| missing(path))
+}
+
+/// Splits a pointer into the pointer of its parent and its last, unescaped
+/// token, `None` for the whole document.
+fn split_pointer(path: &str) -> Result<Option<(&str, String)>, PatchError> {
+    if path.is_empty() {
+        return Ok(None);
+    }
+    match path.rsplit_once('/') {
+        Some((parent, token)) => Ok(Some((parent, token.replace("~1", "/").replace("~0", "~")))),
+        None => Err(PatchError::Invalid(format!("{path} is not a JSON Pointer"))),
+    }
+}
+
+/// Parses an array index, which has no sign or leading zeros.
+fn array_index(token: &str) -> Option<usize> {
+    let canonical = token == "0" || (!token.is_empty() && !token.starts_with('0') && token.bytes().all(|b| b.is_ascii_digit()));
+    canonical.then(|| token.parse().ok()).flatten()
+}
+
+fn missing(path: &str) -> PatchError {
+    PatchError::Invalid(format!("{path} does not exist"))
+}
@@ -15,7 +15,7 @@
 //! ## Quick Start
 //!
 //! ```no_run
-//! use offline_first_core::{create_db, post_data as push_data, get_by_id};
+//! use offline_first_core::{create_db, free_c_string, post_data as push_data, get_by_id};
 //! use std::ffi::CString;
 //!
 //! // Create database instance
@@ -25,58 +25,264 @@
 //! // Insert data
 //! let json_data = CString::new(r#"{"id":"1","hash":"abc","data":{"key":"value"}}"#).unwrap();
 //! let result = push_data(db_state, json_data.as_ptr());
+//! free_c_string(result);
 //! ```
 //!
+//! ## Memory Management
+//!
+//! Every function returning `*const c_char` hands over ownership of a string
+//! allocated by this library, including the strings passed to callbacks such
+//! as [`WatchCallback`]. Release each one exactly once with
+//! [`free_c_string`]; freeing it with the C `free` (or Dart's `malloc.free`)
+//! uses the wrong allocator, and not freeing it leaks. Copy the contents
+//! (e.g. `toDartString()`) before releasing. [`ByteBuffer`]s are released
+//! with [`free_buffer`] the same way.
+//!
+//! ## Handles
+//!
+//! [`create_db`] returns a [`DbHandle`], an opaque integer that every other
+//! function takes to identify the database; `0` means the open failed. A
+//! closed or unknown handle is rejected with a `BadRequest` response instead
+//! of touching freed memory. Opening a database that is already open returns
+//! its existing handle, so a Flutter hot restart that lost its handles gets
+//! the live database back.
+//!
+//! ## Panics
+//!
+//! No panic unwinds into the caller. A panic inside a function is caught at
+//! the boundary and reported as a `DatabaseError` response; functions
+//! returning null, `0` or nothing record it for [`get_last_error`] instead. This
+//! needs the crate built with `panic = "unwind"`, as the release profile is.
+//!
 //! ## FFI Functions
 //!
 //! This library exposes C-compatible functions for cross-language integration:
 //!
 //! - [`create_db`] - Initialize database instance
+//! - [`create_db_with_path`] - Initialize a database instance in a given directory
+//! - [`create_db_with_config`] - Initialize a database instance with JSON options such as the map size
 //! - [`post_data`] - Insert new records (alias: `push_data`)
 //! - [`get_by_id`] - Retrieve records by ID
+//! - [`get_field`] - Retrieve one value of a record's data by JSON Pointer
+//! - [`get_by_ids`] - Retrieve several records by ID in one call
+//! - [`record_exists`] - Check whether a record exists without decoding it
+//! - [`attach_asset_db`] / [`detach_asset_db`] - Attach a read-only asset database
+//! - [`get_with_fallback`] - Retrieve by ID from user data, then the asset database
 //! - [`get_all`] - Retrieve all records
+//! - [`get_all_ids`] - List record IDs without loading the records
+//! - [`count_records`] - Count records without loading them
+//! - [`get_paginated`] - Retrieve one page of records (limit/offset)
+//! - [`get_page_after`] - Retrieve the page following a continuation token
+//! - [`get_all_desc`], [`get_paginated_desc`], [`get_page_after_desc`] - Descending-order variants
+//! - [`get_by_prefix`] - Retrieve all records whose ID starts with a prefix
+//! - [`count_namespace`], [`clear_namespace`] - Count or delete the records of a key namespace such as `user:`
+//! - [`collection_put`], [`collection_get_by_id`], [`collection_get_all`], [`collection_delete_by_id`], [`list_collections`], [`drop_collection`] - Named collections, each dropped at once
+//! - [`write_transaction`] - Atomic writes spanning `main` and named collections
+//! - [`get_groups`] - Retrieve the records of several prefixes in one transaction
+//! - [`get_range`] - Retrieve the records in a key range
+//! - [`query`] - Retrieve the records matching a path filter
+//! - [`query_sorted`], [`get_all_sorted`] - Retrieve records sorted by a data field
+//! - [`query_projected`] - Retrieve only selected fields of matching records
+//! - [`aggregate`] - Count, sum, min, max and average over matching records
+//! - [`distinct`] - List the unique values at a path, e.g. for filter dropdowns
+//! - [`get_commit_sequence`], [`get_by_id_at`], [`query_at`] - Read-your-writes across isolates with commit sequence tokens
+//! - [`get_change_token`], [`get_changes_between`] - Poll whether anything changed since the last render, without callbacks
+//! - [`get_all_with_quarantine`] - Retrieve all records, reporting undecodable ones
+//! - [`quarantine_list`] - List records that cannot be decoded
+//! - [`mark_for_resync`] - Flag records for re-download from the server
+//! - [`get_resync_queue`] - List records flagged for re-download
+//! - [`clear_resync`] - Acknowledge re-downloaded records
 //! - [`update_data`] - Update existing records
+//! - [`upsert_data`] - Insert or replace a record in one transaction
+//! - [`update_if_hash`] - Replace a record only if it still has the hash read, with a `Conflict` response otherwise
+//! - [`compare_and_swap`] - Replace a field of a record only if it still holds the expected value, atomically
+//! - [`apply_json_patch`] - Apply a JSON Patch (RFC 6902) to the data of a record, atomically
 //! - [`delete_by_id`] - Delete records by ID
+//! - [`delete_many`] - Delete several records by ID in one transaction
+//! - [`soft_delete`], [`restore`] and [`purge_deleted`] - Hide records from reads in a recoverable trash, restore them, and empty it
 //! - [`clear_all_records`] - Clear all database contents
 //! - [`reset_database`] - Reset database to clean state
+//! - [`rename_database`], [`clone_database`] - Rename the database without losing its records, or copy it to a new name
+//! - [`list_databases`] - List the databases of a directory with their size and last modification
+//! - [`delete_database`] - Delete a closed database and its lock file by name or path
 //! - [`close_database`] - Explicit connection cleanup
+//! - [`copy_records`] - Copy matching records into another database
+//! - [`shard_by`] - Redistribute records into hash shards by a field
+//! - [`build_prebuilt_db`] - Generate a compacted asset database from a dataset
+//! - [`apply_dataset_patch`] - Apply a signed differential update to a dataset database
+//! - [`get_dataset_version`] - Read the dataset version of a database
+//! - [`get_meta_value`], [`set_meta_value`], [`remove_meta_value`] - Keep app bookkeeping such as sync checkpoints out of the records
+//! - [`import_from_file`] - Import a (optionally signed) dataset file
+//! - [`export_csv`] - Write the records to CSV with `data` flattened into columns, for spreadsheets
+//! - [`import_ndjson`] - Stream a large NDJSON file in, skipping, overwriting or failing on existing IDs
+//! - [`analyze_storage`] - Report value sizes, compressibility and the largest records
+//! - [`get_memory_stats`] - Report resident map pages and outstanding returned strings
+//! - [`free_c_string`] - Release a string returned by this library
+//! - [`set_response_format`] - Opt into responses with stable machine-readable error codes
+//! - [`get_by_id_buffer`], [`get_all_buffer`], [`free_buffer`] - Length-prefixed [`ByteBuffer`] variants of the reads
+//! - [`create_index`], [`drop_index`], [`list_indexes`] - Manage compound indexes over JSON paths
+//! - [`ensure_indexes`] - Declare the indexes on every start; missing or changed ones are (re)built
+//! - [`query_index`] - Retrieve records through an index, sorted by its remaining paths
+//! - [`get_by_indexed_value`] - Look up records by an indexed value, e.g. a slug
+//! - [`set_hash_index`], [`find_by_hash`] - Find the records with a given hash, e.g. to skip writing content already stored
+//! - [`set_number_policy`] - Choose how integers beyond 2^53 are written
+//! - [`set_overflow_threshold`] - Move large fields of oversized records to a chunk store
+//! - [`set_cache_limit`] - Bound the database as a cache with oldest-first eviction
+//! - [`set_write_rate_limit`] - Throttle write bursts with a token bucket
+//! - [`get_startup_report`] - Tell whether the previous session ended without closing the database
+//! - [`run_maintenance`], [`set_compaction_policy`], [`compact`] - Compact fragmented databases when the device is idle and charging
+//! - [`backup_database`], [`compact_database`] - Take a consistent copy of the database, optionally without free pages, while it stays open and writable
+//! - [`start_expiry_sweeper`], [`stop_expiry_sweeper`] - Delete expired records on a background thread
+//! - [`notify_app_background`], [`notify_app_foreground`] - Sync and pause background work on app lifecycle changes
+//! - [`flush_database`] - Flush commits to disk for databases opened without sync on commit
+//! - [`watch`], [`unwatch`] - Receive debounced batches of changed record IDs under a prefix
+//! - [`register_dart_post_cobject`], [`watch_port`] - Stream the same batches into a Dart `ReceivePort`
+//! - [`subscribe_query`], [`unsubscribe_query`] - Keep the result set of a query current with added, updated and removed events
+//! - [`get_all_delta`], [`ack_delta`], [`remove_delta_consumer`] - Changes-only reads per named consumer
+//! - [`set_delta_encoding`], [`get_encoded_delta`] - Deltas with updates as JSON merge patches against the acknowledged version
+//! - [`set_changelog_retention`], [`purge_changelog`] - Bound the change log and its deletions on long-lived installs
+//! - [`plan_sync`] - Preview what a sync would push, pull and conflict on
+//! - [`set_sync_limits`] - Cap the payload of sync batches and the size of synced records
+//! - [`merge_remote`], [`list_conflicts`], [`resolve_conflict`] - Merge server changes and settle conflicts from an inbox
+//! - [`apply_remote_changes`] - Merge server changes settling conflicts by policy: last write wins, keep a side, or a callback
+//! - [`set_crdt_mode`] - Merge concurrent edits of a record field by field, last writer wins per field
+//! - [`set_record_timestamps`] - Stamp `created_at` and `updated_at` on every record written
+//! - [`get_modified_since`] - Retrieve the records written since a time, through an index over `updated_at`
+//! - [`set_history_limit`], [`get_history`] and [`revert_to_version`] - Keep the last versions of each record for undo and audit, and restore one
+//! - [`get_hlc`], [`get_record_hlc`], [`observe_hlc`] - Hybrid logical clock timestamps of record writes, ordered across devices
+//! - [`get_sync_digest`], [`diff_sync_digest`], [`get_sync_digest_records`] - Find the divergent key ranges against the server from range hashes of the records
+//! - [`set_sync_key`], [`seal_sync_records`], [`open_sync_records`] - Encrypt record bodies end to end for sync, with [`wrap_sync_key`] and [`set_wrapped_sync_key`] to move the key between devices
+//! - [`put_attachment`], [`get_attachment`], [`delete_attachment`] - Store attachments such as photos apart from the records
+//! - [`read_attachment_chunk`], [`ack_attachment_chunk`], [`begin_attachment_download`], [`write_attachment_chunk`], [`get_attachment_progress`], [`get_pending_attachments`], [`set_attachment_progress_callback`] - Resumable chunked attachment transfers with per-chunk checksums
+//! - [`begin_sync_run`], [`finish_sync_run`], [`get_sync_status`], [`get_sync_history`] - Sync run telemetry and last-sync status
+//! - [`sync_now`] - End-to-end sync with an HTTP sync server (`http-sync` feature), or any [`sync_engine::SyncAdapter`] from Rust
+//! - [`enqueue_outbox`], [`peek_outbox`], [`ack_outbox`] - Outbox of pending remote mutations, replayed in order once online
+//! - [`run_simulation`] - Replay a multi-device sync simulation deterministically (`simulation` feature)
+//! - [`backfill_field`] - Set a default value on records lacking a new field
+//! - [`register_migration`], [`migrate`], [`get_schema_version`] - Migrate records between schema versions in batched transactions
+//! - [`register_external_collection`], [`query_external`], [`join_external`] - Query and join read-only JSON lookup files without importing them
+//! - [`get_last_error`] - Why a function returning null or `0`, such as [`create_db`], failed
+//! - [`get_library_version`] - Crate and LMDB versions and enabled features, for compatibility checks
+//! - [`set_encryption_key`], [`remove_encryption_key`] - Encrypt each tenant's records with its own key, and crypto-shred a tenant by dropping it
+//! - [`set_field_encryption`], [`remove_field_encryption`] - Encrypt selected fields of records, keeping the rest queryable
+//! - [`get_current_time`], [`set_clock_offset`], [`set_fixed_clock`] - Correct or freeze the clock used for expiry and stored timestamps
 
 pub mod local_db_model;
 pub mod local_db_state;
+pub mod namespace;
+pub mod query;
+pub mod resync;
+#[cfg(feature = "http-sync")]
+pub mod http_sync;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod sync_engine;
+pub mod value_codec;
+mod aggregate;
+mod asset;
+mod attachments;
+mod backfill;
+mod backup;
+mod cache;
+mod clock;
+mod collections;
+mod conflicts;
+mod copy;
+mod crdt;
+mod dart_port;
+mod dataset;
+mod delta;
+mod digest;
+mod encryption;
+mod export;
+mod expiry;
+mod external;
+mod field_encryption;
+mod hash_index;
+mod hlc;
+mod import;
+mod index;
+mod json_patch;
+mod lifecycle;
+mod maintenance;
+mod manage;
+mod meta;
+mod migration;
+mod numbers;
+mod outbox;
+mod overflow;
+mod rate_limit;
+mod record_history;
+mod registry;
+mod signing;
+mod scan;
+mod session;
+mod soft_delete;
+mod startup;
+mod stats;
+mod subscription;
+mod sync_encryption;
+mod sync_history;
+mod sync_plan;
+mod timestamps;
+mod transaction;
+mod versions;
+mod watch;
+mod writer;
 mod test;
 mod app_response;
 
-use crate::local_db_model::LocalDbModel;
+use crate::local_db_model::{AggregateSpec, AttachmentManifest, AttachmentProgress, Backfill, BackupResult, BuildOptions, CacheLimit, ChangeBatch, ChangelogCutoff, CompactionPolicy, ConflictEntry, ConflictPolicy, ConflictResolution, ConflictSide, DbOptions, Direction, ExpirySweep, Hlc, ImportOptions, IndexDefinition, LocalDbModel, NumberPolicy, PatchOp, RemoteChanges, SubscriptionEvent, SyncDigest, SyncEndpointConfig, SyncLimits, SyncManifest, SyncRun, SyncRunReport, WriteOp, WriteRateLimit};
 use crate::local_db_state::AppDbState;
+use crate::query::{PathFilter, SortSpec};
+pub use crate::dart_port::{DartPort, DartPostCObjectFn};
+pub use crate::registry::DbHandle;
 
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::PoisonError;
 use log::{info, warn};
 use std::path::Path;
 
 use crate::app_response::AppResponse;
 
-/// Creates a new database instance with the specified name.
+/// Response format of every function, see [`set_response_format`].
+static RESPONSE_FORMAT: AtomicU32 = AtomicU32::new(1);
+
+/// Newest version accepted by [`set_response_format`].
+const LATEST_RESPONSE_FORMAT: u32 = 2;
+
+thread_local! {
+    /// Most recent failure of a function that can only return null, see [`get_last_error`].
+    static LAST_ERROR: RefCell<Option<AppResponse>> = const { RefCell::new(None) };
+}
+
+/// Opens the database with the specified name, creating it when missing, and
+/// returns its handle.
 ///
 /// This function initializes an LMDB environment and creates the main database
 /// for storing key-value pairs. The database will be created as a directory
 /// with `.lmdb` extension.
 ///
+/// Opening a name that is already open returns the existing handle, so an app
+/// that lost its handles, e.g. on a Flutter hot restart, gets the live
+/// database back instead of a second environment. Release it with
+/// [`close_database`].
+///
 /// # Parameters
 ///
 /// * `name` - A null-terminated C string containing the database name
 ///
 /// # Returns
 ///
-/// Returns a pointer to the [`AppDbState`] instance on success, or a null pointer on failure.
-/// The caller is responsible for managing the returned pointer's lifetime.
+/// Returns the handle of the database on success, or `0` on failure.
 ///
 /// # Safety
 ///
-/// This function is unsafe because it:
-/// - Dereferences a raw pointer without validation
-/// - Returns a raw pointer that must be properly managed
-/// - Requires the input string to be valid UTF-8
+/// The name must be a valid pointer to a null-terminated UTF-8 string.
 ///
 /// # Examples
 ///
@@ -85,73 +291,229 @@ use crate::app_response::AppResponse;
 /// use offline_first_core::create_db;
 ///
 /// let name = CString::new("test_database").unwrap();
-/// let db_state = create_db(name.as_ptr());
-/// 
-/// if !db_state.is_null() {
+/// let db = create_db(name.as_ptr());
+///
+/// if db != 0 {
 ///     // Database created successfully
 /// }
 /// ```
 ///
 /// # Errors
 ///
-/// Returns null pointer if:
+/// Returns `0` if:
 /// - Input name pointer is null
 /// - Input string contains invalid UTF-8
 /// - Database initialization fails
+///
+/// The reason is then available from [`get_last_error`].
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn create_db(name: *const c_char) -> *mut AppDbState {
-    if name.is_null() {
-        warn!("Null name pointer passed to create_db");
-        return std::ptr::null_mut();
-    }
+pub extern "C" fn create_db(name: *const c_char) -> DbHandle {
+    ffi_boundary("create_db", || {
+        if name.is_null() {
+            set_last_error(AppResponse::BadRequest("Null name pointer passed to create_db".to_string()));
+            return 0;
+        }
 
-    let name_str = match unsafe { CStr::from_ptr(name).to_str() } {
-        Ok(s) => s,
-        Err(e) => {
-            warn!("Invalid UTF-8 in name parameter: {e}");
-            return std::ptr::null_mut();
+        let name_str = match unsafe { CStr::from_ptr(name).to_str() } {
+            Ok(s) => s,
+            Err(e) => {
+                set_last_error(AppResponse::BadRequest(format!("Invalid UTF-8 in name parameter: {e}")));
+                return 0;
+            }
+        };
+
+        open_database(name_str, &DbOptions::default())
+    })
+}
+
+/// Opens the database with the specified name in `base_dir`, creating it
+/// when missing, and returns its handle.
+///
+/// Behaves like [`create_db`], but places the database directory in
+/// `base_dir` instead of the working directory, which is not writable in the
+/// iOS and Android sandboxes. Pass the app's documents or support directory,
+/// e.g. from `getApplicationSupportDirectory()` in Flutter. Missing
+/// directories are created. The same name in different directories opens
+/// different databases.
+///
+/// # Parameters
+///
+/// * `name` - A null-terminated C string containing the database name
+/// * `base_dir` - A null-terminated C string with the directory holding the
+///   database
+///
+/// # Returns
+///
+/// Returns the handle of the database on success, or `0` on failure, with
+/// the reason available from [`get_last_error`].
+///
+/// # Safety
+///
+/// Both parameters must be valid pointers to null-terminated UTF-8 strings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::create_db_with_path;
+///
+/// let name = CString::new("notes").unwrap();
+/// let base_dir = CString::new("/data/user/0/com.example.app/files").unwrap();
+/// let db = create_db_with_path(name.as_ptr(), base_dir.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn create_db_with_path(name: *const c_char, base_dir: *const c_char) -> DbHandle {
+    ffi_boundary("create_db_with_path", || {
+        if name.is_null() || base_dir.is_null() {
+            set_last_error(AppResponse::BadRequest("Null pointer passed to create_db_with_path".to_string()));
+            return 0;
         }
-    };
 
-    // Use a more appropriate directory path for cross-platform compatibility
-    let db_path = format!("{name_str}");
-    let lmdb_dir = format!("{db_path}.lmdb");
+        let name_str = match unsafe { CStr::from_ptr(name).to_str() } {
+            Ok(s) => s,
+            Err(e) => {
+                set_last_error(AppResponse::BadRequest(format!("Invalid UTF-8 in name parameter: {e}")));
+                return 0;
+            }
+        };
+        let base_dir_str = match unsafe { CStr::from_ptr(base_dir).to_str() } {
+            Ok(s) => s,
+            Err(e) => {
+                set_last_error(AppResponse::BadRequest(format!("Invalid UTF-8 in base_dir parameter: {e}")));
+                return 0;
+            }
+        };
+
+        open_database(name_str, &DbOptions { base_dir: base_dir_str.to_string(), ..DbOptions::default() })
+    })
+}
+
+/// Opens the database with the specified name using JSON options, creating
+/// it when missing, and returns its handle.
+///
+/// Behaves like [`create_db`], with the directory, the memory map size, the
+/// reader slots and the durability flags taken from the options: large
+/// datasets can request a larger map than the default 1 GB, constrained
+/// devices a smaller one, apps reading from many isolates more than the
+/// default 126 readers, and bulk imports can skip the flush on every commit
+/// (`no_sync`, then [`flush_database`]). See
+/// [`local_db_model::DbOptions`]. When the database is already open, its
+/// existing handle is returned and the options are ignored.
+///
+/// # Parameters
+///
+/// * `name` - A null-terminated C string containing the database name
+/// * `options_json` - A null-terminated C string with the options, e.g.
+///   `{"base_dir":"/data/user/0/com.example.app/files","map_size":4294967296,"max_readers":512,"no_sync":true}`;
+///   missing fields take their defaults
+///
+/// # Returns
+///
+/// Returns the handle of the database on success, or `0` on failure, with
+/// the reason available from [`get_last_error`].
+///
+/// # Safety
+///
+/// Both parameters must be valid pointers to null-terminated UTF-8 strings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::create_db_with_config;
+///
+/// let name = CString::new("catalog").unwrap();
+/// let options = CString::new(r#"{"map_size":4294967296}"#).unwrap();
+/// let db = create_db_with_config(name.as_ptr(), options.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn create_db_with_config(name: *const c_char, options_json: *const c_char) -> DbHandle {
+    ffi_boundary("create_db_with_config", || {
+        if name.is_null() || options_json.is_null() {
+            set_last_error(AppResponse::BadRequest("Null pointer passed to create_db_with_config".to_string()));
+            return 0;
+        }
+
+        let name_str = match unsafe { CStr::from_ptr(name).to_str() } {
+            Ok(s) => s,
+            Err(e) => {
+                set_last_error(AppResponse::BadRequest(format!("Invalid UTF-8 in name parameter: {e}")));
+                return 0;
+            }
+        };
+        let options = match unsafe { CStr::from_ptr(options_json).to_str() } {
+            Ok(json) => serde_json::from_str::<DbOptions>(json)
+                .map_err(|e| AppResponse::SerializationError(format!("Error parsing database options: {e}")))
+                .and_then(|options| options.validate().map(|_| options)),
+            Err(e) => Err(AppResponse::BadRequest(format!("Invalid UTF-8 in options parameter: {e}"))),
+        };
+
+        match options {
+            Ok(options) => open_database(name_str, &options),
+            Err(error) => {
+                set_last_error(error);
+                0
+            }
+        }
+    })
+}
+
+/// Opens the database `name` with `options` for the `create_db` functions,
+/// returning the handle of the database if it is already open.
+fn open_database(name: &str, options: &DbOptions) -> DbHandle {
+    let lmdb_dir = Path::new(&options.base_dir).join(format!("{name}.lmdb")).to_string_lossy().into_owned();
 
-    info!("Attempting to create/open database at: {}", lmdb_dir);
+    let opened = registry::open(&lmdb_dir, || {
+        info!("Attempting to create/open database at: {}", lmdb_dir);
 
-    if Path::new(&lmdb_dir).exists() {
-        info!("Database already exists; attempting clean close before reopen");
-        match AppDbState::init(db_path.clone()) {
-            Ok(mut existing) => {
-                if let Err(e) = existing.close_database() {
-                    warn!("Failed to close existing LMDB environment: {e:?}");
-                } else {
-                    info!("Existing LMDB environment closed successfully");
+        // The probe open below consumes the shutdown marker, keep what it found.
+        let mut probed_startup = None;
+        if Path::new(&lmdb_dir).exists() {
+            info!("Database already exists; attempting clean close before reopen");
+            match AppDbState::init_with_options(name.to_string(), options) {
+                Ok(mut existing) => {
+                    probed_startup = Some(existing.startup_report().clone());
+                    if let Err(e) = existing.close_database() {
+                        warn!("Failed to close existing LMDB environment: {e:?}");
+                    } else {
+                        info!("Existing LMDB environment closed successfully");
+                    }
+                }
+                Err(e) => {
+                    warn!("Could not open existing environment for closing: {e:?}");
                 }
             }
+        } else {
+            info!("Creating new database at: {}", lmdb_dir);
+        }
+
+        match AppDbState::init_with_options(name.to_string(), options) {
+            Ok(mut response) => {
+                info!("✅ Database initialized successfully");
+                if let Some(startup) = probed_startup.filter(|startup| startup.recovered) {
+                    response.startup = startup;
+                }
+                Ok(response)
+            },
             Err(e) => {
-                warn!("Could not open existing environment for closing: {e:?}");
-            }
+                warn!("❌ Failed to initialize database: {:?}", e);
+                warn!("LMDB error details: {}", e);
+                warn!("Attempted path: {}", lmdb_dir);
+                warn!("The directory might not be writable");
+                Err(AppResponse::DatabaseError(format!("Cannot open database at {lmdb_dir}: {e}")))
+            },
         }
-    } else {
-        info!("Creating new database at: {}", lmdb_dir);
-    }
+    });
 
-    let state = AppDbState::init(db_path);
-    
-    match state {
-        Ok(response) => {
-            info!("✅ Database initialized successfully");
-            Box::into_raw(Box::new(response))
-        },
-        Err(e) => {
-            warn!("❌ Failed to initialize database: {:?}", e);
-            warn!("LMDB error details: {}", e);
-            warn!("Attempted path: {}", lmdb_dir);
-            warn!("Current working directory might not be writable");
-            std::ptr::null_mut()
-        },
+    match opened {
+        Ok(handle) => handle,
+        Err(error) => {
+            set_last_error(error);
+            0
+        }
     }
 }
 
@@ -162,7 +524,7 @@ pub extern "C" fn create_db(name: *const c_char) -> *mut AppDbState {
 ///
 /// # Parameters
 ///
-/// * `state` - Pointer to the database state instance
+/// * `handle` - Handle of the database returned by [`create_db`]
 /// * `json_ptr` - Null-terminated C string containing JSON data
 ///
 /// # Returns
@@ -173,7 +535,7 @@ pub extern "C" fn create_db(name: *const c_char) -> *mut AppDbState {
 /// # Safety
 ///
 /// This function is unsafe because it dereferences raw pointers.
-/// Both parameters must be valid pointers to their respective types.
+/// The JSON string must be a valid pointer.
 ///
 /// # Examples
 ///
@@ -200,43 +562,43 @@ pub extern "C" fn create_db(name: *const c_char) -> *mut AppDbState {
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn push_data(state: *mut AppDbState, json_ptr: *const c_char) -> *const c_char {
-    let state = match unsafe { state.as_ref() } {
-        Some(s) => s,
-        None => {
-            let error = AppResponse::BadRequest("Null state pointer".to_string());
+pub extern "C" fn push_data(handle: DbHandle, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("push_data", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to push_data"));
             return response_to_c_string(&error);
-        }
-    };
+        };
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
 
-    let json_str = match c_ptr_to_string(json_ptr, "JSON") {
-        Ok(response) => response,
-        Err(err) => return err
-    };
+        let json_str = match c_ptr_to_string(json_ptr, "JSON") {
+            Ok(response) => response,
+            Err(err) => return err
+        };
 
-    let model: LocalDbModel = match serde_json::from_str(&json_str) {
-        Ok(m) => m,
-        Err(e) => {
-            let error = AppResponse::SerializationError(format!("Invalid JSON: {e}"));
-            return response_to_c_string(&error);
-        }
-    };
+        let model: LocalDbModel = match serde_json::from_str(&json_str) {
+            Ok(m) => m,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Invalid JSON: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
     
-    match state.post(model) {
-        Ok(result_model) => {
-            match serde_json::to_string(&result_model) {
-                Ok(json) => {
-                    let success = AppResponse::Ok(json);
-                    response_to_c_string(&success)
-                },
-                Err(e) => {
-                    let error = AppResponse::SerializationError(format!("Failed to serialize result: {e}"));
-                    response_to_c_string(&error)
+        match state.post(model) {
+            Ok(result_model) => {
+                match serde_json::to_string(&result_model) {
+                    Ok(json) => {
+                        let success = AppResponse::Ok(json);
+                        response_to_c_string(&success)
+                    },
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Failed to serialize result: {e}"));
+                        response_to_c_string(&error)
+                    }
                 }
-            }
-        },
-        Err(e) => response_to_c_string(&e)
-    }
+            },
+            Err(e) => response_to_c_string(&e)
+        }
+    })
 }
 
 /// Inserts a new record into the database (HTTP-style naming).
@@ -244,15 +606,17 @@ pub extern "C" fn push_data(state: *mut AppDbState, json_ptr: *const c_char) ->
 /// Alias for [`push_data`]. Provided to align with endpoint semantics.
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn post_data(state: *mut AppDbState, json_ptr: *const c_char) -> *const c_char {
-    push_data(state, json_ptr)
+pub extern "C" fn post_data(handle: DbHandle, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("post_data", || {
+        push_data(handle, json_ptr)
+    })
 }
 
 /// Retrieves a record from the database by its ID.
 ///
 /// # Parameters
 ///
-/// * `state` - Pointer to the database state instance
+/// * `handle` - Handle of the database returned by [`create_db`]
 /// * `id` - Null-terminated C string containing the record ID
 ///
 /// # Returns
@@ -262,7 +626,7 @@ pub extern "C" fn post_data(state: *mut AppDbState, json_ptr: *const c_char) ->
 ///
 /// # Safety
 ///
-/// Both parameters must be valid pointers. The ID string must be valid UTF-8.
+/// The ID must be a valid pointer to a UTF-8 string.
 ///
 /// # Examples
 ///
@@ -278,432 +642,9186 @@ pub extern "C" fn post_data(state: *mut AppDbState, json_ptr: *const c_char) ->
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn get_by_id(state: *mut AppDbState, id: *const c_char) -> *const c_char {
-    if state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to get_by_id".to_string());
-        return response_to_c_string(&error);
-    }
+pub extern "C" fn get_by_id(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("get_by_id", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_id"));
+            return response_to_c_string(&error);
+        };
 
-    if id.is_null() {
-        let error = AppResponse::BadRequest("Null id pointer passed to get_by_id".to_string());
-        return response_to_c_string(&error);
-    }
+        if id.is_null() {
+            let error = AppResponse::BadRequest("Null id pointer passed to get_by_id".to_string());
+            return response_to_c_string(&error);
+        }
 
-    let state = unsafe { &*state };
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
 
-    let id_str = match c_ptr_to_string(id, "id") {
-        Ok(json) => json,
-        Err(error_ptr) => return error_ptr,
-    };
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    match state.get_by_id(&id_str) {
-        Ok(Some(model)) => {
-            match serde_json::to_string(&model) {
-                Ok(json) => {
-                    let success = AppResponse::Ok(json);
-                    response_to_c_string(&success)
-                },
-                Err(e) => {
-                    let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
-                    response_to_c_string(&error)
+        match state.get_by_id(&id_str) {
+            Ok(Some(model)) => {
+                match serde_json::to_string(&model) {
+                    Ok(json) => {
+                        let success = AppResponse::Ok(json);
+                        response_to_c_string(&success)
+                    },
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                        response_to_c_string(&error)
+                    }
                 }
-            }
-        },
-        Ok(None) => {
-            let error = AppResponse::NotFound(format!("No model found with id: {id_str}"));
-            response_to_c_string(&error)
-        },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No model found with id: {id_str}"));
+                response_to_c_string(&error)
+            },
+            Err(e) => response_to_c_string(&e)
         }
-    }
+    })
 }
 
-/// Retrieves all records from the database.
+/// Retrieves a single value of the `data` of a record by JSON Pointer,
+/// without transferring the rest of the record.
+///
+/// See [`AppDbState::get_field`].
 ///
 /// # Parameters
 ///
-/// * `state` - Pointer to the database state instance
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+/// * `json_pointer` - Null-terminated C string with the JSON Pointer into `data`, e.g. `/meta/title`
 ///
 /// # Returns
 ///
-/// Returns a JSON-formatted C string containing an array of all records,
-/// or an error response on failure.
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON of the
+/// value, or a `NotFound` response if no record has the ID or the value
+/// does not exist.
 ///
 /// # Safety
 ///
-/// The state parameter must be a valid pointer to an [`AppDbState`] instance.
+/// The string parameters must be valid pointers.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, get_all};
+/// use offline_first_core::{create_db, get_field};
 ///
 /// let db_name = CString::new("test_db").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// let all_records = get_all(db_state);
+/// let id = CString::new("record_1").unwrap();
+/// let pointer = CString::new("/meta/title").unwrap();
+/// let result = get_field(db_state, id.as_ptr(), pointer.as_ptr());
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn get_all(state: *mut AppDbState) -> *const c_char {
-    if state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to get_all".to_string());
-        return response_to_c_string(&error);
-    }
+pub extern "C" fn get_field(handle: DbHandle, id: *const c_char, json_pointer: *const c_char) -> *const c_char {
+    ffi_boundary("get_field", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_field"));
+            return response_to_c_string(&error);
+        };
 
-    let state = unsafe { &*state };
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let pointer = match c_ptr_to_string(json_pointer, "JSON pointer") {
+            Ok(pointer) => pointer,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    match state.get() {
-        Ok(models) => {
-            match serde_json::to_string(&models) {
-                Ok(json) => {
-                    let success = AppResponse::Ok(json);
-                    response_to_c_string(&success)
-                },
-                Err(e) => {
-                    let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
-                    response_to_c_string(&error)
-                }
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_field(&id_str, &pointer) {
+            Ok(Some(value)) => response_to_c_string(&AppResponse::Ok(value.to_string())),
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No field {pointer} in record {id_str}"));
+                response_to_c_string(&error)
             }
-        },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
+            Err(e) => response_to_c_string(&e),
         }
-    }
+    })
 }
 
-/// Updates an existing record in the database.
-///
-/// The record is identified by the ID field in the provided JSON data.
-/// If no record with that ID exists, the operation returns an error.
+/// Checks whether a record exists without decoding it.
 ///
 /// # Parameters
 ///
-/// * `state` - Pointer to the database state instance
-/// * `json_ptr` - Null-terminated C string containing updated JSON data
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
 ///
 /// # Returns
 ///
-/// Returns a JSON-formatted C string containing the updated record on success,
-/// or an error response if the record doesn't exist or on failure.
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` or `false`,
+/// or an error response on failure.
 ///
 /// # Safety
 ///
-/// Both parameters must be valid pointers.
+/// The string parameter must be a valid pointer.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, update_data};
+/// use offline_first_core::{create_db, record_exists};
 ///
 /// let db_name = CString::new("test_db").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// let json = CString::new(r#"{"id":"1","hash":"new_hash","data":{"updated":true}}"#).unwrap();
-/// let result = update_data(db_state, json.as_ptr());
+/// let id = CString::new("user_123").unwrap();
+/// let result = record_exists(db_state, id.as_ptr());
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn update_data(state: *mut AppDbState, json_ptr: *const c_char) -> *const c_char {
-    if state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to update_data".to_string());
-        return response_to_c_string(&error);
-    }
-
-    if json_ptr.is_null() {
-        let error = AppResponse::BadRequest("Null JSON pointer passed to update_data".to_string());
-        return response_to_c_string(&error);
-    }
-
-    let json_str = match c_ptr_to_string(json_ptr, "JSON") {
-        Ok(json) => json,
-        Err(error_ptr) => return error_ptr,
-    };
-
-    let model: LocalDbModel = match serde_json::from_str(&json_str) {
-        Ok(m) => m,
-        Err(e) => {
-            let error = AppResponse::SerializationError(format!("Error deserializing JSON: {e:?}"));
+pub extern "C" fn record_exists(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("record_exists", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to record_exists"));
             return response_to_c_string(&error);
-        }
-    };
+        };
 
-    let state = unsafe { &*state };
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    match state.put(model) {
-        Ok(Some(updated_model)) => {
-            match serde_json::to_string(&updated_model) {
-                Ok(json) => {
-                    let success = AppResponse::Ok(json);
-                    response_to_c_string(&success)
-                },
-                Err(e) => {
-                    let error = AppResponse::SerializationError(format!("Error serializing updated model: {e:?}"));
-                    response_to_c_string(&error)
-                }
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.record_exists(&id_str) {
+            Ok(exists) => response_to_c_string(&AppResponse::Ok(exists.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
             }
-        },
-        Ok(None) => {
-            let error = AppResponse::NotFound("Model not found for update".to_string());
-            response_to_c_string(&error)
-        },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
         }
-    }
-}
-
-/// Updates an existing record (HTTP-style naming).
-///
-/// Alias for [`update_data`]. Provided to align with endpoint semantics.
-#[no_mangle]
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn put_data(state: *mut AppDbState, json_ptr: *const c_char) -> *const c_char {
-    update_data(state, json_ptr)
+    })
 }
 
-/// Deletes a record from the database by its ID.
+/// Attaches a pre-built read-only asset database next to the user database.
 ///
 /// # Parameters
 ///
-/// * `db_state` - Pointer to the database state instance
-/// * `id` - Null-terminated C string containing the record ID to delete
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the asset database name (without `.lmdb`)
 ///
 /// # Returns
 ///
 /// Returns a JSON-formatted C string indicating success or failure.
-/// Success response includes confirmation of deletion.
 ///
 /// # Safety
 ///
-/// Both parameters must be valid pointers.
+/// The string parameter must be a valid pointer.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, delete_by_id};
+/// use offline_first_core::{create_db, attach_asset_db};
 ///
-/// let db_name = CString::new("test_db").unwrap();
+/// let db_name = CString::new("user_data").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// let id = CString::new("record_to_delete").unwrap();
-/// let result = delete_by_id(db_state, id.as_ptr());
+/// let asset = CString::new("assets/catalog").unwrap();
+/// let result = attach_asset_db(db_state, asset.as_ptr());
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn delete_by_id(db_state: *mut AppDbState, id: *const c_char) -> *const c_char {
-    if db_state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to delete_by_id".to_string());
-        return response_to_c_string(&error);
-    }
-
-    if id.is_null() {
-        let error = AppResponse::BadRequest("Null id pointer passed to delete_by_id".to_string());
-        return response_to_c_string(&error);
-    }
+pub extern "C" fn attach_asset_db(handle: DbHandle, name: *const c_char) -> *const c_char {
+    ffi_boundary("attach_asset_db", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to attach_asset_db"));
+            return response_to_c_string(&error);
+        };
 
-    let id_str = match c_ptr_to_string(id, "id") {
-        Ok(id) => id,
-        Err(error_ptr) => return error_ptr,
-    };
+        let name_str = match c_ptr_to_string(name, "asset name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    let db_state = unsafe { &mut *db_state };
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
 
-    match db_state.delete_by_id(&id_str) {
-        Ok(true) => {
-            let success = AppResponse::Ok("Record deleted successfully".to_string());
-            response_to_c_string(&success)
-        },
-        Ok(false) => {
-            let not_found = AppResponse::NotFound(format!("No record found with id: {id_str}"));
-            response_to_c_string(&not_found)
-        },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
+        match state.attach_asset_db(&name_str) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(format!("Asset database {name_str} attached"))),
+            Err(lmdb::Error::NotFound) => {
+                let error = AppResponse::NotFound(format!("Asset database not found: {name_str}"));
+                response_to_c_string(&error)
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
         }
-    }
+    })
 }
 
-/// Clears all records from the database.
+/// Detaches the asset database, if any.
 ///
-/// This operation removes all records while maintaining the database structure.
-/// The database remains operational after this call.
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string indicating success.
+#[no_mangle]
+pub extern "C" fn detach_asset_db(handle: DbHandle) -> *const c_char {
+    ffi_boundary("detach_asset_db", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to detach_asset_db"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.detach_asset_db();
+        response_to_c_string(&AppResponse::Ok("Asset database detached".to_string()))
+    })
+}
+
+/// Retrieves a record by ID from the user database, falling back to the
+/// attached asset database.
 ///
 /// # Parameters
 ///
-/// * `db_state` - Pointer to the database state instance
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
 ///
 /// # Returns
 ///
-/// Returns a JSON-formatted C string indicating the number of records cleared
-/// or an error response on failure.
+/// Returns a JSON-formatted C string containing the record data or a
+/// `NotFound` response when neither database holds the ID.
 ///
 /// # Safety
 ///
-/// The db_state parameter must be a valid pointer.
+/// The string parameter must be a valid pointer.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, clear_all_records};
+/// use offline_first_core::{create_db, get_with_fallback};
 ///
-/// let db_name = CString::new("test_db").unwrap();
+/// let db_name = CString::new("user_data").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// let result = clear_all_records(db_state);
+/// let id = CString::new("product_42").unwrap();
+/// let result = get_with_fallback(db_state, id.as_ptr());
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn clear_all_records(db_state: *mut AppDbState) -> *const c_char {
-    if db_state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to clear_all_records".to_string());
-        return response_to_c_string(&error);
-    }
+pub extern "C" fn get_with_fallback(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("get_with_fallback", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_with_fallback"));
+            return response_to_c_string(&error);
+        };
 
-    let db_state = unsafe { &*db_state };
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    match db_state.clear_all_records() {
-        Ok(_) => {
-            let success = AppResponse::Ok("All records cleared successfully".to_string());
-            response_to_c_string(&success)
-        },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_with_fallback(&id_str) {
+            Ok(Some(model)) => {
+                match serde_json::to_string(&model) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No model found with id: {id_str}"));
+                response_to_c_string(&error)
+            },
+            Err(e) => response_to_c_string(&e)
         }
-    }
+    })
 }
 
-/// Resets the database to a clean state with a new name.
+/// Retrieves several records by ID in a single call.
 ///
-/// This operation:
-/// 1. Closes the current database connection
-/// 2. Removes the existing database directory
-/// 3. Creates a new database with the specified name
+/// All lookups share one read transaction, so the result is a consistent
+/// snapshot and only one FFI round trip is needed per screen load.
 ///
 /// # Parameters
 ///
-/// * `db_state` - Pointer to the database state instance
-/// * `name_ptr` - Null-terminated C string containing the new database name
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ids_json` - Null-terminated C string containing a JSON array of record IDs
 ///
 /// # Returns
 ///
-/// Returns a JSON-formatted C string indicating success or failure.
+/// Returns a JSON-formatted C string whose `Ok` payload contains the `found`
+/// records and the `missing` IDs, or an error response on failure.
 ///
 /// # Safety
 ///
-/// Both parameters must be valid pointers.
+/// The string parameter must be a valid pointer.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, reset_database};
+/// use offline_first_core::{create_db, get_by_ids};
 ///
 /// let db_name = CString::new("test_db").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// let new_name = CString::new("reset_db").unwrap();
-/// let result = reset_database(db_state, new_name.as_ptr());
+/// let ids = CString::new(r#"["record_1","record_2"]"#).unwrap();
+/// let result = get_by_ids(db_state, ids.as_ptr());
 /// ```
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn reset_database(db_state: *mut AppDbState, name_ptr: *const c_char) -> *const c_char {
-    if db_state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to reset_database".to_string());
+pub extern "C" fn get_by_ids(handle: DbHandle, ids_json: *const c_char) -> *const c_char {
+    ffi_boundary("get_by_ids", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_ids"));
+            return response_to_c_string(&error);
+        };
+
+        let ids = match parse_ids_json(ids_json) {
+            Ok(ids) => ids,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_by_ids(&ids) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e)
+        }
+    })
+}
+
+/// Retrieves all records from the database.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string containing an array of all records,
+/// or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_all};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let all_records = get_all(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_all(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_all", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get() {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => {
+                        let success = AppResponse::Ok(json);
+                        response_to_c_string(&success)
+                    },
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Lists the IDs of all records without loading them.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// record IDs in key order, or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_all_ids};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let ids = get_all_ids(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_all_ids(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_all_ids", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_ids"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_all_ids() {
+            Ok(ids) => {
+                match serde_json::to_string(&ids) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing IDs: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Returns the number of records in the database.
+///
+/// The count is read from LMDB statistics, so no record is deserialized.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the record count,
+/// or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, count_records};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let count = count_records(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn count_records(handle: DbHandle) -> *const c_char {
+    ffi_boundary("count_records", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to count_records"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.count_records() {
+            Ok(count) => response_to_c_string(&AppResponse::Ok(count.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves one page of records in key order.
+///
+/// Intended for lazily loading large databases without serializing every
+/// record into a single response.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit` - Maximum number of records to return
+/// * `offset` - Number of records to skip from the start
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string containing an array with the records of
+/// the requested page, or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_paginated};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// // Second page of 20 records
+/// let page = get_paginated(db_state, 20, 20);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_paginated(handle: DbHandle, limit: u32, offset: u32) -> *const c_char {
+    ffi_boundary("get_paginated", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_paginated"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_paginated(limit as usize, offset as usize) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves the page of records following a continuation token.
+///
+/// Unlike [`get_paginated`], the cost of a call does not grow with the page
+/// position because the cursor seeks straight to the token key.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `last_key` - Null-terminated C string with the `next_token` of the previous
+///   page, or a null pointer (or empty string) to start from the first record
+/// * `limit` - Maximum number of records to return
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload contains the `records`
+/// and the `next_token` (`null` when there are no more records), or an error
+/// response on failure.
+///
+/// # Safety
+///
+/// `last_key` must be null or a
+/// valid C string.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_page_after};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let first_page = get_page_after(db_state, std::ptr::null(), 50);
+/// let token = CString::new("todo:050").unwrap();
+/// let next_page = get_page_after(db_state, token.as_ptr(), 50);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_page_after(handle: DbHandle, last_key: *const c_char, limit: u32) -> *const c_char {
+    ffi_boundary("get_page_after", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_page_after"));
+            return response_to_c_string(&error);
+        };
+
+        let last_key = match optional_c_ptr_to_string(last_key, "last_key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_page_after(last_key.as_deref(), limit as usize) {
+            Ok(page) => {
+                match serde_json::to_string(&page) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing page: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves all records in descending key order.
+///
+/// With time-ordered keys this returns newest-first lists without reversing
+/// the full result set on the caller side.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// records, or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_all_desc};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let newest_first = get_all_desc(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_all_desc(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_all_desc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_desc"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_all_ordered(Direction::Desc) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Descending-order variant of [`get_paginated`]: the offset counts from the last key.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit` - Maximum number of records to return
+/// * `offset` - Number of records to skip from the last key
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// records, or an error response on failure.
+#[no_mangle]
+pub extern "C" fn get_paginated_desc(handle: DbHandle, limit: u32, offset: u32) -> *const c_char {
+    ffi_boundary("get_paginated_desc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_paginated_desc"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_paginated_ordered(limit as usize, offset as usize, Direction::Desc) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Descending-order variant of [`get_page_after`]: each page continues below the token key.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `last_key` - Null-terminated C string with the `next_token` of the previous
+///   page, or null to start from the last record
+/// * `limit` - Maximum number of records to return
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload contains `records` and
+/// `next_token`, or an error response on failure.
+///
+/// # Safety
+///
+/// `last_key` must be null or a
+/// valid C string.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_page_after_desc(handle: DbHandle, last_key: *const c_char, limit: u32) -> *const c_char {
+    ffi_boundary("get_page_after_desc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_page_after_desc"));
+            return response_to_c_string(&error);
+        };
+
+        let last_key = match optional_c_ptr_to_string(last_key, "last_key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_page_after_ordered(last_key.as_deref(), limit as usize, Direction::Desc) {
+            Ok(page) => {
+                match serde_json::to_string(&page) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing page: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves all records whose ID starts with a prefix.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `prefix` - Null-terminated C string with the key prefix (e.g. `todo:`)
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// matching records in key order, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_by_prefix};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let prefix = CString::new("todo:").unwrap();
+/// let todos = get_by_prefix(db_state, prefix.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_by_prefix(handle: DbHandle, prefix: *const c_char) -> *const c_char {
+    ffi_boundary("get_by_prefix", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_prefix"));
+            return response_to_c_string(&error);
+        };
+
+        let prefix_str = match c_ptr_to_string(prefix, "prefix") {
+            Ok(prefix) => prefix,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_by_prefix(&prefix_str) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves the records whose ID lies in a key range.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `start_key` - Null-terminated C string with the inclusive lower bound, or
+///   null to start at the first record
+/// * `end_key` - Null-terminated C string with the exclusive upper bound, or
+///   null to run to the last record
+/// * `limit` - Maximum number of records to return
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// records in key order, or an error response on failure.
+///
+/// # Safety
+///
+/// `start_key` and `end_key` must be null or valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_range};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let start = CString::new("01HKA").unwrap();
+/// let end = CString::new("01HNE").unwrap();
+/// let records = get_range(db_state, start.as_ptr(), end.as_ptr(), 500);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_range(handle: DbHandle, start_key: *const c_char, end_key: *const c_char, limit: u32) -> *const c_char {
+    ffi_boundary("get_range", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_range"));
+            return response_to_c_string(&error);
+        };
+
+        let start_key = match optional_c_ptr_to_string(start_key, "start key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let end_key = match optional_c_ptr_to_string(end_key, "end key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_range(start_key.as_deref(), end_key.as_deref(), limit as usize) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves the records matching a filter, evaluated during cursor iteration.
+///
+/// The filter is a JSON object mapping dotted paths (rooted at the record) to
+/// the value they must hold or to an object of `$gt`, `$gte`, `$lt`, `$lte`,
+/// `$in`, `$ne` and `$contains` operators (see [`PathFilter`]); all entries
+/// must match. Only matching records are deserialized, which is much cheaper
+/// than filtering the result of [`get_all`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `filter_json` - Null-terminated C string with the filter, e.g.
+///   `{"data.amount":{"$gt":100},"data.status":{"$in":["a","b"]}}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// matching records in key order, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, query};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let filter = CString::new(r#"{"data.status":"pending"}"#).unwrap();
+/// let pending = query(db_state, filter.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query(handle: DbHandle, filter_json: *const c_char) -> *const c_char {
+    ffi_boundary("query", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query"));
+            return response_to_c_string(&error);
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.query(&filter) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves all records, including entries for records that cannot be decoded.
+///
+/// Where [`get_all`] silently skips undecodable records, this variant reports
+/// them in a `quarantined` list (id, error and raw size) next to the decoded `records`.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an object with
+/// `records` and `quarantined` arrays, or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_all_with_quarantine};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = get_all_with_quarantine(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_all_with_quarantine(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_all_with_quarantine", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_with_quarantine"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_with_quarantine() {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Lists the records that cannot be decoded.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// quarantined entries (`id`, `error`, `raw_size`), or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, quarantine_list};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let broken = quarantine_list(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn quarantine_list(handle: DbHandle) -> *const c_char {
+    ffi_boundary("quarantine_list", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to quarantine_list"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.quarantine_list() {
+            Ok(entries) => {
+                match serde_json::to_string(&entries) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing quarantine list: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Updates an existing record in the database.
+///
+/// The record is identified by the ID field in the provided JSON data.
+/// If no record with that ID exists, the operation returns an error.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `json_ptr` - Null-terminated C string containing updated JSON data
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string containing the updated record on success,
+/// or an error response if the record doesn't exist or on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, update_data};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let json = CString::new(r#"{"id":"1","hash":"new_hash","data":{"updated":true}}"#).unwrap();
+/// let result = update_data(db_state, json.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn update_data(handle: DbHandle, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("update_data", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to update_data"));
+            return response_to_c_string(&error);
+        };
+
+        if json_ptr.is_null() {
+            let error = AppResponse::BadRequest("Null JSON pointer passed to update_data".to_string());
+            return response_to_c_string(&error);
+        }
+
+        let json_str = match c_ptr_to_string(json_ptr, "JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let model: LocalDbModel = match serde_json::from_str(&json_str) {
+            Ok(m) => m,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error deserializing JSON: {e:?}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.put(model) {
+            Ok(Some(updated_model)) => {
+                match serde_json::to_string(&updated_model) {
+                    Ok(json) => {
+                        let success = AppResponse::Ok(json);
+                        response_to_c_string(&success)
+                    },
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing updated model: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound("Model not found for update".to_string());
+                response_to_c_string(&error)
+            },
+            Err(e) => response_to_c_string(&e)
+        }
+    })
+}
+
+/// Updates an existing record (HTTP-style naming).
+///
+/// Alias for [`update_data`]. Provided to align with endpoint semantics.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn put_data(handle: DbHandle, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("put_data", || {
+        update_data(handle, json_ptr)
+    })
+}
+
+/// Replaces a record only if its stored hash is still `expected_hash`, for
+/// optimistic concurrency across isolates and sync.
+///
+/// See [`AppDbState::update_if_hash`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `json_ptr` - Null-terminated C string containing the new record JSON
+/// * `expected_hash` - Null-terminated C string with the hash the caller read
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the record
+/// written, a `NotFound` response if no record has the ID, or a `Conflict`
+/// response carrying the stored hash, e.g.
+/// `{"Conflict":{"message":"Record 1 has hash h2, expected h1","current_hash":"h2"}}`.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, update_if_hash};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let json = CString::new(r#"{"id":"1","hash":"h2","data":{"name":"updated"}}"#).unwrap();
+/// let expected = CString::new("h1").unwrap();
+/// let result = update_if_hash(db_state, json.as_ptr(), expected.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn update_if_hash(handle: DbHandle, json_ptr: *const c_char, expected_hash: *const c_char) -> *const c_char {
+    ffi_boundary("update_if_hash", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to update_if_hash"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(json_ptr, "JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let model: LocalDbModel = match serde_json::from_str(&json_str) {
+            Ok(model) => model,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Invalid JSON: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+        let expected_hash = match c_ptr_to_string(expected_hash, "expected hash") {
+            Ok(hash) => hash,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.update_if_hash(model, &expected_hash) {
+            Ok(model) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing updated model: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Replaces the value at a dotted path of a record only if it still holds
+/// the expected value, in one write transaction.
+///
+/// See [`AppDbState::compare_and_swap`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the record ID
+/// * `field_path` - Null-terminated C string with the path, e.g. `data.status`
+/// * `expected_json` - C string with the expected JSON value, `null` for a missing field
+/// * `new_json` - C string with the JSON value to write
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::CompareAndSwapResult`], e.g.
+/// `{"swapped":false,"current":"uploading"}`, or a `NotFound` response if no
+/// record has the ID.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{compare_and_swap, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("uploads").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("upload_1").unwrap();
+/// let path = CString::new("data.status").unwrap();
+/// let expected = CString::new(r#""queued""#).unwrap();
+/// let new = CString::new(r#""uploading""#).unwrap();
+/// let result = compare_and_swap(db, id.as_ptr(), path.as_ptr(), expected.as_ptr(), new.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn compare_and_swap(handle: DbHandle, id: *const c_char, field_path: *const c_char, expected_json: *const c_char, new_json: *const c_char) -> *const c_char {
+    ffi_boundary("compare_and_swap", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to compare_and_swap"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "ID") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let field_path = match c_ptr_to_string(field_path, "field path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+        let mut values = Vec::with_capacity(2);
+        for (ptr, label) in [(expected_json, "expected JSON"), (new_json, "new JSON")] {
+            let json_str = match c_ptr_to_string(ptr, label) {
+                Ok(json) => json,
+                Err(error_ptr) => return error_ptr,
+            };
+            match serde_json::from_str::<serde_json::Value>(&json_str) {
+                Ok(value) => values.push(value),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error parsing {label}: {e}"));
+                    return response_to_c_string(&error);
+                }
+            }
+        }
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.compare_and_swap(&id, &field_path, &values[0], &values[1]) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing compare-and-swap result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Applies a JSON Patch (RFC 6902) to the `data` of a record, atomically.
+///
+/// See [`AppDbState::apply_json_patch`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the record ID
+/// * `patch_ops_json` - C string with the array of [`local_db_model::PatchOp`]s
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the patched
+/// record, a `NotFound` response if no record has the ID, or a `Conflict`
+/// response carrying the stored hash if a `test` operation fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{apply_json_patch, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("mail").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("mail_1").unwrap();
+/// let ops = CString::new(r#"[{"op":"test","path":"/status","value":"draft"},{"op":"replace","path":"/status","value":"sent"}]"#).unwrap();
+/// let result = apply_json_patch(db, id.as_ptr(), ops.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn apply_json_patch(handle: DbHandle, id: *const c_char, patch_ops_json: *const c_char) -> *const c_char {
+    ffi_boundary("apply_json_patch", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to apply_json_patch"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "ID") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let json_str = match c_ptr_to_string(patch_ops_json, "patch JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let ops: Vec<PatchOp> = match serde_json::from_str(&json_str) {
+            Ok(ops) => ops,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing JSON patch: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.apply_json_patch(&id, &ops) {
+            Ok(model) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing patched model: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Inserts a record if its ID is new and replaces it otherwise, in one
+/// transaction.
+///
+/// Unlike [`push_data`] followed by [`update_data`], there is no window
+/// between checking and writing. See [`AppDbState::upsert`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `json_ptr` - Null-terminated C string containing the record JSON
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::UpsertResult`], e.g.
+/// `{"inserted":true,"record":{"id":"1","hash":"abc123","data":{"name":"test"}}}`.
+///
+/// # Safety
+///
+/// The JSON string must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, upsert_data};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let json = CString::new(r#"{"id":"1","hash":"abc123","data":{"name":"test"}}"#).unwrap();
+/// let result = upsert_data(db_state, json.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn upsert_data(handle: DbHandle, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("upsert_data", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to upsert_data"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(json_ptr, "JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let model: LocalDbModel = match serde_json::from_str(&json_str) {
+            Ok(model) => model,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Invalid JSON: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.upsert(model) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing upsert result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes a record from the database by its ID.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID to delete
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string indicating success or failure.
+/// Success response includes confirmation of deletion.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, delete_by_id};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("record_to_delete").unwrap();
+/// let result = delete_by_id(db_state, id.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn delete_by_id(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("delete_by_id", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to delete_by_id"));
+            return response_to_c_string(&error);
+        };
+
+        if id.is_null() {
+            let error = AppResponse::BadRequest("Null id pointer passed to delete_by_id".to_string());
+            return response_to_c_string(&error);
+        }
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let db_state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match db_state.delete_by_id(&id_str) {
+            Ok(true) => {
+                let success = AppResponse::Ok("Record deleted successfully".to_string());
+                response_to_c_string(&success)
+            },
+            Ok(false) => {
+                let not_found = AppResponse::NotFound(format!("No record found with id: {id_str}"));
+                response_to_c_string(&not_found)
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes several records by ID in a single write transaction.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ids_json` - Null-terminated C string containing a JSON array of record IDs
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload lists the `deleted`
+/// and `not_found` IDs, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, delete_many};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let ids = CString::new(r#"["record_1","record_2"]"#).unwrap();
+/// let result = delete_many(db_state, ids.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn delete_many(handle: DbHandle, ids_json: *const c_char) -> *const c_char {
+    ffi_boundary("delete_many", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to delete_many"));
+            return response_to_c_string(&error);
+        };
+
+        let ids = match parse_ids_json(ids_json) {
+            Ok(ids) => ids,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let db_state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match db_state.delete_many(&ids) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing delete result: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Soft-deletes a record: it is hidden from every read until it is restored
+/// with [`restore`] or purged with [`purge_deleted`].
+///
+/// See [`AppDbState::soft_delete`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"`, or a
+/// `NotFound` response if no record has the ID.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, restore, soft_delete};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("record_1").unwrap();
+/// let result = soft_delete(db_state, id.as_ptr());
+/// let result = restore(db_state, id.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn soft_delete(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("soft_delete", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to soft_delete"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.soft_delete(&id_str) {
+            Ok(true) => response_to_c_string(&AppResponse::Ok(true.to_string())),
+            Ok(false) => {
+                let error = AppResponse::NotFound(format!("No record found with id: {id_str}"));
+                response_to_c_string(&error)
+            }
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Restores a soft-deleted record.
+///
+/// See [`AppDbState::restore`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the restored
+/// record, a `NotFound` response if no record with the ID is soft-deleted,
+/// or a `Conflict` response if a record with the ID was written since.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn restore(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("restore", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to restore"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.restore(&id_str) {
+            Ok(Some(model)) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing restored model: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No soft-deleted record with id: {id_str}"));
+                response_to_c_string(&error)
+            }
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes for good the records soft-deleted at least `older_than_ms`
+/// milliseconds ago, `0` emptying the trash.
+///
+/// See [`AppDbState::purge_deleted`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `older_than_ms` - Minimum age of the soft deletions purged, in milliseconds
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records purged.
+#[no_mangle]
+pub extern "C" fn purge_deleted(handle: DbHandle, older_than_ms: u64) -> *const c_char {
+    ffi_boundary("purge_deleted", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to purge_deleted"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.purge_deleted(older_than_ms) {
+            Ok(purged) => response_to_c_string(&AppResponse::Ok(purged.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Flags records for re-download from the server.
+///
+/// Use this for records reported by [`quarantine_list`] (or otherwise found to be
+/// corrupt) instead of deleting them; the sync layer reads the queue with
+/// [`get_resync_queue`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ids_json` - Null-terminated C string containing a JSON array of record IDs
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of newly
+/// queued IDs, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, mark_for_resync};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let ids = CString::new(r#"["record_1","record_2"]"#).unwrap();
+/// let result = mark_for_resync(db_state, ids.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn mark_for_resync(handle: DbHandle, ids_json: *const c_char) -> *const c_char {
+    ffi_boundary("mark_for_resync", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to mark_for_resync"));
+            return response_to_c_string(&error);
+        };
+
+        let ids = match parse_ids_json(ids_json) {
+            Ok(ids) => ids,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.mark_for_resync(&ids) {
+            Ok(marked) => response_to_c_string(&AppResponse::Ok(marked.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Lists the records flagged for re-download.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of queue
+/// entries (`id`, `marked_at`), or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_resync_queue};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let queue = get_resync_queue(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_resync_queue(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_resync_queue", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_resync_queue"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_resync_queue() {
+            Ok(entries) => {
+                match serde_json::to_string(&entries) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing resync queue: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Removes re-downloaded records from the resync queue.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ids_json` - Null-terminated C string containing a JSON array of record IDs
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// entries removed, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, clear_resync};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let ids = CString::new(r#"["record_1"]"#).unwrap();
+/// let result = clear_resync(db_state, ids.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn clear_resync(handle: DbHandle, ids_json: *const c_char) -> *const c_char {
+    ffi_boundary("clear_resync", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to clear_resync"));
+            return response_to_c_string(&error);
+        };
+
+        let ids = match parse_ids_json(ids_json) {
+            Ok(ids) => ids,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.clear_resync(&ids) {
+            Ok(cleared) => response_to_c_string(&AppResponse::Ok(cleared.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Clears all records from the database.
+///
+/// This operation removes all records while maintaining the database structure.
+/// The database remains operational after this call.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string indicating the number of records cleared
+/// or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, clear_all_records};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = clear_all_records(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn clear_all_records(handle: DbHandle) -> *const c_char {
+    ffi_boundary("clear_all_records", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to clear_all_records"));
+            return response_to_c_string(&error);
+        };
+
+        let db_state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match db_state.clear_all_records() {
+            Ok(_) => {
+                let success = AppResponse::Ok("All records cleared successfully".to_string());
+                response_to_c_string(&success)
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Resets the database to a clean state with a new name.
+///
+/// This operation:
+/// 1. Closes the current database connection
+/// 2. Removes the existing database directory
+/// 3. Creates a new database with the specified name
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name_ptr` - Null-terminated C string containing the new database name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string indicating success or failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, reset_database};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let new_name = CString::new("reset_db").unwrap();
+/// let result = reset_database(db_state, new_name.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn reset_database(handle: DbHandle, name_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("reset_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to reset_database"));
+            return response_to_c_string(&error);
+        };
+
+        if name_ptr.is_null() {
+            let error = AppResponse::BadRequest("Null name pointer passed to reset_database".to_string());
+            return response_to_c_string(&error);
+        }
+
+        let name = match c_ptr_to_string(name_ptr, "name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut db_state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match db_state.reset_database(&name) {
+            Ok(_) => {
+                registry::rename(handle, &db_state.path);
+                let success = AppResponse::Ok(format!("Database '{name}' was reset successfully"));
+                response_to_c_string(&success)
+            },
+            Err(e) => {
+                let error = AppResponse::DatabaseError(format!("Error resetting database: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Explicitly closes the database connection.
+///
+/// This function provides explicit connection management, which is particularly
+/// useful for Flutter hot restart scenarios where resources need to be cleaned up
+/// before reconnecting.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string indicating success or failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, close_database};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// // Before hot restart or application shutdown
+/// let result = close_database(db);
+/// ```
+///
+/// # Notes
+///
+/// The handle is invalid afterwards: functions called with it return a
+/// `BadRequest`, and [`create_db`] must be called again to reopen the
+/// database. Calls still running on it finish before the environment closes.
+#[no_mangle]
+pub extern "C" fn close_database(handle: DbHandle) -> *const c_char {
+    ffi_boundary("close_database", || {
+        let Some(db) = registry::remove(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to close_database"));
+            return response_to_c_string(&error);
+        };
+
+        let mut db_state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match db_state.close_database() {
+            Ok(_) => {
+                let success = AppResponse::Ok("Database connection closed successfully".to_string());
+                response_to_c_string(&success)
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Copies matching records from one database into another.
+///
+/// Both databases are opened by name for the duration of the call; the source
+/// must exist and the destination is created when missing. Intended for
+/// splitting one database into per-feature databases.
+///
+/// # Parameters
+///
+/// * `src_name` - Null-terminated C string with the source database name
+/// * `dst_name` - Null-terminated C string with the destination database name
+/// * `filter_json` - Null-terminated C string with a JSON object mapping record
+///   paths to expected values (e.g. `{"data.feature":"todos"}`), or null to copy
+///   every record
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records copied, or an error response on failure.
+///
+/// # Safety
+///
+/// `src_name` and `dst_name` must be valid pointers; `filter_json` may be null.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::copy_records;
+///
+/// let src = CString::new("app").unwrap();
+/// let dst = CString::new("todos").unwrap();
+/// let filter = CString::new(r#"{"data.feature":"todos"}"#).unwrap();
+/// let result = copy_records(src.as_ptr(), dst.as_ptr(), filter.as_ptr());
+/// ```
+#[no_mangle]
+pub extern "C" fn copy_records(src_name: *const c_char, dst_name: *const c_char, filter_json: *const c_char) -> *const c_char {
+    ffi_boundary("copy_records", || {
+        let src_name = match c_ptr_to_string(src_name, "source name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let dst_name = match c_ptr_to_string(dst_name, "destination name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let filter = if filter_json.is_null() {
+            PathFilter::default()
+        } else {
+            match parse_filter_json(filter_json) {
+                Ok(filter) => filter,
+                Err(error_ptr) => return error_ptr,
+            }
+        };
+
+        match AppDbState::copy_records(&src_name, &dst_name, &filter) {
+            Ok(copied) => response_to_c_string(&AppResponse::Ok(copied.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Redistributes the records of a database into hash shards.
+///
+/// Records are assigned to `{src_name}_shard_{i}` by a stable hash of the value
+/// at `field_path`. The source database is left untouched.
+///
+/// # Parameters
+///
+/// * `src_name` - Null-terminated C string with the source database name
+/// * `field_path` - Null-terminated C string with the dotted path to shard by (e.g. `data.category`)
+/// * `shard_count` - Number of shard databases to create
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload lists the shard
+/// database names and their record counts, or an error response on failure.
+///
+/// # Safety
+///
+/// `src_name` and `field_path` must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::shard_by;
+///
+/// let src = CString::new("catalog").unwrap();
+/// let path = CString::new("data.category").unwrap();
+/// let result = shard_by(src.as_ptr(), path.as_ptr(), 4);
+/// ```
+#[no_mangle]
+pub extern "C" fn shard_by(src_name: *const c_char, field_path: *const c_char, shard_count: u32) -> *const c_char {
+    ffi_boundary("shard_by", || {
+        let src_name = match c_ptr_to_string(src_name, "source name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let field_path = match c_ptr_to_string(field_path, "field path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        match AppDbState::shard_by(&src_name, &field_path, shard_count as usize) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing shard result: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Generates a compacted database from a JSON or NDJSON dataset, ready to be
+/// shipped as an app asset.
+///
+/// # Parameters
+///
+/// * `input_path` - Null-terminated C string with the dataset file path
+/// * `output_name` - Null-terminated C string with the output database name (without `.lmdb`)
+/// * `options_json` - Null-terminated C string with build options
+///   (`{"compress": bool, "schema_version": number}`), or null for the defaults
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload holds the output path
+/// and record count, or an error response on failure.
+///
+/// # Safety
+///
+/// `input_path` and `output_name` must be valid pointers; `options_json` may be null.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::build_prebuilt_db;
+///
+/// let input = CString::new("data/catalog.ndjson").unwrap();
+/// let output = CString::new("assets/catalog").unwrap();
+/// let result = build_prebuilt_db(input.as_ptr(), output.as_ptr(), std::ptr::null());
+/// ```
+#[no_mangle]
+pub extern "C" fn build_prebuilt_db(input_path: *const c_char, output_name: *const c_char, options_json: *const c_char) -> *const c_char {
+    ffi_boundary("build_prebuilt_db", || {
+        let input_path = match c_ptr_to_string(input_path, "input path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let output_name = match c_ptr_to_string(output_name, "output name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let options = if options_json.is_null() {
+            BuildOptions::default()
+        } else {
+            let json_str = match c_ptr_to_string(options_json, "options JSON") {
+                Ok(json) => json,
+                Err(error_ptr) => return error_ptr,
+            };
+            match serde_json::from_str(&json_str) {
+                Ok(options) => options,
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Invalid build options: {e}"));
+                    return response_to_c_string(&error);
+                }
+            }
+        };
+
+        match AppDbState::build_prebuilt_db(&input_path, &output_name, &options) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing build result: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Applies a signed differential patch onto a shipped dataset database.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `patch_path` - Null-terminated C string with the patch file path; the
+///   signature is read from `{patch_path}.sig`
+/// * `public_key_hex` - Null-terminated C string with the hex-encoded ed25519 public key
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload holds the new dataset
+/// version and the number of upserted and deleted records, or an error
+/// response on failure.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, apply_dataset_patch};
+///
+/// let db_name = CString::new("catalog").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let patch = CString::new("updates/catalog-v4.json").unwrap();
+/// let key = CString::new("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a").unwrap();
+/// let result = apply_dataset_patch(db_state, patch.as_ptr(), key.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn apply_dataset_patch(handle: DbHandle, patch_path: *const c_char, public_key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("apply_dataset_patch", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to apply_dataset_patch"));
+            return response_to_c_string(&error);
+        };
+
+        let patch_path = match c_ptr_to_string(patch_path, "patch path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let public_key_hex = match c_ptr_to_string(public_key_hex, "public key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.apply_dataset_patch(&patch_path, &public_key_hex) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing patch result: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Imports the records of a JSON or NDJSON dataset file, optionally verifying
+/// its detached ed25519 signature first.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `path` - Null-terminated C string with the dataset file path
+/// * `public_key_hex` - Null-terminated C string with the hex-encoded ed25519
+///   public key, or null to import without verification
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records imported, or an error response on failure.
+///
+/// # Safety
+///
+/// `path` must be a valid pointer; `public_key_hex` may be null.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, import_from_file};
+///
+/// let db_name = CString::new("user_data").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let path = CString::new("downloads/recipes.ndjson").unwrap();
+/// let key = CString::new("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a").unwrap();
+/// let result = import_from_file(db_state, path.as_ptr(), key.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn import_from_file(handle: DbHandle, path: *const c_char, public_key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("import_from_file", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to import_from_file"));
+            return response_to_c_string(&error);
+        };
+
+        let path = match c_ptr_to_string(path, "path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let public_key_hex = match optional_c_ptr_to_string(public_key_hex, "public key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.import_from_file(&path, public_key_hex.as_deref()) {
+            Ok(imported) => response_to_c_string(&AppResponse::Ok(imported.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the dataset version of the database, `0` when it was never set.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the version number,
+/// or an error response on failure.
+#[no_mangle]
+pub extern "C" fn get_dataset_version(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_dataset_version", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_dataset_version"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.dataset_version() {
+            Ok(version) => response_to_c_string(&AppResponse::Ok(version.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Analyzes how the database uses its storage.
+///
+/// Reports the value size distribution, a compressibility estimate and the
+/// largest records and key prefixes, see [`AppDbState::analyze_storage`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `top_n` - Number of largest records and prefixes to report
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the serialized
+/// [`local_db_model::StorageReport`], or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, analyze_storage};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let report = analyze_storage(db_state, 10);
+/// ```
+#[no_mangle]
+pub extern "C" fn analyze_storage(handle: DbHandle, top_n: u32) -> *const c_char {
+    ffi_boundary("analyze_storage", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to analyze_storage"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.analyze_storage(top_n as usize) {
+            Ok(report) => {
+                match serde_json::to_string(&report) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing storage report: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Reports the memory used by the library for a database.
+///
+/// Includes the resident part of the memory map and the strings returned by
+/// this library that have not been released with [`free_c_string`] yet, see
+/// [`AppDbState::memory_stats`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the serialized
+/// [`local_db_model::MemoryStats`], or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_memory_stats};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let stats = get_memory_stats(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_memory_stats(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_memory_stats", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_memory_stats"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.memory_stats() {
+            Ok(stats) => {
+                match serde_json::to_string(&stats) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing memory stats: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Releases a string returned by any function of this library.
+///
+/// Releasing strings through this function rather than the C `free` uses the
+/// allocator that created them and keeps the outstanding buffer counters of
+/// [`get_memory_stats`] accurate. Passing a null pointer does nothing.
+///
+/// # Parameters
+///
+/// * `ptr` - A string returned by this library
+///
+/// # Safety
+///
+/// `ptr` must be null or a string returned by this library that has not been
+/// released yet; it must not be used afterwards.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_all, free_c_string};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = get_all(db_state);
+/// free_c_string(result);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn free_c_string(ptr: *const c_char) {
+    ffi_boundary("free_c_string", || {
+        if ptr.is_null() {
+            return;
+        }
+
+        let c_str = unsafe { CString::from_raw(ptr as *mut c_char) };
+        stats::release_returned_buffer(c_str.as_bytes_with_nul().len());
+    })
+}
+
+/// Length-prefixed bytes returned by the `*_buffer` functions.
+///
+/// Unlike a C string, the payload is not terminated: read exactly `len`
+/// bytes from `ptr` (e.g. `ptr.asTypedList(len)` in Dart), so it may hold any
+/// byte and needs no scan for a terminator. The payload is the same response
+/// JSON the string functions return, UTF-8 encoded. Release it with
+/// [`free_buffer`]. A failure to build the response is reported as a null
+/// `ptr` with `len` 0, with the reason available from [`get_last_error`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteBuffer {
+    /// First byte of the payload.
+    pub ptr: *mut u8,
+    /// Number of bytes at `ptr`.
+    pub len: usize,
+}
+
+impl ByteBuffer {
+    fn null() -> Self {
+        ByteBuffer { ptr: std::ptr::null_mut(), len: 0 }
+    }
+}
+
+/// Releases a [`ByteBuffer`] returned by this library. A buffer with a null
+/// `ptr` is ignored.
+///
+/// # Parameters
+///
+/// * `buffer` - A buffer returned by this library
+///
+/// # Safety
+///
+/// `buffer` must be null or returned by this library and not released yet;
+/// its bytes must not be used afterwards.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, free_buffer, get_all_buffer};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let buffer = get_all_buffer(db_state);
+/// free_buffer(buffer);
+/// ```
+#[no_mangle]
+pub extern "C" fn free_buffer(buffer: ByteBuffer) {
+    ffi_boundary("free_buffer", || {
+        if buffer.ptr.is_null() {
+            return;
+        }
+
+        let bytes = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(buffer.ptr, buffer.len)) };
+        stats::release_returned_buffer(bytes.len());
+    })
+}
+
+/// Creates a secondary index over one or more JSON paths.
+///
+/// Existing records are indexed immediately and every later write keeps the
+/// index up to date, see [`AppDbState::create_index`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the index name
+/// * `paths_json` - Null-terminated C string with a JSON array of dotted paths,
+///   e.g. `["data.account_id","data.created_at"]`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records indexed, or an error response on failure.
+///
+/// # Safety
+///
+/// String parameters must be valid null-terminated C strings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, create_index};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("by_account_date").unwrap();
+/// let paths = CString::new(r#"["data.account_id","data.created_at"]"#).unwrap();
+/// let result = create_index(db_state, name.as_ptr(), paths.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn create_index(handle: DbHandle, name: *const c_char, paths_json: *const c_char) -> *const c_char {
+    ffi_boundary("create_index", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to create_index"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "index name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let paths = match parse_paths_json(paths_json) {
+            Ok(paths) => paths,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.create_index(&name, &paths) {
+            Ok(indexed) => response_to_c_string(&AppResponse::Ok(indexed.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a secondary index and its entries.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the index name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if the index
+/// existed and `false` otherwise, or an error response on failure.
+///
+/// # Safety
+///
+/// The name parameter must be a valid null-terminated C string.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn drop_index(handle: DbHandle, name: *const c_char) -> *const c_char {
+    ffi_boundary("drop_index", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to drop_index"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "index name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.drop_index(&name) {
+            Ok(existed) => response_to_c_string(&AppResponse::Ok(existed.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Lists the secondary indexes of a database.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// [`local_db_model::IndexDefinition`], or an error response on failure.
+#[no_mangle]
+pub extern "C" fn list_indexes(handle: DbHandle) -> *const c_char {
+    ffi_boundary("list_indexes", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to list_indexes"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.list_indexes() {
+            Ok(indexes) => {
+                match serde_json::to_string(&indexes) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing indexes: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves records through a secondary index.
+///
+/// The values fix the leading paths of the index; matching records come back
+/// sorted by the remaining paths, see [`AppDbState::query_index`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the index name
+/// * `values_json` - Null-terminated C string with a JSON array of values for
+///   the leading paths (may be empty), e.g. `["acc_1"]`
+/// * `limit` - Maximum number of records to return
+/// * `descending` - Whether to return the records in descending order
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// records, or an error response on failure.
+///
+/// # Safety
+///
+/// String parameters must be valid null-terminated C strings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, query_index};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("by_account_date").unwrap();
+/// let values = CString::new(r#"["acc_1"]"#).unwrap();
+/// let latest = query_index(db_state, name.as_ptr(), values.as_ptr(), 20, true);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query_index(handle: DbHandle, name: *const c_char, values_json: *const c_char, limit: u32, descending: bool) -> *const c_char {
+    ffi_boundary("query_index", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query_index"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "index name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let json_str = match c_ptr_to_string(values_json, "values JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let values: Vec<serde_json::Value> = match serde_json::from_str(&json_str) {
+            Ok(values) => values,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Expected a JSON array of values: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let direction = if descending { Direction::Desc } else { Direction::Asc };
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.query_index(&name, &values, direction, limit as usize) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Sets how numbers that JSON consumers cannot represent exactly are handled
+/// by later writes.
+///
+/// Integers outside `±(2^53 - 1)` are rounded by JavaScript and Dart on the
+/// web; see [`local_db_model::NumberPolicy`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `policy` - Null-terminated C string: `preserve` (default), `reject`,
+///   `stringify` or `lossy`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the policy now in
+/// effect, or an error response for an unknown policy.
+///
+/// # Safety
+///
+/// The policy parameter must be a valid null-terminated C string.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_number_policy};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("ledger").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let policy = CString::new("reject").unwrap();
+/// let result = set_number_policy(db_state, policy.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_number_policy(handle: DbHandle, policy: *const c_char) -> *const c_char {
+    ffi_boundary("set_number_policy", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_number_policy"));
+            return response_to_c_string(&error);
+        };
+
+        let policy_name = match c_ptr_to_string(policy, "number policy") {
+            Ok(policy) => policy,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let policy: NumberPolicy = match serde_json::from_value(serde_json::Value::String(policy_name.clone())) {
+            Ok(policy) => policy,
+            Err(_) => {
+                let error = AppResponse::BadRequest(format!(
+                    "Unknown number policy {policy_name}, expected preserve, reject, stringify or lossy"
+                ));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.set_number_policy(policy);
+        response_to_c_string(&AppResponse::Ok(policy_name))
+    })
+}
+
+/// Sets the encoded record size above which large fields are moved to the
+/// chunk store by later writes.
+///
+/// Reads reassemble overflowed fields transparently; see
+/// [`AppDbState::set_overflow_threshold`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `threshold` - Size in bytes, or 0 to disable overflow (the default)
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the threshold now
+/// in effect.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_overflow_threshold};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = set_overflow_threshold(db_state, 256 * 1024);
+/// ```
+#[no_mangle]
+pub extern "C" fn set_overflow_threshold(handle: DbHandle, threshold: u64) -> *const c_char {
+    ffi_boundary("set_overflow_threshold", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_overflow_threshold"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.set_overflow_threshold((threshold > 0).then_some(threshold as usize));
+        response_to_c_string(&AppResponse::Ok(threshold.to_string()))
+    })
+}
+
+/// Bounds the database as a cache; later writes evict the least recently
+/// written records while it is above the limit.
+///
+/// See [`AppDbState::set_cache_limit`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit_json` - JSON of a [`local_db_model::CacheLimit`], e.g.
+///   `{"max_records":5000,"max_bytes":52428800}`, or `null` to remove the limit
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records evicted to fit the new limit.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_cache_limit};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("image_cache").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let limit = CString::new(r#"{"max_records":5000}"#).unwrap();
+/// let result = set_cache_limit(db_state, limit.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_cache_limit(handle: DbHandle, limit_json: *const c_char) -> *const c_char {
+    ffi_boundary("set_cache_limit", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_cache_limit"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(limit_json, "cache limit JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let limit: Option<CacheLimit> = match serde_json::from_str(&json_str) {
+            Ok(limit) => limit,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing cache limit: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_cache_limit(limit) {
+            Ok(evicted) => response_to_c_string(&AppResponse::Ok(evicted.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Reports the integrity fast-check run when the database was opened.
+///
+/// `recovered` is `true` when the previous session did not close the database
+/// (crash, kill, or a state that was never passed to [`close_database`]); apps
+/// can log it or trigger a resync. See [`local_db_model::StartupReport`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON report.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_startup_report};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let report = get_startup_report(db_state);
+/// // {"Ok":"{\"recovered\":false,\"stale_readers_cleared\":0,\"last_page_ok\":true}"}
+/// ```
+#[no_mangle]
+pub extern "C" fn get_startup_report(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_startup_report", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_startup_report"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match serde_json::to_string(state.startup_report()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing startup report: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Sets when [`run_maintenance`] compacts the database.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `policy_json` - Null-terminated C string with a JSON
+///   [`local_db_model::CompactionPolicy`]; missing fields keep their defaults
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON policy now
+/// in effect.
+///
+/// # Safety
+///
+/// The policy parameter must be a valid null-terminated C string.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_compaction_policy};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let policy = CString::new(r#"{"min_free_ratio": 0.4, "require_charging": false}"#).unwrap();
+/// let result = set_compaction_policy(db_state, policy.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_compaction_policy(handle: DbHandle, policy_json: *const c_char) -> *const c_char {
+    ffi_boundary("set_compaction_policy", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_compaction_policy"));
+            return response_to_c_string(&error);
+        };
+
+        let policy_json = match c_ptr_to_string(policy_json, "compaction policy") {
+            Ok(policy) => policy,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let policy: CompactionPolicy = match serde_json::from_str(&policy_json) {
+            Ok(policy) => policy,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Invalid compaction policy: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.set_compaction_policy(policy);
+
+        match serde_json::to_string(state.compaction_policy()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing compaction policy: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Measures fragmentation and compacts the database when the compaction
+/// policy allows it.
+///
+/// Apps call this periodically, e.g. from a background task, and report the
+/// device state; see [`AppDbState::run_maintenance`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `idle` - Whether the app is idle
+/// * `charging` - Whether the device is charging
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON
+/// [`local_db_model::MaintenanceReport`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, run_maintenance};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let report = run_maintenance(db_state, true, true);
+/// ```
+#[no_mangle]
+pub extern "C" fn run_maintenance(handle: DbHandle, idle: bool, charging: bool) -> *const c_char {
+    ffi_boundary("run_maintenance", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to run_maintenance"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.run_maintenance(idle, charging) {
+            Ok(report) => {
+                match serde_json::to_string(&report) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing maintenance report: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Compacts the database immediately, regardless of the compaction policy.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON
+/// [`local_db_model::CompactionResult`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{compact, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = compact(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn compact(handle: DbHandle) -> *const c_char {
+    ffi_boundary("compact", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to compact"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.compact() {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing compaction result: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves the records matching a filter, sorted by the value at a path.
+///
+/// Sorting happens in Rust so large result sets do not have to be sorted on
+/// the Dart side; see [`query::SortSpec`] for how values of different types
+/// are ordered.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `filter_json` - Null-terminated C string with the filter, as for [`query`]
+/// * `sort_json` - Null-terminated C string with the sort, e.g.
+///   `{"by":"data.created_at","order":"desc"}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// matching records in sort order, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, query_sorted};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let filter = CString::new(r#"{"data.status":"pending"}"#).unwrap();
+/// let sort = CString::new(r#"{"by":"data.created_at","order":"desc"}"#).unwrap();
+/// let newest_first = query_sorted(db_state, filter.as_ptr(), sort.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query_sorted(handle: DbHandle, filter_json: *const c_char, sort_json: *const c_char) -> *const c_char {
+    ffi_boundary("query_sorted", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query_sorted"));
+            return response_to_c_string(&error);
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+        let sort = match parse_sort_json(sort_json) {
+            Ok(sort) => sort,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        sorted_records_response(&state, &filter, &sort)
+    })
+}
+
+/// Retrieves all records sorted by the value at a path.
+///
+/// Same as [`query_sorted`] with an empty filter.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `sort_json` - Null-terminated C string with the sort, e.g.
+///   `{"by":"data.created_at","order":"desc"}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of all
+/// records in sort order, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_all_sorted};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let sort = CString::new(r#"{"by":"data.name"}"#).unwrap();
+/// let by_name = get_all_sorted(db_state, sort.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_all_sorted(handle: DbHandle, sort_json: *const c_char) -> *const c_char {
+    ffi_boundary("get_all_sorted", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_sorted"));
+            return response_to_c_string(&error);
+        };
+
+        let sort = match parse_sort_json(sort_json) {
+            Ok(sort) => sort,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        sorted_records_response(&state, &PathFilter::default(), &sort)
+    })
+}
+
+fn sorted_records_response(state: &AppDbState, filter: &PathFilter, sort: &SortSpec) -> *const c_char {
+    match state.query_sorted(filter, sort) {
+        Ok(models) => {
+            match serde_json::to_string(&models) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            }
+        },
+        Err(e) => {
+            let error = AppResponse::from(e);
+            response_to_c_string(&error)
+        }
+    }
+}
+
+/// Retrieves only the given fields of the records matching a filter.
+///
+/// Meant for list views: each record is returned as an object holding just
+/// the projected paths, so much less JSON crosses the FFI boundary than with
+/// [`query`]. See [`AppDbState::query_projected`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `filter_json` - Null-terminated C string with the filter, as for [`query`]
+/// * `fields_json` - Null-terminated C string with a JSON array of dotted
+///   paths, e.g. `["id","data.title"]`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// projected records in key order, e.g.
+/// `[{"id":"n1","data":{"title":"Groceries"}}]`, or an error response.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, query_projected};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let filter = CString::new("{}").unwrap();
+/// let fields = CString::new(r#"["id","data.title"]"#).unwrap();
+/// let rows = query_projected(db_state, filter.as_ptr(), fields.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query_projected(handle: DbHandle, filter_json: *const c_char, fields_json: *const c_char) -> *const c_char {
+    ffi_boundary("query_projected", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query_projected"));
+            return response_to_c_string(&error);
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+        let fields = match parse_paths_json(fields_json) {
+            Ok(fields) => fields,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.query_projected(&filter, &fields) {
+            Ok(rows) => {
+                match serde_json::to_string(&rows) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Limits how many write transactions per second this instance accepts.
+///
+/// Writes beyond the limit (insert, update, delete) are rejected with a
+/// `Busy` response whose `retry_after_ms` tells when to retry, e.g.
+/// `{"Busy":{"message":"Write rate limit exceeded","retry_after_ms":20}}`.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ops_per_sec` - Sustained write transactions per second, or 0 to remove
+///   the limit (the default)
+/// * `burst` - Write transactions allowed back to back after an idle period
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON limit now
+/// in effect (`null` when unlimited), or an error response for an invalid
+/// limit.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_write_rate_limit};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("events").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let result = set_write_rate_limit(db_state, 50.0, 200);
+/// ```
+#[no_mangle]
+pub extern "C" fn set_write_rate_limit(handle: DbHandle, ops_per_sec: f64, burst: u32) -> *const c_char {
+    ffi_boundary("set_write_rate_limit", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_write_rate_limit"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        let limit = (ops_per_sec != 0.0).then_some(WriteRateLimit { ops_per_sec, burst });
+
+        if let Err(e) = state.set_write_rate_limit(limit) {
+            return response_to_c_string(&e);
+        }
+
+        match serde_json::to_string(&state.write_rate_limit()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing write rate limit: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Computes aggregates over the records matching a filter.
+///
+/// Runs in a single cursor pass without transferring records across the FFI
+/// boundary. See [`AppDbState::aggregate`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `spec_json` - Null-terminated C string with the aggregate spec, e.g.
+///   `{"filter":{"data.status":"paid"},"aggregations":[{"op":"count"},{"op":"avg","path":"data.amount"}]}`;
+///   `op` is one of `count`, `sum`, `min`, `max` and `avg`, and every op but
+///   `count` needs a `path`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload holds the number of
+/// matched records and one value per aggregation, keyed like `avg(data.amount)`,
+/// e.g. `{"matched":3,"values":{"avg(data.amount)":12.5,"count":3}}`. `min`,
+/// `max` and `avg` are `null` when no numeric value was found.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{aggregate, create_db};
+///
+/// let db_name = CString::new("orders").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let spec = CString::new(r#"{"aggregations":[{"op":"sum","path":"data.amount"}]}"#).unwrap();
+/// let result = aggregate(db_state, spec.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn aggregate(handle: DbHandle, spec_json: *const c_char) -> *const c_char {
+    ffi_boundary("aggregate", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to aggregate"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(spec_json, "aggregate JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let spec: AggregateSpec = match serde_json::from_str(&json_str) {
+            Ok(spec) => spec,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing aggregate spec: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.aggregate(&spec) {
+            Ok(result) => {
+                match serde_json::to_string(&result) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing aggregates: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the commit sequence of the last record write.
+///
+/// Call this right after a write to obtain a session token covering it, and
+/// pass the token as `min_sequence` to [`get_by_id_at`] or [`query_at`] on
+/// the reading side. See [`AppDbState::commit_sequence`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the sequence
+/// number, `0` before the first write, or an error response on failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_commit_sequence, post_data};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let json = CString::new(r#"{"id":"n1","hash":"h","data":{}}"#).unwrap();
+/// post_data(db_state, json.as_ptr());
+/// let token = get_commit_sequence(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_commit_sequence(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_commit_sequence", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_commit_sequence"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.commit_sequence() {
+            Ok(sequence) => response_to_c_string(&AppResponse::Ok(sequence.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Returns a token identifying the current state of the records, for hosts
+/// that poll for changes instead of watching them. See
+/// [`AppDbState::change_token`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the token, e.g.
+/// `"41"`, or an error response on failure.
+#[no_mangle]
+pub extern "C" fn get_change_token(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_change_token", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_change_token"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.change_token() {
+            Ok(token) => response_to_c_string(&AppResponse::Ok(token.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Tells whether anything was committed between two tokens returned by
+/// [`get_change_token`]. See [`AppDbState::changes_between`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `token_a` - Older token, e.g. the one read at the last render
+/// * `token_b` - Newer token
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::ChangeSummary`], e.g.
+/// `{"from":41,"to":44,"changed":true,"commits":3}`, or a `BadRequest` if
+/// the tokens are out of order or `token_b` was not issued yet.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_changes_between};
+///
+/// let db_name = CString::new("todos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let summary = get_changes_between(db_state, 41, 44);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_changes_between(handle: DbHandle, token_a: u64, token_b: u64) -> *const c_char {
+    ffi_boundary("get_changes_between", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_changes_between"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.changes_between(token_a, token_b) {
+            Ok(summary) => match serde_json::to_string(&summary) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing change summary: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves a record by ID once the commit sequence reaches `min_sequence`.
+///
+/// Waits up to half a second for a write made elsewhere (e.g. on another
+/// isolate) to become visible before reading. See [`AppDbState::get_by_id_at`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+/// * `min_sequence` - Token from [`get_commit_sequence`], or 0 to read right away
+///
+/// # Returns
+///
+/// Returns the same responses as [`get_by_id`], or a `Busy` response if the
+/// sequence is not reached in time.
+///
+/// # Safety
+///
+/// Both pointers must be valid. The ID string must be valid UTF-8.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_by_id_at};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("n1").unwrap();
+/// let result = get_by_id_at(db_state, id.as_ptr(), 42);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_by_id_at(handle: DbHandle, id: *const c_char, min_sequence: u64) -> *const c_char {
+    ffi_boundary("get_by_id_at", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_id_at"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_by_id_at(&id_str, min_sequence) {
+            Ok(Some(model)) => {
+                match serde_json::to_string(&model) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No model found with id: {id_str}"));
+                response_to_c_string(&error)
+            },
+            Err(e) => response_to_c_string(&e)
+        }
+    })
+}
+
+/// Retrieves the records matching a filter once the commit sequence reaches
+/// `min_sequence`.
+///
+/// Waits up to half a second for a write made elsewhere to become visible
+/// before querying. See [`AppDbState::query_at`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `filter_json` - Null-terminated C string with the filter, as for [`query`]
+/// * `min_sequence` - Token from [`get_commit_sequence`], or 0 to read right away
+///
+/// # Returns
+///
+/// Returns the same responses as [`query`], or a `Busy` response if the
+/// sequence is not reached in time.
+///
+/// # Safety
+///
+/// All pointers must be valid.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, query_at};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let filter = CString::new("{}").unwrap();
+/// let notes = query_at(db_state, filter.as_ptr(), 42);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query_at(handle: DbHandle, filter_json: *const c_char, min_sequence: u64) -> *const c_char {
+    ffi_boundary("query_at", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query_at"));
+            return response_to_c_string(&error);
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.query_at(&filter, min_sequence) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the unique values at a path across all records.
+///
+/// Meant for filter dropdowns (categories, tags): only the distinct values
+/// cross the FFI boundary instead of the whole dataset. Array values
+/// contribute their elements. See [`AppDbState::distinct`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `field_path` - Null-terminated C string with a dotted path, e.g. `data.category`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a sorted JSON array
+/// of the values, e.g. `["books","garden"]`, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, distinct};
+///
+/// let db_name = CString::new("products").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let path = CString::new("data.category").unwrap();
+/// let categories = distinct(db_state, path.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn distinct(handle: DbHandle, field_path: *const c_char) -> *const c_char {
+    ffi_boundary("distinct", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to distinct"));
+            return response_to_c_string(&error);
+        };
+
+        let path = match c_ptr_to_string(field_path, "field path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.distinct(&path) {
+            Ok(values) => {
+                match serde_json::to_string(&values) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing distinct values: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves the records of several key prefixes in one call.
+///
+/// All groups are read in a single transaction, so a dashboard composed of
+/// several collections loads from one consistent snapshot with one FFI call.
+/// See [`AppDbState::get_groups`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `prefixes_json` - Null-terminated C string with a JSON array of key
+///   prefixes, e.g. `["todo:","project:"]`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON object
+/// mapping each prefix to its records in key order, e.g.
+/// `{"project:":[],"todo:":[{"id":"todo:1",...}]}`, or an error response.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, get_groups};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let prefixes = CString::new(r#"["todo:","project:"]"#).unwrap();
+/// let groups = get_groups(db_state, prefixes.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_groups(handle: DbHandle, prefixes_json: *const c_char) -> *const c_char {
+    ffi_boundary("get_groups", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_groups"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(prefixes_json, "prefixes JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let prefixes: Vec<String> = match serde_json::from_str(&json_str) {
+            Ok(prefixes) => prefixes,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Expected a JSON array of prefixes: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_groups(&prefixes) {
+            Ok(groups) => {
+                match serde_json::to_string(&groups) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing groups: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Retrieves the records whose first indexed path holds a value.
+///
+/// Resolves index entry, ID and record in one call, e.g. a note by its slug.
+/// See [`AppDbState::get_by_indexed_value`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the index name
+/// * `value_json` - Null-terminated C string with the JSON value to look up,
+///   e.g. `"groceries"` (quoted) or `42`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// matching records (one for a unique value, empty when none match), or an
+/// error response on failure.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_by_indexed_value};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("by_slug").unwrap();
+/// let slug = CString::new(r#""groceries""#).unwrap();
+/// let notes = get_by_indexed_value(db_state, name.as_ptr(), slug.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_by_indexed_value(handle: DbHandle, name: *const c_char, value_json: *const c_char) -> *const c_char {
+    ffi_boundary("get_by_indexed_value", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_indexed_value"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "index name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let json_str = match c_ptr_to_string(value_json, "value JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let value: serde_json::Value = match serde_json::from_str(&json_str) {
+            Ok(value) => value,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Expected a JSON value: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_by_indexed_value(&name, &value) {
+            Ok(models) => {
+                match serde_json::to_string(&models) {
+                    Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                    Err(e) => {
+                        let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                        response_to_c_string(&error)
+                    }
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Enables or disables the index from record hash to ID used by
+/// [`find_by_hash`].
+///
+/// See [`AppDbState::set_hash_index`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `enabled` - Whether the hash index is kept
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the state set,
+/// `"true"` or `"false"`.
+#[no_mangle]
+pub extern "C" fn set_hash_index(handle: DbHandle, enabled: bool) -> *const c_char {
+    ffi_boundary("set_hash_index", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_hash_index"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_hash_index(enabled) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(enabled.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves the IDs of the records with a given hash, so a sync layer can
+/// skip writing content that already exists locally.
+///
+/// See [`AppDbState::find_by_hash`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `hash` - Null-terminated C string with the hash to look up
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// the matching IDs, empty when none match.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, find_by_hash, set_hash_index};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("photos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+/// set_hash_index(db_state, true);
+///
+/// let hash = CString::new("sha256_9f2c").unwrap();
+/// let ids = find_by_hash(db_state, hash.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn find_by_hash(handle: DbHandle, hash: *const c_char) -> *const c_char {
+    ffi_boundary("find_by_hash", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to find_by_hash"));
+            return response_to_c_string(&error);
+        };
+
+        let hash = match c_ptr_to_string(hash, "hash") {
+            Ok(hash) => hash,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.find_by_hash(&hash) {
+            Ok(ids) => match serde_json::to_string(&ids) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing IDs: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Starts a background thread deleting expired records.
+///
+/// Every `interval_ms` the thread deletes the records whose expiry time, read
+/// from the first path of the given index, lies before now, in write
+/// transactions of at most `batch_size` records, so TTL cleanup never blocks
+/// foreground calls for long. A running sweeper is replaced. See
+/// [`AppDbState::start_expiry_sweeper`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `config_json` - Null-terminated C string with the sweeper configuration,
+///   e.g. `{"index":"by_expiry","interval_ms":60000,"batch_size":500}`; the
+///   index must exist and hold expiry times in milliseconds since the Unix epoch
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the configuration
+/// the sweeper runs with, or an error response.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, create_index, start_expiry_sweeper};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("cache").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("by_expiry").unwrap();
+/// let paths = CString::new(r#"["data.expires_at"]"#).unwrap();
+/// create_index(db_state, name.as_ptr(), paths.as_ptr());
+///
+/// let config = CString::new(r#"{"index":"by_expiry","interval_ms":60000}"#).unwrap();
+/// let result = start_expiry_sweeper(db_state, config.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn start_expiry_sweeper(handle: DbHandle, config_json: *const c_char) -> *const c_char {
+    ffi_boundary("start_expiry_sweeper", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to start_expiry_sweeper"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(config_json, "sweeper JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let config: ExpirySweep = match serde_json::from_str(&json_str) {
+            Ok(config) => config,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing expiry sweeper configuration: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        if let Err(e) = state.start_expiry_sweeper(config) {
+            return response_to_c_string(&e);
+        }
+
+        match serde_json::to_string(&state.expiry_sweeper()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing expiry sweeper configuration: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Stops the background expiry sweeper, waiting for a sweep in progress.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if a
+/// sweeper was running, `"false"` otherwise.
+#[no_mangle]
+pub extern "C" fn stop_expiry_sweeper(handle: DbHandle) -> *const c_char {
+    ffi_boundary("stop_expiry_sweeper", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to stop_expiry_sweeper"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        let stopped = state.stop_expiry_sweeper().is_some();
+        response_to_c_string(&AppResponse::Ok(stopped.to_string()))
+    })
+}
+
+/// Prepares the database for the app moving to the background.
+///
+/// Call from the platform callback for entering the background (e.g.
+/// `AppLifecycleState.paused` in Flutter): the expiry sweeper is paused and
+/// the environment is synced to disk, so a suspended or killed app loses
+/// nothing. See [`AppDbState::enter_background`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or an error
+/// response if the sync fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, notify_app_background, notify_app_foreground};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// notify_app_background(db_state);
+/// notify_app_foreground(db_state);
+/// ```
+#[no_mangle]
+pub extern "C" fn notify_app_background(handle: DbHandle) -> *const c_char {
+    ffi_boundary("notify_app_background", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to notify_app_background"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.enter_background() {
+            Ok(()) => response_to_c_string(&AppResponse::Ok("Database ready for background".to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Resumes the background work paused by [`notify_app_background`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or an error
+/// response if the expiry sweeper cannot be restarted.
+#[no_mangle]
+pub extern "C" fn notify_app_foreground(handle: DbHandle) -> *const c_char {
+    ffi_boundary("notify_app_foreground", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to notify_app_foreground"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.enter_foreground() {
+            Ok(()) => response_to_c_string(&AppResponse::Ok("Database resumed".to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Callback receiving the changes of a watch, see [`watch`].
+///
+/// `batch_json` is a response string like the ones returned by every other
+/// function, e.g.
+/// `{"Ok":"{\"watch_id\":1,\"ids\":[\"todo:1\"],\"cleared\":false}"}`. It
+/// stays valid until the receiver releases it with [`free_c_string`], so the
+/// callback may hand it to another thread (e.g. a Dart
+/// `NativeCallable.listener`).
+pub type WatchCallback = extern "C" fn(watch_id: u64, batch_json: *const c_char);
+
+/// Watches the records under a key prefix.
+///
+/// Changes are collected for `debounce_ms` after the first one and delivered
+/// to `callback` as one batch listing every changed ID, so bulk writes during
+/// sync produce a few events instead of one per record. The callback runs on
+/// a dispatcher thread. See [`AppDbState::watch`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `prefix` - Null-terminated C string with the key prefix, empty for all records
+/// * `debounce_ms` - Window in milliseconds over which changes are batched, e.g. 16
+/// * `callback` - Function receiving each batch
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the watch ID, or
+/// an error response.
+///
+/// # Safety
+///
+/// The prefix must be a valid pointer and `callback` must stay
+/// callable until [`unwatch`] or the database is released.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, free_c_string, watch};
+/// use std::ffi::CString;
+/// use std::os::raw::c_char;
+///
+/// extern "C" fn on_change(watch_id: u64, batch_json: *const c_char) {
+///     println!("Watch {watch_id} fired");
+///     free_c_string(batch_json);
+/// }
+///
+/// let db_name = CString::new("todos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let prefix = CString::new("todo:").unwrap();
+/// let watch_id = watch(db_state, prefix.as_ptr(), 16, Some(on_change));
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn watch(handle: DbHandle, prefix: *const c_char, debounce_ms: u32, callback: Option<WatchCallback>) -> *const c_char {
+    ffi_boundary("watch", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to watch"));
+            return response_to_c_string(&error);
+        };
+
+        let Some(callback) = callback else {
+            let error = AppResponse::BadRequest("Null callback passed to watch".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let prefix = match c_ptr_to_string(prefix, "prefix") {
+            Ok(prefix) => prefix,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        let deliver = move |batch: &ChangeBatch| {
+            let response = match serde_json::to_string(batch) {
+                Ok(json) => AppResponse::Ok(json),
+                Err(e) => AppResponse::SerializationError(format!("Error serializing changes: {e:?}")),
+            };
+            callback(batch.watch_id, response_to_c_string(&response));
+        };
+
+        match state.watch(&prefix, std::time::Duration::from_millis(u64::from(debounce_ms)), deliver) {
+            Ok(watch_id) => response_to_c_string(&AppResponse::Ok(watch_id.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a watch created by [`watch`]. Changes not delivered yet are dropped.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `watch_id` - ID returned by [`watch`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// watch existed, `"false"` otherwise.
+#[no_mangle]
+pub extern "C" fn unwatch(handle: DbHandle, watch_id: u64) -> *const c_char {
+    ffi_boundary("unwatch", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to unwatch"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        response_to_c_string(&AppResponse::Ok(state.unwatch(watch_id).to_string()))
+    })
+}
+
+/// Sets the function posting change events to Dart ports, for
+/// [`watch_port`]. Pass `NativeApi.postCObject` from `dart:ffi` once per
+/// process; calling it again replaces the function.
+///
+/// # Parameters
+///
+/// * `post` - `Dart_PostCObject`, as returned by `NativeApi.postCObject`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or a
+/// `BadRequest` for a null function.
+#[no_mangle]
+pub extern "C" fn register_dart_post_cobject(post: Option<DartPostCObjectFn>) -> *const c_char {
+    ffi_boundary("register_dart_post_cobject", || {
+        let Some(post) = post else {
+            let error = AppResponse::BadRequest("Null function passed to register_dart_post_cobject".to_string());
+            return response_to_c_string(&error);
+        };
+
+        dart_port::set_post_cobject(post);
+        response_to_c_string(&AppResponse::Ok("Dart post function set".to_string()))
+    })
+}
+
+/// Watches the records under a key prefix like [`watch`], posting each batch
+/// to a Dart `ReceivePort` instead of calling a callback.
+///
+/// Each message is a Dart `String` holding the response JSON a
+/// [`WatchCallback`] receives, e.g.
+/// `{"Ok":"{\"watch_id\":1,\"ids\":[\"todo:1\"],\"cleared\":false}"}`. Dart
+/// owns the messages; there is nothing to free. Remove the watch with
+/// [`unwatch`] before closing the port; batches posted to a closed port are
+/// dropped.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `prefix` - Null-terminated C string with the key prefix, empty for all records
+/// * `debounce_ms` - Window in milliseconds over which changes are batched, e.g. 16
+/// * `port` - `sendPort.nativePort` of the receiving port
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the watch ID, or
+/// a `BadRequest` if [`register_dart_post_cobject`] was not called or the
+/// port is `0`.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn watch_port(handle: DbHandle, prefix: *const c_char, debounce_ms: u32, port: DartPort) -> *const c_char {
+    ffi_boundary("watch_port", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to watch_port"));
+            return response_to_c_string(&error);
+        };
+
+        if !dart_port::is_registered() {
+            let error = AppResponse::BadRequest("Call register_dart_post_cobject before watch_port".to_string());
+            return response_to_c_string(&error);
+        }
+        if port == 0 {
+            let error = AppResponse::BadRequest("Illegal Dart port 0 passed to watch_port".to_string());
+            return response_to_c_string(&error);
+        }
+
+        let prefix = match c_ptr_to_string(prefix, "prefix") {
+            Ok(prefix) => prefix,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        let deliver = move |batch: &ChangeBatch| {
+            let response = match serde_json::to_string(batch) {
+                Ok(json) => AppResponse::Ok(json),
+                Err(e) => AppResponse::SerializationError(format!("Error serializing changes: {e:?}")),
+            };
+            let Ok(message) = encode_response(&response, RESPONSE_FORMAT.load(Ordering::Relaxed)) else {
+                return;
+            };
+            if !dart_port::post_string(port, &message) {
+                warn!("Dropped changes of watch {} for closed Dart port {port}", batch.watch_id);
+            }
+        };
+
+        match state.watch(&prefix, std::time::Duration::from_millis(u64::from(debounce_ms)), deliver) {
+            Ok(watch_id) => response_to_c_string(&AppResponse::Ok(watch_id.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Callback receiving the changes of a query subscription, see
+/// [`subscribe_query`].
+///
+/// `event_json` is a response string whose `Ok` payload is a
+/// [`local_db_model::SubscriptionEvent`], e.g.
+/// `{"Ok":"{\"name\":\"open_todos\",\"added\":[...],\"updated\":[],\"removed\":[\"todo:1\"]}"}`.
+/// Release it with [`free_c_string`], as for [`WatchCallback`].
+pub type SubscriptionCallback = extern "C" fn(event_json: *const c_char);
+
+/// Subscribes to the records matching a filter, for a reactive list view.
+///
+/// Returns the current result set; afterwards `callback` receives the records
+/// added to, updated in and removed from it as records are written, batched
+/// over `debounce_ms`. Subscribing again under the same name replaces the
+/// subscription. See [`AppDbState::subscribe`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string naming the subscription
+/// * `filter_json` - Null-terminated C string with a [`query::PathFilter`], `{}` for all records
+/// * `debounce_ms` - Window in milliseconds over which changes are batched, e.g. 16
+/// * `callback` - Function receiving each event
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the array of
+/// matching records, or an error response.
+///
+/// # Safety
+///
+/// The strings must be valid pointers and `callback` must stay callable
+/// until [`unsubscribe_query`] or the database is closed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, free_c_string, subscribe_query};
+/// use std::ffi::CString;
+/// use std::os::raw::c_char;
+///
+/// extern "C" fn on_event(event_json: *const c_char) {
+///     free_c_string(event_json);
+/// }
+///
+/// let db_name = CString::new("todos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("open_todos").unwrap();
+/// let filter = CString::new(r#"{"data.done": false}"#).unwrap();
+/// let records = subscribe_query(db_state, name.as_ptr(), filter.as_ptr(), 16, Some(on_event));
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn subscribe_query(handle: DbHandle, name: *const c_char, filter_json: *const c_char, debounce_ms: u32, callback: Option<SubscriptionCallback>) -> *const c_char {
+    ffi_boundary("subscribe_query", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to subscribe_query"));
+            return response_to_c_string(&error);
+        };
+
+        let Some(callback) = callback else {
+            let error = AppResponse::BadRequest("Null callback passed to subscribe_query".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "subscription name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        let deliver = move |event: &SubscriptionEvent| {
+            let response = match serde_json::to_string(event) {
+                Ok(json) => AppResponse::Ok(json),
+                Err(e) => AppResponse::SerializationError(format!("Error serializing subscription event: {e:?}")),
+            };
+            callback(response_to_c_string(&response));
+        };
+
+        match state.subscribe(&name, filter, std::time::Duration::from_millis(u64::from(debounce_ms)), deliver) {
+            Ok(records) => match serde_json::to_string(&records) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a subscription created by [`subscribe_query`]. Changes not
+/// delivered yet are dropped.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string naming the subscription
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// subscription existed, `"false"` otherwise.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn unsubscribe_query(handle: DbHandle, name: *const c_char) -> *const c_char {
+    ffi_boundary("unsubscribe_query", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to unsubscribe_query"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "subscription name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        response_to_c_string(&AppResponse::Ok(state.unsubscribe(&name).to_string()))
+    })
+}
+
+/// Returns the records changed since a named consumer last acknowledged a
+/// commit sequence, and the IDs deleted since.
+///
+/// See [`AppDbState::get_all_delta`]; pass the returned `sequence` to
+/// [`ack_delta`] once the changes are processed.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `consumer` - C string with the consumer name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::ChangeDelta`], e.g.
+/// `{"consumer":"search","since":4,"sequence":7,"reset":false,"records":[...],"deleted":["a"]}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_all_delta};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("todos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let consumer = CString::new("search_index").unwrap();
+/// let result = get_all_delta(db_state, consumer.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_all_delta(handle: DbHandle, consumer: *const c_char) -> *const c_char {
+    ffi_boundary("get_all_delta", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_delta"));
+            return response_to_c_string(&error);
+        };
+
+        let consumer = match c_ptr_to_string(consumer, "consumer") {
+            Ok(consumer) => consumer,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_all_delta(&consumer) {
+            Ok(delta) => match serde_json::to_string(&delta) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing delta: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Advances the cursor of a delta consumer to a processed `sequence`.
+///
+/// See [`AppDbState::ack_delta`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `consumer` - C string with the consumer name
+/// * `sequence` - The `sequence` of the processed delta
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the acknowledged
+/// sequence.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ack_delta(handle: DbHandle, consumer: *const c_char, sequence: u64) -> *const c_char {
+    ffi_boundary("ack_delta", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to ack_delta"));
+            return response_to_c_string(&error);
+        };
+
+        let consumer = match c_ptr_to_string(consumer, "consumer") {
+            Ok(consumer) => consumer,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.ack_delta(&consumer, sequence) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(sequence.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a delta consumer and its cursor.
+///
+/// See [`AppDbState::remove_delta_consumer`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `consumer` - C string with the consumer name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// consumer existed, `"false"` otherwise.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn remove_delta_consumer(handle: DbHandle, consumer: *const c_char) -> *const c_char {
+    ffi_boundary("remove_delta_consumer", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to remove_delta_consumer"));
+            return response_to_c_string(&error);
+        };
+
+        let consumer = match c_ptr_to_string(consumer, "consumer") {
+            Ok(consumer) => consumer,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.remove_delta_consumer(&consumer) {
+            Ok(removed) => response_to_c_string(&AppResponse::Ok(removed.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Enables delta encoding of record updates, keeping a full snapshot every
+/// `snapshot_every` versions, or disables it with `0`.
+///
+/// See [`AppDbState::set_delta_encoding`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `snapshot_every` - Versions between full snapshots, `0` to disable
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the snapshot
+/// interval set.
+#[no_mangle]
+pub extern "C" fn set_delta_encoding(handle: DbHandle, snapshot_every: u32) -> *const c_char {
+    ffi_boundary("set_delta_encoding", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_delta_encoding"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_delta_encoding((snapshot_every > 0).then_some(snapshot_every)) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(snapshot_every.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the records changed since the consumer last acknowledged a
+/// sequence, with updates as merge patches where possible.
+///
+/// See [`AppDbState::get_encoded_delta`]. Acknowledge it with
+/// [`ack_delta`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `consumer` - C string with the consumer name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::EncodedDelta`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_encoded_delta, set_delta_encoding};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+/// set_delta_encoding(db, 16);
+///
+/// let consumer = CString::new("sync").unwrap();
+/// let delta = get_encoded_delta(db, consumer.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_encoded_delta(handle: DbHandle, consumer: *const c_char) -> *const c_char {
+    ffi_boundary("get_encoded_delta", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_encoded_delta"));
+            return response_to_c_string(&error);
+        };
+
+        let consumer = match c_ptr_to_string(consumer, "consumer") {
+            Ok(consumer) => consumer,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_encoded_delta(&consumer) {
+            Ok(delta) => match serde_json::to_string(&delta) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing encoded delta: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Sets the age after which [`run_maintenance`] purges logged changes, so
+/// a consumer that stopped acknowledging cannot grow the change log
+/// without bounds.
+///
+/// See [`AppDbState::set_changelog_retention`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `max_age_ms` - Age in milliseconds, or 0 to keep changes until acknowledged (the default)
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the window now in
+/// effect.
+#[no_mangle]
+pub extern "C" fn set_changelog_retention(handle: DbHandle, max_age_ms: u64) -> *const c_char {
+    ffi_boundary("set_changelog_retention", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_changelog_retention"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.set_changelog_retention((max_age_ms > 0).then_some(max_age_ms));
+        response_to_c_string(&AppResponse::Ok(max_age_ms.to_string()))
+    })
+}
+
+/// Deletes the logged changes and deletions before a commit sequence or
+/// older than an age, acknowledged or not. Consumers that had not
+/// acknowledged them receive every record with `reset` on their next delta.
+///
+/// See [`AppDbState::purge_changelog`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `before_json` - C string with a JSON [`local_db_model::ChangelogCutoff`], e.g. `{"sequence": 1200}` or `{"age_ms": 604800000}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON
+/// [`local_db_model::ChangelogPurge`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, purge_changelog};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("todos").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let before = CString::new(r#"{"age_ms": 604800000}"#).unwrap();
+/// let result = purge_changelog(db_state, before.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn purge_changelog(handle: DbHandle, before_json: *const c_char) -> *const c_char {
+    ffi_boundary("purge_changelog", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to purge_changelog"));
+            return response_to_c_string(&error);
+        };
+
+        let before_json = match c_ptr_to_string(before_json, "cutoff") {
+            Ok(before_json) => before_json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let before: ChangelogCutoff = match serde_json::from_str(&before_json) {
+            Ok(before) => before,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing changelog cutoff: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.purge_changelog(before) {
+            Ok(purge) => match serde_json::to_string(&purge) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing changelog purge: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Callback receiving the progress of [`backfill_field`] after each batch,
+/// on the thread that called it.
+pub type BackfillProgressCallback = extern "C" fn(scanned: u64, updated: u64);
+
+/// Sets a default value on the records lacking a field, in batched write
+/// transactions.
+///
+/// See [`AppDbState::backfill_field`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `backfill_json` - JSON of a [`local_db_model::Backfill`], e.g.
+///   `{"field":"data.priority","value":0,"filter":{"data.type":"task"}}`
+/// * `progress` - Optional function called after each batch
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the final
+/// [`local_db_model::BackfillProgress`], e.g. `{"scanned":1500,"updated":312}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{backfill_field, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("tasks").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let backfill = CString::new(r#"{"field":"data.priority","value":0}"#).unwrap();
+/// let result = backfill_field(db_state, backfill.as_ptr(), None);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn backfill_field(handle: DbHandle, backfill_json: *const c_char, progress: Option<BackfillProgressCallback>) -> *const c_char {
+    ffi_boundary("backfill_field", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to backfill_field"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(backfill_json, "backfill JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let backfill: Backfill = match serde_json::from_str(&json_str) {
+            Ok(backfill) => backfill,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing backfill: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        let result = state.backfill_field(&backfill, |done| {
+            if let Some(progress) = progress {
+                progress(done.scanned as u64, done.updated as u64);
+            }
+        });
+
+        match result {
+            Ok(done) => match serde_json::to_string(&done) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing backfill progress: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves a record by ID like [`get_by_id`], returning a [`ByteBuffer`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+///
+/// # Returns
+///
+/// Returns a buffer holding the JSON response, to release with
+/// [`free_buffer`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, free_buffer, get_by_id_buffer};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("my_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("user_123").unwrap();
+/// let buffer = get_by_id_buffer(db_state, id.as_ptr());
+/// let json = unsafe { std::slice::from_raw_parts(buffer.ptr, buffer.len) };
+/// free_buffer(buffer);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_by_id_buffer(handle: DbHandle, id: *const c_char) -> ByteBuffer {
+    ffi_boundary("get_by_id_buffer", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_by_id_buffer"));
+            return response_to_buffer(&error);
+        };
+
+        if id.is_null() {
+            let error = AppResponse::BadRequest("Null id pointer passed to get_by_id_buffer".to_string());
+            return response_to_buffer(&error);
+        }
+
+        let id_str = match unsafe { CStr::from_ptr(id).to_str() } {
+            Ok(id) => id,
+            Err(e) => {
+                let error = AppResponse::BadRequest(format!("Invalid UTF-8 in id: {e}"));
+                return response_to_buffer(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_by_id(id_str) {
+            Ok(Some(model)) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_buffer(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                    response_to_buffer(&error)
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No model found with id: {id_str}"));
+                response_to_buffer(&error)
+            }
+            Err(e) => response_to_buffer(&e),
+        }
+    })
+}
+
+/// Retrieves all records like [`get_all`], returning a [`ByteBuffer`].
+///
+/// Large result sets are where avoiding the terminator scan pays off most.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a buffer holding the JSON response, to release with
+/// [`free_buffer`].
+#[no_mangle]
+pub extern "C" fn get_all_buffer(handle: DbHandle) -> ByteBuffer {
+    ffi_boundary("get_all_buffer", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_all_buffer"));
+            return response_to_buffer(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get() {
+            Ok(models) => match serde_json::to_string(&models) {
+                Ok(json) => response_to_buffer(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing models: {e:?}"));
+                    response_to_buffer(&error)
+                }
+            },
+            Err(e) => response_to_buffer(&AppResponse::from(e)),
+        }
+    })
+}
+
+/// Makes the defined indexes match a declared list, creating missing ones
+/// and rebuilding those whose paths changed.
+///
+/// Meant to be called right after [`create_db`] on every start; see
+/// [`AppDbState::ensure_indexes`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `indexes_json` - JSON array of [`local_db_model::IndexDefinition`], e.g.
+///   `[{"name":"by_slug","paths":["data.slug"]}]`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::IndexSyncReport`], e.g.
+/// `{"created":["by_slug"],"rebuilt":[],"unchanged":[]}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, ensure_indexes};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let indexes = CString::new(r#"[{"name":"by_slug","paths":["data.slug"]}]"#).unwrap();
+/// let result = ensure_indexes(db_state, indexes.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ensure_indexes(handle: DbHandle, indexes_json: *const c_char) -> *const c_char {
+    ffi_boundary("ensure_indexes", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to ensure_indexes"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(indexes_json, "indexes JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let indexes: Vec<IndexDefinition> = match serde_json::from_str(&json_str) {
+            Ok(indexes) => indexes,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Expected a JSON array of index definitions: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.ensure_indexes(&indexes) {
+            Ok(report) => match serde_json::to_string(&report) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing index report: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Selects the JSON format of every response returned from now on, for the
+/// whole process.
+///
+/// * `1` (the default) - `{"Ok": "..."}` or `{"NotFound": "..."}`, keyed by
+///   the [`AppResponse`] variant
+/// * `2` - an [`app_response::ResponseEnvelope`] with a stable error code,
+///   e.g. `{"ok":false,"data":null,"error":{"code":1003,"name":"NOT_FOUND",
+///   "category":"not_found","message":"...","detail":null}}`, so callers can
+///   branch on codes instead of messages (see [`AppResponse::code`])
+///
+/// # Parameters
+///
+/// * `version` - Format version, 1 or 2
+///
+/// # Returns
+///
+/// Returns a C string, in the newly selected format, whose payload is the
+/// previous version.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::set_response_format;
+///
+/// let result = set_response_format(2);
+/// ```
+#[no_mangle]
+pub extern "C" fn set_response_format(version: u32) -> *const c_char {
+    ffi_boundary("set_response_format", || {
+        if !(1..=LATEST_RESPONSE_FORMAT).contains(&version) {
+            let error = AppResponse::BadRequest(format!("Unknown response format {version}, expected 1 to {LATEST_RESPONSE_FORMAT}"));
+            return response_to_c_string(&error);
+        }
+
+        let previous = RESPONSE_FORMAT.swap(version, Ordering::Relaxed);
+        response_to_c_string(&AppResponse::Ok(previous.to_string()))
+    })
+}
+
+/// Registers a JSON array or NDJSON file as a read-only external collection,
+/// usable with [`query_external`] and [`join_external`] without importing it.
+///
+/// Registering a name again replaces the collection; closing the database
+/// drops all registrations.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the collection name
+/// * `path` - Null-terminated C string with the path of the file
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// records in the collection, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, register_external_collection};
+///
+/// let db_name = CString::new("orders").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let name = CString::new("products").unwrap();
+/// let path = CString::new("assets/products.ndjson").unwrap();
+/// let result = register_external_collection(db_state, name.as_ptr(), path.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn register_external_collection(handle: DbHandle, name: *const c_char, path: *const c_char) -> *const c_char {
+    ffi_boundary("register_external_collection", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to register_external_collection"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "collection name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let path = match c_ptr_to_string(path, "path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.register_external(&name, &path) {
+            Ok(records) => response_to_c_string(&AppResponse::Ok(records.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a registered external collection.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the collection name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if the
+/// collection was registered, `false` otherwise.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn unregister_external_collection(handle: DbHandle, name: *const c_char) -> *const c_char {
+    ffi_boundary("unregister_external_collection", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to unregister_external_collection"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "collection name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        response_to_c_string(&AppResponse::Ok(state.unregister_external(&name).to_string()))
+    })
+}
+
+/// Retrieves the records of an external collection matching a filter.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `name` - Null-terminated C string with the collection name
+/// * `filter_json` - Null-terminated C string with a [`PathFilter`], e.g.
+///   `{"data.country":"ES"}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of the
+/// matching records, or an error response on failure.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn query_external(handle: DbHandle, name: *const c_char, filter_json: *const c_char) -> *const c_char {
+    ffi_boundary("query_external", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to query_external"));
+            return response_to_c_string(&error);
+        };
+
+        let name = match c_ptr_to_string(name, "collection name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.query_external(&name, &filter) {
+            Ok(models) => match serde_json::to_string(&models) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves the records matching a filter, each paired with the record of an
+/// external collection whose ID is the value at `path`.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `filter_json` - Null-terminated C string with a [`PathFilter`]
+/// * `path` - Null-terminated C string with the dotted path of the reference,
+///   e.g. `data.product_id`
+/// * `collection` - Null-terminated C string with the collection name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// [`local_db_model::JoinedRecord`], e.g.
+/// `[{"record":{...},"joined":{...}},{"record":{...},"joined":null}]`.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, join_external};
+///
+/// let db_name = CString::new("orders").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let filter = CString::new("{}").unwrap();
+/// let path = CString::new("data.product_id").unwrap();
+/// let collection = CString::new("products").unwrap();
+/// let orders = join_external(db_state, filter.as_ptr(), path.as_ptr(), collection.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn join_external(handle: DbHandle, filter_json: *const c_char, path: *const c_char, collection: *const c_char) -> *const c_char {
+    ffi_boundary("join_external", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to join_external"));
+            return response_to_c_string(&error);
+        };
+
+        let filter = match parse_filter_json(filter_json) {
+            Ok(filter) => filter,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let path = match c_ptr_to_string(path, "path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection name") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.join_external(&filter, &path, &collection) {
+            Ok(joined) => match serde_json::to_string(&joined) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the most recent failure on the calling thread of a function that
+/// reports errors only by returning null or `0`, such as [`create_db`].
+///
+/// The error is kept until the next such failure on the same thread;
+/// successful calls do not clear it, so check it only after a failed return.
+/// Dart calls from one isolate run on its thread, so each isolate sees its
+/// own errors.
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string holding the failure as an error
+/// response, e.g. `{"DatabaseError":"Cannot open database at notes.lmdb: ..."}`,
+/// or `{"Ok":"null"}` if no failure was recorded on this thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, free_c_string, get_last_error};
+///
+/// let name = CString::new("/read-only/notes").unwrap();
+/// if create_db(name.as_ptr()) == 0 {
+///     let error = get_last_error();
+///     free_c_string(error);
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn get_last_error() -> *const c_char {
+    ffi_boundary("get_last_error", || {
+        match LAST_ERROR.with(|last| last.borrow().clone()) {
+            Some(error) => response_to_c_string(&error),
+            None => response_to_c_string(&AppResponse::Ok("null".to_string())),
+        }
+    })
+}
+
+/// Returns the version and build information of the library, so the Dart
+/// side can verify that it loaded a compatible binary and include the
+/// details in bug reports.
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::LibraryVersion`], e.g.
+/// `{"version":"0.5.0","lmdb_version":"0.9.22","features":["signing"],"index_format":1,"response_formats":[1,2],"os":"android","arch":"aarch64"}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{free_c_string, get_library_version};
+///
+/// let version = get_library_version();
+/// free_c_string(version);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_library_version() -> *const c_char {
+    ffi_boundary("get_library_version", || {
+        match serde_json::to_string(&AppDbState::library_version()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing library version: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Registers the key of a tenant, so that records whose ID starts with
+/// `prefix` are stored encrypted from now on (requires the `encryption`
+/// feature).
+///
+/// Keys are not persisted; register them after every [`create_db`], for
+/// example from the platform keychain. See [`AppDbState::set_encryption_key`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `tenant` - Null-terminated C string with the tenant name
+/// * `prefix` - Null-terminated C string with the ID prefix of the tenant's records
+/// * `key_hex` - Null-terminated C string with the 32-byte key, hex-encoded
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or a `BadRequest`
+/// for a malformed key, a prefix taken by another tenant, or a build without
+/// encryption.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, set_encryption_key};
+///
+/// let db_name = CString::new("accounts").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let tenant = CString::new("acct42").unwrap();
+/// let prefix = CString::new("acct42:").unwrap();
+/// let key = CString::new("07".repeat(32)).unwrap();
+/// let result = set_encryption_key(db_state, tenant.as_ptr(), prefix.as_ptr(), key.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_encryption_key(handle: DbHandle, tenant: *const c_char, prefix: *const c_char, key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("set_encryption_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_encryption_key"));
+            return response_to_c_string(&error);
+        };
+
+        let tenant = match c_ptr_to_string(tenant, "tenant") {
+            Ok(tenant) => tenant,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let prefix = match c_ptr_to_string(prefix, "prefix") {
+            Ok(prefix) => prefix,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let key_hex = match c_ptr_to_string(key_hex, "key") {
+            Ok(key_hex) => key_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let Some(key) = signing::decode_hex(&key_hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            let error = AppResponse::BadRequest("Encryption key must be 32 hex-encoded bytes".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_encryption_key(&tenant, &prefix, &key) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(format!("Encryption key of {tenant} set"))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Forgets the key of a tenant, wiping it from memory. Its records stay
+/// unreadable until the key is registered again, or for good once the app
+/// has destroyed its copy (crypto-shredding).
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `tenant` - Null-terminated C string with the tenant name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if a key
+/// was registered, `false` otherwise.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn remove_encryption_key(handle: DbHandle, tenant: *const c_char) -> *const c_char {
+    ffi_boundary("remove_encryption_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to remove_encryption_key"));
+            return response_to_c_string(&error);
+        };
+
+        let tenant = match c_ptr_to_string(tenant, "tenant") {
+            Ok(tenant) => tenant,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        response_to_c_string(&AppResponse::Ok(state.remove_encryption_key(&tenant).to_string()))
+    })
+}
+
+/// Encrypts the values at the given paths of every record written from now
+/// on, keeping the rest of each record plain (requires the `encryption`
+/// feature).
+///
+/// The configuration is not persisted; set it after every [`create_db`]. See
+/// [`AppDbState::set_field_encryption`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `paths_json` - Null-terminated C string with a JSON array of dotted paths below `data`, e.g. `["data.ssn"]`
+/// * `key_hex` - Null-terminated C string with the 32-byte key, hex-encoded
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or a `BadRequest`
+/// for a malformed key, a path not below `data`, or a build without
+/// encryption.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, set_field_encryption};
+///
+/// let db_name = CString::new("patients").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let paths = CString::new(r#"["data.ssn"]"#).unwrap();
+/// let key = CString::new("07".repeat(32)).unwrap();
+/// let result = set_field_encryption(db_state, paths.as_ptr(), key.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_field_encryption(handle: DbHandle, paths_json: *const c_char, key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("set_field_encryption", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_field_encryption"));
+            return response_to_c_string(&error);
+        };
+
+        let paths_json = match c_ptr_to_string(paths_json, "paths") {
+            Ok(paths_json) => paths_json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let paths: Vec<String> = match serde_json::from_str(&paths_json) {
+            Ok(paths) => paths,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing encrypted paths: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let key_hex = match c_ptr_to_string(key_hex, "key") {
+            Ok(key_hex) => key_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let Some(key) = signing::decode_hex(&key_hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            let error = AppResponse::BadRequest("Encryption key must be 32 hex-encoded bytes".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_field_encryption(&paths, &key) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(format!("Field encryption set for {} paths", paths.len()))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Stops encrypting fields and wipes the key from memory. Fields encrypted
+/// before are read back as their `{"$encrypted": ...}` markers until the key
+/// is set again.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if field
+/// encryption was set, `false` otherwise.
+#[no_mangle]
+pub extern "C" fn remove_field_encryption(handle: DbHandle) -> *const c_char {
+    ffi_boundary("remove_field_encryption", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to remove_field_encryption"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        response_to_c_string(&AppResponse::Ok(state.remove_field_encryption().to_string()))
+    })
+}
+
+/// Returns the current time of the database clock, in milliseconds since the
+/// Unix epoch, including the offset set with [`set_clock_offset`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the time, e.g.
+/// `{"Ok":"1760000000000"}`.
+#[no_mangle]
+pub extern "C" fn get_current_time(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_current_time", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_current_time"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        response_to_c_string(&AppResponse::Ok(state.now_ms().to_string()))
+    })
+}
+
+/// Sets the milliseconds added to the system time by the database clock, e.g.
+/// the server time minus the device time measured during sync, so that expiry
+/// checks and stored timestamps survive a wrong device clock.
+///
+/// See [`AppDbState::set_clock_offset`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `offset_ms` - Offset in milliseconds, `0` to use the system time as is
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the previous offset.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, set_clock_offset};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// // The server clock is 90 seconds ahead of the device
+/// let result = set_clock_offset(db_state, 90_000);
+/// ```
+#[no_mangle]
+pub extern "C" fn set_clock_offset(handle: DbHandle, offset_ms: i64) -> *const c_char {
+    ffi_boundary("set_clock_offset", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_clock_offset"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        let previous = state.clock_offset();
+        state.set_clock_offset(offset_ms);
+        response_to_c_string(&AppResponse::Ok(previous.to_string()))
+    })
+}
+
+/// Freezes the database clock at `now_ms` milliseconds since the Unix epoch,
+/// for tests of time-dependent behavior; `0` lets it run again.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `now_ms` - Fixed time, or `0` for the system time plus offset
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the clock's time
+/// after the change.
+#[no_mangle]
+pub extern "C" fn set_fixed_clock(handle: DbHandle, now_ms: u64) -> *const c_char {
+    ffi_boundary("set_fixed_clock", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_fixed_clock"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+        state.set_fixed_clock((now_ms > 0).then_some(now_ms));
+        response_to_c_string(&AppResponse::Ok(state.now_ms().to_string()))
+    })
+}
+
+/// Previews a sync without applying anything: what would be pushed, pulled
+/// and conflicted.
+///
+/// See [`AppDbState::plan_sync`]. The records to push and pull are also split
+/// into batches within the limits set with [`set_sync_limits`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `manifest_json` - C string with the server changes since the last pull,
+///   e.g. `{"consumer":"sync","remote":{"n1":"h2","n2":null},"sizes":{"n1":2048}}`,
+///   where `null` marks a server deletion, `sizes` optionally gives the size
+///   of the server records and `consumer` defaults to `sync`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::SyncPlan`], e.g.
+/// `{"sequence":7,"push":["n3"],"push_deletes":[],"pull":["n1"],"pull_deletes":[],"conflicts":[],"in_sync":0,`
+/// `"push_batches":[{"ids":["n3"],"bytes":310}],"pull_batches":[{"ids":["n1"],"bytes":2048}],"oversized":[]}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, plan_sync};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let manifest = CString::new(r#"{"remote":{"n1":"h2"}}"#).unwrap();
+/// let plan = plan_sync(db, manifest.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn plan_sync(handle: DbHandle, manifest_json: *const c_char) -> *const c_char {
+    ffi_boundary("plan_sync", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to plan_sync"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(manifest_json, "manifest JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let manifest: SyncManifest = match serde_json::from_str(&json_str) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing sync manifest: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.plan_sync(&manifest) {
+            Ok(plan) => match serde_json::to_string(&plan) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing sync plan: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Sets the size limits of the push and pull batches planned by
+/// [`plan_sync`] and pushed by [`sync_now`], for slow or metered networks.
+///
+/// See [`AppDbState::set_sync_limits`]; `0` means no limit for both.
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `max_batch_bytes` - Maximum payload of a batch, or 0 to put every record
+///   in one batch (the default)
+/// * `max_record_bytes` - Size above which records are left out of the
+///   batches and reported as `oversized`, or 0 for no cap (the default)
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON limits now
+/// in effect, e.g. `{"max_batch_bytes":262144,"max_record_bytes":null}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_sync_limits};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// // On a metered connection
+/// let result = set_sync_limits(db, 256 * 1024, 1024 * 1024);
+/// ```
+#[no_mangle]
+pub extern "C" fn set_sync_limits(handle: DbHandle, max_batch_bytes: u64, max_record_bytes: u64) -> *const c_char {
+    ffi_boundary("set_sync_limits", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_sync_limits"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+        state.set_sync_limits(SyncLimits { max_batch_bytes: Some(max_batch_bytes), max_record_bytes: Some(max_record_bytes) });
+
+        match serde_json::to_string(&state.sync_limits()) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing sync limits: {e:?}"));
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Applies changes pulled from the server, moving those that conflict with
+/// pending local changes to the conflict inbox.
+///
+/// See [`AppDbState::merge_remote`]; list the conflicts with
+/// [`list_conflicts`] and settle them with [`resolve_conflict`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `changes_json` - C string with the server changes, e.g.
+///   `{"consumer":"sync","records":[{"id":"n1","hash":"h2","data":{}}],"deleted":["n2"]}`;
+///   `consumer` defaults to `sync`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::MergeResult`], e.g.
+/// `{"applied":1,"deleted":1,"unchanged":0,"conflicts":["n3"],"resolved":0}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, merge_remote};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let changes = CString::new(r#"{"records":[{"id":"n1","hash":"h2","data":{}}]}"#).unwrap();
+/// let result = merge_remote(db, changes.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn merge_remote(handle: DbHandle, changes_json: *const c_char) -> *const c_char {
+    ffi_boundary("merge_remote", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to merge_remote"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(changes_json, "changes JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let changes: RemoteChanges = match serde_json::from_str(&json_str) {
+            Ok(changes) => changes,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing remote changes: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.merge_remote(&changes) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing merge result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Callback settling a conflict detected by [`apply_remote_changes`], on the
+/// thread that called it.
+///
+/// `conflict_json` is the JSON [`local_db_model::ConflictEntry`] and is only
+/// valid during the call. The callback returns the JSON
+/// [`local_db_model::ConflictResolution`] (`"local"`, `"remote"` or a merged
+/// record), which must stay valid until the callback is called again or
+/// [`apply_remote_changes`] returns, or null to move the conflict to the
+/// inbox. It must not call into the database.
+pub type ConflictCallback = extern "C" fn(conflict_json: *const c_char) -> *const c_char;
+
+/// Applies changes pulled from the server like [`merge_remote`], settling
+/// the conflicts with pending local changes by a policy instead of the
+/// conflict inbox.
+///
+/// See [`AppDbState::apply_remote_changes`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `changes_json` - C string with the server changes, as for [`merge_remote`]
+/// * `policy_json` - C string with the JSON [`local_db_model::ConflictPolicy`]:
+///   `"inbox"`, `"keep_local"`, `"keep_remote"`, `"callback"` or
+///   `{"last_write_wins":"data.updated_at"}`
+/// * `callback` - Callback settling each conflict under the `"callback"`
+///   policy, or null
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::MergeResult`], e.g.
+/// `{"applied":1,"deleted":0,"unchanged":0,"conflicts":[],"resolved":1}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{apply_remote_changes, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let changes = CString::new(r#"{"records":[{"id":"n1","hash":"h2","data":{"updated_at":1736812800000}}]}"#).unwrap();
+/// let policy = CString::new(r#"{"last_write_wins":"data.updated_at"}"#).unwrap();
+/// let result = apply_remote_changes(db, changes.as_ptr(), policy.as_ptr(), None);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn apply_remote_changes(handle: DbHandle, changes_json: *const c_char, policy_json: *const c_char, callback: Option<ConflictCallback>) -> *const c_char {
+    ffi_boundary("apply_remote_changes", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to apply_remote_changes"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(changes_json, "changes JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let changes: RemoteChanges = match serde_json::from_str(&json_str) {
+            Ok(changes) => changes,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing remote changes: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let policy_str = match c_ptr_to_string(policy_json, "conflict policy") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let policy: ConflictPolicy = match serde_json::from_str(&policy_str) {
+            Ok(policy) => policy,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing conflict policy: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+        if policy == ConflictPolicy::Callback && callback.is_none() {
+            let error = AppResponse::BadRequest("The callback conflict policy requires a callback".to_string());
+            return response_to_c_string(&error);
+        }
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        let defer = |conflict: &ConflictEntry| {
+            let Some(callback) = callback else {
+                return Ok(None);
+            };
+            let conflict_json = CString::new(serde_json::to_string(conflict)?)
+                .map_err(|e| AppResponse::SerializationError(format!("Error passing conflict {} to callback: {e}", conflict.id)))?;
+            let resolution = callback(conflict_json.as_ptr());
+            if resolution.is_null() {
+                return Ok(None);
+            }
+            // SAFETY: the callback returns a NUL-terminated string valid until its next call.
+            let resolution = unsafe { CStr::from_ptr(resolution) }.to_string_lossy();
+            serde_json::from_str(&resolution)
+                .map(Some)
+                .map_err(|e| AppResponse::SerializationError(format!("Error parsing resolution of conflict {}: {e}", conflict.id)))
+        };
+
+        match state.apply_remote_changes(&changes, &policy, defer) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing merge result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Turns CRDT mode on or off, merging concurrent edits of a record field by
+/// field instead of reporting a conflict.
+///
+/// See [`AppDbState::set_crdt_mode`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `enabled` - Whether CRDT mode is on
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the mode set,
+/// `"true"` or `"false"`.
+#[no_mangle]
+pub extern "C" fn set_crdt_mode(handle: DbHandle, enabled: bool) -> *const c_char {
+    ffi_boundary("set_crdt_mode", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_crdt_mode"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_crdt_mode(enabled) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(enabled.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Turns record timestamps on or off, stamping `created_at` and
+/// `updated_at` on every record written.
+///
+/// See [`AppDbState::set_record_timestamps`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `enabled` - Whether records are stamped
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the setting,
+/// `"true"` or `"false"`.
+#[no_mangle]
+pub extern "C" fn set_record_timestamps(handle: DbHandle, enabled: bool) -> *const c_char {
+    ffi_boundary("set_record_timestamps", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_record_timestamps"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_record_timestamps(enabled) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(enabled.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves the records written at or after a time, oldest first, a simple
+/// sync primitive for apps not using delta consumers.
+///
+/// See [`AppDbState::get_modified_since`]; record timestamps must be on
+/// (see [`set_record_timestamps`]).
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `since_ms` - Milliseconds since the Unix epoch, e.g. the greatest
+///   `updated_at` received so far
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// the records, ordered by `updated_at`.
+#[no_mangle]
+pub extern "C" fn get_modified_since(handle: DbHandle, since_ms: u64) -> *const c_char {
+    ffi_boundary("get_modified_since", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_modified_since"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_modified_since(since_ms) {
+            Ok(models) => match serde_json::to_string(&models) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Sets how many versions of each record history mode keeps, for undo and
+/// audit features. `0` turns history mode off and drops all history.
+///
+/// See [`AppDbState::set_history_limit`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit` - Number of versions kept of each record, `0` to turn history mode off
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the limit.
+#[no_mangle]
+pub extern "C" fn set_history_limit(handle: DbHandle, limit: u64) -> *const c_char {
+    ffi_boundary("set_history_limit", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_history_limit"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_history_limit(limit) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(limit.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves the kept versions of a record, newest first.
+///
+/// See [`AppDbState::get_history`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// `{"version", "written_at", "record"}` entries, `record` being `null` for
+/// a deletion. The array is empty if the record has no history.
+///
+/// # Safety
+///
+/// The `id` parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_history(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("get_history", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_history"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_history(&id_str) {
+            Ok(entries) => match serde_json::to_string(&entries) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing history: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Writes a version from the history of a record again, as its newest
+/// version.
+///
+/// See [`AppDbState::revert_to_version`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string containing the record ID
+/// * `version` - `version` of an entry returned by [`get_history`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the reverted
+/// record, a `NotFound` response if the history does not hold the version,
+/// or a `BadRequest` response if the version is a deletion.
+///
+/// # Safety
+///
+/// The `id` parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, revert_to_version, set_history_limit};
+///
+/// let db_name = CString::new("test_db").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+/// set_history_limit(db_state, 20);
+///
+/// let id = CString::new("record_1").unwrap();
+/// let result = revert_to_version(db_state, id.as_ptr(), 3);
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn revert_to_version(handle: DbHandle, id: *const c_char, version: u64) -> *const c_char {
+    ffi_boundary("revert_to_version", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to revert_to_version"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.revert_to_version(&id_str, version) {
+            Ok(model) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing reverted model: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the last hybrid logical clock timestamp issued by the database.
+///
+/// See [`AppDbState::last_hlc`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::Hlc`], e.g. `{"physical_ms":1760000000000,"counter":2}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_hlc};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let hlc = get_hlc(db);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_hlc(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_hlc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_hlc"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.last_hlc() {
+            Ok(hlc) => match serde_json::to_string(&hlc) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing HLC: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Returns the hybrid logical clock timestamp of the last write of a record.
+///
+/// See [`AppDbState::record_hlc`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - C string with the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::Hlc`], or `NotFound` if the record has no timestamp.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_record_hlc};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("n1").unwrap();
+/// let hlc = get_record_hlc(db, id.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_record_hlc(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("get_record_hlc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_record_hlc"));
+            return response_to_c_string(&error);
+        };
+
+        let id_str = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.record_hlc(&id_str) {
+            Ok(Some(hlc)) => match serde_json::to_string(&hlc) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing HLC: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No HLC timestamp for id: {id_str}"));
+                response_to_c_string(&error)
+            }
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Advances the database clock past the timestamp of a remote change, so
+/// that every later local write orders after it.
+///
+/// See [`AppDbState::observe_hlc`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `hlc_json` - C string with the JSON [`local_db_model::Hlc`] of the remote change
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::Hlc`] issued for the receipt.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, observe_hlc};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let remote = CString::new(r#"{"physical_ms":1760000000000,"counter":3}"#).unwrap();
+/// let hlc = observe_hlc(db, remote.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn observe_hlc(handle: DbHandle, hlc_json: *const c_char) -> *const c_char {
+    ffi_boundary("observe_hlc", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to observe_hlc"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(hlc_json, "HLC JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let remote: Hlc = match serde_json::from_str(&json_str) {
+            Ok(hlc) => hlc,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing HLC: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.observe_hlc(remote) {
+            Ok(hlc) => match serde_json::to_string(&hlc) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing HLC: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the digest of the records, to compare with the digest of the
+/// server before a sync.
+///
+/// See [`AppDbState::sync_digest`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::SyncDigest`], e.g.
+/// `{"root":"9d2a4c01f3e87b65","records":1200,"buckets":[...]}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_sync_digest};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let digest = get_sync_digest(db);
+/// ```
+#[no_mangle]
+pub extern "C" fn get_sync_digest(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_sync_digest", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_sync_digest"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.sync_digest() {
+            Ok(digest) => match serde_json::to_string(&digest) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing sync digest: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the digest buckets in which the records differ from the digest
+/// of the server.
+///
+/// See [`AppDbState::diff_sync_digest`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `remote_json` - C string with the JSON [`local_db_model::SyncDigest`] of the server
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the array of
+/// divergent buckets, e.g. `[3,141]`, empty when the records are equal.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, diff_sync_digest};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let remote = CString::new(r#"{"root":"9d2a4c01f3e87b65","records":1200,"buckets":[]}"#).unwrap();
+/// let buckets = diff_sync_digest(db, remote.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn diff_sync_digest(handle: DbHandle, remote_json: *const c_char) -> *const c_char {
+    ffi_boundary("diff_sync_digest", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to diff_sync_digest"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(remote_json, "sync digest JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let remote: SyncDigest = match serde_json::from_str(&json_str) {
+            Ok(digest) => digest,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing sync digest: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.diff_sync_digest(&remote) {
+            Ok(buckets) => match serde_json::to_string(&buckets) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing digest buckets: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the hash of each record in the given digest buckets, by ID.
+///
+/// See [`AppDbState::sync_digest_records`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `buckets_json` - C string with the JSON array of buckets, e.g. from [`diff_sync_digest`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an object of
+/// record hashes by ID, e.g. `{"n1":"hash_a","n7":"hash_c"}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, get_sync_digest_records};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let buckets = CString::new("[3,141]").unwrap();
+/// let records = get_sync_digest_records(db, buckets.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_sync_digest_records(handle: DbHandle, buckets_json: *const c_char) -> *const c_char {
+    ffi_boundary("get_sync_digest_records", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_sync_digest_records"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(buckets_json, "buckets JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let buckets: Vec<usize> = match serde_json::from_str(&json_str) {
+            Ok(buckets) => buckets,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing digest buckets: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.sync_digest_records(&buckets) {
+            Ok(records) => match serde_json::to_string(&records) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing record hashes: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Lists the records in the conflict inbox with both of their versions.
+///
+/// See [`AppDbState::list_conflicts`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// [`local_db_model::ConflictEntry`], e.g.
+/// `[{"id":"n3","local":{...},"remote":null,"detected_at":1736812800000}]`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, list_conflicts};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let conflicts = list_conflicts(db);
+/// ```
+#[no_mangle]
+pub extern "C" fn list_conflicts(handle: DbHandle) -> *const c_char {
+    ffi_boundary("list_conflicts", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to list_conflicts"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.list_conflicts() {
+            Ok(conflicts) => match serde_json::to_string(&conflicts) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing conflicts: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Settles the conflict of a record and removes it from the conflict inbox.
+///
+/// See [`AppDbState::resolve_conflict`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - C string with the ID of the record
+/// * `resolution` - C string with `local` to keep the local version,
+///   `remote` to take the server version, or a merged record as JSON, e.g.
+///   `{"id":"n3","hash":"merged","data":{"title":"Both edits"}}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the record now
+/// stored as JSON, or `null` if it is deleted; `NotFound` if the record has
+/// no conflict.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, resolve_conflict};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("n3").unwrap();
+/// let resolution = CString::new("remote").unwrap();
+/// let result = resolve_conflict(db, id.as_ptr(), resolution.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn resolve_conflict(handle: DbHandle, id: *const c_char, resolution: *const c_char) -> *const c_char {
+    ffi_boundary("resolve_conflict", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to resolve_conflict"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let resolution_str = match c_ptr_to_string(resolution, "resolution") {
+            Ok(resolution) => resolution,
+            Err(error_ptr) => return error_ptr,
+        };
+        let resolution = match resolution_str.trim() {
+            "local" => ConflictResolution::Keep(ConflictSide::Local),
+            "remote" => ConflictResolution::Keep(ConflictSide::Remote),
+            json => match serde_json::from_str(json) {
+                Ok(resolution) => resolution,
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!(
+                        "Expected local, remote or a merged record as resolution: {e}"
+                    ));
+                    return response_to_c_string(&error);
+                }
+            },
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.resolve_conflict(&id, &resolution) {
+            Ok(record) => match serde_json::to_string(&record) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing record: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Registers the key sealing the record bodies of sync payloads (requires the
+/// `encryption` feature).
+///
+/// Keys are not persisted; register them after every [`create_db`], for
+/// example from the platform keychain. See [`AppDbState::set_sync_key`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `key_hex` - Null-terminated C string with the 32-byte key, hex-encoded
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or a `BadRequest`
+/// for a malformed key or a build without encryption.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, set_sync_key};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let key = CString::new("07".repeat(32)).unwrap();
+/// let result = set_sync_key(db, key.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_sync_key(handle: DbHandle, key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("set_sync_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_sync_key"));
+            return response_to_c_string(&error);
+        };
+
+        let key_hex = match c_ptr_to_string(key_hex, "key") {
+            Ok(key_hex) => key_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let Some(key) = signing::decode_hex(&key_hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            let error = AppResponse::BadRequest("Sync key must be 32 hex-encoded bytes".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_sync_key(&key) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok("Sync key set".to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Forgets the sync key, wiping it from memory.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if a key
+/// was registered, `false` otherwise.
+#[no_mangle]
+pub extern "C" fn remove_sync_key(handle: DbHandle) -> *const c_char {
+    ffi_boundary("remove_sync_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to remove_sync_key"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        response_to_c_string(&AppResponse::Ok(state.remove_sync_key().to_string()))
+    })
+}
+
+/// Returns the sync key encrypted with a wrapping key, for handing it to
+/// another device of the user. See [`AppDbState::wrap_sync_key`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `wrapping_key_hex` - Null-terminated C string with the 32-byte wrapping
+///   key, hex-encoded, e.g. derived from a recovery passphrase
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the wrapped key,
+/// hex-encoded, or a `BadRequest` if no sync key is registered.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn wrap_sync_key(handle: DbHandle, wrapping_key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("wrap_sync_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to wrap_sync_key"));
+            return response_to_c_string(&error);
+        };
+
+        let wrapping_key_hex = match c_ptr_to_string(wrapping_key_hex, "wrapping key") {
+            Ok(wrapping_key_hex) => wrapping_key_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let Some(wrapping_key) = signing::decode_hex(&wrapping_key_hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            let error = AppResponse::BadRequest("Wrapping key must be 32 hex-encoded bytes".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.wrap_sync_key(&wrapping_key) {
+            Ok(wrapped) => response_to_c_string(&AppResponse::Ok(wrapped)),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Registers a sync key wrapped with [`wrap_sync_key`] on another device.
+/// See [`AppDbState::set_wrapped_sync_key`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `wrapped_hex` - Null-terminated C string with the wrapped key
+/// * `wrapping_key_hex` - Null-terminated C string with the 32-byte wrapping
+///   key, hex-encoded
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or a
+/// `SerializationError` if the wrapping key is wrong.
+///
+/// # Safety
+///
+/// The string parameters must be valid pointers.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_wrapped_sync_key(handle: DbHandle, wrapped_hex: *const c_char, wrapping_key_hex: *const c_char) -> *const c_char {
+    ffi_boundary("set_wrapped_sync_key", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_wrapped_sync_key"));
+            return response_to_c_string(&error);
+        };
+
+        let wrapped_hex = match c_ptr_to_string(wrapped_hex, "wrapped key") {
+            Ok(wrapped_hex) => wrapped_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let wrapping_key_hex = match c_ptr_to_string(wrapping_key_hex, "wrapping key") {
+            Ok(wrapping_key_hex) => wrapping_key_hex,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let Some(wrapping_key) = signing::decode_hex(&wrapping_key_hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            let error = AppResponse::BadRequest("Wrapping key must be 32 hex-encoded bytes".to_string());
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_wrapped_sync_key(&wrapped_hex, &wrapping_key) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok("Sync key set".to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Seals the bodies of records with the sync key, for upload. See
+/// [`AppDbState::seal_sync_records`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `records_json` - Null-terminated C string with a JSON array of records,
+///   e.g. the `records` of [`get_all_delta`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the array of
+/// sealed records, e.g. `[{"id":"n1","hash":"h2","data":{"$sealed":"..."}}]`,
+/// or a `BadRequest` if no sync key is registered.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ffi::CString;
+/// use offline_first_core::{create_db, seal_sync_records};
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let records = CString::new(r#"[{"id":"n1","hash":"h2","data":{"title":"Draft"}}]"#).unwrap();
+/// let upload = seal_sync_records(db, records.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn seal_sync_records(handle: DbHandle, records_json: *const c_char) -> *const c_char {
+    ffi_boundary("seal_sync_records", || {
+        sync_records(handle, records_json, "seal_sync_records", AppDbState::seal_sync_records)
+    })
+}
+
+/// Decrypts the sealed bodies of downloaded records; records that are not
+/// sealed are returned as they are. See [`AppDbState::open_sync_records`].
+/// [`merge_remote`] decrypts sealed records itself.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `records_json` - Null-terminated C string with a JSON array of records
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the array of
+/// decrypted records, or a `SerializationError` if a body was sealed with
+/// another key or tampered with.
+///
+/// # Safety
+///
+/// The string parameter must be a valid pointer.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn open_sync_records(handle: DbHandle, records_json: *const c_char) -> *const c_char {
+    ffi_boundary("open_sync_records", || {
+        sync_records(handle, records_json, "open_sync_records", AppDbState::open_sync_records)
+    })
+}
+
+/// Shared body of [`seal_sync_records`] and [`open_sync_records`].
+fn sync_records(
+    handle: DbHandle,
+    records_json: *const c_char,
+    name: &str,
+    transform: fn(&AppDbState, &[LocalDbModel]) -> Result<Vec<LocalDbModel>, AppResponse>,
+) -> *const c_char {
+    let Some(db) = registry::get(handle) else {
+        let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to {name}"));
+        return response_to_c_string(&error);
+    };
+
+    let json_str = match c_ptr_to_string(records_json, "records JSON") {
+        Ok(json) => json,
+        Err(error_ptr) => return error_ptr,
+    };
+    let records: Vec<LocalDbModel> = match serde_json::from_str(&json_str) {
+        Ok(records) => records,
+        Err(e) => {
+            let error = AppResponse::SerializationError(format!("Error parsing records: {e}"));
+            return response_to_c_string(&error);
+        }
+    };
+
+    let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+    match transform(&state, &records) {
+        Ok(records) => match serde_json::to_string(&records) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing records: {e:?}"));
+                response_to_c_string(&error)
+            }
+        },
+        Err(e) => response_to_c_string(&e),
+    }
+}
+
+/// Stores an attachment, such as a photo of a record, pending upload.
+///
+/// See [`AppDbState::put_attachment`]; send the returned manifest to the
+/// server, then each chunk read with [`read_attachment_chunk`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+/// * `bytes` - Content of the attachment
+/// * `len` - Number of bytes at `bytes`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::AttachmentManifest`], e.g.
+/// `{"id":"photo_1","bytes":300000,"chunk_size":262144,"checksums":[2874923104,105231778]}`.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes, or be null with `len` 0.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, put_attachment};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let id = CString::new("photo_1").unwrap();
+/// let photo = std::fs::read("photo.jpg").unwrap();
+/// let manifest = put_attachment(db, id.as_ptr(), photo.as_ptr(), photo.len());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn put_attachment(handle: DbHandle, id: *const c_char, bytes: *const u8, len: usize) -> *const c_char {
+    ffi_boundary("put_attachment", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to put_attachment"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let bytes = match c_ptr_to_bytes(bytes, len, "attachment") {
+            Ok(bytes) => bytes,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.put_attachment(&id, bytes) {
+            Ok(manifest) => match serde_json::to_string(&manifest) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing attachment manifest: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Copies a stored or fully downloaded attachment into a buffer of the
+/// caller. See [`AppDbState::get_attachment`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+/// * `out` - Buffer receiving the content, at least the `bytes` of the
+///   manifest long
+/// * `capacity` - Number of bytes available at `out`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// bytes copied; `NotFound` if there is no such attachment, or `BadRequest`
+/// if it is still downloading or `out` is too small.
+///
+/// # Safety
+///
+/// `out` must point to `capacity` writable bytes.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_attachment(handle: DbHandle, id: *const c_char, out: *mut u8, capacity: usize) -> *const c_char {
+    ffi_boundary("get_attachment", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_attachment"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.get_attachment(&id) {
+            Ok(Some(bytes)) => copy_to_c_buffer(&bytes, out, capacity),
+            Ok(None) => response_to_c_string(&AppResponse::NotFound(format!("Attachment {id} not found"))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes an attachment and cancels its transfer.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `true` if the
+/// attachment existed, `false` otherwise.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn delete_attachment(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("delete_attachment", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to delete_attachment"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.delete_attachment(&id) {
+            Ok(existed) => response_to_c_string(&AppResponse::Ok(existed.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Copies one chunk of an attachment into a buffer of the caller, for
+/// upload. See [`AppDbState::read_attachment_chunk`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+/// * `index` - Index of the chunk
+/// * `out` - Buffer receiving the chunk, at least `chunk_size` long
+/// * `capacity` - Number of bytes available at `out`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// bytes copied, or `NotFound` if the chunk is not stored.
+///
+/// # Safety
+///
+/// `out` must point to `capacity` writable bytes.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn read_attachment_chunk(handle: DbHandle, id: *const c_char, index: u32, out: *mut u8, capacity: usize) -> *const c_char {
+    ffi_boundary("read_attachment_chunk", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to read_attachment_chunk"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.read_attachment_chunk(&id, index) {
+            Ok(chunk) => copy_to_c_buffer(&chunk, out, capacity),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Records that the server has received one chunk of an upload. See
+/// [`AppDbState::ack_attachment_chunk`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+/// * `index` - Index of the chunk
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::AttachmentProgress`] of the upload.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ack_attachment_chunk(handle: DbHandle, id: *const c_char, index: u32) -> *const c_char {
+    ffi_boundary("ack_attachment_chunk", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to ack_attachment_chunk"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        attachment_progress_response(state.ack_attachment_chunk(&id, index))
+    })
+}
+
+/// Registers an attachment to download from its server manifest, keeping
+/// the chunks already received when the same manifest is registered again.
+/// See [`AppDbState::begin_attachment_download`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `manifest_json` - Null-terminated C string with the
+///   [`local_db_model::AttachmentManifest`] from the server
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::AttachmentProgress`] of the download, whose `missing`
+/// chunks are the ones to request.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn begin_attachment_download(handle: DbHandle, manifest_json: *const c_char) -> *const c_char {
+    ffi_boundary("begin_attachment_download", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to begin_attachment_download"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(manifest_json, "manifest JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let manifest: AttachmentManifest = match serde_json::from_str(&json_str) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing attachment manifest: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        attachment_progress_response(state.begin_attachment_download(&manifest))
+    })
+}
+
+/// Stores one downloaded chunk of an attachment after checking its size and
+/// checksum. See [`AppDbState::write_attachment_chunk`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+/// * `index` - Index of the chunk
+/// * `bytes` - Content of the chunk
+/// * `len` - Number of bytes at `bytes`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::AttachmentProgress`] of the download, or a `BadRequest`
+/// if the chunk does not match the manifest and must be requested again.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes, or be null with `len` 0.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn write_attachment_chunk(handle: DbHandle, id: *const c_char, index: u32, bytes: *const u8, len: usize) -> *const c_char {
+    ffi_boundary("write_attachment_chunk", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to write_attachment_chunk"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+        let bytes = match c_ptr_to_bytes(bytes, len, "chunk") {
+            Ok(bytes) => bytes,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        attachment_progress_response(state.write_attachment_chunk(&id, index, bytes))
+    })
+}
+
+/// Returns the progress of the transfer of an attachment. See
+/// [`AppDbState::attachment_progress`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `id` - Null-terminated C string with the ID of the attachment
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::AttachmentProgress`], or `NotFound` if there is no such
+/// attachment.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_attachment_progress(handle: DbHandle, id: *const c_char) -> *const c_char {
+    ffi_boundary("get_attachment_progress", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_attachment_progress"));
+            return response_to_c_string(&error);
+        };
+
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.attachment_progress(&id) {
+            Ok(Some(progress)) => attachment_progress_response(Ok(progress)),
+            Ok(None) => response_to_c_string(&AppResponse::NotFound(format!("Attachment {id} not found"))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Lists the unfinished attachment uploads and downloads, for the sync layer
+/// to resume. See [`AppDbState::pending_attachments`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// [`local_db_model::AttachmentProgress`].
+#[no_mangle]
+pub extern "C" fn get_pending_attachments(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_pending_attachments", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_pending_attachments"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.pending_attachments() {
+            Ok(pending) => match serde_json::to_string(&pending) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing pending attachments: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Callback receiving the progress of an attachment transfer after each
+/// chunk, on the thread that transferred it.
+///
+/// `id` is only valid during the call.
+pub type AttachmentProgressCallback = extern "C" fn(id: *const c_char, bytes_done: u64, bytes_total: u64);
+
+/// Sets the function called after each chunk acknowledged with
+/// [`ack_attachment_chunk`] or written with [`write_attachment_chunk`], e.g.
+/// to drive a progress bar, or removes it with a null callback. See
+/// [`AppDbState::set_attachment_progress_callback`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `callback` - Function to call, or null
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response.
+#[no_mangle]
+pub extern "C" fn set_attachment_progress_callback(handle: DbHandle, callback: Option<AttachmentProgressCallback>) -> *const c_char {
+    ffi_boundary("set_attachment_progress_callback", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_attachment_progress_callback"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        state.set_attachment_progress_callback(callback.map(|callback| {
+            move |progress: &AttachmentProgress| {
+                let Ok(id) = CString::new(progress.manifest.id.as_str()) else {
+                    return;
+                };
+                callback(id.as_ptr(), progress.bytes_done, progress.manifest.bytes);
+            }
+        }));
+        response_to_c_string(&AppResponse::Ok("Attachment progress callback set".to_string()))
+    })
+}
+
+/// Shared response of the functions returning the progress of a transfer.
+fn attachment_progress_response(result: Result<AttachmentProgress, AppResponse>) -> *const c_char {
+    match result {
+        Ok(progress) => match serde_json::to_string(&progress) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing attachment progress: {e:?}"));
+                response_to_c_string(&error)
+            }
+        },
+        Err(e) => response_to_c_string(&e),
+    }
+}
+
+/// Copies `bytes` into the caller's buffer `out` of `capacity` bytes,
+/// returning the number of bytes copied.
+fn copy_to_c_buffer(bytes: &[u8], out: *mut u8, capacity: usize) -> *const c_char {
+    if out.is_null() {
+        return response_to_c_string(&AppResponse::BadRequest("Null output buffer pointer".to_string()));
+    }
+    if bytes.len() > capacity {
+        let error = AppResponse::BadRequest(format!("Output buffer of {capacity} bytes is too small for {} bytes", bytes.len()));
         return response_to_c_string(&error);
     }
+    // SAFETY: the caller guarantees `out` points to `capacity` writable bytes,
+    // and `bytes` fits in them.
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    response_to_c_string(&AppResponse::Ok(bytes.len().to_string()))
+}
+
+/// Flushes committed transactions to disk, for databases opened with
+/// `no_sync` or `no_meta_sync` through [`create_db_with_config`], e.g. after
+/// a bulk import. See [`AppDbState::flush`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response, or an error
+/// response if the sync fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db_with_config, flush_database};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("catalog").unwrap();
+/// let options = CString::new(r#"{"no_sync":true}"#).unwrap();
+/// let db = create_db_with_config(db_name.as_ptr(), options.as_ptr());
+///
+/// // Bulk import...
+/// flush_database(db);
+/// ```
+#[no_mangle]
+pub extern "C" fn flush_database(handle: DbHandle) -> *const c_char {
+    ffi_boundary("flush_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to flush_database"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.flush() {
+            Ok(()) => response_to_c_string(&AppResponse::Ok("Database flushed".to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Starts a sync run, finishing one still open as interrupted.
+///
+/// See [`AppDbState::begin_sync_run`]; finish it with [`finish_sync_run`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `consumer` - C string with the delta consumer the sync layer
+///   acknowledges after each push, usually `sync`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the started
+/// [`local_db_model::SyncRun`].
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn begin_sync_run(handle: DbHandle, consumer: *const c_char) -> *const c_char {
+    ffi_boundary("begin_sync_run", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to begin_sync_run"));
+            return response_to_c_string(&error);
+        };
+
+        let consumer = match c_ptr_to_string(consumer, "consumer") {
+            Ok(consumer) => consumer,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.begin_sync_run(&consumer) {
+            Ok(run) => sync_run_response(&run),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Finishes the open sync run with what the sync layer counted.
+///
+/// See [`AppDbState::finish_sync_run`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `report_json` - C string with a [`local_db_model::SyncRunReport`], e.g.
+///   `{"failed":["n7"],"error":null}`; missing fields default to none
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the finished
+/// [`local_db_model::SyncRun`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{begin_sync_run, create_db, finish_sync_run};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let consumer = CString::new("sync").unwrap();
+/// begin_sync_run(db, consumer.as_ptr());
+/// // Push, pull and merge...
+/// let report = CString::new(r#"{"error":"Server unavailable"}"#).unwrap();
+/// let result = finish_sync_run(db, report.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn finish_sync_run(handle: DbHandle, report_json: *const c_char) -> *const c_char {
+    ffi_boundary("finish_sync_run", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to finish_sync_run"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(report_json, "report JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let report: SyncRunReport = match serde_json::from_str(&json_str) {
+            Ok(report) => report,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing sync run report: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.finish_sync_run(&report) {
+            Ok(run) => sync_run_response(&run),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the sync run in progress, the last finished run and the time of
+/// the last successful one.
+///
+/// See [`AppDbState::sync_status`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::SyncStatus`], e.g.
+/// `{"running":null,"last_run":{"id":3,...},"last_success_at":1700000000000}`.
+#[no_mangle]
+pub extern "C" fn get_sync_status(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_sync_status", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_sync_status"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.sync_status() {
+            Ok(status) => match serde_json::to_string(&status) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing sync status: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the latest sync runs, newest first.
+///
+/// See [`AppDbState::sync_history`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit` - Maximum number of runs to return
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// [`local_db_model::SyncRun`].
+#[no_mangle]
+pub extern "C" fn get_sync_history(handle: DbHandle, limit: u32) -> *const c_char {
+    ffi_boundary("get_sync_history", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_sync_history"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.sync_history(limit as usize) {
+            Ok(runs) => match serde_json::to_string(&runs) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing sync history: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+fn sync_run_response(run: &SyncRun) -> *const c_char {
+    match serde_json::to_string(run) {
+        Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+        Err(e) => {
+            let error = AppResponse::SerializationError(format!("Error serializing sync run: {e:?}"));
+            response_to_c_string(&error)
+        }
+    }
+}
+
+/// Runs a sync against an HTTP sync server: pulls and merges the server
+/// changes, then pushes the local ones.
+///
+/// See [`AppDbState::sync_now`] and [`http_sync::HttpSyncAdapter`]. Blocks
+/// until the sync finishes, so call it off the UI thread.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `endpoint_config_json` - C string with the JSON
+///   [`local_db_model::SyncEndpointConfig`], e.g.
+///   `{"url":"https://api.example.com/sync","headers":{"Authorization":"Bearer ..."}}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the finished
+/// [`local_db_model::SyncRun`], or `BadRequest` if this build lacks the
+/// `http-sync` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, sync_now};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let config = CString::new(r#"{"url":"https://api.example.com/sync","conflict_policy":"keep_remote"}"#).unwrap();
+/// let run = sync_now(db, config.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn sync_now(handle: DbHandle, endpoint_config_json: *const c_char) -> *const c_char {
+    ffi_boundary("sync_now", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to sync_now"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(endpoint_config_json, "endpoint config JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let config: SyncEndpointConfig = match serde_json::from_str(&json_str) {
+            Ok(config) => config,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing sync endpoint config: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match sync_over_http(&state, config) {
+            Ok(run) => sync_run_response(&run),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+#[cfg(feature = "http-sync")]
+fn sync_over_http(state: &AppDbState, config: SyncEndpointConfig) -> Result<SyncRun, AppResponse> {
+    state.sync_now(&mut http_sync::HttpSyncAdapter::new(config)?)
+}
+
+#[cfg(not(feature = "http-sync"))]
+fn sync_over_http(_state: &AppDbState, _config: SyncEndpointConfig) -> Result<SyncRun, AppResponse> {
+    Err(AppResponse::BadRequest("HTTP sync is not supported by this build".to_string()))
+}
+
+/// Appends a pending remote mutation to the outbox, to replay once online.
+///
+/// See [`AppDbState::enqueue_outbox`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `request_json` - C string with the request, any JSON value but `null`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::OutboxEntry`] enqueued.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, enqueue_outbox};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("chat").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let request = CString::new(r#"{"method":"POST","path":"/messages","body":{"text":"Hi"}}"#).unwrap();
+/// let entry = enqueue_outbox(db, request.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn enqueue_outbox(handle: DbHandle, request_json: *const c_char) -> *const c_char {
+    ffi_boundary("enqueue_outbox", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to enqueue_outbox"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(request_json, "request JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let request: serde_json::Value = match serde_json::from_str(&json_str) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing outbox request: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.enqueue_outbox(&request) {
+            Ok(entry) => match serde_json::to_string(&entry) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing outbox entry: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the oldest pending outbox entries without removing them.
+///
+/// See [`AppDbState::peek_outbox`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `limit` - Maximum number of entries to return
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// [`local_db_model::OutboxEntry`], oldest first.
+#[no_mangle]
+pub extern "C" fn peek_outbox(handle: DbHandle, limit: u32) -> *const c_char {
+    ffi_boundary("peek_outbox", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to peek_outbox"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.peek_outbox(limit as usize) {
+            Ok(entries) => match serde_json::to_string(&entries) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing outbox entries: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes the outbox entries up to `sequence` once the server accepted
+/// them.
+///
+/// See [`AppDbState::ack_outbox`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `sequence` - The `sequence` of the last entry accepted
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// entries removed.
+#[no_mangle]
+pub extern "C" fn ack_outbox(handle: DbHandle, sequence: u64) -> *const c_char {
+    ffi_boundary("ack_outbox", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to ack_outbox"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.ack_outbox(sequence) {
+            Ok(removed) => response_to_c_string(&AppResponse::Ok(removed.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Replays a multi-device sync simulation and returns its report.
+///
+/// Each device of the log is a database in a temporary directory, removed
+/// afterwards; see [`simulation::Simulation::run`]. Meant for integration
+/// tests of an app's sync and conflict handling.
+///
+/// # Parameters
+///
+/// * `log_json` - C string with a [`simulation::SimulationLog`], e.g.
+///   `{"ops":[{"op":"put","device":"phone","record":{...}},{"op":"sync","device":"phone"}],"settle":true}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`simulation::SimulationReport`], or `BadRequest` if this build lacks the
+/// `simulation` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::run_simulation;
+/// use std::ffi::CString;
+///
+/// let log = CString::new(r#"{"ops":[
+///     {"op":"put","device":"phone","record":{"id":"n1","hash":"a","data":{}}},
+///     {"op":"put","device":"tablet","record":{"id":"n1","hash":"b","data":{}}},
+///     {"op":"sync","device":"phone"},
+///     {"op":"sync","device":"tablet"}
+/// ]}"#).unwrap();
+/// let result = run_simulation(log.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn run_simulation(log_json: *const c_char) -> *const c_char {
+    ffi_boundary("run_simulation", || {
+        let json_str = match c_ptr_to_string(log_json, "simulation log JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        match replay_simulation(&json_str) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+#[cfg(feature = "simulation")]
+fn replay_simulation(json: &str) -> Result<String, AppResponse> {
+    let log: simulation::SimulationLog = serde_json::from_str(json)
+        .map_err(|e| AppResponse::SerializationError(format!("Error parsing simulation log: {e}")))?;
+    let report = simulation::Simulation::in_temp_dir().run(&log)?;
+    serde_json::to_string(&report).map_err(|e| AppResponse::SerializationError(format!("Error serializing simulation report: {e:?}")))
+}
+
+#[cfg(not(feature = "simulation"))]
+fn replay_simulation(_json: &str) -> Result<String, AppResponse> {
+    Err(AppResponse::BadRequest("Simulation is not supported by this build".to_string()))
+}
+
+/// Inserts or replaces a record in a named collection, creating the
+/// collection when missing.
+///
+/// See [`AppDbState::collection_put`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `collection` - C string with the collection name
+/// * `json_ptr` - C string with the record, e.g. `{"id":"o1","hash":"h1","data":{}}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the stored record.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{collection_put, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("shop").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let collection = CString::new("orders").unwrap();
+/// let order = CString::new(r#"{"id":"o1","hash":"h1","data":{"total":42}}"#).unwrap();
+/// let result = collection_put(db, collection.as_ptr(), order.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn collection_put(handle: DbHandle, collection: *const c_char, json_ptr: *const c_char) -> *const c_char {
+    ffi_boundary("collection_put", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to collection_put"));
+            return response_to_c_string(&error);
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+        let json_str = match c_ptr_to_string(json_ptr, "JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let model: LocalDbModel = match serde_json::from_str(&json_str) {
+            Ok(model) => model,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing JSON: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.collection_put(&collection, &model) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(json_str)),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves a record of a named collection by ID.
+///
+/// See [`AppDbState::collection_get`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `collection` - C string with the collection name
+/// * `id` - C string with the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the record, or a
+/// `NotFound` response if the record or the collection does not exist.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn collection_get_by_id(handle: DbHandle, collection: *const c_char, id: *const c_char) -> *const c_char {
+    ffi_boundary("collection_get_by_id", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to collection_get_by_id"));
+            return response_to_c_string(&error);
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.collection_get(&collection, &id) {
+            Ok(Some(model)) => match serde_json::to_string(&model) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Ok(None) => {
+                let error = AppResponse::NotFound(format!("No model found with id {id} in collection {collection}"));
+                response_to_c_string(&error)
+            }
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Retrieves every record of a named collection, in ID order.
+///
+/// See [`AppDbState::collection_get_all`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `collection` - C string with the collection name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// records, empty if the collection does not exist.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn collection_get_all(handle: DbHandle, collection: *const c_char) -> *const c_char {
+    ffi_boundary("collection_get_all", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to collection_get_all"));
+            return response_to_c_string(&error);
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.collection_get_all(&collection) {
+            Ok(models) => match serde_json::to_string(&models) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing to JSON: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes a record of a named collection.
+///
+/// See [`AppDbState::collection_delete`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `collection` - C string with the collection name
+/// * `id` - C string with the record ID
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response if the record was
+/// deleted, or a `NotFound` response if it did not exist.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn collection_delete_by_id(handle: DbHandle, collection: *const c_char, id: *const c_char) -> *const c_char {
+    ffi_boundary("collection_delete_by_id", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to collection_delete_by_id"));
+            return response_to_c_string(&error);
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+        let id = match c_ptr_to_string(id, "id") {
+            Ok(id) => id,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.collection_delete(&collection, &id) {
+            Ok(true) => response_to_c_string(&AppResponse::Ok("Record deleted successfully".to_string())),
+            Ok(false) => {
+                let error = AppResponse::NotFound(format!("No record found with id {id} in collection {collection}"));
+                response_to_c_string(&error)
+            }
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Lists the named collections of a database.
+///
+/// See [`AppDbState::collections`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is an array of
+/// collection names, e.g. `["orders","products"]`.
+#[no_mangle]
+pub extern "C" fn list_collections(handle: DbHandle) -> *const c_char {
+    ffi_boundary("list_collections", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to list_collections"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.collections() {
+            Ok(names) => match serde_json::to_string(&names) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing collections: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Removes a named collection and all of its records at once, with LMDB's
+/// `mdb_drop` in one write transaction.
+///
+/// See [`AppDbState::drop_collection`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `collection` - C string with the collection name
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// collection existed, `"false"` otherwise.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, drop_collection};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("shop").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let collection = CString::new("orders").unwrap();
+/// let result = drop_collection(db, collection.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn drop_collection(handle: DbHandle, collection: *const c_char) -> *const c_char {
+    ffi_boundary("drop_collection", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to drop_collection"));
+            return response_to_c_string(&error);
+        };
+
+        let collection = match c_ptr_to_string(collection, "collection") {
+            Ok(collection) => collection,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.drop_collection(&collection) {
+            Ok(existed) => response_to_c_string(&AppResponse::Ok(existed.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Applies writes to `main` and named collections in one atomic write
+/// transaction.
+///
+/// See [`AppDbState::write_transaction`]; nothing is committed if a write
+/// fails.
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `ops_json` - C string with a JSON array of [`local_db_model::WriteOp`],
+///   e.g. `[{"op":"put","collection":"orders","record":{...}},{"op":"increment","collection":"counters","id":"orders","path":"data.count"}]`;
+///   writes without `collection` go to `main`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a
+/// [`local_db_model::TransactionResult`], e.g.
+/// `{"written":2,"deleted":0,"not_found":[]}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, write_transaction};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("shop").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let ops = CString::new(r#"[
+///     {"op":"put","collection":"orders","record":{"id":"o1","hash":"h1","data":{"total":42}}},
+///     {"op":"delete","id":"cart:o1"},
+///     {"op":"increment","collection":"counters","id":"orders","path":"data.count"}
+/// ]"#).unwrap();
+/// let result = write_transaction(db, ops.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn write_transaction(handle: DbHandle, ops_json: *const c_char) -> *const c_char {
+    ffi_boundary("write_transaction", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to write_transaction"));
+            return response_to_c_string(&error);
+        };
+
+        let json_str = match c_ptr_to_string(ops_json, "operations JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let ops: Vec<WriteOp> = match serde_json::from_str(&json_str) {
+            Ok(ops) => ops,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing write operations: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.write_transaction(&ops) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing transaction result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Counts the records of a key namespace without decoding them.
+///
+/// See [`AppDbState::count_namespace`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `namespace` - C string with the namespace, e.g. `user` for keys such as
+///   `user:123`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the count.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn count_namespace(handle: DbHandle, namespace: *const c_char) -> *const c_char {
+    ffi_boundary("count_namespace", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to count_namespace"));
+            return response_to_c_string(&error);
+        };
+
+        let namespace = match c_ptr_to_string(namespace, "namespace") {
+            Ok(namespace) => namespace,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.count_namespace(&namespace) {
+            Ok(count) => response_to_c_string(&AppResponse::Ok(count.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes every record of a key namespace in one write transaction.
+///
+/// See [`AppDbState::clear_namespace`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `namespace` - C string with the namespace, e.g. `feed` for keys such as
+///   `feed:42`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the number of
+/// deleted records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{clear_namespace, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("app").unwrap();
+/// let db = create_db(db_name.as_ptr());
+///
+/// let namespace = CString::new("feed").unwrap();
+/// let result = clear_namespace(db, namespace.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn clear_namespace(handle: DbHandle, namespace: *const c_char) -> *const c_char {
+    ffi_boundary("clear_namespace", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to clear_namespace"));
+            return response_to_c_string(&error);
+        };
+
+        let namespace = match c_ptr_to_string(namespace, "namespace") {
+            Ok(namespace) => namespace,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.clear_namespace(&namespace) {
+            Ok(deleted) => response_to_c_string(&AppResponse::Ok(deleted.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the schema version the records were migrated to.
+///
+/// See [`AppDbState::schema_version`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the version,
+/// e.g. `"3"`, `"0"` when no migration ran.
+#[no_mangle]
+pub extern "C" fn get_schema_version(handle: DbHandle) -> *const c_char {
+    ffi_boundary("get_schema_version", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_schema_version"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.schema_version() {
+            Ok(version) => response_to_c_string(&AppResponse::Ok(version.to_string())),
+            Err(e) => {
+                let error = AppResponse::from(e);
+                response_to_c_string(&error)
+            }
+        }
+    })
+}
+
+/// Callback migrating a record to the next schema version, on the thread
+/// that called [`migrate`].
+///
+/// `record_json` is the JSON of the record and is only valid during the
+/// call. The callback returns the JSON of the migrated record, which must
+/// stay valid until the callback is called again or [`migrate`] returns,
+/// `record_json` itself to keep the record unchanged, or null to delete it.
+pub type MigrationCallback = extern "C" fn(from_version: u64, record_json: *const c_char) -> *const c_char;
+
+/// Registers the step migrating records from `from_version` to
+/// `from_version + 1`, or removes it with a null callback. See
+/// [`AppDbState::register_migration`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `from_version` - Schema version the step migrates from
+/// * `callback` - Function transforming each record, or null
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response.
+#[no_mangle]
+pub extern "C" fn register_migration(handle: DbHandle, from_version: u64, callback: Option<MigrationCallback>) -> *const c_char {
+    ffi_boundary("register_migration", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to register_migration"));
+            return response_to_c_string(&error);
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(callback) = callback else {
+            state.migrations.remove(&from_version);
+            return response_to_c_string(&AppResponse::Ok(format!("Migration from schema version {from_version} removed")));
+        };
+        state.register_migration(from_version, move |model| {
+            let record = CString::new(serde_json::to_string(&model)?)
+                .map_err(|e| AppResponse::SerializationError(format!("Error passing record {} to migration: {e}", model.id)))?;
+            let migrated = callback(from_version, record.as_ptr());
+            if migrated.is_null() {
+                return Ok(None);
+            }
+            // SAFETY: the callback returns a NUL-terminated string valid until its next call.
+            let migrated = unsafe { CStr::from_ptr(migrated) }.to_string_lossy();
+            serde_json::from_str(&migrated)
+                .map(Some)
+                .map_err(|e| AppResponse::SerializationError(format!("Error parsing record {} returned by migration: {e}", model.id)))
+        });
+        response_to_c_string(&AppResponse::Ok(format!("Migration from schema version {from_version} registered")))
+    })
+}
+
+/// Migrates the records to `target_version` with the steps registered
+/// with [`register_migration`], in batched write transactions.
+///
+/// See [`AppDbState::migrate`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `target_version` - Schema version to migrate to
+/// * `batch_size` - Maximum number of records per write transaction
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the final
+/// [`local_db_model::MigrationProgress`], e.g.
+/// `{"schema_version":2,"scanned":1500,"updated":1480,"deleted":20}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, migrate, register_migration};
+/// use std::ffi::c_char;
+///
+/// extern "C" fn keep(_from_version: u64, record_json: *const c_char) -> *const c_char {
+///     record_json
+/// }
+///
+/// let db_name = std::ffi::CString::new("tasks").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// register_migration(db_state, 0, Some(keep));
+/// let result = migrate(db_state, 1, 500);
+/// ```
+#[no_mangle]
+pub extern "C" fn migrate(handle: DbHandle, target_version: u64, batch_size: u32) -> *const c_char {
+    ffi_boundary("migrate", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to migrate"));
+            return response_to_c_string(&error);
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.migrate(target_version, batch_size as usize, |_| {}) {
+            Ok(done) => match serde_json::to_string(&done) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing migration progress: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Returns the value the app stored under a metadata key.
+///
+/// See [`AppDbState::meta_value`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `key` - Metadata key, e.g. `sync_cursor`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the JSON of the
+/// value, or a `NotFound` response if the key was never set.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_meta_value(handle: DbHandle, key: *const c_char) -> *const c_char {
+    ffi_boundary("get_meta_value", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to get_meta_value"));
+            return response_to_c_string(&error);
+        };
+
+        let key = match c_ptr_to_string(key, "metadata key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.meta_value::<serde_json::Value>(&key) {
+            Ok(Some(value)) => response_to_c_string(&AppResponse::Ok(value.to_string())),
+            Ok(None) => response_to_c_string(&AppResponse::NotFound(format!("No metadata found with key: {key}"))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Stores a JSON value under a metadata key, replacing the previous value,
+/// e.g. a sync checkpoint that must not show up among the records.
+///
+/// See [`AppDbState::set_meta_value`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `key` - Metadata key, e.g. `sync_cursor`
+/// * `value_json` - Any JSON value, e.g. `"2024-05-01T10:00:00Z"` or `{"page":3}`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string with an `Ok` response.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, set_meta_value};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let key = CString::new("sync_cursor").unwrap();
+/// let value = CString::new(r#""2024-05-01T10:00:00Z""#).unwrap();
+/// let result = set_meta_value(db_state, key.as_ptr(), value.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn set_meta_value(handle: DbHandle, key: *const c_char, value_json: *const c_char) -> *const c_char {
+    ffi_boundary("set_meta_value", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to set_meta_value"));
+            return response_to_c_string(&error);
+        };
+
+        let key = match c_ptr_to_string(key, "metadata key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
+        let json_str = match c_ptr_to_string(value_json, "metadata value JSON") {
+            Ok(json) => json,
+            Err(error_ptr) => return error_ptr,
+        };
+        let value: serde_json::Value = match serde_json::from_str(&json_str) {
+            Ok(value) => value,
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error parsing metadata value: {e}"));
+                return response_to_c_string(&error);
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.set_meta_value(&key, &value) {
+            Ok(()) => response_to_c_string(&AppResponse::Ok(format!("Metadata {key} set"))),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
 
-    if name_ptr.is_null() {
-        let error = AppResponse::BadRequest("Null name pointer passed to reset_database".to_string());
-        return response_to_c_string(&error);
-    }
+/// Removes the value stored under a metadata key.
+///
+/// See [`AppDbState::remove_meta_value`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `key` - Metadata key
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// key existed, `"false"` otherwise.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn remove_meta_value(handle: DbHandle, key: *const c_char) -> *const c_char {
+    ffi_boundary("remove_meta_value", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to remove_meta_value"));
+            return response_to_c_string(&error);
+        };
 
-    let name = match c_ptr_to_string(name_ptr, "name") {
-        Ok(name) => name,
-        Err(error_ptr) => return error_ptr,
-    };
+        let key = match c_ptr_to_string(key, "metadata key") {
+            Ok(key) => key,
+            Err(error_ptr) => return error_ptr,
+        };
 
-    let db_state = unsafe { &mut *db_state };
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
 
-    match db_state.reset_database(&name) {
-        Ok(_) => {
-            let success = AppResponse::Ok(format!("Database '{name}' was reset successfully"));
-            response_to_c_string(&success)
-        },
-        Err(e) => {
-            let error = AppResponse::DatabaseError(format!("Error resetting database: {e:?}"));
-            response_to_c_string(&error)
+        match state.remove_meta_value(&key) {
+            Ok(removed) => response_to_c_string(&AppResponse::Ok(removed.to_string())),
+            Err(e) => response_to_c_string(&e),
         }
-    }
+    })
 }
 
-/// Explicitly closes the database connection.
+/// Streams the records of an NDJSON file into the database in batched
+/// write transactions, e.g. to restore a backup or seed a large database.
 ///
-/// This function provides explicit connection management, which is particularly
-/// useful for Flutter hot restart scenarios where resources need to be cleaned up
-/// before reconnecting.
+/// See [`AppDbState::import_ndjson`].
 ///
 /// # Parameters
 ///
-/// * `db_state` - Pointer to the database state instance
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `path` - Path of the NDJSON file
+/// * `options_json` - JSON of a [`local_db_model::ImportOptions`], e.g.
+///   `{"policy":"overwrite","batch_size":1000}`, or null for the defaults
+///   (skip existing IDs, 1000 records per transaction)
 ///
 /// # Returns
 ///
-/// Returns a JSON-formatted C string indicating success or failure.
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::ImportResult`], e.g. `{"read":10000,"imported":9950,"skipped":50}`.
 ///
-/// # Safety
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, import_ndjson};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let path = CString::new("backups/notes.ndjson").unwrap();
+/// let options = CString::new(r#"{"policy":"fail"}"#).unwrap();
+/// let result = import_ndjson(db_state, path.as_ptr(), options.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn import_ndjson(handle: DbHandle, path: *const c_char, options_json: *const c_char) -> *const c_char {
+    ffi_boundary("import_ndjson", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to import_ndjson"));
+            return response_to_c_string(&error);
+        };
+
+        let path = match c_ptr_to_string(path, "path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let options = match optional_c_ptr_to_string(options_json, "import options JSON") {
+            Ok(None) => ImportOptions::default(),
+            Ok(Some(json_str)) => match serde_json::from_str(&json_str) {
+                Ok(options) => options,
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error parsing import options: {e}"));
+                    return response_to_c_string(&error);
+                }
+            },
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.import_ndjson(&path, &options) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing import result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Writes the records to a CSV file with `data` flattened into columns,
+/// e.g. to inspect an offline database in a spreadsheet.
+///
+/// See [`AppDbState::export_csv`].
 ///
-/// The db_state parameter must be a valid pointer.
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `path` - Path of the CSV file to write, replaced if it exists
+/// * `filter_json` - Records to export as a path filter, e.g.
+///   `{"data.type":"item"}`, or null for all records
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::CsvExportResult`], e.g.
+/// `{"records":120,"columns":["id","hash","data.title"]}`.
 ///
 /// # Examples
 ///
 /// ```no_run
+/// use offline_first_core::{create_db, export_csv};
 /// use std::ffi::CString;
-/// use offline_first_core::{create_db, close_database};
 ///
-/// let db_name = CString::new("test_db").unwrap();
+/// let db_name = CString::new("inventory").unwrap();
 /// let db_state = create_db(db_name.as_ptr());
 ///
-/// // Before hot restart or application shutdown
-/// let result = close_database(db_state);
+/// let path = CString::new("exports/inventory.csv").unwrap();
+/// let result = export_csv(db_state, path.as_ptr(), std::ptr::null());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn export_csv(handle: DbHandle, path: *const c_char, filter_json: *const c_char) -> *const c_char {
+    ffi_boundary("export_csv", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to export_csv"));
+            return response_to_c_string(&error);
+        };
+
+        let path = match c_ptr_to_string(path, "path") {
+            Ok(path) => path,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let filter = if filter_json.is_null() {
+            PathFilter::default()
+        } else {
+            match parse_filter_json(filter_json) {
+                Ok(filter) => filter,
+                Err(error_ptr) => return error_ptr,
+            }
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        match state.export_csv(&path, &filter) {
+            Ok(result) => match serde_json::to_string(&result) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing export result: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Copies the database into a directory while it stays open and writable,
+/// e.g. before a sync or on user request.
+///
+/// See [`AppDbState::backup_database`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `dest_dir` - Directory of the copy, created when missing; it must not
+///   already hold a database
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::BackupResult`], e.g.
+/// `{"path":"backups/before_sync/data.mdb","bytes":20971520}`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{backup_database, create_db};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("notes").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let dest = CString::new("backups/before_sync").unwrap();
+/// let result = backup_database(db_state, dest.as_ptr());
 /// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn backup_database(handle: DbHandle, dest_dir: *const c_char) -> *const c_char {
+    ffi_boundary("backup_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to backup_database"));
+            return response_to_c_string(&error);
+        };
+
+        let dest_dir = match c_ptr_to_string(dest_dir, "destination directory") {
+            Ok(dir) => dir,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        backup_response(state.backup_database(&dest_dir))
+    })
+}
+
+/// Copies the database into a directory without its free pages, while it
+/// stays open and writable. Use [`compact`] to compact it in place instead.
 ///
-/// # Notes
+/// See [`AppDbState::compact_database`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `dest_dir` - Directory of the copy, created when missing; it must not
+///   already hold a database
+///
+/// # Returns
 ///
-/// In LMDB, connections are automatically closed when the environment is dropped.
-/// This function serves as an explicit indicator that the connection should no longer be used.
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::BackupResult`] of the compacted copy.
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub extern "C" fn close_database(db_state: *mut AppDbState) -> *const c_char {
-    if db_state.is_null() {
-        let error = AppResponse::BadRequest("Null state pointer passed to close_database".to_string());
-        return response_to_c_string(&error);
-    }
+pub extern "C" fn compact_database(handle: DbHandle, dest_dir: *const c_char) -> *const c_char {
+    ffi_boundary("compact_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to compact_database"));
+            return response_to_c_string(&error);
+        };
+
+        let dest_dir = match c_ptr_to_string(dest_dir, "destination directory") {
+            Ok(dir) => dir,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
+
+        backup_response(state.compact_database(&dest_dir))
+    })
+}
+
+/// Copies the database to a new database in the same base directory, which
+/// can then be opened with [`create_db`] under its name.
+///
+/// See [`AppDbState::clone_database`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `new_name` - Name of the copy; no database of that name may exist
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the
+/// [`local_db_model::BackupResult`] of the copy.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn clone_database(handle: DbHandle, new_name: *const c_char) -> *const c_char {
+    ffi_boundary("clone_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to clone_database"));
+            return response_to_c_string(&error);
+        };
+
+        let new_name = match c_ptr_to_string(new_name, "new name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let state = db.read().unwrap_or_else(PoisonError::into_inner);
 
-    let db_state = unsafe { &mut *db_state };
+        backup_response(state.clone_database(&new_name))
+    })
+}
 
-    match db_state.close_database() {
-        Ok(_) => {
-            let success = AppResponse::Ok("Database connection closed successfully".to_string());
-            response_to_c_string(&success)
+/// Shared response of the functions copying a database.
+fn backup_response(result: Result<BackupResult, AppResponse>) -> *const c_char {
+    match result {
+        Ok(backup) => match serde_json::to_string(&backup) {
+            Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+            Err(e) => {
+                let error = AppResponse::SerializationError(format!("Error serializing backup result: {e:?}"));
+                response_to_c_string(&error)
+            }
         },
-        Err(e) => {
-            let error = AppResponse::from(e);
-            response_to_c_string(&error)
-        }
+        Err(e) => response_to_c_string(&e),
     }
 }
 
+/// Renames the database, keeping its records and its handle.
+///
+/// See [`AppDbState::rename_database`].
+///
+/// # Parameters
+///
+/// * `handle` - Handle of the database returned by [`create_db`]
+/// * `new_name` - New name of the database, in the same base directory
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is the new
+/// directory of the database.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::{create_db, rename_database};
+/// use std::ffi::CString;
+///
+/// let db_name = CString::new("guest").unwrap();
+/// let db_state = create_db(db_name.as_ptr());
+///
+/// let new_name = CString::new("user_42").unwrap();
+/// let result = rename_database(db_state, new_name.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn rename_database(handle: DbHandle, new_name: *const c_char) -> *const c_char {
+    ffi_boundary("rename_database", || {
+        let Some(db) = registry::get(handle) else {
+            let error = AppResponse::BadRequest(format!("Unknown database handle {handle} passed to rename_database"));
+            return response_to_c_string(&error);
+        };
+
+        let new_name = match c_ptr_to_string(new_name, "new name") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        let mut state = db.write().unwrap_or_else(PoisonError::into_inner);
+
+        match state.rename_database(&new_name) {
+            Ok(()) => {
+                registry::rename(handle, &state.path);
+                response_to_c_string(&AppResponse::Ok(state.path.clone()))
+            }
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Lists the databases of a directory, e.g. to show the per-user stores of
+/// a multi-account app.
+///
+/// See [`AppDbState::list_databases`].
+///
+/// # Parameters
+///
+/// * `base_dir` - Directory to scan, or an empty string for the working directory
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is a JSON array of
+/// [`local_db_model::DatabaseInfo`] in name order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::list_databases;
+/// use std::ffi::CString;
+///
+/// let base_dir = CString::new("/data/user/0/com.example.app/files").unwrap();
+/// let result = list_databases(base_dir.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn list_databases(base_dir: *const c_char) -> *const c_char {
+    ffi_boundary("list_databases", || {
+        let base_dir = match c_ptr_to_string(base_dir, "base directory") {
+            Ok(dir) => dir,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        match AppDbState::list_databases(&base_dir) {
+            Ok(databases) => match serde_json::to_string(&databases) {
+                Ok(json) => response_to_c_string(&AppResponse::Ok(json)),
+                Err(e) => {
+                    let error = AppResponse::SerializationError(format!("Error serializing databases: {e:?}"));
+                    response_to_c_string(&error)
+                }
+            },
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
+/// Deletes a database that is not open, with its lock file, e.g. to clean
+/// up on logout.
+///
+/// See [`AppDbState::delete_database`].
+///
+/// # Parameters
+///
+/// * `name_or_path` - Name of a database in the working directory, or the
+///   path of a database directory ending in `.lmdb`
+///
+/// # Returns
+///
+/// Returns a JSON-formatted C string whose `Ok` payload is `"true"` if the
+/// database existed, `"false"` otherwise, or a `BadRequest` response if it
+/// is open; close it with [`close_database`] first.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::delete_database;
+/// use std::ffi::CString;
+///
+/// let path = CString::new("/data/user/0/com.example.app/files/user_42.lmdb").unwrap();
+/// let result = delete_database(path.as_ptr());
+/// ```
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn delete_database(name_or_path: *const c_char) -> *const c_char {
+    ffi_boundary("delete_database", || {
+        let name_or_path = match c_ptr_to_string(name_or_path, "database name or path") {
+            Ok(name) => name,
+            Err(error_ptr) => return error_ptr,
+        };
+
+        match AppDbState::delete_database(&name_or_path) {
+            Ok(deleted) => response_to_c_string(&AppResponse::Ok(deleted.to_string())),
+            Err(e) => response_to_c_string(&e),
+        }
+    })
+}
+
 /// Converts an [`AppResponse`] to a C-compatible string.
 ///
 /// This internal helper function serializes the response to JSON format
@@ -720,25 +9838,108 @@ pub extern "C" fn close_database(db_state: *mut AppDbState) -> *const c_char {
 ///
 /// # Safety
 ///
-/// Returns a null pointer if serialization or C string creation fails.
+/// Returns a null pointer if serialization or C string creation fails; the
+/// reason is recorded for [`get_last_error`].
 fn response_to_c_string(response: &AppResponse) -> *const c_char {
-    let json = match serde_json::to_string(response) {
+    let json = match encode_response(response, RESPONSE_FORMAT.load(Ordering::Relaxed)) {
         Ok(j) => j,
         Err(e) => {
-            warn!("Error serializing response: {e}");
+            set_last_error(AppResponse::SerializationError(format!("Error serializing response: {e}")));
             return std::ptr::null();
         }
     };
 
     match CString::new(json) {
-        Ok(c_str) => c_str.into_raw(),
+        Ok(c_str) => {
+            stats::track_returned_buffer(c_str.as_bytes_with_nul().len());
+            c_str.into_raw()
+        },
         Err(e) => {
-            warn!("Error creating CString: {e}");
+            set_last_error(AppResponse::SerializationError(format!("Error creating CString: {e}")));
             std::ptr::null()
         }
     }
 }
 
+/// Serializes a response in the given [`set_response_format`] version.
+fn encode_response(response: &AppResponse, format: u32) -> Result<String, serde_json::Error> {
+    match format {
+        2 => serde_json::to_string(&response.envelope()),
+        _ => serde_json::to_string(response),
+    }
+}
+
+/// Converts an [`AppResponse`] to a [`ByteBuffer`] holding its JSON.
+///
+/// Returns a null buffer if serialization fails.
+fn response_to_buffer(response: &AppResponse) -> ByteBuffer {
+    let json = match encode_response(response, RESPONSE_FORMAT.load(Ordering::Relaxed)) {
+        Ok(json) => json,
+        Err(e) => {
+            set_last_error(AppResponse::SerializationError(format!("Error serializing response: {e}")));
+            return ByteBuffer::null();
+        }
+    };
+
+    let len = json.len();
+    stats::track_returned_buffer(len);
+    let ptr = Box::into_raw(json.into_bytes().into_boxed_slice()) as *mut u8;
+    ByteBuffer { ptr, len }
+}
+
+/// Return types of FFI functions, and how each reports a panic.
+trait FfiReturn {
+    fn from_panic(error: AppResponse) -> Self;
+}
+
+impl FfiReturn for *const c_char {
+    fn from_panic(error: AppResponse) -> Self {
+        response_to_c_string(&error)
+    }
+}
+
+impl FfiReturn for DbHandle {
+    fn from_panic(error: AppResponse) -> Self {
+        set_last_error(error);
+        0
+    }
+}
+
+impl FfiReturn for ByteBuffer {
+    fn from_panic(error: AppResponse) -> Self {
+        response_to_buffer(&error)
+    }
+}
+
+impl FfiReturn for () {
+    fn from_panic(error: AppResponse) -> Self {
+        set_last_error(error);
+    }
+}
+
+/// Runs the body of the FFI function `function`, turning a panic into a
+/// [`AppResponse::DatabaseError`] in its return type. Unwinding into the C
+/// caller is undefined behavior and would take the whole app down.
+fn ffi_boundary<R: FfiReturn>(function: &str, body: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_string());
+            R::from_panic(AppResponse::DatabaseError(format!("Panic in {function}: {message}")))
+        }
+    }
+}
+
+/// Records the failure of a function returning null, for [`get_last_error`].
+fn set_last_error(error: AppResponse) {
+    warn!("{error}");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+}
+
 /// Converts a C string pointer to a Rust String with comprehensive error handling.
 ///
 /// This internal helper function safely converts C string pointers to Rust strings,
@@ -770,4 +9971,75 @@ fn c_ptr_to_string(ptr: *const c_char, field_name: &str) -> Result<String, *cons
             Err(response_to_c_string(&error))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Like [`c_ptr_to_string`], but maps a null pointer to `None`.
+fn optional_c_ptr_to_string(ptr: *const c_char, field_name: &str) -> Result<Option<String>, *const c_char> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    c_ptr_to_string(ptr, field_name).map(Some)
+}
+
+/// Borrows `len` bytes at `ptr` passed over FFI, allowing a null `ptr` for
+/// an empty slice.
+fn c_ptr_to_bytes<'a>(ptr: *const u8, len: usize, field_name: &str) -> Result<&'a [u8], *const c_char> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        let error = AppResponse::BadRequest(format!("Null {field_name} pointer"));
+        return Err(response_to_c_string(&error));
+    }
+    // SAFETY: the caller guarantees `ptr` points to `len` readable bytes
+    // that outlive the call.
+    Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+/// Parses a C string holding a JSON array of record IDs.
+///
+/// Errors are returned as ready-to-send C strings, like [`c_ptr_to_string`].
+fn parse_ids_json(ptr: *const c_char) -> Result<Vec<String>, *const c_char> {
+    let json_str = c_ptr_to_string(ptr, "ids JSON")?;
+
+    serde_json::from_str(&json_str).map_err(|e| {
+        let error = AppResponse::SerializationError(format!("Expected a JSON array of IDs: {e}"));
+        response_to_c_string(&error)
+    })
+}
+
+/// Parses a C string holding a JSON array of dotted paths.
+///
+/// Errors are returned as ready-to-send C strings, like [`c_ptr_to_string`].
+fn parse_paths_json(ptr: *const c_char) -> Result<Vec<String>, *const c_char> {
+    let json_str = c_ptr_to_string(ptr, "paths JSON")?;
+
+    serde_json::from_str(&json_str).map_err(|e| {
+        let error = AppResponse::SerializationError(format!("Expected a JSON array of paths: {e}"));
+        response_to_c_string(&error)
+    })
+}
+
+/// Parses a C string holding a JSON object of path filters.
+///
+/// Errors are returned as ready-to-send C strings, like [`c_ptr_to_string`].
+fn parse_filter_json(ptr: *const c_char) -> Result<PathFilter, *const c_char> {
+    let json_str = c_ptr_to_string(ptr, "filter JSON")?;
+
+    serde_json::from_str(&json_str).map_err(|e| {
+        let error = AppResponse::SerializationError(format!("Expected a JSON object of path filters: {e}"));
+        response_to_c_string(&error)
+    })
+}
+
+/// Parses a C string holding a JSON [`SortSpec`].
+///
+/// Errors are returned as ready-to-send C strings, like [`c_ptr_to_string`].
+fn parse_sort_json(ptr: *const c_char) -> Result<SortSpec, *const c_char> {
+    let json_str = c_ptr_to_string(ptr, "sort JSON")?;
+
+    serde_json::from_str(&json_str).map_err(|e| {
+        let error = AppResponse::SerializationError(format!("Expected a sort like {{\"by\":\"data.created_at\",\"order\":\"desc\"}}: {e}"));
+        response_to_c_string(&error)
+    })
+}
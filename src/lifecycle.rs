@@ -0,0 +1,75 @@
+//! Mobile app lifecycle hooks.
+//!
+//! Mobile platforms may suspend or kill a backgrounded app at any moment, so
+//! when the app moves to the background everything written must be durable
+//! and no background thread should keep the process busy. The hooks sync the
+//! environment to disk and pause the expiry sweeper until the app returns to
+//! the foreground. Writes are committed synchronously, so there is no
+//! write-behind queue left to flush.
+
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_state::AppDbState;
+
+impl AppDbState {
+    /// Prepares the database for the app moving to the background: the
+    /// expiry sweeper is paused and the environment is synced to disk.
+    ///
+    /// Calling it again while in the background only syncs.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    ///
+    /// // From the platform's "did enter background" callback
+    /// db.enter_background()?;
+    /// // ...and when the app is resumed
+    /// db.enter_foreground()?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the sync fails.
+    pub fn enter_background(&mut self) -> Result<(), AppResponse> {
+        if let Some(sweep) = self.stop_expiry_sweeper() {
+            self.paused_sweep = Some(sweep);
+        }
+
+        let (env, _) = self.env_db()?;
+        env.sync(true)?;
+        info!("Database {} ready for background", self.path);
+        Ok(())
+    }
+
+    /// Flushes committed transactions to disk, for databases opened with
+    /// `no_sync` or `no_meta_sync` in their
+    /// [`DbOptions`](crate::local_db_model::DbOptions), e.g. after a bulk
+    /// import.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the sync fails.
+    pub fn flush(&self) -> Result<(), AppResponse> {
+        let (env, _) = self.env_db()?;
+        env.sync(true)?;
+        Ok(())
+    }
+
+    /// Resumes the background work paused by [`AppDbState::enter_background`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`AppDbState::start_expiry_sweeper`] if the
+    /// sweeper cannot be restarted, e.g. because its index was dropped.
+    pub fn enter_foreground(&mut self) -> Result<(), AppResponse> {
+        if let Some(sweep) = self.paused_sweep.take() {
+            self.start_expiry_sweeper(sweep)?;
+        }
+        Ok(())
+    }
+}
@@ -5,9 +5,15 @@
 //! which provides a flexible structure for storing arbitrary JSON data with
 //! unique identifiers and content hashing.
 
+use std::collections::BTreeMap;
+
+use lmdb::EnvironmentFlags;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 
+use crate::app_response::AppResponse;
+use crate::query::PathFilter;
+
 /// A flexible data model for storing structured information in the database.
 ///
 /// `LocalDbModel` serves as the primary data container for all database operations.
@@ -19,6 +25,8 @@ use serde_json::Value as JsonValue;
 /// - **id**: Unique identifier used as the database key
 /// - **hash**: Content hash for data integrity and change detection
 /// - **data**: Arbitrary JSON data containing the actual application data
+/// - **created_at** / **updated_at**: Times the record was first and last
+///   written, stamped by the crate while record timestamps are on
 ///
 /// # Examples
 ///
@@ -40,6 +48,7 @@ use serde_json::Value as JsonValue;
 ///             "notifications": true
 ///         }
 ///     }),
+///     ..Default::default()
 /// };
 /// ```
 ///
@@ -70,6 +79,7 @@ use serde_json::Value as JsonValue;
 ///             "version": "1.0.0"
 ///         }
 ///     }),
+///     ..Default::default()
 /// };
 /// ```
 ///
@@ -86,6 +96,7 @@ use serde_json::Value as JsonValue;
 ///     id: "test".to_string(),
 ///     hash: "test_hash".to_string(),
 ///     data: json!({"key": "value"}),
+///     ..Default::default()
 /// };
 ///
 /// // Serialize to JSON string
@@ -111,6 +122,7 @@ use serde_json::Value as JsonValue;
 ///     id: "original".to_string(),
 ///     hash: "hash123".to_string(),
 ///     data: json!({"status": "active"}),
+///     ..Default::default()
 /// };
 ///
 /// let mut updated = original.clone();
@@ -137,6 +149,7 @@ use serde_json::Value as JsonValue;
 ///         "language": "en",
 ///         "auto_save": true
 ///     }),
+///     ..Default::default()
 /// };
 ///
 /// // Store the model
@@ -167,7 +180,13 @@ use serde_json::Value as JsonValue;
 /// - Can contain objects, arrays, strings, numbers, booleans, or null
 /// - Size limitations apply based on LMDB configuration
 /// - Nested structures are fully supported
-#[derive(Debug, Deserialize, Serialize, Clone)]
+///
+/// ## Timestamps
+/// - Left out of the JSON while unset
+/// - Stamped on every write while
+///   [`AppDbState::set_record_timestamps`](crate::local_db_state::AppDbState::set_record_timestamps)
+///   is on, replacing the values passed in
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct LocalDbModel {
     /// Unique identifier for this record.
     ///
@@ -239,4 +258,1960 @@ pub struct LocalDbModel {
     /// ]);
     /// ```
     pub data: JsonValue,
-}
\ No newline at end of file
+
+    /// Milliseconds since the Unix epoch at which the record was first
+    /// written, kept by later writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+
+    /// Milliseconds since the Unix epoch at which the record was last
+    /// written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+}
+/// Outcome of a batch delete operation.
+///
+/// Every requested ID ends up in exactly one of the two lists, preserving the
+/// order in which the IDs were supplied.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "deleted": ["user_1", "user_2"],
+///   "not_found": ["user_99"]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct DeleteManyResult {
+    /// IDs that existed and were removed.
+    pub deleted: Vec<String>,
+
+    /// IDs that had no matching record.
+    pub not_found: Vec<String>,
+}
+
+/// Outcome of a multi-get operation.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "found": [{"id": "user_1", "hash": "h1", "data": {}}],
+///   "missing": ["user_99"]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct GetManyResult {
+    /// Records that were found, in the order their IDs were requested.
+    pub found: Vec<LocalDbModel>,
+
+    /// Requested IDs with no matching record.
+    pub missing: Vec<String>,
+}
+
+/// A stored record that could not be decoded.
+///
+/// Quarantined entries are reported instead of being silently dropped so the
+/// application can surface, repair or re-download them.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct QuarantinedRecord {
+    /// Key of the record (lossily converted if it is not valid UTF-8).
+    pub id: String,
+
+    /// Description of the decoding failure.
+    pub error: String,
+
+    /// Size in bytes of the raw stored value.
+    pub raw_size: usize,
+}
+
+/// Result of reading every record while keeping track of undecodable ones.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "records": [{"id": "user_1", "hash": "h1", "data": {}}],
+///   "quarantined": [{"id": "user_2", "error": "Invalid UTF-8 ...", "raw_size": 42}]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct GetAllResult {
+    /// Records that were decoded successfully.
+    pub records: Vec<LocalDbModel>,
+
+    /// Records that failed to decode.
+    pub quarantined: Vec<QuarantinedRecord>,
+}
+
+/// A page of records returned by cursor-based pagination.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "records": [{"id": "todo:001", "hash": "h1", "data": {}}],
+///   "next_token": "todo:001"
+/// }
+/// ```
+///
+/// `next_token` is `null` once the end of the key space has been reached.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PageResult {
+    /// Records of this page, in key order.
+    pub records: Vec<LocalDbModel>,
+
+    /// Continuation token to pass to the next call, or `None` when there are no more records.
+    pub next_token: Option<String>,
+}
+
+/// A record flagged for re-download from the server.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"id": "todo:001", "marked_at": 1736812800000}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ResyncEntry {
+    /// ID of the record to fetch again.
+    pub id: String,
+
+    /// Milliseconds since the Unix epoch at which the record was first flagged.
+    pub marked_at: u64,
+}
+
+/// Outcome of redistributing a database into hash shards.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"shards": ["catalog_shard_0", "catalog_shard_1"], "counts": [512, 488]}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ShardResult {
+    /// Names of the shard databases, indexed by shard number.
+    pub shards: Vec<String>,
+
+    /// Number of records written to each shard.
+    pub counts: Vec<usize>,
+}
+
+/// Options for generating a prebuilt asset database.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"compress": true, "schema_version": 3, "dataset_version": 12}
+/// ```
+///
+/// All fields are optional.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BuildOptions {
+    /// Compress every value (requires the `compression` feature).
+    pub compress: bool,
+
+    /// Schema version stamped into every value header.
+    pub schema_version: u16,
+
+    /// Dataset version recorded in the generated database, which patches build on.
+    pub dataset_version: u64,
+}
+
+/// Outcome of generating a prebuilt asset database.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"path": "catalog.lmdb", "records": 1200}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BuildResult {
+    /// Directory of the generated database.
+    pub path: String,
+
+    /// Number of records written.
+    pub records: usize,
+}
+
+/// Differential update for a shipped dataset.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "from_version": 3,
+///   "to_version": 4,
+///   "upserts": [{"id": "product_1", "hash": "h2", "data": {"price": 10}}],
+///   "deletes": ["product_9"]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DatasetPatch {
+    /// Dataset version the patch applies to.
+    pub from_version: u64,
+
+    /// Dataset version after the patch is applied.
+    pub to_version: u64,
+
+    /// Records to add or replace.
+    #[serde(default)]
+    pub upserts: Vec<LocalDbModel>,
+
+    /// IDs of records to remove.
+    #[serde(default)]
+    pub deletes: Vec<String>,
+}
+
+/// Outcome of applying a [`DatasetPatch`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"version": 4, "upserted": 1, "deleted": 1}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PatchResult {
+    /// Dataset version after the patch.
+    pub version: u64,
+
+    /// Number of records added or replaced.
+    pub upserted: usize,
+
+    /// Number of records removed; IDs that were already absent are not counted.
+    pub deleted: usize,
+}
+
+/// Key order in which records are returned.
+///
+/// Serialized as `"asc"` / `"desc"`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Ascending key order (the default).
+    #[default]
+    Asc,
+
+    /// Descending key order, e.g. newest first with time-ordered keys.
+    Desc,
+}
+
+/// How numbers that JSON consumers cannot represent exactly are handled on write.
+///
+/// A number is unsafe when it is an integer outside `±(2^53 - 1)`: JavaScript
+/// and Dart on the web store every number as an `f64` and silently round it.
+/// Integer literals beyond the 64-bit range are already rounded to an `f64`
+/// when the JSON is parsed, so they count as unsafe too. NaN and infinities
+/// cannot be written as JSON and are rejected by the parser.
+///
+/// Serialized as `"preserve"`, `"reject"`, `"stringify"` or `"lossy"`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberPolicy {
+    /// Store numbers exactly as parsed (the default).
+    #[default]
+    Preserve,
+
+    /// Fail the write with a validation error naming the offending path.
+    Reject,
+
+    /// Replace unsafe numbers with their decimal string.
+    Stringify,
+
+    /// Replace unsafe numbers with the nearest `f64`, as a consumer would see them.
+    Lossy,
+}
+
+/// Size of a single stored record.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RecordSize {
+    /// Record ID.
+    pub id: String,
+
+    /// Size of the stored value in bytes, header included.
+    pub size: usize,
+}
+
+/// Aggregated size of the records sharing a key prefix (the part of the ID
+/// before the first `:`, e.g. `todo` for `todo:123`).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PrefixSize {
+    /// Key prefix; IDs without `:` are grouped under the empty prefix.
+    pub prefix: String,
+
+    /// Number of records with this prefix.
+    pub records: usize,
+
+    /// Total size of their stored values in bytes.
+    pub bytes: usize,
+}
+
+/// Number of values whose size falls in a bucket.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SizeBucket {
+    /// Exclusive upper bound in bytes, `None` for the last, open-ended bucket.
+    pub up_to: Option<usize>,
+
+    /// Number of values in the bucket.
+    pub count: usize,
+}
+
+/// Storage analysis of the main database.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "record_count": 2,
+///   "total_bytes": 5120,
+///   "min_size": 120,
+///   "max_size": 5000,
+///   "mean_size": 2560,
+///   "median_size": 5000,
+///   "size_buckets": [{"up_to": 256, "count": 1}, {"up_to": null, "count": 0}],
+///   "compressed_records": 0,
+///   "estimated_compression_ratio": 0.41,
+///   "largest_records": [{"id": "doc:1", "size": 5000}],
+///   "largest_prefixes": [{"prefix": "doc", "records": 1, "bytes": 5000}]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct StorageReport {
+    /// Number of stored values.
+    pub record_count: usize,
+
+    /// Sum of all value sizes in bytes.
+    pub total_bytes: usize,
+
+    /// Smallest value size in bytes.
+    pub min_size: usize,
+
+    /// Largest value size in bytes.
+    pub max_size: usize,
+
+    /// Mean value size in bytes.
+    pub mean_size: usize,
+
+    /// Median value size in bytes.
+    pub median_size: usize,
+
+    /// Value size distribution.
+    pub size_buckets: Vec<SizeBucket>,
+
+    /// Number of values already stored compressed.
+    pub compressed_records: usize,
+
+    /// Estimated compressed size of the uncompressed payloads as a fraction of
+    /// their current size (e.g. `0.4` means they would shrink to about 40%).
+    pub estimated_compression_ratio: f64,
+
+    /// The largest records, biggest first.
+    pub largest_records: Vec<RecordSize>,
+
+    /// The key prefixes using the most space, biggest first.
+    pub largest_prefixes: Vec<PrefixSize>,
+}
+
+/// Memory used by the library for one database.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "map_size": 10485760,
+///   "page_size": 4096,
+///   "used_pages": 12,
+///   "resident_pages": 9,
+///   "readers": 1,
+///   "max_readers": 126,
+///   "outstanding_buffers": 2,
+///   "outstanding_buffer_bytes": 180
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MemoryStats {
+    /// Size of the memory map in bytes (address space, not resident memory).
+    pub map_size: usize,
+
+    /// LMDB page size in bytes.
+    pub page_size: usize,
+
+    /// Number of database pages in use.
+    pub used_pages: usize,
+
+    /// Number of used database pages currently resident in memory, or `None`
+    /// on platforms where this cannot be queried.
+    pub resident_pages: Option<usize>,
+
+    /// Number of reader slots in use.
+    pub readers: u32,
+
+    /// Number of reader slots, see [`DbOptions::max_readers`].
+    pub max_readers: u32,
+
+    /// Number of strings returned over FFI that were not released with
+    /// `free_c_string` yet (across all databases).
+    pub outstanding_buffers: usize,
+
+    /// Total size in bytes of those strings.
+    pub outstanding_buffer_bytes: usize,
+}
+
+/// Version and build information of the library.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "version": "0.5.0",
+///   "lmdb_version": "0.9.22",
+///   "features": ["signing"],
+///   "index_format": 1,
+///   "response_formats": [1, 2],
+///   "os": "android",
+///   "arch": "aarch64"
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LibraryVersion {
+    /// Version of this crate.
+    pub version: String,
+
+    /// Version of the bundled LMDB.
+    pub lmdb_version: String,
+
+    /// Enabled Cargo features.
+    pub features: Vec<String>,
+
+    /// Version of the index entry format; indexes built with another one are
+    /// rebuilt on open.
+    pub index_format: u64,
+
+    /// Response formats accepted by `set_response_format`.
+    pub response_formats: Vec<u32>,
+
+    /// Target operating system, e.g. `android` or `ios`.
+    pub os: String,
+
+    /// Target architecture, e.g. `aarch64`.
+    pub arch: String,
+}
+
+/// Definition of a secondary index.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"name": "by_account_date", "paths": ["data.account_id", "data.created_at"]}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct IndexDefinition {
+    /// Index name.
+    pub name: String,
+
+    /// Indexed dotted paths, rooted at the model, in sort order.
+    pub paths: Vec<String>,
+}
+
+/// Outcome of [`crate::local_db_state::AppDbState::ensure_indexes`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"created": ["by_slug"], "rebuilt": [], "unchanged": ["by_date"]}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct IndexSyncReport {
+    /// Declared indexes that did not exist.
+    pub created: Vec<String>,
+
+    /// Indexes rebuilt because their paths changed.
+    pub rebuilt: Vec<String>,
+
+    /// Indexes already matching their declaration.
+    pub unchanged: Vec<String>,
+}
+
+/// Outcome of the integrity fast-check run when a database is opened.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"recovered": true, "stale_readers_cleared": 1, "last_page_ok": true}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct StartupReport {
+    /// Whether the previous session ended without closing the database, e.g.
+    /// because the app crashed or was killed.
+    pub recovered: bool,
+
+    /// Number of reader slots left behind by dead processes that were freed.
+    pub stale_readers_cleared: usize,
+
+    /// Whether the last committed page lies within the data file. Only
+    /// checked after an unclean shutdown; `false` means the file was
+    /// truncated and the database should be restored or reset.
+    pub last_page_ok: bool,
+}
+
+/// When `run_maintenance` compacts the database.
+///
+/// Missing fields take their default values.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"min_free_ratio": 0.25, "min_file_bytes": 4194304, "require_idle": true, "require_charging": true}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CompactionPolicy {
+    /// Free pages divided by used pages above which compaction is due.
+    pub min_free_ratio: f64,
+
+    /// Data file size in bytes below which compaction is never worth it.
+    pub min_file_bytes: u64,
+
+    /// Only compact when the app reports it is idle.
+    pub require_idle: bool,
+
+    /// Only compact when the app reports the device is charging.
+    pub require_charging: bool,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            min_free_ratio: 0.25,
+            min_file_bytes: 4 * 1024 * 1024,
+            require_idle: true,
+            require_charging: true,
+        }
+    }
+}
+
+/// Result of compacting a database.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"bytes_before": 52428800, "bytes_after": 20971520}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CompactionResult {
+    /// Size of the data file before compaction.
+    pub bytes_before: u64,
+
+    /// Size of the data file after compaction.
+    pub bytes_after: u64,
+}
+
+/// Outcome of copying a database to another directory.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"path": "backups/2024-05-01/data.mdb", "bytes": 20971520}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BackupResult {
+    /// Data file of the copy.
+    pub path: String,
+
+    /// Size of the copied data file.
+    pub bytes: u64,
+}
+
+/// A database found on disk by
+/// [`crate::local_db_state::AppDbState::list_databases`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"name": "user_42", "path": "/data/files/user_42.lmdb", "bytes": 20971520, "modified_at": 1714557600000, "open": false}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DatabaseInfo {
+    /// Name the database is opened with.
+    pub name: String,
+
+    /// Directory of the database.
+    pub path: String,
+
+    /// Size of the data file.
+    pub bytes: u64,
+
+    /// Milliseconds since the Unix epoch at which the data file was last modified.
+    pub modified_at: u64,
+
+    /// Whether a handle in this process has the database open.
+    pub open: bool,
+}
+
+/// Outcome of a maintenance run.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "used_pages": 12800,
+///   "free_pages": 5120,
+///   "free_ratio": 0.4,
+///   "file_bytes": 52428800,
+///   "compaction": {"bytes_before": 52428800, "bytes_after": 31457280},
+///   "skipped": null,
+///   "changelog": {"purged": 5400, "reset": []}
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MaintenanceReport {
+    /// Number of database pages in use before maintenance, free ones included.
+    pub used_pages: usize,
+
+    /// Number of pages on the free list before maintenance.
+    pub free_pages: usize,
+
+    /// `free_pages` divided by `used_pages`.
+    pub free_ratio: f64,
+
+    /// Size of the data file before maintenance.
+    pub file_bytes: u64,
+
+    /// Result of the compaction, `None` when it did not run.
+    pub compaction: Option<CompactionResult>,
+
+    /// Why compaction did not run, `None` when it ran.
+    pub skipped: Option<String>,
+
+    /// Result of purging the change log with the retention window, `None`
+    /// when no window is set.
+    #[serde(default)]
+    pub changelog: Option<ChangelogPurge>,
+}
+
+/// Sustained rate and burst size of the write rate limiter.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"ops_per_sec": 50.0, "burst": 200}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct WriteRateLimit {
+    /// Write transactions per second allowed on average.
+    pub ops_per_sec: f64,
+
+    /// Write transactions allowed back to back after an idle period.
+    pub burst: u32,
+}
+
+/// Aggregate function computed by `aggregate`.
+///
+/// Serialized lowercase (`"count"`, `"sum"`, ...).
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateOp {
+    /// Number of matching records, or of those with a number at the path.
+    Count,
+    /// Sum of the numbers at the path.
+    Sum,
+    /// Smallest number at the path.
+    Min,
+    /// Largest number at the path.
+    Max,
+    /// Mean of the numbers at the path.
+    Avg,
+}
+
+/// One aggregate to compute.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Aggregation {
+    /// Function to apply.
+    pub op: AggregateOp,
+
+    /// Dotted path of the aggregated numbers, rooted at the model. Optional
+    /// for `count` only.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl Aggregation {
+    /// Key of this aggregate in [`AggregateResult::values`], e.g.
+    /// `sum(data.amount)`, or `count` for a count without path.
+    pub fn key(&self) -> String {
+        let op = match self.op {
+            AggregateOp::Count => "count",
+            AggregateOp::Sum => "sum",
+            AggregateOp::Min => "min",
+            AggregateOp::Max => "max",
+            AggregateOp::Avg => "avg",
+        };
+        match &self.path {
+            Some(path) => format!("{op}({path})"),
+            None => op.to_string(),
+        }
+    }
+}
+
+/// Aggregates to compute over the records matching a filter.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "filter": {"data.status": "paid"},
+///   "aggregations": [
+///     {"op": "count"},
+///     {"op": "sum", "path": "data.amount"},
+///     {"op": "avg", "path": "data.amount"}
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AggregateSpec {
+    /// Records to aggregate over; all records when omitted.
+    #[serde(default)]
+    pub filter: PathFilter,
+
+    /// Aggregates to compute.
+    pub aggregations: Vec<Aggregation>,
+}
+
+/// Result of an aggregation.
+///
+/// Values that are not numbers are ignored. `min`, `max` and `avg` are `null`
+/// when no matching record has a number at their path; `sum` is then `0`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"matched": 12, "values": {"count": 12.0, "sum(data.amount)": 1530.5, "avg(data.amount)": 127.54}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AggregateResult {
+    /// Number of records matching the filter.
+    pub matched: usize,
+
+    /// Aggregate values keyed by [`Aggregation::key`].
+    pub values: BTreeMap<String, Option<f64>>,
+}
+
+/// Configuration of the background expiry sweeper.
+///
+/// `index` names an index whose first path holds the expiry time of a record
+/// in milliseconds since the Unix epoch, e.g. one created over
+/// `["data.expires_at"]`. Missing `interval_ms` and `batch_size` take their
+/// default values.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"index": "by_expiry", "interval_ms": 60000, "batch_size": 500}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ExpirySweep {
+    /// Index over the expiry time of records.
+    pub index: String,
+
+    /// Pause between two sweeps.
+    #[serde(default = "ExpirySweep::default_interval_ms")]
+    pub interval_ms: u64,
+
+    /// Maximum number of records deleted per write transaction.
+    #[serde(default = "ExpirySweep::default_batch_size")]
+    pub batch_size: usize,
+}
+
+impl ExpirySweep {
+    fn default_interval_ms() -> u64 {
+        60_000
+    }
+
+    fn default_batch_size() -> usize {
+        500
+    }
+}
+
+/// Changes delivered to a watch.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"watch_id": 1, "ids": ["todo:1", "todo:7"], "cleared": false}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ChangeBatch {
+    /// ID of the watch the changes are delivered to.
+    pub watch_id: u64,
+
+    /// IDs of the records inserted, updated or deleted since the last batch,
+    /// sorted and without duplicates.
+    pub ids: Vec<String>,
+
+    /// Whether all records were cleared since the last batch.
+    pub cleared: bool,
+}
+
+/// Changes of the result set of a query subscription, see
+/// [`crate::local_db_state::AppDbState::subscribe`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"name": "open_todos", "added": [{"id": "todo:9", "hash": "h9", "data": {"done": false}}], "updated": [], "removed": ["todo:1"]}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct SubscriptionEvent {
+    /// Name of the subscription.
+    pub name: String,
+
+    /// Records that started matching the query, in key order.
+    pub added: Vec<LocalDbModel>,
+
+    /// Matching records that were written again, in key order.
+    pub updated: Vec<LocalDbModel>,
+
+    /// IDs of the records that stopped matching the query or were deleted,
+    /// in key order.
+    pub removed: Vec<String>,
+}
+
+/// Bounds of a database used as a cache.
+///
+/// When a write leaves the database above either bound, the records written
+/// least recently are evicted until it fits again. Missing fields are
+/// unbounded.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"max_records": 5000, "max_bytes": 52428800}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct CacheLimit {
+    /// Maximum number of records.
+    pub max_records: Option<u64>,
+
+    /// Maximum total size in bytes of the stored record values.
+    pub max_bytes: Option<u64>,
+}
+
+/// Digest of the records of a database, see
+/// [`crate::local_db_state::AppDbState::sync_digest`]. Hashes are 64-bit
+/// FNV-1a values in hex.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"root": "9d2a4c01f3e87b65", "records": 1200, "buckets": ["c4a1f0e2b3d49a87", "..."]}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SyncDigest {
+    /// Hash of the bucket hashes; equal roots mean equal records.
+    pub root: String,
+
+    /// Number of records in the digest.
+    pub records: u64,
+
+    /// Hash of each of the 256 buckets the IDs are split into.
+    pub buckets: Vec<String>,
+}
+
+/// Pending remote mutation in the outbox, see
+/// [`crate::local_db_state::AppDbState::enqueue_outbox`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"sequence": 7, "enqueued_at": 1736812800000, "request": {"method": "POST", "path": "/messages"}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct OutboxEntry {
+    /// Position in the outbox; acknowledge it with
+    /// [`crate::local_db_state::AppDbState::ack_outbox`].
+    pub sequence: u64,
+
+    /// Time the request was enqueued, in milliseconds since the Unix epoch.
+    pub enqueued_at: u64,
+
+    /// The request, as given by the app.
+    pub request: JsonValue,
+}
+
+/// Hybrid logical clock timestamp of a record write, see
+/// [`crate::local_db_state::AppDbState::record_hlc`]. Timestamps order by
+/// `physical_ms`, then `counter`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"physical_ms": 1760000000000, "counter": 2}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hlc {
+    /// Wall-clock part, in milliseconds since the Unix epoch; at most 48 bits.
+    pub physical_ms: u64,
+
+    /// Orders the timestamps issued within the same millisecond.
+    pub counter: u16,
+}
+
+/// Records changed since a consumer's last acknowledged commit sequence,
+/// returned by [`crate::local_db_state::AppDbState::get_all_delta`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChangeDelta {
+    /// Name of the consumer.
+    pub consumer: String,
+
+    /// Sequence the consumer had acknowledged.
+    pub since: u64,
+
+    /// Commit sequence covered by this delta, to acknowledge once processed.
+    pub sequence: u64,
+
+    /// `true` when `records` holds every record and the consumer must drop
+    /// what it had: on its first delta and after the records were cleared.
+    pub reset: bool,
+
+    /// Records written since `since`, in their current state.
+    pub records: Vec<LocalDbModel>,
+
+    /// IDs of the records deleted since `since`.
+    pub deleted: Vec<String>,
+}
+
+/// Update of a record as a JSON merge patch (RFC 7386) of its `data`, see
+/// [`crate::local_db_state::AppDbState::get_encoded_delta`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"id": "n1", "base_hash": "h1", "hash": "h2", "patch": {"title": "Renamed", "draft": null}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RecordPatch {
+    /// ID of the record.
+    pub id: String,
+
+    /// Hash of the version the patch applies to.
+    pub base_hash: String,
+
+    /// Hash of the patched version.
+    pub hash: String,
+
+    /// Merge patch turning the `data` of the base version into the new one.
+    pub patch: JsonValue,
+}
+
+/// Records changed since a consumer's last acknowledged commit sequence,
+/// with updates as patches where possible, returned by
+/// [`crate::local_db_state::AppDbState::get_encoded_delta`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EncodedDelta {
+    /// Name of the consumer.
+    pub consumer: String,
+
+    /// Sequence the consumer had acknowledged.
+    pub since: u64,
+
+    /// Commit sequence covered by this delta, to acknowledge once processed.
+    pub sequence: u64,
+
+    /// `true` when `records` holds every record and the consumer must drop
+    /// what it had, as in [`ChangeDelta`].
+    pub reset: bool,
+
+    /// Records written since `since` that are sent in full.
+    pub records: Vec<LocalDbModel>,
+
+    /// Records updated since `since`, as patches against the version of `since`.
+    pub patches: Vec<RecordPatch>,
+
+    /// IDs of the records deleted since `since`.
+    pub deleted: Vec<String>,
+}
+
+/// Which logged changes [`crate::local_db_state::AppDbState::purge_changelog`]
+/// deletes.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"sequence": 1200}
+/// ```
+///
+/// or, for the changes older than one week:
+///
+/// ```json
+/// {"age_ms": 604800000}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangelogCutoff {
+    /// The changes committed before this commit sequence.
+    Sequence(u64),
+
+    /// The changes older than this many milliseconds, by the database clock.
+    AgeMs(u64),
+}
+
+/// Result of purging the change log.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"purged": 5400, "reset": ["search_index"]}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct ChangelogPurge {
+    /// Number of logged changes and deletions deleted.
+    pub purged: usize,
+
+    /// Consumers that had not acknowledged every purged change; their next
+    /// delta holds every record with `reset` set.
+    pub reset: Vec<String>,
+}
+
+/// Changes committed between two change tokens, see
+/// [`crate::local_db_state::AppDbState::changes_between`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"from": 41, "to": 44, "changed": true, "commits": 3}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeSummary {
+    /// Older token.
+    pub from: u64,
+
+    /// Newer token.
+    pub to: u64,
+
+    /// Whether any record was written, deleted or cleared in between.
+    pub changed: bool,
+
+    /// Number of write transactions committed in between.
+    pub commits: u64,
+}
+
+/// Default value to set on the records lacking a field, see
+/// [`crate::local_db_state::AppDbState::backfill_field`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"field": "data.priority", "value": 0, "filter": {"data.type": "task"}, "batch_size": 500}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Backfill {
+    /// Dotted path of the field under `data`, e.g. `data.settings.theme`.
+    pub field: String,
+
+    /// Value set where the field is missing or `null`.
+    pub value: JsonValue,
+
+    /// Records to backfill; all records when omitted.
+    #[serde(default)]
+    pub filter: PathFilter,
+
+    /// Maximum number of records visited per write transaction.
+    #[serde(default = "Backfill::default_batch_size")]
+    pub batch_size: usize,
+}
+
+impl Backfill {
+    fn default_batch_size() -> usize {
+        500
+    }
+}
+
+/// Progress of a backfill, reported after every batch.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"scanned": 1500, "updated": 312}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct BackfillProgress {
+    /// Records visited so far.
+    pub scanned: usize,
+
+    /// Records the field was set on so far.
+    pub updated: usize,
+}
+
+/// What an import does with a record whose ID already exists.
+///
+/// Serialized as `"skip"`, `"overwrite"` or `"fail"`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportPolicy {
+    /// Keep the existing record (the default).
+    #[default]
+    Skip,
+
+    /// Replace the existing record.
+    Overwrite,
+
+    /// Stop the import with an error.
+    Fail,
+}
+
+/// Options of [`crate::local_db_state::AppDbState::import_ndjson`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"policy": "overwrite", "batch_size": 1000}
+/// ```
+///
+/// All fields are optional.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Handling of records whose ID already exists.
+    pub policy: ImportPolicy,
+
+    /// Maximum number of records per write transaction.
+    pub batch_size: usize,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { policy: ImportPolicy::default(), batch_size: 1000 }
+    }
+}
+
+/// Outcome of an NDJSON import.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"read": 10000, "imported": 9950, "skipped": 50}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct ImportResult {
+    /// Records read from the file.
+    pub read: usize,
+
+    /// Records written, new or replacing an existing one.
+    pub imported: usize,
+
+    /// Records left out because their ID already existed.
+    pub skipped: usize,
+}
+
+/// Outcome of a CSV export, see
+/// [`crate::local_db_state::AppDbState::export_csv`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"records": 120, "columns": ["id", "hash", "data.title", "data.tags"]}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CsvExportResult {
+    /// Records written, one row each.
+    pub records: usize,
+
+    /// Header of the file, in column order.
+    pub columns: Vec<String>,
+}
+
+/// Progress of a schema migration, reported after every batch, see
+/// [`crate::local_db_state::AppDbState::migrate`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"schema_version": 2, "scanned": 1500, "updated": 1480, "deleted": 20}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct MigrationProgress {
+    /// Schema version the records are migrated to so far.
+    pub schema_version: u64,
+
+    /// Records visited so far, over all steps.
+    pub scanned: usize,
+
+    /// Records rewritten by a step so far.
+    pub updated: usize,
+
+    /// Records deleted by a step so far.
+    pub deleted: usize,
+}
+
+/// A record paired with the external record it references, returned by
+/// [`crate::local_db_state::AppDbState::join_external`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JoinedRecord {
+    /// The record of the database.
+    pub record: LocalDbModel,
+
+    /// The external record, `None` when the reference is missing or unknown.
+    pub joined: Option<LocalDbModel>,
+}
+
+/// What the server changed since the app's last pull, the input of
+/// [`crate::local_db_state::AppDbState::plan_sync`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"consumer": "sync", "remote": {"note_1": "hash_b", "note_2": null}, "sizes": {"note_1": 2048}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SyncManifest {
+    /// Delta consumer the sync layer acknowledges after each push, see
+    /// [`crate::local_db_state::AppDbState::ack_delta`].
+    #[serde(default = "SyncManifest::default_consumer")]
+    pub consumer: String,
+
+    /// Hash of each record the server changed, `null` for those it deleted.
+    #[serde(default)]
+    pub remote: BTreeMap<String, Option<String>>,
+
+    /// Size in bytes of the changed server records, used to batch the pull;
+    /// records without a size count as empty.
+    #[serde(default)]
+    pub sizes: BTreeMap<String, u64>,
+}
+
+impl SyncManifest {
+    fn default_consumer() -> String {
+        "sync".to_string()
+    }
+}
+
+/// A record changed both locally and on the server, with different results.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SyncConflict {
+    /// ID of the record.
+    pub id: String,
+
+    /// Local hash, `None` when the record was deleted locally.
+    pub local_hash: Option<String>,
+
+    /// Server hash, `None` when the record was deleted on the server.
+    pub remote_hash: Option<String>,
+}
+
+/// What a sync would do, returned by
+/// [`crate::local_db_state::AppDbState::plan_sync`]. Nothing is applied.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"sequence": 42, "push": ["note_3"], "push_deletes": [], "pull": ["note_1"],
+///  "pull_deletes": ["note_2"], "conflicts": [], "in_sync": 0,
+///  "push_batches": [{"ids": ["note_3"], "bytes": 310}],
+///  "pull_batches": [{"ids": ["note_1"], "bytes": 2048}], "oversized": []}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SyncPlan {
+    /// Commit sequence the plan was computed at, to acknowledge for the
+    /// consumer once the push succeeded.
+    pub sequence: u64,
+
+    /// IDs of the local records to upload.
+    pub push: Vec<String>,
+
+    /// IDs of the records deleted locally, to delete on the server.
+    pub push_deletes: Vec<String>,
+
+    /// IDs of the server records to download.
+    pub pull: Vec<String>,
+
+    /// IDs of the local records the server deleted.
+    pub pull_deletes: Vec<String>,
+
+    /// Records changed on both sides with different results.
+    pub conflicts: Vec<SyncConflict>,
+
+    /// Server changes already matching the local state.
+    pub in_sync: usize,
+
+    /// `push` split into batches within the [`SyncLimits`] of the database.
+    pub push_batches: Vec<SyncBatch>,
+
+    /// `pull` split into batches within the [`SyncLimits`] of the database.
+    pub pull_batches: Vec<SyncBatch>,
+
+    /// IDs in `push` or `pull` above the record size cap, left out of the
+    /// batches.
+    pub oversized: Vec<String>,
+}
+
+/// Records transferred together by one sync request.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SyncBatch {
+    /// IDs of the records in the batch.
+    pub ids: Vec<String>,
+
+    /// Total size of the records in bytes.
+    pub bytes: u64,
+}
+
+/// Size limits of sync transfers, for slow or metered networks.
+///
+/// Sizes are those of the records serialized as JSON.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"max_batch_bytes": 262144, "max_record_bytes": 1048576}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct SyncLimits {
+    /// Maximum payload of a batch; a larger record travels alone. `None` or
+    /// `0` puts every record in one batch.
+    #[serde(default)]
+    pub max_batch_bytes: Option<u64>,
+
+    /// Records above this size are not transferred. `None` or `0` sets no
+    /// cap.
+    #[serde(default)]
+    pub max_record_bytes: Option<u64>,
+}
+
+/// How a database is opened, see
+/// [`crate::local_db_state::AppDbState::init_with_options`].
+///
+/// Missing fields take their default values.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"base_dir": "/data/user/0/com.example.app/files", "map_size": 4294967296, "max_readers": 512, "no_sync": true}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DbOptions {
+    /// Directory holding the database directory; empty for the working
+    /// directory.
+    pub base_dir: String,
+
+    /// Size in bytes of the memory map, the maximum size of the database.
+    /// It only reserves address space; disk space is used as data grows.
+    pub map_size: usize,
+
+    /// Maximum number of threads and processes reading the database at
+    /// once. Apps with many isolates raise it to avoid `ReadersFull`.
+    pub max_readers: u32,
+
+    /// Skips flushing to disk on commit (`MDB_NOSYNC`), e.g. for a bulk
+    /// import. A crash may undo the last transactions, but cannot corrupt
+    /// the database unless `write_map` is also set. Data is flushed by
+    /// `flush_database`, `notify_app_background` and `close_database`.
+    pub no_sync: bool,
+
+    /// Flushes the data but not the meta page on commit (`MDB_NOMETASYNC`):
+    /// a crash may undo the last transaction, keeping the database intact.
+    pub no_meta_sync: bool,
+
+    /// Writes through a writable memory map (`MDB_WRITEMAP`), which is faster
+    /// for large writes. On some platforms the data file grows to `map_size`
+    /// at once, and a stray write from a bug can corrupt the database.
+    pub write_map: bool,
+}
+
+impl DbOptions {
+    /// Returns the LMDB environment flags selected by the durability options.
+    pub(crate) fn env_flags(&self) -> EnvironmentFlags {
+        let mut flags = EnvironmentFlags::empty();
+        flags.set(EnvironmentFlags::NO_SYNC, self.no_sync);
+        flags.set(EnvironmentFlags::NO_META_SYNC, self.no_meta_sync);
+        flags.set(EnvironmentFlags::WRITE_MAP, self.write_map);
+        flags
+    }
+
+    /// Smallest accepted `map_size`.
+    pub const MIN_MAP_SIZE: usize = 1024 * 1024;
+
+    /// Checks that the options can open a database.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `map_size`
+    /// is below [`DbOptions::MIN_MAP_SIZE`] or `max_readers` is `0`.
+    pub fn validate(&self) -> Result<(), AppResponse> {
+        if self.map_size < Self::MIN_MAP_SIZE {
+            return Err(AppResponse::BadRequest(format!(
+                "map_size must be at least {} bytes",
+                Self::MIN_MAP_SIZE
+            )));
+        }
+        if self.max_readers == 0 {
+            return Err(AppResponse::BadRequest("max_readers must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            base_dir: String::new(),
+            map_size: 1024 * 1024 * 1024,
+            max_readers: 126,
+            no_sync: false,
+            no_meta_sync: false,
+            write_map: false,
+        }
+    }
+}
+
+/// Server changes to merge into the database, see
+/// [`crate::local_db_state::AppDbState::merge_remote`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"consumer": "sync", "records": [{"id": "n1", "hash": "h2", "data": {}}], "deleted": ["n2"]}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RemoteChanges {
+    /// Delta consumer the sync layer acknowledges after each push, see
+    /// [`SyncManifest::consumer`].
+    #[serde(default = "SyncManifest::default_consumer")]
+    pub consumer: String,
+
+    /// Records written on the server.
+    #[serde(default)]
+    pub records: Vec<LocalDbModel>,
+
+    /// IDs of the records deleted on the server.
+    #[serde(default)]
+    pub deleted: Vec<String>,
+}
+
+/// One page of server changes returned by
+/// [`crate::sync_engine::SyncAdapter::pull`].
+///
+/// Missing fields take their default values.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"records": [{"id": "n1", "hash": "h2", "data": {}}], "deleted": ["n2"], "cursor": "c_1042", "has_more": false}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct PullPage {
+    /// Records written on the server.
+    pub records: Vec<LocalDbModel>,
+
+    /// IDs of the records deleted on the server.
+    pub deleted: Vec<String>,
+
+    /// Server cursor to pull the next page after, `None` to keep the
+    /// previous one.
+    pub cursor: Option<String>,
+
+    /// `true` when more pages follow.
+    pub has_more: bool,
+}
+
+/// Sync server and options of the HTTP sync client, see
+/// [`crate::local_db_state::AppDbState::sync_now`].
+///
+/// The client pulls with `GET {url}/pull?cursor=...`, answered with a
+/// [`PullPage`], and pushes with `POST {url}/push` and a [`RemoteChanges`]
+/// body.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "url": "https://api.example.com/sync",
+///   "headers": {"Authorization": "Bearer eyJhbGciOi..."},
+///   "timeout_ms": 30000,
+///   "conflict_policy": {"last_write_wins": "data.updated_at"}
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SyncEndpointConfig {
+    /// Base URL of the sync endpoints.
+    pub url: String,
+
+    /// Headers sent with every request, e.g. for authentication.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Timeout of each request in milliseconds.
+    #[serde(default = "SyncEndpointConfig::default_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Policy settling the conflicts of pulled changes; the `callback`
+    /// policy is not available over HTTP.
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+}
+
+impl SyncEndpointConfig {
+    fn default_timeout_ms() -> u64 {
+        30_000
+    }
+}
+
+/// Outcome of merging server changes.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"applied": 12, "deleted": 1, "unchanged": 0, "conflicts": ["n3"], "resolved": 2}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MergeResult {
+    /// Server records written locally.
+    pub applied: usize,
+
+    /// Local records deleted because the server deleted them.
+    pub deleted: usize,
+
+    /// Server changes matching a pending local change.
+    pub unchanged: usize,
+
+    /// IDs of the records added to, or updated in, the conflict inbox.
+    pub conflicts: Vec<String>,
+
+    /// Conflicts settled by the conflict policy instead of the inbox.
+    #[serde(default)]
+    pub resolved: usize,
+}
+
+/// Both versions of a record in the conflict inbox.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"id": "n3", "local": {"id": "n3", "hash": "a", "data": {}}, "remote": null, "detected_at": 1736812800000}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConflictEntry {
+    /// ID of the record.
+    pub id: String,
+
+    /// Local version when the conflict was detected, `None` if it was
+    /// deleted locally.
+    pub local: Option<LocalDbModel>,
+
+    /// Latest server version, `None` if it was deleted on the server.
+    pub remote: Option<LocalDbModel>,
+
+    /// Milliseconds since the Unix epoch at which the conflict was detected.
+    pub detected_at: u64,
+}
+
+/// Side of a conflict to keep.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictSide {
+    /// Keep the local version, which stays pending upload.
+    Local,
+
+    /// Take the server version.
+    Remote,
+}
+
+/// How to settle a conflict: `"local"`, `"remote"` or a merged record.
+///
+/// # JSON Format
+///
+/// ```json
+/// "remote"
+/// ```
+///
+/// ```json
+/// {"id": "n3", "hash": "merged", "data": {"title": "Both edits"}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ConflictResolution {
+    /// Keep one of the versions.
+    Keep(ConflictSide),
+
+    /// Write a record merged by the app, pending upload.
+    Merged(LocalDbModel),
+}
+
+/// How [`crate::local_db_state::AppDbState::apply_remote_changes`] settles
+/// the conflicts it detects.
+///
+/// # JSON Format
+///
+/// `"inbox"`, `"keep_local"`, `"keep_remote"`, `"callback"`, or last write
+/// wins by a timestamp path:
+///
+/// ```json
+/// {"last_write_wins": "data.updated_at"}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Move conflicts to the inbox, like
+    /// [`crate::local_db_state::AppDbState::merge_remote`].
+    #[default]
+    Inbox,
+
+    /// Keep the local version, which stays pending upload.
+    KeepLocal,
+
+    /// Take the server version.
+    KeepRemote,
+
+    /// Keep the version with the greater timestamp at this dotted path
+    /// below `data`, numbers or strings such as ISO 8601 dates. The server
+    /// version wins ties; a deletion or a version without a timestamp loses.
+    LastWriteWins(String),
+
+    /// Ask the callback passed along.
+    Callback,
+}
+
+/// Layout of an attachment, shared by both ends of a transfer, see
+/// [`crate::local_db_state::AppDbState::put_attachment`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"id": "photo_1", "bytes": 300000, "chunk_size": 262144, "checksums": [2874923104, 105231778]}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct AttachmentManifest {
+    /// ID of the attachment, e.g. stored in the data of the record it
+    /// belongs to.
+    pub id: String,
+
+    /// Size of the attachment in bytes.
+    pub bytes: u64,
+
+    /// Size in bytes of every chunk but the last.
+    pub chunk_size: u32,
+
+    /// CRC-32 (IEEE) of each chunk, in order.
+    pub checksums: Vec<u32>,
+}
+
+/// Direction of an attachment transfer.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    /// Stored locally, to send to the server.
+    Upload,
+
+    /// Announced by the server, to receive.
+    Download,
+}
+
+/// Progress of an attachment transfer.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "manifest": {"id": "photo_1", "bytes": 300000, "chunk_size": 262144, "checksums": [2874923104, 105231778]},
+///   "direction": "upload",
+///   "missing": [1],
+///   "bytes_done": 262144,
+///   "complete": false
+/// }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct AttachmentProgress {
+    /// Layout of the attachment.
+    pub manifest: AttachmentManifest,
+
+    /// Whether the attachment is being sent or received.
+    pub direction: TransferDirection,
+
+    /// Indexes of the chunks still to transfer, in order.
+    pub missing: Vec<u32>,
+
+    /// Bytes transferred so far.
+    pub bytes_done: u64,
+
+    /// Whether every chunk was transferred.
+    pub complete: bool,
+}
+
+/// One sync run, see
+/// [`crate::local_db_state::AppDbState::begin_sync_run`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "id": 42,
+///   "consumer": "sync",
+///   "started_at": 1736812800000,
+///   "finished_at": 1736812803500,
+///   "pushed": 12,
+///   "pulled": 30,
+///   "deleted": 1,
+///   "conflicts": 2,
+///   "bytes_sent": 524288,
+///   "bytes_received": 0,
+///   "failed": ["n7", "n9"],
+///   "error": null
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SyncRun {
+    /// Sequence number of the run, increasing from 1.
+    pub id: u64,
+
+    /// Delta consumer whose acknowledgements count as pushed records.
+    pub consumer: String,
+
+    /// Milliseconds since the Unix epoch at which the run started.
+    pub started_at: u64,
+
+    /// Milliseconds since the Unix epoch at which the run finished, `None`
+    /// while it is running.
+    pub finished_at: Option<u64>,
+
+    /// Records uploaded.
+    pub pushed: u64,
+
+    /// Records downloaded.
+    pub pulled: u64,
+
+    /// Local records deleted because the server deleted them.
+    pub deleted: u64,
+
+    /// Records moved to the conflict inbox.
+    pub conflicts: u64,
+
+    /// Bytes uploaded.
+    pub bytes_sent: u64,
+
+    /// Bytes downloaded.
+    pub bytes_received: u64,
+
+    /// IDs of the records or attachments that failed to sync.
+    pub failed: Vec<String>,
+
+    /// Why the run failed, `None` if it completed.
+    pub error: Option<String>,
+}
+
+/// Outcome of a sync run reported by the sync layer, added to what the
+/// database counted itself, see
+/// [`crate::local_db_state::AppDbState::finish_sync_run`].
+///
+/// Missing fields take their default values.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"bytes_sent": 20480, "failed": ["n7"], "error": null}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct SyncRunReport {
+    /// Records uploaded, besides the acknowledged delta.
+    pub pushed: u64,
+
+    /// Records downloaded, besides those merged with `merge_remote`.
+    pub pulled: u64,
+
+    /// Bytes uploaded, besides attachment chunks.
+    pub bytes_sent: u64,
+
+    /// Bytes downloaded, besides attachment chunks.
+    pub bytes_received: u64,
+
+    /// IDs of the records or attachments that failed to sync.
+    pub failed: Vec<String>,
+
+    /// Why the run failed, `None` if it completed.
+    pub error: Option<String>,
+}
+
+/// Sync state for a settings screen, e.g. "Last synced 5 min ago, 2 items
+/// failed".
+///
+/// # JSON Format
+///
+/// ```json
+/// {"running": null, "last_run": {"id": 42, "failed": ["n7", "n9"], ...}, "last_success_at": 1736812803500}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SyncStatus {
+    /// The run in progress, if any.
+    pub running: Option<SyncRun>,
+
+    /// The last finished run, if any.
+    pub last_run: Option<SyncRun>,
+
+    /// Milliseconds since the Unix epoch at which the last run without an
+    /// error finished.
+    pub last_success_at: Option<u64>,
+}
+
+/// One write of a transaction spanning `main` and named collections, see
+/// [`crate::local_db_state::AppDbState::write_transaction`]. Without
+/// `collection`, the write goes to the records of `main`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"op": "put", "collection": "orders", "record": {"id": "o1", "hash": "h1", "data": {"total": 42}}}
+/// {"op": "delete", "id": "draft_o1"}
+/// {"op": "increment", "collection": "counters", "id": "orders", "path": "data.count", "by": 1}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WriteOp {
+    /// Inserts or replaces a record.
+    Put {
+        #[serde(default)]
+        collection: Option<String>,
+        record: LocalDbModel,
+    },
+
+    /// Deletes a record; a missing record is reported, not an error.
+    Delete {
+        #[serde(default)]
+        collection: Option<String>,
+        id: String,
+    },
+
+    /// Adds `by` to the integer at a dotted `path` rooted at the record,
+    /// e.g. `data.count`. A missing value counts as `0`, and a missing
+    /// record is created with an empty hash.
+    Increment {
+        #[serde(default)]
+        collection: Option<String>,
+        id: String,
+        path: String,
+        #[serde(default = "WriteOp::default_increment")]
+        by: i64,
+    },
+}
+
+impl WriteOp {
+    fn default_increment() -> i64 {
+        1
+    }
+}
+
+/// Outcome of an upsert, see
+/// [`crate::local_db_state::AppDbState::upsert`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"inserted": true, "record": {"id": "user_123", "hash": "abc123", "data": {"name": "John"}}}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UpsertResult {
+    /// `true` if no record had the ID, `false` if it was replaced.
+    pub inserted: bool,
+
+    /// Record as written.
+    pub record: LocalDbModel,
+}
+
+/// Outcome of a compare-and-swap, see
+/// [`crate::local_db_state::AppDbState::compare_and_swap`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"swapped": false, "current": "uploading"}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CompareAndSwapResult {
+    /// `true` if the field held the expected value and was replaced.
+    pub swapped: bool,
+
+    /// Value of the field after the call: the new value if swapped, the
+    /// stored one otherwise, `null` if the field is missing.
+    pub current: JsonValue,
+}
+
+/// One operation of a JSON Patch (RFC 6902), see
+/// [`crate::local_db_state::AppDbState::apply_json_patch`]. Paths are JSON
+/// Pointers (RFC 6901) into the `data` of the record, `""` being the whole
+/// of it.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"op": "test", "path": "/status", "value": "draft"}
+/// {"op": "replace", "path": "/status", "value": "sent"}
+/// {"op": "add", "path": "/tags/-", "value": "urgent"}
+/// {"op": "move", "from": "/draft_body", "path": "/body"}
+/// {"op": "remove", "path": "/draft_at"}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOp {
+    /// Adds a member to an object, replacing an existing one, or inserts an
+    /// element into an array, `-` appending it.
+    Add { path: String, value: JsonValue },
+
+    /// Removes an existing member or element.
+    Remove { path: String },
+
+    /// Replaces an existing value.
+    Replace { path: String, value: JsonValue },
+
+    /// Removes the value at `from` and adds it at `path`.
+    Move { from: String, path: String },
+
+    /// Adds a copy of the value at `from` at `path`.
+    Copy { from: String, path: String },
+
+    /// Checks that the value at `path` equals `value`, failing the patch
+    /// otherwise.
+    Test { path: String, value: JsonValue },
+}
+
+/// A version of a record kept by history mode, see
+/// [`crate::local_db_state::AppDbState::get_history`].
+///
+/// # JSON Format
+///
+/// ```json
+/// {"version": 42, "written_at": 1717171717000, "record": {"id": "doc_1", "hash": "h3", "data": {"title": "Draft"}}}
+/// {"version": 43, "written_at": 1717171720000, "record": null}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HistoryEntry {
+    /// Commit sequence of the transaction that wrote the version, see
+    /// [`crate::local_db_state::AppDbState::commit_sequence`].
+    pub version: u64,
+
+    /// Milliseconds since the Unix epoch at which the version was written.
+    pub written_at: u64,
+
+    /// The record as written, `None` for its deletion.
+    pub record: Option<LocalDbModel>,
+}
+
+/// Outcome of a write transaction.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"written": 2, "deleted": 1, "not_found": []}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TransactionResult {
+    /// Records inserted, replaced or incremented.
+    pub written: usize,
+
+    /// Records deleted.
+    pub deleted: usize,
+
+    /// IDs of the records to delete that did not exist.
+    pub not_found: Vec<String>,
+}
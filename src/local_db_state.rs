@@ -4,15 +4,57 @@
 //! as the storage engine. It handles all database operations including initialization, CRUD operations,
 //! and connection management.
 
-use crate::local_db_model::LocalDbModel;
+use crate::local_db_model::{CacheLimit, CompactionPolicy, CompareAndSwapResult, DbOptions, DeleteManyResult, Direction, ExpirySweep, GetAllResult, GetManyResult, IndexDefinition, LocalDbModel, NumberPolicy, PageResult, QuarantinedRecord, StartupReport, SyncLimits, UpsertResult};
+use crate::scan::{scan_directed, scan_from};
 use log::{info, warn};
-use lmdb::{Environment, Database, Transaction, WriteFlags, Cursor, DatabaseFlags, Error as LmdbError};
+use lmdb::{Environment, EnvironmentFlags, Database, Transaction, Cursor, DatabaseFlags, Error as LmdbError};
+use lmdb_sys::{mdb_stat, MDB_stat, MDB_SUCCESS};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 use crate::app_response::AppResponse;
+use crate::asset::AssetDb;
+use crate::attachments::{AttachmentProgressFn, ATTACHMENTS_DB_NAME};
+use crate::cache::CACHE_DB_NAME;
+use crate::clock::Clock;
+use crate::collections::MAX_COLLECTIONS;
+use crate::conflicts::CONFLICTS_DB_NAME;
+use crate::delta::CHANGES_DB_NAME;
+use crate::digest::DIGEST_DB_NAME;
+use crate::encryption::TenantKey;
+use crate::field_encryption::FieldEncryption;
+use crate::hlc::HLC_DB_NAME;
+use crate::expiry::ExpirySweeper;
+use crate::external::ExternalCollection;
+use crate::index::{INDEX_DB_NAME, INDEX_DEFS_DB_NAME};
+use crate::meta::META_DB_NAME;
+use crate::migration::MigrationFn;
+use crate::outbox::OUTBOX_DB_NAME;
+use crate::overflow::CHUNKS_DB_NAME;
+use crate::rate_limit::TokenBucket;
+use crate::record_history::HISTORY_DB_NAME;
+use crate::resync::RESYNC_DB_NAME;
+use crate::soft_delete::TRASH_DB_NAME;
+use crate::startup::{close_handle, open_handle};
+use crate::sync_encryption::SyncKey;
+use crate::versions::VERSIONS_DB_NAME;
+use crate::watch::WatchHub;
 
 /// The default database name within the LMDB environment.
-const MAIN_DB_NAME: &str = "main";
+pub(crate) const MAIN_DB_NAME: &str = "main";
+
+/// Internal bookkeeping databases created next to `main` in the same environment.
+///
+/// They are never visible through the record APIs and are removed together
+/// with the environment on reset.
+pub(crate) const SIDE_DB_NAMES: &[&str] = &[RESYNC_DB_NAME, META_DB_NAME, INDEX_DEFS_DB_NAME, INDEX_DB_NAME, CHUNKS_DB_NAME, CACHE_DB_NAME, CHANGES_DB_NAME, CONFLICTS_DB_NAME, ATTACHMENTS_DB_NAME, HLC_DB_NAME, DIGEST_DB_NAME, OUTBOX_DB_NAME, VERSIONS_DB_NAME, HISTORY_DB_NAME, TRASH_DB_NAME];
+
+/// Named databases of an environment: `main`, the side databases and the
+/// collections.
+const MAX_DBS: u32 = 1 + SIDE_DB_NAMES.len() as u32 + MAX_COLLECTIONS;
 
 /// Database state container that manages the LMDB environment and database connections.
 ///
@@ -32,12 +74,65 @@ const MAIN_DB_NAME: &str = "main";
 /// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 pub struct AppDbState {
-    /// LMDB environment handle (None when closed)
-    env: Option<Environment>,
+    /// LMDB environment handle (None when closed), shared with background threads
+    env: Option<Arc<Environment>>,
+    /// Whether this instance registered a handle for clean-shutdown tracking
+    owns_handle: bool,
     /// Main database handle within the environment (None when closed)
     db: Option<Database>,
+    /// Internal side database handles keyed by name (empty when closed)
+    side_dbs: HashMap<&'static str, Database>,
+    /// Read-only asset database consulted by lookups with fallback
+    pub(crate) asset: Option<AssetDb>,
+    /// Read-only external collections registered by name
+    pub(crate) external: BTreeMap<String, ExternalCollection>,
+    /// Handling of unsafe numbers on write
+    pub(crate) number_policy: NumberPolicy,
+    /// Encoded value size above which large fields overflow to the chunk store
+    pub(crate) overflow_threshold: Option<usize>,
+    /// Keys of the tenants whose records are encrypted, by tenant name,
+    /// shared with the background views so a removed key is gone for them too
+    pub(crate) encryption_keys: Arc<RwLock<BTreeMap<String, TenantKey>>>,
+    /// Paths encrypted within records and their key, if set
+    pub(crate) field_encryption: Option<FieldEncryption>,
+    /// Key sealing the record bodies of sync payloads, if registered
+    pub(crate) sync_key: Option<SyncKey>,
+    /// Bounds enforced by evicting the least recently written records
+    pub(crate) cache_limit: Option<CacheLimit>,
+    /// Token bucket limiting write transactions, if a limit is set
+    pub(crate) write_limiter: Mutex<Option<TokenBucket>>,
+    /// When maintenance runs compact the database
+    pub(crate) compaction_policy: CompactionPolicy,
+    /// Age after which maintenance purges logged changes, if set
+    pub(crate) changelog_retention: Option<u64>,
+    /// Size limits of the batches planned for sync
+    pub(crate) sync_limits: SyncLimits,
+    /// Called with the progress of attachment transfers, if set
+    pub(crate) attachment_progress: Option<AttachmentProgressFn>,
+    /// Schema migration steps registered by the app, by version migrated from
+    pub(crate) migrations: BTreeMap<u64, MigrationFn>,
+    /// Outcome of the integrity fast-check run on open
+    pub(crate) startup: StartupReport,
+    /// Background thread deleting expired records, if started
+    pub(crate) sweeper: Option<ExpirySweeper>,
+    /// Sweeper configuration to restore when the app returns to the foreground
+    pub(crate) paused_sweep: Option<ExpirySweep>,
+    /// Watches notified of committed changes, shared with background threads
+    pub(crate) watch_hub: Arc<WatchHub>,
+    /// Watches of the query subscriptions, by subscription name
+    pub(crate) subscriptions: Mutex<BTreeMap<String, u64>>,
+    /// Wall clock of expiry checks and stored timestamps, shared with background threads
+    pub(crate) clock: Arc<Clock>,
+    /// Directory holding the database directory, empty for the working directory
+    pub(crate) base_dir: PathBuf,
+    /// Size of the memory map the environment is opened with
+    pub(crate) map_size: usize,
+    /// Number of reader slots the environment is opened with
+    pub(crate) max_readers: u32,
+    /// Durability flags the environment is opened with
+    pub(crate) env_flags: EnvironmentFlags,
     /// Filesystem path to the database directory
-    path: String,
+    pub(crate) path: String,
 }
 
 impl AppDbState {
@@ -45,7 +140,8 @@ impl AppDbState {
     ///
     /// This function creates an LMDB environment with the specified name, setting up
     /// a directory-based storage system. The database is configured with a 1GB memory
-    /// map size and support for up to 10 named databases.
+    /// map size (see [`AppDbState::init_with_options`]) and room for the main
+    /// database, the internal side databases and up to 32 collections.
     ///
     /// # Parameters
     ///
@@ -75,7 +171,66 @@ impl AppDbState {
     /// - LMDB environment initialization fails
     /// - The main database cannot be created within the environment
     pub fn init(name: String) -> Result<Self, LmdbError> {
-        let db_dir = format!("{name}.lmdb");
+        Self::init_with_path(name, "")
+    }
+
+    /// Initializes a new database instance or opens an existing one in the
+    /// directory `base_dir`, e.g. the app's documents or support directory,
+    /// instead of the working directory.
+    ///
+    /// Missing directories are created. An empty `base_dir` is the working
+    /// directory, as with [`AppDbState::init`]; [`AppDbState::reset_database`]
+    /// stays in `base_dir`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init_with_path("user_data".to_string(), "/data/user/0/com.example.app/files")?;
+    ///
+    /// // The database directory will be "/data/user/0/com.example.app/files/user_data.lmdb"
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`AppDbState::init`].
+    pub fn init_with_path(name: String, base_dir: &str) -> Result<Self, LmdbError> {
+        Self::init_with_options(name, &DbOptions { base_dir: base_dir.to_string(), ..DbOptions::default() })
+    }
+
+    /// Initializes a new database instance or opens an existing one with
+    /// `options`, e.g. a larger memory map for a large dataset or a smaller
+    /// one for a constrained device.
+    ///
+    /// The map size, reader limit and durability flags apply to this
+    /// instance, including after [`AppDbState::reset_database`]; LMDB raises
+    /// the map size to at least the size of the existing data.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::DbOptions;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let options = DbOptions { map_size: 4 * 1024 * 1024 * 1024, ..DbOptions::default() };
+    /// let db = AppDbState::init_with_options("catalog".to_string(), &options)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `LmdbError::Invalid` if the options fail
+    /// [`DbOptions::validate`], and otherwise fails like [`AppDbState::init`].
+    pub fn init_with_options(name: String, options: &DbOptions) -> Result<Self, LmdbError> {
+        if let Err(e) = options.validate() {
+            warn!("❌ Invalid options for database {name}: {e}");
+            return Err(LmdbError::Invalid);
+        }
+
+        let base_dir = options.base_dir.as_str();
+        let db_dir = Path::new(base_dir).join(format!("{name}.lmdb")).to_string_lossy().into_owned();
         let path = Path::new(&db_dir);
         
         info!("Initializing database at: {}", db_dir);
@@ -94,17 +249,18 @@ impl AppDbState {
         
         info!("Opening LMDB environment...");
         let env = Environment::new()
-            .set_max_dbs(10)
-            .set_map_size(1024 * 1024 * 1024) // 1GB
+            .set_max_dbs(MAX_DBS)
+            .set_map_size(options.map_size)
+            .set_max_readers(options.max_readers)
+            .set_flags(options.env_flags())
             .open(path)
-            .map_err(|e| {
+            .inspect_err(|e| {
                 warn!("❌ Failed to open LMDB environment at {}: {:?}", db_dir, e);
                 warn!("This could be due to:");
                 warn!("1. Directory permissions");
                 warn!("2. Insufficient storage space"); 
                 warn!("3. LMDB lock file issues");
                 warn!("4. Android security restrictions");
-                e
             })?;
         
         info!("✅ LMDB environment opened at {}", name);
@@ -118,95 +274,806 @@ impl AppDbState {
             Err(e) => {
                 info!("Main database not found, creating new one...");
                 env.create_db(Some(MAIN_DB_NAME), DatabaseFlags::empty())
-                    .map_err(|create_err| {
+                    .inspect_err(|create_err| {
                         warn!("❌ Failed to create main database: {:?}", create_err);
                         warn!("Original open error: {:?}", e);
-                        create_err
                     })?
             }
-        }; 
-        
+        };
+
+        let side_dbs = Self::open_side_databases(&env)?;
+        let startup = open_handle(&env, side_dbs[META_DB_NAME], &db_dir)?;
 
         info!("✅ Database initialized successfully at {}", db_dir);
-        
-        Ok(Self {
-            env: Some(env),
+
+        let state = Self {
+            env: Some(Arc::new(env)),
+            owns_handle: true,
             db: Some(db),
+            side_dbs,
+            asset: None,
+            external: BTreeMap::new(),
+            number_policy: NumberPolicy::default(),
+            overflow_threshold: None,
+            encryption_keys: Arc::default(),
+            field_encryption: None,
+            sync_key: None,
+            cache_limit: None,
+            write_limiter: Mutex::new(None),
+            compaction_policy: CompactionPolicy::default(),
+            changelog_retention: None,
+            sync_limits: SyncLimits::default(),
+            attachment_progress: None,
+            migrations: BTreeMap::new(),
+            startup,
+            sweeper: None,
+            paused_sweep: None,
+            watch_hub: Arc::default(),
+            subscriptions: Mutex::default(),
+            clock: Arc::default(),
+            base_dir: PathBuf::from(base_dir),
+            map_size: options.map_size,
+            max_readers: options.max_readers,
+            env_flags: options.env_flags(),
             path: db_dir
+        };
+        if let Err(e) = state.validate_indexes() {
+            warn!("❌ Failed to validate indexes of {}: {e}", state.path);
+        }
+        Ok(state)
+    }
+
+    /// Opens the database like [`AppDbState::init`] and makes its indexes
+    /// match `indexes` with [`AppDbState::ensure_indexes`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::IndexDefinition;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init_with_indexes("notes".to_string(), &[IndexDefinition {
+    ///     name: "by_slug".to_string(),
+    ///     paths: vec!["data.slug".to_string()],
+    /// }])?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`AppDbState::init`] and
+    /// [`AppDbState::ensure_indexes`].
+    pub fn init_with_indexes(name: String, indexes: &[IndexDefinition]) -> Result<Self, AppResponse> {
+        let state = Self::init(name)?;
+        state.ensure_indexes(indexes)?;
+        Ok(state)
+    }
+
+    /// Returns a state sharing this instance's environment, for use by a
+    /// background thread.
+    ///
+    /// The view applies the default write policies and does not count as a
+    /// handle, so closing it neither syncs nor records a clean shutdown. The
+    /// owner must stop the thread before closing the environment.
+    pub(crate) fn background_view(&self) -> Result<AppDbState, LmdbError> {
+        let env = self.env.clone().ok_or(LmdbError::Other(1))?;
+        Ok(Self {
+            env: Some(env),
+            owns_handle: false,
+            db: self.db,
+            side_dbs: self.side_dbs.clone(),
+            asset: None,
+            external: BTreeMap::new(),
+            number_policy: NumberPolicy::default(),
+            overflow_threshold: None,
+            encryption_keys: Arc::clone(&self.encryption_keys),
+            field_encryption: self.field_encryption.clone(),
+            sync_key: self.sync_key.clone(),
+            cache_limit: self.cache_limit,
+            write_limiter: Mutex::new(None),
+            compaction_policy: CompactionPolicy::default(),
+            changelog_retention: None,
+            sync_limits: SyncLimits::default(),
+            attachment_progress: None,
+            migrations: BTreeMap::new(),
+            startup: self.startup.clone(),
+            sweeper: None,
+            paused_sweep: None,
+            watch_hub: Arc::clone(&self.watch_hub),
+            subscriptions: Mutex::default(),
+            clock: Arc::clone(&self.clock),
+            base_dir: self.base_dir.clone(),
+            map_size: self.map_size,
+            max_readers: self.max_readers,
+            env_flags: self.env_flags,
+            path: self.path.clone(),
         })
     }
 
-    /// Helper to get active environment and database handles.
-    /// Returns error if the database has been explicitly closed.
-    fn env_db(&self) -> Result<(&Environment, Database), LmdbError> {
-        let env = self.env.as_ref().ok_or(LmdbError::Other(1))?;
-        let db = self.db.as_ref().copied().ok_or(LmdbError::Other(1))?;
-        Ok((env, db))
+    /// Opens (creating when missing) every internal side database.
+    fn open_side_databases(env: &Environment) -> Result<HashMap<&'static str, Database>, LmdbError> {
+        SIDE_DB_NAMES
+            .iter()
+            .map(|name| {
+                env.create_db(Some(name), DatabaseFlags::empty())
+                    .inspect_err(|e| warn!("❌ Failed to open side database {}: {:?}", name, e))
+                    .map(|db| (*name, db))
+            })
+            .collect()
+    }
+
+    /// Opens the environment at `path` with its main and side databases,
+    /// using the options this instance was opened with.
+    fn open_environment(&self, path: &Path) -> Result<(Environment, Database, HashMap<&'static str, Database>), LmdbError> {
+        let env = Environment::new()
+            .set_max_dbs(MAX_DBS)
+            .set_map_size(self.map_size)
+            .set_max_readers(self.max_readers)
+            .set_flags(self.env_flags)
+            .open(path)?;
+        let db = env.create_db(Some(MAIN_DB_NAME), DatabaseFlags::empty())?;
+        let side_dbs = Self::open_side_databases(&env)?;
+        Ok((env, db, side_dbs))
+    }
+
+    /// Reopens the environment at `self.path` after [`close_database`](Self::close_database).
+    pub(crate) fn reopen(&mut self) -> Result<(), LmdbError> {
+        let (env, db, side_dbs) = self.open_environment(Path::new(&self.path))?;
+        open_handle(&env, side_dbs[META_DB_NAME], &self.path)?;
+
+        self.env = Some(Arc::new(env));
+        self.db = Some(db);
+        self.side_dbs = side_dbs;
+        Ok(())
+    }
+
+    /// Helper to get active environment and database handles.
+    /// Returns error if the database has been explicitly closed.
+    pub(crate) fn env_db(&self) -> Result<(&Environment, Database), LmdbError> {
+        let env = self.env.as_deref().ok_or(LmdbError::Other(1))?;
+        let db = self.db.as_ref().copied().ok_or(LmdbError::Other(1))?;
+        Ok((env, db))
+    }
+
+    /// Same as [`env_db`](Self::env_db) but for one of the internal side databases.
+    pub(crate) fn side_db(&self, name: &str) -> Result<(&Environment, Database), LmdbError> {
+        let env = self.env.as_deref().ok_or(LmdbError::Other(1))?;
+        let db = self.side_dbs.get(name).copied().ok_or(LmdbError::Other(1))?;
+        Ok((env, db))
+    }
+
+    /// Inserts a new record into the database.
+    ///
+    /// This method serializes the provided model to JSON and stores it using the model's
+    /// ID as the key. The operation is performed within a write transaction to ensure
+    /// data consistency.
+    ///
+    /// # Parameters
+    ///
+    /// * `model` - The data model to insert into the database
+    ///
+    /// # Returns
+    ///
+    /// Returns the inserted model on success, or an error response if the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::{local_db_state::AppDbState, local_db_model::LocalDbModel};
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let model = LocalDbModel {
+    ///     id: "user_123".to_string(),
+    ///     hash: "abc123".to_string(),
+    ///     data: json!({"name": "John", "age": 30}),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let result = db.post(model)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - JSON serialization fails
+    /// - Transaction creation fails
+    /// - A number is rejected by the [`NumberPolicy`]
+    /// - The write is throttled by the write rate limit
+    /// - Database write operation fails
+    /// - Transaction commit fails
+    pub fn post(&self, mut model: LocalDbModel) -> Result<LocalDbModel, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db().map_err(AppResponse::from)?;
+        let mut txn = env.begin_rw_txn().map_err(AppResponse::from)?;
+        let writer = self.record_writer(&txn).map_err(AppResponse::from)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn).map_err(AppResponse::from)?;
+
+        Ok(model)
+    }
+
+    /// Retrieves a record from the database by its ID.
+    ///
+    /// This method performs a read-only lookup using the provided ID as the key.
+    /// If found, the JSON data is deserialized back into a `LocalDbModel`.
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - The unique identifier of the record to retrieve
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(Some(LocalDbModel))` if the record is found, `Ok(None)` if not found,
+    /// or an error response if the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// match db.get_by_id("user_123")? {
+    ///     Some(model) => println!("Found user: {:?}", model),
+    ///     None => println!("User not found"),
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - The stored value header names an unsupported format
+    /// - The stored data is not valid UTF-8
+    /// - JSON deserialization fails
+    pub fn get_by_id(&self, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+
+        match txn.get(db, &id) {
+            Ok(bytes) => Ok(Some(self.decode_record(&txn, id.as_bytes(), bytes)?)),
+            Err(LmdbError::NotFound) => {
+                info!("No value found for id {id}");
+                Ok(None)
+            }
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Checks whether a record with the given ID exists.
+    ///
+    /// Only the key is looked up; the stored value is never decoded, so this
+    /// also reports records that are quarantined.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// if !db.record_exists("user_123")? {
+    ///     println!("New record, insert it");
+    /// }
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The database has been closed
+    /// - Transaction creation fails
+    pub fn record_exists(&self, id: &str) -> Result<bool, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+
+        match txn.get(db, &id) {
+            Ok(_) => Ok(true),
+            Err(LmdbError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Retrieves several records by ID using a single read transaction.
+    ///
+    /// # Parameters
+    ///
+    /// * `ids` - The identifiers of the records to retrieve
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`GetManyResult`] with the found records and the IDs that had
+    /// no matching record, or an error if the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let ids = vec!["user_1".to_string(), "user_2".to_string()];
+    /// let result = db.get_by_ids(&ids)?;
+    /// println!("Found {}, missing {:?}", result.found.len(), result.missing);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - A stored value cannot be decoded
+    pub fn get_by_ids(&self, ids: &[String]) -> Result<GetManyResult, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut result = GetManyResult::default();
+
+        for id in ids {
+            match txn.get(db, id) {
+                Ok(bytes) => result.found.push(self.decode_record(&txn, id.as_bytes(), bytes)?),
+                Err(LmdbError::NotFound) => result.missing.push(id.clone()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the number of records in the database.
+    ///
+    /// The count comes from the B-tree statistics (`mdb_stat`), so no record is
+    /// read or deserialized. Undecodable records are counted as well.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// println!("Database holds {} records", db.count_records()?);
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The database has been closed
+    /// - Transaction creation fails
+    pub fn count_records(&self) -> Result<usize, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+
+        let mut stat = MaybeUninit::<MDB_stat>::uninit();
+        // SAFETY: `txn` and `db` are live handles of the same environment and
+        // `mdb_stat` fully initializes `stat` when it returns success.
+        let stat = unsafe {
+            match mdb_stat(txn.txn(), db.dbi(), stat.as_mut_ptr()) {
+                MDB_SUCCESS => stat.assume_init(),
+                code => return Err(LmdbError::from_err_code(code)),
+            }
+        };
+
+        Ok(stat.ms_entries)
+    }
+
+    /// Retrieves all records from the database.
+    ///
+    /// This method iterates through all key-value pairs in the database,
+    /// deserializing each JSON value back into a `LocalDbModel`. Records that
+    /// fail to deserialize are logged and skipped.
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector containing all successfully deserialized records,
+    /// or an error if the database operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let all_records = db.get()?;
+    /// println!("Found {} records", all_records.len());
+    ///
+    /// for record in all_records {
+    ///     println!("Record ID: {}", record.id);
+    /// }
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get(&self) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let mut models = Vec::new();
+        
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+        
+        for (key, value) in cursor.iter() {
+            match self.decode_record(&txn, key, value) {
+                Ok(model) => models.push(model),
+                Err(e) => info!("Error decoding model: {e}"),
+            }
+        }
+
+        Ok(models)
+    }
+
+    /// Retrieves all records in the given key order.
+    ///
+    /// Equivalent to [`get`](Self::get) for [`Direction::Asc`]; with
+    /// [`Direction::Desc`] the cursor walks backwards from the last key.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::{local_db_model::Direction, local_db_state::AppDbState};
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let newest_first = db.get_all_ordered(Direction::Desc)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_all_ordered(&self, direction: Direction) -> Result<Vec<LocalDbModel>, LmdbError> {
+        self.get_paginated_ordered(usize::MAX, 0, direction)
+    }
+
+    /// Lists the IDs of all records in key order.
+    ///
+    /// Only the cursor keys are read, so no value is decoded and quarantined
+    /// records are listed as well.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// for id in db.get_all_ids()? {
+    ///     println!("Record ID: {}", id);
+    /// }
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_all_ids(&self) -> Result<Vec<String>, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        let ids = cursor
+            .iter()
+            .map(|(key, _)| String::from_utf8_lossy(key).into_owned())
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// Retrieves one page of records in key order.
+    ///
+    /// The first `offset` entries are skipped without being decoded, then up to
+    /// `limit` records are returned. As with [`AppDbState::get`], records that
+    /// fail to decode are logged and skipped, but they still count towards the
+    /// offset so page boundaries stay stable.
+    ///
+    /// # Parameters
+    ///
+    /// * `limit` - Maximum number of records to return
+    /// * `offset` - Number of entries to skip from the start
+    ///
+    /// # Returns
+    ///
+    /// Returns the records of the requested page, or an error if the database
+    /// operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// // Third page of 50 records
+    /// let page = db.get_paginated(50, 100)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_paginated(&self, limit: usize, offset: usize) -> Result<Vec<LocalDbModel>, LmdbError> {
+        self.get_paginated_ordered(limit, offset, Direction::Asc)
+    }
+
+    /// Same as [`get_paginated`](Self::get_paginated), walking the keys in `direction`.
+    ///
+    /// With [`Direction::Desc`] the offset counts from the last key, so newest-first
+    /// lists over time-ordered keys need no client-side reversal.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_paginated_ordered(&self, limit: usize, offset: usize, direction: Direction) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let mut models = Vec::with_capacity(limit.min(1024));
+
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+
+        for (key, value) in scan_directed(&cursor, None, direction).skip(offset).take(limit) {
+            match self.decode_record(&txn, key, value) {
+                Ok(model) => models.push(model),
+                Err(e) => info!("Error decoding model: {e}"),
+            }
+        }
+
+        Ok(models)
+    }
+
+    /// Retrieves the page of records that follows a continuation token.
+    ///
+    /// The cursor is positioned directly at `last_key` with `MDB_SET_RANGE`, so
+    /// fetching any page costs the same regardless of how deep into the key
+    /// space it is. Entries that fail to decode are logged and skipped but still
+    /// count towards `limit`.
+    ///
+    /// # Parameters
+    ///
+    /// * `last_key` - Token returned by the previous call, or `None` to start
+    ///   from the first record. The record with this key itself is not returned.
+    /// * `limit` - Maximum number of records to return, at least 1
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`PageResult`] with the records and the token for the next page
+    /// (`None` when the end has been reached), or an error if the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let mut token = None;
+    /// loop {
+    ///     let page = db.get_page_after(token.as_deref(), 100)?;
+    ///     println!("Loaded {} records", page.records.len());
+    ///     match page.next_token {
+    ///         Some(next) => token = Some(next),
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - `limit` is zero, as an empty page would look like the end of the data
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_page_after(&self, last_key: Option<&str>, limit: usize) -> Result<PageResult, AppResponse> {
+        self.get_page_after_ordered(last_key, limit, Direction::Asc)
+    }
+
+    /// Same as [`get_page_after`](Self::get_page_after), walking the keys in `direction`.
+    ///
+    /// With [`Direction::Desc`] each page continues below `last_key`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - `limit` is zero
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_page_after_ordered(&self, last_key: Option<&str>, limit: usize, direction: Direction) -> Result<PageResult, AppResponse> {
+        if limit == 0 {
+            return Err(AppResponse::BadRequest("Page limit must be at least 1".to_string()));
+        }
+        let mut page = PageResult::default();
+
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+
+        let last_key = last_key.filter(|key| !key.is_empty());
+        let mut entries = scan_directed(&cursor, last_key.map(str::as_bytes), direction)
+            .skip_while(|(key, _)| Some(*key) == last_key.map(str::as_bytes));
+
+        let mut last_visited = None;
+        for (key, value) in entries.by_ref().take(limit) {
+            last_visited = Some(key);
+            match self.decode_record(&txn, key, value) {
+                Ok(model) => page.records.push(model),
+                Err(e) => info!("Error decoding model: {e}"),
+            }
+        }
+
+        if entries.next().is_some() {
+            page.next_token = last_visited.map(|key| String::from_utf8_lossy(key).into_owned());
+        }
+
+        Ok(page)
     }
 
-    /// Inserts a new record into the database.
+    /// Retrieves all records whose ID starts with `prefix`, in key order.
     ///
-    /// This method serializes the provided model to JSON and stores it using the model's
-    /// ID as the key. The operation is performed within a write transaction to ensure
-    /// data consistency.
+    /// The cursor is positioned at the first matching key with `MDB_SET_RANGE`
+    /// and stops at the first key outside the prefix, so only the matching
+    /// range is visited. This makes namespaced keys such as `todo:123` cheap to
+    /// query. Records that fail to decode are logged and skipped.
     ///
-    /// # Parameters
+    /// # Examples
     ///
-    /// * `model` - The data model to insert into the database
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let todos = db.get_by_prefix("todo:")?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_by_prefix(&self, prefix: &str) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        self.prefix_records(&txn, db, prefix)
+    }
+
+    /// Retrieves the records of several key prefixes in one read transaction.
+    ///
+    /// Meant for dashboards composed of several small collections: every
+    /// group comes from the same snapshot and a single call replaces one
+    /// [`AppDbState::get_by_prefix`] per collection. Each prefix is scanned
+    /// on its own, so records matching overlapping prefixes appear in every
+    /// group they match.
     ///
     /// # Returns
     ///
-    /// Returns the inserted model on success, or an error response if the operation fails.
+    /// Returns a map from each prefix to its records in key order; prefixes
+    /// without records map to an empty list.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use offline_first_core::{local_db_state::AppDbState, local_db_model::LocalDbModel};
-    /// use serde_json::json;
+    /// use offline_first_core::local_db_state::AppDbState;
     ///
     /// let db = AppDbState::init("test_db".to_string())?;
     ///
-    /// let model = LocalDbModel {
-    ///     id: "user_123".to_string(),
-    ///     hash: "abc123".to_string(),
-    ///     data: json!({"name": "John", "age": 30}),
-    /// };
-    ///
-    /// let result = db.post(model)?;
-    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// let prefixes = vec!["todo:".to_string(), "project:".to_string()];
+    /// let groups = db.get_groups(&prefixes)?;
+    /// println!("{} todos", groups["todo:"].len());
+    /// # Ok::<(), lmdb::Error>(())
     /// ```
     ///
     /// # Errors
     ///
     /// This function will return an error if:
-    /// - JSON serialization fails
     /// - Transaction creation fails
-    /// - Database write operation fails
-    /// - Transaction commit fails
-    pub fn post(&self, model: LocalDbModel) -> Result<LocalDbModel, AppResponse> {
-        let json = serde_json::to_string(&model)?;
+    /// - Cursor creation fails
+    pub fn get_groups(&self, prefixes: &[String]) -> Result<BTreeMap<String, Vec<LocalDbModel>>, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
 
-        let (env, db) = self.env_db().map_err(AppResponse::from)?;
-        let mut txn = env.begin_rw_txn().map_err(AppResponse::from)?;
-        txn.put(db, &model.id, &json, WriteFlags::empty()).map_err(AppResponse::from)?;
-        txn.commit().map_err(AppResponse::from)?;
+        let mut groups = BTreeMap::new();
+        for prefix in prefixes {
+            groups.insert(prefix.clone(), self.prefix_records(&txn, db, prefix)?);
+        }
+        Ok(groups)
+    }
 
-        Ok(model)
+    fn prefix_records<T: Transaction>(&self, txn: &T, db: Database, prefix: &str) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let cursor = txn.open_ro_cursor(db)?;
+
+        let start = Some(prefix.as_bytes()).filter(|p| !p.is_empty());
+        let models = scan_from(&cursor, start)
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .filter_map(|(key, value)| match self.decode_record(txn, key, value) {
+                Ok(model) => Some(model),
+                Err(e) => {
+                    info!("Error decoding model: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(models)
     }
 
-    /// Retrieves a record from the database by its ID.
+    /// Retrieves the records whose ID lies in `[start_key, end_key)`, in key order.
     ///
-    /// This method performs a read-only lookup using the provided ID as the key.
-    /// If found, the JSON data is deserialized back into a `LocalDbModel`.
+    /// Keys sort bytewise, so time-ordered keys (e.g. ULIDs) can be queried as
+    /// "everything between these two timestamps". The cursor is positioned at
+    /// `start_key` with `MDB_SET_RANGE` and the scan stops at `end_key`, so only
+    /// the requested range is visited. Records that fail to decode are logged
+    /// and skipped but still count towards `limit`.
     ///
     /// # Parameters
     ///
-    /// * `id` - The unique identifier of the record to retrieve
+    /// * `start_key` - Inclusive lower bound, or `None` to start at the first record
+    /// * `end_key` - Exclusive upper bound, or `None` to run to the last record
+    /// * `limit` - Maximum number of records to return
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let january = db.get_range(Some("01HKA"), Some("01HNE"), 500)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn get_range(&self, start_key: Option<&str>, end_key: Option<&str>, limit: usize) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+
+        let start = start_key.filter(|key| !key.is_empty()).map(str::as_bytes);
+        let end = end_key.map(str::as_bytes);
+
+        let models = scan_from(&cursor, start)
+            .take_while(|(key, _)| end.is_none_or(|end| *key < end))
+            .take(limit)
+            .filter_map(|(key, value)| match self.decode_record(&txn, key, value) {
+                Ok(model) => Some(model),
+                Err(e) => {
+                    info!("Error decoding model: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(models)
+    }
+
+    /// Retrieves all records, reporting undecodable ones instead of skipping them.
+    ///
+    /// Unlike [`AppDbState::get`], records whose value fails to decode are returned
+    /// as [`QuarantinedRecord`] entries, so data loss is visible to the caller.
     ///
     /// # Returns
     ///
-    /// Returns `Ok(Some(LocalDbModel))` if the record is found, `Ok(None)` if not found,
-    /// or `Err(LmdbError)` if the operation fails.
+    /// Returns a [`GetAllResult`] with the decoded records and the quarantined ones,
+    /// or an error if the database operation fails.
     ///
     /// # Examples
     ///
@@ -215,9 +1082,9 @@ impl AppDbState {
     ///
     /// let db = AppDbState::init("test_db".to_string())?;
     ///
-    /// match db.get_by_id("user_123")? {
-    ///     Some(model) => println!("Found user: {:?}", model),
-    ///     None => println!("User not found"),
+    /// let result = db.get_with_quarantine()?;
+    /// for entry in &result.quarantined {
+    ///     println!("Record {} is unreadable: {}", entry.id, entry.error);
     /// }
     /// # Ok::<(), lmdb::Error>(())
     /// ```
@@ -226,38 +1093,36 @@ impl AppDbState {
     ///
     /// This function will return an error if:
     /// - Transaction creation fails
-    /// - The stored data is not valid UTF-8
-    /// - JSON deserialization fails
-    pub fn get_by_id(&self, id: &str) -> Result<Option<LocalDbModel>, LmdbError> {
+    /// - Cursor creation fails
+    pub fn get_with_quarantine(&self) -> Result<GetAllResult, LmdbError> {
+        let mut result = GetAllResult::default();
+
         let (env, db) = self.env_db()?;
         let txn = env.begin_ro_txn()?;
-        
-        match txn.get(db, &id) {
-            Ok(bytes) => {
-                let json_str = std::str::from_utf8(bytes)
-                    .map_err(|_| LmdbError::Other(1))?;
-                let model = serde_json::from_str(json_str)
-                    .map_err(|_| LmdbError::Other(1))?;
-                Ok(Some(model))
-            }
-            Err(LmdbError::NotFound) => {
-                info!("No value found for id {id}");
-                Ok(None)
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        for (key, value) in cursor.iter() {
+            match self.decode_record(&txn, key, value) {
+                Ok(model) => result.records.push(model),
+                Err(e) => result.quarantined.push(QuarantinedRecord {
+                    id: String::from_utf8_lossy(key).into_owned(),
+                    error: e.to_string(),
+                    raw_size: value.len(),
+                }),
             }
-            Err(e) => Err(e)
         }
+
+        Ok(result)
     }
 
-    /// Retrieves all records from the database.
+    /// Lists every record that cannot be decoded.
     ///
-    /// This method iterates through all key-value pairs in the database,
-    /// deserializing each JSON value back into a `LocalDbModel`. Records that
-    /// fail to deserialize are logged and skipped.
+    /// Healthy records are only checked, not built, so this is cheaper than
+    /// [`AppDbState::get_with_quarantine`].
     ///
     /// # Returns
     ///
-    /// Returns a vector containing all successfully deserialized records,
-    /// or an error if the database operation fails.
+    /// Returns the quarantined entries, or an error if the database operation fails.
     ///
     /// # Examples
     ///
@@ -266,12 +1131,8 @@ impl AppDbState {
     ///
     /// let db = AppDbState::init("test_db".to_string())?;
     ///
-    /// let all_records = db.get()?;
-    /// println!("Found {} records", all_records.len());
-    ///
-    /// for record in all_records {
-    ///     println!("Record ID: {}", record.id);
-    /// }
+    /// let broken = db.quarantine_list()?;
+    /// println!("{} unreadable records", broken.len());
     /// # Ok::<(), lmdb::Error>(())
     /// ```
     ///
@@ -280,26 +1141,23 @@ impl AppDbState {
     /// This function will return an error if:
     /// - Transaction creation fails
     /// - Cursor creation fails
-    pub fn get(&self) -> Result<Vec<LocalDbModel>, LmdbError> {
-        let mut models = Vec::new();
-        
+    pub fn quarantine_list(&self) -> Result<Vec<QuarantinedRecord>, LmdbError> {
         let (env, db) = self.env_db()?;
         let txn = env.begin_ro_txn()?;
         let mut cursor = txn.open_ro_cursor(db)?;
-        
-        for (_, value) in cursor.iter() {
-            match std::str::from_utf8(value) {
-                Ok(json_str) => {
-                    match serde_json::from_str::<LocalDbModel>(json_str) {
-                        Ok(model) => models.push(model),
-                        Err(e) => info!("Error deserializing model: {e:?}"),
-                    }
-                }
-                Err(e) => info!("Error converting to UTF-8: {e:?}"),
-            }
-        }
-        
-        Ok(models)
+
+        let quarantined = cursor
+            .iter()
+            .filter_map(|(key, value)| {
+                let error = self.check_record(&txn, key, value).err()?;
+                Some(QuarantinedRecord {
+                    id: String::from_utf8_lossy(key).into_owned(),
+                    error: error.to_string(),
+                    raw_size: value.len(),
+                })
+            })
+            .collect();
+        Ok(quarantined)
     }
 
     /// Deletes a record from the database by its ID.
@@ -327,33 +1185,81 @@ impl AppDbState {
     ///     true => println!("Record deleted successfully"),
     ///     false => println!("Record not found"),
     /// }
-    /// # Ok::<(), lmdb::Error>(())
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
     /// ```
     ///
     /// # Errors
     ///
     /// This function will return an error if:
+    /// - The write is throttled by the write rate limit
     /// - Transaction creation fails
     /// - Database operations fail
     /// - Transaction commit fails
-    pub fn delete_by_id(&self, id: &str) -> Result<bool, LmdbError> {
+    pub fn delete_by_id(&self, id: &str) -> Result<bool, AppResponse> {
+        self.throttle_write()?;
         let (env, db) = self.env_db()?;
         let mut txn = env.begin_rw_txn()?;
-        
-        let existed = match txn.get(db, &id) {
-            Ok(_) => true,
-            Err(LmdbError::NotFound) => false,
-            Err(e) => return Err(e),
-        };
-        
-        if existed {
-            txn.del(db, &id, None)?;
-        }
-        
-        txn.commit()?;
+        let writer = self.record_writer(&txn)?;
+
+        let existed = writer.del(&mut txn, db, id.as_bytes())?;
+
+        writer.commit(txn)?;
         Ok(existed)
     }
 
+    /// Deletes several records in a single write transaction.
+    ///
+    /// Either every deletion is committed or none is, which makes this suitable
+    /// for sync reconciliation where large sets of records must be purged at once.
+    ///
+    /// # Parameters
+    ///
+    /// * `ids` - The identifiers of the records to delete
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`DeleteManyResult`] listing which IDs were deleted and which
+    /// were not present, or an error if the operation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let ids = vec!["user_1".to_string(), "user_2".to_string()];
+    /// let result = db.delete_many(&ids)?;
+    /// println!("Deleted {}, missing {}", result.deleted.len(), result.not_found.len());
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The write is throttled by the write rate limit
+    /// - Transaction creation fails
+    /// - Database operations fail
+    /// - Transaction commit fails
+    pub fn delete_many(&self, ids: &[String]) -> Result<DeleteManyResult, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        let mut result = DeleteManyResult::default();
+
+        for id in ids {
+            if writer.del(&mut txn, db, id.as_bytes())? {
+                result.deleted.push(id.clone());
+            } else {
+                result.not_found.push(id.clone());
+            }
+        }
+
+        writer.commit(txn)?;
+        Ok(result)
+    }
+
     /// Updates an existing record in the database.
     ///
     /// This method first verifies that a record with the given ID exists, then
@@ -380,13 +1286,14 @@ impl AppDbState {
     ///     id: "user_123".to_string(),
     ///     hash: "new_hash".to_string(),
     ///     data: json!({"name": "Jane", "age": 25}),
+    ///     ..Default::default()
     /// };
     ///
     /// match db.put(updated_model)? {
     ///     Some(model) => println!("Updated: {:?}", model),
     ///     None => println!("Record not found for update"),
     /// }
-    /// # Ok::<(), lmdb::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Errors
@@ -394,29 +1301,198 @@ impl AppDbState {
     /// This function will return an error if:
     /// - Transaction creation fails
     /// - JSON serialization fails
+    /// - A number is rejected by the [`NumberPolicy`]
+    /// - The write is throttled by the write rate limit
     /// - Database operations fail
     /// - Transaction commit fails
-    pub fn put(&self, model: LocalDbModel) -> Result<Option<LocalDbModel>, LmdbError> {
+    pub fn put(&self, mut model: LocalDbModel) -> Result<Option<LocalDbModel>, AppResponse> {
+        self.throttle_write()?;
         let (env, db) = self.env_db()?;
         let mut txn = env.begin_rw_txn()?;
         
         let exists = match txn.get(db, &model.id) {
             Ok(_) => true,
             Err(LmdbError::NotFound) => false,
-            Err(e) => return Err(e),
+            Err(e) => return Err(e.into()),
         };
         
         if exists {
-            let json = serde_json::to_string(&model)
-                .map_err(|_| LmdbError::Other(1))?;
-            txn.put(db, &model.id, &json, WriteFlags::empty())?;
-            txn.commit()?;
+            let writer = self.record_writer(&txn)?;
+            self.write_model(&mut txn, &writer, db, &mut model)?;
+            writer.commit(txn)?;
             Ok(Some(model))
         } else {
             Ok(None)
         }
     }
 
+    /// Inserts the record if no record has its ID and replaces it otherwise,
+    /// in one transaction, so callers need no prior existence check.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::{local_db_state::AppDbState, local_db_model::LocalDbModel};
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let model = LocalDbModel {
+    ///     id: "user_123".to_string(),
+    ///     hash: "abc123".to_string(),
+    ///     data: json!({"name": "John"}),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let result = db.upsert(model)?;
+    /// println!("{}", if result.inserted { "Inserted" } else { "Updated" });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`AppDbState::post`].
+    pub fn upsert(&self, mut model: LocalDbModel) -> Result<UpsertResult, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let inserted = match txn.get(db, &model.id) {
+            Ok(_) => false,
+            Err(LmdbError::NotFound) => true,
+            Err(e) => return Err(e.into()),
+        };
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn)?;
+        Ok(UpsertResult { inserted, record: model })
+    }
+
+    /// Replaces a record only if the stored version still has the hash
+    /// `expected_hash`, the hash the caller read, so concurrent writers on
+    /// other isolates or a sync are not overwritten unseen.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::{app_response::AppResponse, local_db_state::AppDbState};
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// if let Some(mut model) = db.get_by_id("user_123")? {
+    ///     let read_hash = model.hash.clone();
+    ///     model.data["age"] = 26.into();
+    ///     model.hash = "new_hash".to_string();
+    ///     match db.update_if_hash(model, &read_hash) {
+    ///         Err(AppResponse::Conflict { current_hash, .. }) => println!("Changed meanwhile, now {current_hash}"),
+    ///         result => println!("{result:?}"),
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if no record has the ID,
+    /// [`AppResponse::Conflict`] with the stored hash if it differs from
+    /// `expected_hash`, or the errors of [`AppDbState::put`].
+    pub fn update_if_hash(&self, mut model: LocalDbModel, expected_hash: &str) -> Result<LocalDbModel, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let current_hash = match txn.get(db, &model.id) {
+            Ok(value) => serde_json::from_str::<LocalDbModel>(&self.record_json(model.id.as_bytes(), value)?)?.hash,
+            Err(LmdbError::NotFound) => return Err(AppResponse::NotFound(format!("No model found with id: {}", model.id))),
+            Err(e) => return Err(e.into()),
+        };
+        if current_hash != expected_hash {
+            return Err(AppResponse::Conflict {
+                message: format!("Record {} has hash {current_hash}, expected {expected_hash}", model.id),
+                current_hash,
+            });
+        }
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn)?;
+        Ok(model)
+    }
+
+    /// Replaces the value at the dotted `field_path` of a record with `new`
+    /// only if it is still `expected`, in one write transaction, so state
+    /// machines such as `status: queued -> uploading` can move on safely
+    /// when several isolates or workers race for the same record.
+    ///
+    /// A missing field compares as `null`; swapping it creates the field and
+    /// any missing parent objects. The record keeps its `hash`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("uploads".to_string())?;
+    ///
+    /// let claim = db.compare_and_swap("upload_1", "data.status", &json!("queued"), &json!("uploading"))?;
+    /// if claim.swapped {
+    ///     // This worker owns the upload
+    /// } else {
+    ///     println!("Already {}", claim.current);
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `field_path` is not a path
+    /// below `data` or a parent of the field is not an object,
+    /// [`AppResponse::NotFound`] if no record has the ID, or the errors of
+    /// [`AppDbState::put`].
+    pub fn compare_and_swap(&self, id: &str, field_path: &str, expected: &JsonValue, new: &JsonValue) -> Result<CompareAndSwapResult, AppResponse> {
+        let segments: Vec<&str> = match field_path.strip_prefix("data.") {
+            Some(rest) if !rest.split('.').any(str::is_empty) => rest.split('.').collect(),
+            _ => return Err(AppResponse::BadRequest(format!("Compare-and-swap field {field_path} is not a path below data"))),
+        };
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let mut model = match txn.get(db, &id) {
+            Ok(value) => self.decode_record(&txn, id.as_bytes(), value)?,
+            Err(LmdbError::NotFound) => return Err(AppResponse::NotFound(format!("No model found with id: {id}"))),
+            Err(e) => return Err(e.into()),
+        };
+        let current = segments
+            .iter()
+            .try_fold(&model.data, |value, segment| value.as_object()?.get(*segment))
+            .cloned()
+            .unwrap_or(JsonValue::Null);
+        if current != *expected {
+            return Ok(CompareAndSwapResult { swapped: false, current });
+        }
+
+        let (field, parents) = segments.split_last().unwrap_or((&"", &[]));
+        let mut node = &mut model.data;
+        for segment in parents {
+            let JsonValue::Object(map) = node else {
+                return Err(AppResponse::BadRequest(format!("Cannot swap {field_path} of record {id}: a parent is not an object")));
+            };
+            node = map.entry(*segment).or_insert_with(|| JsonValue::Object(Map::new()));
+        }
+        let JsonValue::Object(map) = node else {
+            return Err(AppResponse::BadRequest(format!("Cannot swap {field_path} of record {id}: a parent is not an object")));
+        };
+        map.insert(field.to_string(), new.clone());
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn)?;
+        Ok(CompareAndSwapResult { swapped: true, current: new.clone() })
+    }
+
     /// Removes all records from the database while preserving the database structure.
     ///
     /// This method iterates through all records and deletes them individually.
@@ -464,7 +1540,9 @@ impl AppDbState {
                 Err(e) => warn!("Error deleting key: {e:?}"),
             }
         }
-        txn.commit()?;
+        let writer = self.record_writer(&txn)?;
+        writer.clear(&mut txn)?;
+        writer.commit(txn)?;
         Ok(count)
     }
 
@@ -473,7 +1551,7 @@ impl AppDbState {
     /// This operation performs the following steps:
     /// 1. Closes the current database environment
     /// 2. Removes the existing database directory and all its contents
-    /// 3. Creates a new database environment with the specified name
+    /// 3. Creates a new database environment with the specified name, in the same directory
     /// 4. Updates the internal state to use the new database
     ///
     /// # Parameters
@@ -510,26 +1588,25 @@ impl AppDbState {
     /// Ensure that any important data is backed up before calling this method.
     pub fn reset_database(&mut self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
         self.close_database()?;
+        // The indexes of a paused sweeper go away with the data
+        self.paused_sweep = None;
         if Path::new(&self.path).exists() {
             fs::remove_dir_all(&self.path)?;
         }
         
-        let new_db_dir = format!("{name}.lmdb");
+        let new_db_dir = self.base_dir.join(format!("{name}.lmdb")).to_string_lossy().into_owned();
         let path = Path::new(&new_db_dir);
         
         if !path.exists() {
             fs::create_dir_all(path)?;
         }
         
-        let new_env = Environment::new()
-            .set_max_dbs(10)
-            .set_map_size(1024 * 1024 * 1024)
-            .open(path)?;
-            
-        let new_db = new_env.create_db(Some(MAIN_DB_NAME), DatabaseFlags::empty())?;
+        let (new_env, new_db, new_side_dbs) = self.open_environment(path)?;
+        self.startup = open_handle(&new_env, new_side_dbs[META_DB_NAME], &new_db_dir)?;
         
-        self.env = Some(new_env);
+        self.env = Some(Arc::new(new_env));
         self.db = Some(new_db);
+        self.side_dbs = new_side_dbs;
         self.path = new_db_dir;
         
         Ok(true)
@@ -565,8 +1642,19 @@ impl AppDbState {
     /// The actual cleanup occurs when the `AppDbState` instance is dropped.
     /// This method primarily serves as documentation and explicit lifecycle management
     /// for integration scenarios.
+    ///
+    /// Closing the last handle on the database records a clean shutdown, see
+    /// [`startup_report`](Self::startup_report). A running expiry sweeper is
+    /// stopped first.
     pub fn close_database(&mut self) -> Result<(), LmdbError> {
-        if let Some(env) = self.env.take() {
+        self.stop_expiry_sweeper();
+        self.drop_subscriptions();
+        if let Some(env) = self.env.take().filter(|_| self.owns_handle) {
+            if let Some(&meta) = self.side_dbs.get(META_DB_NAME) {
+                if let Err(e) = close_handle(&env, meta, &self.path) {
+                    warn!("Failed to record clean shutdown of {}: {e:?}", self.path);
+                }
+            }
             // Best-effort sync before closing
             if let Err(e) = env.sync(true) {
                 warn!("Failed to sync LMDB env before close: {e:?}");
@@ -574,7 +1662,24 @@ impl AppDbState {
             drop(env);
         }
         self.db = None;
+        self.side_dbs.clear();
+        self.asset = None;
+        self.external.clear();
         info!("LMDB environment closed");
         Ok(())
     }
 }
+
+impl Drop for AppDbState {
+    /// Closes the database if [`close_database`](AppDbState::close_database)
+    /// was not called, so that the shutdown is recorded as clean, and stops
+    /// the watch dispatcher.
+    fn drop(&mut self) {
+        if self.env.is_some() {
+            let _ = self.close_database();
+        }
+        if self.owns_handle {
+            self.watch_hub.shutdown();
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Fragmentation tracking and policy-driven compaction.
+//!
+//! LMDB never shrinks its data file: pages freed by deletes and updates go to
+//! a free list and are reused by later writes. After bulk deletes most of the
+//! file can be free pages. [`AppDbState::run_maintenance`] measures the free
+//! page ratio and compacts the database when the [`CompactionPolicy`] allows
+//! it, so apps only report whether the device is idle and charging instead of
+//! deciding themselves when to call [`AppDbState::compact`]. A maintenance
+//! run also bounds the delta change log when a retention window is set.
+
+use std::fs;
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr;
+
+use lmdb::{Environment, Error as LmdbError, Transaction};
+use lmdb_sys::{mdb_cursor_close, mdb_cursor_get, mdb_cursor_open, MDB_cursor, MDB_val, MDB_CP_COMPACT, MDB_FIRST, MDB_NEXT, MDB_NOTFOUND, MDB_SUCCESS};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::dataset::remove_dir_if_exists;
+use crate::local_db_model::{ChangelogCutoff, CompactionPolicy, CompactionResult, MaintenanceReport};
+use crate::local_db_state::AppDbState;
+use crate::startup::open_handle_count;
+use crate::stats::env_info;
+
+/// Handle of LMDB's internal free list database.
+const FREE_DBI: u32 = 0;
+
+impl AppDbState {
+    /// Sets when [`run_maintenance`](Self::run_maintenance) compacts the
+    /// database.
+    ///
+    /// The policy is not persisted; apps set it after opening the database.
+    pub fn set_compaction_policy(&mut self, policy: CompactionPolicy) {
+        self.compaction_policy = policy;
+    }
+
+    /// Returns the current [`CompactionPolicy`].
+    pub fn compaction_policy(&self) -> &CompactionPolicy {
+        &self.compaction_policy
+    }
+
+    /// Measures fragmentation and compacts the database when the
+    /// [`CompactionPolicy`] allows it, after purging the changes older than
+    /// the changelog retention window, if set (see
+    /// [`set_changelog_retention`](Self::set_changelog_retention)).
+    ///
+    /// `idle` and `charging` describe the device as reported by the app;
+    /// compaction rewrites the whole data file, so by default it only runs
+    /// while both hold.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let report = db.run_maintenance(true, true)?;
+    /// if let Some(compaction) = report.compaction {
+    ///     println!("Reclaimed {} bytes", compaction.bytes_before - compaction.bytes_after);
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed, the free list cannot be
+    /// read, or purging or compaction fails (see [`compact`](Self::compact)).
+    pub fn run_maintenance(&mut self, idle: bool, charging: bool) -> Result<MaintenanceReport, AppResponse> {
+        // Purge first, so compaction reclaims the freed pages
+        let changelog = match self.changelog_retention {
+            Some(max_age_ms) => Some(self.purge_changelog(ChangelogCutoff::AgeMs(max_age_ms))?),
+            None => None,
+        };
+
+        let (env, _) = self.env_db()?;
+        let (info, _) = env_info(env)?;
+        let used_pages = info.me_last_pgno + 1;
+        let free_pages = free_pages(env)?;
+        let free_ratio = free_pages as f64 / used_pages as f64;
+        let file_bytes = self.data_file_bytes();
+
+        let policy = &self.compaction_policy;
+        let skipped = if free_ratio < policy.min_free_ratio {
+            Some(format!("Free page ratio {free_ratio:.2} is below {:.2}", policy.min_free_ratio))
+        } else if file_bytes < policy.min_file_bytes {
+            Some(format!("Data file of {file_bytes} bytes is below {} bytes", policy.min_file_bytes))
+        } else if policy.require_idle && !idle {
+            Some("App is not idle".to_string())
+        } else if policy.require_charging && !charging {
+            Some("Device is not charging".to_string())
+        } else if open_handle_count(&self.path) > 1 {
+            Some("Database is open by another handle".to_string())
+        } else {
+            None
+        };
+
+        let compaction = match skipped {
+            Some(_) => None,
+            None => Some(self.compact()?),
+        };
+
+        Ok(MaintenanceReport {
+            used_pages,
+            free_pages,
+            free_ratio,
+            file_bytes,
+            compaction,
+            skipped,
+            changelog,
+        })
+    }
+
+    /// Rewrites the data file without free pages.
+    ///
+    /// The database is copied with `MDB_CP_COMPACT`, closed, replaced by the
+    /// copy and reopened; records, indexes and metadata are unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if another handle in this process
+    /// has the database open, or a database error if copying or reopening
+    /// fails.
+    pub fn compact(&mut self) -> Result<CompactionResult, AppResponse> {
+        if open_handle_count(&self.path) > 1 {
+            return Err(AppResponse::BadRequest(format!("Cannot compact {}: it is open by another handle", self.path)));
+        }
+
+        let bytes_before = self.data_file_bytes();
+        let copy_dir = format!("{}.compact", self.path);
+        remove_dir_if_exists(&copy_dir)?;
+        if let Err(e) = self.copy_environment(&copy_dir, MDB_CP_COMPACT) {
+            remove_dir_if_exists(&copy_dir)?;
+            return Err(e);
+        }
+
+        // Closing drops the attached asset database and stops the expiry
+        // sweeper; neither is part of the file.
+        let asset = self.asset.take();
+        let sweep = self.stop_expiry_sweeper();
+        self.close_database()?;
+        let replaced = fs::rename(Path::new(&copy_dir).join("data.mdb"), Path::new(&self.path).join("data.mdb"));
+        self.reopen()?;
+        self.asset = asset;
+        if let Some(sweep) = sweep {
+            self.start_expiry_sweeper(sweep)?;
+        }
+        replaced.map_err(|e| AppResponse::DatabaseError(format!("Cannot replace the data file of {}: {e}", self.path)))?;
+        remove_dir_if_exists(&copy_dir)?;
+
+        let bytes_after = self.data_file_bytes();
+        info!("✅ Compacted {} from {bytes_before} to {bytes_after} bytes", self.path);
+        Ok(CompactionResult { bytes_before, bytes_after })
+    }
+
+    fn data_file_bytes(&self) -> u64 {
+        fs::metadata(Path::new(&self.path).join("data.mdb")).map(|file| file.len()).unwrap_or(0)
+    }
+}
+
+/// Counts the pages on the free list.
+fn free_pages(env: &Environment) -> Result<usize, LmdbError> {
+    let txn = env.begin_ro_txn()?;
+    let mut cursor: *mut MDB_cursor = ptr::null_mut();
+    let mut key = MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+    let mut data = MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+
+    // SAFETY: the cursor lives within `txn` and is closed before it ends.
+    // Free list values are page number lists whose first element is their
+    // length, which is all that is read.
+    unsafe {
+        match mdb_cursor_open(txn.txn(), FREE_DBI, &mut cursor) {
+            MDB_SUCCESS => {}
+            code => return Err(LmdbError::from_err_code(code)),
+        }
+
+        let mut total = 0;
+        let mut op = MDB_FIRST;
+        let result = loop {
+            match mdb_cursor_get(cursor, &mut key, &mut data, op) {
+                MDB_SUCCESS if data.mv_size >= size_of::<usize>() => {
+                    total += ptr::read_unaligned(data.mv_data as *const usize);
+                }
+                MDB_SUCCESS => {}
+                MDB_NOTFOUND => break Ok(total),
+                code => break Err(LmdbError::from_err_code(code)),
+            }
+            op = MDB_NEXT;
+        };
+        mdb_cursor_close(cursor);
+        result
+    }
+}
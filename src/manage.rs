@@ -0,0 +1,231 @@
+//! Management of database directories.
+//!
+//! A database named `name` lives in the directory `{name}.lmdb` below its
+//! base directory. [`AppDbState::rename_database`] moves that directory
+//! without losing data, where [`AppDbState::reset_database`] starts over,
+//! and [`AppDbState::clone_database`] creates an independent copy next to
+//! it. [`AppDbState::list_databases`] finds the databases of a base
+//! directory, e.g. one per account, and [`AppDbState::delete_database`]
+//! removes one that is not open, e.g. on logout.
+
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use lmdb_sys::MDB_CP_COMPACT;
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::dataset::remove_dir_if_exists;
+use crate::local_db_model::{BackupResult, DatabaseInfo};
+use crate::local_db_state::AppDbState;
+use crate::startup::{is_open, open_handle_count};
+
+impl AppDbState {
+    /// Renames the database to `new_name`, in the same base directory,
+    /// keeping its records.
+    ///
+    /// The environment is closed, its directory renamed and the environment
+    /// reopened at the new path. The attached asset database and a running
+    /// expiry sweeper are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("guest".to_string())?;
+    ///
+    /// // The guest signed up, keep their data under their account
+    /// db.rename_database("user_42")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `new_name` is empty, a
+    /// database `new_name` already exists or another handle in this process
+    /// has the database open, or a database error if the directory cannot
+    /// be renamed; the database is then reopened under its old name.
+    pub fn rename_database(&mut self, new_name: &str) -> Result<(), AppResponse> {
+        let new_path = self.sibling_path(new_name)?;
+        if Path::new(&new_path).exists() {
+            return Err(AppResponse::BadRequest(format!("Cannot rename {} to {new_name}: {new_path} already exists", self.path)));
+        }
+        if open_handle_count(&self.path) > 1 {
+            return Err(AppResponse::BadRequest(format!("Cannot rename {}: it is open by another handle", self.path)));
+        }
+
+        // Closing drops the attached asset database and stops the expiry
+        // sweeper; both are restored on the reopened environment.
+        let asset = self.asset.take();
+        let sweep = self.stop_expiry_sweeper();
+        self.close_database()?;
+        let renamed = fs::rename(&self.path, &new_path);
+        let old_path = std::mem::replace(&mut self.path, new_path);
+        if renamed.is_err() {
+            self.path = old_path.clone();
+        }
+        self.reopen()?;
+        self.asset = asset;
+        if let Some(sweep) = sweep {
+            self.start_expiry_sweeper(sweep)?;
+        }
+        renamed.map_err(|e| AppResponse::DatabaseError(format!("Cannot rename {old_path} to {new_name}: {e}")))?;
+
+        info!("✅ Renamed database {old_path} to {}", self.path);
+        Ok(())
+    }
+
+    /// Copies the database to a new database `new_name` in the same base
+    /// directory, e.g. to duplicate a workspace or to try a risky migration
+    /// on a scratch copy. The database stays open and writable; the copy is
+    /// a compacted snapshot and shares nothing with it afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("workspace".to_string())?;
+    ///
+    /// db.clone_database("workspace_copy")?;
+    /// let copy = AppDbState::init("workspace_copy".to_string())?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `new_name` is empty or a
+    /// database `new_name` already exists, or a database error if the copy
+    /// fails.
+    pub fn clone_database(&self, new_name: &str) -> Result<BackupResult, AppResponse> {
+        let new_path = self.sibling_path(new_name)?;
+        if Path::new(&new_path).exists() {
+            return Err(AppResponse::BadRequest(format!("Cannot clone {} to {new_name}: {new_path} already exists", self.path)));
+        }
+
+        let result = match self.copy_environment(&new_path, MDB_CP_COMPACT) {
+            Ok(result) => result,
+            Err(e) => {
+                remove_dir_if_exists(&new_path)?;
+                return Err(e);
+            }
+        };
+        info!("✅ Cloned database {} to {new_path}", self.path);
+        Ok(result)
+    }
+
+    /// Returns the databases in `base_dir`, the working directory when empty,
+    /// in name order: the `{name}.lmdb` directories holding a data file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// for db in AppDbState::list_databases("/data/user/0/com.example.app/files")? {
+    ///     println!("{}: {} bytes", db.name, db.bytes);
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if `base_dir` cannot be read.
+    pub fn list_databases(base_dir: &str) -> Result<Vec<DatabaseInfo>, AppResponse> {
+        let dir = if base_dir.is_empty() { "." } else { base_dir };
+        let entries = fs::read_dir(dir).map_err(|e| AppResponse::NotFound(format!("Cannot read {dir}: {e}")))?;
+
+        let mut databases = Vec::new();
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(|name| name.strip_suffix(".lmdb")) else {
+                continue;
+            };
+            let path = Path::new(base_dir).join(&file_name);
+            let Ok(data_file) = fs::metadata(path.join("data.mdb")) else {
+                continue;
+            };
+            let modified_at = data_file
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_millis() as u64);
+
+            databases.push(DatabaseInfo {
+                name: name.to_string(),
+                path: path.to_string_lossy().into_owned(),
+                bytes: data_file.len(),
+                modified_at,
+                open: is_open(&path),
+            });
+        }
+        databases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(databases)
+    }
+
+    /// Deletes the database `name_or_path` with its lock file. Returns
+    /// whether it existed.
+    ///
+    /// `name_or_path` is either a database name, resolved like
+    /// [`AppDbState::init`] in the working directory, or the path of a
+    /// database directory ending in `.lmdb`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// // On logout, after closing the database
+    /// AppDbState::delete_database("/data/user/0/com.example.app/files/user_42.lmdb")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `name_or_path` is empty, the
+    /// database is open in this process, or the directory holds files other
+    /// than an LMDB environment, or a database error if it cannot be removed.
+    pub fn delete_database(name_or_path: &str) -> Result<bool, AppResponse> {
+        if name_or_path.is_empty() {
+            return Err(AppResponse::BadRequest("Database name cannot be empty".to_string()));
+        }
+        let dir = match name_or_path.ends_with(".lmdb") {
+            true => name_or_path.to_string(),
+            false => format!("{name_or_path}.lmdb"),
+        };
+        let path = Path::new(&dir);
+        if !path.exists() {
+            return Ok(false);
+        }
+        if is_open(path) {
+            return Err(AppResponse::BadRequest(format!("Cannot delete {dir}: it is open, close it first")));
+        }
+
+        let entries = fs::read_dir(path).map_err(|e| AppResponse::DatabaseError(format!("Cannot read {dir}: {e}")))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| AppResponse::DatabaseError(format!("Cannot read {dir}: {e}")))?;
+            if !matches!(entry.file_name().to_str(), Some("data.mdb" | "lock.mdb")) {
+                return Err(AppResponse::BadRequest(format!(
+                    "Cannot delete {dir}: {} is not part of a database",
+                    entry.file_name().to_string_lossy()
+                )));
+            }
+        }
+
+        remove_dir_if_exists(&dir)?;
+        // Left over by a compaction that was interrupted
+        remove_dir_if_exists(&format!("{dir}.compact"))?;
+        info!("✅ Deleted database {dir}");
+        Ok(true)
+    }
+
+    /// Returns the directory of the database `name` next to this one.
+    pub(crate) fn sibling_path(&self, name: &str) -> Result<String, AppResponse> {
+        if name.is_empty() {
+            return Err(AppResponse::BadRequest("Database name cannot be empty".to_string()));
+        }
+        Ok(self.base_dir.join(format!("{name}.lmdb")).to_string_lossy().into_owned())
+    }
+}
@@ -0,0 +1,165 @@
+//! Internal metadata database.
+//!
+//! Bookkeeping values (such as the version of a shipped dataset) are kept in
+//! the `__meta` database instead of as magic keys in `main`, so they never show
+//! up in the record APIs and can be updated in the same transaction as the
+//! records they describe.
+//!
+//! Apps keep their own bookkeeping there too, such as a sync checkpoint, with
+//! [`AppDbState::meta_value`] and [`AppDbState::set_meta_value`]. Their keys
+//! are stored below `app/`, apart from the keys of the crate.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::app_response::AppResponse;
+use crate::local_db_state::AppDbState;
+
+/// Name of the internal metadata database.
+pub(crate) const META_DB_NAME: &str = "__meta";
+
+/// Version of the dataset the main database was built or last patched to.
+pub(crate) const DATASET_VERSION_KEY: &str = "dataset_version";
+
+/// Schema version the records were last migrated to.
+pub(crate) const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Key after which an interrupted migration step resumes.
+pub(crate) const MIGRATION_RESUME_KEY: &str = "migration_resume";
+
+/// Sequence number of the last committed record write transaction.
+pub(crate) const COMMIT_SEQUENCE_KEY: &str = "commit_sequence";
+
+/// Last hybrid logical clock timestamp issued, packed as in [`crate::hlc`].
+pub(crate) const HLC_KEY: &str = "hlc";
+
+/// Set once the sync digest buckets are built and kept current by writes.
+pub(crate) const SYNC_DIGEST_KEY: &str = "sync_digest";
+
+/// Server cursor of the last page merged by the sync engine.
+pub(crate) const SYNC_CURSOR_KEY: &str = "sync_cursor";
+
+/// Sequence of the last request enqueued in the outbox.
+pub(crate) const OUTBOX_SEQUENCE_KEY: &str = "outbox_sequence";
+
+/// Snapshot interval of delta encoding, set while it is enabled.
+pub(crate) const DELTA_ENCODING_KEY: &str = "delta_encoding";
+
+/// Set while CRDT mode is on.
+pub(crate) const CRDT_MODE_KEY: &str = "crdt_mode";
+
+/// Set while record timestamps are on.
+pub(crate) const RECORD_TIMESTAMPS_KEY: &str = "record_timestamps";
+
+/// Number of versions kept of each record, absent while history mode is off.
+pub(crate) const HISTORY_LIMIT_KEY: &str = "history_limit";
+
+/// Prefix of the keys set by the app.
+const APP_META_PREFIX: &str = "app/";
+
+impl AppDbState {
+    /// Returns the dataset version of this database, `0` when it was never set.
+    ///
+    /// The version is stamped by [`AppDbState::build_prebuilt_db`] and advanced
+    /// by [`AppDbState::apply_dataset_patch`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn dataset_version(&self) -> Result<u64, LmdbError> {
+        Ok(self.meta_u64(DATASET_VERSION_KEY)?.unwrap_or(0))
+    }
+
+    /// Returns the value the app stored under `key`, `None` if it was never
+    /// set.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// db.set_meta_value("sync_cursor", &"2024-05-01T10:00:00Z")?;
+    /// let cursor: Option<String> = db.meta_value("sync_cursor")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `key` is empty, or
+    /// [`AppResponse::SerializationError`] if the stored value is not a `T`.
+    pub fn meta_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, AppResponse> {
+        let key = app_meta_key(key)?;
+        let (env, meta) = self.side_db(META_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        match txn.get(meta, &key) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(bytes)?)),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores `value` as JSON under `key`, replacing the previous value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `key` is empty, or a database
+    /// error if the write fails.
+    pub fn set_meta_value<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), AppResponse> {
+        let key = app_meta_key(key)?;
+        let (env, meta) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        txn.put(meta, &key, &serde_json::to_vec(value)?, WriteFlags::empty())?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Removes the value stored under `key`. Returns whether it existed.
+    pub fn remove_meta_value(&self, key: &str) -> Result<bool, AppResponse> {
+        let key = app_meta_key(key)?;
+        let (env, meta) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let removed = match txn.del(meta, &key, None) {
+            Ok(()) => true,
+            Err(LmdbError::NotFound) => false,
+            Err(e) => return Err(e.into()),
+        };
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Reads an unsigned counter from the metadata database.
+    pub(crate) fn meta_u64(&self, key: &str) -> Result<Option<u64>, LmdbError> {
+        let (env, meta) = self.side_db(META_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        get_meta_u64(&txn, meta, key)
+    }
+}
+
+/// Reads an unsigned counter from the metadata database within `txn`.
+pub(crate) fn get_meta_u64<T: Transaction>(txn: &T, meta: Database, key: &str) -> Result<Option<u64>, LmdbError> {
+    match txn.get(meta, &key) {
+        Ok(bytes) => Ok(Some(decode_u64(bytes)?)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes an unsigned counter into the metadata database as part of `txn`.
+pub(crate) fn put_meta_u64(txn: &mut RwTransaction, meta: Database, key: &str, value: u64) -> Result<(), LmdbError> {
+    txn.put(meta, &key, &value.to_be_bytes(), WriteFlags::empty())
+}
+
+pub(crate) fn decode_u64(bytes: &[u8]) -> Result<u64, LmdbError> {
+    let bytes: [u8; 8] = bytes.try_into().map_err(|_| LmdbError::Corrupted)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn app_meta_key(key: &str) -> Result<String, AppResponse> {
+    if key.is_empty() {
+        return Err(AppResponse::BadRequest("Metadata key cannot be empty".to_string()));
+    }
+    Ok(format!("{APP_META_PREFIX}{key}"))
+}
@@ -0,0 +1,177 @@
+//! Schema migrations.
+//!
+//! The schema version of a database is kept in the `__meta` database and
+//! starts at `0`. An app registers one step per version with
+//! [`AppDbState::register_migration`], transforming a record of version `n`
+//! into one of version `n + 1`, and [`AppDbState::migrate`] runs the missing
+//! steps over the records of `main` in order.
+//!
+//! Each step visits the records in key order, in write transactions of at
+//! most `batch_size` records. The key to resume from is saved with every
+//! batch and the version is advanced with the last one, so a migration
+//! interrupted by a failing step or a killed app picks up where it stopped
+//! instead of transforming records twice.
+
+use std::sync::Arc;
+
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+use log::info;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{LocalDbModel, MigrationProgress};
+use crate::local_db_state::AppDbState;
+use crate::meta::{put_meta_u64, META_DB_NAME, MIGRATION_RESUME_KEY, SCHEMA_VERSION_KEY};
+use crate::scan::scan_from;
+
+/// Step migrating a record to the next schema version; `None` deletes it.
+pub(crate) type MigrationFn = Arc<dyn Fn(LocalDbModel) -> Result<Option<LocalDbModel>, AppResponse> + Send + Sync>;
+
+impl AppDbState {
+    /// Returns the schema version the records were migrated to, `0` when
+    /// no migration ran.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn schema_version(&self) -> Result<u64, LmdbError> {
+        Ok(self.meta_u64(SCHEMA_VERSION_KEY)?.unwrap_or(0))
+    }
+
+    /// Registers the step migrating records from `from_version` to
+    /// `from_version + 1`, replacing any step registered for that version.
+    ///
+    /// The step receives each record and returns it transformed, or `None`
+    /// to delete it. It must keep the record ID. Steps are not persisted:
+    /// the app registers them every time it opens the database.
+    pub fn register_migration<F>(&mut self, from_version: u64, step: F)
+    where
+        F: Fn(LocalDbModel) -> Result<Option<LocalDbModel>, AppResponse> + Send + Sync + 'static,
+    {
+        self.migrations.insert(from_version, Arc::new(step));
+    }
+
+    /// Migrates the records to `target_version`, running the registered
+    /// steps from the current schema version on, and calling `progress`
+    /// after each batch. Returns the final progress.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("tasks".to_string())?;
+    ///
+    /// // Version 1 renamed `done` to `completed`
+    /// db.register_migration(0, |mut task| {
+    ///     if let Some(data) = task.data.as_object_mut() {
+    ///         let done = data.remove("done").unwrap_or_default();
+    ///         data.insert("completed".to_string(), done);
+    ///     }
+    ///     Ok(Some(task))
+    /// });
+    /// let done = db.migrate(1, 500, |progress| println!("{} records migrated", progress.updated))?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `target_version` is below the
+    /// current version, a step is missing, `batch_size` is zero or a step
+    /// changes a record ID. Returns the error of a failing step, or a
+    /// database error if a batch fails; batches committed before stay
+    /// migrated and the next call resumes after them.
+    pub fn migrate<F>(&self, target_version: u64, batch_size: usize, mut progress: F) -> Result<MigrationProgress, AppResponse>
+    where
+        F: FnMut(&MigrationProgress),
+    {
+        let current = self.schema_version()?;
+        if target_version < current {
+            return Err(AppResponse::BadRequest(format!(
+                "Cannot migrate from schema version {current} down to {target_version}"
+            )));
+        }
+        if batch_size == 0 {
+            return Err(AppResponse::BadRequest("Migration batch size must be at least 1".to_string()));
+        }
+        let steps = (current..target_version)
+            .map(|version| {
+                self.migrations.get(&version).cloned().ok_or_else(|| {
+                    AppResponse::BadRequest(format!("No migration registered from schema version {version}"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (env, db) = self.env_db()?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut done = MigrationProgress { schema_version: current, ..MigrationProgress::default() };
+
+        for (version, step) in (current..target_version).zip(steps) {
+            loop {
+                let mut txn = env.begin_rw_txn()?;
+                let writer = self.record_writer(&txn)?;
+                let resume = match txn.get(meta_db, &MIGRATION_RESUME_KEY) {
+                    Ok(key) => Some(key.to_vec()),
+                    Err(LmdbError::NotFound) => None,
+                    Err(e) => return Err(e.into()),
+                };
+
+                let mut last_key = None;
+                let mut batch: Vec<LocalDbModel> = Vec::new();
+                {
+                    let cursor = txn.open_ro_cursor(db)?;
+                    for (key, value) in scan_from(&cursor, resume.as_deref()).take(batch_size) {
+                        last_key = Some(key.to_vec());
+                        batch.push(self.decode_record(&txn, key, value)?);
+                    }
+                }
+
+                let scanned = batch.len();
+                for model in batch {
+                    let id = model.id.clone();
+                    match step(model)? {
+                        Some(mut migrated) if migrated.id == id => {
+                            self.write_model(&mut txn, &writer, db, &mut migrated)?;
+                            done.updated += 1;
+                        }
+                        Some(migrated) => {
+                            return Err(AppResponse::BadRequest(format!(
+                                "Migration from schema version {version} changed record ID {id} to {}",
+                                migrated.id
+                            )));
+                        }
+                        None => {
+                            writer.del(&mut txn, db, id.as_bytes())?;
+                            done.deleted += 1;
+                        }
+                    }
+                }
+
+                let finished = scanned < batch_size;
+                if finished {
+                    match txn.del(meta_db, &MIGRATION_RESUME_KEY, None) {
+                        Ok(()) | Err(LmdbError::NotFound) => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                    put_meta_u64(&mut txn, meta_db, SCHEMA_VERSION_KEY, version + 1)?;
+                } else if let Some(key) = last_key {
+                    // Continue right after the last key visited
+                    txn.put(meta_db, &MIGRATION_RESUME_KEY, &[key, vec![0x00]].concat(), WriteFlags::empty())?;
+                }
+                writer.commit(txn)?;
+
+                done.scanned += scanned;
+                if finished {
+                    done.schema_version = version + 1;
+                }
+                progress(&done);
+                if finished {
+                    break;
+                }
+            }
+            info!("Migrated records to schema version {}", version + 1);
+        }
+
+        Ok(done)
+    }
+}
+
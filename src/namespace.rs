@@ -0,0 +1,88 @@
+//! Key namespaces.
+//!
+//! Apps that keep several kinds of records in `main` rather than in separate
+//! collections prefix their IDs with a namespace: [`namespace_key`] turns
+//! `user` and `123` into `user:123`, and namespaces nest (`ns:user:123`).
+//! Since keys sort bytewise, a namespace is a contiguous key range, so
+//! [`AppDbState::count_namespace`] and [`AppDbState::clear_namespace`] visit
+//! only its records, positioned with `MDB_SET_RANGE`.
+
+use lmdb::Transaction;
+
+use crate::app_response::AppResponse;
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+
+/// Separator between a namespace and the rest of a key.
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// Returns the key of `id` in `namespace`, e.g. `user:123`.
+pub fn namespace_key(namespace: &str, id: &str) -> String {
+    format!("{namespace}{NAMESPACE_SEPARATOR}{id}")
+}
+
+impl AppDbState {
+    /// Returns the number of records in `namespace` without decoding them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `namespace` is empty.
+    pub fn count_namespace(&self, namespace: &str) -> Result<usize, AppResponse> {
+        let prefix = namespace_prefix(namespace)?;
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+
+        Ok(scan_from(&cursor, Some(prefix.as_bytes()))
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .count())
+    }
+
+    /// Deletes every record in `namespace`, nested namespaces included, in
+    /// one write transaction, and returns how many were deleted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("app".to_string())?;
+    ///
+    /// // Sign-out: drop the cached feed, keep the settings
+    /// let deleted = db.clear_namespace("feed")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `namespace` is empty, or a
+    /// database error if the transaction fails.
+    pub fn clear_namespace(&self, namespace: &str) -> Result<usize, AppResponse> {
+        let prefix = namespace_prefix(namespace)?;
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+
+        let keys: Vec<Vec<u8>> = {
+            let cursor = txn.open_ro_cursor(db)?;
+            scan_from(&cursor, Some(prefix.as_bytes()))
+                .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+                .map(|(key, _)| key.to_vec())
+                .collect()
+        };
+        for key in &keys {
+            writer.del(&mut txn, db, key)?;
+        }
+
+        writer.commit(txn)?;
+        Ok(keys.len())
+    }
+}
+
+fn namespace_prefix(namespace: &str) -> Result<String, AppResponse> {
+    if namespace.is_empty() {
+        return Err(AppResponse::BadRequest("Namespace cannot be empty".to_string()));
+    }
+    Ok(namespace_key(namespace, ""))
+}
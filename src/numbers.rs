@@ -0,0 +1,104 @@
+//! Write-time handling of numbers that are not safe for JSON consumers.
+//!
+//! See [`NumberPolicy`] for which numbers are considered unsafe. The policy is
+//! applied to the `data` of every record written through the record APIs
+//! before it is encoded.
+
+use serde_json::{Number, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{LocalDbModel, NumberPolicy};
+use crate::local_db_state::AppDbState;
+
+/// Largest integer an `f64` represents exactly along with all smaller ones.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+impl AppDbState {
+    /// Sets how unsafe numbers are handled by later writes of this instance.
+    ///
+    /// The policy is not persisted; apps set it after opening the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::NumberPolicy;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("ledger".to_string())?;
+    /// db.set_number_policy(NumberPolicy::Reject);
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    pub fn set_number_policy(&mut self, policy: NumberPolicy) {
+        self.number_policy = policy;
+    }
+
+    /// Returns the current [`NumberPolicy`].
+    pub fn number_policy(&self) -> NumberPolicy {
+        self.number_policy
+    }
+
+    /// Applies the number policy to the data of `model`.
+    pub(crate) fn check_numbers(&self, model: &mut LocalDbModel) -> Result<(), AppResponse> {
+        apply_number_policy(&mut model.data, self.number_policy).map_err(|mut path| {
+            path.push("data".to_string());
+            path.reverse();
+            AppResponse::ValidationError(format!(
+                "Number at {} cannot be represented exactly by JSON consumers in record {}",
+                path.join("."),
+                model.id
+            ))
+        })
+    }
+}
+
+/// Applies `policy` to every number in `value`.
+///
+/// On rejection, returns the path segments of the offending number from the
+/// innermost outwards.
+fn apply_number_policy(value: &mut JsonValue, policy: NumberPolicy) -> Result<(), Vec<String>> {
+    if policy == NumberPolicy::Preserve {
+        return Ok(());
+    }
+
+    match value {
+        JsonValue::Number(number) if !is_safe(number) => match policy {
+            NumberPolicy::Reject => return Err(Vec::new()),
+            NumberPolicy::Stringify => *value = JsonValue::String(number.to_string()),
+            NumberPolicy::Lossy => {
+                if let Some(lossy) = number.as_f64().and_then(Number::from_f64) {
+                    *number = lossy;
+                }
+            }
+            NumberPolicy::Preserve => {}
+        },
+        JsonValue::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                apply_number_policy(item, policy).map_err(|mut path| {
+                    path.push(index.to_string());
+                    path
+                })?;
+            }
+        }
+        JsonValue::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                apply_number_policy(item, policy).map_err(|mut path| {
+                    path.push(key.clone());
+                    path
+                })?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_safe(number: &Number) -> bool {
+    if let Some(n) = number.as_u64() {
+        n <= MAX_SAFE_INTEGER
+    } else if let Some(n) = number.as_i64() {
+        n.unsigned_abs() <= MAX_SAFE_INTEGER
+    } else {
+        // Integer literals outside the 64-bit range are parsed as integral floats.
+        number.as_f64().is_some_and(|n| n.fract() != 0.0 || n.abs() <= MAX_SAFE_INTEGER as f64)
+    }
+}
@@ -0,0 +1,119 @@
+//! Outbox of pending remote mutations.
+//!
+//! Apps that call their own API for some writes, such as "send this
+//! message" or "mark this order paid", enqueue each request with
+//! [`AppDbState::enqueue_outbox`] while offline. When connectivity returns,
+//! they replay the requests in order: [`AppDbState::peek_outbox`] returns the
+//! oldest ones, and [`AppDbState::ack_outbox`] removes them once the server
+//! accepted them. Requests are JSON documents of the app's own shape; the
+//! crate only keeps them in order.
+//!
+//! The outbox lives in the `__outbox` database and survives restarts:
+//!
+//! ```text
+//! {sequence, u64 BE}  -> {OutboxEntry, JSON}
+//! ```
+//!
+//! Sequences are issued from a counter in `__meta`, so they keep growing
+//! after the outbox is emptied.
+
+use lmdb::{Transaction, WriteFlags};
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::OutboxEntry;
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64, META_DB_NAME, OUTBOX_SEQUENCE_KEY};
+use crate::scan::scan_from;
+
+/// Side database holding the outbox.
+pub(crate) const OUTBOX_DB_NAME: &str = "__outbox";
+
+impl AppDbState {
+    /// Appends `request` to the outbox and returns its entry.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("chat".to_string())?;
+    ///
+    /// db.enqueue_outbox(&json!({"method": "POST", "path": "/messages", "body": {"text": "Hi"}}))?;
+    ///
+    /// // Once online
+    /// for entry in db.peek_outbox(50)? {
+    ///     // send entry.request
+    ///     db.ack_outbox(entry.sequence)?;
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `request` is `null`, or a
+    /// database error if the write fails.
+    pub fn enqueue_outbox(&self, request: &JsonValue) -> Result<OutboxEntry, AppResponse> {
+        if request.is_null() {
+            return Err(AppResponse::BadRequest("Outbox request cannot be null".to_string()));
+        }
+        let (env, outbox_db) = self.side_db(OUTBOX_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let sequence = get_meta_u64(&txn, meta_db, OUTBOX_SEQUENCE_KEY)?.unwrap_or(0) + 1;
+        let entry = OutboxEntry { sequence, enqueued_at: self.now_ms(), request: request.clone() };
+        txn.put(outbox_db, &sequence.to_be_bytes(), &serde_json::to_vec(&entry)?, WriteFlags::empty())?;
+        put_meta_u64(&mut txn, meta_db, OUTBOX_SEQUENCE_KEY, sequence)?;
+        txn.commit()?;
+        Ok(entry)
+    }
+
+    /// Returns up to `limit` pending entries, oldest first, without removing
+    /// them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the read transaction fails or an entry cannot be
+    /// decoded.
+    pub fn peek_outbox(&self, limit: usize) -> Result<Vec<OutboxEntry>, AppResponse> {
+        let (env, outbox_db) = self.side_db(OUTBOX_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(outbox_db)?;
+
+        scan_from(&cursor, None)
+            .take(limit)
+            .map(|(_, value)| Ok(serde_json::from_slice(value)?))
+            .collect()
+    }
+
+    /// Removes the entries up to `sequence`, the sequence of the last entry
+    /// the server accepted. Returns the number of entries removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `sequence` was never issued,
+    /// or a database error if the write fails.
+    pub fn ack_outbox(&self, sequence: u64) -> Result<u64, AppResponse> {
+        let (env, outbox_db) = self.side_db(OUTBOX_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        let issued = get_meta_u64(&txn, meta_db, OUTBOX_SEQUENCE_KEY)?.unwrap_or(0);
+        if sequence > issued {
+            return Err(AppResponse::BadRequest(format!("Outbox sequence {sequence} is beyond the last one issued, {issued}")));
+        }
+
+        let end = sequence.to_be_bytes();
+        let keys: Vec<Vec<u8>> = {
+            let cursor = txn.open_ro_cursor(outbox_db)?;
+            scan_from(&cursor, None).map(|(key, _)| key).take_while(|key| *key <= end.as_slice()).map(<[u8]>::to_vec).collect()
+        };
+        for key in &keys {
+            txn.del(outbox_db, key, None)?;
+        }
+        txn.commit()?;
+        Ok(keys.len() as u64)
+    }
+}
@@ -0,0 +1,283 @@
+//! Overflow of large fields into the chunk store.
+//!
+//! When an overflow threshold is set, a record whose encoded value exceeds it
+//! has its largest top-level `data` fields moved out of the value: each field
+//! is serialized, split into [`OVERFLOW_CHUNK_SIZE`] chunks stored in the
+//! `__chunks` database, and replaced in the record by a stub
+//!
+//! ```json
+//! {"$overflow": {"bytes": 1048576, "chunks": 16}}
+//! ```
+//!
+//! The value header of such a record carries the overflowed flag; stubs are
+//! only resolved in flagged records, so a field that merely looks like a stub
+//! is read back as written.
+//!
+//! Chunk keys are `{record id} 0x00 {field} 0x00 {chunk index, big-endian u32}`,
+//! so the chunks of a record are adjacent and removed with it. Reads through
+//! the record APIs reassemble the fields transparently. Filters and indexes
+//! evaluate the stored value, so they see the stub of an overflowed field.
+
+use std::borrow::Cow;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::crdt::stamp_clocks;
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+use crate::timestamps::stamp_times;
+use crate::value_codec::{encode_model, encode_model_with, json_payload, split_value, ValueHeader};
+use crate::writer::RecordWriter;
+
+/// Side database holding the chunks of overflowed fields.
+pub(crate) const CHUNKS_DB_NAME: &str = "__chunks";
+
+/// Size of one chunk of an overflowed field.
+pub const OVERFLOW_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Key of the stub object replacing an overflowed field.
+const STUB_KEY: &str = "$overflow";
+
+/// Fields at most this large stay in the record; moving them would not pay off.
+const MIN_OVERFLOW_FIELD: usize = 64;
+
+impl AppDbState {
+    /// Sets the encoded value size above which large fields of a record are
+    /// moved to the chunk store on write, or `None` to disable overflow (the
+    /// default).
+    ///
+    /// The largest top-level fields of `data` are moved first, until the value
+    /// fits or no field larger than 64 bytes is left; the record is written
+    /// either way. The threshold is not persisted; apps set it after opening
+    /// the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    /// db.set_overflow_threshold(Some(256 * 1024));
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    pub fn set_overflow_threshold(&mut self, threshold: Option<usize>) {
+        self.overflow_threshold = threshold;
+    }
+
+    /// Returns the current overflow threshold.
+    pub fn overflow_threshold(&self) -> Option<usize> {
+        self.overflow_threshold
+    }
+
+    /// Applies the write-time policies to `model` and stores it.
+    ///
+    /// The number policy may rewrite `model`; overflowed and encrypted fields
+    /// stay in `model` and are only replaced by stubs or markers in the
+    /// stored value.
+    pub(crate) fn write_model(&self, txn: &mut RwTransaction, writer: &RecordWriter, db: Database, model: &mut LocalDbModel) -> Result<(), AppResponse> {
+        self.check_numbers(model)?;
+        if writer.crdt || writer.timestamps {
+            let previous = match txn.get(db, &model.id) {
+                Ok(value) => Some(self.decode_record(txn, model.id.as_bytes(), value)?),
+                Err(LmdbError::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            };
+            if writer.crdt {
+                stamp_clocks(previous.as_ref(), model, writer.hlc(txn)?);
+            }
+            if writer.timestamps {
+                stamp_times(previous.as_ref(), model, writer.changed_at);
+            }
+        }
+        let sealed_fields = self.seal_fields(model)?;
+        let encrypted = self.store_model(txn, writer, db, sealed_fields.as_ref().unwrap_or(model))?;
+        if let (Some(versions), Some(sequence)) = (&writer.versions, writer.sequence()) {
+            // Only plaintext records are versioned
+            if encrypted || sealed_fields.is_some() {
+                versions.track_del(txn, model.id.as_bytes())?;
+            } else {
+                versions.track(txn, model, sequence)?;
+            }
+        }
+        if let (Some(history), Some(sequence)) = (&writer.history, writer.sequence()) {
+            if encrypted || sealed_fields.is_some() {
+                history.forget(txn, model.id.as_bytes())?;
+            } else {
+                history.track(txn, model, sequence, writer.changed_at)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores `model`, encrypted or with its large fields overflowed as
+    /// configured. Returns whether it was encrypted.
+    fn store_model(&self, txn: &mut RwTransaction, writer: &RecordWriter, db: Database, model: &LocalDbModel) -> Result<bool, AppResponse> {
+        let value = encode_model(model)?;
+        if let Some(sealed) = self.encrypt_value(&model.id, &value)? {
+            writer.put(txn, db, model.id.as_bytes(), &sealed)?;
+            if let Some(digest) = &writer.digest {
+                digest.track(txn, model.id.as_bytes(), &model.hash)?;
+            }
+            return Ok(true);
+        }
+
+        let threshold = match self.overflow_threshold {
+            Some(threshold) if value.len() > threshold && model.data.is_object() => threshold,
+            _ => {
+                writer.put(txn, db, model.id.as_bytes(), &value)?;
+                return Ok(false);
+            }
+        };
+
+        let mut fields: Vec<(String, Vec<u8>)> = model
+            .data
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(field, value)| Ok((field.clone(), serde_json::to_vec(value)?)))
+            .collect::<Result<_, serde_json::Error>>()?;
+        fields.retain(|(_, bytes)| bytes.len() > MIN_OVERFLOW_FIELD);
+        fields.sort_by_key(|(_, bytes)| std::cmp::Reverse(bytes.len()));
+
+        let overflowed = ValueHeader { overflowed: true, ..ValueHeader::default() };
+        let mut stored = model.clone();
+        let mut stored_value = value;
+        let mut moved = Vec::new();
+        for (field, bytes) in fields {
+            if stored_value.len() <= threshold {
+                break;
+            }
+            let chunks = bytes.len().div_ceil(OVERFLOW_CHUNK_SIZE);
+            stored.data[&field] = json!({ STUB_KEY: { "bytes": bytes.len(), "chunks": chunks } });
+            stored_value = encode_model_with(&stored, overflowed)?;
+            moved.push((field, bytes));
+        }
+
+        writer.put(txn, db, stored.id.as_bytes(), &stored_value)?;
+        for (field, bytes) in &moved {
+            for (index, chunk) in bytes.chunks(OVERFLOW_CHUNK_SIZE).enumerate() {
+                let key = chunk_key(&stored.id, field, index as u32);
+                txn.put(writer.chunks_db, &key, &chunk, WriteFlags::empty())?;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Decodes a stored value, decrypting it and reassembling overflowed and
+    /// encrypted fields.
+    pub(crate) fn decode_record<T: Transaction>(&self, txn: &T, id: &[u8], value: &[u8]) -> Result<LocalDbModel, AppResponse> {
+        let mut model: LocalDbModel = serde_json::from_str(&self.record_json(id, value)?)?;
+        self.resolve_overflow(txn, value, &mut model)?;
+        self.open_fields(&mut model)?;
+        Ok(model)
+    }
+
+    /// Checks that a stored value decodes like
+    /// [`decode_record`](Self::decode_record) would, without building its
+    /// `data` unless overflowed or encrypted fields have to be resolved.
+    pub(crate) fn check_record<T: Transaction>(&self, txn: &T, id: &[u8], value: &[u8]) -> Result<(), AppResponse> {
+        if has_overflow(value) || self.field_encryption.is_some() {
+            return self.decode_record(txn, id, value).map(|_| ());
+        }
+        serde_json::from_str::<RecordShape>(&self.record_json(id, value)?)?;
+        Ok(())
+    }
+
+    /// Replaces the overflow stubs of `model`, decoded from the stored
+    /// `value`, with the reassembled fields.
+    pub(crate) fn resolve_overflow<T: Transaction>(&self, txn: &T, value: &[u8], model: &mut LocalDbModel) -> Result<(), AppResponse> {
+        let Some(fields) = model.data.as_object_mut().filter(|_| has_overflow(value)) else {
+            return Ok(());
+        };
+
+        for (field, value) in fields.iter_mut() {
+            let Some(chunks) = stub_chunks(value) else {
+                continue;
+            };
+
+            let (_, chunks_db) = self.side_db(CHUNKS_DB_NAME)?;
+            let mut bytes = Vec::new();
+            for index in 0..chunks {
+                match txn.get(chunks_db, &chunk_key(&model.id, field, index)) {
+                    Ok(chunk) => bytes.extend_from_slice(chunk),
+                    Err(LmdbError::NotFound) => {
+                        return Err(AppResponse::DatabaseError(format!(
+                            "Missing overflow chunk {index} of {}.{field}",
+                            model.id
+                        )));
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            *value = serde_json::from_slice(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `value` with its overflowed fields inlined again, for copying a
+    /// record into another database. Values without stubs are borrowed as is.
+    pub(crate) fn inline_overflow<'v, T: Transaction>(&self, txn: &T, value: &'v [u8]) -> Result<Cow<'v, [u8]>, AppResponse> {
+        if !has_overflow(value) {
+            return Ok(Cow::Borrowed(value));
+        }
+        // Encrypted fields stay sealed in the copy
+        let mut model: LocalDbModel = serde_json::from_str(json_payload(value)?.as_ref())?;
+        self.resolve_overflow(txn, value, &mut model)?;
+        Ok(Cow::Owned(encode_model(&model)?))
+    }
+}
+
+/// Returns whether a stored record holds overflow stubs.
+pub(crate) fn has_overflow(value: &[u8]) -> bool {
+    split_value(value).is_ok_and(|(header, _)| header.overflowed)
+}
+
+/// The fields of a [`LocalDbModel`] with `data` skipped, for checking that a
+/// record decodes.
+#[derive(Deserialize)]
+#[allow(dead_code)] // Only deserialized
+struct RecordShape {
+    id: String,
+    hash: String,
+    data: IgnoredAny,
+    created_at: Option<u64>,
+    updated_at: Option<u64>,
+}
+
+/// Deletes every chunk of the record `id`.
+pub(crate) fn delete_chunks(txn: &mut RwTransaction, chunks_db: Database, id: &[u8]) -> Result<(), LmdbError> {
+    let prefix = [id, &[0x00]].concat();
+    let keys: Vec<Vec<u8>> = {
+        let cursor = txn.open_ro_cursor(chunks_db)?;
+        scan_from(&cursor, Some(&prefix))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, _)| key.to_vec())
+            .collect()
+    };
+    for key in keys {
+        txn.del(chunks_db, &key, None)?;
+    }
+    Ok(())
+}
+
+/// Returns the chunk count if `value` is an overflow stub.
+fn stub_chunks(value: &JsonValue) -> Option<u32> {
+    let object = value.as_object().filter(|object| object.len() == 1)?;
+    let chunks = object.get(STUB_KEY)?.get("chunks")?.as_u64()?;
+    u32::try_from(chunks).ok()
+}
+
+fn chunk_key(id: &str, field: &str, index: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(id.len() + field.len() + 6);
+    key.extend_from_slice(id.as_bytes());
+    key.push(0x00);
+    key.extend_from_slice(field.as_bytes());
+    key.push(0x00);
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
@@ -0,0 +1,652 @@
+//! Query evaluation over stored records.
+//!
+//! Filtering is performed during cursor iteration. To keep selective queries
+//! cheap on large values, records are first *probed*: only the JSON paths a
+//! filter references are extracted from the raw value, while every other field
+//! is skipped by the parser without being materialized. A record is fully
+//! decoded into a [`LocalDbModel`] only once it is known to match.
+//!
+//! Paths are dotted and rooted at the stored model, e.g. `id`, `hash`,
+//! `updated_at`, `data.status` or `data.items.0.name` (numeric segments
+//! index arrays).
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Formatter;
+
+use lmdb::{Cursor, Error as LmdbError, Transaction};
+use log::info;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{Direction, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::overflow::has_overflow;
+
+/// Prefix tree of the paths requested from a probe.
+#[derive(Default)]
+struct PathTrie {
+    children: HashMap<String, PathTrie>,
+    /// Indexes (into the probe output) of the paths ending at this node.
+    terminals: Vec<usize>,
+}
+
+impl PathTrie {
+    fn build(paths: &[&str]) -> Self {
+        let mut root = PathTrie::default();
+        for (index, path) in paths.iter().enumerate() {
+            let mut node = &mut root;
+            for segment in path.split('.') {
+                node = node.children.entry(segment.to_string()).or_default();
+            }
+            node.terminals.push(index);
+        }
+        root
+    }
+
+    /// Resolves this node and all its descendants against an already decoded value.
+    fn resolve(&self, value: &JsonValue, out: &mut [Option<JsonValue>]) {
+        for &index in &self.terminals {
+            out[index] = Some(value.clone());
+        }
+        for (segment, child) in &self.children {
+            let next = match value {
+                JsonValue::Object(map) => map.get(segment),
+                JsonValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => None,
+            };
+            if let Some(next) = next {
+                child.resolve(next, out);
+            }
+        }
+    }
+}
+
+/// Deserialization seed that walks a JSON document, only materializing the
+/// sub-values that some requested path ends at.
+struct ProbeSeed<'a> {
+    node: &'a PathTrie,
+    out: &'a mut [Option<JsonValue>],
+}
+
+impl<'de> DeserializeSeed<'de> for ProbeSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if self.node.terminals.is_empty() {
+            deserializer.deserialize_any(self)
+        } else {
+            // A requested path ends here, so the value is needed in full anyway.
+            let value = JsonValue::deserialize(deserializer)?;
+            self.node.resolve(&value, self.out);
+            Ok(())
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for ProbeSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> { Ok(()) }
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> { Ok(()) }
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> { Ok(()) }
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> { Ok(()) }
+    fn visit_str<E>(self, _: &str) -> Result<(), E> { Ok(()) }
+    fn visit_unit<E>(self) -> Result<(), E> { Ok(()) }
+    fn visit_none<E>(self) -> Result<(), E> { Ok(()) }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0usize;
+        loop {
+            let found = match self.node.children.get(&index.to_string()) {
+                Some(child) => seq.next_element_seed(ProbeSeed { node: child, out: &mut *self.out })?,
+                None => seq.next_element::<IgnoredAny>()?.map(|_| ()),
+            };
+            if found.is_none() {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<std::borrow::Cow<'de, str>>()? {
+            match self.node.children.get(key.as_ref()) {
+                Some(child) => map.next_value_seed(ProbeSeed { node: child, out: &mut *self.out })?,
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the values at `paths` from a raw JSON document without building
+/// the rest of the document.
+///
+/// The result has one entry per requested path, `None` when the path is absent.
+pub(crate) fn probe_paths(json: &str, paths: &[&str]) -> Result<Vec<Option<JsonValue>>, serde_json::Error> {
+    let trie = PathTrie::build(paths);
+    let mut out = vec![None; paths.len()];
+    probe(json, &trie, &mut out)?;
+    Ok(out)
+}
+
+/// Extracts the value at `segments`, member names or array indexes taken
+/// as they are, from a raw JSON document without building the rest of the
+/// document. Unlike a dotted path, a segment may contain `.`.
+pub(crate) fn probe_segments(json: &str, segments: &[&str]) -> Result<Option<JsonValue>, serde_json::Error> {
+    let mut trie = PathTrie::default();
+    let mut node = &mut trie;
+    for segment in segments {
+        node = node.children.entry(segment.to_string()).or_default();
+    }
+    node.terminals.push(0);
+
+    let mut out = [None];
+    probe(json, &trie, &mut out)?;
+    let [value] = out;
+    Ok(value)
+}
+
+fn probe(json: &str, trie: &PathTrie, out: &mut [Option<JsonValue>]) -> Result<(), serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    ProbeSeed { node: trie, out }.deserialize(&mut deserializer)?;
+    deserializer.end()
+}
+
+/// Splits a JSON Pointer (RFC 6901) into its unescaped tokens, `None` if it
+/// is neither empty nor starts with `/`.
+pub(crate) fn pointer_tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    let rest = pointer.strip_prefix('/')?;
+    Some(rest.split('/').map(|token| token.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Comparison operators accepted in a filter condition.
+const OPERATORS: &[&str] = &["$gt", "$gte", "$lt", "$lte", "$in", "$ne", "$contains"];
+
+/// Filter over record paths, e.g. `{"data.status": "pending"}`.
+///
+/// Each path maps to a condition, and a record matches when all conditions
+/// hold. A condition is either a value the path must hold exactly, or an
+/// object of operators that must all hold:
+///
+/// | Operator    | Holds when the value at the path...                          |
+/// |-------------|--------------------------------------------------------------|
+/// | `$gt`/`$gte`| is greater than (or equal to) the operand                    |
+/// | `$lt`/`$lte`| is less than (or equal to) the operand                       |
+/// | `$in`       | equals one of the values of the operand array                |
+/// | `$ne`       | differs from the operand (also when the path is absent)      |
+/// | `$contains` | is a string containing the operand, or an array holding it   |
+///
+/// Ordering operators compare numbers with numbers and strings with strings
+/// (bytewise); any other combination does not match. For example
+/// `{"data.amount": {"$gt": 100}, "data.status": {"$in": ["a", "b"]}}`.
+///
+/// Unknown operators are rejected when the filter is deserialized. An empty
+/// filter matches every record without probing it.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(try_from = "BTreeMap<String, JsonValue>", into = "BTreeMap<String, JsonValue>")]
+pub struct PathFilter(pub BTreeMap<String, JsonValue>);
+
+impl TryFrom<BTreeMap<String, JsonValue>> for PathFilter {
+    type Error = String;
+
+    fn try_from(conditions: BTreeMap<String, JsonValue>) -> Result<Self, Self::Error> {
+        for (path, condition) in &conditions {
+            for (operator, operand) in operators(condition).into_iter().flatten() {
+                if !OPERATORS.contains(&operator.as_str()) {
+                    return Err(format!("Unknown operator {operator} for {path}"));
+                }
+                if operator == "$in" && !operand.is_array() {
+                    return Err(format!("Operator $in for {path} expects an array"));
+                }
+            }
+        }
+        Ok(PathFilter(conditions))
+    }
+}
+
+impl From<PathFilter> for BTreeMap<String, JsonValue> {
+    fn from(filter: PathFilter) -> Self {
+        filter.0
+    }
+}
+
+impl PathFilter {
+    /// Returns whether this filter matches every record.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the filtered paths, in the order [`PathFilter::matches_values`] expects.
+    pub(crate) fn paths(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+
+    /// Checks the values probed at [`PathFilter::paths`] against the conditions.
+    pub(crate) fn matches_values(&self, values: &[Option<JsonValue>]) -> bool {
+        self.0.values().zip(values).all(|(condition, actual)| condition_matches(condition, actual.as_ref()))
+    }
+
+    /// Probes `json` for the filtered paths and checks them against the conditions.
+    pub(crate) fn matches_json(&self, json: &str) -> Result<bool, serde_json::Error> {
+        if self.is_empty() {
+            return Ok(true);
+        }
+
+        let probed = probe_paths(json, &self.paths())?;
+        Ok(self.matches_values(&probed))
+    }
+}
+
+/// Returns the operators of `condition` if it is an operator object.
+fn operators(condition: &JsonValue) -> Option<&serde_json::Map<String, JsonValue>> {
+    match condition {
+        JsonValue::Object(map) if !map.is_empty() && map.keys().all(|key| key.starts_with('$')) => Some(map),
+        _ => None,
+    }
+}
+
+fn condition_matches(condition: &JsonValue, actual: Option<&JsonValue>) -> bool {
+    match operators(condition) {
+        None => actual == Some(condition),
+        Some(map) => map.iter().all(|(operator, operand)| operator_matches(operator, operand, actual)),
+    }
+}
+
+fn operator_matches(operator: &str, operand: &JsonValue, actual: Option<&JsonValue>) -> bool {
+    match operator {
+        "$ne" => actual != Some(operand),
+        "$in" => match (operand, actual) {
+            (JsonValue::Array(candidates), Some(actual)) => candidates.contains(actual),
+            _ => false,
+        },
+        "$contains" => match (actual, operand) {
+            (Some(JsonValue::String(text)), JsonValue::String(needle)) => text.contains(needle.as_str()),
+            (Some(JsonValue::Array(items)), operand) => items.contains(operand),
+            _ => false,
+        },
+        "$gt" | "$gte" | "$lt" | "$lte" => {
+            let Some(ordering) = actual.and_then(|actual| compare(actual, operand)) else {
+                return false;
+            };
+            match operator {
+                "$gt" => ordering == Ordering::Greater,
+                "$gte" => ordering != Ordering::Less,
+                "$lt" => ordering == Ordering::Less,
+                _ => ordering != Ordering::Greater,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn compare(actual: &JsonValue, operand: &JsonValue) -> Option<Ordering> {
+    match (actual, operand) {
+        (JsonValue::Number(a), JsonValue::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (JsonValue::String(a), JsonValue::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Order of results by the value at a path.
+///
+/// Deserialized from `{"by": "data.created_at", "order": "desc"}`; `order`
+/// is `"asc"` or `"desc"` and defaults to ascending. Values of different
+/// types sort missing and `null` < `false` < `true` < numbers < strings <
+/// arrays and objects, like index keys. Records with equal values keep their
+/// key order.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SortSpec {
+    /// Dotted path of the sort value, rooted at the model.
+    pub by: String,
+
+    /// Sort direction.
+    #[serde(default)]
+    pub order: Direction,
+}
+
+impl SortSpec {
+    /// Sorts `models` by the value at [`SortSpec::by`].
+    pub fn apply(&self, models: &mut Vec<LocalDbModel>) {
+        let mut keyed: Vec<(Option<JsonValue>, LocalDbModel)> =
+            models.drain(..).map(|model| (model_value(&model, &self.by), model)).collect();
+
+        keyed.sort_by(|(a, _), (b, _)| {
+            let ordering = sort_order(a.as_ref(), b.as_ref());
+            match self.order {
+                Direction::Asc => ordering,
+                Direction::Desc => ordering.reverse(),
+            }
+        });
+        models.extend(keyed.into_iter().map(|(_, model)| model));
+    }
+}
+
+/// Returns the value at a dotted `path` rooted at the model.
+pub(crate) fn model_value(model: &LocalDbModel, path: &str) -> Option<JsonValue> {
+    let (root, rest) = match path.split_once('.') {
+        Some((root, rest)) => (root, Some(rest)),
+        None => (path, None),
+    };
+    let data = match root {
+        "id" => return rest.is_none().then(|| JsonValue::String(model.id.clone())),
+        "hash" => return rest.is_none().then(|| JsonValue::String(model.hash.clone())),
+        "created_at" => return model.created_at.filter(|_| rest.is_none()).map(JsonValue::from),
+        "updated_at" => return model.updated_at.filter(|_| rest.is_none()).map(JsonValue::from),
+        "data" => &model.data,
+        _ => return None,
+    };
+
+    rest.into_iter()
+        .flat_map(|rest| rest.split('.'))
+        .try_fold(data, |value, segment| match value {
+            JsonValue::Object(map) => map.get(segment),
+            JsonValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+        .cloned()
+}
+
+/// Builds an object holding each present value at its dotted path.
+fn project(paths: &[&str], values: Vec<Option<JsonValue>>) -> JsonValue {
+    let mut root = serde_json::Map::new();
+    for (path, value) in paths.iter().zip(values) {
+        let Some(value) = value else {
+            continue;
+        };
+
+        let mut node = &mut root;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            if segments.peek().is_none() {
+                node.insert(segment.to_string(), value);
+                break;
+            }
+            let child = node.entry(segment).or_insert_with(|| JsonValue::Object(serde_json::Map::new()));
+            match child {
+                JsonValue::Object(map) => node = map,
+                // A shorter projected path already holds the whole value
+                _ => break,
+            }
+        }
+    }
+    JsonValue::Object(root)
+}
+
+/// Total order over optional JSON values used for sorting.
+pub(crate) fn sort_order(a: Option<&JsonValue>, b: Option<&JsonValue>) -> Ordering {
+    fn rank(value: Option<&JsonValue>) -> u8 {
+        match value {
+            None | Some(JsonValue::Null) => 0,
+            Some(JsonValue::Bool(false)) => 1,
+            Some(JsonValue::Bool(true)) => 2,
+            Some(JsonValue::Number(_)) => 3,
+            Some(JsonValue::String(_)) => 4,
+            Some(_) => 5,
+        }
+    }
+
+    rank(a).cmp(&rank(b)).then_with(|| match (a, b) {
+        (Some(JsonValue::Number(a)), Some(JsonValue::Number(b))) => {
+            a.as_f64().unwrap_or(0.0).total_cmp(&b.as_f64().unwrap_or(0.0))
+        }
+        (Some(JsonValue::String(a)), Some(JsonValue::String(b))) => a.cmp(b),
+        (Some(a), Some(b)) if rank(Some(a)) == 5 => a.to_string().cmp(&b.to_string()),
+        _ => Ordering::Equal,
+    })
+}
+
+impl AppDbState {
+    /// Returns the records matching a [`PathFilter`], in key order.
+    ///
+    /// The filter is evaluated during cursor iteration: only the filtered
+    /// paths are probed and only matching records are fully deserialized, so
+    /// large databases can be filtered without loading every record.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let filter: PathFilter = serde_json::from_str(r#"{"data.amount": {"$gt": 100}, "data.status": {"$in": ["a", "b"]}}"#).unwrap();
+    /// let matching = db.query(&filter)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn query(&self, filter: &PathFilter) -> Result<Vec<LocalDbModel>, LmdbError> {
+        self.filter_by_paths(&filter.paths(), |values| filter.matches_values(values))
+    }
+
+    /// Returns the records matching a [`PathFilter`], ordered by a [`SortSpec`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::{PathFilter, SortSpec};
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let filter: PathFilter = serde_json::from_str(r#"{"data.status": "pending"}"#).unwrap();
+    /// let sort: SortSpec = serde_json::from_str(r#"{"by": "data.created_at", "order": "desc"}"#).unwrap();
+    /// let newest_first = db.query_sorted(&filter, &sort)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn query_sorted(&self, filter: &PathFilter, sort: &SortSpec) -> Result<Vec<LocalDbModel>, LmdbError> {
+        let mut models = self.query(filter)?;
+        sort.apply(&mut models);
+        Ok(models)
+    }
+
+    /// Returns only the given fields of the records matching a [`PathFilter`],
+    /// in key order.
+    ///
+    /// Each record is returned as a JSON object holding the projected paths,
+    /// nested as in the record: projecting `["id", "data.title"]` yields
+    /// `{"id": "n1", "data": {"title": "Groceries"}}`. Paths absent from a
+    /// record are left out. Filter and projection paths are probed in one
+    /// pass over the stored value, so records are never fully deserialized
+    /// and list views only receive the fields they render. Records with
+    /// overflowed fields, and all records while field encryption is set, are
+    /// projected from the decoded record instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let fields = vec!["id".to_string(), "data.title".to_string()];
+    /// let rows = db.query_projected(&PathFilter::default(), &fields)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn query_projected(&self, filter: &PathFilter, fields: &[String]) -> Result<Vec<JsonValue>, LmdbError> {
+        let filter_paths = filter.paths();
+        let field_paths: Vec<&str> = fields.iter().map(String::as_str).collect();
+        let paths = [filter_paths.as_slice(), field_paths.as_slice()].concat();
+
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+        let mut rows = Vec::new();
+
+        for (key, value) in cursor.iter() {
+            let json_str = match self.record_json(key, value) {
+                Ok(s) => s,
+                Err(e) => {
+                    info!("Error decoding value: {e}");
+                    continue;
+                }
+            };
+
+            let mut probed = match probe_paths(&json_str, &paths) {
+                Ok(probed) => probed,
+                Err(e) => {
+                    info!("Error probing model: {e:?}");
+                    continue;
+                }
+            };
+            if !filter.matches_values(&probed[..filter_paths.len()]) {
+                continue;
+            }
+
+            let mut values = probed.split_off(filter_paths.len());
+            if has_overflow(value) || self.field_encryption.is_some() {
+                // Projected fields may be stubs or encrypted; project the decoded record instead
+                let reassembled = self
+                    .decode_record(&txn, key, value)
+                    .and_then(|model| Ok(serde_json::to_string(&model)?))
+                    .and_then(|json| Ok(probe_paths(&json, &field_paths)?));
+                match reassembled {
+                    Ok(reassembled) => values = reassembled,
+                    Err(e) => {
+                        info!("Error reassembling model: {e}");
+                        continue;
+                    }
+                }
+            }
+            rows.push(project(&field_paths, values));
+        }
+
+        Ok(rows)
+    }
+
+    /// Returns the value at the JSON Pointer (RFC 6901) `pointer` of the
+    /// `data` of the record `id`, `None` if it does not exist; `""` returns
+    /// the whole of `data`.
+    ///
+    /// The pointed value is probed from the stored value like
+    /// [`AppDbState::query_projected`] does, so the rest of a large record is
+    /// never built. Records with overflowed fields, and all records while
+    /// field encryption is set, are decoded in full instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("docs".to_string())?;
+    ///
+    /// let title = db.get_field("doc_1", "/meta/title")?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `pointer` is not a JSON
+    /// Pointer, [`AppResponse::NotFound`] if no record has the ID, or an
+    /// error if the read fails or the record cannot be decoded.
+    pub fn get_field(&self, id: &str, pointer: &str) -> Result<Option<JsonValue>, AppResponse> {
+        let tokens = pointer_tokens(pointer).ok_or_else(|| AppResponse::BadRequest(format!("{pointer} is not a JSON Pointer")))?;
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let value = match txn.get(db, &id) {
+            Ok(value) => value,
+            Err(LmdbError::NotFound) => return Err(AppResponse::NotFound(format!("No model found with id: {id}"))),
+            Err(e) => return Err(e.into()),
+        };
+
+        let json_str = self.record_json(id.as_bytes(), value)?;
+        if has_overflow(value) || self.field_encryption.is_some() {
+            // Stubs and encrypted fields are only resolved on the whole record
+            let model = self.decode_record(&txn, id.as_bytes(), value)?;
+            return Ok(model.data.pointer(pointer).cloned());
+        }
+        let segments: Vec<&str> = std::iter::once("data").chain(tokens.iter().map(String::as_str)).collect();
+        Ok(probe_segments(&json_str, &segments)?)
+    }
+
+    /// Returns the records for which `predicate` holds, probing only the given paths.
+    ///
+    /// The predicate receives the probed values in the same order as `paths`
+    /// (`None` for absent paths). Non-matching records are never fully
+    /// deserialized; records whose value cannot be decoded are logged and skipped,
+    /// consistent with [`AppDbState::get`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let pending = db.filter_by_paths(&["data.status"], |values| {
+    ///     values[0] == Some(json!("pending"))
+    /// })?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn filter_by_paths<F>(&self, paths: &[&str], predicate: F) -> Result<Vec<LocalDbModel>, LmdbError>
+    where
+        F: Fn(&[Option<JsonValue>]) -> bool,
+    {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+        let mut models = Vec::new();
+
+        for (key, value) in cursor.iter() {
+            let json_str = match self.record_json(key, value) {
+                Ok(s) => s,
+                Err(e) => {
+                    info!("Error decoding value: {e}");
+                    continue;
+                }
+            };
+
+            match probe_paths(&json_str, paths) {
+                Ok(probed) if predicate(&probed) => match serde_json::from_str::<LocalDbModel>(&json_str) {
+                    Ok(mut model) => match self.resolve_overflow(&txn, value, &mut model).and_then(|()| self.open_fields(&mut model)) {
+                        Ok(()) => models.push(model),
+                        Err(e) => info!("Error reassembling model: {e}"),
+                    },
+                    Err(e) => info!("Error deserializing model: {e:?}"),
+                },
+                Ok(_) => {}
+                Err(e) => info!("Error probing model: {e:?}"),
+            }
+        }
+
+        Ok(models)
+    }
+}
@@ -0,0 +1,110 @@
+//! Token-bucket rate limiting of writes.
+//!
+//! Each call writing records on behalf of the app takes one token: `post`,
+//! `put`, `upsert`, `update_if_hash`, `compare_and_swap`, `delete_by_id`,
+//! `delete_many`, `apply_json_patch`, `write_transaction`, the collection
+//! writes, `clear_namespace`, `merge_remote`, `apply_remote_changes`,
+//! `resolve_conflict`, `soft_delete`, `restore` and `revert_to_version`.
+//! Bulk and maintenance operations are not throttled, as each is one
+//! deliberate call: `clear_all_records`, `import_ndjson`, `import_from_file`,
+//! `apply_dataset_patch`, `backfill_field`, `migrate`, the expiry sweep and
+//! `purge_deleted`, nor are writes of settings and sync bookkeeping.
+//!
+//! The bucket holds up to `burst` tokens and refills at `ops_per_sec`, so
+//! short bursts go through while a runaway sync loop or logging bug is held
+//! to the sustained rate instead of saturating storage I/O. A write that finds
+//! the bucket empty is rejected with [`AppResponse::Busy`] carrying the time
+//! until the next token.
+
+use std::sync::PoisonError;
+use std::time::Instant;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::WriteRateLimit;
+use crate::local_db_state::AppDbState;
+
+/// Token bucket state behind [`WriteRateLimit`].
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    limit: WriteRateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: WriteRateLimit) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            limit,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes one token, or returns the milliseconds until one is available.
+    fn acquire(&mut self) -> Result<(), u64> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.ops_per_sec).min(f64::from(self.limit.burst));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - self.tokens) / self.limit.ops_per_sec * 1000.0).ceil() as u64)
+        }
+    }
+}
+
+impl AppDbState {
+    /// Limits the write transactions of this instance, or removes the limit
+    /// with `None` (the default).
+    ///
+    /// The limit is not persisted; apps set it after opening the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::WriteRateLimit;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("events".to_string())?;
+    /// db.set_write_rate_limit(Some(WriteRateLimit { ops_per_sec: 50.0, burst: 200 }))?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `ops_per_sec` is not a positive
+    /// finite number or `burst` is zero.
+    pub fn set_write_rate_limit(&mut self, limit: Option<WriteRateLimit>) -> Result<(), AppResponse> {
+        if let Some(limit) = &limit {
+            if !(limit.ops_per_sec.is_finite() && limit.ops_per_sec > 0.0) || limit.burst == 0 {
+                return Err(AppResponse::BadRequest(
+                    "Write rate limit needs a positive ops_per_sec and a burst of at least 1".to_string(),
+                ));
+            }
+        }
+
+        *self.write_limiter.get_mut().unwrap_or_else(PoisonError::into_inner) = limit.map(TokenBucket::new);
+        Ok(())
+    }
+
+    /// Returns the current write rate limit.
+    pub fn write_rate_limit(&self) -> Option<WriteRateLimit> {
+        let limiter = self.write_limiter.lock().unwrap_or_else(PoisonError::into_inner);
+        limiter.as_ref().map(|bucket| bucket.limit.clone())
+    }
+
+    /// Takes a token for one write transaction.
+    pub(crate) fn throttle_write(&self) -> Result<(), AppResponse> {
+        let mut limiter = self.write_limiter.lock().unwrap_or_else(PoisonError::into_inner);
+        match limiter.as_mut().map(TokenBucket::acquire) {
+            Some(Err(retry_after_ms)) => Err(AppResponse::Busy {
+                message: "Write rate limit exceeded".to_string(),
+                retry_after_ms,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
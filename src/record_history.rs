@@ -0,0 +1,229 @@
+//! Version history of records.
+//!
+//! Offline editors offering undo or an audit trail need the earlier versions
+//! of a record. With history mode on, every write of a record keeps the
+//! version written, and every deletion a tombstone, up to the last `limit`
+//! versions of each record. [`AppDbState::get_history`] lists them and
+//! [`AppDbState::revert_to_version`] writes one of them again.
+//!
+//! The versions live in the `__history` database:
+//!
+//! ```text
+//! {id} 0x00 {version, u64 BE}  -> {HistoryEntry, JSON}
+//! ```
+//!
+//! A version is the commit sequence of the transaction that wrote it; a
+//! record written twice in one transaction keeps its last version. The
+//! history of a deleted record is kept, so it can be restored. Removing all
+//! records drops the history. Records with encrypted fields or encrypted for
+//! a tenant have no history, so their plaintext is never stored.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde_json::json;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{Direction, HistoryEntry, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, HISTORY_LIMIT_KEY, META_DB_NAME};
+use crate::scan::{scan_directed, scan_from};
+
+/// Side database holding the record versions of history mode.
+pub(crate) const HISTORY_DB_NAME: &str = "__history";
+
+/// Keeps the history of the records written within a write transaction.
+pub(crate) struct HistoryTracker {
+    history_db: Database,
+    limit: u64,
+}
+
+impl HistoryTracker {
+    /// Adds the version of `model` written by the transaction `version` at
+    /// `written_at`.
+    pub(crate) fn track(&self, txn: &mut RwTransaction, model: &LocalDbModel, version: u64, written_at: u64) -> Result<(), AppResponse> {
+        let entry = HistoryEntry { version, written_at, record: Some(model.clone()) };
+        let key = model.id.as_bytes();
+        txn.put(self.history_db, &history_key(key, version), &serde_json::to_vec(&entry)?, WriteFlags::empty())?;
+        trim(txn, self.history_db, key, self.limit)?;
+        Ok(())
+    }
+
+    /// Adds the deletion of the record `key` by the transaction `version` at
+    /// `written_at`.
+    pub(crate) fn track_del(&self, txn: &mut RwTransaction, key: &[u8], version: u64, written_at: u64) -> Result<(), LmdbError> {
+        let entry = json!({"version": version, "written_at": written_at, "record": null});
+        txn.put(self.history_db, &history_key(key, version), &entry.to_string(), WriteFlags::empty())?;
+        trim(txn, self.history_db, key, self.limit)
+    }
+
+    /// Drops the history of the record `key`.
+    pub(crate) fn forget(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<(), LmdbError> {
+        trim(txn, self.history_db, key, 0)
+    }
+
+    /// Drops every version, for when all records are removed.
+    pub(crate) fn reset(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        txn.clear_db(self.history_db)
+    }
+}
+
+impl AppDbState {
+    /// Keeps the last `limit` versions of each record written from now on,
+    /// trimming longer histories. `0` turns history mode off and drops all
+    /// history.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("docs".to_string())?;
+    /// db.set_history_limit(20)?;
+    ///
+    /// // Undo the last edit of doc_1
+    /// if let Some(previous) = db.get_history("doc_1")?.get(1) {
+    ///     db.revert_to_version("doc_1", previous.version)?;
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write fails.
+    pub fn set_history_limit(&self, limit: u64) -> Result<(), AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let (_, history_db) = self.side_db(HISTORY_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        if limit == 0 {
+            match txn.del(meta_db, &HISTORY_LIMIT_KEY, None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+            txn.clear_db(history_db)?;
+        } else {
+            txn.put(meta_db, &HISTORY_LIMIT_KEY, &limit.to_be_bytes(), WriteFlags::empty())?;
+            let ids: Vec<Vec<u8>> = {
+                let cursor = txn.open_ro_cursor(history_db)?;
+                let mut ids: Vec<Vec<u8>> = Vec::new();
+                for (key, _) in scan_from(&cursor, None) {
+                    let id = record_id(key);
+                    if ids.last().is_none_or(|last| last.as_slice() != id) {
+                        ids.push(id.to_vec());
+                    }
+                }
+                ids
+            };
+            for id in &ids {
+                trim(&mut txn, history_db, id, limit)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the number of versions kept of each record, `None` while
+    /// history mode is off.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn history_limit(&self) -> Result<Option<u64>, LmdbError> {
+        self.meta_u64(HISTORY_LIMIT_KEY)
+    }
+
+    /// Returns the kept versions of the record `id`, newest first, the first
+    /// being the current version unless the record was written while history
+    /// mode was off.
+    ///
+    /// # Examples
+    ///
+    /// See [`AppDbState::set_history_limit`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the read transaction fails or a version cannot be
+    /// decoded.
+    pub fn get_history(&self, id: &str) -> Result<Vec<HistoryEntry>, AppResponse> {
+        let (env, history_db) = self.side_db(HISTORY_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(history_db)?;
+
+        let prefix = [id.as_bytes(), &[0x00]].concat();
+        let upper = [prefix.as_slice(), &[0xFF]].concat();
+        scan_directed(&cursor, Some(upper.as_slice()), Direction::Desc)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, value)| Ok(serde_json::from_slice(value)?))
+            .collect()
+    }
+
+    /// Writes `version` of the record `id` again, as a new version, and
+    /// returns it. Reverting is itself undoable, and restores a deleted
+    /// record.
+    ///
+    /// # Examples
+    ///
+    /// See [`AppDbState::set_history_limit`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::NotFound`] if the history of `id` does not hold
+    /// `version`, [`AppResponse::BadRequest`] if `version` is a deletion, or
+    /// the errors of [`AppDbState::put`].
+    pub fn revert_to_version(&self, id: &str, version: u64) -> Result<LocalDbModel, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let (_, history_db) = self.side_db(HISTORY_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let entry: HistoryEntry = match txn.get(history_db, &history_key(id.as_bytes(), version)) {
+            Ok(value) => serde_json::from_slice(value)?,
+            Err(LmdbError::NotFound) => return Err(AppResponse::NotFound(format!("No version {version} in the history of {id}"))),
+            Err(e) => return Err(e.into()),
+        };
+        let mut model = entry
+            .record
+            .ok_or_else(|| AppResponse::BadRequest(format!("Version {version} of {id} is a deletion")))?;
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        writer.commit(txn)?;
+        Ok(model)
+    }
+
+    /// Returns the history tracker for a write transaction, `None` while
+    /// history mode is off.
+    pub(crate) fn history_tracker<T: Transaction>(&self, txn: &T) -> Result<Option<HistoryTracker>, LmdbError> {
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let Some(limit) = get_meta_u64(txn, meta_db, HISTORY_LIMIT_KEY)? else {
+            return Ok(None);
+        };
+        let (_, history_db) = self.side_db(HISTORY_DB_NAME)?;
+        Ok(Some(HistoryTracker { history_db, limit }))
+    }
+}
+
+/// Deletes the oldest versions of the record `key` beyond `limit`.
+fn trim(txn: &mut RwTransaction, history_db: Database, key: &[u8], limit: u64) -> Result<(), LmdbError> {
+    let prefix = [key, &[0x00]].concat();
+    let keys: Vec<Vec<u8>> = {
+        let cursor = txn.open_ro_cursor(history_db)?;
+        scan_from(&cursor, Some(&prefix))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, _)| key.to_vec())
+            .collect()
+    };
+    let excess = keys.len().saturating_sub(limit as usize);
+    for key in &keys[..excess] {
+        txn.del(history_db, key, None)?;
+    }
+    Ok(())
+}
+
+/// Returns the record ID of a history key.
+fn record_id(key: &[u8]) -> &[u8] {
+    &key[..key.len().saturating_sub(9)]
+}
+
+fn history_key(key: &[u8], version: u64) -> Vec<u8> {
+    [key, &[0x00], &version.to_be_bytes()].concat()
+}
@@ -0,0 +1,83 @@
+//! Registry of the databases opened over FFI.
+//!
+//! FFI callers never hold a pointer to an [`AppDbState`]. [`create_db`]
+//! returns an opaque integer handle, and every other function looks the
+//! database up by that handle. A handle that was closed or never existed is
+//! rejected with an error instead of dereferencing freed memory, and opening
+//! a name that is already open returns its existing handle, so a Flutter hot
+//! restart that lost its handles gets the live database back instead of
+//! opening its environment a second time.
+//!
+//! [`create_db`]: crate::create_db
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use log::info;
+
+use crate::local_db_state::AppDbState;
+
+/// Opaque handle of a database opened over FFI; `0` is never a valid handle.
+pub type DbHandle = u64;
+
+/// A registered database, locked for writing only by the calls that change
+/// its configuration or lifecycle.
+pub(crate) type SharedDb = Arc<RwLock<AppDbState>>;
+
+struct Registry {
+    next_handle: DbHandle,
+    databases: BTreeMap<DbHandle, (String, SharedDb)>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry { next_handle: 1, databases: BTreeMap::new() });
+
+/// Serializes opens, so that a name is never opened twice concurrently.
+static OPENING: Mutex<()> = Mutex::new(());
+
+/// Returns the handle of the open database `name`, or registers the database
+/// returned by `open` under a new handle.
+pub(crate) fn open<E>(name: &str, open: impl FnOnce() -> Result<AppDbState, E>) -> Result<DbHandle, E> {
+    let _opening = OPENING.lock().unwrap_or_else(PoisonError::into_inner);
+    let existing = {
+        let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+        registry.databases.iter().find(|(_, (open_name, _))| open_name == name).map(|(&handle, _)| handle)
+    };
+    if let Some(handle) = existing {
+        info!("Database {name} is already open, reusing handle {handle}");
+        return Ok(handle);
+    }
+
+    let state = open()?;
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let handle = registry.next_handle;
+    registry.next_handle += 1;
+    registry.databases.insert(handle, (name.to_string(), Arc::new(RwLock::new(state))));
+    Ok(handle)
+}
+
+/// Records that the database behind `handle` now lives under `name`.
+pub(crate) fn rename(handle: DbHandle, name: &str) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((open_name, _)) = registry.databases.get_mut(&handle) {
+        *open_name = name.to_string();
+    }
+}
+
+/// Returns the database behind `handle`.
+pub(crate) fn get(handle: DbHandle) -> Option<SharedDb> {
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.databases.get(&handle).map(|(_, db)| Arc::clone(db))
+}
+
+/// Returns the open database whose directory is `path`.
+pub(crate) fn find(path: &str) -> Option<SharedDb> {
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.databases.values().find(|(open_name, _)| open_name == path).map(|(_, db)| Arc::clone(db))
+}
+
+/// Unregisters `handle`, returning its database. Calls already running on it
+/// keep it alive until they return.
+pub(crate) fn remove(handle: DbHandle) -> Option<SharedDb> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.databases.remove(&handle).map(|(_, db)| db)
+}
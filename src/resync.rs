@@ -0,0 +1,113 @@
+//! Repair-by-resync queue.
+//!
+//! When a record is found to be corrupt or is quarantined, deleting it would
+//! lose the only hint that it ever existed. Instead, the application flags the
+//! IDs here and the sync layer drains the queue by re-downloading those records
+//! from the server, acknowledging each one with [`AppDbState::clear_resync`].
+//!
+//! The queue lives in its own internal database so it never shows up in the
+//! record APIs.
+
+use lmdb::{Cursor, Error as LmdbError, Transaction, WriteFlags};
+use log::info;
+
+use crate::local_db_model::ResyncEntry;
+use crate::local_db_state::AppDbState;
+
+/// Name of the internal database holding the resync queue.
+pub(crate) const RESYNC_DB_NAME: &str = "__resync";
+
+impl AppDbState {
+    /// Flags records for re-download from the server.
+    ///
+    /// Returns the number of newly queued IDs; IDs that are already queued keep
+    /// their original `marked_at`. The IDs do not need to exist in the main
+    /// database, so records that were already lost can still be requested again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let quarantined: Vec<String> = db
+    ///     .quarantine_list()?
+    ///     .into_iter()
+    ///     .map(|record| record.id)
+    ///     .collect();
+    /// db.mark_for_resync(&quarantined)?;
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write transaction fails.
+    pub fn mark_for_resync(&self, ids: &[String]) -> Result<usize, LmdbError> {
+        let (env, db) = self.side_db(RESYNC_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let marked_at = self.now_ms();
+        let mut marked = 0;
+
+        for id in ids {
+            let entry = ResyncEntry { id: id.clone(), marked_at };
+            let value = serde_json::to_vec(&entry).map_err(|_| LmdbError::Other(1))?;
+            match txn.put(db, id, &value, WriteFlags::NO_OVERWRITE) {
+                Ok(()) => marked += 1,
+                Err(LmdbError::KeyExist) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        txn.commit()?;
+        Ok(marked)
+    }
+
+    /// Returns every queued entry in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn get_resync_queue(&self) -> Result<Vec<ResyncEntry>, LmdbError> {
+        let (env, db) = self.side_db(RESYNC_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        let entries = cursor
+            .iter()
+            .filter_map(|(_, value)| match serde_json::from_slice::<ResyncEntry>(value) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    info!("Error deserializing resync entry: {e:?}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Removes entries from the queue once their records have been re-downloaded.
+    ///
+    /// Returns the number of entries removed; IDs that were not queued are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write transaction fails.
+    pub fn clear_resync(&self, ids: &[String]) -> Result<usize, LmdbError> {
+        let (env, db) = self.side_db(RESYNC_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let mut cleared = 0;
+
+        for id in ids {
+            match txn.del(db, id, None) {
+                Ok(()) => cleared += 1,
+                Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        txn.commit()?;
+        Ok(cleared)
+    }
+}
@@ -0,0 +1,90 @@
+//! Positioned cursor scans.
+//!
+//! The iterator helpers of the `lmdb` crate (`iter_start`, `iter_from`) panic
+//! when the positioning operation finds nothing, e.g. on an empty database or
+//! when the start key sorts after the last entry, and they only walk forwards.
+//! [`Scan`] performs the same walk in either direction on top of
+//! [`Cursor::get`] and simply ends when LMDB reports `MDB_NOTFOUND`.
+
+use std::os::raw::c_uint;
+
+use lmdb::{Cursor, Error as LmdbError, RoCursor};
+use lmdb_sys::{MDB_FIRST, MDB_LAST, MDB_NEXT, MDB_PREV, MDB_SET_RANGE};
+use log::warn;
+
+use crate::local_db_model::Direction;
+
+type Entry<'txn> = (&'txn [u8], &'txn [u8]);
+
+/// Iterator over `(key, value)` pairs starting at a cursor position.
+pub(crate) struct Scan<'c, 'txn> {
+    cursor: &'c RoCursor<'txn>,
+    start: Option<&'c [u8]>,
+    direction: Direction,
+    started: bool,
+    done: bool,
+}
+
+/// Walks the database in ascending key order, starting at the first key that
+/// is greater than or equal to `start` (or at the first key when `start` is `None`).
+pub(crate) fn scan_from<'c, 'txn>(cursor: &'c RoCursor<'txn>, start: Option<&'c [u8]>) -> Scan<'c, 'txn> {
+    scan_directed(cursor, start, Direction::Asc)
+}
+
+/// Walks the database in `direction`. Descending scans start at the last key
+/// that is less than or equal to `start` (or at the last key when `start` is `None`).
+pub(crate) fn scan_directed<'c, 'txn>(cursor: &'c RoCursor<'txn>, start: Option<&'c [u8]>, direction: Direction) -> Scan<'c, 'txn> {
+    Scan { cursor, start, direction, started: false, done: false }
+}
+
+impl<'txn> Scan<'_, 'txn> {
+    fn get(&self, key: Option<&[u8]>, op: c_uint) -> Option<Entry<'txn>> {
+        match self.cursor.get(key, None, op) {
+            Ok((Some(key), value)) => Some((key, value)),
+            Ok((None, _)) | Err(LmdbError::NotFound) => None,
+            Err(e) => {
+                warn!("Cursor scan stopped early: {e:?}");
+                None
+            }
+        }
+    }
+
+    fn first(&self) -> Option<Entry<'txn>> {
+        match (self.direction, self.start) {
+            (Direction::Asc, None) => self.get(None, MDB_FIRST),
+            (Direction::Asc, Some(start)) => self.get(Some(start), MDB_SET_RANGE),
+            (Direction::Desc, None) => self.get(None, MDB_LAST),
+            // SET_RANGE lands on the first key >= start: keep it on an exact
+            // match, otherwise step back; past the end, begin at the last key.
+            (Direction::Desc, Some(start)) => match self.get(Some(start), MDB_SET_RANGE) {
+                Some(entry) if entry.0 == start => Some(entry),
+                Some(_) => self.get(None, MDB_PREV),
+                None => self.get(None, MDB_LAST),
+            },
+        }
+    }
+}
+
+impl<'txn> Iterator for Scan<'_, 'txn> {
+    type Item = Entry<'txn>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let entry = if !self.started {
+            self.started = true;
+            self.first()
+        } else {
+            match self.direction {
+                Direction::Asc => self.get(None, MDB_NEXT),
+                Direction::Desc => self.get(None, MDB_PREV),
+            }
+        };
+
+        // An unpositioned cursor must not be stepped again.
+        self.done = entry.is_none();
+        entry
+    }
+}
@@ -0,0 +1,162 @@
+//! Read-your-writes consistency through commit sequences.
+//!
+//! Every record write transaction is assigned the next commit sequence,
+//! stored in the `__meta` database together with the records it wrote. A
+//! writer reads [`AppDbState::commit_sequence`] after its write and hands the
+//! value to a reader, for example another isolate; reads given that value as
+//! `min_sequence` wait until a transaction at least that recent is visible, so
+//! they never observe state older than the write.
+//!
+//! The same sequence is the change token of hosts that poll instead of
+//! watching: [`AppDbState::change_token`] is read when the UI renders, and
+//! [`AppDbState::changes_between`] later tells whether anything was committed
+//! since, without reading a record.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ChangeSummary, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::meta::COMMIT_SEQUENCE_KEY;
+use crate::query::PathFilter;
+
+/// How long reads wait for their `min_sequence` to become visible.
+const SEQUENCE_WAIT: Duration = Duration::from_millis(500);
+
+/// Longest pause between two checks of the commit sequence.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+impl AppDbState {
+    /// Returns the sequence of the last committed record write, `0` when the
+    /// database was never written.
+    ///
+    /// The value read right after a write covers that write and can be passed
+    /// as `min_sequence` to [`AppDbState::get_by_id_at`] or
+    /// [`AppDbState::query_at`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn commit_sequence(&self) -> Result<u64, lmdb::Error> {
+        Ok(self.meta_u64(COMMIT_SEQUENCE_KEY)?.unwrap_or(0))
+    }
+
+    /// Returns a token identifying the current state of the records, to
+    /// compare later with [`AppDbState::changes_between`]. It is the commit
+    /// sequence, so it only grows until the database is reset.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("todos".to_string())?;
+    ///
+    /// let rendered = db.change_token()?;
+    /// // ... on the next frame
+    /// if db.changes_between(rendered, db.change_token()?)?.changed {
+    ///     // render again
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn change_token(&self) -> Result<u64, lmdb::Error> {
+        self.commit_sequence()
+    }
+
+    /// Returns what was committed between the tokens `from` and `to`, both
+    /// returned by [`AppDbState::change_token`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `from` is newer than `to` or
+    /// `to` was not issued yet, as for a token from before
+    /// [`AppDbState::reset_database`]; the host should then render again and
+    /// take a new token. Returns a database error if the sequence cannot be
+    /// read.
+    pub fn changes_between(&self, from: u64, to: u64) -> Result<ChangeSummary, AppResponse> {
+        if from > to {
+            return Err(AppResponse::BadRequest(format!("Change token {from} is newer than {to}")));
+        }
+        let current = self.commit_sequence()?;
+        if to > current {
+            return Err(AppResponse::BadRequest(format!("Change token {to} was not issued by this database, which is at {current}")));
+        }
+        Ok(ChangeSummary { from, to, changed: to > from, commits: to - from })
+    }
+
+    /// Waits until the commit sequence reaches `min_sequence`, checking with
+    /// a growing interval for at most `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::Busy`] if the sequence is still behind after
+    /// `timeout`, or a database error if it cannot be read. A sequence from
+    /// before [`AppDbState::reset_database`] is never reached again.
+    pub fn wait_for_sequence(&self, min_sequence: u64, timeout: Duration) -> Result<(), AppResponse> {
+        let deadline = Instant::now() + timeout;
+        let mut interval = Duration::from_millis(1);
+
+        loop {
+            let current = self.commit_sequence()?;
+            if current >= min_sequence {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(AppResponse::Busy {
+                    message: format!("Commit sequence {min_sequence} not visible yet (at {current})"),
+                    retry_after_ms: MAX_POLL_INTERVAL.as_millis() as u64,
+                });
+            }
+            thread::sleep(interval.min(deadline - now));
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// Like [`AppDbState::get_by_id`], but first waits up to half a
+    /// second for the commit sequence to reach `min_sequence`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::LocalDbModel;
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// db.post(LocalDbModel { id: "n1".to_string(), hash: "h".to_string(), data: json!({}), ..Default::default() })?;
+    /// let token = db.commit_sequence()?;
+    ///
+    /// // Elsewhere, e.g. on another isolate
+    /// assert!(db.get_by_id_at("n1", token)?.is_some());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::Busy`] if the sequence is not reached in time,
+    /// or the errors of [`AppDbState::get_by_id`].
+    pub fn get_by_id_at(&self, id: &str, min_sequence: u64) -> Result<Option<LocalDbModel>, AppResponse> {
+        self.wait_for_sequence(min_sequence, SEQUENCE_WAIT)?;
+        self.get_by_id(id)
+    }
+
+    /// Like [`AppDbState::query`], but first waits up to half a second
+    /// for the commit sequence to reach `min_sequence`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::Busy`] if the sequence is not reached in time,
+    /// or the errors of [`AppDbState::query`].
+    pub fn query_at(&self, filter: &PathFilter, min_sequence: u64) -> Result<Vec<LocalDbModel>, AppResponse> {
+        self.wait_for_sequence(min_sequence, SEQUENCE_WAIT)?;
+        Ok(self.query(filter)?)
+    }
+}
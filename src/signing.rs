@@ -0,0 +1,65 @@
+//! Detached ed25519 signatures for distributed content.
+//!
+//! Datasets and patches distributed outside the app store are authenticated
+//! with a detached signature: the file `{path}.sig` next to the content holds
+//! the hex-encoded 64-byte ed25519 signature of the content's exact bytes.
+//! Verification requires the `signing` feature (enabled by default).
+
+use std::fs;
+
+use crate::app_response::AppResponse;
+
+/// Verifies `data`, read from `path`, against the signature in `{path}.sig`.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::BadRequest`] if the public key or signature is
+/// malformed, if the signature does not match, or if this build lacks the
+/// `signing` feature; [`AppResponse::NotFound`] if the signature file is missing.
+pub(crate) fn verify_detached(path: &str, data: &[u8], public_key_hex: &str) -> Result<(), AppResponse> {
+    let sig_path = format!("{path}.sig");
+    let sig_hex = fs::read_to_string(&sig_path)
+        .map_err(|e| AppResponse::NotFound(format!("Cannot read signature {sig_path}: {e}")))?;
+
+    let public_key: [u8; 32] = decode_hex(public_key_hex)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AppResponse::BadRequest("Public key must be 32 hex-encoded bytes".to_string()))?;
+    let signature: [u8; 64] = decode_hex(sig_hex.trim())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AppResponse::BadRequest(format!("Signature in {sig_path} must be 64 hex-encoded bytes")))?;
+
+    verify(&public_key, data, &signature)
+}
+
+#[cfg(feature = "signing")]
+fn verify(public_key: &[u8; 32], data: &[u8], signature: &[u8; 64]) -> Result<(), AppResponse> {
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    let key = VerifyingKey::from_bytes(public_key)
+        .map_err(|e| AppResponse::BadRequest(format!("Invalid public key: {e}")))?;
+    key.verify_strict(data, &Signature::from_bytes(signature))
+        .map_err(|_| AppResponse::BadRequest("Signature verification failed".to_string()))
+}
+
+#[cfg(not(feature = "signing"))]
+fn verify(_: &[u8; 32], _: &[u8], _: &[u8; 64]) -> Result<(), AppResponse> {
+    Err(AppResponse::BadRequest(
+        "Signature verification is not supported by this build".to_string(),
+    ))
+}
+
+/// Decodes a hex string, returning `None` on odd length or invalid digits.
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encodes bytes as a lowercase hex string.
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
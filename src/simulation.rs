@@ -0,0 +1,389 @@
+//! Deterministic multi-device sync simulation.
+//!
+//! A [`Simulation`] replays an interleaved log of operations from several
+//! simulated devices against the merge and conflict engine: each device is a
+//! real database, and a sync step pulls the server changes with
+//! [`AppDbState::merge_remote`], then pushes the device's delta to an
+//! in-memory server. The clocks of all devices are frozen at a time derived
+//! from the step, so a log always yields the same [`SimulationReport`]. A
+//! test can assert on it, or check [`SimulationReport::converged`] after
+//! random interleavings from [`interleave`].
+//!
+//! Requires the `simulation` feature; meant for tests, not for apps.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ConflictResolution, DbOptions, LocalDbModel, MergeResult, RemoteChanges};
+use crate::local_db_state::AppDbState;
+
+/// Delta consumer of the simulated sync layer.
+const SYNC_CONSUMER: &str = "sync";
+
+/// Clock of the first step, in milliseconds since the Unix epoch.
+const START_MS: u64 = 1_700_000_000_000;
+
+/// Milliseconds the clocks advance per step.
+const STEP_MS: u64 = 1_000;
+
+/// One operation of a simulated device.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"op": "put", "device": "phone", "record": {"id": "n1", "hash": "h1", "data": {}}}
+/// {"op": "delete", "device": "phone", "id": "n1"}
+/// {"op": "sync", "device": "tablet"}
+/// {"op": "resolve", "device": "tablet", "id": "n1", "resolution": "remote"}
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SimOp {
+    /// Writes a record locally.
+    Put { device: String, record: LocalDbModel },
+
+    /// Deletes a record locally.
+    Delete { device: String, id: String },
+
+    /// Pulls and merges the server changes, then pushes the local ones.
+    Sync { device: String },
+
+    /// Settles a conflict in the device's inbox.
+    Resolve { device: String, id: String, resolution: ConflictResolution },
+}
+
+impl SimOp {
+    /// Returns the device running the operation.
+    pub fn device(&self) -> &str {
+        match self {
+            SimOp::Put { device, .. } | SimOp::Delete { device, .. } | SimOp::Sync { device } | SimOp::Resolve { device, .. } => device,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SimOp::Put { .. } => "put",
+            SimOp::Delete { .. } => "delete",
+            SimOp::Sync { .. } => "sync",
+            SimOp::Resolve { .. } => "resolve",
+        }
+    }
+}
+
+/// Operations to replay.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"ops": [{"op": "put", "device": "phone", "record": {"id": "n1", "hash": "h1", "data": {}}}], "seed": 7, "settle": true}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SimulationLog {
+    /// Operations in replay order.
+    pub ops: Vec<SimOp>,
+
+    /// Reorders `ops` with [`interleave`] before the replay, keeping the
+    /// order of each device's own operations.
+    pub seed: Option<u64>,
+
+    /// Syncs every device twice after the log, in device order, so that the
+    /// devices converge unless conflicts are left unresolved.
+    pub settle: bool,
+}
+
+/// Outcome of one replayed operation.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SimStep {
+    /// Device running the operation.
+    pub device: String,
+
+    /// Name of the operation, e.g. `sync`.
+    pub op: String,
+
+    /// Result of the merge of a sync step.
+    pub merge: Option<MergeResult>,
+
+    /// Records and deletions a sync step pushed to the server.
+    pub pushed: usize,
+
+    /// Error of a failed operation; the replay goes on.
+    pub error: Option<String>,
+}
+
+/// State of a simulated device after the replay.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct SimDeviceState {
+    /// Records of the device, in ID order.
+    pub records: Vec<LocalDbModel>,
+
+    /// IDs of the records in the device's conflict inbox.
+    pub conflicts: Vec<String>,
+}
+
+/// Result of a replay.
+///
+/// # JSON Format
+///
+/// ```json
+/// {"steps": [...], "devices": {"phone": {"records": [...], "conflicts": []}}, "server": [...], "converged": true}
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct SimulationReport {
+    /// Outcome of each operation, in replay order.
+    pub steps: Vec<SimStep>,
+
+    /// Final state of each device.
+    pub devices: BTreeMap<String, SimDeviceState>,
+
+    /// Records on the server, in ID order.
+    pub server: Vec<LocalDbModel>,
+
+    /// Whether every device holds the server's records and no conflicts.
+    pub converged: bool,
+}
+
+/// In-memory sync server: the latest version of each record, `None` once
+/// deleted, with the server sequence of its last change.
+#[derive(Default)]
+struct SimServer {
+    sequence: u64,
+    records: BTreeMap<String, (u64, Option<LocalDbModel>)>,
+}
+
+impl SimServer {
+    fn changes_since(&self, since: u64) -> RemoteChanges {
+        let mut changes = RemoteChanges { consumer: SYNC_CONSUMER.to_string(), records: Vec::new(), deleted: Vec::new() };
+        for (id, (_, record)) in self.records.iter().filter(|(_, (sequence, _))| *sequence > since) {
+            match record {
+                Some(record) => changes.records.push(record.clone()),
+                None => changes.deleted.push(id.clone()),
+            }
+        }
+        changes
+    }
+
+    /// Stores a pushed version, returning whether it changed anything.
+    fn push(&mut self, id: &str, record: Option<LocalDbModel>) -> bool {
+        let current = self.records.get(id).and_then(|(_, record)| record.as_ref());
+        if current.map(|record| &record.hash) == record.as_ref().map(|record| &record.hash) {
+            return false;
+        }
+        self.sequence += 1;
+        self.records.insert(id.to_string(), (self.sequence, record));
+        true
+    }
+}
+
+struct SimDevice {
+    db: AppDbState,
+    /// Server sequence pulled last.
+    pulled: u64,
+}
+
+/// Simulated devices syncing through an in-memory server.
+pub struct Simulation {
+    dir: PathBuf,
+    remove_dir: bool,
+    devices: BTreeMap<String, SimDevice>,
+    server: SimServer,
+    clock_ms: u64,
+}
+
+impl Simulation {
+    /// Creates a simulation keeping the device databases in `dir`. Devices
+    /// are created on their first operation.
+    pub fn new(dir: &Path) -> Self {
+        Simulation { dir: dir.to_path_buf(), remove_dir: false, devices: BTreeMap::new(), server: SimServer::default(), clock_ms: START_MS }
+    }
+
+    /// Creates a simulation in a new directory under the system temporary
+    /// directory, removed when the simulation is dropped.
+    pub fn in_temp_dir() -> Self {
+        static RUNS: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "offline_first_simulation_{}_{}",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        Simulation { dir, remove_dir: true, devices: BTreeMap::new(), server: SimServer::default(), clock_ms: START_MS }
+    }
+
+    /// Replays `log` and returns the report.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::simulation::{Simulation, SimulationLog};
+    ///
+    /// let log: SimulationLog = serde_json::from_str(r#"{"ops": [
+    ///     {"op": "put", "device": "phone", "record": {"id": "n1", "hash": "a", "data": {}}},
+    ///     {"op": "sync", "device": "phone"},
+    ///     {"op": "sync", "device": "tablet"}
+    /// ]}"#).unwrap();
+    /// let report = Simulation::in_temp_dir().run(&log)?;
+    /// assert!(report.converged);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if a device name is empty, or a
+    /// database error if a device database cannot be opened. Failed
+    /// operations are reported in their [`SimStep`] instead.
+    pub fn run(&mut self, log: &SimulationLog) -> Result<SimulationReport, AppResponse> {
+        let ops = match log.seed {
+            Some(seed) => {
+                let mut logs: BTreeMap<String, Vec<SimOp>> = BTreeMap::new();
+                for op in &log.ops {
+                    logs.entry(op.device().to_string()).or_default().push(op.clone());
+                }
+                interleave(&logs, seed)
+            }
+            None => log.ops.clone(),
+        };
+
+        let mut report = SimulationReport::default();
+        for op in &ops {
+            report.steps.push(self.step(op)?);
+        }
+        if log.settle {
+            let devices: Vec<String> = self.devices.keys().cloned().collect();
+            for device in devices.iter().chain(&devices) {
+                report.steps.push(self.step(&SimOp::Sync { device: device.clone() })?);
+            }
+        }
+
+        report.server = self.server.records.values().filter_map(|(_, record)| record.clone()).collect();
+        for (name, device) in &self.devices {
+            let state = SimDeviceState {
+                records: device.db.get()?,
+                conflicts: device.db.list_conflicts()?.into_iter().map(|conflict| conflict.id).collect(),
+            };
+            report.devices.insert(name.clone(), state);
+        }
+        report.converged = report
+            .devices
+            .values()
+            .all(|device| device.conflicts.is_empty() && same_records(&device.records, &report.server));
+        Ok(report)
+    }
+
+    /// Replays one operation.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Simulation::run`].
+    pub fn step(&mut self, op: &SimOp) -> Result<SimStep, AppResponse> {
+        self.clock_ms += STEP_MS;
+        let now = self.clock_ms;
+        let db = &self.device(op.device())?.db;
+        db.set_fixed_clock(Some(now));
+
+        let mut step = SimStep { device: op.device().to_string(), op: op.name().to_string(), ..SimStep::default() };
+        let outcome = match op {
+            SimOp::Put { record, .. } => db.post(record.clone()).map(|_| ()),
+            SimOp::Delete { id, .. } => db.delete_by_id(id).map(|_| ()),
+            SimOp::Resolve { id, resolution, .. } => db.resolve_conflict(id, resolution).map(|_| ()),
+            SimOp::Sync { device } => self.sync(device, &mut step),
+        };
+        if let Err(e) = outcome {
+            step.error = Some(e.to_string());
+        }
+        Ok(step)
+    }
+
+    /// Pulls the server changes into a device, then pushes its delta except
+    /// the records in conflict. The delta is acknowledged once the conflict
+    /// inbox is empty, so that a version kept locally is pushed after the
+    /// conflict is resolved.
+    fn sync(&mut self, name: &str, step: &mut SimStep) -> Result<(), AppResponse> {
+        let Some(device) = self.devices.get_mut(name) else {
+            return Err(AppResponse::NotFound(format!("Unknown device {name}")));
+        };
+
+        let changes = self.server.changes_since(device.pulled);
+        step.merge = Some(device.db.merge_remote(&changes)?);
+
+        let conflicts: Vec<String> = device.db.list_conflicts()?.into_iter().map(|conflict| conflict.id).collect();
+        let delta = device.db.get_all_delta(SYNC_CONSUMER)?;
+        let mut accepted = RemoteChanges { consumer: SYNC_CONSUMER.to_string(), records: Vec::new(), deleted: Vec::new() };
+        for record in delta.records.into_iter().filter(|record| !conflicts.contains(&record.id)) {
+            step.pushed += usize::from(self.server.push(&record.id, Some(record.clone())));
+            accepted.records.push(record);
+        }
+        for id in delta.deleted.into_iter().filter(|id| !conflicts.contains(id)) {
+            step.pushed += usize::from(self.server.push(&id, None));
+            accepted.deleted.push(id);
+        }
+
+        // The server confirms the pushed versions instead of sending them back
+        // with the next pull
+        device.db.merge_remote(&accepted)?;
+        device.pulled = self.server.sequence;
+        if conflicts.is_empty() {
+            device.db.ack_delta(SYNC_CONSUMER, delta.sequence)?;
+        }
+        Ok(())
+    }
+
+    fn device(&mut self, name: &str) -> Result<&mut SimDevice, AppResponse> {
+        if name.is_empty() {
+            return Err(AppResponse::BadRequest("Device name cannot be empty".to_string()));
+        }
+        match self.devices.entry(name.to_string()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let options = DbOptions { base_dir: self.dir.to_string_lossy().into_owned(), ..DbOptions::default() };
+                let db = AppDbState::init_with_options(name.to_string(), &options)
+                    .map_err(|e| AppResponse::DatabaseError(format!("Error opening simulated device {name}: {e}")))?;
+                Ok(entry.insert(SimDevice { db, pulled: 0 }))
+            }
+        }
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.devices.clear();
+        if self.remove_dir {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn same_records(a: &[LocalDbModel], b: &[LocalDbModel]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.id == b.id && a.hash == b.hash && a.data == b.data)
+}
+
+/// Merges per-device logs into one, picking the device of each next
+/// operation with a PRNG seeded by `seed`. The order of each device's own
+/// operations is kept, and the same seed always yields the same log.
+pub fn interleave(logs: &BTreeMap<String, Vec<SimOp>>, seed: u64) -> Vec<SimOp> {
+    let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+    let mut next = || {
+        // xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+
+    let mut remaining: Vec<std::slice::Iter<SimOp>> = logs.values().map(|ops| ops.iter()).collect();
+    let mut merged = Vec::new();
+    while !remaining.is_empty() {
+        let index = (next() % remaining.len() as u64) as usize;
+        match remaining[index].next() {
+            Some(op) => merged.push(op.clone()),
+            None => {
+                let _ = remaining.remove(index);
+            }
+        }
+    }
+    merged
+}
@@ -0,0 +1,156 @@
+//! Soft deletion of records.
+//!
+//! Apps offering a trash or undo of deletions would otherwise keep an
+//! `is_deleted` flag in `data` and filter it out of every read.
+//! [`AppDbState::soft_delete`] moves a record out of the main database into
+//! the `__trash` database instead, so every read, query, index and count
+//! stops seeing it, [`AppDbState::restore`] moves it back and
+//! [`AppDbState::purge_deleted`] deletes the records soft-deleted long
+//! enough ago for good.
+//!
+//! ```text
+//! {id}  -> {deleted_at, u64 BE ms}{record, as stored}
+//! ```
+//!
+//! The trashed record keeps its tenant encryption and encrypted fields; its
+//! overflowed fields are stored inline. To the rest of the database a soft
+//! deletion is a deletion and a restore a write, so watches, delta consumers
+//! and the record history see them as such.
+
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+use crate::value_codec::encode_model;
+
+/// Side database holding the soft-deleted records.
+pub(crate) const TRASH_DB_NAME: &str = "__trash";
+
+impl AppDbState {
+    /// Soft-deletes the record `id`, hiding it from reads until it is
+    /// restored or purged. Returns whether the record existed. A record
+    /// soft-deleted before under the same ID is replaced.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// db.soft_delete("note_1")?;
+    ///
+    /// // The user undoes the deletion
+    /// db.restore("note_1")?;
+    ///
+    /// // Empty the trash of anything deleted over 30 days ago
+    /// db.purge_deleted(30 * 24 * 60 * 60 * 1000)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the record is encrypted for a
+    /// tenant whose key is not registered, or the errors of
+    /// [`AppDbState::delete_by_id`].
+    pub fn soft_delete(&self, id: &str) -> Result<bool, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let (_, trash_db) = self.side_db(TRASH_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let model = match txn.get(db, &id) {
+            Ok(value) => self.decode_record(&txn, id.as_bytes(), value)?,
+            Err(LmdbError::NotFound) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let sealed_fields = self.seal_fields(&model)?;
+        let value = encode_model(sealed_fields.as_ref().unwrap_or(&model))?;
+        let value = self.encrypt_value(id, &value)?.unwrap_or(value);
+
+        let writer = self.record_writer(&txn)?;
+        writer.del(&mut txn, db, id.as_bytes())?;
+        let trashed = [writer.changed_at.to_be_bytes().as_slice(), &value].concat();
+        txn.put(trash_db, &id, &trashed, WriteFlags::empty())?;
+        writer.commit(txn)?;
+        Ok(true)
+    }
+
+    /// Restores the soft-deleted record `id` and returns it, `None` if no
+    /// record with the ID is soft-deleted.
+    ///
+    /// # Examples
+    ///
+    /// See [`AppDbState::soft_delete`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::Conflict`] with the hash of the record if a
+    /// record with the ID was written since, or the errors of
+    /// [`AppDbState::put`].
+    pub fn restore(&self, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let (_, trash_db) = self.side_db(TRASH_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+
+        let mut model = match txn.get(trash_db, &id) {
+            Ok(value) => self.decode_record(&txn, id.as_bytes(), value.get(8..).unwrap_or_default())?,
+            Err(LmdbError::NotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match txn.get(db, &id) {
+            Ok(value) => {
+                let current = self.decode_record(&txn, id.as_bytes(), value)?;
+                return Err(AppResponse::Conflict {
+                    message: format!("Cannot restore {id}: a record with the ID exists"),
+                    current_hash: current.hash,
+                });
+            }
+            Err(LmdbError::NotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let writer = self.record_writer(&txn)?;
+        self.write_model(&mut txn, &writer, db, &mut model)?;
+        txn.del(trash_db, &id, None)?;
+        writer.commit(txn)?;
+        Ok(Some(model))
+    }
+
+    /// Deletes for good the records soft-deleted at least `older_than_ms`
+    /// milliseconds ago, `0` emptying the trash. Returns the number of
+    /// records purged.
+    ///
+    /// # Examples
+    ///
+    /// See [`AppDbState::soft_delete`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write fails.
+    pub fn purge_deleted(&self, older_than_ms: u64) -> Result<usize, AppResponse> {
+        let (env, trash_db) = self.side_db(TRASH_DB_NAME)?;
+        let cutoff = self.now_ms().saturating_sub(older_than_ms);
+        let mut txn = env.begin_rw_txn()?;
+
+        let expired: Vec<Vec<u8>> = {
+            let cursor = txn.open_ro_cursor(trash_db)?;
+            scan_from(&cursor, None)
+                .filter(|(_, value)| deleted_at(value) <= cutoff)
+                .map(|(key, _)| key.to_vec())
+                .collect()
+        };
+        for key in &expired {
+            txn.del(trash_db, key, None)?;
+        }
+        txn.commit()?;
+        Ok(expired.len())
+    }
+}
+
+/// Returns the time a trashed record was soft-deleted.
+fn deleted_at(value: &[u8]) -> u64 {
+    value.get(..8).and_then(|bytes| bytes.try_into().ok()).map_or(0, u64::from_be_bytes)
+}
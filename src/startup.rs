@@ -0,0 +1,133 @@
+//! Clean-shutdown tracking and the startup integrity fast-check.
+//!
+//! Opening a database stores an "open" marker in `__meta`; closing it (or
+//! dropping the [`AppDbState`]) replaces it with a "clean" marker. Finding the
+//! open marker on the next start means the previous session crashed or was
+//! killed, so a bounded quick-verify runs before the database is used: reader
+//! slots left by dead processes are freed and the last committed page is
+//! checked to lie within the data file. Neither depends on the database size.
+//!
+//! Handles opened on the same database within one process are counted, so
+//! only the first open checks the marker and only the last close sets it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use lmdb::{Database, Environment, Error as LmdbError, Transaction};
+use lmdb_sys::{mdb_reader_check, MDB_SUCCESS};
+use log::warn;
+
+use crate::local_db_model::StartupReport;
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64};
+use crate::stats::env_info;
+
+/// Number of open handles per database directory in this process.
+static OPEN_HANDLES: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Metadata key of the shutdown marker.
+const SHUTDOWN_STATE_KEY: &str = "shutdown_state";
+const STATE_CLEAN: u64 = 0;
+const STATE_OPEN: u64 = 1;
+
+impl AppDbState {
+    /// Returns the outcome of the integrity fast-check run when this database
+    /// was opened.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// if db.startup_report().recovered {
+    ///     println!("The previous session did not close the database");
+    /// }
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    pub fn startup_report(&self) -> &StartupReport {
+        &self.startup
+    }
+}
+
+/// Registers a handle on the environment at `path`. The first handle checks
+/// the shutdown marker, runs the quick-verify after an unclean shutdown and
+/// marks the database open.
+pub(crate) fn open_handle(env: &Environment, meta: Database, path: &str) -> Result<StartupReport, LmdbError> {
+    let mut handles = OPEN_HANDLES.lock().unwrap_or_else(PoisonError::into_inner);
+    let count = handles.get(path).copied().unwrap_or(0);
+
+    let mut report = StartupReport {
+        recovered: false,
+        stale_readers_cleared: 0,
+        last_page_ok: true,
+    };
+    if count == 0 {
+        let txn = env.begin_ro_txn()?;
+        report.recovered = get_meta_u64(&txn, meta, SHUTDOWN_STATE_KEY)? == Some(STATE_OPEN);
+        drop(txn);
+
+        if report.recovered {
+            warn!("Database {path} was not closed cleanly, verifying");
+            quick_verify(env, path, &mut report)?;
+        }
+
+        let mut txn = env.begin_rw_txn()?;
+        put_meta_u64(&mut txn, meta, SHUTDOWN_STATE_KEY, STATE_OPEN)?;
+        txn.commit()?;
+    }
+
+    handles.insert(path.to_string(), count + 1);
+    Ok(report)
+}
+
+/// Releases a handle on the environment at `path`, marking the shutdown clean
+/// when it was the last one.
+pub(crate) fn close_handle(env: &Environment, meta: Database, path: &str) -> Result<(), LmdbError> {
+    let mut handles = OPEN_HANDLES.lock().unwrap_or_else(PoisonError::into_inner);
+    match handles.remove(path) {
+        Some(count) if count > 1 => {
+            handles.insert(path.to_string(), count - 1);
+            Ok(())
+        }
+        _ => {
+            let mut txn = env.begin_rw_txn()?;
+            put_meta_u64(&mut txn, meta, SHUTDOWN_STATE_KEY, STATE_CLEAN)?;
+            txn.commit()
+        }
+    }
+}
+
+/// Returns the number of open handles on the environment at `path`.
+pub(crate) fn open_handle_count(path: &str) -> usize {
+    let handles = OPEN_HANDLES.lock().unwrap_or_else(PoisonError::into_inner);
+    handles.get(path).copied().unwrap_or(0)
+}
+
+/// Returns whether a handle in this process has the environment at `path`
+/// open, however the path is spelled.
+pub(crate) fn is_open(path: &Path) -> bool {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let handles = OPEN_HANDLES.lock().unwrap_or_else(PoisonError::into_inner);
+    handles.keys().any(|open| fs::canonicalize(open).is_ok_and(|open| open == target))
+}
+
+fn quick_verify(env: &Environment, path: &str, report: &mut StartupReport) -> Result<(), LmdbError> {
+    let mut dead: c_int = 0;
+    // SAFETY: the environment is open and `dead` outlives the call.
+    match unsafe { mdb_reader_check(env.env(), &mut dead) } {
+        MDB_SUCCESS => report.stale_readers_cleared = dead as usize,
+        code => return Err(LmdbError::from_err_code(code)),
+    }
+
+    let (info, stat) = env_info(env)?;
+    let used_bytes = (info.me_last_pgno as u64 + 1) * stat.ms_psize as u64;
+    report.last_page_ok = fs::metadata(Path::new(path).join("data.mdb")).is_ok_and(|file| file.len() >= used_bytes);
+    if !report.last_page_ok {
+        warn!("Database {path} is shorter than its last committed page ({used_bytes} bytes)");
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Storage and memory statistics.
+//!
+//! [`AppDbState::analyze_storage`] walks the main database once and reports
+//! how its space is used, to help decide whether enabling the `compression`
+//! feature pays off or whether large blobs should live outside the records.
+//!
+//! [`AppDbState::memory_stats`] reports what the library keeps in memory: the
+//! part of the memory map that is resident and the strings handed out over
+//! FFI that the caller has not released yet.
+//!
+//! [`AppDbState::library_version`] describes the binary itself, for
+//! compatibility checks and bug reports.
+
+use std::collections::HashMap;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lmdb::{Environment, Error as LmdbError, Transaction};
+use lmdb_sys::{mdb_env_info, mdb_env_stat, mdb_version, MDB_envinfo, MDB_stat, MDB_SUCCESS};
+
+use crate::index::INDEX_FORMAT_VERSION;
+use crate::local_db_model::{LibraryVersion, MemoryStats, PrefixSize, RecordSize, SizeBucket, StorageReport};
+use crate::local_db_state::AppDbState;
+use crate::scan::scan_from;
+use crate::value_codec::split_value;
+
+/// Exclusive upper bounds of the size distribution buckets; a final bucket
+/// collects everything larger.
+const SIZE_BUCKET_BOUNDS: [usize; 5] = [256, 1024, 4096, 16 * 1024, 64 * 1024];
+
+static OUTSTANDING_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+static OUTSTANDING_BUFFER_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Records a string of `len` bytes (terminator included) handed out over FFI.
+pub(crate) fn track_returned_buffer(len: usize) {
+    OUTSTANDING_BUFFERS.fetch_add(1, Ordering::Relaxed);
+    OUTSTANDING_BUFFER_BYTES.fetch_add(len, Ordering::Relaxed);
+}
+
+/// Records that a string of `len` bytes handed out over FFI was released.
+pub(crate) fn release_returned_buffer(len: usize) {
+    // Saturate so strings returned before a counter reset cannot wrap it.
+    let _ = OUTSTANDING_BUFFERS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n.saturating_sub(1)));
+    let _ = OUTSTANDING_BUFFER_BYTES.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n.saturating_sub(len)));
+}
+
+impl AppDbState {
+    /// Reports the value size distribution of the main database together with
+    /// the `top_n` largest records and key prefixes.
+    ///
+    /// The compressibility estimate is the order-0 byte entropy of the values
+    /// that are not compressed yet, divided by 8 bits. It ignores repetition
+    /// across bytes, so real zlib output is usually smaller still; the figure
+    /// is meant for comparing datasets, not as an exact prediction.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let report = db.analyze_storage(10)?;
+    /// if report.estimated_compression_ratio < 0.5 {
+    ///     println!("Compression would halve {} bytes", report.total_bytes);
+    /// }
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Transaction creation fails
+    /// - Cursor creation fails
+    pub fn analyze_storage(&self, top_n: usize) -> Result<StorageReport, LmdbError> {
+        let (env, db) = self.env_db()?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(db)?;
+
+        let mut sizes = Vec::new();
+        let mut records = Vec::new();
+        let mut prefixes: HashMap<String, PrefixSize> = HashMap::new();
+        let mut byte_counts = [0u64; 256];
+        let mut compressed_records = 0;
+
+        for (key, value) in scan_from(&cursor, None) {
+            let id = String::from_utf8_lossy(key).into_owned();
+            let size = value.len();
+
+            match split_value(value) {
+                Ok((header, _)) if header.compressed => compressed_records += 1,
+                _ => value.iter().for_each(|&byte| byte_counts[byte as usize] += 1),
+            }
+
+            let prefix = id.split_once(':').map(|(prefix, _)| prefix).unwrap_or_default();
+            let entry = prefixes.entry(prefix.to_string()).or_insert_with(|| PrefixSize {
+                prefix: prefix.to_string(),
+                records: 0,
+                bytes: 0,
+            });
+            entry.records += 1;
+            entry.bytes += size;
+
+            sizes.push(size);
+            records.push(RecordSize { id, size });
+        }
+
+        if sizes.is_empty() {
+            return Ok(StorageReport {
+                size_buckets: size_buckets(&sizes),
+                estimated_compression_ratio: 1.0,
+                ..StorageReport::default()
+            });
+        }
+
+        sizes.sort_unstable();
+        let total_bytes: usize = sizes.iter().sum();
+
+        records.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.id.cmp(&b.id)));
+        records.truncate(top_n);
+
+        let mut largest_prefixes: Vec<PrefixSize> = prefixes.into_values().collect();
+        largest_prefixes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.prefix.cmp(&b.prefix)));
+        largest_prefixes.truncate(top_n);
+
+        Ok(StorageReport {
+            record_count: sizes.len(),
+            total_bytes,
+            min_size: sizes[0],
+            max_size: sizes[sizes.len() - 1],
+            mean_size: total_bytes / sizes.len(),
+            median_size: sizes[sizes.len() / 2],
+            size_buckets: size_buckets(&sizes),
+            compressed_records,
+            estimated_compression_ratio: entropy_ratio(&byte_counts),
+            largest_records: records,
+            largest_prefixes,
+        })
+    }
+
+    /// Reports the memory used by this database and by the strings returned
+    /// over FFI.
+    ///
+    /// The memory map reserves `map_size` bytes of address space, but only the
+    /// pages that were touched count against the resident memory of the app;
+    /// `resident_pages` tells how many currently are. It is read from
+    /// `/proc/self/smaps` and is `None` on platforms other than Linux and
+    /// Android. The buffer counters only decrease for strings released with
+    /// `free_c_string`, strings released with the C `free` stay counted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("test_db".to_string())?;
+    ///
+    /// let stats = db.memory_stats()?;
+    /// println!("{} of {} pages resident", stats.resident_pages.unwrap_or(0), stats.used_pages);
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The database has been closed
+    /// - The environment information cannot be read
+    pub fn memory_stats(&self) -> Result<MemoryStats, LmdbError> {
+        let (env, _) = self.env_db()?;
+        let (info, stat) = env_info(env)?;
+
+        let page_size = stat.ms_psize as usize;
+        let used_pages = info.me_last_pgno + 1;
+
+        Ok(MemoryStats {
+            map_size: info.me_mapsize,
+            page_size,
+            used_pages,
+            resident_pages: resident_pages(&Path::new(&self.path).join("data.mdb"), page_size),
+            readers: info.me_numreaders,
+            max_readers: info.me_maxreaders,
+            outstanding_buffers: OUTSTANDING_BUFFERS.load(Ordering::Relaxed),
+            outstanding_buffer_bytes: OUTSTANDING_BUFFER_BYTES.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Returns the version of this library, of the LMDB it bundles and the
+    /// enabled features, for checking binary compatibility at startup.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let info = AppDbState::library_version();
+    /// assert!(info.features.iter().any(|feature| feature == "signing"));
+    /// ```
+    pub fn library_version() -> LibraryVersion {
+        let (mut major, mut minor, mut patch): (c_int, c_int, c_int) = (0, 0, 0);
+        // SAFETY: mdb_version only writes the three integers and returns a
+        // static string, which is not used.
+        unsafe {
+            mdb_version(&mut major, &mut minor, &mut patch);
+        }
+
+        let features = [
+            ("compression", cfg!(feature = "compression")),
+            ("encryption", cfg!(feature = "encryption")),
+            ("http-sync", cfg!(feature = "http-sync")),
+            ("signing", cfg!(feature = "signing")),
+            ("simulation", cfg!(feature = "simulation")),
+            ("static", cfg!(feature = "static")),
+        ];
+
+        LibraryVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            lmdb_version: format!("{major}.{minor}.{patch}"),
+            features: features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect(),
+            index_format: INDEX_FORMAT_VERSION,
+            response_formats: (1..=crate::LATEST_RESPONSE_FORMAT).collect(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+}
+
+/// Reads the environment information and statistics of `env`.
+pub(crate) fn env_info(env: &Environment) -> Result<(MDB_envinfo, MDB_stat), LmdbError> {
+    let mut info = MaybeUninit::<MDB_envinfo>::uninit();
+    let mut stat = MaybeUninit::<MDB_stat>::uninit();
+    // SAFETY: the environment is open and both calls fully initialize their
+    // output when they return success.
+    unsafe {
+        match mdb_env_info(env.env(), info.as_mut_ptr()) {
+            MDB_SUCCESS => {}
+            code => return Err(LmdbError::from_err_code(code)),
+        }
+        match mdb_env_stat(env.env(), stat.as_mut_ptr()) {
+            MDB_SUCCESS => {}
+            code => return Err(LmdbError::from_err_code(code)),
+        }
+        Ok((info.assume_init(), stat.assume_init()))
+    }
+}
+
+/// Reads the resident size of the mapping of `data_file` from
+/// `/proc/self/smaps` and converts it to database pages.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn resident_pages(data_file: &Path, page_size: usize) -> Option<usize> {
+    let data_file = fs::canonicalize(data_file).ok()?;
+    let smaps = fs::read_to_string("/proc/self/smaps").ok()?;
+
+    let mut in_mapping = false;
+    let mut resident_kb = 0;
+    for line in smaps.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            // Mapping headers start with the address range
+            Some(range) if range.contains('-') => {
+                in_mapping = fields.nth(4).is_some_and(|path| Path::new(path) == data_file);
+            }
+            Some("Rss:") if in_mapping => {
+                resident_kb += fields.next().and_then(|kb| kb.parse::<usize>().ok()).unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+    Some(resident_kb * 1024 / page_size)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn resident_pages(_: &Path, _: usize) -> Option<usize> {
+    None
+}
+
+fn size_buckets(sizes: &[usize]) -> Vec<SizeBucket> {
+    let mut buckets: Vec<SizeBucket> = SIZE_BUCKET_BOUNDS
+        .iter()
+        .map(|&bound| SizeBucket { up_to: Some(bound), count: 0 })
+        .chain(std::iter::once(SizeBucket { up_to: None, count: 0 }))
+        .collect();
+
+    for &size in sizes {
+        let index = SIZE_BUCKET_BOUNDS.iter().position(|&bound| size < bound).unwrap_or(SIZE_BUCKET_BOUNDS.len());
+        buckets[index].count += 1;
+    }
+    buckets
+}
+
+/// Shannon entropy of the byte histogram in bits per byte, divided by 8.
+fn entropy_ratio(byte_counts: &[u64; 256]) -> f64 {
+    let total: u64 = byte_counts.iter().sum();
+    if total == 0 {
+        return 1.0;
+    }
+
+    let entropy: f64 = byte_counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum();
+    entropy / 8.0
+}
@@ -0,0 +1,156 @@
+//! Query subscriptions backing reactive list views.
+//!
+//! A subscription is a named [`PathFilter`] whose result set is kept up to
+//! date as records are written. [`AppDbState::subscribe`] returns the records
+//! matching it and registers a watch on every record; each batch of changed
+//! IDs delivered to the watch is re-evaluated against the filter, record by
+//! record, and reported as one [`SubscriptionEvent`] listing the records
+//! added to, updated in and removed from the result set. The query is never
+//! run again as a whole.
+//!
+//! The records are read on the watch dispatcher thread through a view of the
+//! database (see [`AppDbState::background_view`]), which holds the encryption
+//! keys registered when subscribing. Closing the database drops every
+//! subscription.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use lmdb::{Error as LmdbError, Transaction};
+use log::warn;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{ChangeBatch, LocalDbModel, SubscriptionEvent};
+use crate::local_db_state::AppDbState;
+use crate::query::PathFilter;
+
+impl AppDbState {
+    /// Registers the subscription `name` to the records matching `filter`
+    /// and returns them, in key order.
+    ///
+    /// Afterwards `callback` receives a [`SubscriptionEvent`] whenever
+    /// written records enter, change in or leave the result set. Changes are
+    /// batched over `debounce` like those of [`AppDbState::watch`], and the
+    /// callback runs on the same dispatcher thread. Subscribing again under
+    /// the same name replaces the subscription.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    ///
+    /// let db = AppDbState::init("todos".to_string())?;
+    ///
+    /// let filter: PathFilter = serde_json::from_str(r#"{"data.done": false}"#).unwrap();
+    /// let open = db.subscribe("open_todos", filter, Duration::from_millis(16), |event| {
+    ///     println!("{} added, {} removed", event.added.len(), event.removed.len());
+    /// })?;
+    /// db.unsubscribe("open_todos");
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `name` is empty, or an error if
+    /// the database is closed or the query fails.
+    pub fn subscribe<F>(&self, name: &str, filter: PathFilter, debounce: Duration, callback: F) -> Result<Vec<LocalDbModel>, AppResponse>
+    where
+        F: Fn(&SubscriptionEvent) + Send + Sync + 'static,
+    {
+        if name.is_empty() {
+            return Err(AppResponse::BadRequest("Subscription name cannot be empty".to_string()));
+        }
+
+        let view = self.background_view()?;
+        let members: Arc<Mutex<BTreeSet<String>>> = Arc::default();
+        // Batches wait for the initial result set
+        let mut initial = members.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let watch_id = {
+            let name = name.to_string();
+            let filter = filter.clone();
+            let members = Arc::clone(&members);
+            self.watch("", debounce, move |batch| {
+                let mut members = members.lock().unwrap_or_else(PoisonError::into_inner);
+                let mut event = SubscriptionEvent { name: name.clone(), ..SubscriptionEvent::default() };
+                match evaluate(&view, &filter, &mut members, batch, &mut event) {
+                    Ok(()) if !event.added.is_empty() || !event.updated.is_empty() || !event.removed.is_empty() => {
+                        drop(members);
+                        callback(&event);
+                    }
+                    Ok(()) => {}
+                    Err(e) => warn!("Re-evaluating subscription {name} failed: {e}"),
+                }
+            })?
+        };
+
+        let records = match self.query(&filter) {
+            Ok(records) => records,
+            Err(e) => {
+                self.unwatch(watch_id);
+                return Err(e.into());
+            }
+        };
+        initial.extend(records.iter().map(|record| record.id.clone()));
+        drop(initial);
+
+        let replaced = self.subscriptions.lock().unwrap_or_else(PoisonError::into_inner).insert(name.to_string(), watch_id);
+        if let Some(replaced) = replaced {
+            self.unwatch(replaced);
+        }
+        Ok(records)
+    }
+
+    /// Removes the subscription `name`. Changes it has not delivered yet are
+    /// dropped. Returns whether the subscription existed.
+    pub fn unsubscribe(&self, name: &str) -> bool {
+        let watch_id = self.subscriptions.lock().unwrap_or_else(PoisonError::into_inner).remove(name);
+        watch_id.is_some_and(|watch_id| self.unwatch(watch_id))
+    }
+
+    /// Returns the names of the subscriptions, sorted.
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().unwrap_or_else(PoisonError::into_inner).keys().cloned().collect()
+    }
+
+    /// Removes every subscription, releasing their views of the database.
+    pub(crate) fn drop_subscriptions(&self) {
+        let subscriptions = std::mem::take(&mut *self.subscriptions.lock().unwrap_or_else(PoisonError::into_inner));
+        for watch_id in subscriptions.into_values() {
+            self.unwatch(watch_id);
+        }
+    }
+}
+
+/// Re-evaluates the records changed by `batch` against `filter`, updating
+/// `members` and recording the changes of the result set in `event`.
+fn evaluate(view: &AppDbState, filter: &PathFilter, members: &mut BTreeSet<String>, batch: &ChangeBatch, event: &mut SubscriptionEvent) -> Result<(), AppResponse> {
+    // After a clear the members may be gone without being listed
+    let mut ids: BTreeSet<String> = batch.ids.iter().cloned().collect();
+    if batch.cleared {
+        ids.extend(members.iter().cloned());
+    }
+
+    let (env, db) = view.env_db()?;
+    let txn = env.begin_ro_txn()?;
+    for id in ids {
+        let record = match txn.get(db, &id) {
+            Ok(value) if filter.matches_json(&view.record_json(id.as_bytes(), value)?)? => Some(view.decode_record(&txn, id.as_bytes(), value)?),
+            Ok(_) | Err(LmdbError::NotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+        match record {
+            Some(record) if members.contains(&id) => event.updated.push(record),
+            Some(record) => {
+                members.insert(id);
+                event.added.push(record);
+            }
+            None if members.remove(&id) => event.removed.push(id),
+            None => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! End-to-end encrypted sync payloads.
+//!
+//! With a sync key registered, [`AppDbState::seal_sync_records`] encrypts the
+//! body of each record before upload, so the server relays data it cannot
+//! read, and [`AppDbState::open_sync_records`] decrypts it after download
+//! ([`AppDbState::merge_remote`] does so itself). The ID and hash stay plain
+//! for the server to route and compare records, and are authenticated with
+//! the body, so the server cannot move a body to another record.
+//!
+//! The sync key is shared by all the devices of a user and never stored:
+//! apps keep it in the platform keychain and move it between devices only
+//! wrapped with [`AppDbState::wrap_sync_key`], e.g. under a key derived from
+//! a recovery passphrase. A sealed record looks like
+//!
+//! ```json
+//! {"id": "n1", "hash": "h2", "data": {"$sealed": "{nonce and ciphertext, hex}"}}
+//! ```
+//!
+//! Sealing requires the `encryption` feature.
+
+use serde_json::Value;
+
+use crate::app_response::AppResponse;
+use crate::encryption::{open, seal};
+use crate::local_db_model::LocalDbModel;
+use crate::local_db_state::AppDbState;
+use crate::signing::{decode_hex, encode_hex};
+
+/// Field holding the sealed body of a record.
+const SEALED_FIELD: &str = "$sealed";
+
+/// Associated data of a wrapped sync key.
+const WRAPPED_KEY_CONTEXT: &[u8] = b"offline_first_core sync key";
+
+/// The registered sync key.
+#[derive(Clone)]
+pub(crate) struct SyncKey([u8; 32]);
+
+#[cfg(feature = "encryption")]
+impl Drop for SyncKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+impl AppDbState {
+    /// Registers the key sealing sync payloads, replacing an earlier one.
+    ///
+    /// Keys are not persisted; apps register them after every open.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if this build lacks the
+    /// `encryption` feature.
+    pub fn set_sync_key(&mut self, key: &[u8; 32]) -> Result<(), AppResponse> {
+        if !cfg!(feature = "encryption") {
+            return Err(AppResponse::BadRequest("Encryption is not supported by this build".to_string()));
+        }
+        self.sync_key = Some(SyncKey(*key));
+        Ok(())
+    }
+
+    /// Forgets the sync key, wiping it from memory. Returns whether a key was
+    /// registered.
+    pub fn remove_sync_key(&mut self) -> bool {
+        self.sync_key.take().is_some()
+    }
+
+    /// Returns the sync key encrypted with `wrapping_key`, hex-encoded, for
+    /// handing it to another device of the user.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if no sync key is registered.
+    pub fn wrap_sync_key(&self, wrapping_key: &[u8; 32]) -> Result<String, AppResponse> {
+        let key = self.registered_sync_key()?;
+        Ok(encode_hex(&seal(wrapping_key, WRAPPED_KEY_CONTEXT, &key.0)?))
+    }
+
+    /// Registers a sync key wrapped with [`AppDbState::wrap_sync_key`] on
+    /// another device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    /// let wrapping_key = [3u8; 32]; // Derived from the user's recovery passphrase
+    /// let wrapped = "..."; // Wrapped sync key from the user's other device
+    /// db.set_wrapped_sync_key(wrapped, &wrapping_key)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `wrapped` is not hex or this
+    /// build lacks the `encryption` feature, or a serialization error if
+    /// `wrapping_key` is wrong.
+    pub fn set_wrapped_sync_key(&mut self, wrapped: &str, wrapping_key: &[u8; 32]) -> Result<(), AppResponse> {
+        let wrapped = decode_hex(wrapped)
+            .ok_or_else(|| AppResponse::BadRequest("Wrapped sync key must be hex-encoded".to_string()))?;
+        let key = <[u8; 32]>::try_from(open(wrapping_key, WRAPPED_KEY_CONTEXT, &wrapped)?)
+            .map_err(|_| AppResponse::SerializationError("Wrapped sync key has an invalid length".to_string()))?;
+        self.set_sync_key(&key)
+    }
+
+    /// Returns copies of `records` with their bodies sealed with the sync
+    /// key, for upload.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    /// db.set_sync_key(&[7u8; 32])?; // From the platform keychain
+    ///
+    /// let delta = db.get_all_delta("sync")?;
+    /// let upload = db.seal_sync_records(&delta.records)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if no sync key is registered.
+    pub fn seal_sync_records(&self, records: &[LocalDbModel]) -> Result<Vec<LocalDbModel>, AppResponse> {
+        let key = self.registered_sync_key()?;
+        records
+            .iter()
+            .map(|record| {
+                let body = serde_json::to_vec(&record.data)?;
+                let sealed = seal(&key.0, &associated_data(record), &body)?;
+                Ok(LocalDbModel {
+                    data: serde_json::json!({ SEALED_FIELD: encode_hex(&sealed) }),
+                    ..record.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Returns copies of downloaded `records` with sealed bodies decrypted.
+    /// Records that are not sealed are returned as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if a record is sealed and no sync
+    /// key is registered, or a serialization error if a body was sealed with
+    /// another key or tampered with.
+    pub fn open_sync_records(&self, records: &[LocalDbModel]) -> Result<Vec<LocalDbModel>, AppResponse> {
+        records
+            .iter()
+            .map(|record| {
+                let Some(sealed) = sealed_body(&record.data) else {
+                    return Ok(record.clone());
+                };
+                let key = self.registered_sync_key()?;
+                let sealed = decode_hex(sealed).ok_or_else(|| {
+                    AppResponse::SerializationError(format!("Sealed body of record {} must be hex-encoded", record.id))
+                })?;
+                let body = open(&key.0, &associated_data(record), &sealed)?;
+                Ok(LocalDbModel { data: serde_json::from_slice(&body)?, ..record.clone() })
+            })
+            .collect()
+    }
+
+    fn registered_sync_key(&self) -> Result<&SyncKey, AppResponse> {
+        self.sync_key
+            .as_ref()
+            .ok_or_else(|| AppResponse::BadRequest("No sync key is registered".to_string()))
+    }
+}
+
+/// Returns the sealed body of `data`, if it is one.
+fn sealed_body(data: &Value) -> Option<&str> {
+    match data.as_object() {
+        Some(object) if object.len() == 1 => object.get(SEALED_FIELD)?.as_str(),
+        _ => None,
+    }
+}
+
+/// Binds a sealed body to the ID and hash of its record.
+fn associated_data(record: &LocalDbModel) -> Vec<u8> {
+    [record.id.as_bytes(), &[0], record.hash.as_bytes()].concat()
+}
@@ -0,0 +1,226 @@
+//! Pluggable sync engine.
+//!
+//! [`AppDbState::sync_now`] runs a whole sync against a [`SyncAdapter`],
+//! the transport to the server, so apps get end-to-end sync without an
+//! engine of their own. A run takes the same steps as the simulated devices
+//! of the `simulation` feature:
+//!
+//! 1. Pulls the server changes page by page after the stored server cursor
+//!    and merges each page with [`AppDbState::apply_remote_changes`], under
+//!    the conflict policy of the adapter.
+//! 2. Pushes the delta of the `sync` consumer, except the records in the
+//!    conflict inbox, one request per batch within the
+//!    [`SyncLimits`](crate::local_db_model::SyncLimits) of the database.
+//!    Records above the record size cap stay pending and are reported as
+//!    failed.
+//! 3. Merges the versions of each batch as confirmed by the server, so they
+//!    are not taken for local changes later, and acknowledges the delta
+//!    unless conflicts or oversized records are pending.
+//!
+//! Each run is recorded in the sync history (see [`crate::sync_history`]),
+//! with the error of the step that failed. The server cursor is kept in
+//! `__meta` after every merged page, so an interrupted sync resumes where
+//! it stopped.
+//!
+//! With the `http-sync` feature, [`crate::http_sync::HttpSyncAdapter`] talks
+//! to a JSON endpoint over HTTP.
+
+use std::collections::{HashMap, HashSet};
+
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{
+    ConflictEntry, ConflictPolicy, ConflictResolution, PullPage, RemoteChanges, SyncBatch, SyncRun, SyncRunReport,
+};
+use crate::local_db_state::AppDbState;
+use crate::meta::{META_DB_NAME, SYNC_CURSOR_KEY};
+use crate::sync_plan::batches;
+
+/// Delta consumer acknowledged by [`AppDbState::sync_now`].
+pub const SYNC_CONSUMER: &str = "sync";
+
+/// Transport of [`AppDbState::sync_now`] to a sync server.
+///
+/// # Examples
+///
+/// ```no_run
+/// use offline_first_core::app_response::AppResponse;
+/// use offline_first_core::local_db_model::{PullPage, RemoteChanges};
+/// use offline_first_core::local_db_state::AppDbState;
+/// use offline_first_core::sync_engine::SyncAdapter;
+///
+/// struct Loopback {
+///     server: Vec<RemoteChanges>,
+/// }
+///
+/// impl SyncAdapter for Loopback {
+///     fn pull(&mut self, _cursor: Option<&str>) -> Result<PullPage, AppResponse> {
+///         Ok(PullPage::default())
+///     }
+///
+///     fn push(&mut self, changes: &RemoteChanges) -> Result<(), AppResponse> {
+///         self.server.push(changes.clone());
+///         Ok(())
+///     }
+/// }
+///
+/// let db = AppDbState::init("notes".to_string())?;
+/// let run = db.sync_now(&mut Loopback { server: Vec::new() })?;
+/// println!("{} pushed, {} pulled", run.pushed, run.pulled);
+/// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+/// ```
+pub trait SyncAdapter {
+    /// Returns the server changes after `cursor`, the cursor of the last
+    /// page merged, `None` on the first pull.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server cannot be reached or answers with an
+    /// error; the sync stops.
+    fn pull(&mut self, cursor: Option<&str>) -> Result<PullPage, AppResponse>;
+
+    /// Uploads the local changes, sealed with the sync key when one is
+    /// registered. Returning `Ok` confirms that the server stored them.
+    ///
+    /// The changes may include versions pulled before, which the server
+    /// already holds. Later pulls must not return the changes pushed by this
+    /// device, which would be taken for conflicting server edits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejects the changes; they are pushed
+    /// again by the next sync.
+    fn push(&mut self, changes: &RemoteChanges) -> Result<(), AppResponse>;
+
+    /// Policy settling the conflicts of pulled changes, the conflict inbox
+    /// unless overridden.
+    fn conflict_policy(&self) -> ConflictPolicy {
+        ConflictPolicy::Inbox
+    }
+
+    /// Settles a conflict under the [`ConflictPolicy::Callback`] policy,
+    /// `None` to move it to the conflict inbox, which is what the default
+    /// does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error to stop the sync.
+    fn resolve_conflict(&mut self, _conflict: &ConflictEntry) -> Result<Option<ConflictResolution>, AppResponse> {
+        Ok(None)
+    }
+
+    /// Called after the server confirmed `batch`, the `done`-th of `total`
+    /// push requests, for progress reporting. Does nothing by default.
+    fn batch_pushed(&mut self, _batch: &SyncBatch, _done: usize, _total: usize) {}
+}
+
+impl AppDbState {
+    /// Runs a sync against `adapter`: pulls and merges the server changes,
+    /// then pushes the local ones. Returns the finished run.
+    ///
+    /// # Examples
+    ///
+    /// See [`SyncAdapter`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the step that failed, after finishing the run
+    /// with it. Pages merged before stay merged.
+    pub fn sync_now<A: SyncAdapter + ?Sized>(&self, adapter: &mut A) -> Result<SyncRun, AppResponse> {
+        self.begin_sync_run(SYNC_CONSUMER)?;
+        match self.sync_steps(adapter) {
+            Ok(report) => self.finish_sync_run(&report),
+            Err(e) => {
+                self.finish_sync_run(&SyncRunReport { error: Some(e.to_string()), ..SyncRunReport::default() })?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the server cursor of the last page merged by
+    /// [`AppDbState::sync_now`], `None` before the first one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn sync_cursor(&self) -> Result<Option<String>, LmdbError> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        match txn.get(meta_db, &SYNC_CURSOR_KEY) {
+            Ok(bytes) => Ok(Some(String::from_utf8_lossy(bytes).into_owned())),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn sync_steps<A: SyncAdapter + ?Sized>(&self, adapter: &mut A) -> Result<SyncRunReport, AppResponse> {
+        let policy = adapter.conflict_policy();
+        let mut cursor = self.sync_cursor()?;
+        loop {
+            let page = adapter.pull(cursor.as_deref())?;
+            let changes = RemoteChanges { consumer: SYNC_CONSUMER.to_string(), records: page.records, deleted: page.deleted };
+            self.apply_remote_changes(&changes, &policy, |conflict| adapter.resolve_conflict(conflict))?;
+            if let Some(next) = page.cursor {
+                self.set_sync_cursor(&next)?;
+                cursor = Some(next);
+            }
+            if !page.has_more {
+                break;
+            }
+        }
+
+        let conflicts: HashSet<String> = self.list_conflicts()?.into_iter().map(|conflict| conflict.id).collect();
+        let delta = self.get_all_delta(SYNC_CONSUMER)?;
+        let mut records = HashMap::new();
+        let mut sizes = Vec::new();
+        for record in delta.records.iter().filter(|record| !conflicts.contains(&record.id)) {
+            sizes.push((&record.id, serde_json::to_string(record)?.len() as u64));
+            records.insert(record.id.as_str(), record);
+        }
+        let mut deleted: Vec<String> = delta.deleted.iter().filter(|id| !conflicts.contains(*id)).cloned().collect();
+
+        let mut report = SyncRunReport::default();
+        let mut requests = batches(sizes.into_iter(), self.sync_limits, &mut report.failed);
+        // Deletions travel with the first request
+        if requests.is_empty() && !deleted.is_empty() {
+            requests.push(SyncBatch::default());
+        }
+
+        let mut pushed = 0;
+        for (index, batch) in requests.iter().enumerate() {
+            let confirmed = RemoteChanges {
+                consumer: SYNC_CONSUMER.to_string(),
+                records: batch.ids.iter().map(|id| records[id.as_str()].clone()).collect(),
+                deleted: std::mem::take(&mut deleted),
+            };
+            let upload = match self.sync_key {
+                Some(_) => RemoteChanges { records: self.seal_sync_records(&confirmed.records)?, ..confirmed.clone() },
+                None => confirmed.clone(),
+            };
+            adapter.push(&upload)?;
+
+            // The server confirmed the pushed versions; merging them records
+            // them as the last server versions
+            self.merge_remote(&confirmed)?;
+            pushed += (confirmed.records.len() + confirmed.deleted.len()) as u64;
+            report.bytes_sent += batch.bytes;
+            adapter.batch_pushed(batch, index + 1, requests.len());
+        }
+
+        // The delta stays pending until the conflicts are settled and the
+        // oversized records fit, so the pushed records are counted here
+        if !conflicts.is_empty() || !report.failed.is_empty() {
+            return Ok(SyncRunReport { pushed, ..report });
+        }
+        self.ack_delta(SYNC_CONSUMER, delta.sequence)?;
+        Ok(report)
+    }
+
+    fn set_sync_cursor(&self, cursor: &str) -> Result<(), LmdbError> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        txn.put(meta_db, &SYNC_CURSOR_KEY, &cursor, WriteFlags::empty())?;
+        txn.commit()
+    }
+}
@@ -0,0 +1,190 @@
+//! Sync telemetry.
+//!
+//! The sync layer brackets each run with [`AppDbState::begin_sync_run`] and
+//! [`AppDbState::finish_sync_run`]. While a run is open, the database counts
+//! what it sees itself: records merged with [`AppDbState::merge_remote`],
+//! the delta acknowledged by the run's consumer and attachment chunks
+//! transferred. The sync layer adds the rest, such as failed items, when it
+//! finishes the run.
+//!
+//! Runs are kept in the `__meta` database, so a settings screen can show
+//! [`AppDbState::sync_status`] and [`AppDbState::sync_history`] without
+//! bookkeeping of its own:
+//!
+//! ```text
+//! "sync_run/" {id, u64 BE}  -> {run, JSON}
+//! "sync_run_sequence"       -> {id of the last run, u64 BE}
+//! "open_sync_run"           -> {id of the open run, u64 BE}    absent when none
+//! "last_sync_success"       -> {finish time in ms, u64 BE}
+//! ```
+//!
+//! Only the last [`SYNC_HISTORY_LIMIT`] runs are kept.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{Direction, SyncRun, SyncRunReport, SyncStatus};
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64, META_DB_NAME};
+use crate::scan::scan_directed;
+
+/// Number of runs kept in the history.
+pub const SYNC_HISTORY_LIMIT: u64 = 100;
+
+const SYNC_RUN_PREFIX: &[u8] = b"sync_run/";
+const SYNC_RUN_SEQUENCE_KEY: &str = "sync_run_sequence";
+const OPEN_SYNC_RUN_KEY: &str = "open_sync_run";
+const LAST_SYNC_SUCCESS_KEY: &str = "last_sync_success";
+
+impl AppDbState {
+    /// Starts a sync run whose pushed records are the delta acknowledged by
+    /// `consumer`. A run still open, e.g. because the app was killed during
+    /// the sync, is finished as interrupted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::SyncRunReport;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// db.begin_sync_run("sync")?;
+    /// // Push, pull and merge...
+    /// let run = db.finish_sync_run(&SyncRunReport { failed: vec!["n7".to_string()], ..SyncRunReport::default() })?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `consumer` is empty, or a
+    /// database error if the write fails.
+    pub fn begin_sync_run(&self, consumer: &str) -> Result<SyncRun, AppResponse> {
+        if consumer.is_empty() {
+            return Err(AppResponse::BadRequest("Consumer name cannot be empty".to_string()));
+        }
+
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let now = self.now_ms();
+
+        if let Some(mut interrupted) = open_run(&txn, meta_db)? {
+            interrupted.finished_at = Some(now);
+            interrupted.error = Some("Interrupted by a new sync run".to_string());
+            put_run(&mut txn, meta_db, &interrupted)?;
+        }
+
+        let id = get_meta_u64(&txn, meta_db, SYNC_RUN_SEQUENCE_KEY)?.unwrap_or(0) + 1;
+        let run = SyncRun { id, consumer: consumer.to_string(), started_at: now, ..SyncRun::default() };
+        put_run(&mut txn, meta_db, &run)?;
+        put_meta_u64(&mut txn, meta_db, SYNC_RUN_SEQUENCE_KEY, id)?;
+        put_meta_u64(&mut txn, meta_db, OPEN_SYNC_RUN_KEY, id)?;
+        if id > SYNC_HISTORY_LIMIT {
+            match txn.del(meta_db, &run_key(id - SYNC_HISTORY_LIMIT), None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        txn.commit()?;
+        Ok(run)
+    }
+
+    /// Finishes the open sync run, adding `report` to what the database
+    /// counted, and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if no run is open, or a database
+    /// error if the write fails.
+    pub fn finish_sync_run(&self, report: &SyncRunReport) -> Result<SyncRun, AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        let mut run = open_run(&txn, meta_db)?
+            .ok_or_else(|| AppResponse::BadRequest("No sync run in progress".to_string()))?;
+
+        let now = self.now_ms();
+        run.finished_at = Some(now);
+        run.pushed += report.pushed;
+        run.pulled += report.pulled;
+        run.bytes_sent += report.bytes_sent;
+        run.bytes_received += report.bytes_received;
+        run.failed.extend(report.failed.iter().cloned());
+        run.error = report.error.clone();
+
+        put_run(&mut txn, meta_db, &run)?;
+        txn.del(meta_db, &OPEN_SYNC_RUN_KEY, None)?;
+        if run.error.is_none() {
+            put_meta_u64(&mut txn, meta_db, LAST_SYNC_SUCCESS_KEY, now)?;
+        }
+        txn.commit()?;
+        Ok(run)
+    }
+
+    /// Returns the run in progress, the last finished run and the time of the
+    /// last successful one.
+    pub fn sync_status(&self) -> Result<SyncStatus, AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(meta_db)?;
+        let last_key = run_key(u64::MAX);
+
+        let mut status = SyncStatus {
+            running: open_run(&txn, meta_db)?,
+            last_success_at: get_meta_u64(&txn, meta_db, LAST_SYNC_SUCCESS_KEY)?,
+            ..SyncStatus::default()
+        };
+        for (_, value) in scan_directed(&cursor, Some(&last_key), Direction::Desc).take_while(|(key, _)| key.starts_with(SYNC_RUN_PREFIX)) {
+            let run: SyncRun = serde_json::from_slice(value)?;
+            if run.finished_at.is_some() {
+                status.last_run = Some(run);
+                break;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Returns up to `limit` runs, newest first, including the one in
+    /// progress.
+    pub fn sync_history(&self, limit: usize) -> Result<Vec<SyncRun>, AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        let cursor = txn.open_ro_cursor(meta_db)?;
+        let last_key = run_key(u64::MAX);
+
+        scan_directed(&cursor, Some(&last_key), Direction::Desc)
+            .take_while(|(key, _)| key.starts_with(SYNC_RUN_PREFIX))
+            .take(limit)
+            .map(|(_, value)| Ok(serde_json::from_slice(value)?))
+            .collect()
+    }
+
+    /// Applies `update` to the open sync run, if any, as part of `txn`.
+    pub(crate) fn track_sync_run(&self, txn: &mut RwTransaction, update: impl FnOnce(&mut SyncRun)) -> Result<(), AppResponse> {
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        if let Some(mut run) = open_run(txn, meta_db)? {
+            update(&mut run);
+            put_run(txn, meta_db, &run)?;
+        }
+        Ok(())
+    }
+}
+
+fn run_key(id: u64) -> Vec<u8> {
+    [SYNC_RUN_PREFIX, &id.to_be_bytes()].concat()
+}
+
+fn open_run<T: Transaction>(txn: &T, meta_db: Database) -> Result<Option<SyncRun>, AppResponse> {
+    let Some(id) = get_meta_u64(txn, meta_db, OPEN_SYNC_RUN_KEY)? else {
+        return Ok(None);
+    };
+    match txn.get(meta_db, &run_key(id)) {
+        Ok(value) => Ok(Some(serde_json::from_slice(value)?)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn put_run(txn: &mut RwTransaction, meta_db: Database, run: &SyncRun) -> Result<(), AppResponse> {
+    txn.put(meta_db, &run_key(run.id), &serde_json::to_vec(run)?, WriteFlags::empty())?;
+    Ok(())
+}
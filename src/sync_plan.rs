@@ -0,0 +1,136 @@
+//! Sync dry runs.
+//!
+//! [`AppDbState::plan_sync`] previews a sync without applying anything, so an
+//! app can show "12 changes to upload, 3 conflicts" before the user syncs on
+//! a metered connection. The local side of the plan comes from the delta
+//! consumer the sync layer acknowledges after each push (see
+//! [`crate::delta`]); the server side comes from a manifest of the hashes it
+//! changed since the last pull. A record changed on both sides conflicts
+//! unless both ended up with the same hash or were both deleted.
+//!
+//! For slow or metered networks, the records to push and pull are split into
+//! batches within the [`SyncLimits`] of the database, so the sync client can
+//! send one request per batch and report progress as each completes, as
+//! [`AppDbState::sync_now`] does. Deletions travel as ID lists and are not
+//! batched.
+
+use std::collections::BTreeMap;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{SyncBatch, SyncConflict, SyncLimits, SyncManifest, SyncPlan};
+use crate::local_db_state::AppDbState;
+
+impl AppDbState {
+    /// Sets the size limits of the batches planned by
+    /// [`AppDbState::plan_sync`] and pushed by [`AppDbState::sync_now`].
+    ///
+    /// A limit of `0` means no limit, like `None`, as it does for the FFI
+    /// function. The limits are not persisted; apps set them after opening
+    /// the database, e.g. tighter ones on a metered connection.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::SyncLimits;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let mut db = AppDbState::init("notes".to_string())?;
+    /// db.set_sync_limits(SyncLimits { max_batch_bytes: Some(256 * 1024), max_record_bytes: Some(1024 * 1024) });
+    /// # Ok::<(), lmdb::Error>(())
+    /// ```
+    pub fn set_sync_limits(&mut self, limits: SyncLimits) {
+        self.sync_limits = SyncLimits {
+            max_batch_bytes: limits.max_batch_bytes.filter(|&max| max != 0),
+            max_record_bytes: limits.max_record_bytes.filter(|&max| max != 0),
+        };
+    }
+
+    /// Returns the limits set with [`AppDbState::set_sync_limits`].
+    pub fn sync_limits(&self) -> SyncLimits {
+        self.sync_limits
+    }
+
+    /// Returns what a sync would push, pull and conflict on, given the
+    /// server changes in `manifest`. Nothing is written, and the consumer of
+    /// the manifest is not registered: an unknown consumer has every record
+    /// pending upload.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::SyncManifest;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    ///
+    /// let manifest: SyncManifest = serde_json::from_str(r#"{"remote":{"n1":"h2","n2":null}}"#).unwrap();
+    /// let plan = db.plan_sync(&manifest)?;
+    /// println!("{} to upload, {} conflicts", plan.push.len() + plan.push_deletes.len(), plan.conflicts.len());
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if the consumer is empty, or a
+    /// database error if the database cannot be read.
+    pub fn plan_sync(&self, manifest: &SyncManifest) -> Result<SyncPlan, AppResponse> {
+        let delta = self.peek_delta(&manifest.consumer)?;
+
+        let mut sizes = BTreeMap::new();
+        let mut local = BTreeMap::new();
+        for record in delta.records {
+            sizes.insert(record.id.clone(), serde_json::to_string(&record)?.len() as u64);
+            local.insert(record.id, Some(record.hash));
+        }
+        local.extend(delta.deleted.into_iter().map(|id| (id, None)));
+
+        let mut plan = SyncPlan { sequence: delta.sequence, ..SyncPlan::default() };
+        for (id, local_hash) in &local {
+            match manifest.remote.get(id) {
+                Some(remote_hash) if remote_hash == local_hash => plan.in_sync += 1,
+                Some(remote_hash) => plan.conflicts.push(SyncConflict {
+                    id: id.clone(),
+                    local_hash: local_hash.clone(),
+                    remote_hash: remote_hash.clone(),
+                }),
+                None if local_hash.is_some() => plan.push.push(id.clone()),
+                None => plan.push_deletes.push(id.clone()),
+            }
+        }
+
+        for (id, remote_hash) in manifest.remote.iter().filter(|(id, _)| !local.contains_key(*id)) {
+            let local_hash = self.get_by_id(id)?.map(|record| record.hash);
+            match remote_hash {
+                _ if *remote_hash == local_hash => plan.in_sync += 1,
+                Some(_) => plan.pull.push(id.clone()),
+                None => plan.pull_deletes.push(id.clone()),
+            }
+        }
+
+        let push = plan.push.iter().map(|id| (id, sizes.get(id).copied().unwrap_or(0)));
+        plan.push_batches = batches(push, self.sync_limits, &mut plan.oversized);
+        let pull = plan.pull.iter().map(|id| (id, manifest.sizes.get(id).copied().unwrap_or(0)));
+        plan.pull_batches = batches(pull, self.sync_limits, &mut plan.oversized);
+        Ok(plan)
+    }
+}
+
+/// Splits records, given by ID and size, into batches within `limits`,
+/// adding those above the record size cap to `oversized`.
+pub(crate) fn batches<'a>(records: impl Iterator<Item = (&'a String, u64)>, limits: SyncLimits, oversized: &mut Vec<String>) -> Vec<SyncBatch> {
+    let mut batches: Vec<SyncBatch> = Vec::new();
+    for (id, bytes) in records {
+        if limits.max_record_bytes.is_some_and(|cap| bytes > cap) {
+            oversized.push(id.clone());
+            continue;
+        }
+        match batches.last_mut() {
+            Some(batch) if limits.max_batch_bytes.is_none_or(|max| batch.bytes + bytes <= max) => {
+                batch.ids.push(id.clone());
+                batch.bytes += bytes;
+            }
+            _ => batches.push(SyncBatch { ids: vec![id.clone()], bytes }),
+        }
+    }
+    batches
+}
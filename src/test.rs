@@ -123,6 +123,7 @@ pub mod tests {
             id: id.to_string(),
             hash: format!("hash_{}", id),
             data: data.unwrap_or(serde_json::json!({"test": "data"})),
+            ..Default::default()
         }
     }
 
@@ -329,8 +330,6 @@ pub mod tests {
                 let _ = std::fs::remove_file(artifact);
             }
         }
-        
-        assert!(true, "Final cleanup test completed");
     }
 
     fn generate_unique_db_name(prefix: &str) -> String {
@@ -395,7 +394,7 @@ pub mod tests {
         let second_instance = AppDbState::init(db_name.to_string());
 
         // Check if we were able to open a second instance
-        if second_instance.is_ok() {
+        if let Ok(second_db) = second_instance.as_ref() {
             info!("Second instance opened successfully - database supports multiple connections");
 
             // Test writing to the first instance
@@ -404,7 +403,6 @@ pub mod tests {
             info!("Write to first instance: {}", result_1.is_ok());
 
             // Test writing to the second instance
-            let second_db = second_instance.as_ref().unwrap();
             let model_2 = create_test_model("test2", None);
             let result_2 = second_db.post(model_2.clone());
             info!("Write to second instance: {}", result_2.is_ok());
@@ -425,10 +423,8 @@ pub mod tests {
             info!("Second instance failed to open the same database");
 
             // Analyze the specific error type
-            match second_instance.err().unwrap() {
-                error => {
-                    info!("LMDB error: {:?}", error);
-                }
+            if let Err(error) = second_instance {
+                info!("LMDB error: {:?}", error);
             }
 
             // Verify that the first instance still works
@@ -502,7 +498,7 @@ pub mod tests {
 
         let name = CString::new(generate_unique_db_name("ffi_close_reopen")).unwrap();
         let db_ptr = create_db(name.as_ptr());
-        assert!(!db_ptr.is_null(), "create_db should return valid pointer");
+        assert_ne!(db_ptr, 0, "create_db should return valid pointer");
 
         // Insert one record
         let json = CString::new(r#"{"id":"a1","hash":"h1","data":{"k":1}}"#).unwrap();
@@ -526,19 +522,17 @@ pub mod tests {
 
         // Reopen with same name
         let db_ptr2 = create_db(name.as_ptr());
-        assert!(!db_ptr2.is_null(), "re-create should succeed");
+        assert_ne!(db_ptr2, 0, "re-create should succeed");
+        assert_ne!(db_ptr2, db_ptr, "closed handles are never reused");
 
-        // Insert again -> should succeed on new pointer
+        // Insert again -> should succeed on new handle
         let json3 = CString::new(r#"{"id":"a3","hash":"h3","data":{"k":3}}"#).unwrap();
         let res_ptr3 = push_data(db_ptr2, json3.as_ptr());
         assert!(!res_ptr3.is_null());
         unsafe { let _ = CString::from_raw(res_ptr3 as *mut i8); }
 
-        // Cleanup heap-allocated states (avoid leaks)
-        unsafe {
-            let _ = Box::from_raw(db_ptr2);
-            let _ = Box::from_raw(db_ptr);
-        }
+        // Cleanup
+        unsafe { let _ = CString::from_raw(close_database(db_ptr2) as *mut i8); }
     }
 
     #[test]
@@ -552,17 +546,17 @@ pub mod tests {
 
         // First create
         let db1 = create_db(name.as_ptr());
-        assert!(!db1.is_null());
+        assert_ne!(db1, 0);
 
-        // Second create on same existing dir: should still succeed
+        // Second create while still open (e.g. after a hot restart): same handle
         let db2 = create_db(name.as_ptr());
-        assert!(!db2.is_null());
+        assert_eq!(db2, db1);
 
-        // Cleanup
-        unsafe {
-            let _ = Box::from_raw(db2);
-            let _ = Box::from_raw(db1);
-        }
+        // Cleanup: the handle is gone after one close
+        let closed = unsafe { CString::from_raw(crate::close_database(db1) as *mut i8) };
+        assert!(closed.to_str().unwrap().contains("Ok"));
+        let closed = unsafe { CString::from_raw(crate::close_database(db2) as *mut i8) };
+        assert!(closed.to_str().unwrap().contains("Unknown database handle"));
     }
     #[test]
     fn test_get_by_id() {
@@ -1176,11 +1170,11 @@ pub mod tests {
         let db_name = CString::new("ffi_test_create").unwrap();
         let db_ptr = create_db(db_name.as_ptr());
         
-        assert!(!db_ptr.is_null(), "Database pointer should not be null");
+        assert_ne!(db_ptr, 0, "Database pointer should not be null");
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1189,7 +1183,7 @@ pub mod tests {
         use crate::{create_db};
         
         let db_ptr = create_db(std::ptr::null());
-        assert!(db_ptr.is_null(), "Should return null for null input");
+        assert_eq!(db_ptr, 0, "Should return null for null input");
     }
 
     #[test]
@@ -1200,7 +1194,7 @@ pub mod tests {
         let invalid_bytes = [0xFF, 0xFE, 0xFD, 0x00]; // Invalid UTF-8 + null terminator
         let db_ptr = create_db(invalid_bytes.as_ptr() as *const i8);
         
-        assert!(db_ptr.is_null(), "Should return null for invalid UTF-8");
+        assert_eq!(db_ptr, 0, "Should return null for invalid UTF-8");
     }
 
     #[test]
@@ -1212,7 +1206,7 @@ pub mod tests {
         
         let db_name = CString::new("ffi_test_push").unwrap();
         let db_ptr = create_db(db_name.as_ptr());
-        assert!(!db_ptr.is_null());
+        assert_ne!(db_ptr, 0);
         
         let json_data = CString::new(r#"{"id":"test1","hash":"hash1","data":{"key":"value"}}"#).unwrap();
         let result_ptr = push_data(db_ptr, json_data.as_ptr());
@@ -1226,7 +1220,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1239,7 +1233,7 @@ pub mod tests {
 
         let db_name = CString::new("ffi_test_post_put").unwrap();
         let db_ptr = create_db(db_name.as_ptr());
-        assert!(!db_ptr.is_null());
+        assert_ne!(db_ptr, 0);
 
         // Post
         let json = CString::new(r#"{"id":"p1","hash":"h1","data":{"k":"v"}}"#).unwrap();
@@ -1262,7 +1256,7 @@ pub mod tests {
         assert!(s.contains("\"Ok\""));
         assert!(s.contains("v2"));
 
-        unsafe { let _ = Box::from_raw(db_ptr); }
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
     }
 
     #[test]
@@ -1277,7 +1271,7 @@ pub mod tests {
         
         // Test null state pointer
         let json_data = CString::new(r#"{"id":"test1","hash":"hash1","data":{}}"#).unwrap();
-        let result_ptr = push_data(std::ptr::null_mut(), json_data.as_ptr());
+        let result_ptr = push_data(0, json_data.as_ptr());
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1294,7 +1288,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1318,7 +1312,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1348,7 +1342,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1372,7 +1366,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1388,7 +1382,7 @@ pub mod tests {
         
         // Test null state pointer
         let id = CString::new("test1").unwrap();
-        let result_ptr = get_by_id(std::ptr::null_mut(), id.as_ptr());
+        let result_ptr = get_by_id(0, id.as_ptr());
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1405,7 +1399,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1440,7 +1434,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1448,7 +1442,7 @@ pub mod tests {
     fn test_ffi_get_all_null_pointer() {
         use crate::{get_all};
         
-        let result_ptr = get_all(std::ptr::null_mut());
+        let result_ptr = get_all(0);
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1482,7 +1476,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1506,7 +1500,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1522,7 +1516,7 @@ pub mod tests {
         
         // Test null state pointer
         let json_data = CString::new(r#"{"id":"test1","hash":"hash1","data":{}}"#).unwrap();
-        let result_ptr = update_data(std::ptr::null_mut(), json_data.as_ptr());
+        let result_ptr = update_data(0, json_data.as_ptr());
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1539,7 +1533,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1569,7 +1563,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1593,7 +1587,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1609,7 +1603,7 @@ pub mod tests {
         
         // Test null state pointer
         let id = CString::new("test1").unwrap();
-        let result_ptr = delete_by_id(std::ptr::null_mut(), id.as_ptr());
+        let result_ptr = delete_by_id(0, id.as_ptr());
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1626,7 +1620,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1659,7 +1653,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1667,7 +1661,7 @@ pub mod tests {
     fn test_ffi_clear_all_records_null_pointer() {
         use crate::{clear_all_records};
         
-        let result_ptr = clear_all_records(std::ptr::null_mut());
+        let result_ptr = clear_all_records(0);
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1701,7 +1695,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1717,7 +1711,7 @@ pub mod tests {
         
         // Test null state pointer
         let new_name = CString::new("new_db").unwrap();
-        let result_ptr = reset_database(std::ptr::null_mut(), new_name.as_ptr());
+        let result_ptr = reset_database(0, new_name.as_ptr());
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1734,7 +1728,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1747,7 +1741,7 @@ pub mod tests {
         
         let db_name = CString::new("ffi_test_close").unwrap();
         let db_ptr = create_db(db_name.as_ptr());
-        assert!(!db_ptr.is_null());
+        assert_ne!(db_ptr, 0);
         
         let result_ptr = close_database(db_ptr);
         assert!(!result_ptr.is_null());
@@ -1759,7 +1753,7 @@ pub mod tests {
         
         // Cleanup
         unsafe {
-            let _db = Box::from_raw(db_ptr);
+            let _db = CString::from_raw(crate::close_database(db_ptr) as *mut i8);
         }
     }
 
@@ -1767,7 +1761,7 @@ pub mod tests {
     fn test_ffi_close_database_null_pointer() {
         use crate::{close_database};
         
-        let result_ptr = close_database(std::ptr::null_mut());
+        let result_ptr = close_database(0);
         assert!(!result_ptr.is_null());
         
         let result_str = unsafe { CString::from_raw(result_ptr as *mut i8) };
@@ -1827,6 +1821,7 @@ pub mod tests {
                     id: "deep_test".to_string(),
                     hash: "deep_hash".to_string(),
                     data: serde_json::from_str(&deep_json).unwrap_or(serde_json::json!({})),
+                    ..Default::default()
                 };
                 
                 // This should work or fail gracefully
@@ -1838,6 +1833,7 @@ pub mod tests {
                     id: "large_array".to_string(),
                     hash: "large_hash".to_string(),
                     data: large_array,
+                    ..Default::default()
                 };
                 
                 let _result = state.post(large_model);
@@ -1847,6 +1843,7 @@ pub mod tests {
                     id: "empty_test".to_string(),
                     hash: "".to_string(),
                     data: serde_json::json!(null),
+                    ..Default::default()
                 };
                 
                 let _result = state.post(empty_model);
@@ -1874,6 +1871,7 @@ pub mod tests {
                         id: id.to_string(),
                         hash: hash.to_string(),
                         data,
+                        ..Default::default()
                     };
                     
                     match state.post(model.clone()) {
@@ -1912,6 +1910,7 @@ pub mod tests {
                     id: long_id.clone(),
                     hash: "test_hash".to_string(),
                     data: serde_json::json!({"test": "data"}),
+                    ..Default::default()
                 };
                 
                 match state.post(model) {
@@ -1934,6 +1933,7 @@ pub mod tests {
                     id: "large_value_test".to_string(),
                     hash: "large_hash".to_string(),
                     data: large_data,
+                    ..Default::default()
                 };
                 
                 let _result = state.post(large_model);
@@ -1947,6 +1947,7 @@ pub mod tests {
                     id: "huge_value_test".to_string(),
                     hash: "huge_hash".to_string(),
                     data: huge_data,
+                    ..Default::default()
                 };
                 
                 // This should likely fail
@@ -1970,6 +1971,7 @@ pub mod tests {
                     id: "a".to_string(),
                     hash: "h".to_string(),
                     data: serde_json::json!({"key": "value"}),
+                    ..Default::default()
                 };
                 assert!(state.post(single_char_model).is_ok());
                 
@@ -1978,6 +1980,7 @@ pub mod tests {
                     id: "whitespace_test".to_string(),
                     hash: "   ".to_string(),
                     data: serde_json::json!({"spaces": "   "}),
+                    ..Default::default()
                 };
                 assert!(state.post(whitespace_model).is_ok());
                 
@@ -1986,6 +1989,7 @@ pub mod tests {
                     id: "12345".to_string(),
                     hash: "67890".to_string(),
                     data: serde_json::json!({"number": 42}),
+                    ..Default::default()
                 };
                 assert!(state.post(numeric_model).is_ok());
                 
@@ -1994,6 +1998,7 @@ pub mod tests {
                     id: "zero_test".to_string(),
                     hash: "zero_hash".to_string(),
                     data: serde_json::json!({"zero": 0, "false": false, "null": null}),
+                    ..Default::default()
                 };
                 assert!(state.post(zero_model).is_ok());
             }
@@ -2148,6 +2153,7 @@ pub mod tests {
                         id: format!("memory_test_{}", i),
                         hash: format!("hash_{}", i),
                         data: large_data,
+                        ..Default::default()
                     };
                     
                     if let Err(e) = state.post(model) {
@@ -2560,9 +2566,6417 @@ pub mod tests {
     }
 
     // ===============================
-    // HELPER FUNCTIONS
+    // BATCH AND EXTENDED API TESTS
     // ===============================
 
+    #[test]
+    fn test_delete_many() {
+        let state = AppDbState::init(generate_unique_db_name("delete_many")).unwrap();
+        for i in 1..=3 {
+            state.post(create_test_model(&i.to_string(), None)).unwrap();
+        }
+
+        let ids = vec!["1".to_string(), "3".to_string(), "missing".to_string()];
+        let result = state.delete_many(&ids).unwrap();
+
+        assert_eq!(result.deleted, vec!["1".to_string(), "3".to_string()]);
+        assert_eq!(result.not_found, vec!["missing".to_string()]);
+        assert!(state.get_by_id("1").unwrap().is_none());
+        assert!(state.get_by_id("2").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_ffi_delete_many() {
+        use crate::{create_db, push_data, delete_many};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_delete_many")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for i in 1..=2 {
+            let json = CString::new(format!(r#"{{"id":"d{i}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let ids = CString::new(r#"["d1","d2","d3"]"#).unwrap();
+        let result = unsafe { CString::from_raw(delete_many(db_ptr, ids.as_ptr()) as *mut i8) };
+        let result_json = result.to_str().unwrap();
+        assert!(result_json.contains("\"Ok\""));
+        assert!(result_json.contains(r#"\"not_found\":[\"d3\"]"#));
+
+        let bad = CString::new(r#"{"not":"an array"}"#).unwrap();
+        let result = unsafe { CString::from_raw(delete_many(db_ptr, bad.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(delete_many(0, ids.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_probe_paths_extracts_only_requested_values() {
+        use crate::query::probe_paths;
+
+        let json = r#"{"id":"a","hash":"h","data":{"status":"pending","blob":[1,2,{"x":"y"}],"items":[{"name":"first"}]}}"#;
+        let probed = probe_paths(json, &["data.status", "data.items.0.name", "data.missing", "id"]).unwrap();
+
+        assert_eq!(probed[0], Some(serde_json::json!("pending")));
+        assert_eq!(probed[1], Some(serde_json::json!("first")));
+        assert_eq!(probed[2], None);
+        assert_eq!(probed[3], Some(serde_json::json!("a")));
+        assert!(probe_paths("{not json", &["id"]).is_err());
+    }
+
+    #[test]
+    fn test_filter_by_paths() {
+        let state = AppDbState::init(generate_unique_db_name("filter_paths")).unwrap();
+        for (id, status) in [("1", "pending"), ("2", "done"), ("3", "pending")] {
+            let data = serde_json::json!({"status": status, "payload": "x".repeat(1024)});
+            state.post(create_test_model(id, Some(data))).unwrap();
+        }
+
+        let pending = state
+            .filter_by_paths(&["data.status"], |values| values[0] == Some(serde_json::json!("pending")))
+            .unwrap();
+
+        let ids: Vec<&str> = pending.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_get_by_ids() {
+        let state = AppDbState::init(generate_unique_db_name("get_by_ids")).unwrap();
+        for i in 1..=3 {
+            state.post(create_test_model(&i.to_string(), None)).unwrap();
+        }
+
+        let ids = vec!["3".to_string(), "nope".to_string(), "1".to_string()];
+        let result = state.get_by_ids(&ids).unwrap();
+
+        let found: Vec<&str> = result.found.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(found, vec!["3", "1"]);
+        assert_eq!(result.missing, vec!["nope".to_string()]);
+    }
+
+    #[test]
+    fn test_ffi_get_by_ids() {
+        use crate::{create_db, push_data, get_by_ids};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_get_by_ids")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"g1","hash":"h","data":{"k":"v"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let ids = CString::new(r#"["g1","g2"]"#).unwrap();
+        let result = unsafe { CString::from_raw(get_by_ids(db_ptr, ids.as_ptr()) as *mut i8) };
+        let result_json = result.to_str().unwrap();
+        assert!(result_json.contains("\"Ok\""));
+        assert!(result_json.contains(r#"\"missing\":[\"g2\"]"#));
+
+        let result = unsafe { CString::from_raw(get_by_ids(db_ptr, std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_value_header_roundtrip_and_legacy_values() {
+        use crate::value_codec::{split_value, ValueFormat, HEADER_MAGIC_V1};
+        use lmdb::{Transaction, WriteFlags};
+
+        let state = AppDbState::init(generate_unique_db_name("value_header")).unwrap();
+        state.post(create_test_model("new", None)).unwrap();
+
+        let (env, db) = state.env_db().unwrap();
+        {
+            let txn = env.begin_ro_txn().unwrap();
+            let raw = txn.get(db, &"new").unwrap();
+            assert_eq!(raw[0], HEADER_MAGIC_V1);
+            let (header, _) = split_value(raw).unwrap();
+            assert_eq!(header.format, ValueFormat::Json);
+            assert_eq!(header.schema_version, 0);
+        }
+
+        // Values written before the header existed remain readable
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, &"legacy", &r#"{"id":"legacy","hash":"h","data":{"v":1}}"#, WriteFlags::empty()).unwrap();
+        txn.put(db, &"future", &[HEADER_MAGIC_V1, 9, 0, 0, 1, b'{', b'}'], WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        assert_eq!(state.get_by_id("legacy").unwrap().unwrap().data["v"], 1);
+        match state.get_by_id("future") {
+            Err(crate::app_response::AppResponse::SerializationError(msg)) => {
+                assert!(msg.contains("Unknown value format"), "unexpected message: {msg}");
+            }
+            other => panic!("Expected a format error, got {other:?}"),
+        }
+        assert_eq!(state.get().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_get_with_quarantine() {
+        use lmdb::{Transaction, WriteFlags};
+
+        let state = AppDbState::init(generate_unique_db_name("quarantine")).unwrap();
+        state.post(create_test_model("good", None)).unwrap();
+        {
+            let (env, db) = state.env_db().unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, &"bad", &[0xFFu8, 0xFE, 0xFD], WriteFlags::empty()).unwrap();
+            txn.put(db, &"bad_id", &r#"{"id":1,"hash":"h","data":{}}"#, WriteFlags::empty()).unwrap();
+            txn.put(db, &"no_data", &r#"{"id":"no_data","hash":"h"}"#, WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        assert_eq!(state.get().unwrap().len(), 1);
+
+        let result = state.get_with_quarantine().unwrap();
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(result.quarantined.len(), 3);
+        assert_eq!(result.quarantined[0].id, "bad");
+        assert_eq!(result.quarantined[0].raw_size, 3);
+
+        // The cheap check reports the same records and errors as a full decode
+        assert_eq!(state.quarantine_list().unwrap(), result.quarantined);
+    }
+
+    #[test]
+    fn test_ffi_quarantine_list() {
+        use crate::{create_db, get_all_with_quarantine, quarantine_list};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_quarantine")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(quarantine_list(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[]"}"#);
+
+        let result = unsafe { CString::from_raw(get_all_with_quarantine(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("quarantined"));
+
+        let result = unsafe { CString::from_raw(quarantine_list(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_paginated() {
+        let state = AppDbState::init(generate_unique_db_name("paginated")).unwrap();
+        for i in 0..10 {
+            state.post(create_test_model(&format!("rec_{i:02}"), None)).unwrap();
+        }
+
+        let first = state.get_paginated(4, 0).unwrap();
+        let ids: Vec<&str> = first.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["rec_00", "rec_01", "rec_02", "rec_03"]);
+
+        let last = state.get_paginated(4, 8).unwrap();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[0].id, "rec_08");
+
+        assert!(state.get_paginated(4, 20).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_get_paginated() {
+        use crate::{create_db, push_data, get_paginated};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_paginated")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for i in 0..3 {
+            let json = CString::new(format!(r#"{{"id":"p{i}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let result = unsafe { CString::from_raw(get_paginated(db_ptr, 1, 1) as *mut i8) };
+        let result_json = result.to_str().unwrap();
+        assert!(result_json.contains("p1"));
+        assert!(!result_json.contains("p0") && !result_json.contains("p2"));
+
+        let result = unsafe { CString::from_raw(get_paginated(0, 1, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_page_after() {
+        let state = AppDbState::init(generate_unique_db_name("page_after")).unwrap();
+
+        // Empty database must not panic
+        let empty = state.get_page_after(None, 10).unwrap();
+        assert!(empty.records.is_empty());
+        assert!(empty.next_token.is_none());
+
+        for i in 0..5 {
+            state.post(create_test_model(&format!("k{i}"), None)).unwrap();
+        }
+
+        let first = state.get_page_after(None, 2).unwrap();
+        assert_eq!(first.records.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["k0", "k1"]);
+        assert_eq!(first.next_token.as_deref(), Some("k1"));
+
+        let second = state.get_page_after(first.next_token.as_deref(), 2).unwrap();
+        assert_eq!(second.records.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["k2", "k3"]);
+
+        let last = state.get_page_after(second.next_token.as_deref(), 2).unwrap();
+        assert_eq!(last.records.len(), 1);
+        assert!(last.next_token.is_none());
+
+        // Tokens past the end or between keys behave like a range seek
+        assert!(state.get_page_after(Some("zzz"), 2).unwrap().records.is_empty());
+        assert_eq!(state.get_page_after(Some("k2a"), 1).unwrap().records[0].id, "k3");
+        assert!(matches!(
+            state.get_page_after(None, 0),
+            Err(crate::app_response::AppResponse::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_ffi_get_page_after() {
+        use crate::{create_db, push_data, get_page_after};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_page_after")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for i in 0..3 {
+            let json = CString::new(format!(r#"{{"id":"c{i}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let result = unsafe { CString::from_raw(get_page_after(db_ptr, std::ptr::null(), 2) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"next_token\":\"c1\""#));
+
+        let token = CString::new("c1").unwrap();
+        let result = unsafe { CString::from_raw(get_page_after(db_ptr, token.as_ptr(), 2) as *mut i8) };
+        let result_json = result.to_str().unwrap();
+        assert!(result_json.contains("c2"));
+        assert!(result_json.contains(r#"\"next_token\":null"#));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_resync_queue() {
+        let state = AppDbState::init(generate_unique_db_name("resync")).unwrap();
+        state.post(create_test_model("kept", None)).unwrap();
+
+        let ids = vec!["b".to_string(), "a".to_string()];
+        assert_eq!(state.mark_for_resync(&ids).unwrap(), 2);
+        let first = state.get_resync_queue().unwrap();
+        assert_eq!(first.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+        // Re-marking keeps the original timestamp
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(state.mark_for_resync(&["a".to_string()]).unwrap(), 0);
+        assert_eq!(state.get_resync_queue().unwrap(), first);
+
+        // The queue never leaks into the record APIs
+        assert_eq!(state.get().unwrap().len(), 1);
+
+        assert_eq!(state.clear_resync(&["a".to_string(), "missing".to_string()]).unwrap(), 1);
+        assert_eq!(state.get_resync_queue().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ffi_resync_queue() {
+        use crate::{create_db, mark_for_resync, get_resync_queue, clear_resync};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_resync")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let ids = CString::new(r#"["r1","r2"]"#).unwrap();
+        let result = unsafe { CString::from_raw(mark_for_resync(db_ptr, ids.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+
+        let result = unsafe { CString::from_raw(get_resync_queue(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("marked_at"));
+
+        let result = unsafe { CString::from_raw(clear_resync(db_ptr, ids.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+
+        let bad = CString::new("not json").unwrap();
+        let result = unsafe { CString::from_raw(mark_for_resync(db_ptr, bad.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_copy_records() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+
+        let src_name = generate_unique_db_name("copy_src");
+        let dst_name = generate_unique_db_name("copy_dst");
+        {
+            let src = AppDbState::init(src_name.clone()).unwrap();
+            for (id, feature) in [("1", "todos"), ("2", "notes"), ("3", "todos")] {
+                src.post(create_test_model(id, Some(serde_json::json!({"feature": feature})))).unwrap();
+            }
+        }
+
+        let filter: PathFilter = serde_json::from_str(r#"{"data.feature":"todos"}"#).unwrap();
+        assert_eq!(AppDbState::copy_records(&src_name, &dst_name, &filter).unwrap(), 2);
+
+        let dst = AppDbState::init(dst_name.clone()).unwrap();
+        let ids: Vec<String> = dst.get().unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec!["1".to_string(), "3".to_string()]);
+        drop(dst);
+
+        assert_eq!(AppDbState::copy_records(&src_name, &dst_name, &PathFilter::default()).unwrap(), 3);
+        assert!(matches!(
+            AppDbState::copy_records(&generate_unique_db_name("copy_missing"), &dst_name, &filter),
+            Err(AppResponse::NotFound(_))
+        ));
+        assert!(matches!(
+            AppDbState::copy_records(&src_name, &src_name, &filter),
+            Err(AppResponse::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_ffi_copy_records() {
+        use crate::copy_records;
+
+        let src_name = generate_unique_db_name("ffi_copy_src");
+        {
+            let src = AppDbState::init(src_name.clone()).unwrap();
+            src.post(create_test_model("a", None)).unwrap();
+        }
+
+        let src = CString::new(src_name).unwrap();
+        let dst = CString::new(generate_unique_db_name("ffi_copy_dst")).unwrap();
+        let result = unsafe { CString::from_raw(copy_records(src.as_ptr(), dst.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let bad = CString::new("[1]").unwrap();
+        let result = unsafe { CString::from_raw(copy_records(src.as_ptr(), dst.as_ptr(), bad.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        // Databases open over FFI are copied through their open environments
+        let src_handle = crate::create_db(src.as_ptr());
+        let dst_handle = crate::create_db(dst.as_ptr());
+        let json = CString::new(r#"{"id":"b","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(crate::push_data(src_handle, json.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(copy_records(src.as_ptr(), dst.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+        let result = unsafe { CString::from_raw(crate::count_records(dst_handle) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+
+        unsafe { let _ = CString::from_raw(crate::close_database(src_handle) as *mut i8); }
+        unsafe { let _ = CString::from_raw(crate::close_database(dst_handle) as *mut i8); }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_copy_encrypted_records() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+
+        let key = [3u8; 32];
+        let src_name = generate_unique_db_name("copy_encrypted_src");
+        let src_c = CString::new(src_name.clone()).unwrap();
+        let handle = crate::create_db(src_c.as_ptr());
+        let src = crate::registry::get(handle).unwrap();
+        src.write().unwrap().set_encryption_key("t1", "t1:", &key).unwrap();
+        {
+            let src = src.read().unwrap();
+            src.post(create_test_model("t1:a", Some(serde_json::json!({"kind": "x"})))).unwrap();
+            src.post(create_test_model("t1:b", Some(serde_json::json!({"kind": "y"})))).unwrap();
+            src.post(create_test_model("plain", Some(serde_json::json!({"kind": "x"})))).unwrap();
+        }
+
+        // Encrypted records are filtered with the source key and copied sealed
+        let dst_name = generate_unique_db_name("copy_encrypted_dst");
+        let filter: PathFilter = serde_json::from_str(r#"{"data.kind":"x"}"#).unwrap();
+        assert_eq!(AppDbState::copy_records(&src_name, &dst_name, &filter).unwrap(), 2);
+        let result = AppDbState::shard_by(&src_name, "data.kind", 2).unwrap();
+        assert_eq!(result.counts.iter().sum::<usize>(), 3);
+        {
+            let mut dst = AppDbState::init(dst_name.clone()).unwrap();
+            assert!(dst.get_by_id("t1:a").is_err());
+            dst.set_encryption_key("t1", "t1:", &key).unwrap();
+            assert_eq!(dst.get_by_id("t1:a").unwrap().unwrap().data["kind"], "x");
+            assert!(dst.get_by_id("t1:b").unwrap().is_none());
+        }
+        for shard_name in &result.shards {
+            let mut shard = AppDbState::init(shard_name.clone()).unwrap();
+            shard.set_encryption_key("t1", "t1:", &key).unwrap();
+            assert_eq!(shard.get().unwrap().len(), shard.count_records().unwrap());
+        }
+
+        // Without the key they fail the copy instead of being dropped
+        assert!(src.write().unwrap().remove_encryption_key("t1"));
+        assert!(matches!(
+            AppDbState::copy_records(&src_name, &generate_unique_db_name("copy_encrypted_dst"), &filter),
+            Err(AppResponse::BadRequest(_))
+        ));
+        assert!(matches!(AppDbState::shard_by(&src_name, "data.kind", 3), Err(AppResponse::BadRequest(_))));
+
+        drop(src);
+        unsafe { let _ = CString::from_raw(crate::close_database(handle) as *mut i8); }
+    }
+
+    #[test]
+    fn test_count_records() {
+        let state = AppDbState::init(generate_unique_db_name("count")).unwrap();
+        assert_eq!(state.count_records().unwrap(), 0);
+
+        for i in 0..5 {
+            state.post(create_test_model(&i.to_string(), None)).unwrap();
+        }
+        state.mark_for_resync(&["0".to_string()]).unwrap();
+        assert_eq!(state.count_records().unwrap(), 5);
+
+        state.delete_by_id("0").unwrap();
+        assert_eq!(state.count_records().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_ffi_count_records() {
+        use crate::{create_db, push_data, count_records, close_database};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_count")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"c1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(count_records(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        unsafe { let _ = CString::from_raw(close_database(db_ptr) as *mut i8); }
+        let result = unsafe { CString::from_raw(count_records(db_ptr) as *mut i8) };
+        assert!(!result.to_str().unwrap().contains("Ok"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_shard_by() {
+        let src_name = generate_unique_db_name("shard_src");
+        {
+            let src = AppDbState::init(src_name.clone()).unwrap();
+            for i in 0..30 {
+                let data = serde_json::json!({"category": format!("cat_{}", i % 6)});
+                src.post(create_test_model(&i.to_string(), Some(data))).unwrap();
+            }
+            src.post(create_test_model("no_category", None)).unwrap();
+        }
+
+        let result = AppDbState::shard_by(&src_name, "data.category", 3).unwrap();
+        assert_eq!(result.shards.len(), 3);
+        assert_eq!(result.counts.iter().sum::<usize>(), 31);
+
+        // Records sharing a category always land in the same shard
+        for shard_name in &result.shards {
+            let shard = AppDbState::init(shard_name.clone()).unwrap();
+            for record in shard.get().unwrap() {
+                let category = &record.data["category"];
+                let peers = (0..30).filter(|i| serde_json::json!(format!("cat_{}", i % 6)) == *category);
+                for peer in peers {
+                    assert!(shard.get_by_id(&peer.to_string()).unwrap().is_some());
+                }
+            }
+        }
+
+        assert!(AppDbState::shard_by(&src_name, "data.category", 0).is_err());
+
+        // A second run would mix into the shards of the first
+        assert!(matches!(
+            AppDbState::shard_by(&src_name, "data.category", 3),
+            Err(crate::app_response::AppResponse::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_ffi_shard_by() {
+        use crate::shard_by;
+
+        let src_name = generate_unique_db_name("ffi_shard_src");
+        {
+            let src = AppDbState::init(src_name.clone()).unwrap();
+            src.post(create_test_model("a", None)).unwrap();
+        }
+
+        let src = CString::new(src_name).unwrap();
+        let path = CString::new("id").unwrap();
+        let result = unsafe { CString::from_raw(shard_by(src.as_ptr(), path.as_ptr(), 2) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("_shard_1"));
+
+        let result = unsafe { CString::from_raw(shard_by(src.as_ptr(), std::ptr::null(), 2) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+    }
+
+    #[test]
+    fn test_record_exists() {
+        use lmdb::{Transaction, WriteFlags};
+
+        let state = AppDbState::init(generate_unique_db_name("exists")).unwrap();
+        state.post(create_test_model("here", None)).unwrap();
+        {
+            let (env, db) = state.env_db().unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, &"broken", &[0xFFu8, 0xFE], WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        assert!(state.record_exists("here").unwrap());
+        assert!(state.record_exists("broken").unwrap());
+        assert!(!state.record_exists("gone").unwrap());
+    }
+
+    #[test]
+    fn test_ffi_record_exists() {
+        use crate::{create_db, push_data, record_exists};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_exists")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"e1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("e1").unwrap();
+        let result = unsafe { CString::from_raw(record_exists(db_ptr, id.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        let id = CString::new("e2").unwrap();
+        let result = unsafe { CString::from_raw(record_exists(db_ptr, id.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"false"}"#);
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_all_ids() {
+        let state = AppDbState::init(generate_unique_db_name("all_ids")).unwrap();
+        assert!(state.get_all_ids().unwrap().is_empty());
+
+        for id in ["b", "c", "a"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+        state.mark_for_resync(&["z".to_string()]).unwrap();
+
+        assert_eq!(state.get_all_ids().unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_ffi_get_all_ids() {
+        use crate::{create_db, push_data, get_all_ids};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_all_ids")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for id in ["k2", "k1"] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let result = unsafe { CString::from_raw(get_all_ids(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[\"k1\",\"k2\"]"}"#);
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_with_fallback() {
+        let asset_name = generate_unique_db_name("asset");
+        {
+            let asset = AppDbState::init(asset_name.clone()).unwrap();
+            asset.post(create_test_model("shared", Some(serde_json::json!({"from": "asset"})))).unwrap();
+            asset.post(create_test_model("catalog_only", None)).unwrap();
+        }
+
+        let mut state = AppDbState::init(generate_unique_db_name("asset_user")).unwrap();
+        state.post(create_test_model("shared", Some(serde_json::json!({"from": "user"})))).unwrap();
+        assert!(state.get_with_fallback("catalog_only").unwrap().is_none());
+
+        state.attach_asset_db(&asset_name).unwrap();
+        let shared = state.get_with_fallback("shared").unwrap().unwrap();
+        assert_eq!(shared.data["from"], "user");
+        assert!(state.get_with_fallback("catalog_only").unwrap().is_some());
+        assert!(state.get_with_fallback("nowhere").unwrap().is_none());
+
+        // Fallback never leaks into the user database
+        assert!(state.get_by_id("catalog_only").unwrap().is_none());
+
+        state.detach_asset_db();
+        assert!(state.get_with_fallback("catalog_only").unwrap().is_none());
+        assert!(state.attach_asset_db(&generate_unique_db_name("asset_missing")).is_err());
+    }
+
+    #[test]
+    fn test_ffi_get_with_fallback() {
+        use crate::{create_db, attach_asset_db, get_with_fallback};
+
+        let asset_name = generate_unique_db_name("ffi_asset");
+        {
+            let asset = AppDbState::init(asset_name.clone()).unwrap();
+            asset.post(create_test_model("from_asset", None)).unwrap();
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_asset_user")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let asset = CString::new(asset_name).unwrap();
+        let result = unsafe { CString::from_raw(attach_asset_db(db_ptr, asset.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+
+        let id = CString::new("from_asset").unwrap();
+        let result = unsafe { CString::from_raw(get_with_fallback(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("from_asset"));
+
+        let missing = CString::new("missing_asset_db").unwrap();
+        let result = unsafe { CString::from_raw(attach_asset_db(db_ptr, missing.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_build_prebuilt_db() {
+        use crate::local_db_model::BuildOptions;
+
+        let dataset = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("dataset")));
+        std::fs::write(
+            &dataset,
+            "{\"id\":\"p2\",\"hash\":\"h\",\"data\":{\"name\":\"pear\"}}\n\n{\"id\":\"p1\",\"hash\":\"h\",\"data\":{\"name\":\"apple\"}}\n",
+        )
+        .unwrap();
+
+        let output = generate_unique_db_name("prebuilt");
+        let options = BuildOptions { compress: false, schema_version: 7, dataset_version: 3 };
+        let result = AppDbState::build_prebuilt_db(dataset.to_str().unwrap(), &output, &options).unwrap();
+        assert_eq!(result.records, 2);
+        assert!(!std::path::Path::new(&format!("{output}.staging.lmdb")).exists());
+
+        // The asset ships the dataset version but none of the staging state
+        {
+            use lmdb::{Cursor, Environment, EnvironmentFlags, Transaction};
+
+            let env = Environment::new()
+                .set_max_dbs(64)
+                .set_flags(EnvironmentFlags::READ_ONLY)
+                .open(std::path::Path::new(&format!("{output}.lmdb")))
+                .unwrap();
+            let meta = env.open_db(Some(crate::meta::META_DB_NAME)).unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let mut cursor = txn.open_ro_cursor(meta).unwrap();
+            let keys: Vec<&[u8]> = cursor.iter().map(|(key, _)| key).collect();
+            assert_eq!(keys, vec![crate::meta::DATASET_VERSION_KEY.as_bytes()]);
+        }
+
+        let mut user = AppDbState::init(generate_unique_db_name("prebuilt_user")).unwrap();
+        user.attach_asset_db(&output).unwrap();
+        assert_eq!(user.get_with_fallback("p1").unwrap().unwrap().data["name"], "apple");
+        assert_eq!(AppDbState::init(output.clone()).unwrap().dataset_version().unwrap(), 3);
+
+        // Duplicate IDs and malformed lines are rejected
+        std::fs::write(&dataset, r#"[{"id":"a","hash":"h","data":{}},{"id":"a","hash":"h","data":{}}]"#).unwrap();
+        assert!(AppDbState::build_prebuilt_db(dataset.to_str().unwrap(), &output, &options).is_err());
+        std::fs::write(&dataset, "{\"id\":\"a\",\"hash\":\"h\",\"data\":{}}\nnot json\n").unwrap();
+        assert!(AppDbState::build_prebuilt_db(dataset.to_str().unwrap(), &output, &options).is_err());
+
+        std::fs::remove_file(dataset).unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_build_prebuilt_db_compressed() {
+        use crate::local_db_model::BuildOptions;
+        use crate::value_codec::split_value;
+        use lmdb::Transaction;
+
+        let dataset = std::env::temp_dir().join(format!("{}.json", generate_unique_db_name("dataset_zlib")));
+        let data = serde_json::json!({"text": "lorem ipsum ".repeat(100)});
+        std::fs::write(&dataset, serde_json::to_string(&vec![create_test_model("z", Some(data))]).unwrap()).unwrap();
+
+        let output = generate_unique_db_name("prebuilt_zlib");
+        let options = BuildOptions { compress: true, ..BuildOptions::default() };
+        AppDbState::build_prebuilt_db(dataset.to_str().unwrap(), &output, &options).unwrap();
+
+        let asset = AppDbState::init(output).unwrap();
+        let (env, db) = asset.env_db().unwrap();
+        let txn = env.begin_ro_txn().unwrap();
+        let (header, payload) = split_value(txn.get(db, &"z").unwrap()).unwrap();
+        assert!(header.compressed);
+        assert!(payload.len() < 200);
+        drop(txn);
+
+        assert!(asset.get_by_id("z").unwrap().is_some());
+        std::fs::remove_file(dataset).unwrap();
+    }
+
+    #[test]
+    fn test_get_by_prefix() {
+        let state = AppDbState::init(generate_unique_db_name("prefix")).unwrap();
+        for id in ["note:1", "todo:2", "todo:1", "todoz", "user:1"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+
+        let todos: Vec<String> = state.get_by_prefix("todo:").unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(todos, vec!["todo:1", "todo:2"]);
+
+        assert!(state.get_by_prefix("zzz").unwrap().is_empty());
+        assert_eq!(state.get_by_prefix("").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_ffi_get_by_prefix() {
+        use crate::{create_db, push_data, get_by_prefix};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_prefix")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for id in ["a:1", "b:1"] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let prefix = CString::new("b:").unwrap();
+        let result = unsafe { CString::from_raw(get_by_prefix(db_ptr, prefix.as_ptr()) as *mut i8) };
+        let text = result.to_str().unwrap();
+        assert!(text.contains("b:1") && !text.contains("a:1"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_range() {
+        let state = AppDbState::init(generate_unique_db_name("range")).unwrap();
+        for ts in ["1000", "1100", "1200", "1300", "1400"] {
+            state.post(create_test_model(ts, None)).unwrap();
+        }
+
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids(state.get_range(Some("1100"), Some("1300"), 10).unwrap()), vec!["1100", "1200"]);
+        assert_eq!(ids(state.get_range(Some("1050"), None, 2).unwrap()), vec!["1100", "1200"]);
+        assert_eq!(ids(state.get_range(None, Some("1100"), 10).unwrap()), vec!["1000"]);
+        assert!(state.get_range(Some("2000"), None, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_get_range() {
+        use crate::{create_db, push_data, get_range};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_range")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for id in ["r1", "r2", "r3"] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let start = CString::new("r2").unwrap();
+        let result = unsafe { CString::from_raw(get_range(db_ptr, start.as_ptr(), std::ptr::null(), 10) as *mut i8) };
+        let text = result.to_str().unwrap();
+        assert!(text.contains("r2") && text.contains("r3") && !text.contains("r1"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_descending_order() {
+        use crate::local_db_model::Direction;
+
+        let state = AppDbState::init(generate_unique_db_name("desc")).unwrap();
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert!(state.get_all_ordered(Direction::Desc).unwrap().is_empty());
+        assert!(state.get_page_after_ordered(Some("x"), 2, Direction::Desc).unwrap().records.is_empty());
+
+        for id in ["b", "d", "f", "h"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+
+        assert_eq!(ids(state.get_all_ordered(Direction::Desc).unwrap()), vec!["h", "f", "d", "b"]);
+        assert_eq!(ids(state.get_paginated_ordered(2, 1, Direction::Desc).unwrap()), vec!["f", "d"]);
+
+        let page = state.get_page_after_ordered(None, 3, Direction::Desc).unwrap();
+        assert_eq!(ids(page.records), vec!["h", "f", "d"]);
+        let page = state.get_page_after_ordered(page.next_token.as_deref(), 3, Direction::Desc).unwrap();
+        assert_eq!(ids(page.records), vec!["b"]);
+        assert!(page.next_token.is_none());
+
+        // Tokens that are not stored keys resume below them
+        assert_eq!(ids(state.get_page_after_ordered(Some("e"), 5, Direction::Desc).unwrap().records), vec!["d", "b"]);
+        assert_eq!(ids(state.get_page_after_ordered(Some("z"), 1, Direction::Desc).unwrap().records), vec!["h"]);
+        assert!(state.get_page_after_ordered(Some("a"), 5, Direction::Desc).unwrap().records.is_empty());
+    }
+
+    #[test]
+    fn test_ffi_descending_order() {
+        use crate::{create_db, push_data, get_all_desc, get_page_after_desc};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_desc")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for id in ["d1", "d2"] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let result = unsafe { CString::from_raw(get_all_desc(db_ptr) as *mut i8) };
+        let text = result.to_str().unwrap();
+        assert!(text.find("d2").unwrap() < text.find("d1").unwrap());
+
+        let result = unsafe { CString::from_raw(get_page_after_desc(db_ptr, std::ptr::null(), 1) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"next_token\":\"d2\""#));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_apply_dataset_patch() {
+        use crate::app_response::AppResponse;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_hex = to_hex(key.verifying_key().as_bytes());
+
+        let state = AppDbState::init(generate_unique_db_name("patch")).unwrap();
+        state.post(create_test_model("old", None)).unwrap();
+        state.post(create_test_model("kept", None)).unwrap();
+        assert_eq!(state.dataset_version().unwrap(), 0);
+
+        let patch_path = std::env::temp_dir().join(format!("{}.json", generate_unique_db_name("patch_file")));
+        let patch_path = patch_path.to_str().unwrap();
+        let patch = br#"{"from_version":0,"to_version":1,"upserts":[{"id":"new","hash":"h","data":{}}],"deletes":["old","never"]}"#;
+        std::fs::write(patch_path, patch).unwrap();
+        std::fs::write(format!("{patch_path}.sig"), to_hex(&key.sign(patch).to_bytes())).unwrap();
+
+        // A different key is rejected before anything is written
+        let other_hex = to_hex(SigningKey::from_bytes(&[8u8; 32]).verifying_key().as_bytes());
+        assert!(matches!(state.apply_dataset_patch(patch_path, &other_hex), Err(AppResponse::BadRequest(_))));
+        assert!(state.record_exists("old").unwrap());
+
+        let result = state.apply_dataset_patch(patch_path, &public_hex).unwrap();
+        assert_eq!((result.version, result.upserted, result.deleted), (1, 1, 1));
+        assert_eq!(state.get_all_ids().unwrap(), vec!["kept", "new"]);
+        assert_eq!(state.dataset_version().unwrap(), 1);
+
+        // The same patch no longer applies to version 1
+        assert!(state.apply_dataset_patch(patch_path, &public_hex).is_err());
+
+        std::fs::remove_file(patch_path).unwrap();
+        std::fs::remove_file(format!("{patch_path}.sig")).unwrap();
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_import_from_file_verifies_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let key = SigningKey::from_bytes(&[9u8; 32]);
+        let public_hex = to_hex(key.verifying_key().as_bytes());
+
+        let path = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("import")));
+        let path = path.to_str().unwrap();
+        let content = b"{\"id\":\"i1\",\"hash\":\"h\",\"data\":{}}\n{\"id\":\"i2\",\"hash\":\"h\",\"data\":{}}\n";
+        std::fs::write(path, content).unwrap();
+
+        let state = AppDbState::init(generate_unique_db_name("import_db")).unwrap();
+
+        // Missing signature
+        assert!(state.import_from_file(path, Some(&public_hex)).is_err());
+
+        // Tampered content
+        std::fs::write(format!("{path}.sig"), to_hex(&key.sign(b"something else").to_bytes())).unwrap();
+        assert!(state.import_from_file(path, Some(&public_hex)).is_err());
+        assert_eq!(state.count_records().unwrap(), 0);
+
+        std::fs::write(format!("{path}.sig"), to_hex(&key.sign(content).to_bytes())).unwrap();
+        assert_eq!(state.import_from_file(path, Some(&public_hex)).unwrap(), 2);
+        assert_eq!(state.get_all_ids().unwrap(), vec!["i1", "i2"]);
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(format!("{path}.sig")).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_import_from_file() {
+        use crate::{create_db, import_from_file};
+
+        let path = std::env::temp_dir().join(format!("{}.json", generate_unique_db_name("ffi_import")));
+        std::fs::write(&path, r#"[{"id":"f1","hash":"h","data":{}}]"#).unwrap();
+
+        let db_name = CString::new(generate_unique_db_name("ffi_import_db")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(import_from_file(db_ptr, c_path.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        std::fs::remove_file(path).unwrap();
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_ffi_apply_dataset_patch() {
+        use crate::{create_db, apply_dataset_patch, get_dataset_version};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_patch")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_dataset_version(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+
+        let missing = CString::new("no_such_patch.json").unwrap();
+        let key = CString::new("00").unwrap();
+        let result = unsafe { CString::from_raw(apply_dataset_patch(db_ptr, missing.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_analyze_storage() {
+        let state = AppDbState::init(generate_unique_db_name("analyze")).unwrap();
+
+        let empty = state.analyze_storage(5).unwrap();
+        assert_eq!(empty.record_count, 0);
+        assert!(empty.largest_records.is_empty());
+
+        state.post(create_test_model("note:1", Some(serde_json::json!({"text": "short"})))).unwrap();
+        state.post(create_test_model("note:2", Some(serde_json::json!({"text": "a".repeat(2000)})))).unwrap();
+        state.post(create_test_model("user:1", Some(serde_json::json!({"name": "Ana"})))).unwrap();
+
+        let report = state.analyze_storage(1).unwrap();
+        assert_eq!(report.record_count, 3);
+        assert_eq!(report.size_buckets.iter().map(|b| b.count).sum::<usize>(), 3);
+        assert_eq!(report.size_buckets[0].count, 2);
+        assert_eq!(report.size_buckets[2].count, 1);
+        assert!(report.min_size <= report.median_size && report.median_size <= report.max_size);
+        assert_eq!(report.compressed_records, 0);
+        // The repeated text makes the payloads highly compressible
+        assert!(report.estimated_compression_ratio < 0.5);
+
+        assert_eq!(report.largest_records.len(), 1);
+        assert_eq!(report.largest_records[0].id, "note:2");
+        assert_eq!(report.largest_records[0].size, report.max_size);
+        assert_eq!(report.largest_prefixes.len(), 1);
+        assert_eq!(report.largest_prefixes[0].prefix, "note");
+        assert_eq!(report.largest_prefixes[0].records, 2);
+    }
+
+    #[test]
+    fn test_ffi_analyze_storage() {
+        use crate::{create_db, analyze_storage};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_analyze")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(analyze_storage(db_ptr, 3) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"record_count\":0"#));
+
+        let result = unsafe { CString::from_raw(analyze_storage(0, 3) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let state = AppDbState::init(generate_unique_db_name("memory_stats")).unwrap();
+        for i in 0..50 {
+            state.post(create_test_model(&format!("m{i}"), None)).unwrap();
+        }
+        state.get().unwrap();
+
+        let stats = state.memory_stats().unwrap();
+        assert!(stats.page_size > 0);
+        assert!(stats.used_pages > 0);
+        assert!(stats.map_size >= stats.used_pages * stats.page_size);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let resident = stats.resident_pages.unwrap();
+            assert!(resident > 0 && resident <= stats.used_pages);
+        }
+    }
+
+    #[test]
+    fn test_ffi_memory_stats_and_free_c_string() {
+        use crate::{create_db, free_c_string, get_memory_stats};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_memory_stats")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = get_memory_stats(db_ptr);
+        let json = unsafe { std::ffi::CStr::from_ptr(result) }.to_str().unwrap().to_string();
+        assert!(json.contains("outstanding_buffer_bytes"));
+        free_c_string(result);
+        free_c_string(std::ptr::null());
+
+        let result = unsafe { CString::from_raw(get_memory_stats(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_compound_index() {
+        use crate::local_db_model::Direction;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("compound_index")).unwrap();
+        state.post(create_test_model("t1", Some(json!({"account": "a", "at": 30})))).unwrap();
+        state.post(create_test_model("t2", Some(json!({"account": "b", "at": 10})))).unwrap();
+        state.post(create_test_model("t3", Some(json!({"account": "a", "at": -5})))).unwrap();
+
+        let paths = vec!["data.account".to_string(), "data.at".to_string()];
+        assert_eq!(state.create_index("by_account_at", &paths).unwrap(), 3);
+        assert!(state.create_index("by_account_at", &paths).is_err());
+
+        // Written after the index exists
+        state.post(create_test_model("t4", Some(json!({"account": "a", "at": 2.5})))).unwrap();
+
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        let account_a = state.query_index("by_account_at", &[json!("a")], Direction::Asc, 10).unwrap();
+        assert_eq!(ids(account_a), vec!["t3", "t4", "t1"]);
+        let latest = state.query_index("by_account_at", &[json!("a")], Direction::Desc, 2).unwrap();
+        assert_eq!(ids(latest), vec!["t1", "t4"]);
+
+        // Updates move the entry, deletes remove it
+        state.put(create_test_model("t1", Some(json!({"account": "b", "at": 30})))).unwrap();
+        state.delete_by_id("t3").unwrap();
+        let account_a = state.query_index("by_account_at", &[json!("a")], Direction::Asc, 10).unwrap();
+        assert_eq!(ids(account_a), vec!["t4"]);
+        let account_b = state.query_index("by_account_at", &[json!("b"), json!(30)], Direction::Asc, 10).unwrap();
+        assert_eq!(ids(account_b), vec!["t1"]);
+
+        assert!(matches!(
+            state.query_index("missing", &[], Direction::Asc, 10),
+            Err(crate::app_response::AppResponse::NotFound(_))
+        ));
+
+        state.clear_all_records().unwrap();
+        assert!(state.query_index("by_account_at", &[], Direction::Asc, 10).unwrap().is_empty());
+
+        assert_eq!(state.list_indexes().unwrap().len(), 1);
+        assert!(state.drop_index("by_account_at").unwrap());
+        assert!(!state.drop_index("by_account_at").unwrap());
+        assert!(state.list_indexes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_index_large_integers() {
+        use crate::local_db_model::Direction;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("index_large_integers")).unwrap();
+        // 2^53 + 1 and 2^53 round to the same f64, as do the values near u64::MAX
+        let values = [
+            ("n1", json!(-9007199254740993i64)),
+            ("n2", json!(-9007199254740992i64)),
+            ("n3", json!(9007199254740992u64)),
+            ("n4", json!(9007199254740993u64)),
+            ("n5", json!(u64::MAX - 1)),
+            ("n6", json!(u64::MAX)),
+        ];
+        for (id, value) in values.iter().rev() {
+            state.post(create_test_model(id, Some(json!({"n": value})))).unwrap();
+        }
+        state.post(create_test_model("f1", Some(json!({"n": 1.0})))).unwrap();
+        state.post(create_test_model("i1", Some(json!({"n": 1})))).unwrap();
+        state.create_index("by_n", &["data.n".to_string()]).unwrap();
+
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        let ordered = state.query_index("by_n", &[], Direction::Asc, 10).unwrap();
+        assert_eq!(ids(ordered), vec!["n1", "n2", "f1", "i1", "n3", "n4", "n5", "n6"]);
+
+        for (id, value) in &values {
+            assert_eq!(ids(state.get_by_indexed_value("by_n", value).unwrap()), vec![*id]);
+        }
+        assert_eq!(ids(state.get_by_indexed_value("by_n", &json!(1)).unwrap()), vec!["f1", "i1"]);
+    }
+
+    #[test]
+    fn test_index_long_strings() {
+        use crate::local_db_model::Direction;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("index_long_strings")).unwrap();
+        state.create_index("by_title", &["data.title".to_string(), "data.rank".to_string()]).unwrap();
+
+        // Both are past the LMDB key limit and share their first 600 bytes
+        let common = "x".repeat(600);
+        let long_a = format!("{common}a");
+        let long_b = format!("{common}b");
+        state.post(create_test_model("l1", Some(json!({"title": long_a, "rank": 2})))).unwrap();
+        state.post(create_test_model("l2", Some(json!({"title": long_b, "rank": 1})))).unwrap();
+        state.post(create_test_model("l3", Some(json!({"title": long_a, "rank": 1})))).unwrap();
+        state.post(create_test_model("s1", Some(json!({"title": "short", "rank": 1})))).unwrap();
+
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids(state.get_by_indexed_value("by_title", &json!(long_a)).unwrap()), vec!["l3", "l1"]);
+        assert_eq!(ids(state.get_by_indexed_value("by_title", &json!(long_b)).unwrap()), vec!["l2"]);
+        assert!(state.get_by_indexed_value("by_title", &json!(common)).unwrap().is_empty());
+        let first = state.query_index("by_title", &[json!(long_a), json!(2)], Direction::Asc, 10).unwrap();
+        assert_eq!(ids(first), vec!["l1"]);
+        assert_eq!(state.query_index("by_title", &[], Direction::Asc, 10).unwrap().len(), 4);
+
+        // Existing long values do not keep an index from being created
+        assert_eq!(state.create_index("by_title_only", &["data.title".to_string()]).unwrap(), 4);
+
+        // Lookups by a long hash fall back to a scan
+        let long_hash = "h".repeat(700);
+        state.set_hash_index(true).unwrap();
+        let mut model = create_test_model("l4", None);
+        model.hash = long_hash.clone();
+        state.post(model).unwrap();
+        assert_eq!(state.find_by_hash(&long_hash).unwrap(), vec!["l4"]);
+    }
+
+    #[test]
+    fn test_ffi_compound_index() {
+        use crate::{create_db, create_index, list_indexes, post_data, query_index};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_compound_index")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, status) in [("x1", "open"), ("x2", "done"), ("x3", "open")] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{"status":"{status}"}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let name = CString::new("by_status").unwrap();
+        let paths = CString::new(r#"["data.status"]"#).unwrap();
+        let result = unsafe { CString::from_raw(create_index(db_ptr, name.as_ptr(), paths.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"3"}"#);
+
+        let result = unsafe { CString::from_raw(list_indexes(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("by_status"));
+
+        let values = CString::new(r#"["open"]"#).unwrap();
+        let result = unsafe { CString::from_raw(query_index(db_ptr, name.as_ptr(), values.as_ptr(), 10, true) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let records: Vec<LocalDbModel> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(records.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["x3", "x1"]);
+
+        let invalid = CString::new("not json").unwrap();
+        let result = unsafe { CString::from_raw(query_index(db_ptr, name.as_ptr(), invalid.as_ptr(), 10, false) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_number_policy() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::NumberPolicy;
+
+        let json = r#"{"id":"n1","hash":"h","data":{"amount":9007199254740993,"items":[{"q":-9007199254740993}],"ok":12.5,"huge":123456789012345678901234}}"#;
+        let model = || serde_json::from_str::<LocalDbModel>(json).unwrap();
+
+        let mut state = AppDbState::init(generate_unique_db_name("number_policy")).unwrap();
+        assert_eq!(state.number_policy(), NumberPolicy::Preserve);
+        let stored = state.post(model()).unwrap();
+        assert_eq!(stored.data["amount"].as_u64(), Some(9_007_199_254_740_993));
+
+        state.set_number_policy(NumberPolicy::Reject);
+        match state.put(model()) {
+            Err(AppResponse::ValidationError(message)) => assert!(message.contains("data.amount"), "{message}"),
+            other => panic!("Expected a validation error, got {other:?}"),
+        }
+        let mut nested = model();
+        nested.data["huge"] = serde_json::json!(2);
+        nested.data["amount"] = serde_json::json!(1);
+        match state.post(nested) {
+            Err(AppResponse::ValidationError(message)) => assert!(message.contains("data.items.0.q"), "{message}"),
+            other => panic!("Expected a validation error, got {other:?}"),
+        }
+
+        state.set_number_policy(NumberPolicy::Stringify);
+        let stored = state.put(model()).unwrap().unwrap();
+        assert_eq!(stored.data["amount"], "9007199254740993");
+        assert_eq!(stored.data["items"][0]["q"], "-9007199254740993");
+        assert_eq!(stored.data["ok"], 12.5);
+        assert!(stored.data["huge"].is_string());
+        assert_eq!(state.get_by_id("n1").unwrap().unwrap().data, stored.data);
+
+        state.set_number_policy(NumberPolicy::Lossy);
+        let stored = state.post(model()).unwrap();
+        assert_eq!(stored.data["amount"].as_f64(), Some(9_007_199_254_740_992.0));
+        assert!(stored.data["amount"].as_u64().is_none());
+    }
+
+    #[test]
+    fn test_ffi_set_number_policy() {
+        use crate::{create_db, post_data, set_number_policy};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_number_policy")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let policy = CString::new("reject").unwrap();
+        let result = unsafe { CString::from_raw(set_number_policy(db_ptr, policy.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"reject"}"#);
+
+        let json = CString::new(r#"{"id":"big","hash":"h","data":{"cents":18446744073709551615}}"#).unwrap();
+        let result = unsafe { CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("ValidationError"));
+
+        let policy = CString::new("round").unwrap();
+        let result = unsafe { CString::from_raw(set_number_policy(db_ptr, policy.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_query_filter() {
+        use crate::query::PathFilter;
+
+        let state = AppDbState::init(generate_unique_db_name("query_filter")).unwrap();
+        state.post(create_test_model("q1", Some(serde_json::json!({"status": "pending", "owner": {"id": 1}})))).unwrap();
+        state.post(create_test_model("q2", Some(serde_json::json!({"status": "done", "owner": {"id": 1}})))).unwrap();
+        state.post(create_test_model("q3", Some(serde_json::json!({"status": "pending", "owner": {"id": 2}})))).unwrap();
+
+        let filter: PathFilter = serde_json::from_str(r#"{"data.status": "pending"}"#).unwrap();
+        let ids: Vec<String> = state.query(&filter).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec!["q1", "q3"]);
+
+        let filter: PathFilter = serde_json::from_str(r#"{"data.status": "pending", "data.owner.id": 2}"#).unwrap();
+        let ids: Vec<String> = state.query(&filter).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec!["q3"]);
+
+        assert_eq!(state.query(&PathFilter::default()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_query_operators() {
+        use crate::query::PathFilter;
+
+        let state = AppDbState::init(generate_unique_db_name("query_operators")).unwrap();
+        state.post(create_test_model("o1", Some(serde_json::json!({"amount": 50, "status": "a", "tags": ["x"], "note": "urgent call"})))).unwrap();
+        state.post(create_test_model("o2", Some(serde_json::json!({"amount": 150.5, "status": "b", "tags": ["y"]})))).unwrap();
+        state.post(create_test_model("o3", Some(serde_json::json!({"amount": 300, "status": "c", "tags": ["x", "y"]})))).unwrap();
+        state.post(create_test_model("o4", Some(serde_json::json!({"amount": "n/a", "status": "a"})))).unwrap();
+
+        let ids = |filter: &str| -> Vec<String> {
+            let filter: PathFilter = serde_json::from_str(filter).unwrap();
+            state.query(&filter).unwrap().into_iter().map(|m| m.id).collect()
+        };
+
+        assert_eq!(ids(r#"{"data.amount": {"$gt": 100}, "data.status": {"$in": ["a", "b"]}}"#), vec!["o2"]);
+        assert_eq!(ids(r#"{"data.amount": {"$gte": 50, "$lt": 300}}"#), vec!["o1", "o2"]);
+        assert_eq!(ids(r#"{"data.amount": {"$lte": 50}}"#), vec!["o1"]);
+        assert_eq!(ids(r#"{"data.status": {"$ne": "a"}}"#), vec!["o2", "o3"]);
+        assert_eq!(ids(r#"{"data.note": {"$ne": "urgent call"}}"#), vec!["o2", "o3", "o4"]);
+        assert_eq!(ids(r#"{"data.tags": {"$contains": "y"}}"#), vec!["o2", "o3"]);
+        assert_eq!(ids(r#"{"data.note": {"$contains": "urgent"}}"#), vec!["o1"]);
+        assert_eq!(ids(r#"{"data.status": {"$gt": "a"}}"#), vec!["o2", "o3"]);
+
+        assert!(serde_json::from_str::<PathFilter>(r#"{"data.amount": {"$between": [1, 2]}}"#).is_err());
+        assert!(serde_json::from_str::<PathFilter>(r#"{"data.status": {"$in": "a"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_ffi_query() {
+        use crate::{create_db, post_data, query};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_query")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, status) in [("f1", "pending"), ("f2", "done")] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{"status":"{status}"}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let filter = CString::new(r#"{"data.status":"pending"}"#).unwrap();
+        let result = unsafe { CString::from_raw(query(db_ptr, filter.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let records: Vec<LocalDbModel> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "f1");
+
+        let filter = CString::new(r#"{"data.status":{"$ne":"pending"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(query(db_ptr, filter.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("f2"));
+
+        let unknown = CString::new(r#"{"data.status":{"$regex":"p.*"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(query(db_ptr, unknown.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Unknown operator $regex"));
+
+        let invalid = CString::new(r#"["data.status"]"#).unwrap();
+        let result = unsafe { CString::from_raw(query(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(query(db_ptr, std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_overflow_large_fields() {
+        use crate::overflow::{CHUNKS_DB_NAME, OVERFLOW_CHUNK_SIZE};
+        use crate::query::PathFilter;
+        use crate::value_codec::{json_payload, split_value};
+        use lmdb::{Cursor, Transaction};
+
+        let mut state = AppDbState::init(generate_unique_db_name("overflow")).unwrap();
+        state.set_overflow_threshold(Some(4096));
+
+        let blob = "x".repeat(OVERFLOW_CHUNK_SIZE * 2 + 10);
+        let model = create_test_model("doc", Some(serde_json::json!({"title": "big", "blob": blob.clone()})));
+        state.post(model).unwrap();
+
+        let count_chunks = |state: &AppDbState| {
+            let (env, chunks_db) = state.side_db(CHUNKS_DB_NAME).unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let mut cursor = txn.open_ro_cursor(chunks_db).unwrap();
+            cursor.iter().count()
+        };
+        assert_eq!(count_chunks(&state), 3);
+
+        {
+            let (env, db) = state.env_db().unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let value = txn.get(db, &"doc").unwrap();
+            assert!(split_value(value).unwrap().0.overflowed);
+            let stored = json_payload(value).unwrap();
+            assert!(stored.len() < 4096);
+            assert!(stored.contains("$overflow"));
+        }
+
+        let fetched = state.get_by_id("doc").unwrap().unwrap();
+        assert_eq!(fetched.data["blob"], blob);
+        assert_eq!(fetched.data["title"], "big");
+        assert_eq!(state.get().unwrap()[0].data["blob"], blob);
+        let filter: PathFilter = serde_json::from_str(r#"{"data.title": "big"}"#).unwrap();
+        assert_eq!(state.query(&filter).unwrap()[0].data["blob"], blob);
+
+        // Shrinking the record below the threshold drops its chunks
+        state.put(create_test_model("doc", Some(serde_json::json!({"title": "small"})))).unwrap();
+        assert_eq!(count_chunks(&state), 0);
+        assert_eq!(state.get_by_id("doc").unwrap().unwrap().data["title"], "small");
+
+        state.post(create_test_model("doc2", Some(serde_json::json!({"blob": blob.clone()})))).unwrap();
+        assert_eq!(count_chunks(&state), 3);
+        assert!(state.delete_by_id("doc2").unwrap());
+        assert_eq!(count_chunks(&state), 0);
+
+        state.post(create_test_model("doc3", Some(serde_json::json!({"blob": blob})))).unwrap();
+        state.clear_all_records().unwrap();
+        assert_eq!(count_chunks(&state), 0);
+
+        // Data that merely looks like a stub is read back as written
+        let lookalike = serde_json::json!({"attachment": {"$overflow": {"bytes": 10, "chunks": 1}}});
+        state.post(create_test_model("plain", Some(lookalike.clone()))).unwrap();
+        assert_eq!(state.get_by_id("plain").unwrap().unwrap().data, lookalike);
+        assert_eq!(state.query(&PathFilter::default()).unwrap()[0].data, lookalike);
+        assert_eq!(state.get_field("plain", "/attachment").unwrap().unwrap(), lookalike["attachment"]);
+
+        // Without a threshold records are stored whole
+        state.set_overflow_threshold(None);
+        state.post(create_test_model("doc4", Some(serde_json::json!({"blob": "y".repeat(10_000)})))).unwrap();
+        assert_eq!(count_chunks(&state), 0);
+    }
+
+    #[test]
+    fn test_ffi_set_overflow_threshold() {
+        use crate::{create_db, get_by_id, post_data, set_overflow_threshold};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_overflow")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_overflow_threshold(db_ptr, 1024) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1024"}"#);
+        assert_eq!(crate::registry::get(db_ptr).unwrap().read().unwrap().overflow_threshold(), Some(1024));
+
+        let blob = "z".repeat(5000);
+        let json = CString::new(format!(r#"{{"id":"big","hash":"h","data":{{"blob":"{blob}"}}}}"#)).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("big").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(&blob));
+
+        let result = unsafe { CString::from_raw(set_overflow_threshold(db_ptr, 0) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+        assert_eq!(crate::registry::get(db_ptr).unwrap().read().unwrap().overflow_threshold(), None);
+
+        let result = unsafe { CString::from_raw(set_overflow_threshold(0, 1024) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_startup_report_after_unclean_shutdown() {
+        let name = generate_unique_db_name("startup_check");
+
+        let mut state = AppDbState::init(name.clone()).unwrap();
+        assert!(!state.startup_report().recovered);
+        state.post(create_test_model("s1", None)).unwrap();
+        state.close_database().unwrap();
+
+        let state = AppDbState::init(name.clone()).unwrap();
+        assert!(!state.startup_report().recovered);
+        drop(state);
+
+        simulate_unclean_shutdown(&format!("{name}.lmdb"));
+        let state = AppDbState::init(name.clone()).unwrap();
+        let report = state.startup_report();
+        assert!(report.recovered);
+        assert!(report.last_page_ok);
+        assert_eq!(report.stale_readers_cleared, 0);
+        assert!(state.get_by_id("s1").unwrap().is_some());
+        drop(state);
+
+        let state = AppDbState::init(name).unwrap();
+        assert!(!state.startup_report().recovered);
+    }
+
+    #[test]
+    fn test_ffi_get_startup_report() {
+        use crate::{close_database, create_db, get_startup_report};
+
+        let name = generate_unique_db_name("ffi_startup_report");
+        let db_name = CString::new(name.clone()).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_startup_report(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(report["recovered"], false);
+
+        unsafe { let _ = CString::from_raw(close_database(db_ptr) as *mut i8); }
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+
+        // create_db probes existing databases before reopening them; the
+        // report must still reflect the crash
+        simulate_unclean_shutdown(&format!("{name}.lmdb"));
+        let db_ptr = create_db(db_name.as_ptr());
+        let result = unsafe { CString::from_raw(get_startup_report(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"recovered\":true"#));
+
+        let result = unsafe { CString::from_raw(get_startup_report(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_run_maintenance_compacts_fragmented_database() {
+        use crate::local_db_model::{CompactionPolicy, Direction};
+
+        let mut state = AppDbState::init(generate_unique_db_name("maintenance")).unwrap();
+        state.create_index("by_n", &["data.n".to_string()]).unwrap();
+        for i in 0..2000 {
+            let data = serde_json::json!({"n": i, "body": "x".repeat(1000)});
+            state.post(create_test_model(&format!("m{i:04}"), Some(data))).unwrap();
+        }
+        let ids: Vec<String> = (0..1900).map(|i| format!("m{i:04}")).collect();
+        state.delete_many(&ids).unwrap();
+
+        state.set_compaction_policy(CompactionPolicy { min_file_bytes: 0, ..CompactionPolicy::default() });
+
+        let report = state.run_maintenance(true, false).unwrap();
+        assert!(report.free_ratio > 0.5, "free ratio {}", report.free_ratio);
+        assert!(report.free_pages > 0);
+        assert_eq!(report.skipped.as_deref(), Some("Device is not charging"));
+        assert!(report.compaction.is_none());
+
+        let report = state.run_maintenance(true, true).unwrap();
+        let compaction = report.compaction.unwrap();
+        assert!(report.skipped.is_none());
+        assert!(compaction.bytes_after < compaction.bytes_before / 2);
+
+        // Records, indexes and writes keep working on the compacted file
+        assert_eq!(state.get().unwrap().len(), 100);
+        let found = state.query_index("by_n", &[serde_json::json!(1999)], Direction::Asc, 10).unwrap();
+        assert_eq!(found[0].id, "m1999");
+        state.post(create_test_model("after", None)).unwrap();
+        assert!(state.get_by_id("after").unwrap().is_some());
+
+        let report = state.run_maintenance(true, true).unwrap();
+        assert!(report.compaction.is_none());
+        assert!(report.skipped.unwrap().contains("Free page ratio"));
+    }
+
+    #[test]
+    fn test_ffi_maintenance() {
+        use crate::{compact, create_db, post_data, run_maintenance, set_compaction_policy};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_maintenance")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"c1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let policy = CString::new(r#"{"require_idle": false}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_compaction_policy(db_ptr, policy.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let policy: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(policy["require_idle"], false);
+        assert_eq!(policy["require_charging"], true);
+
+        let result = unsafe { CString::from_raw(run_maintenance(db_ptr, false, true) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert!(report["compaction"].is_null());
+        assert!(report["skipped"].is_string());
+
+        let result = unsafe { CString::from_raw(compact(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("bytes_after"));
+        assert!(crate::registry::get(db_ptr).unwrap().read().unwrap().get_by_id("c1").unwrap().is_some());
+
+        let invalid = CString::new(r#"{"min_free_ratio": "high"}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_compaction_policy(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(run_maintenance(0, true, true) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_query_sorted() {
+        use crate::query::{PathFilter, SortSpec};
+
+        let state = AppDbState::init(generate_unique_db_name("query_sorted")).unwrap();
+        state.post(create_test_model("a", Some(serde_json::json!({"created_at": 30, "status": "open"})))).unwrap();
+        state.post(create_test_model("b", Some(serde_json::json!({"created_at": 10, "status": "open"})))).unwrap();
+        state.post(create_test_model("c", Some(serde_json::json!({"created_at": 20, "status": "done"})))).unwrap();
+        state.post(create_test_model("d", Some(serde_json::json!({"status": "open"})))).unwrap();
+        state.post(create_test_model("e", Some(serde_json::json!({"created_at": 10, "status": "open"})))).unwrap();
+
+        let ids = |sort: &str, filter: &str| -> Vec<String> {
+            let sort: SortSpec = serde_json::from_str(sort).unwrap();
+            let filter: PathFilter = serde_json::from_str(filter).unwrap();
+            state.query_sorted(&filter, &sort).unwrap().into_iter().map(|m| m.id).collect()
+        };
+
+        // Missing values sort first, ties keep key order
+        assert_eq!(ids(r#"{"by": "data.created_at"}"#, "{}"), vec!["d", "b", "e", "c", "a"]);
+        assert_eq!(ids(r#"{"by": "data.created_at", "order": "desc"}"#, "{}"), vec!["a", "c", "b", "e", "d"]);
+        assert_eq!(ids(r#"{"by": "data.created_at", "order": "desc"}"#, r#"{"data.status": "open"}"#), vec!["a", "b", "e", "d"]);
+        assert_eq!(ids(r#"{"by": "id", "order": "desc"}"#, "{}"), vec!["e", "d", "c", "b", "a"]);
+
+        assert!(serde_json::from_str::<SortSpec>(r#"{"by": "data.x", "order": "sideways"}"#).is_err());
+    }
+
+    #[test]
+    fn test_ffi_sorted_queries() {
+        use crate::{create_db, get_all_sorted, post_data, query_sorted};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sorted")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, name, kind) in [("1", "carol", "x"), ("2", "alice", "y"), ("3", "bob", "x")] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{"name":"{name}","kind":"{kind}"}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let records_ids = |result: CString| -> Vec<String> {
+            let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+            let records: Vec<LocalDbModel> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+            records.into_iter().map(|m| m.id).collect()
+        };
+
+        let sort = CString::new(r#"{"by":"data.name"}"#).unwrap();
+        let result = unsafe { CString::from_raw(get_all_sorted(db_ptr, sort.as_ptr()) as *mut i8) };
+        assert_eq!(records_ids(result), vec!["2", "3", "1"]);
+
+        let filter = CString::new(r#"{"data.kind":"x"}"#).unwrap();
+        let sort = CString::new(r#"{"by":"data.name","order":"desc"}"#).unwrap();
+        let result = unsafe { CString::from_raw(query_sorted(db_ptr, filter.as_ptr(), sort.as_ptr()) as *mut i8) };
+        assert_eq!(records_ids(result), vec!["1", "3"]);
+
+        let invalid = CString::new(r#"{"order":"desc"}"#).unwrap();
+        let result = unsafe { CString::from_raw(get_all_sorted(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(get_all_sorted(0, sort.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_query_projected() {
+        use crate::query::PathFilter;
+
+        let mut state = AppDbState::init(generate_unique_db_name("query_projected")).unwrap();
+        state.post(create_test_model("p1", Some(serde_json::json!({"title": "One", "body": "long text", "meta": {"tags": ["a"], "views": 3}})))).unwrap();
+        state.post(create_test_model("p2", Some(serde_json::json!({"title": "Two", "body": "more text"})))).unwrap();
+
+        let fields = vec!["id".to_string(), "data.title".to_string(), "data.meta.views".to_string()];
+        let rows = state.query_projected(&PathFilter::default(), &fields).unwrap();
+        assert_eq!(rows, vec![
+            serde_json::json!({"id": "p1", "data": {"title": "One", "meta": {"views": 3}}}),
+            serde_json::json!({"id": "p2", "data": {"title": "Two"}}),
+        ]);
+
+        let filter: PathFilter = serde_json::from_str(r#"{"data.title": "Two"}"#).unwrap();
+        let rows = state.query_projected(&filter, &["data.body".to_string()]).unwrap();
+        assert_eq!(rows, vec![serde_json::json!({"data": {"body": "more text"}})]);
+
+        // Overflowed fields are projected with their full value
+        state.set_overflow_threshold(Some(1024));
+        let blob = "b".repeat(4096);
+        state.post(create_test_model("p3", Some(serde_json::json!({"title": "Three", "body": blob.clone()})))).unwrap();
+        let filter: PathFilter = serde_json::from_str(r#"{"data.title": "Three"}"#).unwrap();
+        let rows = state.query_projected(&filter, &["data.body".to_string()]).unwrap();
+        assert_eq!(rows[0]["data"]["body"], blob);
+    }
+
+    #[test]
+    fn test_ffi_query_projected() {
+        use crate::{create_db, post_data, query_projected};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_query_projected")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h","data":{"title":"Groceries","items":["milk"]}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let filter = CString::new("{}").unwrap();
+        let fields = CString::new(r#"["id","data.title"]"#).unwrap();
+        let result = unsafe { CString::from_raw(query_projected(db_ptr, filter.as_ptr(), fields.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"[{"data":{"title":"Groceries"},"id":"n1"}]"#);
+
+        let invalid = CString::new(r#"{"id": true}"#).unwrap();
+        let result = unsafe { CString::from_raw(query_projected(db_ptr, filter.as_ptr(), invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Expected a JSON array of paths"));
+
+        let result = unsafe { CString::from_raw(query_projected(0, filter.as_ptr(), fields.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_write_rate_limit() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::WriteRateLimit;
+
+        let mut state = AppDbState::init(generate_unique_db_name("write_rate_limit")).unwrap();
+        state.set_write_rate_limit(Some(WriteRateLimit { ops_per_sec: 10.0, burst: 3 })).unwrap();
+
+        for i in 0..3 {
+            state.post(create_test_model(&format!("w{i}"), None)).unwrap();
+        }
+        match state.post(create_test_model("w3", None)) {
+            Err(AppResponse::Busy { retry_after_ms, .. }) => assert!(retry_after_ms > 0 && retry_after_ms <= 100),
+            other => panic!("Expected Busy, got {other:?}"),
+        }
+        assert!(matches!(state.delete_by_id("w0"), Err(AppResponse::Busy { .. })));
+        assert!(state.get_by_id("w3").unwrap().is_none());
+
+        // Reads are never throttled, and tokens come back at the configured rate
+        assert_eq!(state.get().unwrap().len(), 3);
+        thread::sleep(std::time::Duration::from_millis(120));
+        state.put(create_test_model("w0", None)).unwrap();
+
+        assert!(state.set_write_rate_limit(Some(WriteRateLimit { ops_per_sec: 0.0, burst: 1 })).is_err());
+        assert!(state.set_write_rate_limit(Some(WriteRateLimit { ops_per_sec: 1.0, burst: 0 })).is_err());
+
+        state.set_write_rate_limit(None).unwrap();
+        for i in 0..20 {
+            state.post(create_test_model(&format!("u{i}"), None)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ffi_set_write_rate_limit() {
+        use crate::{create_db, post_data, set_write_rate_limit};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_write_rate_limit")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_write_rate_limit(db_ptr, 1.0, 1) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"ops_per_sec":1.0,"burst":1}"#);
+
+        let json = CString::new(r#"{"id":"r1","hash":"h","data":{}}"#).unwrap();
+        let result = unsafe { CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let result = unsafe { CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert!(response["Busy"]["retry_after_ms"].as_u64().unwrap() > 0);
+
+        let result = unsafe { CString::from_raw(set_write_rate_limit(db_ptr, -1.0, 1) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(set_write_rate_limit(db_ptr, 0.0, 0) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"null"}"#);
+        let result = unsafe { CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        use crate::local_db_model::AggregateSpec;
+
+        let state = AppDbState::init(generate_unique_db_name("aggregate")).unwrap();
+        state.post(create_test_model("o1", Some(serde_json::json!({"status": "paid", "amount": 10})))).unwrap();
+        state.post(create_test_model("o2", Some(serde_json::json!({"status": "paid", "amount": 15.5})))).unwrap();
+        state.post(create_test_model("o3", Some(serde_json::json!({"status": "paid", "amount": "n/a"})))).unwrap();
+        state.post(create_test_model("o4", Some(serde_json::json!({"status": "open", "amount": 100})))).unwrap();
+
+        let spec: AggregateSpec = serde_json::from_value(serde_json::json!({
+            "filter": {"data.status": "paid"},
+            "aggregations": [
+                {"op": "count"},
+                {"op": "count", "path": "data.amount"},
+                {"op": "sum", "path": "data.amount"},
+                {"op": "min", "path": "data.amount"},
+                {"op": "max", "path": "data.amount"},
+                {"op": "avg", "path": "data.amount"},
+                {"op": "avg", "path": "data.missing"}
+            ]
+        }))
+        .unwrap();
+        let result = state.aggregate(&spec).unwrap();
+
+        // Non-numeric values match the filter but are not aggregated
+        assert_eq!(result.matched, 3);
+        assert_eq!(result.values["count"], Some(3.0));
+        assert_eq!(result.values["count(data.amount)"], Some(2.0));
+        assert_eq!(result.values["sum(data.amount)"], Some(25.5));
+        assert_eq!(result.values["min(data.amount)"], Some(10.0));
+        assert_eq!(result.values["max(data.amount)"], Some(15.5));
+        assert_eq!(result.values["avg(data.amount)"], Some(12.75));
+        assert_eq!(result.values["avg(data.missing)"], None);
+
+        let everything: AggregateSpec = serde_json::from_str(r#"{"aggregations":[{"op":"sum","path":"data.amount"}]}"#).unwrap();
+        assert_eq!(state.aggregate(&everything).unwrap().values["sum(data.amount)"], Some(125.5));
+
+        let missing_path: AggregateSpec = serde_json::from_str(r#"{"aggregations":[{"op":"max"}]}"#).unwrap();
+        assert!(state.aggregate(&missing_path).is_err());
+    }
+
+    #[test]
+    fn test_ffi_aggregate() {
+        use crate::{aggregate, create_db, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_aggregate")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, amount) in [("o1", 4), ("o2", 8)] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{"amount":{amount}}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let spec = CString::new(r#"{"aggregations":[{"op":"count"},{"op":"avg","path":"data.amount"}]}"#).unwrap();
+        let result = unsafe { CString::from_raw(aggregate(db_ptr, spec.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"matched":2,"values":{"avg(data.amount)":6.0,"count":2.0}}"#);
+
+        let invalid = CString::new(r#"{"aggregations":[{"op":"median","path":"data.amount"}]}"#).unwrap();
+        let result = unsafe { CString::from_raw(aggregate(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(aggregate(0, spec.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_commit_sequence_read_your_writes() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+
+        let state = AppDbState::init(generate_unique_db_name("commit_sequence")).unwrap();
+        assert_eq!(state.commit_sequence().unwrap(), 0);
+
+        state.post(create_test_model("s1", None)).unwrap();
+        state.post(create_test_model("s2", None)).unwrap();
+        assert_eq!(state.commit_sequence().unwrap(), 2);
+
+        // One sequence per transaction; an update of a missing record commits nothing
+        state.delete_many(&["s1".to_string(), "s2".to_string()]).unwrap();
+        assert!(state.put(create_test_model("missing", None)).unwrap().is_none());
+        assert_eq!(state.commit_sequence().unwrap(), 3);
+
+        let token = state.commit_sequence().unwrap() + 1;
+        match state.get_by_id_at("s3", token) {
+            Err(AppResponse::Busy { .. }) => {}
+            other => panic!("Expected Busy, got {other:?}"),
+        }
+
+        // A reader waiting for a token sees the write that produced it
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| state.get_by_id_at("s3", token));
+            thread::sleep(std::time::Duration::from_millis(50));
+            state.post(create_test_model("s3", None)).unwrap();
+            assert!(reader.join().unwrap().unwrap().is_some());
+        });
+
+        assert_eq!(state.query_at(&PathFilter::default(), token).unwrap().len(), 1);
+        assert_eq!(state.get_by_id_at("s3", 0).unwrap().unwrap().id, "s3");
+    }
+
+    #[test]
+    fn test_ffi_commit_sequence() {
+        use crate::{create_db, get_by_id_at, get_commit_sequence, post_data, query_at};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_commit_sequence")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(get_commit_sequence(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let id = CString::new("n1").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id_at(db_ptr, id.as_ptr(), 1) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("n1"));
+
+        let filter = CString::new("{}").unwrap();
+        let result = unsafe { CString::from_raw(query_at(db_ptr, filter.as_ptr(), 2) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Busy"));
+
+        let result = unsafe { CString::from_raw(get_commit_sequence(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_distinct() {
+        let state = AppDbState::init(generate_unique_db_name("distinct")).unwrap();
+        state.post(create_test_model("p1", Some(serde_json::json!({"category": "garden", "tags": ["new", "sale"]})))).unwrap();
+        state.post(create_test_model("p2", Some(serde_json::json!({"category": "books", "tags": ["sale"]})))).unwrap();
+        state.post(create_test_model("p3", Some(serde_json::json!({"category": "garden", "tags": []})))).unwrap();
+        state.post(create_test_model("p4", Some(serde_json::json!({"category": 7})))).unwrap();
+
+        assert_eq!(state.distinct("data.category").unwrap(), vec![serde_json::json!(7), serde_json::json!("books"), serde_json::json!("garden")]);
+        assert_eq!(state.distinct("data.tags").unwrap(), vec![serde_json::json!("new"), serde_json::json!("sale")]);
+        assert!(state.distinct("data.missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_distinct() {
+        use crate::{create_db, distinct, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_distinct")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, category) in [("p1", "garden"), ("p2", "books"), ("p3", "garden")] {
+            let json = CString::new(format!(r#"{{"id":"{id}","hash":"h","data":{{"category":"{category}"}}}}"#)).unwrap();
+            unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let path = CString::new("data.category").unwrap();
+        let result = unsafe { CString::from_raw(distinct(db_ptr, path.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"["books","garden"]"#);
+
+        let result = unsafe { CString::from_raw(distinct(0, path.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_groups() {
+        let state = AppDbState::init(generate_unique_db_name("get_groups")).unwrap();
+        for id in ["todo:1", "todo:2", "project:1", "note:1"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+
+        let prefixes = vec!["todo:".to_string(), "project:".to_string(), "tag:".to_string(), "".to_string()];
+        let groups = state.get_groups(&prefixes).unwrap();
+
+        assert_eq!(groups.len(), 4);
+        let todo_ids: Vec<&str> = groups["todo:"].iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(todo_ids, vec!["todo:1", "todo:2"]);
+        assert_eq!(groups["project:"].len(), 1);
+        assert!(groups["tag:"].is_empty());
+        assert_eq!(groups[""].len(), 4);
+    }
+
+    #[test]
+    fn test_ffi_get_groups() {
+        use crate::{create_db, get_groups, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_get_groups")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"todo:1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let prefixes = CString::new(r#"["todo:","project:"]"#).unwrap();
+        let result = unsafe { CString::from_raw(get_groups(db_ptr, prefixes.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let groups: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(groups["todo:"][0]["id"], "todo:1");
+        assert_eq!(groups["project:"], serde_json::json!([]));
+
+        let invalid = CString::new(r#"{"todo:": 1}"#).unwrap();
+        let result = unsafe { CString::from_raw(get_groups(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Expected a JSON array of prefixes"));
+
+        let result = unsafe { CString::from_raw(get_groups(0, prefixes.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_by_indexed_value() {
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("indexed_value")).unwrap();
+        state.post(create_test_model("n1", Some(json!({"slug": "groceries", "pinned": true})))).unwrap();
+        state.post(create_test_model("n2", Some(json!({"slug": "ideas", "pinned": true})))).unwrap();
+        state.create_index("by_slug", &["data.slug".to_string(), "data.pinned".to_string()]).unwrap();
+
+        let notes = state.get_by_indexed_value("by_slug", &json!("ideas")).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, "n2");
+        assert!(state.get_by_indexed_value("by_slug", &json!("missing")).unwrap().is_empty());
+        assert!(state.get_by_indexed_value("by_title", &json!("ideas")).is_err());
+    }
+
+    #[test]
+    fn test_ffi_get_by_indexed_value() {
+        use crate::{create_db, create_index, get_by_indexed_value, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_indexed_value")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h","data":{"slug":"groceries"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let name = CString::new("by_slug").unwrap();
+        let paths = CString::new(r#"["data.slug"]"#).unwrap();
+        unsafe { let _ = CString::from_raw(create_index(db_ptr, name.as_ptr(), paths.as_ptr()) as *mut i8); }
+
+        let slug = CString::new(r#""groceries""#).unwrap();
+        let result = unsafe { CString::from_raw(get_by_indexed_value(db_ptr, name.as_ptr(), slug.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let records: Vec<LocalDbModel> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(records[0].id, "n1");
+
+        let invalid = CString::new("groceries").unwrap();
+        let result = unsafe { CString::from_raw(get_by_indexed_value(db_ptr, name.as_ptr(), invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Expected a JSON value"));
+
+        let result = unsafe { CString::from_raw(get_by_indexed_value(0, name.as_ptr(), slug.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_expiry_sweeper() {
+        use crate::local_db_model::ExpirySweep;
+        use serde_json::json;
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let mut state = AppDbState::init(generate_unique_db_name("expiry_sweeper")).unwrap();
+        state.create_index("by_expiry", &["data.expires_at".to_string()]).unwrap();
+        for i in 0..5 {
+            state.post(create_test_model(&format!("old{i}"), Some(json!({"expires_at": now - 1000 + i})))).unwrap();
+        }
+        state.post(create_test_model("fresh", Some(json!({"expires_at": now + 3_600_000})))).unwrap();
+        state.post(create_test_model("forever", Some(json!({"expires_at": null})))).unwrap();
+        state.post(create_test_model("text", Some(json!({"expires_at": "soon"})))).unwrap();
+
+        // Three batches: 2 + 2 + 1
+        assert_eq!(state.sweep_expired("by_expiry", 2).unwrap(), 5);
+        assert_eq!(state.get_all_ids().unwrap(), vec!["forever", "fresh", "text"]);
+        assert!(state.sweep_expired("missing", 2).is_err());
+
+        let sweep = ExpirySweep { index: "by_expiry".to_string(), interval_ms: 20, batch_size: 100 };
+        assert!(state.start_expiry_sweeper(ExpirySweep { interval_ms: 0, ..sweep.clone() }).is_err());
+        assert!(state.start_expiry_sweeper(ExpirySweep { index: "missing".to_string(), ..sweep.clone() }).is_err());
+        state.start_expiry_sweeper(sweep.clone()).unwrap();
+        assert_eq!(state.expiry_sweeper(), Some(&sweep));
+
+        state.post(create_test_model("stale", Some(json!({"expires_at": now - 1})))).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while state.record_exists("stale").unwrap() {
+            assert!(std::time::Instant::now() < deadline, "sweeper did not delete the expired record");
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        // Compaction restarts the sweeper, closing stops it
+        state.compact().unwrap();
+        assert_eq!(state.expiry_sweeper(), Some(&sweep));
+        state.close_database().unwrap();
+        assert!(state.expiry_sweeper().is_none());
+    }
+
+    #[test]
+    fn test_ffi_expiry_sweeper() {
+        use crate::{create_db, create_index, start_expiry_sweeper, stop_expiry_sweeper};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_expiry_sweeper")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let config = CString::new(r#"{"index":"by_expiry"}"#).unwrap();
+        let result = unsafe { CString::from_raw(start_expiry_sweeper(db_ptr, config.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let name = CString::new("by_expiry").unwrap();
+        let paths = CString::new(r#"["data.expires_at"]"#).unwrap();
+        unsafe { let _ = CString::from_raw(create_index(db_ptr, name.as_ptr(), paths.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(start_expiry_sweeper(db_ptr, config.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"index":"by_expiry","interval_ms":60000,"batch_size":500}"#);
+
+        let result = unsafe { CString::from_raw(stop_expiry_sweeper(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(stop_expiry_sweeper(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"false"}"#);
+
+        let result = unsafe { CString::from_raw(start_expiry_sweeper(0, config.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_app_lifecycle() {
+        use crate::local_db_model::ExpirySweep;
+
+        let mut state = AppDbState::init(generate_unique_db_name("app_lifecycle")).unwrap();
+        state.create_index("by_expiry", &["data.expires_at".to_string()]).unwrap();
+        let sweep = ExpirySweep { index: "by_expiry".to_string(), interval_ms: 60_000, batch_size: 10 };
+        state.start_expiry_sweeper(sweep.clone()).unwrap();
+
+        state.enter_background().unwrap();
+        assert!(state.expiry_sweeper().is_none());
+        state.post(create_test_model("bg", None)).unwrap();
+        state.enter_background().unwrap();
+
+        state.enter_foreground().unwrap();
+        assert_eq!(state.expiry_sweeper(), Some(&sweep));
+        state.enter_foreground().unwrap();
+        assert_eq!(state.expiry_sweeper(), Some(&sweep));
+
+        // Without a sweeper the hooks only sync
+        state.stop_expiry_sweeper();
+        state.enter_background().unwrap();
+        state.enter_foreground().unwrap();
+        assert!(state.expiry_sweeper().is_none());
+    }
+
+    #[test]
+    fn test_ffi_app_lifecycle() {
+        use crate::{create_db, notify_app_background, notify_app_foreground};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_app_lifecycle")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(notify_app_background(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+        let result = unsafe { CString::from_raw(notify_app_foreground(db_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let result = unsafe { CString::from_raw(notify_app_background(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_watch_debounced_batches() {
+        use crate::local_db_model::ChangeBatch;
+        use std::sync::{Arc, Mutex};
+
+        let state = AppDbState::init(generate_unique_db_name("watch_batches")).unwrap();
+        let batches: Arc<Mutex<Vec<ChangeBatch>>> = Arc::default();
+        let received = Arc::clone(&batches);
+        let watch_id = state
+            .watch("todo:", std::time::Duration::from_millis(30), move |batch| received.lock().unwrap().push(batch.clone()))
+            .unwrap();
+
+        let wait_for = |count: usize| {
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            while batches.lock().unwrap().len() < count {
+                assert!(std::time::Instant::now() < deadline, "watch did not fire");
+                thread::sleep(std::time::Duration::from_millis(5));
+            }
+            thread::sleep(std::time::Duration::from_millis(50));
+            std::mem::take(&mut *batches.lock().unwrap())
+        };
+
+        for i in 0..100 {
+            state.post(create_test_model(&format!("todo:{i:03}"), None)).unwrap();
+        }
+        state.post(create_test_model("note:1", None)).unwrap();
+        let delivered = wait_for(1);
+        assert!(delivered.len() < 100, "{} batches for 100 writes", delivered.len());
+        let ids: std::collections::BTreeSet<String> = delivered.iter().flat_map(|batch| batch.ids.clone()).collect();
+        assert_eq!(ids.len(), 100);
+        assert!(delivered.iter().all(|batch| batch.watch_id == watch_id && !batch.cleared));
+
+        // One transaction, one batch; unknown IDs are not reported
+        let ids: Vec<String> = (0..50).map(|i| format!("todo:{i:03}")).chain(["todo:missing".to_string()]).collect();
+        state.delete_many(&ids).unwrap();
+        let delivered = wait_for(1);
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].ids.len(), 50);
+
+        state.clear_all_records().unwrap();
+        assert!(wait_for(1)[0].cleared);
+
+        assert!(state.unwatch(watch_id));
+        assert!(!state.unwatch(watch_id));
+        state.post(create_test_model("todo:late", None)).unwrap();
+        thread::sleep(std::time::Duration::from_millis(80));
+        assert!(batches.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_watch() {
+        use crate::{create_db, free_c_string, post_data, unwatch, watch};
+        use std::os::raw::c_char;
+        use std::sync::Mutex;
+
+        static BATCHES: Mutex<Vec<(u64, String)>> = Mutex::new(Vec::new());
+        extern "C" fn on_change(watch_id: u64, batch_json: *const c_char) {
+            let json = unsafe { std::ffi::CStr::from_ptr(batch_json) }.to_str().unwrap().to_string();
+            BATCHES.lock().unwrap().push((watch_id, json));
+            free_c_string(batch_json);
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_watch")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let prefix = CString::new("").unwrap();
+        let result = unsafe { CString::from_raw(watch(db_ptr, prefix.as_ptr(), 5, Some(on_change)) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let watch_id: u64 = response["Ok"].as_str().unwrap().parse().unwrap();
+
+        let json = CString::new(r#"{"id":"w1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while BATCHES.lock().unwrap().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "watch did not fire");
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let (id, batch) = BATCHES.lock().unwrap()[0].clone();
+        assert_eq!(id, watch_id);
+        let response: serde_json::Value = serde_json::from_str(&batch).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), format!(r#"{{"watch_id":{watch_id},"ids":["w1"],"cleared":false}}"#));
+
+        let result = unsafe { CString::from_raw(watch(db_ptr, prefix.as_ptr(), 5, None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(unwatch(db_ptr, watch_id) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    // ===============================
+    #[test]
+    fn test_cache_limit_eviction() {
+        use crate::local_db_model::CacheLimit;
+
+        let mut state = AppDbState::init(generate_unique_db_name("cache_limit")).unwrap();
+        for i in 0..5 {
+            state.post(create_test_model(&format!("old_{i}"), None)).unwrap();
+        }
+
+        assert!(state.set_cache_limit(Some(CacheLimit::default())).is_err());
+        assert!(state.set_cache_limit(Some(CacheLimit { max_records: Some(0), max_bytes: None })).is_err());
+
+        let limit = CacheLimit { max_records: Some(3), max_bytes: None };
+        assert_eq!(state.set_cache_limit(Some(limit)).unwrap(), 2);
+        assert_eq!(state.cache_limit(), Some(limit));
+        assert!(state.get_by_id("old_0").unwrap().is_none());
+        assert!(state.get_by_id("old_1").unwrap().is_none());
+
+        // Rewriting a record makes it the most recent one
+        state.put(create_test_model("old_2", Some(serde_json::json!({"v": 2})))).unwrap();
+        state.post(create_test_model("new_0", None)).unwrap();
+        assert!(state.get_by_id("old_3").unwrap().is_none());
+        assert!(state.get_by_id("old_2").unwrap().is_some());
+
+        // Records of one transaction are never evicted by it
+        let path = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("cache_batch")));
+        let path = path.to_str().unwrap();
+        let content: String = (0..4).map(|i| format!("{{\"id\":\"batch_{i}\",\"hash\":\"h\",\"data\":{{}}}}\n")).collect();
+        std::fs::write(path, content).unwrap();
+        assert_eq!(state.import_from_file(path, None).unwrap(), 4);
+        assert_eq!(state.get_all_ids().unwrap(), vec!["batch_0", "batch_1", "batch_2", "batch_3"]);
+        std::fs::remove_file(path).unwrap();
+
+        state.delete_by_id("batch_0").unwrap();
+        state.post(create_test_model("last", None)).unwrap();
+        assert_eq!(state.get_all_ids().unwrap(), vec!["batch_2", "batch_3", "last"]);
+
+        // Writes without a limit are picked up when it is set again
+        state.set_cache_limit(None).unwrap();
+        state.post(create_test_model("untracked", None)).unwrap();
+        let evicted = state.set_cache_limit(Some(CacheLimit { max_records: None, max_bytes: Some(1) })).unwrap();
+        assert_eq!(evicted, 4);
+        assert!(state.get().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_set_cache_limit() {
+        use crate::{create_db, push_data, set_cache_limit};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_cache_limit")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for i in 0..3 {
+            let json = CString::new(serde_json::to_string(&create_test_model(&format!("r{i}"), None)).unwrap()).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let limit = CString::new(r#"{"max_records":1}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_cache_limit(db_ptr, limit.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+
+        let none = CString::new("null").unwrap();
+        let result = unsafe { CString::from_raw(set_cache_limit(db_ptr, none.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+
+        let invalid = CString::new(r#"{"max_records":0}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_cache_limit(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let malformed = CString::new(r#"{"max_records":"many"}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_cache_limit(db_ptr, malformed.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(set_cache_limit(0, limit.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_delta_consumers() {
+        let state = AppDbState::init(generate_unique_db_name("delta_consumers")).unwrap();
+        state.post(create_test_model("a", None)).unwrap();
+        state.post(create_test_model("b", None)).unwrap();
+        assert!(state.get_all_delta("").is_err());
+
+        // First delta holds everything
+        let delta = state.get_all_delta("search").unwrap();
+        assert!(delta.reset);
+        assert_eq!(delta.records.len(), 2);
+        assert_eq!(delta.sequence, state.commit_sequence().unwrap());
+        state.ack_delta("search", delta.sequence).unwrap();
+        assert!(state.ack_delta("search", delta.sequence + 1).is_err());
+
+        state.put(create_test_model("a", Some(serde_json::json!({"v": 2})))).unwrap();
+        state.post(create_test_model("c", None)).unwrap();
+        state.delete_by_id("b").unwrap();
+
+        let delta = state.get_all_delta("search").unwrap();
+        assert!(!delta.reset);
+        let ids: Vec<&str> = delta.records.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(delta.records[0].data, serde_json::json!({"v": 2}));
+        assert_eq!(delta.deleted, vec!["b"]);
+
+        // Another consumer moves independently; unacknowledged changes repeat
+        let other = state.get_all_delta("badge").unwrap();
+        state.ack_delta("badge", other.sequence).unwrap();
+        assert!(state.get_all_delta("badge").unwrap().records.is_empty());
+        assert_eq!(state.get_all_delta("search").unwrap().records.len(), 2);
+
+        state.ack_delta("search", delta.sequence).unwrap();
+        state.ack_delta("search", 1).unwrap();
+        let delta = state.get_all_delta("search").unwrap();
+        assert!(delta.records.is_empty() && delta.deleted.is_empty());
+
+        state.clear_all_records().unwrap();
+        state.post(create_test_model("d", None)).unwrap();
+        let delta = state.get_all_delta("search").unwrap();
+        assert!(delta.reset);
+        assert_eq!(delta.records.len(), 1);
+
+        assert!(state.remove_delta_consumer("search").unwrap());
+        assert!(state.remove_delta_consumer("badge").unwrap());
+        assert!(!state.remove_delta_consumer("badge").unwrap());
+    }
+
+    #[test]
+    fn test_ffi_delta_consumers() {
+        use crate::{ack_delta, create_db, get_all_delta, push_data, remove_delta_consumer};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_delta_consumers")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let consumer = CString::new("search").unwrap();
+        let result = unsafe { CString::from_raw(get_all_delta(db_ptr, consumer.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let delta: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(delta["reset"], true);
+        assert_eq!(delta["records"][0]["id"], "a");
+
+        let sequence = delta["sequence"].as_u64().unwrap();
+        let result = unsafe { CString::from_raw(ack_delta(db_ptr, consumer.as_ptr(), sequence) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), format!(r#"{{"Ok":"{sequence}"}}"#));
+
+        let result = unsafe { CString::from_raw(get_all_delta(db_ptr, consumer.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"records\":[]"#));
+
+        let result = unsafe { CString::from_raw(remove_delta_consumer(db_ptr, consumer.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        let result = unsafe { CString::from_raw(get_all_delta(0, consumer.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_backfill_field() {
+        use crate::local_db_model::Backfill;
+
+        let state = AppDbState::init(generate_unique_db_name("backfill_field")).unwrap();
+        state.post(create_test_model("t1", Some(serde_json::json!({"type": "task"})))).unwrap();
+        state.post(create_test_model("t2", Some(serde_json::json!({"type": "task", "settings": {"priority": 5}})))).unwrap();
+        state.post(create_test_model("t3", Some(serde_json::json!({"type": "task", "settings": {"priority": null}})))).unwrap();
+        state.post(create_test_model("t4", Some(serde_json::json!({"type": "task", "settings": "legacy"})))).unwrap();
+        state.post(create_test_model("n1", Some(serde_json::json!({"type": "note"})))).unwrap();
+
+        let filter: crate::query::PathFilter = serde_json::from_value(serde_json::json!({"data.type": "task"})).unwrap();
+        let backfill = Backfill { field: "data.settings.priority".to_string(), value: serde_json::json!(0), filter, batch_size: 2 };
+
+        assert!(state.backfill_field(&Backfill { field: "hash".to_string(), ..backfill.clone() }, |_| {}).is_err());
+        assert!(state.backfill_field(&Backfill { batch_size: 0, ..backfill.clone() }, |_| {}).is_err());
+
+        let mut reports = Vec::new();
+        let done = state.backfill_field(&backfill, |progress| reports.push(*progress)).unwrap();
+        assert_eq!((done.scanned, done.updated), (5, 2));
+        assert_eq!(reports.iter().map(|progress| progress.scanned).collect::<Vec<_>>(), vec![2, 4, 5]);
+
+        let data = |id: &str| state.get_by_id(id).unwrap().unwrap().data;
+        assert_eq!(data("t1")["settings"]["priority"], 0);
+        assert_eq!(data("t2")["settings"]["priority"], 5);
+        assert_eq!(data("t3")["settings"]["priority"], 0);
+        assert_eq!(data("t4")["settings"], "legacy");
+        assert!(data("n1").get("settings").is_none());
+
+        let again = state.backfill_field(&backfill, |_| {}).unwrap();
+        assert_eq!(again.updated, 0);
+    }
+
+    #[test]
+    fn test_ffi_backfill_field() {
+        use crate::{backfill_field, create_db, push_data};
+
+        static PROGRESS_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        extern "C" fn on_progress(_scanned: u64, _updated: u64) {
+            PROGRESS_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_backfill_field")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let backfill = CString::new(r#"{"field":"data.priority","value":1}"#).unwrap();
+        let result = unsafe { CString::from_raw(backfill_field(db_ptr, backfill.as_ptr(), Some(on_progress)) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"scanned\":1,\"updated\":1}"}"#);
+        assert_eq!(PROGRESS_CALLS.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let invalid = CString::new(r#"{"field":"data.","value":1}"#).unwrap();
+        let result = unsafe { CString::from_raw(backfill_field(db_ptr, invalid.as_ptr(), None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(backfill_field(0, backfill.as_ptr(), None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_ffi_byte_buffers() {
+        use crate::app_response::AppResponse;
+        use crate::{create_db, free_buffer, get_all_buffer, get_by_id_buffer, push_data, ByteBuffer};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_byte_buffers")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let model = create_test_model("b1", Some(serde_json::json!({"text": "nul \u{0} inside"})));
+        let json = CString::new(serde_json::to_string(&model).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let read = |buffer: ByteBuffer| {
+            let bytes = unsafe { std::slice::from_raw_parts(buffer.ptr, buffer.len) }.to_vec();
+            free_buffer(buffer);
+            serde_json::from_slice::<AppResponse>(&bytes).unwrap()
+        };
+
+        let id = CString::new("b1").unwrap();
+        let AppResponse::Ok(json) = read(get_by_id_buffer(db_ptr, id.as_ptr())) else {
+            panic!("get_by_id_buffer failed");
+        };
+        let found: LocalDbModel = serde_json::from_str(&json).unwrap();
+        assert_eq!(found.data["text"], "nul \u{0} inside");
+
+        let AppResponse::Ok(json) = read(get_all_buffer(db_ptr)) else {
+            panic!("get_all_buffer failed");
+        };
+        assert_eq!(serde_json::from_str::<Vec<LocalDbModel>>(&json).unwrap().len(), 1);
+
+        let missing = CString::new("missing").unwrap();
+        assert!(matches!(read(get_by_id_buffer(db_ptr, missing.as_ptr())), AppResponse::NotFound(_)));
+        assert!(matches!(read(get_all_buffer(0)), AppResponse::BadRequest(_)));
+
+        free_buffer(ByteBuffer { ptr: std::ptr::null_mut(), len: 0 });
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_ensure_indexes() {
+        use crate::local_db_model::{Direction, IndexDefinition};
+
+        let name = generate_unique_db_name("ensure_indexes");
+        let by_slug = IndexDefinition { name: "by_slug".to_string(), paths: vec!["data.slug".to_string()] };
+        let by_rank = IndexDefinition { name: "by_rank".to_string(), paths: vec!["data.rank".to_string()] };
+
+        {
+            let state = AppDbState::init_with_indexes(name.clone(), std::slice::from_ref(&by_slug)).unwrap();
+            state.post(create_test_model("n1", Some(serde_json::json!({"slug": "a", "rank": 2, "group": "x"})))).unwrap();
+            state.post(create_test_model("n2", Some(serde_json::json!({"slug": "b", "rank": 1, "group": "x"})))).unwrap();
+        }
+
+        // An app update adds an index and changes the paths of another
+        let by_slug_v2 = IndexDefinition { paths: vec!["data.group".to_string(), "data.slug".to_string()], ..by_slug.clone() };
+        let state = AppDbState::init(name).unwrap();
+        let report = state.ensure_indexes(&[by_slug_v2.clone(), by_rank.clone()]).unwrap();
+        assert_eq!(report.created, vec!["by_rank"]);
+        assert_eq!(report.rebuilt, vec!["by_slug"]);
+        assert!(report.unchanged.is_empty());
+
+        let ranked = state.query_index("by_rank", &[], Direction::Asc, 10).unwrap();
+        assert_eq!(ranked.iter().map(|model| model.id.as_str()).collect::<Vec<_>>(), vec!["n2", "n1"]);
+        let grouped = state.query_index("by_slug", &[serde_json::json!("x")], Direction::Desc, 10).unwrap();
+        assert_eq!(grouped.iter().map(|model| model.id.as_str()).collect::<Vec<_>>(), vec!["n2", "n1"]);
+
+        let report = state.ensure_indexes(&[by_slug_v2.clone(), by_rank.clone()]).unwrap();
+        assert_eq!(report.unchanged, vec!["by_slug", "by_rank"]);
+
+        assert!(state.ensure_indexes(&[by_rank.clone(), by_rank]).is_err());
+        let empty = IndexDefinition { name: "empty".to_string(), paths: Vec::new() };
+        assert!(state.ensure_indexes(&[empty]).is_err());
+    }
+
+    #[test]
+    fn test_ffi_ensure_indexes() {
+        use crate::{create_db, ensure_indexes};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_ensure_indexes")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let indexes = CString::new(r#"[{"name":"by_slug","paths":["data.slug"]}]"#).unwrap();
+        let result = unsafe { CString::from_raw(ensure_indexes(db_ptr, indexes.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"created":["by_slug"],"rebuilt":[],"unchanged":[]}"#);
+
+        let result = unsafe { CString::from_raw(ensure_indexes(db_ptr, indexes.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"unchanged\":[\"by_slug\"]"#));
+
+        let malformed = CString::new(r#"{"name":"by_slug"}"#).unwrap();
+        let result = unsafe { CString::from_raw(ensure_indexes(db_ptr, malformed.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(ensure_indexes(0, indexes.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_response_error_codes() {
+        use crate::app_response::{AppResponse, ErrorCategory, ResponseEnvelope};
+
+        let not_found = AppResponse::NotFound("No model found with id: a".to_string());
+        assert_eq!((not_found.code(), not_found.code_name()), (1003, "NOT_FOUND"));
+        assert_eq!(not_found.category(), Some(ErrorCategory::NotFound));
+        assert_eq!(AppResponse::Ok("x".to_string()).category(), None);
+
+        let busy = AppResponse::Busy { message: "slow down".to_string(), retry_after_ms: 20 };
+        let error = busy.envelope().error.unwrap();
+        assert_eq!((error.code, error.category), (4001, ErrorCategory::Throttled));
+        assert_eq!(error.detail, Some(serde_json::json!({"retry_after_ms": 20})));
+
+        let json = crate::encode_response(&not_found, 2).unwrap();
+        assert_eq!(
+            json,
+            r#"{"ok":false,"data":null,"error":{"code":1003,"name":"NOT_FOUND","category":"not_found","message":"No model found with id: a","detail":null}}"#
+        );
+        let envelope: ResponseEnvelope = serde_json::from_str(&crate::encode_response(&AppResponse::Ok("1".to_string()), 2).unwrap()).unwrap();
+        assert!(envelope.ok && envelope.data.as_deref() == Some("1") && envelope.error.is_none());
+
+        assert_eq!(crate::encode_response(&not_found, 1).unwrap(), r#"{"NotFound":"No model found with id: a"}"#);
+    }
+
+    #[test]
+    fn test_ffi_set_response_format() {
+        use crate::set_response_format;
+
+        // Other tests expect the default format, so only non-changing calls here
+        let result = unsafe { CString::from_raw(set_response_format(3) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(set_response_format(1) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+    }
+
+    #[test]
+    fn test_external_collection() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+
+        let ndjson = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("external")));
+        let ndjson = ndjson.to_str().unwrap();
+        std::fs::write(ndjson, concat!(
+            "{\"id\":\"p1\",\"hash\":\"h\",\"data\":{\"name\":\"Pen\",\"price\":2}}\n",
+            "\n",
+            "{\"id\":\"p2\",\"hash\":\"h\",\"data\":{\"name\":\"Ink\",\"price\":9}}\n",
+            "{\"id\":\"7\",\"hash\":\"h\",\"data\":{\"name\":\"Pad\",\"price\":4}}\n",
+        )).unwrap();
+        let array = std::env::temp_dir().join(format!("{}.json", generate_unique_db_name("external")));
+        let array = array.to_str().unwrap();
+        std::fs::write(array, r#"[{"id":"ES","hash":"h","data":{"name":"Spain"}}]"#).unwrap();
+
+        let mut state = AppDbState::init(generate_unique_db_name("external_db")).unwrap();
+        assert_eq!(state.register_external("products", ndjson).unwrap(), 3);
+        assert_eq!(state.register_external("countries", array).unwrap(), 1);
+        assert_eq!(state.external_collections(), vec!["countries", "products"]);
+
+        assert_eq!(state.get_external_by_id("products", "p2").unwrap().unwrap().data["name"], "Ink");
+        assert!(state.get_external_by_id("products", "p9").unwrap().is_none());
+        assert_eq!(state.get_external_by_id("countries", "ES").unwrap().unwrap().data["name"], "Spain");
+
+        let filter: PathFilter = serde_json::from_str(r#"{"data.price": {"$gt": 3}}"#).unwrap();
+        let ids: Vec<String> = state.query_external("products", &filter).unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(ids, vec!["p2", "7"]);
+        assert_eq!(state.query_external("countries", &PathFilter::default()).unwrap().len(), 1);
+        assert_eq!(state.count_records().unwrap(), 0);
+
+        state.post(create_test_model("o1", Some(serde_json::json!({"product_id": "p1"})))).unwrap();
+        state.post(create_test_model("o2", Some(serde_json::json!({"product_id": 7})))).unwrap();
+        state.post(create_test_model("o3", Some(serde_json::json!({"product_id": "gone"})))).unwrap();
+        let joined = state.join_external(&PathFilter::default(), "data.product_id", "products").unwrap();
+        let names: Vec<Option<String>> = joined
+            .iter()
+            .map(|row| row.joined.as_ref().map(|product| product.data["name"].as_str().unwrap().to_string()))
+            .collect();
+        assert_eq!(names, vec![Some("Pen".to_string()), Some("Pad".to_string()), None]);
+
+        assert!(matches!(state.query_external("missing", &PathFilter::default()), Err(AppResponse::NotFound(_))));
+        assert!(state.unregister_external("products"));
+        assert!(!state.unregister_external("products"));
+
+        std::fs::write(ndjson, "{\"hash\":\"h\",\"data\":{}}\n").unwrap();
+        assert!(matches!(state.register_external("products", ndjson), Err(AppResponse::SerializationError(_))));
+
+        std::fs::remove_file(ndjson).unwrap();
+        std::fs::remove_file(array).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_external_collection() {
+        use crate::app_response::AppResponse;
+        use crate::{create_db, join_external, push_data, query_external, register_external_collection, unregister_external_collection};
+
+        let path = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("ffi_external")));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "{\"id\":\"p1\",\"hash\":\"h\",\"data\":{\"name\":\"Pen\"}}\n").unwrap();
+
+        let db_name = CString::new(generate_unique_db_name("ffi_external_db")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let name = CString::new("products").unwrap();
+        let path_c = CString::new(path).unwrap();
+        let result = unsafe { CString::from_raw(register_external_collection(db_ptr, name.as_ptr(), path_c.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let filter = CString::new(r#"{"data.name":"Pen"}"#).unwrap();
+        let result = unsafe { CString::from_raw(query_external(db_ptr, name.as_ptr(), filter.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("p1"));
+
+        let json = CString::new(serde_json::to_string(&create_test_model("o1", Some(serde_json::json!({"product_id": "p1"})))).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let all = CString::new("{}").unwrap();
+        let field = CString::new("data.product_id").unwrap();
+        let result = unsafe { CString::from_raw(join_external(db_ptr, all.as_ptr(), field.as_ptr(), name.as_ptr()) as *mut i8) };
+        let payload: String = match serde_json::from_str(result.to_str().unwrap()).unwrap() {
+            AppResponse::Ok(payload) => payload,
+            other => panic!("unexpected response {other:?}"),
+        };
+        let rows: Vec<crate::local_db_model::JoinedRecord> = serde_json::from_str(&payload).unwrap();
+        assert_eq!(rows[0].joined.as_ref().unwrap().data["name"], "Pen");
+
+        let result = unsafe { CString::from_raw(unregister_external_collection(db_ptr, name.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(query_external(db_ptr, name.as_ptr(), filter.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_get_last_error() {
+        use crate::{create_db, get_last_error};
+
+        let result = thread::spawn(|| unsafe { CString::from_raw(get_last_error() as *mut i8) }).join().unwrap();
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"null"}"#);
+
+        let db_ptr = create_db(std::ptr::null());
+        assert_eq!(db_ptr, 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"BadRequest":"Null name pointer passed to create_db"}"#);
+
+        // A path under a regular file cannot hold a database directory
+        let file = std::env::temp_dir().join(generate_unique_db_name("last_error_file"));
+        std::fs::write(&file, b"").unwrap();
+        let name = CString::new(file.join("db").to_str().unwrap()).unwrap();
+        assert_eq!(create_db(name.as_ptr()), 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"DatabaseError":"Cannot open database at"#));
+
+        // Errors are per thread
+        let result = thread::spawn(|| unsafe { CString::from_raw(get_last_error() as *mut i8) }).join().unwrap();
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"null"}"#);
+
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_library_version() {
+        let info = AppDbState::library_version();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.lmdb_version.starts_with("0.9."));
+        assert_eq!(info.features.contains(&"signing".to_string()), cfg!(feature = "signing"));
+        assert_eq!(info.features.contains(&"compression".to_string()), cfg!(feature = "compression"));
+        assert_eq!(info.response_formats, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_ffi_get_library_version() {
+        use crate::app_response::AppResponse;
+        use crate::get_library_version;
+        use crate::local_db_model::LibraryVersion;
+
+        let result = unsafe { CString::from_raw(get_library_version() as *mut i8) };
+        let payload = match serde_json::from_str(result.to_str().unwrap()).unwrap() {
+            AppResponse::Ok(payload) => payload,
+            other => panic!("unexpected response {other:?}"),
+        };
+        let info: LibraryVersion = serde_json::from_str(&payload).unwrap();
+        assert_eq!(info, AppDbState::library_version());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encryption_per_tenant_keys() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+        use lmdb::Transaction;
+
+        let mut state = AppDbState::init(generate_unique_db_name("encryption")).unwrap();
+        state.set_encryption_key("acct1", "a1:", &[1u8; 32]).unwrap();
+        state.set_encryption_key("acct2", "a2:", &[2u8; 32]).unwrap();
+        assert!(matches!(state.set_encryption_key("acct3", "a1:", &[3u8; 32]), Err(AppResponse::BadRequest(_))));
+
+        state.post(create_test_model("a1:x", Some(serde_json::json!({"secret": "alpha", "n": 1})))).unwrap();
+        state.post(create_test_model("a2:y", Some(serde_json::json!({"secret": "beta", "n": 2})))).unwrap();
+        state.post(create_test_model("plain", Some(serde_json::json!({"secret": "gamma", "n": 3})))).unwrap();
+
+        {
+            let (env, db) = state.env_db().unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let stored = txn.get(db, &"a1:x").unwrap();
+            assert!(!stored.windows(5).any(|window| window == b"alpha"));
+            assert!(txn.get(db, &"plain").unwrap().windows(5).any(|window| window == b"gamma"));
+        }
+
+        assert_eq!(state.get_by_id("a1:x").unwrap().unwrap().data["secret"], "alpha");
+        let filter: PathFilter = serde_json::from_str(r#"{"data.n": {"$lt": 3}}"#).unwrap();
+        assert_eq!(state.query(&filter).unwrap().len(), 2);
+
+        // Encrypted records stay out of indexes
+        assert_eq!(state.create_index("by_secret", &["data.secret".to_string()]).unwrap(), 1);
+
+        // Crypto-shredding one tenant leaves the others readable
+        assert!(state.remove_encryption_key("acct1"));
+        assert!(matches!(state.get_by_id("a1:x"), Err(AppResponse::BadRequest(_))));
+        let ids: Vec<String> = state.get().unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(ids, vec!["a2:y", "plain"]);
+
+        state.set_encryption_key("acct1", "a1:", &[9u8; 32]).unwrap();
+        assert!(matches!(state.get_by_id("a1:x"), Err(AppResponse::SerializationError(_))));
+        state.set_encryption_key("acct1", "a1:", &[1u8; 32]).unwrap();
+        assert_eq!(state.get_by_id("a1:x").unwrap().unwrap().data["n"], 1);
+
+        // A sealed value moved to another ID of the tenant fails authentication
+        state.post(create_test_model("a1:z", None)).unwrap();
+        {
+            let (env, db) = state.env_db().unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            let stored = txn.get(db, &"a1:x").unwrap().to_vec();
+            txn.put(db, &"a1:z", &stored, lmdb::WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+        assert!(matches!(state.get_by_id("a1:z"), Err(AppResponse::SerializationError(_))));
+
+        // Background views share the keys, so removing one reaches them too
+        let view = state.background_view().unwrap();
+        assert_eq!(view.get_by_id("a1:x").unwrap().unwrap().data["n"], 1);
+        state.remove_encryption_key("acct1");
+        assert!(matches!(view.get_by_id("a1:x"), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn test_encryption_requires_feature() {
+        use crate::app_response::AppResponse;
+
+        let mut state = AppDbState::init(generate_unique_db_name("encryption_off")).unwrap();
+        assert!(matches!(state.set_encryption_key("acct1", "a1:", &[1u8; 32]), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.set_field_encryption(&["data.ssn"], &[1u8; 32]), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_ffi_encryption_keys() {
+        use crate::{create_db, get_by_id, push_data, remove_encryption_key, set_encryption_key};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_encryption")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let tenant = CString::new("acct1").unwrap();
+        let prefix = CString::new("a1:").unwrap();
+        let short_key = CString::new("0102").unwrap();
+        let result = unsafe { CString::from_raw(set_encryption_key(db_ptr, tenant.as_ptr(), prefix.as_ptr(), short_key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let key = CString::new("01".repeat(32)).unwrap();
+        let result = unsafe { CString::from_raw(set_encryption_key(db_ptr, tenant.as_ptr(), prefix.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a1:x", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let id = CString::new("a1:x").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("hash_a1:x"));
+
+        let result = unsafe { CString::from_raw(remove_encryption_key(db_ptr, tenant.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_ffi_boundary_catches_panics() {
+        use crate::{ffi_boundary, get_last_error, DbHandle};
+        use std::os::raw::c_char;
+
+        let result = ffi_boundary("get_all", || -> *const c_char { panic!("boom") });
+        let result = unsafe { CString::from_raw(result as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"DatabaseError":"Panic in get_all: boom"}"#);
+
+        let index = 3;
+        let handle = ffi_boundary("create_db", || -> DbHandle { panic!("index {index} out of range") });
+        assert_eq!(handle, 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"DatabaseError":"Panic in create_db: index 3 out of range"}"#);
+    }
+
+    #[test]
+    fn test_clock_drives_expiry() {
+        let state = AppDbState::init(generate_unique_db_name("clock")).unwrap();
+        state.create_index("by_expiry", &["data.expires_at".to_string()]).unwrap();
+        state.post(create_test_model("soon", Some(serde_json::json!({"expires_at": 2_000})))).unwrap();
+        state.post(create_test_model("later", Some(serde_json::json!({"expires_at": 5_000})))).unwrap();
+
+        state.set_fixed_clock(Some(1_000));
+        assert_eq!(state.now_ms(), 1_000);
+        assert_eq!(state.sweep_expired("by_expiry", 10).unwrap(), 0);
+
+        state.set_fixed_clock(Some(3_000));
+        assert_eq!(state.sweep_expired("by_expiry", 10).unwrap(), 1);
+        state.mark_for_resync(&["later".to_string()]).unwrap();
+        assert_eq!(state.get_resync_queue().unwrap()[0].marked_at, 3_000);
+
+        // The offset applies to the running clock
+        state.set_fixed_clock(None);
+        let device = state.now_ms();
+        state.set_clock_offset(-60_000);
+        assert_eq!(state.clock_offset(), -60_000);
+        assert!(state.now_ms() < device);
+        assert!(state.now_ms() + 60_000 >= device);
+    }
+
+    #[test]
+    fn test_ffi_clock() {
+        use crate::{create_db, get_current_time, set_clock_offset, set_fixed_clock};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_clock")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_fixed_clock(db_ptr, 1_234) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1234"}"#);
+        let result = unsafe { CString::from_raw(get_current_time(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1234"}"#);
+
+        let result = unsafe { CString::from_raw(set_clock_offset(db_ptr, 500) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+        let result = unsafe { CString::from_raw(set_clock_offset(db_ptr, 0) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"500"}"#);
+
+        let result = unsafe { CString::from_raw(get_current_time(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        use crate::local_db_model::{SyncConflict, SyncManifest};
+
+        let state = AppDbState::init(generate_unique_db_name("plan_sync")).unwrap();
+        for id in ["a", "b", "c", "d"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+        let synced = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", synced.sequence).unwrap();
+
+        state.put(LocalDbModel { hash: "a2".to_string(), ..create_test_model("a", None) }).unwrap();
+        state.put(LocalDbModel { hash: "b2".to_string(), ..create_test_model("b", None) }).unwrap();
+        state.delete_by_id("c").unwrap();
+        state.post(create_test_model("e", None)).unwrap();
+
+        let manifest: SyncManifest = serde_json::from_value(serde_json::json!({
+            "remote": {"a": "a2", "b": "b3", "d": null, "f": "f1", "x": null}
+        })).unwrap();
+        let plan = state.plan_sync(&manifest).unwrap();
+        assert_eq!(plan.push, vec!["e"]);
+        assert_eq!(plan.push_deletes, vec!["c"]);
+        assert_eq!(plan.pull, vec!["f"]);
+        assert_eq!(plan.pull_deletes, vec!["d"]);
+        assert_eq!(plan.conflicts, vec![SyncConflict {
+            id: "b".to_string(),
+            local_hash: Some("b2".to_string()),
+            remote_hash: Some("b3".to_string()),
+        }]);
+        assert_eq!(plan.in_sync, 2);
+
+        // Nothing is applied or acknowledged
+        assert!(state.get_by_id("d").unwrap().is_some());
+        assert_eq!(state.get_all_delta("sync").unwrap().records.len(), 3);
+
+        // An unknown consumer has everything pending and is not registered
+        let manifest = SyncManifest { consumer: "other".to_string(), remote: Default::default(), sizes: Default::default() };
+        assert_eq!(state.plan_sync(&manifest).unwrap().push, vec!["a", "b", "d", "e"]);
+        assert!(!state.remove_delta_consumer("other").unwrap());
+    }
+
+    #[test]
+    fn test_ffi_plan_sync() {
+        use crate::{create_db, plan_sync, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_plan_sync")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let manifest = CString::new(r#"{"remote":{"b":"hash_b"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(plan_sync(db_ptr, manifest.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let plan: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(plan["push"], serde_json::json!(["a"]));
+        assert_eq!(plan["pull"], serde_json::json!(["b"]));
+
+        let invalid = CString::new(r#"{"remote":[]}"#).unwrap();
+        let result = unsafe { CString::from_raw(plan_sync(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(plan_sync(0, manifest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_plan_sync_batches() {
+        use crate::local_db_model::{SyncBatch, SyncLimits, SyncManifest};
+
+        let mut state = AppDbState::init(generate_unique_db_name("plan_sync_batches")).unwrap();
+        for (id, size) in [("a", 100), ("b", 100), ("c", 5_000), ("d", 100)] {
+            state.post(create_test_model(id, Some(serde_json::json!({"text": "x".repeat(size)})))).unwrap();
+        }
+        let size = |id: &str| serde_json::to_string(&state.get_by_id(id).unwrap().unwrap()).unwrap().len() as u64;
+        let (a, b, d) = (size("a"), size("b"), size("d"));
+
+        let manifest: SyncManifest = serde_json::from_value(serde_json::json!({
+            "remote": {"p": "h", "q": "h", "r": "h"},
+            "sizes": {"p": 300, "q": 300, "r": 2_000}
+        })).unwrap();
+
+        // Without limits everything travels in one batch
+        let plan = state.plan_sync(&manifest).unwrap();
+        assert_eq!(plan.push_batches.len(), 1);
+        assert_eq!(plan.pull_batches, vec![SyncBatch { ids: vec!["p".into(), "q".into(), "r".into()], bytes: 2_600 }]);
+
+        // 0 means no limit, as for the FFI function
+        state.set_sync_limits(SyncLimits { max_batch_bytes: Some(0), max_record_bytes: None });
+        assert_eq!(state.sync_limits(), SyncLimits::default());
+        state.set_sync_limits(SyncLimits { max_batch_bytes: Some(a + b), max_record_bytes: Some(1_500) });
+        let plan = state.plan_sync(&manifest).unwrap();
+        assert_eq!(plan.push_batches, vec![
+            SyncBatch { ids: vec!["a".into(), "b".into()], bytes: a + b },
+            SyncBatch { ids: vec!["d".into()], bytes: d },
+        ]);
+        assert_eq!(plan.pull_batches, vec![SyncBatch { ids: vec!["p".into()], bytes: 300 }, SyncBatch { ids: vec!["q".into()], bytes: 300 }]);
+        assert_eq!(plan.oversized, vec!["c", "r"]);
+        assert_eq!(plan.push, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_ffi_set_sync_limits() {
+        use crate::{create_db, set_sync_limits};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sync_limits")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_sync_limits(db_ptr, 1024, 0) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"max_batch_bytes\":1024,\"max_record_bytes\":null}"}"#);
+
+        let result = unsafe { CString::from_raw(set_sync_limits(0, 1024, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_init_with_path() {
+        let base_dir = std::env::temp_dir().join(generate_unique_db_name("init_with_path"));
+        let base = base_dir.to_str().unwrap();
+
+        let mut state = AppDbState::init_with_path("notes".to_string(), base).unwrap();
+        state.post(create_test_model("a", None)).unwrap();
+        assert!(base_dir.join("notes.lmdb").is_dir());
+
+        state.reset_database("notes_v2").unwrap();
+        assert!(!base_dir.join("notes.lmdb").exists());
+        assert!(base_dir.join("notes_v2.lmdb").is_dir());
+        assert_eq!(state.count_records().unwrap(), 0);
+
+        state.close_database().unwrap();
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_create_db_with_path() {
+        use crate::{create_db_with_path, get_by_id, get_last_error, push_data};
+
+        let base_dir = std::env::temp_dir().join(generate_unique_db_name("ffi_create_db_with_path"));
+        let first = CString::new(base_dir.join("first").to_str().unwrap()).unwrap();
+        let second = CString::new(base_dir.join("second").to_str().unwrap()).unwrap();
+        let name = CString::new("notes").unwrap();
+
+        let db1 = create_db_with_path(name.as_ptr(), first.as_ptr());
+        let db2 = create_db_with_path(name.as_ptr(), second.as_ptr());
+        assert_ne!(db1, 0);
+        assert_ne!(db2, 0);
+        assert_ne!(db1, db2, "the same name in another directory is another database");
+        assert_eq!(create_db_with_path(name.as_ptr(), first.as_ptr()), db1);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db1, json.as_ptr()) as *mut i8); }
+        let id = CString::new("a").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db2, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+        assert!(base_dir.join("first").join("notes.lmdb").is_dir());
+
+        assert_eq!(create_db_with_path(name.as_ptr(), std::ptr::null()), 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe {
+            let _ = CString::from_raw(crate::close_database(db1) as *mut i8);
+            let _ = CString::from_raw(crate::close_database(db2) as *mut i8);
+        }
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[test]
+    fn test_init_with_options_map_size() {
+        use crate::local_db_model::DbOptions;
+
+        let options = DbOptions { map_size: 8 * 1024 * 1024, ..DbOptions::default() };
+        let mut state = AppDbState::init_with_options(generate_unique_db_name("map_size"), &options).unwrap();
+        assert_eq!(state.memory_stats().unwrap().map_size, 8 * 1024 * 1024);
+
+        // The map size survives a reset
+        state.reset_database(&generate_unique_db_name("map_size_reset")).unwrap();
+        assert_eq!(state.memory_stats().unwrap().map_size, 8 * 1024 * 1024);
+
+        let too_small = DbOptions { map_size: 4096, ..DbOptions::default() };
+        assert!(too_small.validate().is_err());
+        assert!(AppDbState::init_with_options(generate_unique_db_name("map_size_small"), &too_small).is_err());
+    }
+
+    #[test]
+    fn test_ffi_create_db_with_config() {
+        use crate::{create_db_with_config, get_last_error, get_memory_stats};
+
+        let name = CString::new(generate_unique_db_name("ffi_create_db_with_config")).unwrap();
+        let options = CString::new(r#"{"map_size":16777216}"#).unwrap();
+        let db_ptr = create_db_with_config(name.as_ptr(), options.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_memory_stats(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(stats["map_size"], 16777216);
+
+        let other = CString::new(generate_unique_db_name("ffi_create_db_with_config_bad")).unwrap();
+        let options = CString::new(r#"{"map_size":1}"#).unwrap();
+        assert_eq!(create_db_with_config(other.as_ptr(), options.as_ptr()), 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert!(result.to_str().unwrap().contains("map_size must be at least"));
+
+        let options = CString::new(r#"{"map_size":"big"}"#).unwrap();
+        assert_eq!(create_db_with_config(other.as_ptr(), options.as_ptr()), 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_conflict_inbox() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::{ConflictResolution, ConflictSide, RemoteChanges};
+
+        let state = AppDbState::init(generate_unique_db_name("conflict_inbox")).unwrap();
+        for id in ["a", "b", "c", "d"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+        let synced = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", synced.sequence).unwrap();
+
+        state.put(LocalDbModel { hash: "a2".to_string(), ..create_test_model("a", None) }).unwrap();
+        state.delete_by_id("d").unwrap();
+
+        let remote = |records: &[(&str, &str)], deleted: &[&str]| RemoteChanges {
+            consumer: "sync".to_string(),
+            records: records.iter().map(|(id, hash)| LocalDbModel { hash: hash.to_string(), ..create_test_model(id, None) }).collect(),
+            deleted: deleted.iter().map(|id| id.to_string()).collect(),
+        };
+
+        let result = state.merge_remote(&remote(&[("a", "a3"), ("b", "b2"), ("e", "e1")], &["c", "d"])).unwrap();
+        assert_eq!((result.applied, result.deleted, result.unchanged), (2, 1, 1));
+        assert_eq!(result.conflicts, vec!["a"]);
+        assert_eq!(state.get_by_id("a").unwrap().unwrap().hash, "a2");
+        assert_eq!(state.get_by_id("b").unwrap().unwrap().hash, "b2");
+        assert!(state.get_by_id("c").unwrap().is_none());
+
+        // Records written by a merge are not local edits; a conflicting record
+        // keeps the latest server version
+        let result = state.merge_remote(&remote(&[("a", "a4"), ("b", "b3")], &[])).unwrap();
+        assert_eq!((result.applied, result.conflicts.clone()), (1, vec!["a".to_string()]));
+        assert_eq!(state.get_by_id("b").unwrap().unwrap().hash, "b3");
+
+        let conflicts = state.list_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].id, "a");
+        assert_eq!(conflicts[0].local.as_ref().unwrap().hash, "a2");
+        assert_eq!(conflicts[0].remote.as_ref().unwrap().hash, "a4");
+
+        let resolved = state.resolve_conflict("a", &ConflictResolution::Keep(ConflictSide::Remote)).unwrap();
+        assert_eq!(resolved.unwrap().hash, "a4");
+        assert_eq!(state.get_by_id("a").unwrap().unwrap().hash, "a4");
+        assert!(state.list_conflicts().unwrap().is_empty());
+        assert!(matches!(
+            state.resolve_conflict("a", &ConflictResolution::Keep(ConflictSide::Remote)),
+            Err(AppResponse::NotFound(_))
+        ));
+
+        // Deleted on the server, edited locally
+        state.put(LocalDbModel { hash: "e2".to_string(), ..create_test_model("e", None) }).unwrap();
+        let result = state.merge_remote(&remote(&[], &["e"])).unwrap();
+        assert_eq!(result.conflicts, vec!["e"]);
+        assert!(state.list_conflicts().unwrap()[0].remote.is_none());
+        let resolved = state.resolve_conflict("e", &ConflictResolution::Keep(ConflictSide::Local)).unwrap();
+        assert_eq!(resolved.unwrap().hash, "e2");
+
+        // Edited on both sides, merged by the app
+        state.put(LocalDbModel { hash: "b4".to_string(), ..create_test_model("b", None) }).unwrap();
+        assert_eq!(state.merge_remote(&remote(&[("b", "b5")], &[])).unwrap().conflicts, vec!["b"]);
+        let merged = LocalDbModel { hash: "b6".to_string(), ..create_test_model("b", None) };
+        let wrong_id = LocalDbModel { id: "x".to_string(), ..merged.clone() };
+        assert!(matches!(state.resolve_conflict("b", &ConflictResolution::Merged(wrong_id)), Err(AppResponse::BadRequest(_))));
+        state.resolve_conflict("b", &ConflictResolution::Merged(merged)).unwrap();
+        assert_eq!(state.get_by_id("b").unwrap().unwrap().hash, "b6");
+        assert!(state.list_conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_conflict_inbox() {
+        use crate::{create_db, list_conflicts, merge_remote, push_data, resolve_conflict};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_conflict_inbox")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let changes = CString::new(r#"{"records":[{"id":"a","hash":"a2","data":{}}]}"#).unwrap();
+        let result = unsafe { CString::from_raw(merge_remote(db_ptr, changes.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"applied\":0,\"deleted\":0,\"unchanged\":0,\"conflicts\":[\"a\"],\"resolved\":0}"}"#);
+
+        let result = unsafe { CString::from_raw(list_conflicts(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let conflicts: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(conflicts[0]["local"]["hash"], "hash_a");
+        assert_eq!(conflicts[0]["remote"]["hash"], "a2");
+
+        let id = CString::new("a").unwrap();
+        let invalid = CString::new("theirs").unwrap();
+        let result = unsafe { CString::from_raw(resolve_conflict(db_ptr, id.as_ptr(), invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let resolution = CString::new("remote").unwrap();
+        let result = unsafe { CString::from_raw(resolve_conflict(db_ptr, id.as_ptr(), resolution.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(record["hash"], "a2");
+
+        let result = unsafe { CString::from_raw(resolve_conflict(db_ptr, id.as_ptr(), resolution.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let result = unsafe { CString::from_raw(list_conflicts(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_init_with_options_max_readers() {
+        use crate::local_db_model::DbOptions;
+
+        let default = AppDbState::init(generate_unique_db_name("max_readers_default")).unwrap();
+        assert_eq!(default.memory_stats().unwrap().max_readers, 126);
+
+        let options = DbOptions { max_readers: 512, ..DbOptions::default() };
+        let mut state = AppDbState::init_with_options(generate_unique_db_name("max_readers"), &options).unwrap();
+        assert_eq!(state.memory_stats().unwrap().max_readers, 512);
+
+        state.reset_database(&generate_unique_db_name("max_readers_reset")).unwrap();
+        assert_eq!(state.memory_stats().unwrap().max_readers, 512);
+
+        let none = DbOptions { max_readers: 0, ..DbOptions::default() };
+        assert!(none.validate().is_err());
+        assert!(AppDbState::init_with_options(generate_unique_db_name("max_readers_none"), &none).is_err());
+    }
+
+    #[test]
+    fn test_ffi_create_db_with_config_max_readers() {
+        use crate::{create_db_with_config, get_last_error, get_memory_stats};
+
+        let name = CString::new(generate_unique_db_name("ffi_max_readers")).unwrap();
+        let options = CString::new(r#"{"max_readers":300}"#).unwrap();
+        let db_ptr = create_db_with_config(name.as_ptr(), options.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_memory_stats(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(stats["max_readers"], 300);
+
+        let other = CString::new(generate_unique_db_name("ffi_max_readers_none")).unwrap();
+        let options = CString::new(r#"{"max_readers":0}"#).unwrap();
+        assert_eq!(create_db_with_config(other.as_ptr(), options.as_ptr()), 0);
+        let result = unsafe { CString::from_raw(get_last_error() as *mut i8) };
+        assert!(result.to_str().unwrap().contains("max_readers must be greater than 0"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_sync_payload_encryption() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::RemoteChanges;
+
+        let mut phone = AppDbState::init(generate_unique_db_name("sync_sealed_phone")).unwrap();
+        let mut laptop = AppDbState::init(generate_unique_db_name("sync_sealed_laptop")).unwrap();
+        let records = vec![create_test_model("n1", Some(serde_json::json!({"title": "Diary"})))];
+        assert!(matches!(phone.seal_sync_records(&records), Err(AppResponse::BadRequest(_))));
+
+        phone.set_sync_key(&[5u8; 32]).unwrap();
+        let sealed = phone.seal_sync_records(&records).unwrap();
+        assert_eq!((sealed[0].id.as_str(), sealed[0].hash.as_str()), ("n1", "hash_n1"));
+        assert!(!serde_json::to_string(&sealed).unwrap().contains("Diary"));
+        assert_eq!(phone.open_sync_records(&sealed).unwrap()[0].data["title"], "Diary");
+
+        // The server cannot move a body to another record
+        let moved = vec![LocalDbModel { id: "n2".to_string(), ..sealed[0].clone() }];
+        assert!(matches!(phone.open_sync_records(&moved), Err(AppResponse::SerializationError(_))));
+
+        // The key reaches the other device only wrapped
+        let changes = RemoteChanges { consumer: "sync".to_string(), records: sealed, deleted: vec![] };
+        assert!(matches!(laptop.merge_remote(&changes), Err(AppResponse::BadRequest(_))));
+        let wrapped = phone.wrap_sync_key(&[9u8; 32]).unwrap();
+        assert!(laptop.set_wrapped_sync_key(&wrapped, &[8u8; 32]).is_err());
+        laptop.set_wrapped_sync_key(&wrapped, &[9u8; 32]).unwrap();
+        assert_eq!(laptop.merge_remote(&changes).unwrap().applied, 1);
+        assert_eq!(laptop.get_by_id("n1").unwrap().unwrap().data["title"], "Diary");
+
+        // Plain records pass through
+        assert_eq!(laptop.open_sync_records(&records).unwrap()[0].data["title"], "Diary");
+        assert!(laptop.remove_sync_key());
+        assert!(!laptop.remove_sync_key());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_ffi_sync_payload_encryption() {
+        use crate::{create_db, open_sync_records, seal_sync_records, set_sync_key, set_wrapped_sync_key, wrap_sync_key};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sync_sealed")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let records = CString::new(r#"[{"id":"n1","hash":"h1","data":{"title":"Diary"}}]"#).unwrap();
+        let result = unsafe { CString::from_raw(seal_sync_records(db_ptr, records.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let key = CString::new("05".repeat(32)).unwrap();
+        let result = unsafe { CString::from_raw(set_sync_key(db_ptr, key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let result = unsafe { CString::from_raw(seal_sync_records(db_ptr, records.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let sealed = response["Ok"].as_str().unwrap();
+        assert!(sealed.contains("$sealed") && !sealed.contains("Diary"));
+
+        let sealed = CString::new(sealed).unwrap();
+        let result = unsafe { CString::from_raw(open_sync_records(db_ptr, sealed.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Diary"));
+
+        let wrapping_key = CString::new("09".repeat(32)).unwrap();
+        let result = unsafe { CString::from_raw(wrap_sync_key(db_ptr, wrapping_key.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let wrapped = CString::new(response["Ok"].as_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(set_wrapped_sync_key(db_ptr, wrapped.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+        let result = unsafe { CString::from_raw(set_wrapped_sync_key(db_ptr, wrapped.as_ptr(), wrapping_key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let result = unsafe { CString::from_raw(set_sync_key(0, key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn test_sync_payload_encryption_requires_feature() {
+        use crate::app_response::AppResponse;
+
+        let mut state = AppDbState::init(generate_unique_db_name("sync_sealed_off")).unwrap();
+        assert!(matches!(state.set_sync_key(&[5u8; 32]), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_attachment_transfers() {
+        use crate::app_response::AppResponse;
+        use crate::attachments::ATTACHMENT_CHUNK_SIZE;
+        use crate::local_db_model::TransferDirection;
+        use std::sync::{Arc, Mutex};
+
+        let mut phone = AppDbState::init(generate_unique_db_name("attachments_phone")).unwrap();
+        let laptop = AppDbState::init(generate_unique_db_name("attachments_laptop")).unwrap();
+        let photo: Vec<u8> = (0..ATTACHMENT_CHUNK_SIZE as usize * 2 + 1000).map(|i| (i % 251) as u8).collect();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        phone.set_attachment_progress_callback(Some(move |progress: &crate::local_db_model::AttachmentProgress| {
+            sink.lock().unwrap().push(progress.bytes_done);
+        }));
+
+        let manifest = phone.put_attachment("photo_1", &photo).unwrap();
+        assert_eq!((manifest.bytes, manifest.checksums.len()), (photo.len() as u64, 3));
+        assert_eq!(phone.get_attachment("photo_1").unwrap().unwrap(), photo);
+        assert!(phone.get_attachment("photo_2").unwrap().is_none());
+
+        // Upload two chunks, then resume from the pending list
+        phone.ack_attachment_chunk("photo_1", 0).unwrap();
+        let progress = phone.ack_attachment_chunk("photo_1", 2).unwrap();
+        assert_eq!((progress.missing.clone(), progress.bytes_done), (vec![1], u64::from(ATTACHMENT_CHUNK_SIZE) + 1000));
+        let pending = phone.pending_attachments().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].direction, TransferDirection::Upload);
+        assert!(phone.ack_attachment_chunk("photo_1", 3).is_err());
+        assert!(phone.ack_attachment_chunk("photo_1", 1).unwrap().complete);
+        assert!(phone.pending_attachments().unwrap().is_empty());
+        assert_eq!(seen.lock().unwrap().last(), Some(&manifest.bytes));
+
+        // Download on another device, rejecting a corrupted chunk
+        let progress = laptop.begin_attachment_download(&manifest).unwrap();
+        assert_eq!(progress.missing, vec![0, 1, 2]);
+        let chunk = |index: u32| phone.read_attachment_chunk("photo_1", index).unwrap();
+        let mut corrupted = chunk(1);
+        corrupted[0] ^= 1;
+        assert!(matches!(laptop.write_attachment_chunk("photo_1", 1, &corrupted), Err(AppResponse::BadRequest(_))));
+        laptop.write_attachment_chunk("photo_1", 1, &chunk(1)).unwrap();
+        assert!(matches!(laptop.get_attachment("photo_1"), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(laptop.ack_attachment_chunk("photo_1", 0), Err(AppResponse::BadRequest(_))));
+
+        // Registering the same manifest again resumes the download
+        assert_eq!(laptop.begin_attachment_download(&manifest).unwrap().missing, vec![0, 2]);
+        laptop.write_attachment_chunk("photo_1", 0, &chunk(0)).unwrap();
+        assert!(laptop.write_attachment_chunk("photo_1", 2, &chunk(2)).unwrap().complete);
+        assert_eq!(laptop.get_attachment("photo_1").unwrap().unwrap(), photo);
+
+        let mut invalid = manifest.clone();
+        invalid.checksums.pop();
+        assert!(laptop.begin_attachment_download(&invalid).is_err());
+
+        assert!(laptop.delete_attachment("photo_1").unwrap());
+        assert!(!laptop.delete_attachment("photo_1").unwrap());
+        assert!(laptop.attachment_progress("photo_1").unwrap().is_none());
+        assert!(matches!(laptop.write_attachment_chunk("photo_1", 0, &chunk(0)), Err(AppResponse::NotFound(_))));
+    }
+
+    #[test]
+    fn test_ffi_attachment_transfers() {
+        use crate::{ack_attachment_chunk, begin_attachment_download, create_db, get_attachment, get_pending_attachments, put_attachment, read_attachment_chunk, set_attachment_progress_callback, write_attachment_chunk};
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static BYTES_DONE: AtomicU64 = AtomicU64::new(0);
+        extern "C" fn on_progress(_id: *const std::os::raw::c_char, bytes_done: u64, _bytes_total: u64) {
+            BYTES_DONE.store(bytes_done, Ordering::SeqCst);
+        }
+
+        let source_name = CString::new(generate_unique_db_name("ffi_attachments_source")).unwrap();
+        let source = create_db(source_name.as_ptr());
+        let target_name = CString::new(generate_unique_db_name("ffi_attachments_target")).unwrap();
+        let target = create_db(target_name.as_ptr());
+        assert!(source != 0 && target != 0);
+
+        let id = CString::new("doc").unwrap();
+        let content = b"attachment body".to_vec();
+        let result = unsafe { CString::from_raw(put_attachment(source, id.as_ptr(), content.as_ptr(), content.len()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let manifest = CString::new(response["Ok"].as_str().unwrap()).unwrap();
+
+        let result = unsafe { CString::from_raw(get_pending_attachments(source) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"missing\":[0]"#));
+
+        let mut buffer = vec![0u8; 4];
+        let result = unsafe { CString::from_raw(read_attachment_chunk(source, id.as_ptr(), 0, buffer.as_mut_ptr(), buffer.len()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("too small"));
+        let mut buffer = vec![0u8; 64];
+        let result = unsafe { CString::from_raw(read_attachment_chunk(source, id.as_ptr(), 0, buffer.as_mut_ptr(), buffer.len()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"15"}"#);
+        let result = unsafe { CString::from_raw(ack_attachment_chunk(source, id.as_ptr(), 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"complete\":true"#));
+
+        let result = unsafe { CString::from_raw(set_attachment_progress_callback(target, Some(on_progress)) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+        let result = unsafe { CString::from_raw(begin_attachment_download(target, manifest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"direction\":\"download\""#));
+        let result = unsafe { CString::from_raw(write_attachment_chunk(target, id.as_ptr(), 0, buffer.as_ptr(), 15) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"complete\":true"#));
+        assert_eq!(BYTES_DONE.load(Ordering::SeqCst), 15);
+
+        let mut out = vec![0u8; 64];
+        let result = unsafe { CString::from_raw(get_attachment(target, id.as_ptr(), out.as_mut_ptr(), out.len()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"15"}"#);
+        assert_eq!(&out[..15], content.as_slice());
+
+        let result = unsafe { CString::from_raw(put_attachment(0, id.as_ptr(), content.as_ptr(), content.len()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(source) as *mut i8); }
+        unsafe { let _ = CString::from_raw(crate::close_database(target) as *mut i8); }
+    }
+
+    #[test]
+    fn test_init_with_options_durability_flags() {
+        use crate::local_db_model::DbOptions;
+        use std::os::raw::c_uint;
+
+        let flags = |state: &AppDbState| {
+            let (env, _) = state.env_db().unwrap();
+            let mut flags: c_uint = 0;
+            assert_eq!(unsafe { lmdb_sys::mdb_env_get_flags(env.env(), &mut flags) }, 0);
+            flags
+        };
+
+        let default = AppDbState::init(generate_unique_db_name("durability_default")).unwrap();
+        assert_eq!(flags(&default) & (lmdb_sys::MDB_NOSYNC | lmdb_sys::MDB_NOMETASYNC | lmdb_sys::MDB_WRITEMAP), 0);
+
+        let options: DbOptions = serde_json::from_str(r#"{"no_sync":true,"write_map":true}"#).unwrap();
+        let mut state = AppDbState::init_with_options(generate_unique_db_name("durability"), &options).unwrap();
+        assert_ne!(flags(&state) & lmdb_sys::MDB_NOSYNC, 0);
+        assert_ne!(flags(&state) & lmdb_sys::MDB_WRITEMAP, 0);
+        assert_eq!(flags(&state) & lmdb_sys::MDB_NOMETASYNC, 0);
+
+        for i in 0..100 {
+            state.post(create_test_model(&format!("item_{i}"), None)).unwrap();
+        }
+        state.flush().unwrap();
+
+        // The flags survive a reset
+        state.reset_database(&generate_unique_db_name("durability_reset")).unwrap();
+        assert_ne!(flags(&state) & lmdb_sys::MDB_NOSYNC, 0);
+    }
+
+    #[test]
+    fn test_ffi_flush_database() {
+        use crate::{create_db_with_config, flush_database};
+
+        let name = CString::new(generate_unique_db_name("ffi_flush")).unwrap();
+        let options = CString::new(r#"{"no_meta_sync":true}"#).unwrap();
+        let db_ptr = create_db_with_config(name.as_ptr(), options.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(flush_database(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"Database flushed"}"#);
+
+        let result = unsafe { CString::from_raw(flush_database(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_sync_history() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::{RemoteChanges, SyncRunReport};
+
+        let state = AppDbState::init(generate_unique_db_name("sync_history")).unwrap();
+        for id in ["a", "b"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+        assert!(matches!(state.finish_sync_run(&SyncRunReport::default()), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.begin_sync_run(""), Err(AppResponse::BadRequest(_))));
+        let status = state.sync_status().unwrap();
+        assert!(status.running.is_none() && status.last_run.is_none() && status.last_success_at.is_none());
+
+        // First sync: everything is pushed
+        let run = state.begin_sync_run("sync").unwrap();
+        assert_eq!((run.id, run.consumer.as_str(), run.finished_at), (1, "sync", None));
+        let delta = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", delta.sequence).unwrap();
+        state.ack_delta("search", delta.sequence).unwrap();
+        let remote = RemoteChanges {
+            consumer: "sync".to_string(),
+            records: vec![create_test_model("c", None)],
+            deleted: vec!["b".to_string()],
+        };
+        state.merge_remote(&remote).unwrap();
+        assert_eq!(state.sync_status().unwrap().running.unwrap().pulled, 1);
+
+        let report = SyncRunReport { bytes_sent: 120, failed: vec!["x".to_string()], ..SyncRunReport::default() };
+        let run = state.finish_sync_run(&report).unwrap();
+        assert_eq!((run.pushed, run.pulled, run.deleted, run.conflicts), (2, 1, 1, 0));
+        assert_eq!((run.bytes_sent, run.failed.clone(), run.error.clone()), (120, vec!["x".to_string()], None));
+        let status = state.sync_status().unwrap();
+        assert!(status.running.is_none());
+        assert_eq!(status.last_run.as_ref(), Some(&run));
+        assert_eq!(status.last_success_at, run.finished_at);
+
+        // Second sync pushes only the new changes, then fails
+        state.begin_sync_run("sync").unwrap();
+        let delta = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", delta.sequence).unwrap();
+        state.post(create_test_model("d", None)).unwrap();
+        let delta = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", delta.sequence).unwrap();
+        let failed = state.finish_sync_run(&SyncRunReport { error: Some("Server unavailable".to_string()), ..SyncRunReport::default() }).unwrap();
+        // The merged changes come back in the delta and are acknowledged too
+        assert_eq!(failed.pushed, 1 + run.pulled + run.deleted);
+        let status = state.sync_status().unwrap();
+        assert_eq!(status.last_run.unwrap().error.as_deref(), Some("Server unavailable"));
+        assert_eq!(status.last_success_at, run.finished_at);
+
+        // A run left open is finished as interrupted by the next one
+        state.begin_sync_run("sync").unwrap();
+        let running = state.begin_sync_run("sync").unwrap();
+        assert_eq!(state.sync_status().unwrap().running.unwrap().id, running.id);
+
+        let history = state.sync_history(10).unwrap();
+        assert_eq!(history.iter().map(|run| run.id).collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+        assert!(history[1].error.as_deref().unwrap().contains("Interrupted"));
+        assert_eq!(state.sync_history(2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_ffi_sync_history() {
+        use crate::{begin_sync_run, create_db, finish_sync_run, get_sync_history, get_sync_status};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sync_history")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let consumer = CString::new("sync").unwrap();
+        let result = unsafe { CString::from_raw(begin_sync_run(db_ptr, consumer.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let run: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!((run["id"].as_u64(), run["consumer"].as_str()), (Some(1), Some("sync")));
+
+        let invalid = CString::new("{").unwrap();
+        let result = unsafe { CString::from_raw(finish_sync_run(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let report = CString::new(r#"{"pulled":3,"failed":["n7"]}"#).unwrap();
+        let result = unsafe { CString::from_raw(finish_sync_run(db_ptr, report.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let run: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(run["pulled"], 3);
+        assert_eq!(run["failed"][0], "n7");
+
+        let result = unsafe { CString::from_raw(get_sync_status(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let status: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert!(status["running"].is_null());
+        assert_eq!(status["last_run"], run);
+        assert_eq!(status["last_success_at"], run["finished_at"]);
+
+        let result = unsafe { CString::from_raw(get_sync_history(db_ptr, 5) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let history: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(history.as_array().unwrap().len(), 1);
+
+        let result = unsafe { CString::from_raw(get_sync_status(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(feature = "simulation")]
+    #[test]
+    fn test_simulation() {
+        use crate::local_db_model::{ConflictResolution, ConflictSide};
+        use crate::simulation::{interleave, SimOp, Simulation, SimulationLog};
+        use std::collections::BTreeMap;
+
+        let put = |device: &str, id: &str, hash: &str| SimOp::Put {
+            device: device.to_string(),
+            record: LocalDbModel { hash: hash.to_string(), ..create_test_model(id, None) },
+        };
+        let sync = |device: &str| SimOp::Sync { device: device.to_string() };
+
+        // Concurrent edits of one record conflict on the device syncing last
+        let mut log = SimulationLog {
+            ops: vec![
+                put("phone", "n1", "a"),
+                put("phone", "n2", "a"),
+                sync("phone"),
+                sync("tablet"),
+                put("phone", "n1", "p"),
+                put("tablet", "n1", "t"),
+                SimOp::Delete { device: "tablet".to_string(), id: "n2".to_string() },
+                sync("phone"),
+                sync("tablet"),
+            ],
+            ..SimulationLog::default()
+        };
+        let report = Simulation::in_temp_dir().run(&log).unwrap();
+        assert_eq!(report.steps[2].pushed, 2);
+        assert_eq!(report.steps[3].merge.as_ref().unwrap().applied, 2);
+        let merge = report.steps[8].merge.as_ref().unwrap();
+        assert_eq!(merge.conflicts, vec!["n1"]);
+        assert_eq!(report.steps[8].pushed, 1);
+        assert_eq!(report.devices["tablet"].conflicts, vec!["n1"]);
+        assert_eq!(report.server.iter().map(|record| (record.id.as_str(), record.hash.as_str())).collect::<Vec<_>>(), vec![("n1", "p")]);
+        assert!(!report.converged);
+
+        // Settled by the tablet, the devices converge
+        log.ops.push(SimOp::Resolve {
+            device: "tablet".to_string(),
+            id: "n1".to_string(),
+            resolution: ConflictResolution::Keep(ConflictSide::Local),
+        });
+        log.ops.push(SimOp::Resolve { device: "tablet".to_string(), id: "n9".to_string(), resolution: ConflictResolution::Keep(ConflictSide::Local) });
+        log.settle = true;
+        let report = Simulation::in_temp_dir().run(&log).unwrap();
+        assert!(report.steps[10].error.is_some());
+        assert!(report.converged);
+        assert_eq!(report.server[0].hash, "t");
+        assert_eq!(report.devices["phone"].records[0].hash, "t");
+
+        // Random interleavings are reproducible and keep each device's order
+        let mut logs = BTreeMap::new();
+        for device in ["phone", "tablet", "web"] {
+            let ops = (0..4).flat_map(|i| [put(device, &format!("{device}_{i}"), "h"), sync(device)]).collect::<Vec<_>>();
+            logs.insert(device.to_string(), ops);
+        }
+        assert_eq!(format!("{:?}", interleave(&logs, 7)), format!("{:?}", interleave(&logs, 7)));
+        assert_ne!(format!("{:?}", interleave(&logs, 7)), format!("{:?}", interleave(&logs, 8)));
+        for seed in 0..4 {
+            let ops = logs.values().flatten().cloned().collect();
+            let log = SimulationLog { ops, seed: Some(seed), settle: true };
+            let first = Simulation::in_temp_dir().run(&log).unwrap();
+            let second = Simulation::in_temp_dir().run(&log).unwrap();
+            assert_eq!(first.steps, second.steps);
+            assert!(first.converged);
+            assert_eq!(first.server.len(), 12);
+        }
+    }
+
+    #[cfg(feature = "simulation")]
+    #[test]
+    fn test_ffi_run_simulation() {
+        use crate::run_simulation;
+
+        let log = CString::new(
+            r#"{"ops":[
+                {"op":"put","device":"phone","record":{"id":"n1","hash":"a","data":{}}},
+                {"op":"sync","device":"phone"},
+                {"op":"sync","device":"tablet"}
+            ]}"#,
+        )
+        .unwrap();
+        let result = unsafe { CString::from_raw(run_simulation(log.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(report["converged"], true);
+        assert_eq!(report["devices"]["tablet"]["records"][0]["hash"], "a");
+
+        let invalid = CString::new(r#"{"ops":[{"op":"jump"}]}"#).unwrap();
+        let result = unsafe { CString::from_raw(run_simulation(invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+    }
+
+    #[cfg(not(feature = "simulation"))]
+    #[test]
+    fn test_run_simulation_requires_feature() {
+        let log = CString::new(r#"{"ops":[]}"#).unwrap();
+        let result = unsafe { CString::from_raw(crate::run_simulation(log.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+    }
+
+    #[test]
+    fn test_collections() {
+        use crate::app_response::AppResponse;
+        use crate::collections::MAX_COLLECTIONS;
+
+        let mut state = AppDbState::init(generate_unique_db_name("collections")).unwrap();
+        state.post(create_test_model("o1", None)).unwrap();
+        for id in ["o1", "o2", "o3"] {
+            state.collection_put("orders", &create_test_model(id, Some(serde_json::json!({"total": 1})))).unwrap();
+        }
+        state.collection_put("products", &create_test_model("p1", None)).unwrap();
+        state.collection_put("orders", &LocalDbModel { hash: "o2b".to_string(), ..create_test_model("o2", None) }).unwrap();
+
+        assert_eq!(state.collections().unwrap(), vec!["orders", "products"]);
+        assert_eq!(state.collection_get("orders", "o2").unwrap().unwrap().hash, "o2b");
+        assert!(state.collection_get("orders", "p1").unwrap().is_none());
+        assert!(state.collection_get("missing", "o1").unwrap().is_none());
+        let ids: Vec<String> = state.collection_get_all("orders").unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(ids, vec!["o1", "o2", "o3"]);
+        assert!(state.collection_delete("orders", "o3").unwrap());
+        assert!(!state.collection_delete("orders", "o3").unwrap());
+
+        assert!(matches!(state.collection_put("", &create_test_model("x", None)), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.collection_put("orders", &create_test_model("", None)), Err(AppResponse::BadRequest(_))));
+
+        // Dropping removes the collection and its records, not main's
+        assert!(state.drop_collection("orders").unwrap());
+        assert!(!state.drop_collection("orders").unwrap());
+        assert_eq!(state.collections().unwrap(), vec!["products"]);
+        assert!(state.collection_get_all("orders").unwrap().is_empty());
+        assert_eq!(state.get_by_id("o1").unwrap().unwrap().hash, "hash_o1");
+        state.collection_put("orders", &create_test_model("o4", None)).unwrap();
+        assert_eq!(state.collection_get_all("orders").unwrap().len(), 1);
+
+        for i in 2..MAX_COLLECTIONS {
+            state.collection_put(&format!("c{i}"), &create_test_model("x", None)).unwrap();
+        }
+        assert!(matches!(state.collection_put("one_too_many", &create_test_model("x", None)), Err(AppResponse::BadRequest(_))));
+        assert_eq!(state.get().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ffi_collections() {
+        use crate::{collection_delete_by_id, collection_get_all, collection_get_by_id, collection_put, create_db, drop_collection, list_collections};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_collections")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let orders = CString::new("orders").unwrap();
+        for id in ["o1", "o2"] {
+            let json = CString::new(serde_json::to_string(&create_test_model(id, None)).unwrap()).unwrap();
+            let result = unsafe { CString::from_raw(collection_put(db_ptr, orders.as_ptr(), json.as_ptr()) as *mut i8) };
+            assert!(result.to_str().unwrap().contains("Ok"));
+        }
+
+        let id = CString::new("o1").unwrap();
+        let result = unsafe { CString::from_raw(collection_get_by_id(db_ptr, orders.as_ptr(), id.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(record["hash"], "hash_o1");
+
+        let result = unsafe { CString::from_raw(collection_delete_by_id(db_ptr, orders.as_ptr(), id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+        let result = unsafe { CString::from_raw(collection_get_by_id(db_ptr, orders.as_ptr(), id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let result = unsafe { CString::from_raw(collection_get_all(db_ptr, orders.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let records: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(records.as_array().unwrap().len(), 1);
+
+        let result = unsafe { CString::from_raw(list_collections(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[\"orders\"]"}"#);
+
+        let result = unsafe { CString::from_raw(drop_collection(db_ptr, orders.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(drop_collection(db_ptr, orders.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"false"}"#);
+        let result = unsafe { CString::from_raw(list_collections(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[]"}"#);
+
+        let result = unsafe { CString::from_raw(drop_collection(0, orders.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_write_transaction() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::WriteOp;
+
+        let state = AppDbState::init(generate_unique_db_name("write_transaction")).unwrap();
+        state.post(create_test_model("cart:o1", None)).unwrap();
+        let ops: Vec<WriteOp> = serde_json::from_value(serde_json::json!([
+            {"op": "put", "collection": "orders", "record": {"id": "o1", "hash": "h1", "data": {"total": 42}}},
+            {"op": "delete", "id": "cart:o1"},
+            {"op": "delete", "collection": "orders", "id": "o9"},
+            {"op": "delete", "collection": "archive", "id": "o0"},
+            {"op": "increment", "collection": "counters", "id": "orders", "path": "data.stats.count"},
+            {"op": "increment", "collection": "counters", "id": "orders", "path": "data.stats.count", "by": 4},
+            {"op": "put", "record": {"id": "last_order", "hash": "h1", "data": {"id": "o1"}}}
+        ]))
+        .unwrap();
+
+        let result = state.write_transaction(&ops).unwrap();
+        assert_eq!((result.written, result.deleted, result.not_found.clone()), (4, 1, vec!["o9".to_string(), "o0".to_string()]));
+        assert_eq!(state.collection_get("orders", "o1").unwrap().unwrap().data["total"], 42);
+        assert_eq!(state.collection_get("counters", "orders").unwrap().unwrap().data, serde_json::json!({"stats": {"count": 5}}));
+        assert!(state.get_by_id("cart:o1").unwrap().is_none());
+        assert_eq!(state.get_by_id("last_order").unwrap().unwrap().data["id"], "o1");
+        assert_eq!(state.collections().unwrap(), vec!["counters", "orders"]);
+
+        // A failing write rolls back the whole transaction
+        let ops: Vec<WriteOp> = serde_json::from_value(serde_json::json!([
+            {"op": "put", "collection": "orders", "record": {"id": "o2", "hash": "h1", "data": {}}},
+            {"op": "delete", "id": "last_order"},
+            {"op": "increment", "collection": "orders", "id": "o1", "path": "data.total.amount"}
+        ]))
+        .unwrap();
+        assert!(matches!(state.write_transaction(&ops), Err(AppResponse::BadRequest(_))));
+        assert!(state.collection_get("orders", "o2").unwrap().is_none());
+        assert!(state.get_by_id("last_order").unwrap().is_some());
+
+        let ops: Vec<WriteOp> = serde_json::from_value(serde_json::json!([{"op": "increment", "id": "x", "path": "hash"}])).unwrap();
+        assert!(matches!(state.write_transaction(&ops), Err(AppResponse::BadRequest(_))));
+        let ops: Vec<WriteOp> = serde_json::from_value(serde_json::json!([{"op": "delete", "collection": "orders", "id": ""}])).unwrap();
+        assert!(matches!(state.write_transaction(&ops), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_ffi_write_transaction() {
+        use crate::{collection_get_by_id, create_db, write_transaction};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_write_transaction")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let ops = CString::new(
+            r#"[{"op":"put","collection":"orders","record":{"id":"o1","hash":"h1","data":{}}},
+                {"op":"increment","collection":"counters","id":"orders","path":"data.count"}]"#,
+        )
+        .unwrap();
+        let result = unsafe { CString::from_raw(write_transaction(db_ptr, ops.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"written\":2,\"deleted\":0,\"not_found\":[]}"}"#);
+
+        let counters = CString::new("counters").unwrap();
+        let id = CString::new("orders").unwrap();
+        let result = unsafe { CString::from_raw(collection_get_by_id(db_ptr, counters.as_ptr(), id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"count\":1"#));
+
+        let invalid = CString::new(r#"[{"op":"upsert"}]"#).unwrap();
+        let result = unsafe { CString::from_raw(write_transaction(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(write_transaction(0, ops.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_namespaces() {
+        use crate::app_response::AppResponse;
+        use crate::namespace::namespace_key;
+
+        let state = AppDbState::init(generate_unique_db_name("namespaces")).unwrap();
+        assert_eq!(namespace_key("user", "123"), "user:123");
+        for id in ["user:1", "user:2", "user:team:3", "users:4", "user", "feed:1", "settings"] {
+            state.post(create_test_model(id, None)).unwrap();
+        }
+
+        assert_eq!(state.count_namespace("user").unwrap(), 3);
+        assert_eq!(state.count_namespace("user:team").unwrap(), 1);
+        assert_eq!(state.count_namespace("missing").unwrap(), 0);
+        assert!(matches!(state.count_namespace(""), Err(AppResponse::BadRequest(_))));
+
+        assert_eq!(state.clear_namespace("user").unwrap(), 3);
+        assert_eq!(state.count_namespace("user").unwrap(), 0);
+        let ids: Vec<String> = state.get().unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(ids, vec!["feed:1", "settings", "user", "users:4"]);
+        assert_eq!(state.clear_namespace("user").unwrap(), 0);
+        assert!(matches!(state.clear_namespace(""), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_ffi_namespaces() {
+        use crate::{clear_namespace, count_namespace, create_db, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_namespaces")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for id in ["feed:1", "feed:2", "settings"] {
+            let json = CString::new(serde_json::to_string(&create_test_model(id, None)).unwrap()).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let feed = CString::new("feed").unwrap();
+        let result = unsafe { CString::from_raw(count_namespace(db_ptr, feed.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+        let result = unsafe { CString::from_raw(clear_namespace(db_ptr, feed.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"2"}"#);
+        let result = unsafe { CString::from_raw(count_namespace(db_ptr, feed.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+
+        let empty = CString::new("").unwrap();
+        let result = unsafe { CString::from_raw(clear_namespace(db_ptr, empty.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(count_namespace(0, feed.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_migrate() {
+        use crate::app_response::AppResponse;
+
+        let mut state = AppDbState::init(generate_unique_db_name("migrate")).unwrap();
+        for i in 0..5 {
+            state.post(create_test_model(&format!("t{i}"), Some(serde_json::json!({"done": i % 2 == 0})))).unwrap();
+        }
+        state.post(create_test_model("x", Some(serde_json::json!({"obsolete": true})))).unwrap();
+        assert_eq!(state.schema_version().unwrap(), 0);
+        assert!(state.migrate(1, 2, |_| {}).is_err());
+
+        // Version 1 renames `done`, version 2 drops obsolete records
+        state.register_migration(0, |mut model| {
+            let data = model.data.as_object_mut().unwrap();
+            if let Some(done) = data.remove("done") {
+                data.insert("completed".to_string(), done);
+            }
+            Ok(Some(model))
+        });
+        state.register_migration(1, |model| Ok((model.data.get("obsolete").is_none()).then_some(model)));
+        assert!(state.migrate(2, 0, |_| {}).is_err());
+
+        let mut reports = Vec::new();
+        let done = state.migrate(2, 2, |progress| reports.push(*progress)).unwrap();
+        assert_eq!((done.schema_version, done.scanned, done.updated, done.deleted), (2, 12, 11, 1));
+        assert_eq!(reports.iter().map(|progress| progress.schema_version).collect::<Vec<_>>(), vec![0, 0, 0, 1, 1, 1, 1, 2]);
+        assert_eq!(state.schema_version().unwrap(), 2);
+        assert_eq!(state.get_by_id("t0").unwrap().unwrap().data, serde_json::json!({"completed": true}));
+        assert!(state.get_by_id("x").unwrap().is_none());
+        assert!(state.migrate(1, 2, |_| {}).is_err());
+
+        // A failing step keeps the committed batches and resumes after them
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let steps = std::sync::Arc::clone(&seen);
+        state.register_migration(2, move |mut model| {
+            if model.id == "t3" && !steps.lock().unwrap().contains(&model.id) {
+                steps.lock().unwrap().push(model.id);
+                return Err(AppResponse::BadRequest("offline".to_string()));
+            }
+            steps.lock().unwrap().push(model.id.clone());
+            model.data["version"] = serde_json::json!(3);
+            Ok(Some(model))
+        });
+        assert!(state.migrate(3, 2, |_| {}).is_err());
+        assert_eq!(state.schema_version().unwrap(), 2);
+        assert_eq!(state.get_by_id("t0").unwrap().unwrap().data["version"], 3);
+        assert!(state.get_by_id("t2").unwrap().unwrap().data.get("version").is_none());
+
+        let done = state.migrate(3, 2, |_| {}).unwrap();
+        assert_eq!((done.schema_version, done.scanned, done.updated), (3, 3, 3));
+        assert_eq!(*seen.lock().unwrap(), vec!["t0", "t1", "t2", "t3", "t2", "t3", "t4"]);
+        assert!(state.get().unwrap().iter().all(|model| model.data["version"] == 3));
+
+        // Steps must keep the record ID
+        state.register_migration(3, |mut model| {
+            model.id.push('!');
+            Ok(Some(model))
+        });
+        assert!(matches!(state.migrate(4, 10, |_| {}), Err(AppResponse::BadRequest(_))));
+        assert_eq!(state.schema_version().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_ffi_migrate() {
+        use crate::{create_db, get_schema_version, migrate, push_data, register_migration};
+        use std::ffi::c_char;
+
+        extern "C" fn drop_archived(_from_version: u64, record_json: *const c_char) -> *const c_char {
+            let record = unsafe { std::ffi::CStr::from_ptr(record_json) }.to_str().unwrap();
+            if record.contains("archived") {
+                std::ptr::null()
+            } else {
+                record_json
+            }
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_migrate")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        for (id, data) in [("a", serde_json::json!({"state": "open"})), ("b", serde_json::json!({"state": "archived"}))] {
+            let json = CString::new(serde_json::to_string(&create_test_model(id, Some(data))).unwrap()).unwrap();
+            unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        }
+
+        let result = unsafe { CString::from_raw(migrate(db_ptr, 1, 100) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("No migration registered from schema version 0"));
+
+        let result = unsafe { CString::from_raw(register_migration(db_ptr, 0, Some(drop_archived)) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+        let result = unsafe { CString::from_raw(migrate(db_ptr, 1, 100) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let done: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(done, serde_json::json!({"schema_version": 1, "scanned": 2, "updated": 1, "deleted": 1}));
+
+        let result = unsafe { CString::from_raw(get_schema_version(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let result = unsafe { CString::from_raw(register_migration(db_ptr, 1, None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("removed"));
+        let result = unsafe { CString::from_raw(get_schema_version(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(migrate(0, 1, 100) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_meta_values() {
+        let state = AppDbState::init(generate_unique_db_name("meta_values")).unwrap();
+        assert_eq!(state.meta_value::<String>("sync_cursor").unwrap(), None);
+
+        state.set_meta_value("sync_cursor", "2024-05-01T10:00:00Z").unwrap();
+        state.set_meta_value("page", &3u32).unwrap();
+        assert_eq!(state.meta_value::<String>("sync_cursor").unwrap().as_deref(), Some("2024-05-01T10:00:00Z"));
+        assert_eq!(state.meta_value::<u32>("page").unwrap(), Some(3));
+        assert!(state.meta_value::<u32>("sync_cursor").is_err());
+        assert!(state.set_meta_value("", &1).is_err());
+
+        // App keys never clash with the bookkeeping of the crate nor show up as records
+        state.set_meta_value("schema_version", &"custom").unwrap();
+        assert_eq!(state.schema_version().unwrap(), 0);
+        assert!(state.get().unwrap().is_empty());
+
+        assert!(state.remove_meta_value("page").unwrap());
+        assert!(!state.remove_meta_value("page").unwrap());
+        assert_eq!(state.meta_value::<u32>("page").unwrap(), None);
+    }
+
+    #[test]
+    fn test_ffi_meta_values() {
+        use crate::{create_db, get_meta_value, remove_meta_value, set_meta_value};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_meta_values")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let key = CString::new("checkpoint").unwrap();
+        let value = CString::new(r#"{"page":3,"token":"abc"}"#).unwrap();
+        let result = unsafe { CString::from_raw(set_meta_value(db_ptr, key.as_ptr(), value.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+
+        let result = unsafe { CString::from_raw(get_meta_value(db_ptr, key.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let stored: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(stored, serde_json::json!({"page": 3, "token": "abc"}));
+
+        let invalid = CString::new("{page").unwrap();
+        let result = unsafe { CString::from_raw(set_meta_value(db_ptr, key.as_ptr(), invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(remove_meta_value(db_ptr, key.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(get_meta_value(db_ptr, key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let result = unsafe { CString::from_raw(get_meta_value(0, key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_import_ndjson() {
+        use crate::local_db_model::{ImportOptions, ImportPolicy};
+
+        let path = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("import_ndjson")));
+        let path = path.to_str().unwrap();
+        let lines: Vec<String> = (0..5)
+            .map(|i| serde_json::to_string(&create_test_model(&format!("r{i}"), Some(serde_json::json!({"from": "file"})))).unwrap())
+            .collect();
+        std::fs::write(path, format!("{}\n\n{}\n", lines[..3].join("\n"), lines[3..].join("\n"))).unwrap();
+
+        let state = AppDbState::init(generate_unique_db_name("import_ndjson_db")).unwrap();
+        state.post(create_test_model("r1", Some(serde_json::json!({"from": "db"})))).unwrap();
+        state.post(create_test_model("r3", Some(serde_json::json!({"from": "db"})))).unwrap();
+        let from = |id: &str| state.get_by_id(id).unwrap().unwrap().data["from"].clone();
+
+        let skip = ImportOptions { policy: ImportPolicy::Skip, batch_size: 2 };
+        assert!(state.import_ndjson(path, &ImportOptions { batch_size: 0, ..skip }).is_err());
+        assert!(state.import_ndjson("no_such_file.ndjson", &skip).is_err());
+
+        let result = state.import_ndjson(path, &skip).unwrap();
+        assert_eq!((result.read, result.imported, result.skipped), (5, 3, 2));
+        assert_eq!((from("r0"), from("r1")), (serde_json::json!("file"), serde_json::json!("db")));
+
+        // Batches before the conflict are committed
+        state.delete_by_id("r0").unwrap();
+        state.delete_by_id("r2").unwrap();
+        let fail = ImportOptions { policy: ImportPolicy::Fail, batch_size: 1 };
+        assert!(matches!(state.import_ndjson(path, &fail), Err(crate::app_response::AppResponse::BadRequest(_))));
+        assert!(state.get_by_id("r0").unwrap().is_some());
+        assert!(state.get_by_id("r2").unwrap().is_none());
+
+        let result = state.import_ndjson(path, &ImportOptions { policy: ImportPolicy::Overwrite, batch_size: 10 }).unwrap();
+        assert_eq!((result.read, result.imported, result.skipped), (5, 5, 0));
+        assert_eq!(from("r3"), "file");
+        assert_eq!(state.count_records().unwrap(), 5);
+
+        std::fs::write(path, format!("{}\n{{\"id\":", lines[0])).unwrap();
+        match state.import_ndjson(path, &skip) {
+            Err(crate::app_response::AppResponse::SerializationError(message)) => assert!(message.contains("line 2")),
+            other => panic!("expected a serialization error, got {other:?}"),
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_import_ndjson() {
+        use crate::{create_db, import_ndjson};
+
+        let path = std::env::temp_dir().join(format!("{}.ndjson", generate_unique_db_name("ffi_import_ndjson")));
+        std::fs::write(&path, "{\"id\":\"n1\",\"hash\":\"h\",\"data\":{}}\n{\"id\":\"n1\",\"hash\":\"h2\",\"data\":{}}\n").unwrap();
+
+        let db_name = CString::new(generate_unique_db_name("ffi_import_ndjson_db")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(import_ndjson(db_ptr, c_path.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"read\":2,\"imported\":1,\"skipped\":1}"}"#);
+
+        let options = CString::new(r#"{"policy":"overwrite"}"#).unwrap();
+        let result = unsafe { CString::from_raw(import_ndjson(db_ptr, c_path.as_ptr(), options.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"read\":2,\"imported\":2,\"skipped\":0}"}"#);
+
+        let invalid = CString::new(r#"{"policy":"merge"}"#).unwrap();
+        let result = unsafe { CString::from_raw(import_ndjson(db_ptr, c_path.as_ptr(), invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(import_ndjson(0, c_path.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        std::fs::remove_file(path).unwrap();
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let state = AppDbState::init(generate_unique_db_name("export_csv")).unwrap();
+        state.post(create_test_model("a", Some(serde_json::json!({"type": "item", "title": "Chair, oak", "stock": 3, "dims": {"w": 40, "h": 90}})))).unwrap();
+        state.post(create_test_model("b", Some(serde_json::json!({"type": "item", "title": "Say \"hi\"\nthere", "tags": ["x", "y"], "note": null})))).unwrap();
+        state.post(create_test_model("c", Some(serde_json::json!({"type": "other", "title": "skipped"})))).unwrap();
+
+        let path = std::env::temp_dir().join(format!("{}.csv", generate_unique_db_name("export_csv")));
+        let path = path.to_str().unwrap();
+        let filter: crate::query::PathFilter = serde_json::from_value(serde_json::json!({"data.type": "item"})).unwrap();
+
+        let result = state.export_csv(path, &filter).unwrap();
+        assert_eq!(result.records, 2);
+        assert_eq!(
+            result.columns,
+            vec!["id", "hash", "data.dims.h", "data.dims.w", "data.note", "data.stock", "data.tags", "data.title", "data.type"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "id,hash,data.dims.h,data.dims.w,data.note,data.stock,data.tags,data.title,data.type\r\n\
+             a,hash_a,90,40,,3,,\"Chair, oak\",item\r\n\
+             b,hash_b,,,,,\"[\"\"x\"\",\"\"y\"\"]\",\"Say \"\"hi\"\"\nthere\",item\r\n"
+        );
+
+        let result = state.export_csv(path, &Default::default()).unwrap();
+        assert_eq!(result.records, 3);
+        assert!(state.export_csv("no_such_dir/out.csv", &filter).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_export_csv() {
+        use crate::{create_db, export_csv, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_export_csv")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("e1", Some(serde_json::json!({"name": "x"})))).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let path = std::env::temp_dir().join(format!("{}.csv", generate_unique_db_name("ffi_export_csv")));
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(export_csv(db_ptr, c_path.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"records\":1,\"columns\":[\"id\",\"hash\",\"data.name\"]}"}"#);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "id,hash,data.name\r\ne1,hash_e1,x\r\n");
+
+        let filter = CString::new(r#"{"data.name":"y"}"#).unwrap();
+        let result = unsafe { CString::from_raw(export_csv(db_ptr, c_path.as_ptr(), filter.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"records\":0"#));
+
+        let result = unsafe { CString::from_raw(export_csv(0, c_path.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        std::fs::remove_file(path).unwrap();
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_backup_database() {
+        let state = AppDbState::init(generate_unique_db_name("backup")).unwrap();
+        state.post(create_test_model("b1", Some(serde_json::json!({"v": 1})))).unwrap();
+        state.set_meta_value("sync_cursor", "c1").unwrap();
+
+        let base_dir = std::env::temp_dir();
+        let name = generate_unique_db_name("backup_copy");
+        let dest = base_dir.join(format!("{name}.lmdb"));
+        let backup = state.backup_database(dest.to_str().unwrap()).unwrap();
+        assert!(backup.path.ends_with("data.mdb"));
+        assert!(backup.bytes > 0);
+        assert!(matches!(state.backup_database(dest.to_str().unwrap()), Err(crate::app_response::AppResponse::BadRequest(_))));
+
+        // The database stays writable and the copy is unaffected
+        state.post(create_test_model("b2", None)).unwrap();
+
+        let copy = AppDbState::init_with_path(name, base_dir.to_str().unwrap()).unwrap();
+        assert_eq!(copy.get_all_ids().unwrap(), vec!["b1"]);
+        assert_eq!(copy.meta_value::<String>("sync_cursor").unwrap().as_deref(), Some("c1"));
+        drop(copy);
+
+        std::fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_backup_database() {
+        use crate::{backup_database, create_db};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_backup")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let dest = std::env::temp_dir().join(generate_unique_db_name("ffi_backup_copy"));
+        let c_dest = CString::new(dest.to_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(backup_database(db_ptr, c_dest.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let backup: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert!(backup["bytes"].as_u64().unwrap() > 0);
+        assert!(dest.join("data.mdb").exists());
+
+        let result = unsafe { CString::from_raw(backup_database(db_ptr, c_dest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(backup_database(0, c_dest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        std::fs::remove_dir_all(dest).unwrap();
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_compact_database() {
+        let state = AppDbState::init(generate_unique_db_name("compact_copy")).unwrap();
+        let filler = "x".repeat(2000);
+        for i in 0..300 {
+            state.post(create_test_model(&format!("c{i:03}"), Some(serde_json::json!({"filler": filler})))).unwrap();
+        }
+        for i in 0..290 {
+            state.delete_by_id(&format!("c{i:03}")).unwrap();
+        }
+
+        let base_dir = std::env::temp_dir();
+        let plain = base_dir.join(generate_unique_db_name("compact_plain"));
+        let name = generate_unique_db_name("compact_dest");
+        let compacted = base_dir.join(format!("{name}.lmdb"));
+        let backup = state.backup_database(plain.to_str().unwrap()).unwrap();
+        let compact = state.compact_database(compacted.to_str().unwrap()).unwrap();
+        assert!(compact.bytes < backup.bytes / 4, "{} vs {}", compact.bytes, backup.bytes);
+        assert!(state.compact_database(compacted.to_str().unwrap()).is_err());
+
+        let copy = AppDbState::init_with_path(name, base_dir.to_str().unwrap()).unwrap();
+        assert_eq!(copy.count_records().unwrap(), 10);
+        assert_eq!(copy.get_by_id("c299").unwrap().unwrap().data["filler"], filler);
+        drop(copy);
+
+        std::fs::remove_dir_all(plain).unwrap();
+        std::fs::remove_dir_all(compacted).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_compact_database() {
+        use crate::{compact_database, create_db};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_compact_copy")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let dest = std::env::temp_dir().join(generate_unique_db_name("ffi_compact_dest"));
+        let c_dest = CString::new(dest.to_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(compact_database(db_ptr, c_dest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("data.mdb"));
+        assert!(dest.join("data.mdb").exists());
+
+        let result = unsafe { CString::from_raw(compact_database(0, c_dest.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        std::fs::remove_dir_all(dest).unwrap();
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_rename_database() {
+        let old_name = generate_unique_db_name("rename_old");
+        let new_name = generate_unique_db_name("rename_new");
+        let taken = generate_unique_db_name("rename_taken");
+        let _other = AppDbState::init(taken.clone()).unwrap();
+
+        let mut state = AppDbState::init(old_name.clone()).unwrap();
+        state.post(create_test_model("r1", Some(serde_json::json!({"kept": true})))).unwrap();
+        let old_path = state.path.clone();
+
+        assert!(state.rename_database("").is_err());
+        assert!(matches!(state.rename_database(&taken), Err(crate::app_response::AppResponse::BadRequest(_))));
+        {
+            let _second = AppDbState::init(old_name).unwrap();
+            assert!(state.rename_database(&new_name).is_err());
+        }
+
+        state.rename_database(&new_name).unwrap();
+        assert!(state.path.ends_with(&format!("{new_name}.lmdb")));
+        assert!(!std::path::Path::new(&old_path).exists());
+        assert_eq!(state.get_by_id("r1").unwrap().unwrap().data["kept"], true);
+        state.post(create_test_model("r2", None)).unwrap();
+        drop(state);
+
+        let reopened = AppDbState::init(new_name).unwrap();
+        assert_eq!(reopened.get_all_ids().unwrap(), vec!["r1", "r2"]);
+    }
+
+    #[test]
+    fn test_ffi_rename_database() {
+        use crate::{create_db, get_by_id, push_data, rename_database};
+
+        let new_name = generate_unique_db_name("ffi_rename_new");
+        let db_name = CString::new(generate_unique_db_name("ffi_rename_old")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("f1", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let c_new_name = CString::new(new_name.as_str()).unwrap();
+        let result = unsafe { CString::from_raw(rename_database(db_ptr, c_new_name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(&format!("{new_name}.lmdb")));
+
+        // The handle follows the database and the new name maps to it
+        let id = CString::new("f1").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("hash_f1"));
+        assert_eq!(create_db(c_new_name.as_ptr()), db_ptr);
+
+        let result = unsafe { CString::from_raw(rename_database(0, c_new_name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_clone_database() {
+        let state = AppDbState::init(generate_unique_db_name("clone_src")).unwrap();
+        state.post(create_test_model("w1", Some(serde_json::json!({"title": "plan"})))).unwrap();
+        state.set_meta_value("owner", "ana").unwrap();
+
+        let clone_name = generate_unique_db_name("clone_dst");
+        assert!(state.clone_database("").is_err());
+        state.clone_database(&clone_name).unwrap();
+        assert!(matches!(state.clone_database(&clone_name), Err(crate::app_response::AppResponse::BadRequest(_))));
+
+        // Both databases change independently
+        let copy = AppDbState::init(clone_name).unwrap();
+        copy.post(create_test_model("w2", None)).unwrap();
+        state.delete_by_id("w1").unwrap();
+        assert_eq!(copy.get_all_ids().unwrap(), vec!["w1", "w2"]);
+        assert_eq!(copy.meta_value::<String>("owner").unwrap().as_deref(), Some("ana"));
+        assert_eq!(state.count_records().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_ffi_clone_database() {
+        use crate::{clone_database, create_db, get_all, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_clone_src")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("c1", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let clone_name = CString::new(generate_unique_db_name("ffi_clone_dst")).unwrap();
+        let result = unsafe { CString::from_raw(clone_database(db_ptr, clone_name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("data.mdb"));
+
+        let clone_ptr = create_db(clone_name.as_ptr());
+        assert_ne!(clone_ptr, 0);
+        assert_ne!(clone_ptr, db_ptr);
+        let result = unsafe { CString::from_raw(get_all(clone_ptr) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("hash_c1"));
+
+        let result = unsafe { CString::from_raw(clone_database(0, clone_name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(clone_ptr) as *mut i8); }
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_delete_database() {
+        let name = generate_unique_db_name("delete_db");
+        let mut state = AppDbState::init(name.clone()).unwrap();
+        state.post(create_test_model("d1", None)).unwrap();
+        let path = state.path.clone();
+
+        assert!(AppDbState::delete_database("").is_err());
+        assert!(matches!(AppDbState::delete_database(&name), Err(crate::app_response::AppResponse::BadRequest(_))));
+        // The same directory spelled differently is still recognized as open
+        assert!(AppDbState::delete_database(&format!("./{name}.lmdb")).is_err());
+
+        state.close_database().unwrap();
+        assert!(AppDbState::delete_database(&path).unwrap());
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(!AppDbState::delete_database(&name).unwrap());
+
+        // Directories that are not databases are left alone
+        let other = format!("{}.lmdb", generate_unique_db_name("delete_other"));
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(std::path::Path::new(&other).join("notes.txt"), "keep").unwrap();
+        assert!(AppDbState::delete_database(&other).is_err());
+        assert!(std::path::Path::new(&other).join("notes.txt").exists());
+        std::fs::remove_dir_all(other).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_delete_database() {
+        use crate::{create_db, delete_database};
+
+        let name = generate_unique_db_name("ffi_delete_db");
+        let db_name = CString::new(name.as_str()).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(delete_database(db_name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+        let result = unsafe { CString::from_raw(delete_database(db_name.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        assert!(!std::path::Path::new(&format!("{name}.lmdb")).exists());
+
+        let result = unsafe { CString::from_raw(delete_database(db_name.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"false"}"#);
+        let result = unsafe { CString::from_raw(delete_database(std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+    }
+
+    #[test]
+    fn test_list_databases() {
+        let base_dir = std::env::temp_dir().join(generate_unique_db_name("list_dbs"));
+        std::fs::create_dir_all(base_dir.join("not_a_db")).unwrap();
+        std::fs::create_dir_all(base_dir.join("empty.lmdb")).unwrap();
+        let base = base_dir.to_str().unwrap();
+
+        let mut alice = AppDbState::init_with_path("alice".to_string(), base).unwrap();
+        alice.post(create_test_model("a1", None)).unwrap();
+        alice.close_database().unwrap();
+        let bob = AppDbState::init_with_path("bob".to_string(), base).unwrap();
+
+        let databases = AppDbState::list_databases(base).unwrap();
+        assert_eq!(databases.iter().map(|db| db.name.as_str()).collect::<Vec<_>>(), vec!["alice", "bob"]);
+        assert_eq!(databases.iter().map(|db| db.open).collect::<Vec<_>>(), vec![false, true]);
+        assert!(databases[0].path.ends_with("alice.lmdb"));
+        assert!(databases.iter().all(|db| db.bytes > 0 && db.modified_at > 0));
+
+        assert!(AppDbState::list_databases(base_dir.join("missing").to_str().unwrap()).is_err());
+        drop(bob);
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_list_databases() {
+        use crate::{create_db_with_path, list_databases};
+
+        let base_dir = std::env::temp_dir().join(generate_unique_db_name("ffi_list_dbs"));
+        std::fs::create_dir_all(&base_dir).unwrap();
+        let c_base = CString::new(base_dir.to_str().unwrap()).unwrap();
+
+        let db_name = CString::new("carol").unwrap();
+        let db_ptr = create_db_with_path(db_name.as_ptr(), c_base.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(list_databases(c_base.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let databases: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(databases.as_array().unwrap().len(), 1);
+        assert_eq!(databases[0]["name"], "carol");
+        assert_eq!(databases[0]["open"], true);
+
+        let result = unsafe { CString::from_raw(list_databases(std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_field_encryption() {
+        use crate::app_response::AppResponse;
+        use crate::query::PathFilter;
+        use lmdb::Transaction;
+
+        let mut state = AppDbState::init(generate_unique_db_name("field_encryption")).unwrap();
+        assert!(matches!(state.set_field_encryption(&["ssn"], &[1u8; 32]), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.set_field_encryption(&["data..ssn"], &[1u8; 32]), Err(AppResponse::BadRequest(_))));
+        state.set_field_encryption(&["data.ssn", "data.card.number"], &[1u8; 32]).unwrap();
+
+        let patient = serde_json::json!({"name": "Ana", "ssn": "123-45-6789", "card": {"number": 4111, "brand": "visa"}});
+        let model = state.post(create_test_model("p1", Some(patient.clone()))).unwrap();
+        assert_eq!(model.data, patient);
+        state.post(create_test_model("p2", Some(serde_json::json!({"name": "Bo"})))).unwrap();
+
+        {
+            let (env, db) = state.env_db().unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let stored = txn.get(db, &"p1").unwrap();
+            assert!(!stored.windows(11).any(|window| window == b"123-45-6789"));
+            assert!(stored.windows(4).any(|window| window == b"visa"));
+            assert!(stored.windows(10).any(|window| window == b"$encrypted"));
+        }
+
+        assert_eq!(state.get_by_id("p1").unwrap().unwrap().data, patient);
+        assert_eq!(state.get().unwrap().len(), 2);
+
+        // Plain fields stay queryable and indexable
+        let filter: PathFilter = serde_json::from_str(r#"{"data.name": "Ana"}"#).unwrap();
+        assert_eq!(state.query(&filter).unwrap()[0].data["ssn"], "123-45-6789");
+        assert_eq!(state.create_index("by_name", &["data.name".to_string()]).unwrap(), 2);
+
+        // Projections and distinct values are decrypted like whole records
+        let fields = vec!["id".to_string(), "data.ssn".to_string()];
+        let rows = state.query_projected(&filter, &fields).unwrap();
+        assert_eq!(rows, vec![serde_json::json!({"id": "p1", "data": {"ssn": "123-45-6789"}})]);
+        assert_eq!(state.distinct("data.ssn").unwrap(), vec![serde_json::json!("123-45-6789")]);
+        assert_eq!(state.distinct("data.card.number").unwrap(), vec![serde_json::json!(4111)]);
+
+        // Without the key the markers are returned as stored
+        assert!(state.remove_field_encryption());
+        assert!(!state.remove_field_encryption());
+        let sealed = state.get_by_id("p1").unwrap().unwrap();
+        assert_eq!(sealed.data["name"], "Ana");
+        assert!(sealed.data["ssn"]["$encrypted"].is_string());
+
+        state.set_field_encryption(&["data.ssn"], &[9u8; 32]).unwrap();
+        assert!(matches!(state.get_by_id("p1"), Err(AppResponse::SerializationError(_))));
+
+        // A marker moved to another record fails authentication
+        state.remove_field_encryption();
+        let mut moved = create_test_model("p3", Some(serde_json::json!({"ssn": sealed.data["ssn"].clone()})));
+        state.post(moved.clone()).unwrap();
+        state.set_field_encryption(&["data.ssn"], &[1u8; 32]).unwrap();
+        assert!(matches!(state.get_by_id("p3"), Err(AppResponse::SerializationError(_))));
+        moved.data = serde_json::json!({"ssn": "987-65-4321"});
+        state.put(moved).unwrap();
+        assert_eq!(state.get_by_id("p3").unwrap().unwrap().data["ssn"], "987-65-4321");
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_ffi_field_encryption() {
+        use crate::{create_db, get_by_id, push_data, remove_field_encryption, set_field_encryption};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_field_encryption")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let paths = CString::new(r#"["data.ssn"]"#).unwrap();
+        let key = CString::new("01".repeat(32)).unwrap();
+        let result = unsafe { CString::from_raw(set_field_encryption(0, paths.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let bad_paths = CString::new("data.ssn").unwrap();
+        let result = unsafe { CString::from_raw(set_field_encryption(db_ptr, bad_paths.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(set_field_encryption(db_ptr, paths.as_ptr(), key.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Ok""#));
+
+        let model = create_test_model("p1", Some(serde_json::json!({"ssn": "123-45-6789"})));
+        let json = CString::new(serde_json::to_string(&model).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let id = CString::new("p1").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("123-45-6789"));
+
+        let result = unsafe { CString::from_raw(remove_field_encryption(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(!result.to_str().unwrap().contains("123-45-6789"));
+        assert!(result.to_str().unwrap().contains("$encrypted"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_purge_changelog() {
+        use crate::local_db_model::ChangelogCutoff;
+
+        let mut state = AppDbState::init(generate_unique_db_name("purge_changelog")).unwrap();
+        state.post(create_test_model("a", None)).unwrap();
+        state.post(create_test_model("b", None)).unwrap();
+        for consumer in ["search", "badge"] {
+            let delta = state.get_all_delta(consumer).unwrap();
+            state.ack_delta(consumer, delta.sequence).unwrap();
+        }
+
+        // "badge" stops acknowledging, so its changes stay logged
+        state.put(create_test_model("a", Some(serde_json::json!({"v": 2})))).unwrap();
+        state.delete_by_id("b").unwrap();
+        state.post(create_test_model("c", None)).unwrap();
+        let sequence = state.commit_sequence().unwrap();
+        state.ack_delta("search", sequence).unwrap();
+        assert_eq!(state.get_all_delta("badge").unwrap().deleted, vec!["b"]);
+
+        let purge = state.purge_changelog(ChangelogCutoff::Sequence(sequence)).unwrap();
+        assert_eq!(purge.purged, 2);
+        assert_eq!(purge.reset, vec!["badge"]);
+        assert_eq!(state.purge_changelog(ChangelogCutoff::Sequence(sequence)).unwrap().purged, 0);
+
+        let delta = state.get_all_delta("badge").unwrap();
+        assert!(delta.reset);
+        let ids: Vec<&str> = delta.records.iter().map(|record| record.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        let delta = state.get_all_delta("search").unwrap();
+        assert!(!delta.reset && delta.records.is_empty());
+
+        // The retention window purges by age during maintenance
+        state.post(create_test_model("d", None)).unwrap();
+        assert_eq!(state.run_maintenance(false, false).unwrap().changelog, None);
+        state.set_changelog_retention(Some(60_000));
+        assert_eq!(state.changelog_retention(), Some(60_000));
+        assert_eq!(state.run_maintenance(false, false).unwrap().changelog.unwrap().purged, 0);
+
+        state.set_clock_offset(120_000);
+        let purge = state.run_maintenance(false, false).unwrap().changelog.unwrap();
+        assert_eq!(purge.purged, 2);
+        assert_eq!(purge.reset, vec!["search"]);
+        assert!(state.get_all_delta("search").unwrap().reset);
+    }
+
+    #[test]
+    fn test_ffi_purge_changelog() {
+        use crate::{create_db, get_all_delta, purge_changelog, push_data, set_changelog_retention};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_purge_changelog")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let consumer = CString::new("search").unwrap();
+        unsafe { let _ = CString::from_raw(get_all_delta(db_ptr, consumer.as_ptr()) as *mut i8); }
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let before = CString::new(r#"{"age_ms": 86400000}"#).unwrap();
+        let result = unsafe { CString::from_raw(purge_changelog(db_ptr, before.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let purge: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(purge, serde_json::json!({"purged": 0, "reset": []}));
+
+        let before = CString::new(r#"{"sequence": 100}"#).unwrap();
+        let result = unsafe { CString::from_raw(purge_changelog(db_ptr, before.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"purged\":1"#));
+
+        let invalid = CString::new(r#"{"seconds": 5}"#).unwrap();
+        let result = unsafe { CString::from_raw(purge_changelog(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(set_changelog_retention(db_ptr, 86_400_000) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"86400000"}"#);
+
+        let result = unsafe { CString::from_raw(purge_changelog(0, before.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::{ConflictPolicy, ConflictResolution, RemoteChanges};
+
+        let state = AppDbState::init(generate_unique_db_name("conflict_policies")).unwrap();
+        let version = |id: &str, hash: &str, updated_at: Option<u64>| LocalDbModel {
+            hash: hash.to_string(),
+            ..create_test_model(id, updated_at.map(|updated_at| serde_json::json!({"updated_at": updated_at})))
+        };
+        for id in ["a", "b", "c", "d"] {
+            state.post(version(id, id, Some(10))).unwrap();
+        }
+        let synced = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", synced.sequence).unwrap();
+
+        for id in ["a", "b", "c"] {
+            state.put(version(id, &format!("{id}2"), Some(20))).unwrap();
+        }
+        state.delete_by_id("d").unwrap();
+
+        let remote = |records: Vec<LocalDbModel>| RemoteChanges { consumer: "sync".to_string(), records, deleted: Vec::new() };
+        let pulled = remote(vec![version("a", "a3", Some(30)), version("b", "b3", Some(15)), version("c", "c3", None), version("d", "d3", Some(5))]);
+
+        let invalid = ConflictPolicy::LastWriteWins("updated_at".to_string());
+        assert!(matches!(state.apply_remote_changes(&pulled, &invalid, |_| Ok(None)), Err(AppResponse::BadRequest(_))));
+
+        // Newer server versions win; a deletion or a missing timestamp loses
+        let policy = ConflictPolicy::LastWriteWins("data.updated_at".to_string());
+        let result = state.apply_remote_changes(&pulled, &policy, |_| Ok(None)).unwrap();
+        assert_eq!(result.resolved, 4);
+        assert!(result.conflicts.is_empty());
+        let hashes: Vec<String> = state.get().unwrap().into_iter().map(|record| record.hash).collect();
+        assert_eq!(hashes, vec!["a3", "b2", "c2", "d3"]);
+
+        state.put(version("a", "a4", None)).unwrap();
+        let result = state.apply_remote_changes(&remote(vec![version("a", "a5", None)]), &ConflictPolicy::KeepRemote, |_| Ok(None)).unwrap();
+        assert_eq!(result.resolved, 1);
+        assert_eq!(state.get_by_id("a").unwrap().unwrap().hash, "a5");
+
+        state.put(version("a", "a6", None)).unwrap();
+        let result = state.apply_remote_changes(&remote(vec![version("a", "a7", None)]), &ConflictPolicy::KeepLocal, |_| Ok(None)).unwrap();
+        assert_eq!(result.resolved, 1);
+        assert_eq!(state.get_by_id("a").unwrap().unwrap().hash, "a6");
+
+        // The callback merges one conflict and sends the other to the inbox
+        let mut asked = Vec::new();
+        let pulled = remote(vec![version("b", "b4", None), version("c", "c4", None)]);
+        let result = state
+            .apply_remote_changes(&pulled, &ConflictPolicy::Callback, |conflict| {
+                asked.push((conflict.id.clone(), conflict.local.as_ref().unwrap().hash.clone()));
+                Ok((conflict.id == "b").then(|| ConflictResolution::Merged(version("b", "merged", None))))
+            })
+            .unwrap();
+        assert_eq!(asked, vec![("b".to_string(), "b2".to_string()), ("c".to_string(), "c2".to_string())]);
+        assert_eq!((result.resolved, result.conflicts), (1, vec!["c".to_string()]));
+        assert_eq!(state.get_by_id("b").unwrap().unwrap().hash, "merged");
+        assert_eq!(state.list_conflicts().unwrap()[0].remote.as_ref().unwrap().hash, "c4");
+
+        // Conflicts already in the inbox stay there
+        let result = state.apply_remote_changes(&remote(vec![version("c", "c5", None)]), &ConflictPolicy::KeepRemote, |_| Ok(None)).unwrap();
+        assert_eq!((result.resolved, result.conflicts), (0, vec!["c".to_string()]));
+    }
+
+    #[test]
+    fn test_ffi_apply_remote_changes() {
+        use crate::{apply_remote_changes, create_db, get_by_id, push_data};
+        use std::os::raw::c_char;
+
+        extern "C" fn keep_remote(conflict_json: *const c_char) -> *const c_char {
+            let conflict = unsafe { std::ffi::CStr::from_ptr(conflict_json) }.to_str().unwrap();
+            assert!(conflict.contains(r#""id":"a""#));
+            c"\"remote\"".as_ptr()
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_apply_remote_changes")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let changes = CString::new(r#"{"records":[{"id":"a","hash":"a2","data":{}}]}"#).unwrap();
+        let policy = CString::new("\"callback\"").unwrap();
+        let result = unsafe { CString::from_raw(apply_remote_changes(db_ptr, changes.as_ptr(), policy.as_ptr(), None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let invalid = CString::new("\"newest\"").unwrap();
+        let result = unsafe { CString::from_raw(apply_remote_changes(db_ptr, changes.as_ptr(), invalid.as_ptr(), None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(apply_remote_changes(db_ptr, changes.as_ptr(), policy.as_ptr(), Some(keep_remote)) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"applied\":0,\"deleted\":0,\"unchanged\":0,\"conflicts\":[],\"resolved\":1}"}"#);
+        let id = CString::new("a").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"hash\":\"a2\""#));
+
+        let result = unsafe { CString::from_raw(apply_remote_changes(0, changes.as_ptr(), policy.as_ptr(), None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_hlc_timestamps() {
+        use crate::local_db_model::{Hlc, WriteOp};
+
+        let state = AppDbState::init(generate_unique_db_name("hlc_timestamps")).unwrap();
+        assert_eq!(state.last_hlc().unwrap(), Hlc::default());
+        state.set_fixed_clock(Some(1_760_000_000_000));
+
+        state.post(create_test_model("a", None)).unwrap();
+        let a = state.record_hlc("a").unwrap().unwrap();
+        assert_eq!(a, Hlc { physical_ms: 1_760_000_000_000, counter: 0 });
+
+        // Writes within the same millisecond are ordered by the counter
+        state.post(create_test_model("b", None)).unwrap();
+        let b = state.record_hlc("b").unwrap().unwrap();
+        assert_eq!(b, Hlc { physical_ms: 1_760_000_000_000, counter: 1 });
+        assert_eq!(state.last_hlc().unwrap(), b);
+
+        // One transaction, one timestamp
+        let ops = [
+            WriteOp::Put { collection: None, record: create_test_model("c", None) },
+            WriteOp::Put { collection: None, record: create_test_model("d", None) },
+        ];
+        state.write_transaction(&ops).unwrap();
+        let c = state.record_hlc("c").unwrap().unwrap();
+        assert_eq!(state.record_hlc("d").unwrap(), Some(c));
+        assert!(c > b);
+
+        // The clock going backwards does not reorder writes
+        state.set_fixed_clock(Some(1_700_000_000_000));
+        state.put(create_test_model("a", Some(serde_json::json!({"v": 2})))).unwrap();
+        let rewritten = state.record_hlc("a").unwrap().unwrap();
+        assert_eq!(rewritten, Hlc { physical_ms: 1_760_000_000_000, counter: 3 });
+
+        // Writes after a remote change order after it
+        let remote = Hlc { physical_ms: 1_800_000_000_000, counter: 7 };
+        let observed = state.observe_hlc(remote).unwrap();
+        assert_eq!(observed, Hlc { physical_ms: 1_800_000_000_000, counter: 8 });
+        state.post(create_test_model("e", None)).unwrap();
+        assert!(state.record_hlc("e").unwrap().unwrap() > remote);
+        assert!(state.observe_hlc(Hlc { physical_ms: 1 << 48, counter: 0 }).is_err());
+
+        state.delete_by_id("b").unwrap();
+        assert_eq!(state.record_hlc("b").unwrap(), None);
+        state.clear_all_records().unwrap();
+        assert_eq!(state.record_hlc("a").unwrap(), None);
+        assert!(state.last_hlc().unwrap() > remote);
+    }
+
+    #[test]
+    fn test_ffi_hlc() {
+        use crate::{create_db, get_hlc, get_record_hlc, observe_hlc, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_hlc")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("a").unwrap();
+        let result = unsafe { CString::from_raw(get_record_hlc(db_ptr, id.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        let result = unsafe { CString::from_raw(get_hlc(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(response["Ok"].as_str().unwrap()).unwrap(), record);
+
+        let missing = CString::new("missing").unwrap();
+        let result = unsafe { CString::from_raw(get_record_hlc(db_ptr, missing.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let remote = CString::new(r#"{"physical_ms": 250000000000000, "counter": 4}"#).unwrap();
+        let result = unsafe { CString::from_raw(observe_hlc(db_ptr, remote.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"physical_ms\":250000000000000,\"counter\":5}"}"#);
+
+        let invalid = CString::new(r#"{"physical_ms": "now"}"#).unwrap();
+        let result = unsafe { CString::from_raw(observe_hlc(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(get_hlc(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_sync_digest() {
+        let local = AppDbState::init(generate_unique_db_name("sync_digest_local")).unwrap();
+        let remote = AppDbState::init(generate_unique_db_name("sync_digest_remote")).unwrap();
+
+        // The local digest is kept current by the writes that follow
+        let empty = local.sync_digest().unwrap();
+        assert_eq!(empty.records, 0);
+        assert_eq!(empty.buckets.len(), 256);
+        for id in ["a", "b", "c", "d"] {
+            local.post(create_test_model(id, None)).unwrap();
+        }
+        local.put(create_test_model("a", Some(serde_json::json!({"v": 2})))).unwrap();
+        local.delete_by_id("d").unwrap();
+
+        // The remote digest is built from its records on first request
+        for id in ["a", "b", "c"] {
+            remote.post(create_test_model(id, None)).unwrap();
+        }
+        let digest = remote.sync_digest().unwrap();
+        assert_eq!(digest.records, 3);
+        assert!(local.diff_sync_digest(&digest).unwrap().is_empty());
+        assert_eq!(local.sync_digest().unwrap(), digest);
+
+        let mut changed = create_test_model("b", None);
+        changed.hash = "hash_b2".to_string();
+        remote.put(changed).unwrap();
+        let buckets = local.diff_sync_digest(&remote.sync_digest().unwrap()).unwrap();
+        assert_eq!(buckets.len(), 1);
+        let records = local.sync_digest_records(&buckets).unwrap();
+        assert_eq!(records.get("b").map(String::as_str), Some("hash_b"));
+        assert_eq!(remote.sync_digest_records(&buckets).unwrap().get("b").map(String::as_str), Some("hash_b2"));
+
+        assert!(local.sync_digest_records(&[256]).is_err());
+        assert!(local.diff_sync_digest(&Default::default()).is_err());
+
+        local.clear_all_records().unwrap();
+        assert_eq!(local.sync_digest().unwrap(), empty);
+    }
+
+    #[test]
+    fn test_ffi_sync_digest() {
+        use crate::{create_db, diff_sync_digest, get_sync_digest, get_sync_digest_records, push_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sync_digest")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_sync_digest(db_ptr) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let empty = response["Ok"].as_str().unwrap().to_string();
+
+        let json = CString::new(serde_json::to_string(&create_test_model("a", None)).unwrap()).unwrap();
+        unsafe { let _ = CString::from_raw(push_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let remote = CString::new(empty).unwrap();
+        let result = unsafe { CString::from_raw(diff_sync_digest(db_ptr, remote.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let buckets = response["Ok"].as_str().unwrap().to_string();
+        assert_eq!(serde_json::from_str::<Vec<usize>>(&buckets).unwrap().len(), 1);
+
+        let buckets = CString::new(buckets).unwrap();
+        let result = unsafe { CString::from_raw(get_sync_digest_records(db_ptr, buckets.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"a\":\"hash_a\"}"}"#);
+
+        let invalid = CString::new(r#"{"root": 5}"#).unwrap();
+        let result = unsafe { CString::from_raw(diff_sync_digest(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(get_sync_digest(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+    /// In-memory sync server shared by the devices of a test, serving one
+    /// change per page and no device its own changes.
+    #[derive(Default)]
+    struct MemoryServer {
+        log: Vec<(&'static str, String, Option<LocalDbModel>)>,
+    }
+
+    struct MemoryAdapter<'a> {
+        server: &'a std::cell::RefCell<MemoryServer>,
+        device: &'static str,
+        policy: crate::local_db_model::ConflictPolicy,
+        fail_pull: bool,
+    }
+
+    impl crate::sync_engine::SyncAdapter for MemoryAdapter<'_> {
+        fn pull(&mut self, cursor: Option<&str>) -> Result<crate::local_db_model::PullPage, crate::app_response::AppResponse> {
+            if self.fail_pull {
+                return Err(crate::app_response::AppResponse::DatabaseError("Server unreachable".to_string()));
+            }
+            let server = self.server.borrow();
+            let next = cursor.map_or(0, |cursor| cursor.parse::<usize>().unwrap());
+            let mut page = crate::local_db_model::PullPage { has_more: next + 1 < server.log.len(), ..Default::default() };
+            if let Some((device, id, record)) = server.log.get(next) {
+                match record {
+                    _ if *device == self.device => {}
+                    Some(record) => page.records.push(record.clone()),
+                    None => page.deleted.push(id.clone()),
+                }
+                page.cursor = Some((next + 1).to_string());
+            }
+            Ok(page)
+        }
+
+        fn push(&mut self, changes: &crate::local_db_model::RemoteChanges) -> Result<(), crate::app_response::AppResponse> {
+            let mut server = self.server.borrow_mut();
+            let pushed = changes.records.iter().map(|record| (record.id.clone(), Some(record.clone())));
+            for (id, record) in pushed.chain(changes.deleted.iter().map(|id| (id.clone(), None))) {
+                // Versions the server already holds are not logged again
+                let hash = |record: &Option<LocalDbModel>| record.as_ref().map(|record| record.hash.clone());
+                let latest = server.log.iter().rev().find(|(_, logged, _)| *logged == id);
+                if latest.is_none_or(|(_, _, logged)| hash(logged) != hash(&record)) {
+                    server.log.push((self.device, id, record));
+                }
+            }
+            Ok(())
+        }
+
+        fn conflict_policy(&self) -> crate::local_db_model::ConflictPolicy {
+            self.policy.clone()
+        }
+    }
+
+    #[test]
+    fn test_sync_now() {
+        use crate::local_db_model::ConflictPolicy;
+
+        let server = std::cell::RefCell::new(MemoryServer::default());
+        let adapter = |device| MemoryAdapter { server: &server, device, policy: ConflictPolicy::Inbox, fail_pull: false };
+        let phone = AppDbState::init(generate_unique_db_name("sync_now_phone")).unwrap();
+        let tablet = AppDbState::init(generate_unique_db_name("sync_now_tablet")).unwrap();
+
+        phone.post(create_test_model("n1", None)).unwrap();
+        phone.post(create_test_model("n2", None)).unwrap();
+        let run = phone.sync_now(&mut adapter("phone")).unwrap();
+        assert_eq!(run.pushed, 2);
+        assert_eq!(run.error, None);
+        assert_eq!(server.borrow().log.len(), 2);
+
+        // Pulled page by page, resuming after the stored cursor
+        let run = tablet.sync_now(&mut adapter("tablet")).unwrap();
+        assert_eq!(run.pulled, 2);
+        assert_eq!(tablet.get_by_id("n2").unwrap().unwrap().hash, "hash_n2");
+        assert_eq!(tablet.sync_cursor().unwrap().as_deref(), Some("2"));
+        assert_eq!(server.borrow().log.len(), 2);
+
+        // Both devices edit n1; the tablet syncs last and gets the conflict
+        let edit = |db: &AppDbState, hash: &str| {
+            db.put(LocalDbModel { hash: hash.to_string(), ..create_test_model("n1", None) }).unwrap();
+        };
+        edit(&phone, "phone_edit");
+        edit(&tablet, "tablet_edit");
+        assert_eq!(phone.sync_now(&mut adapter("phone")).unwrap().pushed, 1);
+        let run = tablet.sync_now(&mut adapter("tablet")).unwrap();
+        assert_eq!(run.conflicts, 1);
+        assert_eq!(tablet.list_conflicts().unwrap()[0].id, "n1");
+        assert_eq!(server.borrow().log.len(), 3);
+
+        // The conflicting edit is held back until the conflict is settled
+        assert!(tablet.get_all_delta("sync").unwrap().records.iter().any(|record| record.id == "n1"));
+
+        // A failed step finishes the run with its error
+        let mut offline = MemoryAdapter { fail_pull: true, ..adapter("phone") };
+        assert!(phone.sync_now(&mut offline).is_err());
+        let status = phone.sync_status().unwrap();
+        assert!(status.running.is_none());
+        assert!(status.last_run.unwrap().error.unwrap().contains("Server unreachable"));
+    }
+
+    #[test]
+    fn test_sync_now_batches() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::{PullPage, RemoteChanges, SyncBatch, SyncLimits};
+
+        #[derive(Default)]
+        struct BatchAdapter {
+            pushes: Vec<RemoteChanges>,
+            progress: Vec<(usize, usize)>,
+        }
+
+        impl crate::sync_engine::SyncAdapter for BatchAdapter {
+            fn pull(&mut self, _cursor: Option<&str>) -> Result<PullPage, AppResponse> {
+                Ok(PullPage::default())
+            }
+
+            fn push(&mut self, changes: &RemoteChanges) -> Result<(), AppResponse> {
+                self.pushes.push(changes.clone());
+                Ok(())
+            }
+
+            fn batch_pushed(&mut self, _batch: &SyncBatch, done: usize, total: usize) {
+                self.progress.push((done, total));
+            }
+        }
+
+        let mut state = AppDbState::init(generate_unique_db_name("sync_now_batches")).unwrap();
+        for i in 0..5 {
+            state.post(create_test_model(&format!("b{i}"), Some(serde_json::json!({"text": "x".repeat(100)})))).unwrap();
+        }
+        state.post(create_test_model("big", Some(serde_json::json!({"text": "x".repeat(2000)})))).unwrap();
+        state.set_sync_limits(SyncLimits { max_batch_bytes: Some(400), max_record_bytes: Some(1000) });
+
+        let mut adapter = BatchAdapter::default();
+        let run = state.sync_now(&mut adapter).unwrap();
+        assert_eq!(adapter.pushes.len(), 3);
+        assert_eq!(adapter.progress, vec![(1, 3), (2, 3), (3, 3)]);
+        let mut sent = 0;
+        for push in &adapter.pushes {
+            let bytes: usize = push.records.iter().map(|record| serde_json::to_string(record).unwrap().len()).sum();
+            assert!(bytes <= 400);
+            sent += bytes as u64;
+        }
+        let pushed: Vec<&str> = adapter.pushes.iter().flat_map(|push| &push.records).map(|record| record.id.as_str()).collect();
+        assert_eq!(pushed, vec!["b0", "b1", "b2", "b3", "b4"]);
+
+        // The oversized record stays pending and is reported as failed
+        assert_eq!(run.pushed, 5);
+        assert_eq!(run.bytes_sent, sent);
+        assert_eq!(run.failed, vec!["big"]);
+        assert!(state.get_all_delta("sync").unwrap().records.iter().any(|record| record.id == "big"));
+
+        state.set_sync_limits(SyncLimits::default());
+        let mut adapter = BatchAdapter::default();
+        let run = state.sync_now(&mut adapter).unwrap();
+        assert_eq!(adapter.pushes.len(), 1);
+        assert!(run.failed.is_empty());
+        assert!(state.get_all_delta("sync").unwrap().records.is_empty());
+    }
+
+    #[test]
+    fn test_ffi_sync_now() {
+        use crate::{create_db, sync_now};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_sync_now")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let invalid = CString::new(r#"{"headers": {}}"#).unwrap();
+        let result = unsafe { CString::from_raw(sync_now(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let config = CString::new(r#"{"url": "ftp://example.com/sync"}"#).unwrap();
+        let result = unsafe { CString::from_raw(sync_now(db_ptr, config.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(sync_now(0, config.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(feature = "http-sync")]
+    #[test]
+    fn test_http_sync_adapter() {
+        use crate::http_sync::HttpSyncAdapter;
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // Answers a pull with one record, then accepts a push
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sync", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let answer = match request_line.starts_with("GET") {
+                    true => r#"{"records":[{"id":"s1","hash":"hash_s1","data":{}}],"cursor":"c1"}"#,
+                    false => "{}",
+                };
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{answer}", answer.len()).unwrap();
+                requests.push((request_line, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+
+        let state = AppDbState::init(generate_unique_db_name("http_sync_adapter")).unwrap();
+        state.post(create_test_model("l1", None)).unwrap();
+        let config = serde_json::from_value(serde_json::json!({"url": url, "headers": {"Authorization": "Bearer t"}})).unwrap();
+        let run = state.sync_now(&mut HttpSyncAdapter::new(config).unwrap()).unwrap();
+        assert_eq!(run.error, None);
+        assert_eq!(run.pulled, 1);
+        assert_eq!(state.sync_cursor().unwrap().as_deref(), Some("c1"));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].0.starts_with("GET /sync/pull "));
+        assert!(requests[1].0.starts_with("POST /sync/push "));
+        let pushed: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+        let ids: Vec<&str> = pushed["records"].as_array().unwrap().iter().map(|record| record["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["l1", "s1"]);
+    }
+
+    #[test]
+    fn test_outbox() {
+        use crate::app_response::AppResponse;
+        use serde_json::json;
+
+        let db_name = generate_unique_db_name("outbox");
+        let state = AppDbState::init(db_name.clone()).unwrap();
+
+        assert!(state.peek_outbox(10).unwrap().is_empty());
+        assert!(matches!(state.enqueue_outbox(&serde_json::Value::Null), Err(AppResponse::BadRequest(_))));
+        for text in ["a", "b", "c"] {
+            state.enqueue_outbox(&json!({"method": "POST", "body": {"text": text}})).unwrap();
+        }
+
+        let entries = state.peek_outbox(2).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.sequence).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(entries[1].request["body"]["text"], "b");
+        assert!(matches!(state.ack_outbox(4), Err(AppResponse::BadRequest(_))));
+        assert_eq!(state.ack_outbox(2).unwrap(), 2);
+        assert_eq!(state.ack_outbox(2).unwrap(), 0);
+
+        // Pending entries survive a restart and sequences keep growing
+        drop(state);
+        let state = AppDbState::init(db_name).unwrap();
+        let entries = state.peek_outbox(10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].request["body"]["text"], "c");
+        assert_eq!(state.ack_outbox(3).unwrap(), 1);
+        assert_eq!(state.enqueue_outbox(&json!("retry")).unwrap().sequence, 4);
+    }
+
+    #[test]
+    fn test_ffi_outbox() {
+        use crate::{ack_outbox, create_db, enqueue_outbox, peek_outbox};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_outbox")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let request = CString::new(r#"{"method":"DELETE","path":"/orders/7"}"#).unwrap();
+        let result = unsafe { CString::from_raw(enqueue_outbox(db_ptr, request.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"sequence\":1,"#));
+
+        let invalid = CString::new("{not json").unwrap();
+        let result = unsafe { CString::from_raw(enqueue_outbox(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(peek_outbox(db_ptr, 10) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("/orders/7"));
+
+        let result = unsafe { CString::from_raw(ack_outbox(db_ptr, 1) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#""Ok":"1""#));
+
+        let result = unsafe { CString::from_raw(peek_outbox(0, 10) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_delta_encoding() {
+        use crate::app_response::AppResponse;
+        use crate::versions::VERSIONS_DB_NAME;
+        use lmdb::Transaction;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("delta_encoding")).unwrap();
+        let version = |hash: &str, data: serde_json::Value| LocalDbModel { id: "n1".to_string(), hash: hash.to_string(), data, ..Default::default() };
+        let stored_versions = |state: &AppDbState| {
+            let (env, versions_db) = state.side_db(VERSIONS_DB_NAME).unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            let cursor = txn.open_ro_cursor(versions_db).unwrap();
+            let count = crate::scan::scan_from(&cursor, None).count();
+            count
+        };
+
+        assert!(matches!(state.set_delta_encoding(Some(0)), Err(AppResponse::BadRequest(_))));
+        state.set_delta_encoding(Some(3)).unwrap();
+        assert_eq!(state.delta_encoding().unwrap(), Some(3));
+
+        // Versions are kept while a consumer exists
+        assert!(state.get_encoded_delta("sync").unwrap().reset);
+        let base = version("h1", json!({"title": "a", "body": "long text", "tags": [1]}));
+        state.post(base.clone()).unwrap();
+        let delta = state.get_encoded_delta("sync").unwrap();
+        assert_eq!(delta.records.len(), 1);
+        state.ack_delta("sync", delta.sequence).unwrap();
+
+        // Updates since the acknowledged version come as one patch
+        state.put(version("h2", json!({"title": "b", "body": "long text", "tags": [1]}))).unwrap();
+        state.put(version("h3", json!({"title": "c", "body": "long text"}))).unwrap();
+        state.post(create_test_model("n2", None)).unwrap();
+        let delta = state.get_encoded_delta("sync").unwrap();
+        assert_eq!(delta.records.iter().map(|record| record.id.as_str()).collect::<Vec<_>>(), vec!["n2"]);
+        assert_eq!(delta.patches.len(), 1);
+        assert_eq!(delta.patches[0].base_hash, "h1");
+        assert_eq!(delta.patches[0].patch, json!({"title": "c", "tags": null}));
+        let patched = delta.patches[0].apply(&base).unwrap();
+        assert_eq!((patched.hash, patched.data), ("h3".to_string(), json!({"title": "c", "body": "long text"})));
+        assert!(matches!(delta.patches[0].apply(&create_test_model("n1", None)), Err(AppResponse::BadRequest(_))));
+        state.ack_delta("sync", delta.sequence).unwrap();
+
+        // Acknowledged versions are pruned on the next write
+        for round in 0..5 {
+            state.put(version(&format!("r{round}"), json!({"title": round, "body": "long text"}))).unwrap();
+            let delta = state.get_encoded_delta("sync").unwrap();
+            assert_eq!(delta.patches[0].patch, json!({"title": round}));
+            state.ack_delta("sync", delta.sequence).unwrap();
+        }
+        // n1 keeps its acknowledged and its last version, n2 its only one
+        assert_eq!(stored_versions(&state), 3);
+
+        // Null members cannot be expressed as a merge patch
+        state.put(version("n", json!({"title": null, "body": "long text"}))).unwrap();
+        let delta = state.get_encoded_delta("sync").unwrap();
+        assert!(delta.patches.is_empty());
+        assert_eq!(delta.records[0].hash, "n");
+        state.ack_delta("sync", delta.sequence).unwrap();
+
+        state.set_delta_encoding(None).unwrap();
+        assert_eq!(stored_versions(&state), 0);
+        state.put(version("h4", json!({"title": "d"}))).unwrap();
+        let delta = state.get_encoded_delta("sync").unwrap();
+        assert!(delta.patches.is_empty());
+        assert_eq!(delta.records.len(), 1);
+    }
+
+    #[test]
+    fn test_ffi_delta_encoding() {
+        use crate::{create_db, get_encoded_delta, post_data, set_delta_encoding};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_delta_encoding")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_delta_encoding(db_ptr, 8) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#""Ok":"8""#));
+
+        let record = CString::new(r#"{"id":"n1","hash":"h1","data":{"title":"a"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, record.as_ptr()) as *mut i8); }
+        let consumer = CString::new("sync").unwrap();
+        let result = unsafe { CString::from_raw(get_encoded_delta(db_ptr, consumer.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"patches\":[]"#));
+
+        let result = unsafe { CString::from_raw(set_delta_encoding(0, 8) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(get_encoded_delta(0, consumer.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_crdt_merge() {
+        use crate::local_db_model::RemoteChanges;
+        use serde_json::json;
+
+        let a = AppDbState::init(generate_unique_db_name("crdt_a")).unwrap();
+        let b = AppDbState::init(generate_unique_db_name("crdt_b")).unwrap();
+        assert!(!a.crdt_mode().unwrap());
+        a.set_crdt_mode(true).unwrap();
+        b.set_crdt_mode(true).unwrap();
+        assert!(a.crdt_mode().unwrap());
+
+        // Swaps the pending changes of both devices, as a sync through a
+        // server would
+        let exchange = |a: &AppDbState, b: &AppDbState| {
+            let (from_a, from_b) = (a.get_all_delta("sync").unwrap(), b.get_all_delta("sync").unwrap());
+            let merged: Vec<usize> = [(a, from_b), (b, from_a)]
+                .into_iter()
+                .map(|(to, delta)| {
+                    let changes = RemoteChanges { consumer: "sync".to_string(), records: delta.records, deleted: delta.deleted };
+                    let result = to.merge_remote(&changes).unwrap();
+                    assert!(result.conflicts.is_empty());
+                    to.ack_delta("sync", to.get_all_delta("sync").unwrap().sequence).unwrap();
+                    result.resolved
+                })
+                .collect();
+            let (a, b) = (a.get_by_id("n1").unwrap().unwrap(), b.get_by_id("n1").unwrap().unwrap());
+            assert_eq!((&a.hash, &a.data), (&b.hash, &b.data));
+            (merged, a)
+        };
+        let record = |hash: &str, data: serde_json::Value| LocalDbModel { id: "n1".to_string(), hash: hash.to_string(), data, ..Default::default() };
+        let fields = |mut record: LocalDbModel| {
+            record.data.as_object_mut().unwrap().remove("$clock");
+            record.data
+        };
+
+        a.post(record("h1", json!({"title": "Groceries", "done": false}))).unwrap();
+        assert_eq!(a.get_by_id("n1").unwrap().unwrap().data["$clock"].as_object().unwrap().len(), 2);
+        exchange(&a, &b);
+
+        // Edits of different fields are both kept, the same way on both devices
+        a.put(record("h2", json!({"title": "Shopping", "done": false}))).unwrap();
+        b.put(record("h3", json!({"title": "Groceries", "done": true}))).unwrap();
+        let (merged, synced) = exchange(&a, &b);
+        assert_eq!(merged, vec![1, 1]);
+        assert!(synced.hash.starts_with("crdt_"));
+        assert_eq!(fields(synced), json!({"title": "Shopping", "done": true}));
+
+        // The later edit of a field wins
+        a.put(record("h4", json!({"title": "Old", "done": true}))).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        b.put(record("h5", json!({"title": "New", "done": true}))).unwrap();
+        let (_, synced) = exchange(&a, &b);
+        assert_eq!(fields(synced), json!({"title": "New", "done": true}));
+
+        // An edit wins over a deletion
+        a.delete_by_id("n1").unwrap();
+        b.put(record("h6", json!({"title": "New", "done": false}))).unwrap();
+        let (merged, synced) = exchange(&a, &b);
+        assert_eq!(merged, vec![1, 1]);
+        assert_eq!(fields(synced), json!({"title": "New", "done": false}));
+        assert!(a.list_conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_crdt_mode() {
+        use crate::{create_db, get_by_id, push_data, set_crdt_mode};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_crdt_mode")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_crdt_mode(db_ptr, true) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        let model = CString::new(r#"{"id":"n1","hash":"h1","data":{"title":"Groceries"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(push_data(db_ptr, model.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+        let id = CString::new("n1").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("$clock"));
+
+        let result = unsafe { CString::from_raw(set_crdt_mode(db_ptr, false) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"false"}"#);
+
+        let result = unsafe { CString::from_raw(set_crdt_mode(0, true) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_ffi_watch_port() {
+        use crate::dart_port::{DartCObject, DART_COBJECT_STRING};
+        use crate::{create_db, post_data, register_dart_post_cobject, unwatch, watch_port};
+        use std::ffi::c_void;
+        use std::sync::Mutex;
+
+        static POSTED: Mutex<Vec<(i64, String)>> = Mutex::new(Vec::new());
+        unsafe extern "C" fn post(port: i64, message: *mut c_void) -> bool {
+            let object = unsafe { &*(message as *const DartCObject) };
+            assert_eq!(object.kind, DART_COBJECT_STRING);
+            let text = unsafe { std::ffi::CStr::from_ptr(object.value.as_string) }.to_str().unwrap().to_string();
+            POSTED.lock().unwrap().push((port, text));
+            true
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_watch_port")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+        let prefix = CString::new("todo:").unwrap();
+
+        let result = unsafe { CString::from_raw(register_dart_post_cobject(None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(register_dart_post_cobject(Some(post)) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Ok"));
+
+        let result = unsafe { CString::from_raw(watch_port(db_ptr, prefix.as_ptr(), 5, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(watch_port(db_ptr, prefix.as_ptr(), 5, 42) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let watch_id: u64 = response["Ok"].as_str().unwrap().parse().unwrap();
+
+        let json = CString::new(r#"{"id":"todo:1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while POSTED.lock().unwrap().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "watch did not post");
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let (port, message) = POSTED.lock().unwrap()[0].clone();
+        assert_eq!(port, 42);
+        let response: serde_json::Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), format!(r#"{{"watch_id":{watch_id},"ids":["todo:1"],"cleared":false}}"#));
+
+        let result = unsafe { CString::from_raw(unwatch(db_ptr, watch_id) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        let result = unsafe { CString::from_raw(watch_port(0, prefix.as_ptr(), 5, 42) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_change_tokens() {
+        use crate::app_response::AppResponse;
+
+        let state = AppDbState::init(generate_unique_db_name("change_tokens")).unwrap();
+        let rendered = state.change_token().unwrap();
+        let summary = state.changes_between(rendered, state.change_token().unwrap()).unwrap();
+        assert!(!summary.changed);
+
+        state.post(create_test_model("a", None)).unwrap();
+        state.post(create_test_model("b", None)).unwrap();
+        state.delete_by_id("a").unwrap();
+        let now = state.change_token().unwrap();
+        let summary = state.changes_between(rendered, now).unwrap();
+        assert!(summary.changed);
+        assert_eq!((summary.from, summary.to, summary.commits), (rendered, now, 3));
+
+        // Reads do not change the token
+        state.get().unwrap();
+        assert_eq!(state.change_token().unwrap(), now);
+
+        assert!(matches!(state.changes_between(now, rendered), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.changes_between(rendered, now + 1), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_ffi_change_tokens() {
+        use crate::{create_db, get_change_token, get_changes_between, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_change_tokens")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(get_change_token(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"0"}"#);
+
+        let json = CString::new(r#"{"id":"t1","hash":"h","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let result = unsafe { CString::from_raw(get_change_token(db_ptr) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let result = unsafe { CString::from_raw(get_changes_between(db_ptr, 0, 1) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"from":0,"to":1,"changed":true,"commits":1}"#);
+
+        let result = unsafe { CString::from_raw(get_changes_between(db_ptr, 1, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        let result = unsafe { CString::from_raw(get_change_token(0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_query_subscriptions() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::SubscriptionEvent;
+        use crate::query::PathFilter;
+        use serde_json::json;
+        use std::sync::{Arc, Mutex};
+
+        let state = AppDbState::init(generate_unique_db_name("subscriptions")).unwrap();
+        let todo = |id: &str, hash: &str, done: bool| LocalDbModel { id: id.to_string(), hash: hash.to_string(), data: json!({"done": done}), ..Default::default() };
+        state.post(todo("todo:1", "a", false)).unwrap();
+        state.post(todo("todo:2", "a", true)).unwrap();
+
+        let events: Arc<Mutex<Vec<SubscriptionEvent>>> = Arc::default();
+        let received = Arc::clone(&events);
+        let filter: PathFilter = serde_json::from_str(r#"{"data.done": false}"#).unwrap();
+        let open = state
+            .subscribe("open", filter.clone(), std::time::Duration::from_millis(5), move |event| received.lock().unwrap().push(event.clone()))
+            .unwrap();
+        assert_eq!(open.iter().map(|record| record.id.as_str()).collect::<Vec<_>>(), vec!["todo:1"]);
+        assert_eq!(state.subscriptions(), vec!["open".to_string()]);
+
+        let next_event = || {
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            loop {
+                if let Some(event) = events.lock().unwrap().pop() {
+                    return event;
+                }
+                assert!(std::time::Instant::now() < deadline, "subscription did not fire");
+                thread::sleep(std::time::Duration::from_millis(5));
+            }
+        };
+        let ids = |records: &[LocalDbModel]| records.iter().map(|record| record.id.clone()).collect::<Vec<_>>();
+
+        state.put(todo("todo:1", "b", true)).unwrap();
+        let event = next_event();
+        assert_eq!((event.name.as_str(), event.removed), ("open", vec!["todo:1".to_string()]));
+
+        let put = |record: LocalDbModel| crate::local_db_model::WriteOp::Put { collection: None, record };
+        state.write_transaction(&[put(todo("todo:2", "b", false)), put(todo("todo:3", "a", false)), put(todo("todo:4", "a", true))]).unwrap();
+        // One transaction, one event
+        let event = next_event();
+        assert_eq!(ids(&event.added), vec!["todo:2", "todo:3"]);
+        assert!(event.updated.is_empty() && event.removed.is_empty());
+
+        state.put(todo("todo:3", "b", false)).unwrap();
+        let event = next_event();
+        assert_eq!((ids(&event.updated), event.updated[0].hash.as_str()), (vec!["todo:3".to_string()], "b"));
+
+        // Writes outside the result set do not fire
+        state.put(todo("todo:4", "b", true)).unwrap();
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(events.lock().unwrap().is_empty());
+
+        state.clear_all_records().unwrap();
+        assert_eq!(next_event().removed, vec!["todo:2".to_string(), "todo:3".to_string()]);
+
+        assert!(matches!(state.subscribe("", filter, std::time::Duration::ZERO, |_| {}), Err(AppResponse::BadRequest(_))));
+        assert!(state.unsubscribe("open"));
+        assert!(!state.unsubscribe("open"));
+        state.post(todo("todo:5", "a", false)).unwrap();
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_subscribe_query() {
+        use crate::{create_db, free_c_string, post_data, subscribe_query, unsubscribe_query};
+        use std::os::raw::c_char;
+        use std::sync::Mutex;
+
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        extern "C" fn on_event(event_json: *const c_char) {
+            let json = unsafe { std::ffi::CStr::from_ptr(event_json) }.to_str().unwrap().to_string();
+            EVENTS.lock().unwrap().push(json);
+            free_c_string(event_json);
+        }
+
+        let db_name = CString::new(generate_unique_db_name("ffi_subscribe_query")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"s1","hash":"h","data":{"done":false}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let name = CString::new("open").unwrap();
+        let filter = CString::new(r#"{"data.done": false}"#).unwrap();
+        let result = unsafe { CString::from_raw(subscribe_query(db_ptr, name.as_ptr(), filter.as_ptr(), 5, Some(on_event)) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert!(response["Ok"].as_str().unwrap().contains(r#""id":"s1""#));
+
+        let json = CString::new(r#"{"id":"s2","hash":"h","data":{"done":false}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while EVENTS.lock().unwrap().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "subscription did not fire");
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let response: serde_json::Value = serde_json::from_str(&EVENTS.lock().unwrap()[0]).unwrap();
+        let event: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(event["name"], "open");
+        assert_eq!(event["added"][0]["id"], "s2");
+
+        let result = unsafe { CString::from_raw(subscribe_query(db_ptr, name.as_ptr(), filter.as_ptr(), 5, None) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let invalid = CString::new(r#"{"data.done": {"$near": 1}}"#).unwrap();
+        let result = unsafe { CString::from_raw(subscribe_query(db_ptr, name.as_ptr(), invalid.as_ptr(), 5, Some(on_event)) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("Error"));
+
+        let result = unsafe { CString::from_raw(unsubscribe_query(db_ptr, name.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(unsubscribe_query(0, name.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_upsert() {
+        let state = AppDbState::init(generate_unique_db_name("upsert")).unwrap();
+
+        let result = state.upsert(create_test_model("u1", Some(serde_json::json!({"name": "John"})))).unwrap();
+        assert!(result.inserted);
+        assert_eq!(result.record.id, "u1");
+
+        let result = state.upsert(LocalDbModel { hash: "hash_u1_v2".to_string(), ..create_test_model("u1", Some(serde_json::json!({"name": "Jane"}))) }).unwrap();
+        assert!(!result.inserted);
+        let stored = state.get_by_id("u1").unwrap().unwrap();
+        assert_eq!((stored.hash.as_str(), &stored.data), ("hash_u1_v2", &serde_json::json!({"name": "Jane"})));
+        assert_eq!(state.count_records().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_ffi_upsert_data() {
+        use crate::{create_db, upsert_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_upsert_data")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"u1","hash":"h1","data":{"name":"John"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(upsert_data(db_ptr, json.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        assert_eq!(response["Ok"].as_str().unwrap(), r#"{"inserted":true,"record":{"id":"u1","hash":"h1","data":{"name":"John"}}}"#);
+
+        let json = CString::new(r#"{"id":"u1","hash":"h2","data":{"name":"Jane"}}"#).unwrap();
+        let result = unsafe { CString::from_raw(upsert_data(db_ptr, json.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains(r#"\"inserted\":false"#));
+
+        let invalid = CString::new(r#"{"id":"u1"}"#).unwrap();
+        let result = unsafe { CString::from_raw(upsert_data(db_ptr, invalid.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+
+        let result = unsafe { CString::from_raw(upsert_data(0, json.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_update_if_hash() {
+        use crate::app_response::AppResponse;
+
+        let state = AppDbState::init(generate_unique_db_name("update_if_hash")).unwrap();
+        let missing = state.update_if_hash(create_test_model("c1", None), "hash_c1");
+        assert!(matches!(missing, Err(AppResponse::NotFound(_))));
+
+        state.post(create_test_model("c1", None)).unwrap();
+        let first = LocalDbModel { hash: "v2".to_string(), ..create_test_model("c1", Some(serde_json::json!({"by": "first"}))) };
+        let second = LocalDbModel { hash: "v2b".to_string(), ..create_test_model("c1", Some(serde_json::json!({"by": "second"}))) };
+
+        // Both writers read hash_c1; the second one loses
+        assert_eq!(state.update_if_hash(first, "hash_c1").unwrap().hash, "v2");
+        match state.update_if_hash(second, "hash_c1") {
+            Err(error @ AppResponse::Conflict { .. }) => {
+                let AppResponse::Conflict { ref current_hash, .. } = error else { unreachable!() };
+                assert_eq!(current_hash, "v2");
+                assert_eq!((error.code(), error.code_name()), (1004, "CONFLICT"));
+                assert_eq!(error.envelope().error.unwrap().detail, Some(serde_json::json!({"current_hash": "v2"})));
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert_eq!(state.get_by_id("c1").unwrap().unwrap().data, serde_json::json!({"by": "first"}));
+    }
+
+    #[test]
+    fn test_ffi_update_if_hash() {
+        use crate::{create_db, post_data, update_if_hash};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_update_if_hash")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"c1","hash":"h1","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let update = CString::new(r#"{"id":"c1","hash":"h2","data":{"v":2}}"#).unwrap();
+        let expected = CString::new("h1").unwrap();
+        let result = unsafe { CString::from_raw(update_if_hash(db_ptr, update.as_ptr(), expected.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"id\":\"c1\",\"hash\":\"h2\",\"data\":{\"v\":2}}"}"#);
+
+        let result = unsafe { CString::from_raw(update_if_hash(db_ptr, update.as_ptr(), expected.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Conflict":{"message":"Record c1 has hash h2, expected h1","current_hash":"h2"}}"#);
+
+        let missing = CString::new(r#"{"id":"c9","hash":"h2","data":{}}"#).unwrap();
+        let result = unsafe { CString::from_raw(update_if_hash(db_ptr, missing.as_ptr(), expected.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let result = unsafe { CString::from_raw(update_if_hash(db_ptr, update.as_ptr(), std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(update_if_hash(0, update.as_ptr(), expected.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        use crate::app_response::AppResponse;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("compare_and_swap")).unwrap();
+        state.post(create_test_model("job", Some(json!({"status": "queued"})))).unwrap();
+
+        let claim = state.compare_and_swap("job", "data.status", &json!("queued"), &json!("uploading")).unwrap();
+        assert_eq!((claim.swapped, claim.current), (true, json!("uploading")));
+        // A second worker racing for the same transition loses
+        let late = state.compare_and_swap("job", "data.status", &json!("queued"), &json!("uploading")).unwrap();
+        assert_eq!((late.swapped, late.current), (false, json!("uploading")));
+
+        // A missing field compares as null and is created with its parents
+        let created = state.compare_and_swap("job", "data.lease.owner", &json!(null), &json!("w1")).unwrap();
+        assert!(created.swapped);
+        let stored = state.get_by_id("job").unwrap().unwrap();
+        assert_eq!(stored.data, json!({"status": "uploading", "lease": {"owner": "w1"}}));
+        assert_eq!(stored.hash, "hash_job");
+
+        assert!(matches!(state.compare_and_swap("none", "data.status", &json!(null), &json!(1)), Err(AppResponse::NotFound(_))));
+        assert!(matches!(state.compare_and_swap("job", "status", &json!(null), &json!(1)), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.compare_and_swap("job", "data.status.x", &json!(null), &json!(1)), Err(AppResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_ffi_compare_and_swap() {
+        use crate::{compare_and_swap, create_db, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_compare_and_swap")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"job","hash":"h1","data":{"status":"queued"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("job").unwrap();
+        let path = CString::new("data.status").unwrap();
+        let expected = CString::new(r#""queued""#).unwrap();
+        let new = CString::new(r#""uploading""#).unwrap();
+        let result = unsafe { CString::from_raw(compare_and_swap(db_ptr, id.as_ptr(), path.as_ptr(), expected.as_ptr(), new.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"swapped\":true,\"current\":\"uploading\"}"}"#);
+        let result = unsafe { CString::from_raw(compare_and_swap(db_ptr, id.as_ptr(), path.as_ptr(), expected.as_ptr(), new.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"swapped\":false,\"current\":\"uploading\"}"}"#);
+
+        let invalid = CString::new("{").unwrap();
+        let result = unsafe { CString::from_raw(compare_and_swap(db_ptr, id.as_ptr(), path.as_ptr(), invalid.as_ptr(), new.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+        let result = unsafe { CString::from_raw(compare_and_swap(0, id.as_ptr(), path.as_ptr(), expected.as_ptr(), new.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_apply_json_patch() {
+        use crate::app_response::AppResponse;
+        use crate::local_db_model::PatchOp;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("json_patch")).unwrap();
+        let data = json!({"status": "draft", "labels": ["inbox"], "draft_body": "Hi", "a/b": {"~k": 1}});
+        state.post(create_test_model("m1", Some(data))).unwrap();
+
+        let ops: Vec<PatchOp> = serde_json::from_value(json!([
+            {"op": "test", "path": "/status", "value": "draft"},
+            {"op": "replace", "path": "/status", "value": "sent"},
+            {"op": "add", "path": "/labels/-", "value": "outbox"},
+            {"op": "add", "path": "/labels/0", "value": "first"},
+            {"op": "move", "from": "/draft_body", "path": "/body"},
+            {"op": "copy", "from": "/a~1b/~0k", "path": "/count"},
+            {"op": "remove", "path": "/a~1b"}
+        ]))
+        .unwrap();
+        let patched = state.apply_json_patch("m1", &ops).unwrap();
+        let expected = json!({"status": "sent", "labels": ["first", "inbox", "outbox"], "body": "Hi", "count": 1});
+        assert_eq!(patched.data, expected);
+        assert_eq!(patched.hash, "hash_m1");
+        assert_eq!(state.get_by_id("m1").unwrap().unwrap().data, expected);
+
+        // A failing operation leaves the record untouched
+        let ops: Vec<PatchOp> = serde_json::from_value(json!([
+            {"op": "replace", "path": "/status", "value": "archived"},
+            {"op": "test", "path": "/status", "value": "draft"}
+        ]))
+        .unwrap();
+        match state.apply_json_patch("m1", &ops) {
+            Err(AppResponse::Conflict { current_hash, .. }) => assert_eq!(current_hash, "hash_m1"),
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        let ops = [PatchOp::Remove { path: "/missing".to_string() }];
+        assert!(matches!(state.apply_json_patch("m1", &ops), Err(AppResponse::BadRequest(_))));
+        let ops = [PatchOp::Move { from: "/labels".to_string(), path: "/labels/0".to_string() }];
+        assert!(matches!(state.apply_json_patch("m1", &ops), Err(AppResponse::BadRequest(_))));
+        let ops = [PatchOp::Add { path: "/labels/07".to_string(), value: json!(1) }];
+        assert!(matches!(state.apply_json_patch("m1", &ops), Err(AppResponse::BadRequest(_))));
+        assert_eq!(state.get_by_id("m1").unwrap().unwrap().data, expected);
+
+        assert!(matches!(state.apply_json_patch("none", &[]), Err(AppResponse::NotFound(_))));
+    }
+
+    #[test]
+    fn test_ffi_apply_json_patch() {
+        use crate::{apply_json_patch, create_db, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_json_patch")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"m1","hash":"h1","data":{"status":"draft"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("m1").unwrap();
+        let ops = CString::new(r#"[{"op":"test","path":"/status","value":"draft"},{"op":"replace","path":"/status","value":"sent"}]"#).unwrap();
+        let result = unsafe { CString::from_raw(apply_json_patch(db_ptr, id.as_ptr(), ops.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"id\":\"m1\",\"hash\":\"h1\",\"data\":{\"status\":\"sent\"}}"}"#);
+        let result = unsafe { CString::from_raw(apply_json_patch(db_ptr, id.as_ptr(), ops.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().starts_with(r#"{"Conflict":"#));
+
+        let unknown = CString::new(r#"[{"op":"merge","path":"/status"}]"#).unwrap();
+        let result = unsafe { CString::from_raw(apply_json_patch(db_ptr, id.as_ptr(), unknown.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("SerializationError"));
+        let result = unsafe { CString::from_raw(apply_json_patch(0, id.as_ptr(), ops.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_field() {
+        use crate::app_response::AppResponse;
+        use serde_json::json;
+
+        let mut state = AppDbState::init(generate_unique_db_name("get_field")).unwrap();
+        let data = json!({"meta": {"title": "Notes", "a.b": 1, "x/y": [10, 20]}, "body": "x".repeat(1000)});
+        state.post(create_test_model("d1", Some(data.clone()))).unwrap();
+
+        assert_eq!(state.get_field("d1", "/meta/title").unwrap(), Some(json!("Notes")));
+        assert_eq!(state.get_field("d1", "/meta/a.b").unwrap(), Some(json!(1)));
+        assert_eq!(state.get_field("d1", "/meta/x~1y/1").unwrap(), Some(json!(20)));
+        assert_eq!(state.get_field("d1", "").unwrap(), Some(data));
+        assert_eq!(state.get_field("d1", "/meta/missing").unwrap(), None);
+        assert_eq!(state.get_field("d1", "/meta/x~1y/5").unwrap(), None);
+
+        assert!(matches!(state.get_field("d1", "meta"), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.get_field("none", "/meta"), Err(AppResponse::NotFound(_))));
+
+        // Overflowed fields are reassembled
+        state.set_overflow_threshold(Some(4096));
+        state.post(create_test_model("d2", Some(json!({"body": "y".repeat(10_000)})))).unwrap();
+        assert_eq!(state.get_field("d2", "/body").unwrap(), Some(json!("y".repeat(10_000))));
+    }
+
+    #[test]
+    fn test_ffi_get_field() {
+        use crate::{create_db, get_field, post_data};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_get_field")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"d1","hash":"h1","data":{"meta":{"title":"Notes"}}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("d1").unwrap();
+        let pointer = CString::new("/meta").unwrap();
+        let result = unsafe { CString::from_raw(get_field(db_ptr, id.as_ptr(), pointer.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"{\"title\":\"Notes\"}"}"#);
+
+        let missing = CString::new("/tags").unwrap();
+        let result = unsafe { CString::from_raw(get_field(db_ptr, id.as_ptr(), missing.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"NotFound":"No field /tags in record d1"}"#);
+        let result = unsafe { CString::from_raw(get_field(0, id.as_ptr(), pointer.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_find_by_hash() {
+        let state = AppDbState::init(generate_unique_db_name("find_by_hash")).unwrap();
+        for id in ["b", "a", "c"] {
+            state.post(LocalDbModel { hash: if id == "c" { "other".to_string() } else { "same".to_string() }, ..create_test_model(id, None) }).unwrap();
+        }
+
+        // Without the index the records are scanned
+        assert!(!state.hash_index_enabled().unwrap());
+        assert_eq!(state.find_by_hash("same").unwrap(), vec!["a", "b"]);
+
+        state.set_hash_index(true).unwrap();
+        state.set_hash_index(true).unwrap();
+        assert!(state.hash_index_enabled().unwrap());
+        assert_eq!(state.find_by_hash("same").unwrap(), vec!["a", "b"]);
+        assert_eq!(state.find_by_hash("sam").unwrap(), Vec::<String>::new());
+
+        // Writes keep the index up to date
+        state.put(LocalDbModel { hash: "other".to_string(), ..create_test_model("a", None) }).unwrap();
+        state.delete_by_id("c").unwrap();
+        assert_eq!(state.find_by_hash("same").unwrap(), vec!["b"]);
+        assert_eq!(state.find_by_hash("other").unwrap(), vec!["a"]);
+
+        state.set_hash_index(false).unwrap();
+        assert!(!state.hash_index_enabled().unwrap());
+        assert_eq!(state.find_by_hash("other").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_ffi_find_by_hash() {
+        use crate::{create_db, find_by_hash, post_data, set_hash_index};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_find_by_hash")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"p1","hash":"sha_1","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(set_hash_index(db_ptr, true) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let hash = CString::new("sha_1").unwrap();
+        let result = unsafe { CString::from_raw(find_by_hash(db_ptr, hash.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[\"p1\"]"}"#);
+
+        let result = unsafe { CString::from_raw(find_by_hash(db_ptr, std::ptr::null()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+        let result = unsafe { CString::from_raw(set_hash_index(0, true) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_record_timestamps() {
+        use crate::query::{PathFilter, SortSpec};
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("record_timestamps")).unwrap();
+        state.post(create_test_model("old", None)).unwrap();
+        let untouched = state.get_by_id("old").unwrap().unwrap();
+        assert_eq!((untouched.created_at, untouched.updated_at), (None, None));
+        assert!(!serde_json::to_string(&untouched).unwrap().contains("created_at"));
+
+        state.set_record_timestamps(true).unwrap();
+        assert!(state.record_timestamps().unwrap());
+        state.set_fixed_clock(Some(1_000));
+        state.post(create_test_model("a", None)).unwrap();
+        state.set_fixed_clock(Some(2_000));
+        // Values passed in are replaced
+        state.put(LocalDbModel { created_at: Some(5), updated_at: Some(5), ..create_test_model("a", Some(json!({"v": 2}))) }).unwrap();
+        state.post(create_test_model("b", None)).unwrap();
+
+        let a = state.get_by_id("a").unwrap().unwrap();
+        assert_eq!((a.created_at, a.updated_at), (Some(1_000), Some(2_000)));
+        assert_eq!(state.get_by_id("b").unwrap().unwrap().created_at, Some(2_000));
+
+        // Queryable by range and sortable
+        let filter: PathFilter = serde_json::from_value(json!({"created_at": {"$lt": 1_500}})).unwrap();
+        let created: Vec<String> = state.query(&filter).unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(created, vec!["a"]);
+        let sort: SortSpec = serde_json::from_value(json!({"by": "created_at", "order": "desc"})).unwrap();
+        let sorted: Vec<String> = state.query_sorted(&PathFilter::default(), &sort).unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(sorted, vec!["b", "a", "old"]);
+
+        // A record received with a creation time keeps it
+        state.post(LocalDbModel { created_at: Some(7), ..create_test_model("remote", None) }).unwrap();
+        assert_eq!(state.get_by_id("remote").unwrap().unwrap().created_at, Some(7));
+
+        state.set_record_timestamps(false).unwrap();
+        state.put(create_test_model("a", None)).unwrap();
+        assert_eq!(state.get_by_id("a").unwrap().unwrap().updated_at, None);
+    }
+
+    #[test]
+    fn test_ffi_set_record_timestamps() {
+        use crate::{create_db, get_by_id, post_data, set_record_timestamps};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_record_timestamps")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let result = unsafe { CString::from_raw(set_record_timestamps(db_ptr, true) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h1","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let id = CString::new("n1").unwrap();
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert!(record["created_at"].is_u64());
+        assert_eq!(record["created_at"], record["updated_at"]);
+
+        let result = unsafe { CString::from_raw(set_record_timestamps(0, true) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_get_modified_since() {
+        let state = AppDbState::init(generate_unique_db_name("modified_since")).unwrap();
+        state.post(create_test_model("untimed", None)).unwrap();
+        state.set_record_timestamps(true).unwrap();
+        assert!(state.list_indexes().unwrap().iter().any(|index| index.name == "__updated_at"));
+
+        for (id, now) in [("c", 3_000), ("a", 1_000), ("b", 2_000)] {
+            state.set_fixed_clock(Some(now));
+            state.post(create_test_model(id, None)).unwrap();
+        }
+        state.set_fixed_clock(Some(4_000));
+        state.put(create_test_model("a", None)).unwrap();
+
+        let ids = |models: Vec<LocalDbModel>| models.into_iter().map(|model| model.id).collect::<Vec<_>>();
+        assert_eq!(ids(state.get_modified_since(0).unwrap()), vec!["b", "c", "a"]);
+        assert_eq!(ids(state.get_modified_since(3_000).unwrap()), vec!["c", "a"]);
+        assert!(state.get_modified_since(4_001).unwrap().is_empty());
+
+        // Without the index the records are scanned, in the same order
+        state.drop_index("__updated_at").unwrap();
+        assert_eq!(ids(state.get_modified_since(2_000).unwrap()), vec!["b", "c", "a"]);
+
+        state.set_record_timestamps(false).unwrap();
+        assert!(state.list_indexes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_get_modified_since() {
+        use crate::{create_db, get_modified_since, post_data, set_record_timestamps};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_modified_since")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+        unsafe { let _ = CString::from_raw(set_record_timestamps(db_ptr, true) as *mut i8); }
+
+        let json = CString::new(r#"{"id":"n1","hash":"h1","data":{}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let result = unsafe { CString::from_raw(get_modified_since(db_ptr, 0) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let records: Vec<LocalDbModel> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(records.len(), 1);
+        let result = unsafe { CString::from_raw(get_modified_since(db_ptr, u64::MAX) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"[]"}"#);
+
+        let result = unsafe { CString::from_raw(get_modified_since(0, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_record_history() {
+        use crate::app_response::AppResponse;
+        use serde_json::{json, Value as JsonValue};
+
+        let state = AppDbState::init(generate_unique_db_name("record_history")).unwrap();
+        state.post(create_test_model("doc", Some(json!({"v": 0})))).unwrap();
+        assert!(state.get_history("doc").unwrap().is_empty());
+
+        state.set_history_limit(3).unwrap();
+        assert_eq!(state.history_limit().unwrap(), Some(3));
+        state.set_fixed_clock(Some(1_000));
+        for v in 1..=4 {
+            state.put(create_test_model("doc", Some(json!({"v": v})))).unwrap();
+        }
+        state.post(create_test_model("other", None)).unwrap();
+
+        // Only the last 3 versions are kept, newest first
+        let history = state.get_history("doc").unwrap();
+        let values: Vec<JsonValue> = history.iter().map(|entry| entry.record.as_ref().unwrap().data["v"].clone()).collect();
+        assert_eq!(values, vec![json!(4), json!(3), json!(2)]);
+        assert!(history.windows(2).all(|pair| pair[0].version > pair[1].version));
+        assert_eq!(history[0].written_at, 1_000);
+        assert_eq!(state.get_history("other").unwrap().len(), 1);
+
+        // Reverting writes the version again, as a new version
+        let reverted = state.revert_to_version("doc", history[2].version).unwrap();
+        assert_eq!(reverted.data, json!({"v": 2}));
+        assert_eq!(state.get_by_id("doc").unwrap().unwrap().data, json!({"v": 2}));
+        let history = state.get_history("doc").unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].record.as_ref().unwrap().data, json!({"v": 2}));
+
+        // A deletion is kept as a tombstone, and an older version restores the record
+        assert!(state.delete_by_id("doc").unwrap());
+        let history = state.get_history("doc").unwrap();
+        assert!(history[0].record.is_none());
+        assert!(matches!(state.revert_to_version("doc", history[0].version), Err(AppResponse::BadRequest(_))));
+        assert!(matches!(state.revert_to_version("doc", 999), Err(AppResponse::NotFound(_))));
+        state.revert_to_version("doc", history[1].version).unwrap();
+        assert_eq!(state.get_by_id("doc").unwrap().unwrap().data, json!({"v": 2}));
+
+        // Lowering the limit trims the existing histories
+        state.set_history_limit(1).unwrap();
+        assert_eq!(state.get_history("doc").unwrap().len(), 1);
+
+        state.clear_all_records().unwrap();
+        assert!(state.get_history("doc").unwrap().is_empty());
+
+        state.put(create_test_model("doc", None)).unwrap();
+        state.set_history_limit(0).unwrap();
+        assert_eq!(state.history_limit().unwrap(), None);
+        assert!(state.get_history("doc").unwrap().is_empty());
+        state.put(create_test_model("doc", Some(json!({"v": 5})))).unwrap();
+        assert!(state.get_history("doc").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ffi_record_history() {
+        use crate::{create_db, get_history, post_data, put_data, revert_to_version, set_history_limit};
+        use crate::local_db_model::HistoryEntry;
+
+        let db_name = CString::new(generate_unique_db_name("ffi_record_history")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+        let result = unsafe { CString::from_raw(set_history_limit(db_ptr, 10) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"10"}"#);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h1","data":{"title":"Draft"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+        let json = CString::new(r#"{"id":"n1","hash":"h2","data":{"title":"Final"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(put_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("n1").unwrap();
+        let result = unsafe { CString::from_raw(get_history(db_ptr, id.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let history: Vec<HistoryEntry> = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].record.as_ref().unwrap().hash, "h1");
+
+        let result = unsafe { CString::from_raw(revert_to_version(db_ptr, id.as_ptr(), history[1].version) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let model: LocalDbModel = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(model.data["title"], "Draft");
+
+        let result = unsafe { CString::from_raw(revert_to_version(db_ptr, id.as_ptr(), 999) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+        let result = unsafe { CString::from_raw(get_history(0, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[test]
+    fn test_soft_delete() {
+        use crate::app_response::AppResponse;
+        use serde_json::json;
+
+        let state = AppDbState::init(generate_unique_db_name("soft_delete")).unwrap();
+        state.set_fixed_clock(Some(10_000));
+        state.post(create_test_model("keep", None)).unwrap();
+        state.post(create_test_model("gone", Some(json!({"title": "Draft"})))).unwrap();
+
+        assert!(state.soft_delete("gone").unwrap());
+        assert!(!state.soft_delete("missing").unwrap());
+        assert!(state.get_by_id("gone").unwrap().is_none());
+        assert_eq!(state.count_records().unwrap(), 1);
+        assert_eq!(state.get_all_ids().unwrap(), vec!["keep"]);
+
+        let restored = state.restore("gone").unwrap().unwrap();
+        assert_eq!(restored.data, json!({"title": "Draft"}));
+        assert_eq!(state.get_by_id("gone").unwrap().unwrap().hash, "hash_gone");
+        assert!(state.restore("gone").unwrap().is_none());
+
+        // A record written under the ID since blocks the restore
+        state.soft_delete("gone").unwrap();
+        state.post(create_test_model("gone", Some(json!({"title": "New"})))).unwrap();
+        match state.restore("gone") {
+            Err(AppResponse::Conflict { current_hash, .. }) => assert_eq!(current_hash, "hash_gone"),
+            other => panic!("Expected a conflict, got {other:?}"),
+        }
+        state.delete_by_id("gone").unwrap();
+
+        // Only soft deletions at least as old as the age are purged
+        state.set_fixed_clock(Some(20_000));
+        state.soft_delete("keep").unwrap();
+        state.set_fixed_clock(Some(25_000));
+        assert_eq!(state.purge_deleted(10_000).unwrap(), 1);
+        assert!(state.restore("gone").unwrap().is_none());
+        assert_eq!(state.purge_deleted(0).unwrap(), 1);
+        assert!(state.restore("keep").unwrap().is_none());
+        assert_eq!(state.count_records().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_ffi_soft_delete() {
+        use crate::{create_db, get_by_id, post_data, purge_deleted, restore, soft_delete};
+
+        let db_name = CString::new(generate_unique_db_name("ffi_soft_delete")).unwrap();
+        let db_ptr = create_db(db_name.as_ptr());
+        assert_ne!(db_ptr, 0);
+
+        let json = CString::new(r#"{"id":"n1","hash":"h1","data":{"title":"Draft"}}"#).unwrap();
+        unsafe { let _ = CString::from_raw(post_data(db_ptr, json.as_ptr()) as *mut i8); }
+
+        let id = CString::new("n1").unwrap();
+        let result = unsafe { CString::from_raw(soft_delete(db_ptr, id.as_ptr()) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"true"}"#);
+        let result = unsafe { CString::from_raw(get_by_id(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+        let result = unsafe { CString::from_raw(soft_delete(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        let result = unsafe { CString::from_raw(restore(db_ptr, id.as_ptr()) as *mut i8) };
+        let response: serde_json::Value = serde_json::from_str(result.to_str().unwrap()).unwrap();
+        let model: LocalDbModel = serde_json::from_str(response["Ok"].as_str().unwrap()).unwrap();
+        assert_eq!(model.data["title"], "Draft");
+        let result = unsafe { CString::from_raw(restore(db_ptr, id.as_ptr()) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("NotFound"));
+
+        unsafe { let _ = CString::from_raw(soft_delete(db_ptr, id.as_ptr()) as *mut i8); }
+        let result = unsafe { CString::from_raw(purge_deleted(db_ptr, 0) as *mut i8) };
+        assert_eq!(result.to_str().unwrap(), r#"{"Ok":"1"}"#);
+
+        let result = unsafe { CString::from_raw(purge_deleted(0, 0) as *mut i8) };
+        assert!(result.to_str().unwrap().contains("BadRequest"));
+
+        unsafe { let _ = CString::from_raw(crate::close_database(db_ptr) as *mut i8); }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_field_encryption_outside_main() {
+        use crate::conflicts::CONFLICTS_DB_NAME;
+        use crate::local_db_model::{RemoteChanges, WriteOp};
+        use lmdb::{Cursor, Transaction};
+
+        let mut state = AppDbState::init(generate_unique_db_name("field_encryption_outside_main")).unwrap();
+        state.set_field_encryption(&["data.ssn"], &[1u8; 32]).unwrap();
+        let secret = |ssn: &str| Some(serde_json::json!({"ssn": ssn}));
+
+        // A conflict keeps both versions sealed
+        state.post(create_test_model("p1", secret("111-11-1111"))).unwrap();
+        let synced = state.get_all_delta("sync").unwrap();
+        state.ack_delta("sync", synced.sequence).unwrap();
+        state.put(LocalDbModel { hash: "local".to_string(), ..create_test_model("p1", secret("222-22-2222")) }).unwrap();
+        let remote = RemoteChanges {
+            consumer: "sync".to_string(),
+            records: vec![LocalDbModel { hash: "remote".to_string(), ..create_test_model("p1", secret("333-33-3333")) }],
+            deleted: Vec::new(),
+        };
+        assert_eq!(state.merge_remote(&remote).unwrap().conflicts, vec!["p1"]);
+
+        // Collections, written directly and in a transaction
+        state.collection_put("patients", &create_test_model("p2", secret("444-44-4444"))).unwrap();
+        let ops = [WriteOp::Put { collection: Some("patients".to_string()), record: create_test_model("p3", secret("555-55-5555")) }];
+        state.write_transaction(&ops).unwrap();
+
+        let plain = |values: Vec<Vec<u8>>| {
+            let secrets: [&[u8]; 5] = [b"111-11", b"222-22", b"333-33", b"444-44", b"555-55"];
+            values.iter().any(|value| secrets.iter().any(|secret| value.windows(secret.len()).any(|window| window == *secret)))
+        };
+        {
+            let (env, conflicts_db) = state.side_db(CONFLICTS_DB_NAME).unwrap();
+            let collection_db = state.open_collection("patients").unwrap().unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            for db in [conflicts_db, collection_db] {
+                let mut cursor = txn.open_ro_cursor(db).unwrap();
+                let values: Vec<Vec<u8>> = cursor.iter_start().map(|(_, value)| value.to_vec()).collect();
+                assert!(!values.is_empty());
+                assert!(!plain(values));
+            }
+        }
+
+        let conflict = &state.list_conflicts().unwrap()[0];
+        assert_eq!(conflict.local.as_ref().unwrap().data["ssn"], "222-22-2222");
+        assert_eq!(conflict.remote.as_ref().unwrap().data["ssn"], "333-33-3333");
+        assert_eq!(state.collection_get("patients", "p2").unwrap().unwrap().data["ssn"], "444-44-4444");
+        assert_eq!(state.collection_get_all("patients").unwrap()[1].data["ssn"], "555-55-5555");
+    }
+
+    // HELPER FUNCTIONS
+    // ===============================
+
+    /// Leaves the "open" shutdown marker behind, as a crashed session would.
+    fn simulate_unclean_shutdown(db_dir: &str) {
+        use lmdb::{Environment, Transaction, WriteFlags};
+
+        let env = Environment::new().set_max_dbs(10).open(Path::new(db_dir)).unwrap();
+        let meta = env.open_db(Some("__meta")).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(meta, &"shutdown_state", &1u64.to_be_bytes(), WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[cfg(feature = "signing")]
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
     fn get_memory_usage() -> usize {
         // Simple memory usage estimation
         // In a real implementation, you might use system-specific APIs
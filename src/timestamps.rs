@@ -0,0 +1,169 @@
+//! Creation and update times of records.
+//!
+//! While record timestamps are on, every write stamps the record with
+//! `created_at`, kept from its first write, and `updated_at`, both in
+//! milliseconds since the Unix epoch of [`AppDbState::now_ms`]. They are
+//! stored next to `data` in the stored record, so the app's own fields are
+//! left alone:
+//!
+//! ```json
+//! {"id": "n1", "hash": "h2", "data": {"title": "Groceries"}, "created_at": 1717171717000, "updated_at": 1717172000000}
+//! ```
+//!
+//! Reads return them in [`LocalDbModel::created_at`] and
+//! [`LocalDbModel::updated_at`], and filters, sorts and indexes address them
+//! as the paths `created_at` and `updated_at`, e.g. the filter
+//! `{"updated_at": {"$gte": 1717171717000}}` for the records changed since
+//! then. Records written while timestamps were off have none until their
+//! next write.
+//!
+//! A record received from another device keeps the `created_at` it carries
+//! when it is new here. All records of a write transaction get the same
+//! `updated_at`. Records in named collections are not stamped.
+//!
+//! While timestamps are on, a secondary index (see [`crate::index`]) named
+//! `__updated_at` over `updated_at` lets [`AppDbState::get_modified_since`]
+//! read the records changed since a time without scanning the others, a
+//! simpler sync primitive than the change log of [`crate::delta`].
+
+use std::collections::BTreeMap;
+
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::app_response::AppResponse;
+use crate::index::{ids_from, INDEX_DB_NAME};
+use crate::local_db_model::{Direction, IndexDefinition, LocalDbModel};
+use crate::local_db_state::AppDbState;
+use crate::meta::{META_DB_NAME, RECORD_TIMESTAMPS_KEY};
+use crate::query::{PathFilter, SortSpec};
+
+/// Name of the index over `updated_at`.
+pub(crate) const UPDATED_AT_INDEX_NAME: &str = "__updated_at";
+
+impl AppDbState {
+    /// Turns record timestamps on or off. The setting is persisted.
+    ///
+    /// Turning them on creates the `__updated_at` index, indexing the
+    /// existing records; turning them off drops it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    /// use offline_first_core::query::PathFilter;
+    /// use serde_json::json;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// db.set_record_timestamps(true)?;
+    ///
+    /// let since: PathFilter = serde_json::from_value(json!({"updated_at": {"$gte": 1717171717000u64}}))?;
+    /// let changed = db.query(&since)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the write fails.
+    pub fn set_record_timestamps(&self, enabled: bool) -> Result<(), AppResponse> {
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let mut txn = env.begin_rw_txn()?;
+        if enabled {
+            txn.put(meta_db, &RECORD_TIMESTAMPS_KEY, &1u64.to_be_bytes(), WriteFlags::empty())?;
+        } else {
+            match txn.del(meta_db, &RECORD_TIMESTAMPS_KEY, None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        txn.commit()?;
+
+        if enabled {
+            let definition = IndexDefinition { name: UPDATED_AT_INDEX_NAME.to_string(), paths: vec!["updated_at".to_string()] };
+            self.ensure_indexes(&[definition])?;
+        } else {
+            self.drop_index(UPDATED_AT_INDEX_NAME)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether record timestamps are on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn record_timestamps(&self) -> Result<bool, LmdbError> {
+        Ok(self.meta_u64(RECORD_TIMESTAMPS_KEY)?.is_some())
+    }
+
+    /// Returns the records written at or after `since_ms`, in milliseconds
+    /// since the Unix epoch, oldest `updated_at` first.
+    ///
+    /// An app syncing this way keeps the `updated_at` of the last record it
+    /// received and passes it next time; records written in the same
+    /// millisecond are returned again. Deleted records are not reported, and
+    /// records without `updated_at` never match.
+    ///
+    /// Reads the `__updated_at` index, except while an encryption key is
+    /// registered, as records encrypted at rest are not indexed, or after
+    /// the index was dropped; the records are scanned then. Records that
+    /// fail to decode are logged and skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// db.set_record_timestamps(true)?;
+    ///
+    /// let mut cursor = 0;
+    /// for record in db.get_modified_since(cursor)? {
+    ///     // upload record
+    ///     cursor = cursor.max(record.updated_at.unwrap_or_default());
+    /// }
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read fails.
+    pub fn get_modified_since(&self, since_ms: u64) -> Result<Vec<LocalDbModel>, AppResponse> {
+        let (env, db) = self.env_db()?;
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+
+        let indexed = self.read_index_definitions(&txn)?.iter().any(|definition| definition.name == UPDATED_AT_INDEX_NAME);
+        if !indexed || self.has_encryption_keys() {
+            drop(txn);
+            let filter = PathFilter(BTreeMap::from([("updated_at".to_string(), json!({"$gte": since_ms}))]));
+            let mut models = self.query(&filter)?;
+            SortSpec { by: "updated_at".to_string(), order: Direction::Asc }.apply(&mut models);
+            return Ok(models);
+        }
+
+        let mut models = Vec::new();
+        for id in ids_from(&txn, index_db, UPDATED_AT_INDEX_NAME, since_ms as f64)? {
+            match txn.get(db, &id) {
+                Ok(value) => match self.decode_record(&txn, &id, value) {
+                    Ok(model) => models.push(model),
+                    Err(e) => info!("Error decoding model: {e}"),
+                },
+                Err(LmdbError::NotFound) => warn!("Index {UPDATED_AT_INDEX_NAME} references a missing record"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(models)
+    }
+}
+
+/// Stamps `model`, replacing `previous`, its stored version, as written at
+/// `now`.
+pub(crate) fn stamp_times(previous: Option<&LocalDbModel>, model: &mut LocalDbModel, now: u64) {
+    model.created_at = match previous {
+        Some(previous) => previous.created_at.or(Some(now)),
+        None => model.created_at.or(Some(now)),
+    };
+    model.updated_at = Some(now);
+}
@@ -0,0 +1,178 @@
+//! Transactions spanning collections.
+//!
+//! [`AppDbState::write_transaction`] applies writes to the records of `main`
+//! and to named collections (see [`crate::collections`]) in one LMDB write
+//! transaction: either every write is committed or none is, e.g. an order
+//! inserted together with the counter of orders it updates.
+
+use std::collections::BTreeMap;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::{LocalDbModel, TransactionResult, WriteOp};
+use crate::local_db_state::AppDbState;
+use crate::writer::RecordWriter;
+
+/// Database a write goes to.
+#[derive(Clone, Copy)]
+enum Target {
+    Main,
+    /// A collection, `None` if it does not exist.
+    Collection(Option<Database>),
+}
+
+impl AppDbState {
+    /// Applies `ops` in order in one write transaction spanning `main` and
+    /// any number of collections. Collections written to are created when
+    /// missing.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_model::WriteOp;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("shop".to_string())?;
+    ///
+    /// let ops: Vec<WriteOp> = serde_json::from_str(r#"[
+    ///     {"op": "put", "collection": "orders", "record": {"id": "o1", "hash": "h1", "data": {"total": 42}}},
+    ///     {"op": "increment", "collection": "counters", "id": "orders", "path": "data.count"}
+    /// ]"#).unwrap();
+    /// db.write_transaction(&ops)?;
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if an ID is empty or an increment
+    /// does not apply to an integer, or a database error if a write fails. No
+    /// write of the transaction is committed then.
+    pub fn write_transaction(&self, ops: &[WriteOp]) -> Result<TransactionResult, AppResponse> {
+        // LMDB opens database handles in transactions of their own, so every
+        // collection is opened before the write transaction begins
+        let mut collections: BTreeMap<&str, Option<Database>> = BTreeMap::new();
+        for op in ops {
+            let (collection, id) = match op {
+                WriteOp::Put { collection, record } => (collection, &record.id),
+                WriteOp::Delete { collection, id } | WriteOp::Increment { collection, id, .. } => (collection, id),
+            };
+            if id.is_empty() {
+                return Err(AppResponse::BadRequest("Record ID cannot be empty".to_string()));
+            }
+            let Some(name) = collection else {
+                continue;
+            };
+            if !matches!(op, WriteOp::Delete { .. }) {
+                collections.insert(name, Some(self.create_collection(name)?));
+            } else if !collections.contains_key(name.as_str()) {
+                collections.insert(name, self.open_collection(name)?);
+            }
+        }
+        let target = |collection: &Option<String>| match collection {
+            Some(name) => Target::Collection(collections.get(name.as_str()).copied().flatten()),
+            None => Target::Main,
+        };
+
+        self.throttle_write()?;
+        let (env, db) = self.env_db()?;
+        let mut txn = env.begin_rw_txn()?;
+        let writer = self.record_writer(&txn)?;
+        let mut result = TransactionResult::default();
+
+        for op in ops {
+            match op {
+                WriteOp::Put { collection, record } => {
+                    self.put_to(&mut txn, &writer, db, target(collection), &mut record.clone())?;
+                    result.written += 1;
+                }
+                WriteOp::Delete { collection, id } => {
+                    let deleted = match target(collection) {
+                        Target::Main => writer.del(&mut txn, db, id.as_bytes())?,
+                        Target::Collection(None) => false,
+                        Target::Collection(Some(collection_db)) => match txn.del(collection_db, id, None) {
+                            Ok(()) => true,
+                            Err(LmdbError::NotFound) => false,
+                            Err(e) => return Err(e.into()),
+                        },
+                    };
+                    match deleted {
+                        true => result.deleted += 1,
+                        false => result.not_found.push(id.clone()),
+                    }
+                }
+                WriteOp::Increment { collection, id, path, by } => {
+                    let target = target(collection);
+                    let mut record = self.get_from(&txn, db, target, id)?.unwrap_or_else(|| LocalDbModel {
+                        id: id.clone(),
+                        data: JsonValue::Object(Map::new()),
+                        ..LocalDbModel::default()
+                    });
+                    increment(&mut record, path, *by)?;
+                    self.put_to(&mut txn, &writer, db, target, &mut record)?;
+                    result.written += 1;
+                }
+            }
+        }
+
+        writer.commit(txn)?;
+        Ok(result)
+    }
+
+    fn put_to(&self, txn: &mut RwTransaction, writer: &RecordWriter, db: Database, target: Target, record: &mut LocalDbModel) -> Result<(), AppResponse> {
+        match target {
+            Target::Main => self.write_model(txn, writer, db, record),
+            Target::Collection(collection_db) => {
+                let collection_db = collection_db.ok_or(LmdbError::Other(1))?;
+                txn.put(collection_db, &record.id, &self.encode_sealed(record)?, WriteFlags::empty())?;
+                Ok(())
+            }
+        }
+    }
+
+    fn get_from(&self, txn: &RwTransaction, db: Database, target: Target, id: &str) -> Result<Option<LocalDbModel>, AppResponse> {
+        let db = match target {
+            Target::Main => db,
+            Target::Collection(Some(collection_db)) => collection_db,
+            Target::Collection(None) => return Ok(None),
+        };
+        match txn.get(db, &id) {
+            Ok(value) if matches!(target, Target::Main) => Ok(Some(self.decode_record(txn, id.as_bytes(), value)?)),
+            Ok(value) => Ok(Some(self.decode_sealed(id.as_bytes(), value)?)),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Adds `by` to the integer at the dotted `path` of `record`, creating the
+/// objects on the way.
+fn increment(record: &mut LocalDbModel, path: &str, by: i64) -> Result<(), AppResponse> {
+    let id = record.id.clone();
+    let invalid = |reason: &str| AppResponse::BadRequest(format!("Cannot increment {path} of record {id}: {reason}"));
+    let Some(rest) = path.strip_prefix("data.") else {
+        return Err(invalid("the path must start with data."));
+    };
+
+    let mut segments: Vec<&str> = rest.split('.').collect();
+    let field = segments.pop().unwrap_or_default();
+    let mut node = &mut record.data;
+    for segment in segments {
+        let JsonValue::Object(map) = node else {
+            return Err(invalid("a parent is not an object"));
+        };
+        node = map.entry(segment).or_insert_with(|| JsonValue::Object(Map::new()));
+    }
+    let JsonValue::Object(map) = node else {
+        return Err(invalid("a parent is not an object"));
+    };
+
+    let current = match map.get(field) {
+        None | Some(JsonValue::Null) => 0,
+        Some(value) => value.as_i64().ok_or_else(|| invalid("the value is not an integer"))?,
+    };
+    let updated = current.checked_add(by).ok_or_else(|| invalid("the result overflows"))?;
+    map.insert(field.to_string(), updated.into());
+    Ok(())
+}
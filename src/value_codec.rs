@@ -0,0 +1,263 @@
+//! On-disk value encoding.
+//!
+//! Every value written to the main database is prefixed with a small header
+//! describing how the payload is encoded:
+//!
+//! | Byte | Meaning                                                        |
+//! |------|----------------------------------------------------------------|
+//! | 0    | Magic / header version ([`HEADER_MAGIC_V1`])                   |
+//! | 1    | Payload format (see [`ValueFormat`])                           |
+//! | 2    | Flags (bit 0: compressed, bit 1: encrypted, bit 2: overflowed) |
+//! | 3-4  | Schema version of the record (big-endian `u16`)                |
+//!
+//! Values written before the header existed are plain JSON objects and start
+//! with `{`, which can never be mistaken for the magic byte, so they keep
+//! being readable as [`ValueFormat::Json`] with schema version 0.
+//!
+//! Compressed payloads (zlib) are supported when the crate is built with the
+//! `compression` feature. Encrypted payloads are read through the tenant keys
+//! of a database, see [`AppDbState::set_encryption_key`](crate::local_db_state::AppDbState::set_encryption_key).
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::LocalDbModel;
+
+/// First byte of every headered value. A control character is used so that it
+/// can never collide with the first byte of a legacy JSON document.
+pub const HEADER_MAGIC_V1: u8 = 0x01;
+
+/// Size in bytes of the version 1 header.
+pub const HEADER_LEN: usize = 5;
+
+const FLAG_COMPRESSED: u8 = 0b0000_0001;
+const FLAG_ENCRYPTED: u8 = 0b0000_0010;
+const FLAG_OVERFLOWED: u8 = 0b0000_0100;
+
+/// Serialization format of a stored payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueFormat {
+    /// UTF-8 JSON text.
+    Json,
+    /// MessagePack binary encoding.
+    MsgPack,
+    /// CBOR binary encoding.
+    Cbor,
+}
+
+impl ValueFormat {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ValueFormat::Json),
+            1 => Some(ValueFormat::MsgPack),
+            2 => Some(ValueFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    fn as_byte(self) -> u8 {
+        match self {
+            ValueFormat::Json => 0,
+            ValueFormat::MsgPack => 1,
+            ValueFormat::Cbor => 2,
+        }
+    }
+}
+
+/// Decoded value header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueHeader {
+    /// Encoding of the payload following the header.
+    pub format: ValueFormat,
+    /// Whether the payload is compressed.
+    pub compressed: bool,
+    /// Whether the payload is encrypted.
+    pub encrypted: bool,
+    /// Whether fields of the record were moved to the chunk store.
+    pub overflowed: bool,
+    /// Application schema version the record was written with.
+    pub schema_version: u16,
+}
+
+impl Default for ValueHeader {
+    fn default() -> Self {
+        Self {
+            format: ValueFormat::Json,
+            compressed: false,
+            encrypted: false,
+            overflowed: false,
+            schema_version: 0,
+        }
+    }
+}
+
+impl ValueHeader {
+    /// Serializes the header into its on-disk representation.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut flags = 0;
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.encrypted {
+            flags |= FLAG_ENCRYPTED;
+        }
+        if self.overflowed {
+            flags |= FLAG_OVERFLOWED;
+        }
+        let version = self.schema_version.to_be_bytes();
+        [HEADER_MAGIC_V1, self.format.as_byte(), flags, version[0], version[1]]
+    }
+}
+
+/// Splits a stored value into its header and payload.
+///
+/// Legacy values without a header are reported as uncompressed JSON with
+/// schema version 0.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::SerializationError`] if the header is truncated or
+/// names a format this build does not know about.
+pub fn split_value(bytes: &[u8]) -> Result<(ValueHeader, &[u8]), AppResponse> {
+    match bytes.first() {
+        Some(&HEADER_MAGIC_V1) => {
+            if bytes.len() < HEADER_LEN {
+                return Err(AppResponse::SerializationError(format!(
+                    "Truncated value header: expected {HEADER_LEN} bytes, found {}",
+                    bytes.len()
+                )));
+            }
+            let format = ValueFormat::from_byte(bytes[1]).ok_or_else(|| {
+                AppResponse::SerializationError(format!("Unknown value format byte: {}", bytes[1]))
+            })?;
+            let header = ValueHeader {
+                format,
+                compressed: bytes[2] & FLAG_COMPRESSED != 0,
+                encrypted: bytes[2] & FLAG_ENCRYPTED != 0,
+                overflowed: bytes[2] & FLAG_OVERFLOWED != 0,
+                schema_version: u16::from_be_bytes([bytes[3], bytes[4]]),
+            };
+            Ok((header, &bytes[HEADER_LEN..]))
+        }
+        _ => Ok((ValueHeader::default(), bytes)),
+    }
+}
+
+/// Returns the JSON text of a stored value, validating its header.
+///
+/// The text is borrowed from `bytes` unless the payload had to be decompressed.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::SerializationError`] if the value uses a format or
+/// compression this build cannot decode, if it is encrypted, or if the
+/// payload is not valid UTF-8.
+pub fn json_payload(bytes: &[u8]) -> Result<Cow<'_, str>, AppResponse> {
+    let (header, payload) = split_value(bytes)?;
+
+    if header.format != ValueFormat::Json {
+        return Err(AppResponse::SerializationError(format!(
+            "Unsupported value format: {:?}",
+            header.format
+        )));
+    }
+    if header.encrypted {
+        return Err(AppResponse::SerializationError(
+            "Encrypted values need the key of their tenant to be read".to_string(),
+        ));
+    }
+
+    if header.compressed {
+        let inflated = decompress(payload)?;
+        return String::from_utf8(inflated)
+            .map(Cow::Owned)
+            .map_err(|e| AppResponse::SerializationError(format!("Invalid UTF-8 in JSON payload: {e}")));
+    }
+
+    std::str::from_utf8(payload)
+        .map(Cow::Borrowed)
+        .map_err(|e| AppResponse::SerializationError(format!("Invalid UTF-8 in JSON payload: {e}")))
+}
+
+/// Encodes a model as a headered JSON value.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::SerializationError`] if the model cannot be serialized.
+pub fn encode_model(model: &LocalDbModel) -> Result<Vec<u8>, AppResponse> {
+    encode_model_with(model, ValueHeader::default())
+}
+
+/// Encodes a model as a JSON value described by `header`, compressing the
+/// payload when `header.compressed` is set.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::SerializationError`] if the model cannot be
+/// serialized, or if the header asks for a format, compression or encryption
+/// this build cannot produce.
+pub fn encode_model_with(model: &LocalDbModel, header: ValueHeader) -> Result<Vec<u8>, AppResponse> {
+    if header.format != ValueFormat::Json || header.encrypted {
+        return Err(AppResponse::SerializationError(format!(
+            "Cannot encode values with header {header:?}"
+        )));
+    }
+
+    let json = serde_json::to_vec(model)?;
+    let payload = if header.compressed { compress(&json)? } else { json };
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&header.to_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Decodes a stored value (headered or legacy) into a model.
+///
+/// # Errors
+///
+/// Returns [`AppResponse::SerializationError`] if the header is invalid, the
+/// format is unsupported, or the payload does not describe a [`LocalDbModel`].
+pub fn decode_model(bytes: &[u8]) -> Result<LocalDbModel, AppResponse> {
+    let json = json_payload(bytes)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+#[cfg(feature = "compression")]
+fn compress(data: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| AppResponse::SerializationError(format!("Compression failed: {e}")))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    use std::io::Read;
+
+    let mut inflated = Vec::new();
+    flate2::read::ZlibDecoder::new(data)
+        .read_to_end(&mut inflated)
+        .map_err(|e| AppResponse::SerializationError(format!("Decompression failed: {e}")))?;
+    Ok(inflated)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    Err(AppResponse::SerializationError(
+        "Compressed values are not supported by this build".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_: &[u8]) -> Result<Vec<u8>, AppResponse> {
+    Err(AppResponse::SerializationError(
+        "Compressed values are not supported by this build".to_string(),
+    ))
+}
@@ -0,0 +1,347 @@
+//! Delta encoding of record updates.
+//!
+//! A delta consumer, such as the sync layer, receives every changed record in
+//! full from [`AppDbState::get_all_delta`], even when one field of a large
+//! record changed. With delta encoding enabled, the versions of each record
+//! written since the oldest consumer cursor are kept as JSON merge patches
+//! (RFC 7386) of `data` against the previous version, with a full snapshot
+//! every few versions. [`AppDbState::get_encoded_delta`] then returns each
+//! changed record as a [`RecordPatch`] against the version the consumer last
+//! acknowledged, when that version is known, so only the changed fields go
+//! over the network. The receiver applies it with [`RecordPatch::apply`].
+//!
+//! The versions live in the `__versions` database while delta consumers
+//! exist:
+//!
+//! ```text
+//! {id} 0x00 {sequence, u64 BE}  -> {"hash": ..., "snapshot": data} or {"hash": ..., "patch": merge patch}
+//! ```
+//!
+//! Writing a record drops its versions before the latest one every consumer
+//! has acknowledged, which becomes a snapshot. Records with encrypted fields
+//! or encrypted for a tenant are not versioned, so their plaintext is never
+//! stored, and are always sent in full.
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::app_response::AppResponse;
+use crate::delta::oldest_cursor;
+use crate::local_db_model::{EncodedDelta, LocalDbModel, RecordPatch};
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, DELTA_ENCODING_KEY, META_DB_NAME};
+use crate::scan::scan_from;
+
+/// Side database holding the record versions.
+pub(crate) const VERSIONS_DB_NAME: &str = "__versions";
+
+/// A stored version of a record.
+#[derive(Serialize, Deserialize)]
+struct StoredVersion {
+    hash: String,
+    #[serde(flatten)]
+    body: VersionBody,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum VersionBody {
+    /// The full `data` of the version.
+    Snapshot(JsonValue),
+    /// Merge patch of `data` against the previous version.
+    Patch(JsonValue),
+}
+
+/// Keeps the versions of the records written within a write transaction.
+pub(crate) struct VersionTracker {
+    versions_db: Database,
+    snapshot_every: u64,
+    /// Oldest sequence acknowledged by a delta consumer.
+    oldest: u64,
+}
+
+impl VersionTracker {
+    /// Adds the version of `model` written by the transaction `sequence`.
+    pub(crate) fn track(&self, txn: &mut RwTransaction, model: &LocalDbModel, sequence: u64) -> Result<(), AppResponse> {
+        let key = model.id.as_bytes();
+        let mut chain = read_chain(txn, self.versions_db, key)?;
+
+        // A record written twice in one transaction keeps its last version
+        while chain.last().is_some_and(|(version, _)| *version >= sequence) {
+            let (version, _) = chain.pop().ok_or(LmdbError::Corrupted)?;
+            txn.del(self.versions_db, &version_key(key, version), None)?;
+        }
+
+        // Versions before the oldest acknowledged one are no longer needed
+        if let Some(base) = chain.iter().rposition(|(version, _)| *version <= self.oldest).filter(|&base| base > 0) {
+            let (hash, data) = data_at(&chain, chain[base].0).ok_or(LmdbError::Corrupted)?;
+            for (version, _) in chain.drain(..base) {
+                txn.del(self.versions_db, &version_key(key, version), None)?;
+            }
+            chain[0].1 = StoredVersion { hash, body: VersionBody::Snapshot(data) };
+            txn.put(self.versions_db, &version_key(key, chain[0].0), &serde_json::to_vec(&chain[0].1)?, WriteFlags::empty())?;
+        }
+
+        let patches = chain.iter().rev().take_while(|(_, stored)| matches!(stored.body, VersionBody::Patch(_))).count() as u64;
+        let patch = match data_at(&chain, u64::MAX) {
+            Some((_, previous)) if patches + 1 < self.snapshot_every => merge_diff(&previous, &model.data),
+            _ => None,
+        };
+        let body = match patch {
+            Some(patch) => VersionBody::Patch(patch),
+            None => VersionBody::Snapshot(model.data.clone()),
+        };
+        let stored = StoredVersion { hash: model.hash.clone(), body };
+        txn.put(self.versions_db, &version_key(key, sequence), &serde_json::to_vec(&stored)?, WriteFlags::empty())?;
+        Ok(())
+    }
+
+    /// Drops the versions of the record `key`.
+    pub(crate) fn track_del(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<(), LmdbError> {
+        let prefix = [key, &[0x00]].concat();
+        let keys: Vec<Vec<u8>> = {
+            let cursor = txn.open_ro_cursor(self.versions_db)?;
+            scan_from(&cursor, Some(&prefix))
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, _)| key.to_vec())
+                .collect()
+        };
+        for key in keys {
+            txn.del(self.versions_db, &key, None)?;
+        }
+        Ok(())
+    }
+
+    /// Drops every version, for when all records are removed.
+    pub(crate) fn reset(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        txn.clear_db(self.versions_db)
+    }
+}
+
+impl AppDbState {
+    /// Keeps the versions of updated records as merge patches with a full
+    /// snapshot every `snapshot_every` versions, for
+    /// [`get_encoded_delta`](Self::get_encoded_delta), or stops with `None`
+    /// (the default) and drops the stored versions.
+    ///
+    /// The setting is persisted. Only versions written from now on are
+    /// kept, so records updated before are sent in full once.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("notes".to_string())?;
+    /// db.set_delta_encoding(Some(16))?;
+    ///
+    /// let delta = db.get_encoded_delta("sync")?;
+    /// println!("{} patches, {} full records", delta.patches.len(), delta.records.len());
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `snapshot_every` is zero, or a
+    /// database error if the write fails.
+    pub fn set_delta_encoding(&self, snapshot_every: Option<u32>) -> Result<(), AppResponse> {
+        if snapshot_every == Some(0) {
+            return Err(AppResponse::BadRequest("Snapshot interval must be at least 1".to_string()));
+        }
+        let (env, meta_db) = self.side_db(META_DB_NAME)?;
+        let (_, versions_db) = self.side_db(VERSIONS_DB_NAME)?;
+
+        let mut txn = env.begin_rw_txn()?;
+        match snapshot_every {
+            Some(snapshot_every) => {
+                let value = u64::from(snapshot_every).to_be_bytes();
+                txn.put(meta_db, &DELTA_ENCODING_KEY, &value, WriteFlags::empty())?;
+            }
+            None => {
+                match txn.del(meta_db, &DELTA_ENCODING_KEY, None) {
+                    Ok(()) | Err(LmdbError::NotFound) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                txn.clear_db(versions_db)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the snapshot interval of delta encoding, `None` when it is
+    /// disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database is closed or the read transaction fails.
+    pub fn delta_encoding(&self) -> Result<Option<u32>, LmdbError> {
+        Ok(self.meta_u64(DELTA_ENCODING_KEY)?.map(|snapshot_every| snapshot_every as u32))
+    }
+
+    /// Returns the delta of [`get_all_delta`](Self::get_all_delta) with the
+    /// updated records whose acknowledged version is known given as
+    /// [`RecordPatch`]es against it, and the others in full.
+    ///
+    /// Acknowledge it with [`ack_delta`](Self::ack_delta). Without delta
+    /// encoding, or on a reset, every record is in full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of [`get_all_delta`](Self::get_all_delta), or a
+    /// serialization error if a stored version cannot be decoded.
+    pub fn get_encoded_delta(&self, consumer: &str) -> Result<EncodedDelta, AppResponse> {
+        let delta = self.get_all_delta(consumer)?;
+        let mut encoded = EncodedDelta {
+            consumer: delta.consumer,
+            since: delta.since,
+            sequence: delta.sequence,
+            reset: delta.reset,
+            records: Vec::new(),
+            patches: Vec::new(),
+            deleted: delta.deleted,
+        };
+        if delta.reset || self.delta_encoding()?.is_none() {
+            encoded.records = delta.records;
+            return Ok(encoded);
+        }
+
+        let (env, versions_db) = self.side_db(VERSIONS_DB_NAME)?;
+        let txn = env.begin_ro_txn()?;
+        for record in delta.records {
+            let chain = read_chain(&txn, versions_db, record.id.as_bytes())?;
+            let patch = data_at(&chain, delta.since).and_then(|(base_hash, base)| {
+                let patch = merge_diff(&base, &record.data)?;
+                Some(RecordPatch { id: record.id.clone(), base_hash, hash: record.hash.clone(), patch })
+            });
+            match patch {
+                Some(patch) => encoded.patches.push(patch),
+                None => encoded.records.push(record),
+            }
+        }
+        Ok(encoded)
+    }
+
+    /// Returns the tracker of record versions, `None` without delta
+    /// encoding or delta consumers.
+    pub(crate) fn version_tracker<T: Transaction>(&self, txn: &T, changes_db: Option<Database>) -> Result<Option<VersionTracker>, LmdbError> {
+        let Some(changes_db) = changes_db else {
+            return Ok(None);
+        };
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let Some(snapshot_every) = get_meta_u64(txn, meta_db, DELTA_ENCODING_KEY)? else {
+            return Ok(None);
+        };
+        let (_, versions_db) = self.side_db(VERSIONS_DB_NAME)?;
+        Ok(Some(VersionTracker { versions_db, snapshot_every, oldest: oldest_cursor(txn, changes_db)? }))
+    }
+}
+
+impl RecordPatch {
+    /// Applies the patch to `base`, the version it was computed against.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppResponse::BadRequest`] if `base` is another record or
+    /// another version.
+    pub fn apply(&self, base: &LocalDbModel) -> Result<LocalDbModel, AppResponse> {
+        if base.id != self.id || base.hash != self.base_hash {
+            return Err(AppResponse::BadRequest(format!(
+                "Patch of {} applies to version {}, not {} of {}",
+                self.id, self.base_hash, base.hash, base.id
+            )));
+        }
+        let mut data = base.data.clone();
+        merge_patch(&mut data, &self.patch);
+        Ok(LocalDbModel { id: self.id.clone(), hash: self.hash.clone(), data, ..LocalDbModel::default() })
+    }
+}
+
+/// Reads the versions of the record `key`, oldest first.
+fn read_chain<T: Transaction>(txn: &T, versions_db: Database, key: &[u8]) -> Result<Vec<(u64, StoredVersion)>, AppResponse> {
+    let prefix = [key, &[0x00]].concat();
+    let cursor = txn.open_ro_cursor(versions_db)?;
+    scan_from(&cursor, Some(&prefix))
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .map(|(version, value)| {
+            let sequence = version[prefix.len()..].try_into().map_err(|_| LmdbError::Corrupted)?;
+            Ok((u64::from_be_bytes(sequence), serde_json::from_slice(value)?))
+        })
+        .collect()
+}
+
+/// Returns the hash and `data` of the latest version at or before
+/// `sequence`, `None` if there is none.
+fn data_at(chain: &[(u64, StoredVersion)], sequence: u64) -> Option<(String, JsonValue)> {
+    let end = chain.iter().rposition(|(version, _)| *version <= sequence)?;
+    let start = chain[..=end].iter().rposition(|(_, stored)| matches!(stored.body, VersionBody::Snapshot(_)))?;
+
+    let mut data = JsonValue::Null;
+    for (_, stored) in &chain[start..=end] {
+        match &stored.body {
+            VersionBody::Snapshot(snapshot) => data = snapshot.clone(),
+            VersionBody::Patch(patch) => merge_patch(&mut data, patch),
+        }
+    }
+    Some((chain[end].1.hash.clone(), data))
+}
+
+fn version_key(key: &[u8], sequence: u64) -> Vec<u8> {
+    [key, &[0x00], &sequence.to_be_bytes()].concat()
+}
+
+/// Returns the merge patch turning `from` into `to`, `None` if a merge patch
+/// cannot express it because `to` holds `null` object members.
+fn merge_diff(from: &JsonValue, to: &JsonValue) -> Option<JsonValue> {
+    let (JsonValue::Object(from), JsonValue::Object(to)) = (from, to) else {
+        return settable(to).then(|| to.clone());
+    };
+
+    let mut patch = Map::new();
+    for removed in from.keys().filter(|field| !to.contains_key(*field)) {
+        patch.insert(removed.clone(), JsonValue::Null);
+    }
+    for (field, value) in to {
+        match from.get(field) {
+            Some(old) if old == value => {}
+            Some(old) => {
+                patch.insert(field.clone(), merge_diff(old, value)?);
+            }
+            None if settable(value) => {
+                patch.insert(field.clone(), value.clone());
+            }
+            None => return None,
+        }
+    }
+    Some(JsonValue::Object(patch))
+}
+
+/// Returns whether a merge patch can set a member to `value`.
+fn settable(value: &JsonValue) -> bool {
+    match value {
+        JsonValue::Null => false,
+        JsonValue::Object(map) => map.values().all(settable),
+        _ => true,
+    }
+}
+
+/// Applies the merge patch `patch` to `target`.
+fn merge_patch(target: &mut JsonValue, patch: &JsonValue) {
+    let JsonValue::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = JsonValue::Object(Map::new());
+    }
+    if let JsonValue::Object(map) = target {
+        for (field, value) in patch {
+            if value.is_null() {
+                map.remove(field);
+            } else {
+                merge_patch(map.entry(field.clone()).or_insert(JsonValue::Null), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Change notifications for watched key prefixes.
+//!
+//! Every write transaction committed through a
+//! [`RecordWriter`](crate::writer::RecordWriter) publishes the IDs it changed
+//! to the [`WatchHub`] of its database. A watch does not fire per write:
+//! changes are collected for its debounce window after the first one and then
+//! delivered as a single [`ChangeBatch`] listing every changed ID, so a sync
+//! writing thousands of records produces a handful of events instead of
+//! thousands of callbacks. Callbacks run on a dispatcher thread, never on the
+//! writing thread.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::app_response::AppResponse;
+use crate::local_db_model::ChangeBatch;
+use crate::local_db_state::AppDbState;
+
+type Callback = Arc<dyn Fn(&ChangeBatch) + Send + Sync>;
+
+/// Watches of one database and the thread delivering their batches.
+#[derive(Default)]
+pub(crate) struct WatchHub {
+    shared: Arc<Shared>,
+    /// Whether any watch is registered, checked by writers without locking.
+    active: AtomicBool,
+    dispatcher: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<HubState>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct HubState {
+    next_id: u64,
+    watches: BTreeMap<u64, Watch>,
+    stopped: bool,
+}
+
+struct Watch {
+    prefix: String,
+    debounce: Duration,
+    callback: Callback,
+    pending: BTreeSet<String>,
+    cleared: bool,
+    /// When the pending changes are delivered, `None` when there are none.
+    due: Option<Instant>,
+}
+
+impl WatchHub {
+    /// Returns whether writers need to report their changes.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Records the changes of a committed transaction. `cleared` means every
+    /// record was removed.
+    pub(crate) fn publish(&self, keys: &[Vec<u8>], cleared: bool) {
+        let now = Instant::now();
+        let mut state = self.shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+        for watch in state.watches.values_mut() {
+            let before = watch.pending.len();
+            watch.pending.extend(
+                keys.iter()
+                    .map(|key| String::from_utf8_lossy(key))
+                    .filter(|id| id.starts_with(&watch.prefix))
+                    .map(|id| id.into_owned()),
+            );
+            watch.cleared |= cleared;
+            if (watch.pending.len() > before || cleared) && watch.due.is_none() {
+                watch.due = Some(now + watch.debounce);
+            }
+        }
+        self.shared.wake.notify_all();
+    }
+
+    fn add(&self, watch: Watch) -> Result<u64, AppResponse> {
+        let id = {
+            let mut state = self.shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.next_id += 1;
+            let id = state.next_id;
+            state.watches.insert(id, watch);
+            id
+        };
+        self.active.store(true, Ordering::Release);
+
+        let mut dispatcher = self.dispatcher.lock().unwrap_or_else(PoisonError::into_inner);
+        if dispatcher.is_none() {
+            let shared = Arc::clone(&self.shared);
+            let thread = thread::Builder::new()
+                .name("watch-dispatcher".to_string())
+                .spawn(move || dispatch(&shared))
+                .map_err(|e| AppResponse::DatabaseError(format!("Cannot start watch dispatcher: {e}")))?;
+            *dispatcher = Some(thread);
+        }
+        Ok(id)
+    }
+
+    fn remove(&self, watch_id: u64) -> bool {
+        let mut state = self.shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let removed = state.watches.remove(&watch_id).is_some();
+        self.active.store(!state.watches.is_empty(), Ordering::Release);
+        removed
+    }
+
+    /// Drops every watch and stops the dispatcher thread. Pending changes are
+    /// not delivered.
+    pub(crate) fn shutdown(&self) {
+        {
+            let mut state = self.shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.stopped = true;
+            state.watches.clear();
+        }
+        self.active.store(false, Ordering::Release);
+        self.shared.wake.notify_all();
+
+        let dispatcher = self.dispatcher.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(thread) = dispatcher {
+            if thread.join().is_err() {
+                warn!("Watch dispatcher panicked");
+            }
+        }
+    }
+}
+
+/// Body of the dispatcher thread: delivers each batch once it is due.
+fn dispatch(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        if state.stopped {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        for (&watch_id, watch) in state.watches.iter_mut() {
+            if watch.due.is_some_and(|due| due <= now) {
+                watch.due = None;
+                let batch = ChangeBatch {
+                    watch_id,
+                    ids: std::mem::take(&mut watch.pending).into_iter().collect(),
+                    cleared: std::mem::take(&mut watch.cleared),
+                };
+                ready.push((Arc::clone(&watch.callback), batch));
+            }
+        }
+
+        if !ready.is_empty() {
+            drop(state);
+            for (callback, batch) in ready {
+                callback(&batch);
+            }
+            state = shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+            continue;
+        }
+
+        state = match state.watches.values().filter_map(|watch| watch.due).min() {
+            Some(due) => shared.wake.wait_timeout(state, due - now).unwrap_or_else(PoisonError::into_inner).0,
+            None => shared.wake.wait(state).unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+}
+
+impl AppDbState {
+    /// Calls `callback` with the IDs of the records changed under `prefix`.
+    ///
+    /// Changes are collected for `debounce` after the first one and delivered
+    /// as one [`ChangeBatch`]; a bulk write during sync therefore yields one
+    /// event listing all its IDs. `cleared` is set when all records were
+    /// cleared. The callback runs on a dispatcher thread shared by all
+    /// watches of this database and should return quickly. Returns the ID of
+    /// the watch, for [`AppDbState::unwatch`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use offline_first_core::local_db_state::AppDbState;
+    ///
+    /// let db = AppDbState::init("todos".to_string())?;
+    ///
+    /// let watch_id = db.watch("todo:", Duration::from_millis(16), |batch| {
+    ///     println!("{} todos changed", batch.ids.len());
+    /// })?;
+    /// db.unwatch(watch_id);
+    /// # Ok::<(), offline_first_core::app_response::AppResponse>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dispatcher thread cannot be started.
+    pub fn watch<F>(&self, prefix: &str, debounce: Duration, callback: F) -> Result<u64, AppResponse>
+    where
+        F: Fn(&ChangeBatch) + Send + Sync + 'static,
+    {
+        self.watch_hub.add(Watch {
+            prefix: prefix.to_string(),
+            debounce,
+            callback: Arc::new(callback),
+            pending: BTreeSet::new(),
+            cleared: false,
+            due: None,
+        })
+    }
+
+    /// Removes a watch. Changes it has not delivered yet are dropped.
+    /// Returns whether the watch existed.
+    pub fn unwatch(&self, watch_id: u64) -> bool {
+        self.watch_hub.remove(watch_id)
+    }
+}
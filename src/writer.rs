@@ -0,0 +1,272 @@
+//! Writes to the main database.
+//!
+//! Records own data stored outside the main database: the entries of every
+//! defined index and the chunks of overflowed fields. All record writes go
+//! through a [`RecordWriter`] so that this data changes in the same
+//! transaction as the record itself. The writer also advances the commit
+//! sequence (see [`AppDbState::commit_sequence`]) and issues an HLC
+//! timestamp (see [`crate::hlc`]) once per transaction, keeps the sync
+//! digest current (see [`crate::digest`]), logs the changes for delta
+//! consumers (see [`crate::delta`]), drops the versions of deleted
+//! records (see [`crate::versions`]) and records their deletion in the
+//! record history (see [`crate::record_history`]). When the
+//! transaction is committed through it, it evicts records above the cache
+//! limit (see [`crate::cache`]) and notifies the watches of the database of
+//! the changed IDs.
+
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+
+use lmdb::{Database, Error as LmdbError, RwTransaction, Transaction, WriteFlags};
+use log::info;
+
+use crate::cache::CacheTracker;
+use crate::delta::{log_change, log_clear};
+use crate::digest::DigestTracker;
+use crate::hlc::{next_hlc, HLC_DB_NAME};
+use crate::index::{index_entries, INDEX_DB_NAME};
+use crate::local_db_model::{Hlc, IndexDefinition};
+use crate::local_db_state::AppDbState;
+use crate::meta::{get_meta_u64, put_meta_u64, COMMIT_SEQUENCE_KEY, CRDT_MODE_KEY, META_DB_NAME, RECORD_TIMESTAMPS_KEY};
+use crate::overflow::{delete_chunks, CHUNKS_DB_NAME};
+use crate::record_history::HistoryTracker;
+use crate::versions::VersionTracker;
+use crate::watch::WatchHub;
+
+/// Writes records together with their index entries and overflow chunks.
+pub(crate) struct RecordWriter {
+    definitions: Vec<IndexDefinition>,
+    index_db: Database,
+    pub(crate) chunks_db: Database,
+    meta_db: Database,
+    sequence: Cell<Option<u64>>,
+    hlc_db: Database,
+    /// HLC timestamp of this transaction, issued on its first write.
+    hlc: Cell<Option<Hlc>>,
+    /// Watches to notify on commit, `None` when nothing is watched.
+    hub: Option<Arc<WatchHub>>,
+    changed: RefCell<Vec<Vec<u8>>>,
+    cleared: Cell<bool>,
+    /// Write order and size tracking, `None` without a cache limit.
+    cache: Option<CacheTracker>,
+    /// Sync digest buckets, `None` until the digest was first requested.
+    pub(crate) digest: Option<DigestTracker>,
+    /// Change log of delta consumers, `None` when there are none.
+    changes_db: Option<Database>,
+    /// Record versions for delta encoding, `None` when it is disabled or
+    /// there are no delta consumers.
+    pub(crate) versions: Option<VersionTracker>,
+    /// Time logged with the changes, in milliseconds since the Unix epoch.
+    pub(crate) changed_at: u64,
+    /// Whether written records get field timestamps, see [`crate::crdt`].
+    pub(crate) crdt: bool,
+    /// Whether written records get `created_at` and `updated_at`, see
+    /// [`crate::timestamps`].
+    pub(crate) timestamps: bool,
+    /// Record history, `None` while history mode is off.
+    pub(crate) history: Option<HistoryTracker>,
+}
+
+impl RecordWriter {
+    /// Writes a record, replacing the index entries and chunks of its previous value.
+    pub(crate) fn put(&self, txn: &mut RwTransaction, db: Database, key: &[u8], value: &[u8]) -> Result<(), LmdbError> {
+        self.remove_owned(txn, db, key)?;
+        self.record_change(key);
+        txn.put(db, &key, &value, WriteFlags::empty())?;
+        if let Some(hlc) = self.hlc.get() {
+            txn.put(self.hlc_db, &key, &hlc.to_u64().to_be_bytes(), WriteFlags::empty())?;
+        }
+        for entry in index_entries(&self.definitions, key, value) {
+            txn.put(self.index_db, &entry, &key, WriteFlags::empty())?;
+        }
+        if let Some(digest) = &self.digest {
+            digest.track_put(txn, key, value)?;
+        }
+        if let (Some(cache), Some(sequence)) = (&self.cache, self.sequence.get()) {
+            cache.track_put(txn, key, value.len(), sequence)?;
+        }
+        if let (Some(changes_db), Some(sequence)) = (self.changes_db, self.sequence.get()) {
+            log_change(txn, changes_db, key, sequence, self.changed_at, false)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a record with its index entries and chunks. Returns whether it existed.
+    pub(crate) fn del(&self, txn: &mut RwTransaction, db: Database, key: &[u8]) -> Result<bool, LmdbError> {
+        self.remove_owned(txn, db, key)?;
+        match txn.del(db, &key, None) {
+            Ok(()) => {
+                self.record_change(key);
+                match txn.del(self.hlc_db, &key, None) {
+                    Ok(()) | Err(LmdbError::NotFound) => {}
+                    Err(e) => return Err(e),
+                }
+                if let Some(cache) = &self.cache {
+                    cache.track_del(txn, key)?;
+                }
+                if let Some(digest) = &self.digest {
+                    digest.track_del(txn, key)?;
+                }
+                if let Some(versions) = &self.versions {
+                    versions.track_del(txn, key)?;
+                }
+                if let (Some(history), Some(sequence)) = (&self.history, self.sequence.get()) {
+                    history.track_del(txn, key, sequence, self.changed_at)?;
+                }
+                if let (Some(changes_db), Some(sequence)) = (self.changes_db, self.sequence.get()) {
+                    log_change(txn, changes_db, key, sequence, self.changed_at, true)?;
+                }
+                Ok(true)
+            }
+            Err(LmdbError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Drops all index entries and chunks, for when all records are removed.
+    pub(crate) fn clear(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        self.advance_sequence(txn)?;
+        self.cleared.set(self.hub.is_some());
+        if let Some(cache) = &self.cache {
+            cache.reset(txn)?;
+        }
+        if let Some(digest) = &self.digest {
+            digest.reset(txn)?;
+        }
+        if let Some(versions) = &self.versions {
+            versions.reset(txn)?;
+        }
+        if let Some(history) = &self.history {
+            history.reset(txn)?;
+        }
+        if let (Some(changes_db), Some(sequence)) = (self.changes_db, self.sequence.get()) {
+            log_clear(txn, changes_db, sequence)?;
+        }
+        txn.clear_db(self.index_db)?;
+        txn.clear_db(self.hlc_db)?;
+        txn.clear_db(self.chunks_db)
+    }
+
+    /// Evicts the least recently written records while the database is above
+    /// the cache limit, sparing the records written by this transaction.
+    /// Returns the number of records evicted.
+    pub(crate) fn evict(&self, txn: &mut RwTransaction) -> Result<usize, LmdbError> {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+
+        let current = self.sequence.get();
+        let mut evicted = 0;
+        while cache.over_limit() {
+            match cache.oldest(txn)? {
+                Some((sequence, id)) if Some(sequence) != current => {
+                    self.del(txn, cache.main_db, &id)?;
+                    evicted += 1;
+                }
+                _ => break,
+            }
+        }
+        if evicted > 0 {
+            info!("Evicted {evicted} records above the cache limit");
+        }
+        if let Some(sequence) = self.sequence.get() {
+            cache.store(txn, self.meta_db, sequence)?;
+        }
+        Ok(evicted)
+    }
+
+    /// Commits `txn`, evicting records above the cache limit first, and
+    /// notifies the watches of the records it changed.
+    pub(crate) fn commit(self, mut txn: RwTransaction) -> Result<(), LmdbError> {
+        self.evict(&mut txn)?;
+        txn.commit()?;
+        if let Some(hub) = &self.hub {
+            hub.publish(&self.changed.into_inner(), self.cleared.get());
+        }
+        Ok(())
+    }
+
+    /// Returns the commit sequence of this transaction, `None` before its
+    /// first write.
+    pub(crate) fn sequence(&self) -> Option<u64> {
+        self.sequence.get()
+    }
+
+    /// Returns the HLC timestamp of this transaction, issuing it if no
+    /// record was written yet.
+    pub(crate) fn hlc(&self, txn: &mut RwTransaction) -> Result<Hlc, LmdbError> {
+        self.advance_sequence(txn)?;
+        self.hlc.get().ok_or(LmdbError::Corrupted)
+    }
+
+    fn record_change(&self, key: &[u8]) {
+        if self.hub.is_some() {
+            self.changed.borrow_mut().push(key.to_vec());
+        }
+    }
+
+    /// Assigns the next commit sequence and HLC timestamp to this
+    /// transaction on its first write.
+    fn advance_sequence(&self, txn: &mut RwTransaction) -> Result<(), LmdbError> {
+        if self.sequence.get().is_some() {
+            return Ok(());
+        }
+        let next = get_meta_u64(txn, self.meta_db, COMMIT_SEQUENCE_KEY)?.unwrap_or(0) + 1;
+        put_meta_u64(txn, self.meta_db, COMMIT_SEQUENCE_KEY, next)?;
+        self.sequence.set(Some(next));
+        self.hlc.set(Some(next_hlc(txn, self.meta_db, self.changed_at, None)?));
+        Ok(())
+    }
+
+    fn remove_owned(&self, txn: &mut RwTransaction, db: Database, key: &[u8]) -> Result<(), LmdbError> {
+        self.advance_sequence(txn)?;
+        delete_chunks(txn, self.chunks_db, key)?;
+        if self.definitions.is_empty() {
+            return Ok(());
+        }
+
+        let entries = match txn.get(db, &key) {
+            Ok(old) => index_entries(&self.definitions, key, old),
+            Err(LmdbError::NotFound) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            match txn.del(self.index_db, &entry, None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AppDbState {
+    /// Returns the writer to use for main database writes within `txn`.
+    pub(crate) fn record_writer<T: Transaction>(&self, txn: &T) -> Result<RecordWriter, LmdbError> {
+        let (_, index_db) = self.side_db(INDEX_DB_NAME)?;
+        let (_, chunks_db) = self.side_db(CHUNKS_DB_NAME)?;
+        let (_, meta_db) = self.side_db(META_DB_NAME)?;
+        let (_, hlc_db) = self.side_db(HLC_DB_NAME)?;
+        let changes_db = self.change_log(txn)?;
+        Ok(RecordWriter {
+            definitions: self.read_index_definitions(txn)?,
+            index_db,
+            chunks_db,
+            meta_db,
+            sequence: Cell::new(None),
+            hlc_db,
+            hlc: Cell::new(None),
+            hub: self.watch_hub.is_active().then(|| Arc::clone(&self.watch_hub)),
+            changed: RefCell::new(Vec::new()),
+            cleared: Cell::new(false),
+            cache: self.cache_tracker(txn)?,
+            digest: self.digest_tracker(txn)?,
+            changes_db,
+            versions: self.version_tracker(txn, changes_db)?,
+            changed_at: self.now_ms(),
+            crdt: get_meta_u64(txn, meta_db, CRDT_MODE_KEY)?.is_some(),
+            timestamps: get_meta_u64(txn, meta_db, RECORD_TIMESTAMPS_KEY)?.is_some(),
+            history: self.history_tracker(txn)?,
+        })
+    }
+}